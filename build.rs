@@ -9,9 +9,14 @@ fn main() {
 
     if skip_npm {
         println!("cargo:warning=Skipping npm build (SKIP_NPM_BUILD is set)");
+        // Without this, any change in the package (a CSS or JS edit) would
+        // rerun the script and rebuild the crate
+        println!("cargo:rerun-if-changed=build.rs");
+        println!("cargo:rerun-if-env-changed=SKIP_NPM_BUILD");
         return;
     }
 
+    println!("cargo:rerun-if-env-changed=SKIP_NPM_BUILD");
     println!("cargo:rerun-if-changed=package.json");
     println!("cargo:rerun-if-changed=package-lock.json");
     println!("cargo:rerun-if-changed=build.js");
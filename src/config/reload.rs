@@ -0,0 +1,317 @@
+/// Configuration Reload
+///
+/// A few settings can change while the server runs: the rate limit,
+/// read-only mode and the log filter. On SIGHUP or
+/// `POST /api/admin/reload-config` they are re-read from `.env` and the
+/// environment and applied in place; everything else still needs a restart.
+///
+/// A reload is all or nothing: if any value is invalid, nothing is applied
+/// and the running settings stay as they were.
+///
+/// The rate limit can also be set through the admin API; the next reload
+/// puts back the environment's value if it differs.
+use super::Config;
+use crate::logging::{self, LogFilterHandle};
+use crate::middleware::rate_limit::RateLimitState;
+use crate::services::audit_service::{AuditEvent, AuditEventType};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Read-only mode, shared by everything that enforces it so a reload can
+/// switch it for the whole server
+#[derive(Debug, Clone)]
+pub struct ReadOnlyMode(Arc<ReadOnlyInner>);
+
+#[derive(Debug)]
+struct ReadOnlyInner {
+    enabled: AtomicBool,
+    changed_at: Mutex<Option<Instant>>,
+}
+
+impl ReadOnlyMode {
+    pub fn new(enabled: bool) -> Self {
+        ReadOnlyMode(Arc::new(ReadOnlyInner {
+            enabled: AtomicBool::new(enabled),
+            changed_at: Mutex::new(None),
+        }))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Switch the mode, returning whether it changed
+    pub fn set(&self, enabled: bool) -> bool {
+        let mut changed_at = self.0.changed_at.lock();
+        let changed = self.0.enabled.swap(enabled, Ordering::Relaxed) != enabled;
+        if changed {
+            *changed_at = Some(Instant::now());
+        }
+        changed
+    }
+
+    /// Whether a connection opened `age` ago was set up before the last
+    /// switch, and so still has the old session default
+    pub fn predates_change(&self, age: Duration) -> bool {
+        self.0
+            .changed_at
+            .lock()
+            .is_some_and(|changed_at| age > changed_at.elapsed())
+    }
+}
+
+/// The settings a reload can change
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReloadableSettings {
+    pub rate_limit_requests_per_minute: u32,
+    pub read_only_mode: bool,
+    /// `RUST_LOG` directives
+    pub log_filter: String,
+}
+
+impl ReloadableSettings {
+    /// The settings the server started with
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            rate_limit_requests_per_minute: config.rate_limit_requests_per_minute,
+            read_only_mode: config.read_only_mode,
+            log_filter: log_filter_from_env(),
+        }
+    }
+
+    /// Read the settings from the environment
+    ///
+    /// Unlike `Config::from_env`, invalid values are reported rather than
+    /// panicking, since a bad edit must not take a running server down.
+    pub fn from_env() -> Result<Self, String> {
+        let rate_limit_requests_per_minute = env::var("RATE_LIMIT_REQUESTS_PER_MINUTE")
+            .unwrap_or_else(|_| "100".to_string())
+            .parse()
+            .map_err(|_| "RATE_LIMIT_REQUESTS_PER_MINUTE must be a valid number".to_string())?;
+
+        let read_only_mode = env::var("READ_ONLY_MODE")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .map_err(|_| "READ_ONLY_MODE must be true or false".to_string())?;
+
+        Ok(Self {
+            rate_limit_requests_per_minute,
+            read_only_mode,
+            log_filter: log_filter_from_env(),
+        })
+    }
+}
+
+fn log_filter_from_env() -> String {
+    env::var("RUST_LOG")
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+        .unwrap_or_else(|| logging::DEFAULT_FILTER.to_string())
+}
+
+/// What a reload changed
+#[derive(Debug, Clone, Serialize)]
+pub struct ReloadReport {
+    /// Names of the settings whose values changed
+    pub changed: Vec<&'static str>,
+    /// The settings now in effect
+    pub settings: ReloadableSettings,
+}
+
+/// Applies reloaded settings to the running server
+pub struct ConfigReloader {
+    current: Mutex<ReloadableSettings>,
+    rate_limit: Arc<RateLimitState>,
+    read_only: ReadOnlyMode,
+    log_filter: LogFilterHandle,
+}
+
+impl ConfigReloader {
+    pub fn new(
+        current: ReloadableSettings,
+        rate_limit: Arc<RateLimitState>,
+        read_only: ReadOnlyMode,
+        log_filter: LogFilterHandle,
+    ) -> Self {
+        Self {
+            current: Mutex::new(current),
+            rate_limit,
+            read_only,
+            log_filter,
+        }
+    }
+
+    /// Re-read `.env` and the environment, and apply whatever changed
+    pub fn reload(&self) -> Result<ReloadReport, String> {
+        // Values in .env win over the process environment here, otherwise
+        // the values loaded at startup would shadow any edit to the file
+        if let Err(e) = dotenvy::dotenv_override() {
+            if !e.not_found() {
+                return Err(format!("Failed to read .env: {}", e));
+            }
+        }
+        self.apply(ReloadableSettings::from_env()?)
+    }
+
+    /// Apply new settings, all or nothing
+    pub fn apply(&self, next: ReloadableSettings) -> Result<ReloadReport, String> {
+        let mut current = self.current.lock();
+        let mut changed = Vec::new();
+
+        if next.log_filter != current.log_filter {
+            logging::set_filter(&self.log_filter, &next.log_filter)?;
+            changed.push("RUST_LOG");
+        }
+        if next.rate_limit_requests_per_minute != current.rate_limit_requests_per_minute {
+            self.rate_limit
+                .set_requests_per_minute(next.rate_limit_requests_per_minute);
+            changed.push("RATE_LIMIT_REQUESTS_PER_MINUTE");
+        }
+        if next.read_only_mode != current.read_only_mode {
+            self.read_only.set(next.read_only_mode);
+            changed.push("READ_ONLY_MODE");
+        }
+
+        *current = next.clone();
+        Ok(ReloadReport {
+            changed,
+            settings: next,
+        })
+    }
+
+    /// Set the rate limit until the next reload, returning whether it changed
+    pub fn set_rate_limit(&self, requests_per_minute: u32) -> bool {
+        let mut current = self.current.lock();
+        if current.rate_limit_requests_per_minute == requests_per_minute {
+            return false;
+        }
+        self.rate_limit.set_requests_per_minute(requests_per_minute);
+        current.rate_limit_requests_per_minute = requests_per_minute;
+        true
+    }
+}
+
+/// Audit event recording a reload attempt and its outcome
+pub fn audit_event(client_ip: String, outcome: &Result<ReloadReport, String>) -> AuditEvent {
+    let event = AuditEvent::new(
+        AuditEventType::ConfigurationChange,
+        client_ip,
+        "reload configuration".to_string(),
+        "config".to_string(),
+    );
+    match outcome {
+        Ok(report) if report.changed.is_empty() => event.with_details("no changes".to_string()),
+        Ok(report) => event.with_details(format!("changed {}", report.changed.join(", "))),
+        Err(e) => event.with_success(false).with_details(e.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::rate_limit::RateLimitConfig;
+    use tracing_subscriber::{reload, EnvFilter};
+
+    fn settings() -> ReloadableSettings {
+        ReloadableSettings {
+            rate_limit_requests_per_minute: 100,
+            read_only_mode: false,
+            log_filter: "info".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_read_only_mode_switch() {
+        let mode = ReadOnlyMode::new(false);
+        assert!(!mode.predates_change(Duration::from_secs(3600)));
+
+        assert!(mode.set(true));
+        assert!(mode.clone().is_enabled());
+        assert!(!mode.set(true));
+
+        // Connections opened before the switch are stale, new ones are not
+        assert!(mode.predates_change(Duration::from_secs(3600)));
+        assert!(!mode.predates_change(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_apply_reports_changes() {
+        let (_layer, handle) = reload::Layer::new(EnvFilter::new("info"));
+        let rate_limit = Arc::new(RateLimitState::new(RateLimitConfig {
+            requests_per_minute: 100,
+        }));
+        let read_only = ReadOnlyMode::new(false);
+        let reloader =
+            ConfigReloader::new(settings(), rate_limit.clone(), read_only.clone(), handle);
+
+        let report = reloader.apply(settings()).unwrap();
+        assert!(report.changed.is_empty());
+
+        let next = ReloadableSettings {
+            rate_limit_requests_per_minute: 1,
+            read_only_mode: true,
+            log_filter: "pgadmin_rs=trace".to_string(),
+        };
+        let report = reloader.apply(next.clone()).unwrap();
+        assert_eq!(
+            report.changed,
+            vec![
+                "RUST_LOG",
+                "RATE_LIMIT_REQUESTS_PER_MINUTE",
+                "READ_ONLY_MODE"
+            ]
+        );
+        assert_eq!(report.settings, next);
+        assert!(read_only.is_enabled());
+        assert!(rate_limit.check_limit("10.0.0.1"));
+        assert!(!rate_limit.check_limit("10.0.0.1"));
+    }
+
+    #[test]
+    fn test_set_rate_limit_until_reload() {
+        let (_layer, handle) = reload::Layer::new(EnvFilter::new("info"));
+        let rate_limit = Arc::new(RateLimitState::new(RateLimitConfig {
+            requests_per_minute: 100,
+        }));
+        let reloader = ConfigReloader::new(
+            settings(),
+            rate_limit.clone(),
+            ReadOnlyMode::new(false),
+            handle,
+        );
+
+        assert!(reloader.set_rate_limit(5));
+        assert!(!reloader.set_rate_limit(5));
+        assert_eq!(rate_limit.requests_per_minute(), 5);
+
+        // Reloading the original value undoes the runtime change
+        let report = reloader.apply(settings()).unwrap();
+        assert_eq!(report.changed, vec!["RATE_LIMIT_REQUESTS_PER_MINUTE"]);
+        assert_eq!(rate_limit.requests_per_minute(), 100);
+    }
+
+    #[test]
+    fn test_invalid_filter_applies_nothing() {
+        let (_layer, handle) = reload::Layer::new(EnvFilter::new("info"));
+        let read_only = ReadOnlyMode::new(false);
+        let reloader = ConfigReloader::new(
+            settings(),
+            Arc::new(RateLimitState::new(RateLimitConfig::default())),
+            read_only.clone(),
+            handle,
+        );
+
+        let next = ReloadableSettings {
+            read_only_mode: true,
+            log_filter: "pgadmin_rs=loud".to_string(),
+            ..settings()
+        };
+        assert!(reloader.apply(next).is_err());
+        assert!(!read_only.is_enabled());
+        assert_eq!(*reloader.current.lock(), settings());
+    }
+}
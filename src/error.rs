@@ -0,0 +1,297 @@
+use crate::i18n::{self, filters};
+use crate::middleware::{locale, request_id};
+use askama::Template;
+/// Application Errors
+///
+/// `AppError` is the error type returned by route handlers. It responds with
+/// a JSON body `{error, details, code}` that keeps the underlying SQL error
+/// message, and `htmx_error_fragments` turns that body into an HTML alert
+/// for HTMX requests so the page can show it in place.
+use axum::{
+    extract::{rejection::JsonRejection, Request},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    #[error("Database error")]
+    Database(#[from] sqlx::Error),
+    #[error("Failed to render template")]
+    Template(#[from] askama::Error),
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Forbidden(String),
+    /// The request needs confirmation before it is carried out
+    #[error("{0}")]
+    Conflict(String),
+    /// The request ran past its time limit
+    #[error("{0}")]
+    Timeout(String),
+    /// The feature cannot be used in the server's current mode
+    #[error("{0}")]
+    Unavailable(String),
+    /// A quota or allowance is used up for now
+    #[error("{0}")]
+    TooManyRequests(String),
+    #[error("{0}")]
+    Internal(String),
+}
+
+/// JSON body of an error response
+#[derive(Debug, Clone, Serialize, PartialEq, ToSchema)]
+#[schema(as = Error)]
+pub struct ErrorBody {
+    pub error: String,
+    /// Underlying cause, e.g. the server's SQL error message and SQLSTATE
+    pub details: Option<String>,
+    /// Stable machine-readable error code
+    #[schema(schema_with = error_code_schema)]
+    pub code: &'static str,
+    /// Id of the failed request, to quote when reporting the error
+    pub request_id: Option<String>,
+}
+
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::Database(e) => match sqlstate(e).as_deref() {
+                // undefined_table, invalid_schema_name, invalid_catalog_name
+                Some("42P01" | "3F000" | "3D000") => StatusCode::NOT_FOUND,
+                // insufficient_privilege
+                Some("42501") => StatusCode::FORBIDDEN,
+                _ if matches!(e, sqlx::Error::RowNotFound) => StatusCode::NOT_FOUND,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            AppError::Template(_) | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            AppError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+
+    /// Every value `code` returns
+    pub const CODES: [&'static str; 10] = [
+        "not_found",
+        "forbidden",
+        "database_error",
+        "template_error",
+        "bad_request",
+        "conflict",
+        "timeout",
+        "unavailable",
+        "too_many_requests",
+        "internal_error",
+    ];
+
+    pub fn code(&self) -> &'static str {
+        match (self, self.status()) {
+            (_, StatusCode::NOT_FOUND) => "not_found",
+            (_, StatusCode::FORBIDDEN) => "forbidden",
+            (AppError::Database(_), _) => "database_error",
+            (AppError::Template(_), _) => "template_error",
+            (AppError::BadRequest(_), _) => "bad_request",
+            (AppError::Conflict(_), _) => "conflict",
+            (AppError::Timeout(_), _) => "timeout",
+            (AppError::Unavailable(_), _) => "unavailable",
+            (AppError::TooManyRequests(_), _) => "too_many_requests",
+            _ => "internal_error",
+        }
+    }
+
+    pub fn details(&self) -> Option<String> {
+        match self {
+            AppError::Database(sqlx::Error::Database(db)) => Some(match db.code() {
+                Some(code) => format!("{} (SQLSTATE {})", db.message(), code),
+                None => db.message().to_string(),
+            }),
+            AppError::Database(e) => Some(e.to_string()),
+            AppError::Template(e) => Some(e.to_string()),
+            _ => None,
+        }
+    }
+
+    pub fn body(&self) -> ErrorBody {
+        ErrorBody {
+            error: i18n::tr(&self.to_string()),
+            details: self.details(),
+            code: self.code(),
+            request_id: request_id::current(),
+        }
+    }
+}
+
+fn error_code_schema() -> utoipa::openapi::Object {
+    utoipa::openapi::ObjectBuilder::new()
+        .schema_type(utoipa::openapi::Type::String)
+        .description(Some("Stable machine-readable error code"))
+        .enum_values(Some(AppError::CODES))
+        .build()
+}
+
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        AppError::BadRequest(rejection.body_text())
+    }
+}
+
+impl From<tower_sessions::session::Error> for AppError {
+    fn from(error: tower_sessions::session::Error) -> Self {
+        AppError::Internal(format!("Session error: {}", error))
+    }
+}
+
+fn sqlstate(error: &sqlx::Error) -> Option<String> {
+    match error {
+        sqlx::Error::Database(db) => db.code().map(|c| c.into_owned()),
+        _ => None,
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let body = self.body();
+        // Expected when a mode turns a feature off, so not worth an error log
+        if status.is_server_error() && !matches!(self, AppError::Unavailable(_)) {
+            tracing::error!("{}: {}", body.error, body.details.as_deref().unwrap_or(""));
+        }
+
+        let mut response = (status, Json(&body)).into_response();
+        // Picked up by htmx_error_fragments
+        response.extensions_mut().insert(body);
+        response
+    }
+}
+
+#[derive(Template)]
+#[template(path = "components/error.html")]
+struct ErrorFragmentTemplate {
+    body: ErrorBody,
+}
+
+/// Replace `AppError` JSON bodies with an HTML alert on HTMX requests
+pub async fn htmx_error_fragments(request: Request, next: Next) -> Response {
+    let is_htmx = request.headers().contains_key("HX-Request");
+    let response = next.run(request).await;
+    if !is_htmx {
+        return response;
+    }
+
+    let Some(body) = response.extensions().get::<ErrorBody>().cloned() else {
+        return response;
+    };
+    // Runs outside the locale middleware, which reports the request's locale
+    let locale = response
+        .headers()
+        .get(header::CONTENT_LANGUAGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(i18n::supported)
+        .unwrap_or(i18n::DEFAULT_LOCALE);
+    let rendered = locale::scope(locale, async { (ErrorFragmentTemplate { body }).render() }).await;
+    match rendered {
+        Ok(html) => (response.status(), Html(html)).into_response(),
+        Err(_) => response,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    async fn failing() -> Result<(), AppError> {
+        Err(AppError::Database(sqlx::Error::RowNotFound))
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .route("/fail", get(failing))
+            .layer(middleware::from_fn(htmx_error_fragments))
+    }
+
+    async fn body_text(response: Response) -> String {
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_error_body() {
+        let body = AppError::BadRequest("Invalid schema name".to_string()).body();
+        assert_eq!(body.error, "Invalid schema name");
+        assert_eq!(body.code, "bad_request");
+        assert!(body.details.is_none());
+        assert!(body.request_id.is_none());
+
+        let error = AppError::Database(sqlx::Error::PoolTimedOut);
+        assert_eq!(error.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(error.code(), "database_error");
+        assert!(error.details().unwrap().contains("timed out"));
+
+        let conflict = AppError::Conflict("Query not run".to_string());
+        assert_eq!(conflict.status(), StatusCode::CONFLICT);
+        assert_eq!(conflict.code(), "conflict");
+
+        let unavailable = AppError::Unavailable("Not available in demo mode".to_string());
+        assert_eq!(unavailable.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(unavailable.code(), "unavailable");
+
+        let exhausted = AppError::TooManyRequests("Daily export limit reached".to_string());
+        assert_eq!(exhausted.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(exhausted.code(), "too_many_requests");
+
+        let missing = AppError::Database(sqlx::Error::RowNotFound);
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+        assert_eq!(missing.code(), "not_found");
+    }
+
+    #[tokio::test]
+    async fn test_json_error_response() {
+        let response = router()
+            .oneshot(Request::builder().uri("/fail").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let json: serde_json::Value = serde_json::from_str(&body_text(response).await).unwrap();
+        assert_eq!(json["error"], "Database error");
+        assert_eq!(json["code"], "not_found");
+        assert!(json["details"].as_str().unwrap().contains("no rows"));
+    }
+
+    #[tokio::test]
+    async fn test_htmx_error_fragment() {
+        let response = router()
+            .oneshot(
+                Request::builder()
+                    .uri("/fail")
+                    .header("HX-Request", "true")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(response.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/html"));
+
+        let html = body_text(response).await;
+        assert!(html.contains("role=\"alert\""));
+        assert!(html.contains("Database error"));
+    }
+}
@@ -280,6 +280,42 @@ mod tests {
     // Query Parameter Simulation Tests (using path)
     // ============================================================================
 
+    // ============================================================================
+    // Base Path Tests
+    // ============================================================================
+
+    async fn status_under_base_path(base_path: &str, uri: &str) -> StatusCode {
+        let app = Router::new()
+            .route("/", get(html_handler))
+            .route("/health", get(health_handler));
+        crate::with_base_path(app, base_path)
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn test_routes_served_under_base_path() {
+        assert_eq!(
+            status_under_base_path("/pgadmin", "/pgadmin").await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status_under_base_path("/pgadmin", "/pgadmin/health").await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status_under_base_path("/pgadmin", "/pgadmin/").await,
+            StatusCode::SEE_OTHER
+        );
+        assert_eq!(
+            status_under_base_path("/pgadmin", "/health").await,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(status_under_base_path("", "/health").await, StatusCode::OK);
+    }
+
     #[tokio::test]
     async fn test_query_params_in_unknown_route() {
         let app = create_test_router();
@@ -296,6 +332,53 @@ mod tests {
 
         assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
+
+    // ============================================================================
+    // Connection Test Endpoint
+    // ============================================================================
+
+    async fn post_connection_test(body: &str) -> (StatusCode, serde_json::Value) {
+        let app = Router::new().route(
+            "/api/connections/test",
+            axum::routing::post(crate::routes::connections::test_connection),
+        );
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/connections/test")
+                    .header("content-type", "application/json")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_connection_test_reports_failure() {
+        let (status, body) = post_connection_test(
+            r#"{"host": "127.0.0.1", "port": 1, "password": "x", "ssl_mode": "disable"}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["success"], false);
+        assert!(body["latency_ms"].is_u64());
+        assert!(body["error"].as_str().unwrap().contains("127.0.0.1:1"));
+    }
+
+    #[tokio::test]
+    async fn test_connection_test_rejects_invalid_parameters() {
+        let (status, body) = post_connection_test(r#"{"ssl_mode": "sometimes"}"#).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"].as_str().unwrap().contains("ssl_mode"));
+
+        let (status, _) = post_connection_test(r#"{"password_env": "POSTGRES_PASSWORD"}"#).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
 }
 
 // ============================================================================
@@ -309,6 +392,7 @@ mod route_pattern_tests {
         let expected_routes = vec![
             // Health and pages
             ("GET", "/health"),
+            ("GET", "/health/ready"),
             ("GET", "/"),
             ("GET", "/query"),
             ("GET", "/studio"),
@@ -319,21 +403,75 @@ mod route_pattern_tests {
             ("GET", "/api/schemas/{schema}/tables"),
             ("GET", "/api/schemas/{schema}/tables/{table}"),
             ("GET", "/api/schemas/{schema}/tables/{table}/data"),
+            ("GET", "/api/schemas/{schema}/tables/{table}/profile"),
+            ("GET", "/api/schemas/{schema}/tables/{table}/duplicates"),
             // Query routes
             ("POST", "/api/query/execute"),
             ("GET", "/api/query/history"),
             ("DELETE", "/api/query/history"),
+            ("GET", "/api/query/history/groups"),
+            ("GET", "/api/query/history/analytics"),
             ("POST", "/api/query/export"),
+            ("POST", "/api/query/chart"),
+            ("POST", "/api/query/pivot"),
+            ("POST", "/api/query/compare"),
+            ("GET", "/api/query/results/{token}"),
+            ("GET", "/api/query/results/{token}/download"),
             // Schema operations
             ("POST", "/api/schema/create-table"),
             ("POST", "/api/schema/drop-object"),
+            ("POST", "/api/schema/partitioning/create"),
+            ("POST", "/api/schema/partitioning/convert"),
+            ("POST", "/api/schema/partitioning/extend"),
+            // Maintenance routes
+            ("GET", "/api/maintenance/batches"),
+            ("POST", "/api/maintenance/batches"),
+            ("GET", "/api/maintenance/batches/{id}"),
+            ("POST", "/api/maintenance/batches/{id}/cancel"),
+            ("GET", "/api/maintenance/bloated-indexes"),
             // Stats routes
             ("GET", "/api/stats/database"),
             ("GET", "/api/stats/tables"),
             ("GET", "/api/stats/cache"),
+            ("GET", "/api/stats/wal"),
+            ("GET", "/api/stats/buffer-cache"),
+            ("GET", "/api/stats/temp-files"),
+            ("GET", "/api/stats/progress"),
+            ("GET", "/api/stats/history"),
+            ("GET", "/api/stats/series"),
+            ("GET", "/api/stats/stream"),
+            ("GET", "/api/stats/database-counters"),
+            ("GET", "/api/stats/long-transactions"),
+            // Preferences
+            ("GET", "/api/preferences"),
+            ("PUT", "/api/preferences"),
+            // Admin
+            ("POST", "/api/admin/reload-config"),
+            ("GET", "/api/admin/plugins"),
+            ("GET", "/api/admin/rate-limits"),
+            ("PUT", "/api/admin/rate-limits"),
+            ("DELETE", "/api/admin/rate-limits/{ip}"),
+            ("PUT", "/api/admin/rate-limits/{ip}/exempt"),
+            ("DELETE", "/api/admin/rate-limits/{ip}/exempt"),
+            ("GET", "/api/admin/query-limits"),
+            ("GET", "/api/admin/quotas"),
+            ("GET", "/api/admin/webhooks"),
+            ("POST", "/api/admin/webhooks/{id}/test"),
+            // Server settings
+            ("GET", "/api/settings"),
+            ("PUT", "/api/settings/{name}"),
             // Cell editing
             ("GET", "/api/cell/edit"),
             ("POST", "/api/cell/update"),
+            // Connections
+            ("GET", "/api/connections"),
+            ("POST", "/api/connections/select"),
+            ("POST", "/api/connections/test"),
+            ("GET", "/api/connection/status"),
+            // Versioned JSON API
+            ("GET", "/api/v1/schemas"),
+            ("GET", "/api/v1/schemas/{schema}/tables/{table}/rows"),
+            ("POST", "/api/v1/query"),
         ];
 
         // This test documents the expected routes
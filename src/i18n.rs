@@ -0,0 +1,554 @@
+use crate::middleware::locale;
+/// Translations
+///
+/// Message catalogs are flat JSON objects in `locales/<code>.json`, compiled
+/// into the binary, that map English text to its translation. `en.json` lists
+/// every message, mapped to itself, and the other catalogs must have the same
+/// keys; the tests check both against the templates. Text missing from a
+/// catalog is shown as written, so error messages stay readable while a
+/// translation is incomplete. An entry may use `{}` for the variable parts of
+/// a message (`"Backup {} not found"`), which are carried over in order.
+///
+/// Templates translate with the `t` filter (`{{ "Query Editor"|t }}`, or
+/// `{{ "{} rows"|format(count)|t }}` for a message with variable parts, and
+/// `{{ "Query saved"|t|json|safe }}` in scripts) and server code with `tr`,
+/// both in the locale of the request being handled.
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+/// Locale used when nothing else matches
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// Supported locales with their names as shown to their speakers
+pub const LOCALES: [(&str, &str); 4] = [
+    ("en", "English"),
+    ("de", "Deutsch"),
+    ("es", "Español"),
+    ("fr", "Français"),
+];
+
+/// One locale's translations
+#[derive(Debug, Default)]
+struct Catalog {
+    exact: HashMap<String, String>,
+    /// Entries with `{}` placeholders, as the literal parts between them
+    patterns: Vec<(Vec<String>, String)>,
+}
+
+impl Catalog {
+    fn get(&self, text: &str) -> Option<String> {
+        if let Some(translation) = self.exact.get(text) {
+            return Some(translation.clone());
+        }
+        self.patterns.iter().find_map(|(parts, translation)| {
+            let args = match_pattern(parts, text)?;
+            let mut pieces = translation.split("{}");
+            let mut out = pieces.next()?.to_string();
+            for (arg, piece) in args.iter().zip(pieces.by_ref()) {
+                out.push_str(arg);
+                out.push_str(piece);
+            }
+            // A translation with a different number of placeholders is unusable
+            (translation.matches("{}").count() == args.len()).then_some(out)
+        })
+    }
+}
+
+/// The variable parts of `text` when it matches the literal `parts` of a pattern
+fn match_pattern<'a>(parts: &[String], text: &'a str) -> Option<Vec<&'a str>> {
+    let (first, rest) = parts.split_first()?;
+    let (last, middle) = rest.split_last()?;
+    let mut remaining = text.strip_prefix(first.as_str())?;
+    let mut args = Vec::with_capacity(rest.len());
+    for part in middle {
+        let at = remaining.find(part.as_str())?;
+        args.push(&remaining[..at]);
+        remaining = &remaining[at + part.len()..];
+    }
+    let arg = remaining.strip_suffix(last.as_str())?;
+    args.push(arg);
+    Some(args)
+}
+
+/// Catalog sources by locale; `en.json` maps every message to itself
+const SOURCES: [(&str, &str); 4] = [
+    ("en", include_str!("../locales/en.json")),
+    ("de", include_str!("../locales/de.json")),
+    ("es", include_str!("../locales/es.json")),
+    ("fr", include_str!("../locales/fr.json")),
+];
+
+static CATALOGS: LazyLock<HashMap<&'static str, Catalog>> = LazyLock::new(|| {
+    SOURCES
+        .into_iter()
+        .map(|(code, source)| (code, parse_catalog(code, source)))
+        .collect()
+});
+
+/// Read a catalog, keeping only string entries
+fn parse_catalog(code: &str, source: &str) -> Catalog {
+    let mut catalog = Catalog::default();
+    match serde_json::from_str::<serde_json::Map<String, Value>>(source) {
+        Ok(entries) => {
+            for (text, value) in entries {
+                let Value::String(translation) = value else {
+                    continue;
+                };
+                if text.contains("{}") {
+                    let parts = text.split("{}").map(str::to_string).collect();
+                    catalog.patterns.push((parts, translation));
+                } else {
+                    catalog.exact.insert(text, translation);
+                }
+            }
+        }
+        Err(e) => tracing::warn!("Invalid {} message catalog: {}", code, e),
+    }
+    // Try the most specific patterns first
+    catalog
+        .patterns
+        .sort_by_key(|(parts, _)| std::cmp::Reverse(parts.iter().map(String::len).sum::<usize>()));
+    catalog
+}
+
+/// `text` in `locale`, or as written when it has no translation
+pub fn translate(locale: &str, text: &str) -> String {
+    CATALOGS
+        .get(locale)
+        .and_then(|catalog| catalog.get(text))
+        .unwrap_or_else(|| text.to_string())
+}
+
+/// `text` in the current request's locale
+pub fn tr(text: &str) -> String {
+    translate(locale::current(), text)
+}
+
+/// The supported locale for a language tag (`de`, `de-AT`, `DE_de`)
+pub fn supported(tag: &str) -> Option<&'static str> {
+    let language = tag.trim().split(['-', '_']).next()?.to_ascii_lowercase();
+    LOCALES
+        .iter()
+        .map(|(code, _)| *code)
+        .find(|code| *code == language)
+}
+
+/// The supported locale the client prefers most in an `Accept-Language`
+/// header, by quality and then order
+pub fn negotiate(accept_language: &str) -> Option<&'static str> {
+    let mut ranges: Vec<(&str, f32)> = accept_language
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';');
+            let tag = parts.next()?.trim();
+            let quality = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+            (!tag.is_empty() && quality > 0.0).then_some((tag, quality))
+        })
+        .collect();
+    // Stable, so equal qualities keep the client's order
+    ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranges.into_iter().find_map(|(tag, _)| supported(tag))
+}
+
+/// Askama filters for templates, brought into scope with `use crate::i18n::filters`
+pub mod filters {
+    use std::fmt::Display;
+
+    /// Translate text into the current request's locale
+    pub fn t<T: Display>(text: T, _: &dyn askama::Values) -> askama::Result<String> {
+        Ok(super::tr(&text.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// Attributes whose values are shown to the user
+    const TEXT_ATTRIBUTES: [&str; 6] = [
+        "title",
+        "placeholder",
+        "aria-label",
+        "alt",
+        "hx-confirm",
+        "data-tip",
+    ];
+
+    /// Words that read the same in every language: SQL, settings, units
+    const UNTRANSLATED: &[&str] = &[
+        "avg",
+        "count",
+        "CSV",
+        "DDL",
+        "DEFAULT",
+        "DOT",
+        "false",
+        "FROM",
+        "information_schema.tables",
+        "JSON",
+        "LIMIT",
+        "max",
+        "Mermaid",
+        "min",
+        "ms",
+        "NOT",
+        "NULL",
+        "openapi.json",
+        "p50",
+        "p95",
+        "PID",
+        "PK",
+        "public",
+        "s",
+        "SELECT",
+        "SQL",
+        "sum",
+        "table_schema",
+        "toast",
+        "true",
+        "USING",
+        "UTC",
+        "WHERE",
+        "WITH",
+        "CHECK",
+        "work_mem",
+    ];
+
+    /// Runs of text a template shows as written, in the page and in
+    /// user-facing attributes; scripts, comments and template code end a run
+    fn literal_text(source: &str) -> Vec<String> {
+        let mut texts = Vec::new();
+        let mut text = String::new();
+        let mut rest = source;
+        while let Some(c) = rest.chars().next() {
+            let after = |end: &str| rest.find(end).map_or(rest.len(), |at| at + end.len());
+            let skip = if rest.starts_with("{% macro") {
+                // Macros build URLs
+                after("{% endmacro %}")
+            } else if rest.starts_with("{{") {
+                after("}}")
+            } else if rest.starts_with("{%") {
+                after("%}")
+            } else if rest.starts_with("{#") {
+                after("#}")
+            } else if rest.starts_with("<!--") {
+                after("-->")
+            } else if rest.starts_with("<script") {
+                after("</script>")
+            } else if rest.starts_with("<style") {
+                after("</style>")
+            } else if c == '<'
+                && rest[1..]
+                    .starts_with(|n: char| n.is_ascii_alphabetic() || matches!(n, '/' | '!'))
+            {
+                let tag = &rest[..tag_len(rest)];
+                for value in attribute_values(tag) {
+                    texts.extend(literal_text(value));
+                }
+                tag.len()
+            } else {
+                text.push(c);
+                rest = &rest[c.len_utf8()..];
+                continue;
+            };
+            texts.push(std::mem::take(&mut text));
+            rest = &rest[skip..];
+        }
+        texts.push(text);
+        texts
+            .iter()
+            .map(|text| {
+                strip_entities(text)
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .filter(|text| {
+                text.split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+                    .map(|word| word.trim_matches('.'))
+                    .any(|word| {
+                        word.chars().any(char::is_alphabetic) && !UNTRANSLATED.contains(&word)
+                    })
+            })
+            .collect()
+    }
+
+    /// Length of the tag at the start of `source`, which may hold template code
+    fn tag_len(source: &str) -> usize {
+        let mut quote = None;
+        let mut at = 1;
+        while let Some(c) = source[at..].chars().next() {
+            if let Some(end) = ["{{", "{%"]
+                .iter()
+                .zip(["}}", "%}"])
+                .find_map(|(open, close)| source[at..].starts_with(open).then_some(close))
+            {
+                at += source[at..]
+                    .find(end)
+                    .map_or(source.len() - at, |i| i + end.len());
+                continue;
+            }
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (None, '"' | '\'') => quote = Some(c),
+                (None, '>') => return at + 1,
+                _ => {}
+            }
+            at += c.len_utf8();
+        }
+        source.len()
+    }
+
+    /// Values of the user-facing attributes of a tag
+    fn attribute_values(tag: &str) -> Vec<&str> {
+        let mut values = Vec::new();
+        for name in TEXT_ATTRIBUTES {
+            let pattern = format!("{}=", name);
+            for (at, _) in tag.match_indices(&pattern) {
+                if !tag[..at].ends_with(char::is_whitespace) {
+                    continue;
+                }
+                let value = &tag[at + pattern.len()..];
+                let Some(quote) = value.chars().next().filter(|c| matches!(c, '"' | '\'')) else {
+                    continue;
+                };
+                // The closing quote is the first one outside template code
+                let end = tag_len(&format!("<{}>", &value[1..].replace(quote, ">")));
+                values.push(&value[1..end - 1]);
+            }
+        }
+        values
+    }
+
+    /// Text with HTML entities such as `&rarr;` removed
+    fn strip_entities(text: &str) -> String {
+        let mut out = String::new();
+        let mut rest = text;
+        while let Some(at) = rest.find('&') {
+            out.push_str(&rest[..at]);
+            rest = &rest[at..];
+            match rest.find(';').filter(|end| {
+                rest[1..*end]
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '#')
+            }) {
+                Some(end) => rest = &rest[end + 1..],
+                None => {
+                    out.push('&');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+
+    /// Messages a template translates: the string literal that starts each
+    /// `{{ ... }}` expression using the `t` filter
+    fn translated_messages(source: &str) -> Vec<String> {
+        let mut messages = Vec::new();
+        for (at, _) in source.match_indices("{{") {
+            let expression = source[at + 2..].trim_start();
+            let Some(literal) = expression.strip_prefix('"') else {
+                continue;
+            };
+            let mut message = String::new();
+            let mut chars = literal.char_indices();
+            let mut end = literal.len();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '\\' => message.extend(chars.next().map(|(_, c)| c)),
+                    '"' => {
+                        end = i + 1;
+                        break;
+                    }
+                    c => message.push(c),
+                }
+            }
+            let filters = &literal[end..literal.find("}}").unwrap_or(literal.len())];
+            // Filters after the literal, with any strings in their arguments left out
+            let filters: String = filters.split('"').step_by(2).collect();
+            if filters.split('|').skip(1).any(|filter| {
+                filter
+                    .trim_start()
+                    .split(|c: char| !c.is_alphanumeric())
+                    .next()
+                    == Some("t")
+            }) {
+                messages.push(message);
+            }
+        }
+        messages
+    }
+
+    /// Every template under `templates/` with its path
+    fn templates() -> Vec<(String, String)> {
+        fn walk(dir: &Path, out: &mut Vec<(String, String)>) {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    walk(&path, out);
+                } else {
+                    out.push((
+                        path.display().to_string(),
+                        std::fs::read_to_string(&path).unwrap(),
+                    ));
+                }
+            }
+        }
+        let mut out = Vec::new();
+        walk(
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("templates"),
+            &mut out,
+        );
+        out
+    }
+
+    fn catalog_entries(source: &str) -> serde_json::Map<String, Value> {
+        serde_json::from_str(source).unwrap()
+    }
+
+    #[test]
+    fn test_templates_are_translated() {
+        let english = catalog_entries(SOURCES[0].1);
+        let mut untranslated = Vec::new();
+        let mut missing = Vec::new();
+        for (path, source) in templates() {
+            for text in literal_text(&source) {
+                untranslated.push(format!("{}: {}", path, text));
+            }
+            for message in translated_messages(&source) {
+                if !english.contains_key(&message) {
+                    missing.push(format!("{}: {}", path, message));
+                }
+            }
+        }
+        assert!(
+            untranslated.is_empty(),
+            "Template text not passed through the t filter:\n{}",
+            untranslated.join("\n")
+        );
+        assert!(
+            missing.is_empty(),
+            "Template messages missing from locales/en.json:\n{}",
+            missing.join("\n")
+        );
+    }
+
+    #[test]
+    fn test_catalogs_match() {
+        let (_, english) = SOURCES[0];
+        let english = catalog_entries(english);
+        for (message, text) in &english {
+            assert_eq!(text.as_str(), Some(message.as_str()), "en: {}", message);
+        }
+        for (code, source) in &SOURCES[1..] {
+            let catalog = catalog_entries(source);
+            let missing: Vec<_> = english
+                .keys()
+                .filter(|m| !catalog.contains_key(*m))
+                .collect();
+            let extra: Vec<_> = catalog
+                .keys()
+                .filter(|m| !english.contains_key(*m))
+                .collect();
+            assert!(
+                missing.is_empty(),
+                "{} catalog is missing {:?}",
+                code,
+                missing
+            );
+            assert!(
+                extra.is_empty(),
+                "{} catalog has unknown messages {:?}",
+                code,
+                extra
+            );
+            for (message, translation) in catalog {
+                let translation = translation.as_str().unwrap_or_default();
+                assert_eq!(
+                    translation.matches("{}").count(),
+                    message.matches("{}").count(),
+                    "{}: {} => {}",
+                    code,
+                    message,
+                    translation
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_template_scanning() {
+        let source = r#"<p title="{{ "Owner: {}"|format(o)|t }}" class="x">Hi {{ name }}</p>
+            <span data-tip='Refresh'>{% if a %}{{ "Yes"|t }}{% endif %} &rarr; NULL</span>
+            <script>alert({{ "Saved"|t|json|safe }}, "Not checked")</script>{# Comment #}
+            <a href="x">{{ "{:.2}"|format(cost) }} ms</a>"#;
+        assert_eq!(literal_text(source), vec!["Hi", "Refresh"]);
+        assert_eq!(
+            translated_messages(source),
+            vec!["Owner: {}", "Yes", "Saved"]
+        );
+    }
+
+    #[test]
+    fn test_catalogs_load() {
+        for (code, _) in LOCALES {
+            let catalog = CATALOGS.get(code).expect(code);
+            assert!(!catalog.exact.is_empty(), "{} catalog is empty", code);
+        }
+        assert_eq!(translate("de", "Query Editor"), "Abfrage-Editor");
+        assert_eq!(translate("en", "Query Editor"), "Query Editor");
+        assert_eq!(translate("de", "Not in any catalog"), "Not in any catalog");
+        assert_eq!(translate("xx", "Query Editor"), "Query Editor");
+    }
+
+    #[test]
+    fn test_patterns() {
+        let catalog = parse_catalog(
+            "test",
+            r#"{
+                "Backup {} not found": "Sicherung {} nicht gefunden",
+                "Failed to open backup {}: {}": "Sicherung {} konnte nicht geöffnet werden: {}",
+                "Plan {} not found": "Plan nicht gefunden"
+            }"#,
+        );
+        assert_eq!(
+            catalog.get("Backup nightly.dump not found").as_deref(),
+            Some("Sicherung nightly.dump nicht gefunden")
+        );
+        assert_eq!(
+            catalog
+                .get("Failed to open backup a.sql: No such file")
+                .as_deref(),
+            Some("Sicherung a.sql konnte nicht geöffnet werden: No such file")
+        );
+        // Placeholders must line up
+        assert_eq!(catalog.get("Plan 7 not found"), None);
+        assert_eq!(catalog.get("Backup schedule 3 is gone"), None);
+    }
+
+    #[test]
+    fn test_supported() {
+        assert_eq!(supported("de"), Some("de"));
+        assert_eq!(supported("de-AT"), Some("de"));
+        assert_eq!(supported(" FR_ca "), Some("fr"));
+        assert_eq!(supported("pt-BR"), None);
+        assert_eq!(supported(""), None);
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate("de-DE,de;q=0.9,en;q=0.8"), Some("de"));
+        assert_eq!(negotiate("pt-BR, es;q=0.5, en;q=0.4"), Some("es"));
+        assert_eq!(negotiate("en;q=0.2, fr;q=0.7"), Some("fr"));
+        assert_eq!(negotiate("fr;q=0, es"), Some("es"));
+        assert_eq!(negotiate("*"), None);
+        assert_eq!(negotiate("ja, zh;q=bad"), None);
+        assert_eq!(negotiate(""), None);
+    }
+}
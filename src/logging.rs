@@ -0,0 +1,353 @@
+use crate::config::LogFormat;
+use crate::middleware::client_ip::{resolve_client_ip, ClientIp, TrustedProxies};
+use crate::middleware::request_id::RequestId;
+use crate::services::sql_lexer::fingerprint;
+/// Logging
+///
+/// Sets up the tracing subscriber in the configured format. `LOG_FORMAT=json`
+/// writes one JSON object per line with the request span's fields (`route`,
+/// `client_ip`, `request_id`, `query_id`) merged in, for ingestion by Loki / ELK.
+///
+/// Requests and queries slower than `SLOW_REQUEST_THRESHOLD_MS` /
+/// `SLOW_QUERY_THRESHOLD_MS` are logged at WARN.
+///
+/// The `RUST_LOG` filter sits behind a reload layer so a configuration reload
+/// can change it at runtime.
+use axum::{
+    extract::{ConnectInfo, MatchedPath},
+    http::{Request, Response},
+};
+use serde_json::{Map, Value};
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tower_http::trace::OnResponse;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Span, Subscriber};
+use tracing_subscriber::{
+    fmt::{format::Writer, FmtContext, FormatEvent, FormatFields, FormattedFields},
+    layer::SubscriberExt,
+    registry::LookupSpan,
+    reload,
+    util::SubscriberInitExt,
+    EnvFilter, Registry,
+};
+
+/// Filter used when `RUST_LOG` is not set
+pub const DEFAULT_FILTER: &str = "pgadmin_rs=debug,tower_http=debug";
+
+/// Handle for replacing the log filter of the installed subscriber
+pub type LogFilterHandle = reload::Handle<EnvFilter, Registry>;
+
+/// Install the global subscriber, returning a handle to its log filter
+pub fn init(format: LogFormat) -> LogFilterHandle {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| DEFAULT_FILTER.into());
+    let (filter, handle) = reload::Layer::new(filter);
+    let registry = tracing_subscriber::registry().with(filter);
+
+    match format {
+        LogFormat::Text => registry.with(tracing_subscriber::fmt::layer()).init(),
+        LogFormat::Json => registry
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .event_format(JsonFormat)
+                    .fmt_fields(JsonFields),
+            )
+            .init(),
+    }
+    handle
+}
+
+/// Replace the log filter with new `RUST_LOG` directives
+pub fn set_filter(handle: &LogFilterHandle, directives: &str) -> Result<(), String> {
+    let filter = EnvFilter::try_new(directives)
+        .map_err(|e| format!("Invalid RUST_LOG filter '{}': {}", directives, e))?;
+    handle
+        .reload(filter)
+        .map_err(|e| format!("Failed to apply log filter: {}", e))
+}
+
+/// Span for one HTTP request; handlers fill in `query_id` when they run a query
+pub fn request_span<B>(request: &Request<B>) -> Span {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str())
+        .unwrap_or_else(|| request.uri().path());
+    let client_ip = match request.extensions().get::<ClientIp>() {
+        Some(ClientIp(ip)) => ip.clone(),
+        None => {
+            let peer = request
+                .extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| addr.ip());
+            resolve_client_ip(peer, request.headers(), &TrustedProxies::default())
+        }
+    };
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(|RequestId(id)| id.as_str())
+        .unwrap_or("-");
+
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        route = %route,
+        path = %request.uri().path(),
+        client_ip = %client_ip,
+        request_id = %request_id,
+        query_id = tracing::field::Empty,
+    )
+}
+
+/// Logs the status and duration of each finished request, at WARN when it
+/// took longer than `slow_threshold_ms`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResponseLogger {
+    pub slow_threshold_ms: Option<u64>,
+}
+
+impl<B> OnResponse<B> for ResponseLogger {
+    fn on_response(self, response: &Response<B>, latency: Duration, _span: &Span) {
+        let status = response.status().as_u16();
+        let duration_ms = latency.as_millis() as u64;
+        if is_slow(duration_ms, self.slow_threshold_ms) {
+            tracing::warn!(status, duration_ms, "slow request");
+        } else {
+            tracing::info!(status, duration_ms, "request completed");
+        }
+    }
+}
+
+/// Warn about a SQL execution that took longer than `threshold_ms`
+///
+/// The statement is logged as its fingerprint, so literal values stay out of
+/// the logs and repeated executions of one query are easy to group.
+pub fn log_slow_query(sql: &str, duration_ms: u64, threshold_ms: Option<u64>) {
+    if is_slow(duration_ms, threshold_ms) {
+        tracing::warn!(
+            fingerprint = %fingerprint(sql),
+            duration_ms,
+            "slow query"
+        );
+    }
+}
+
+fn is_slow(duration_ms: u64, threshold_ms: Option<u64>) -> bool {
+    threshold_ms.is_some_and(|threshold| duration_ms > threshold)
+}
+
+/// Attach a query history id to the current request's span
+pub fn record_query_id(id: &str) {
+    Span::current().record("query_id", id);
+}
+
+/// Collects event or span fields into a JSON object
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(
+            field.name().to_string(),
+            Value::from(format!("{:?}", value)),
+        );
+    }
+}
+
+/// Formats span fields as a JSON object so the event formatter can merge them
+pub struct JsonFields;
+
+impl<'writer> FormatFields<'writer> for JsonFields {
+    fn format_fields<R: tracing_subscriber::field::RecordFields>(
+        &self,
+        mut writer: Writer<'writer>,
+        fields: R,
+    ) -> fmt::Result {
+        let mut map = Map::new();
+        fields.record(&mut JsonVisitor(&mut map));
+        write!(writer, "{}", Value::Object(map))
+    }
+
+    fn add_fields(
+        &self,
+        current: &'writer mut FormattedFields<Self>,
+        fields: &tracing::span::Record<'_>,
+    ) -> fmt::Result {
+        let mut map = parse_fields(&current.fields);
+        fields.record(&mut JsonVisitor(&mut map));
+        current.fields = Value::Object(map).to_string();
+        Ok(())
+    }
+}
+
+fn parse_fields(fields: &str) -> Map<String, Value> {
+    match serde_json::from_str(fields) {
+        Ok(Value::Object(map)) => map,
+        _ => Map::new(),
+    }
+}
+
+/// One JSON object per event: timestamp, level, target, span fields
+/// (outermost first) and the event's own fields
+pub struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let metadata = event.metadata();
+        let mut map = Map::new();
+        map.insert(
+            "timestamp".to_string(),
+            Value::from(chrono::Utc::now().to_rfc3339()),
+        );
+        map.insert("level".to_string(), Value::from(metadata.level().as_str()));
+        map.insert("target".to_string(), Value::from(metadata.target()));
+
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                if let Some(fields) = span.extensions().get::<FormattedFields<N>>() {
+                    map.extend(parse_fields(&fields.fields));
+                }
+            }
+        }
+        event.record(&mut JsonVisitor(&mut map));
+
+        writeln!(writer, "{}", Value::Object(map))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn capture(f: impl FnOnce()) -> Vec<Value> {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .event_format(JsonFormat)
+            .fmt_fields(JsonFields)
+            .with_writer(move || writer.clone())
+            .finish();
+        tracing::subscriber::with_default(subscriber, f);
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_json_event_includes_span_fields() {
+        let lines = capture(|| {
+            let span = tracing::info_span!(
+                "request",
+                route = "/api/v1/query",
+                client_ip = "203.0.113.7",
+                query_id = tracing::field::Empty,
+            );
+            let _guard = span.enter();
+            record_query_id("3f2a");
+            tracing::info!(status = 200u16, duration_ms = 12u64, "request completed");
+        });
+
+        assert_eq!(lines.len(), 1);
+        let line = &lines[0];
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["message"], "request completed");
+        assert_eq!(line["route"], "/api/v1/query");
+        assert_eq!(line["client_ip"], "203.0.113.7");
+        assert_eq!(line["query_id"], "3f2a");
+        assert_eq!(line["status"], 200);
+        assert_eq!(line["duration_ms"], 12);
+        assert!(line["timestamp"].as_str().is_some());
+    }
+
+    #[test]
+    fn test_json_event_without_span() {
+        let lines = capture(|| tracing::warn!(path = ?"/tmp/x", "Ignoring \"quoted\" value"));
+
+        assert_eq!(lines[0]["level"], "WARN");
+        assert_eq!(lines[0]["message"], "Ignoring \"quoted\" value");
+        assert_eq!(lines[0]["path"], "\"/tmp/x\"");
+        assert!(lines[0].get("query_id").is_none());
+    }
+
+    #[test]
+    fn test_slow_query_logged_with_fingerprint() {
+        let lines = capture(|| {
+            log_slow_query("SELECT * FROM t WHERE id = 7", 250, Some(100));
+            log_slow_query("SELECT 1", 50, Some(100));
+            log_slow_query("SELECT 2", 5000, None);
+        });
+
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["level"], "WARN");
+        assert_eq!(lines[0]["message"], "slow query");
+        assert_eq!(lines[0]["fingerprint"], "SELECT * FROM t WHERE id = ?");
+        assert_eq!(lines[0]["duration_ms"], 250);
+    }
+
+    #[test]
+    fn test_slow_request_logged_at_warn() {
+        let response = Response::new(());
+        let logger = ResponseLogger {
+            slow_threshold_ms: Some(100),
+        };
+        let lines = capture(|| {
+            logger.on_response(&response, Duration::from_millis(20), &Span::none());
+            logger.on_response(&response, Duration::from_millis(150), &Span::none());
+        });
+
+        assert_eq!(lines[0]["level"], "INFO");
+        assert_eq!(lines[0]["message"], "request completed");
+        assert_eq!(lines[1]["level"], "WARN");
+        assert_eq!(lines[1]["message"], "slow request");
+        assert_eq!(lines[1]["duration_ms"], 150);
+    }
+}
@@ -1,9 +1,14 @@
 mod config;
+mod error;
 mod handlers;
+mod i18n;
+mod logging;
 mod middleware;
 mod models;
+mod plugins;
 mod routes;
 mod services;
+mod tls;
 
 #[cfg(test)]
 mod security_tests;
@@ -14,84 +19,481 @@ mod routes_tests;
 #[cfg(test)]
 mod http_tests;
 
+#[cfg(test)]
+mod quota_tests;
+
 use axum::{
     extract::DefaultBodyLimit,
     middleware as axum_middleware,
-    routing::{delete, get, post},
+    routing::{delete, get, patch, post, put},
+    serve::ListenerExt,
     Router,
 };
+use std::future::IntoFuture;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tower::ServiceBuilder;
 use tower_http::{cors::CorsLayer, services::ServeDir, trace::TraceLayer};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[derive(Clone)]
 pub struct AppState {
     pub db_pool: Arc<sqlx::Pool<sqlx::Postgres>>,
+    pub db_monitor: Arc<services::db_service::DatabaseMonitor>,
     pub audit_logger: Arc<services::audit_service::AuditLogger>,
     pub query_history: Arc<services::query_history::QueryHistory>,
+    pub saved_queries: Arc<services::saved_query_service::SavedQueryStore>,
+    pub plan_history: Arc<services::plan_history::PlanHistory>,
+    pub scheduler: Arc<services::scheduler_service::Scheduler>,
+    pub definitions: Arc<services::definitions_service::Definitions>,
+    pub transactions: Arc<services::transaction_service::TransactionManager>,
+    pub branding: Arc<config::Branding>,
+    pub artifact_store: Arc<services::artifact_service::ArtifactStore>,
+    pub result_store: Arc<services::result_store::ResultStore>,
+    pub metadata_cache: Arc<services::metadata_cache::MetadataCache>,
+    pub counter_history: Arc<services::stats_service::CounterHistory>,
+    pub metrics_history: Arc<services::metrics_history::MetricsHistory>,
+    pub connections: Arc<services::connection_service::ConnectionRegistry>,
+    pub read_only_mode: config::reload::ReadOnlyMode,
+    pub rate_limit: Arc<middleware::rate_limit::RateLimitState>,
+    pub query_limiter: Arc<middleware::query_limit::QueryLimiter>,
+    pub config_reloader: Arc<config::reload::ConfigReloader>,
+    pub cost_thresholds: services::query_service::CostThresholds,
+    pub slow_query_threshold_ms: Option<u64>,
+    pub restore_jobs: Arc<services::restore_service::RestoreJobs>,
+    pub maintenance: Arc<services::maintenance_service::MaintenanceQueue>,
+    pub backups: Arc<services::backup_service::BackupManager>,
+    pub webhooks: Arc<services::webhook_service::Webhooks>,
+    pub quotas: Arc<services::quota_service::Quotas>,
+    /// Sample data served instead of the database in demo mode
+    pub demo: Option<Arc<services::demo_service::DemoCatalog>>,
 }
 
 #[tokio::main]
 async fn main() {
-    // Initialize tracing for logging
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "pgadmin_rs=debug,tower_http=debug".into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
-
-    // Load configuration
+    // Load configuration (first, so LOG_FORMAT can come from .env)
     let config = config::Config::from_env();
 
+    // Initialize tracing for logging
+    let log_filter = logging::init(config.log_format);
+
     tracing::info!("Starting pgAdmin-rs server on {}", config.server_address);
-    tracing::info!(
-        "Connecting to PostgreSQL at {}:{}/{}",
-        config.postgres_host,
-        config.postgres_port,
-        config.postgres_db
-    );
+    for plugin in plugins::all() {
+        tracing::info!("Plugin {} enabled", plugin.name());
+    }
 
-    // Create database pool
-    let db_pool = match services::db_service::create_pool(&config).await {
-        Ok(pool) => pool,
+    // In demo mode nothing connects to the database; the pools below are
+    // still created (lazily) but background work that would use them is not
+    // started
+    let demo = config.demo_mode.then(|| {
+        Arc::new(services::demo_service::DemoCatalog::load().unwrap_or_else(|e| panic!("{}", e)))
+    });
+    if demo.is_some() {
+        tracing::info!("Demo mode: serving sample data instead of a database");
+    } else {
+        tracing::info!(
+            "Connecting to PostgreSQL at {}:{}/{}",
+            config.postgres_host,
+            config.postgres_port,
+            config.postgres_db
+        );
+    }
+
+    // Shared so a configuration reload can switch it everywhere at once
+    let read_only_mode = config::reload::ReadOnlyMode::new(config.read_only_mode);
+
+    // Create the database pool; connections open lazily, so startup does not
+    // depend on the database being up yet
+    let db_pool = match config.connect_options() {
+        Ok(options) => services::db_service::create_pool(options, read_only_mode.clone()),
         Err(e) => {
-            eprintln!("\n❌ Failed to create database pool");
+            eprintln!("\n❌ Invalid database configuration");
             eprintln!("Error: {}", e);
             eprintln!("\nConnection details:");
             eprintln!("  Host: {}", config.postgres_host);
             eprintln!("  Port: {}", config.postgres_port);
             eprintln!("  User: {}", config.postgres_user);
             eprintln!("  Database: {}", config.postgres_db);
-            eprintln!("\nPlease check:");
-            eprintln!("  1. PostgreSQL is running");
-            eprintln!("  2. Host/port are correct in .env");
-            eprintln!("  3. Username and password are correct");
-            eprintln!("  4. Database exists (or use 'postgres' as default)");
             std::process::exit(1);
         }
     };
 
-    // Test database connection
-    if let Err(e) = services::db_service::test_connection(&db_pool).await {
-        eprintln!("\n❌ Failed to connect to database");
-        eprintln!("Error: {}", e);
-        std::process::exit(1);
+    // Keep trying to reach the database in the background, with backoff
+    let db_monitor = Arc::new(services::db_service::DatabaseMonitor::new(
+        std::time::Duration::from_secs(config.db_health_check_interval_secs.max(1)),
+    ));
+    if demo.is_none() {
+        db_monitor.spawn(db_pool.clone());
     }
 
-    tracing::info!("Connected to PostgreSQL database");
+    // Cache sidebar metadata (table lists, approximate counts and sizes)
+    let metadata_cache_ttl = std::time::Duration::from_secs(config.metadata_cache_ttl_secs);
+    let metadata_cache = Arc::new(services::metadata_cache::MetadataCache::new(
+        metadata_cache_ttl,
+    ));
+
+    // Register the default connection and the named ones from CONNECTIONS_FILE,
+    // each with its own lazily connecting pool and metadata cache
+    let db_pool = Arc::new(db_pool);
+    let default_profile = services::connection_service::ConnectionProfile::from_config(&config);
+    let default_id = default_profile.id.clone();
+    let named_connections =
+        services::connection_service::load_connections(&config.connections_file)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e));
+    let mut profiles = vec![default_profile];
+    let counter_history = Arc::new(services::stats_service::CounterHistory::new());
+    let mut handles = vec![services::connection_service::ConnectionHandle {
+        pool: db_pool.clone(),
+        metadata_cache: metadata_cache.clone(),
+        counter_history: counter_history.clone(),
+    }];
+    for connection in &named_connections {
+        let options = connection
+            .connect_options()
+            .unwrap_or_else(|e| panic!("{}", e));
+        profiles.push(connection.profile(&options));
+        handles.push(services::connection_service::ConnectionHandle {
+            pool: Arc::new(services::db_service::create_pool(
+                options,
+                read_only_mode.clone(),
+            )),
+            metadata_cache: Arc::new(services::metadata_cache::MetadataCache::new(
+                metadata_cache_ttl,
+            )),
+            counter_history: Arc::new(services::stats_service::CounterHistory::new()),
+        });
+    }
+    if !named_connections.is_empty() {
+        tracing::info!(
+            "Loaded {} named connections from {}",
+            named_connections.len(),
+            config.connections_file
+        );
+    }
+    let mut registry = services::connection_service::ConnectionRegistry::new(profiles.clone());
+    for (profile, handle) in profiles.iter().zip(handles) {
+        // Sample deadlock and error counters of each connection in use
+        if demo.is_none() {
+            handle.counter_history.spawn_sampler(handle.pool.clone());
+        }
+        registry = registry.with_handle(&profile.id, handle);
+    }
+    let connections = Arc::new(registry);
+
+    // Probe the default connection's optional capabilities once it is reachable
+    {
+        let db_monitor = db_monitor.clone();
+        let connections = connections.clone();
+        let db_pool = db_pool.clone();
+        tokio::spawn(async move {
+            db_monitor.wait_available().await;
+            match services::connection_service::probe_capabilities(&db_pool).await {
+                Ok(capabilities) => {
+                    tracing::info!(
+                        "PostgreSQL {} (pg_stat_statements: {}, superuser: {})",
+                        capabilities.server_version,
+                        capabilities.pg_stat_statements,
+                        capabilities.is_superuser
+                    );
+                    connections
+                        .set_capabilities(&default_id, capabilities)
+                        .await;
+                }
+                Err(e) => tracing::warn!("{}", e),
+            }
+        });
+    }
+
+    // Create audit logger (keeps the last AUDIT_MAX_EVENTS events) and forward to any configured sinks
+    let mut audit_sinks = Vec::new();
+    if let Some(addr) = &config.audit_syslog_addr {
+        audit_sinks.push(services::audit_sink::AuditSink::Syslog {
+            addr: addr.clone(),
+            app_name: "pgadmin-rs".to_string(),
+        });
+    }
+    if let Some(url) = &config.audit_webhook_url {
+        audit_sinks.push(
+            services::audit_sink::AuditSink::webhook(url).unwrap_or_else(|e| panic!("{}", e)),
+        );
+    }
+    audit_sinks.extend(
+        plugins::audit_sinks(&config)
+            .unwrap_or_else(|e| panic!("{}", e))
+            .into_iter()
+            .map(services::audit_sink::AuditSink::Plugin),
+    );
+    // Notify the endpoints in WEBHOOKS_FILE of application events
+    let webhooks = Arc::new(
+        services::webhook_service::Webhooks::load(
+            &config.webhooks_file,
+            &services::webhook_service::RETRY_DELAYS,
+        )
+        .await
+        .unwrap_or_else(|e| panic!("{}", e)),
+    );
+    for endpoint in webhooks.describe() {
+        tracing::info!("Sending webhooks to {}", endpoint);
+    }
+
+    // Row and export limits per role, from QUOTAS_FILE
+    let quotas = Arc::new(
+        services::quota_service::Quotas::load(&config.quotas_file)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e)),
+    );
+    if quotas.is_enabled() {
+        tracing::info!("Quotas enabled from {}", config.quotas_file);
+    }
 
-    // Create audit logger (stores last 1000 events)
-    let audit_logger = Arc::new(services::audit_service::AuditLogger::new(1000));
+    let mut audit_logger = services::audit_service::AuditLogger::new(config.audit_max_events);
+    if config.audit_retention_days > 0 {
+        audit_logger =
+            audit_logger.with_max_age(chrono::Duration::days(config.audit_retention_days as i64));
+    }
+    if !audit_sinks.is_empty() {
+        for sink in &audit_sinks {
+            tracing::info!("Forwarding audit events to {}", sink.describe());
+        }
+        audit_logger =
+            audit_logger.with_forwarder(services::audit_sink::AuditForwarder::spawn(audit_sinks));
+    }
+    if !webhooks.is_empty() {
+        audit_logger = audit_logger.with_webhooks(webhooks.clone());
+    }
+    let audit_logger = Arc::new(audit_logger);
     tracing::info!("Audit logging system initialized");
 
     // Create query history manager (stores last 500 queries)
     let query_history = Arc::new(services::query_history::QueryHistory::new(500));
     tracing::info!("Query history system initialized");
 
+    // Load saved queries shown in the query editor sidebar
+    let saved_queries = Arc::new(
+        services::saved_query_service::SavedQueryStore::load(&config.saved_queries_file)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e)),
+    );
+    tracing::info!("Saved queries loaded from {}", config.saved_queries_file);
+
+    // Load EXPLAIN plans captured for comparison
+    let plan_history = Arc::new(
+        services::plan_history::PlanHistory::load(&config.plan_history_file)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e)),
+    );
+
+    // Sample dashboard metrics of the default connection for trend charts
+    let metrics_history = Arc::new(
+        services::metrics_history::MetricsHistory::load(
+            &config.metrics_history_file,
+            std::time::Duration::from_secs(config.metrics_retention_hours * 3600),
+        )
+        .await
+        .unwrap_or_else(|e| panic!("{}", e)),
+    );
+    if demo.is_none() {
+        metrics_history.spawn_sampler(
+            db_pool.clone(),
+            std::time::Duration::from_secs(config.metrics_sample_interval_secs.max(1)),
+        );
+        tracing::info!(
+            "Sampling metrics every {}s into {}",
+            config.metrics_sample_interval_secs.max(1),
+            config.metrics_history_file
+        );
+    }
+
+    // Create artifact store for exports and backups written to disk
+    let mut artifact_store = services::artifact_service::ArtifactStore::new(
+        &config.artifact_dir,
+        services::artifact_service::RetentionPolicy {
+            max_age: std::time::Duration::from_secs(config.artifact_max_age_hours * 60 * 60),
+            max_total_bytes: config.artifact_max_total_mb * 1024 * 1024,
+        },
+    );
+    tracing::info!(
+        "Artifact store at {} (max age {}h, max size {} MB)",
+        config.artifact_dir,
+        config.artifact_max_age_hours,
+        config.artifact_max_total_mb
+    );
+
+    // Offload artifacts to an S3-compatible bucket if one is configured
+    if let Some(s3_config) = services::object_store::S3Config::from_config(&config) {
+        let object_store = s3_config
+            .and_then(services::object_store::ObjectStore::new)
+            .unwrap_or_else(|e| panic!("{}", e));
+        tracing::info!(
+            "Offloading artifacts to {}{}",
+            object_store.describe(),
+            if config.s3_keep_local {
+                " (keeping local copies)"
+            } else {
+                ""
+            }
+        );
+        artifact_store = artifact_store.with_object_store(object_store, config.s3_keep_local);
+    }
+    let artifact_store = Arc::new(artifact_store);
+
+    // Keep large console results on disk for download instead of re-running them
+    let result_store = Arc::new(services::result_store::ResultStore::new(
+        artifact_store.clone(),
+        config.result_persist_rows,
+        std::time::Duration::from_secs(config.result_ttl_minutes * 60),
+    ));
+
+    // Periodically apply the artifact retention policy and expire stored results
+    {
+        let artifact_store = artifact_store.clone();
+        let result_store = result_store.clone();
+        let interval_secs = config.artifact_cleanup_interval_secs.max(1);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+            loop {
+                interval.tick().await;
+                if let Err(e) = result_store.prune_expired().await {
+                    tracing::warn!("Expiring stored results failed: {}", e);
+                }
+                if let Err(e) = artifact_store.prune().await {
+                    tracing::warn!("Artifact cleanup failed: {}", e);
+                }
+            }
+        });
+    }
+
+    // Take pg_dump backups into the artifact store on their schedules
+    let backups = Arc::new(services::backup_service::BackupManager::new(
+        services::backup_service::BackupScheduleStore::load(&config.backup_schedules_file)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e)),
+        artifact_store.clone(),
+        audit_logger.clone(),
+        services::backup_service::DumpConnection::from_config(&config),
+        config.pg_dump_path.clone(),
+    ));
+    if demo.is_none() {
+        backups.spawn();
+        tracing::info!(
+            "Backup scheduler started with schedules from {}",
+            config.backup_schedules_file
+        );
+    }
+
+    // Periodically apply the audit retention policy
+    {
+        let audit_logger = audit_logger.clone();
+        let interval_secs = config.audit_prune_interval_secs.max(1);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+            loop {
+                interval.tick().await;
+                let report = audit_logger.prune().await;
+                if report.deleted > 0 {
+                    tracing::info!("Pruned {} audit events", report.deleted);
+                }
+            }
+        });
+    }
+
+    // Run saved queries on their cron schedules
+    let mut scheduler = services::scheduler_service::Scheduler::new(
+        services::scheduler_service::ScheduleStore::load(&config.schedules_file)
+            .await
+            .unwrap_or_else(|e| panic!("{}", e)),
+        saved_queries.clone(),
+        db_pool.clone(),
+        audit_logger.clone(),
+        read_only_mode.clone(),
+    );
+    if let Some(host) = &config.smtp_host {
+        tracing::info!(
+            "Emailing scheduled results via SMTP {}:{}",
+            host,
+            config.smtp_port
+        );
+        scheduler = scheduler.with_smtp(services::notify::SmtpConfig {
+            host: host.clone(),
+            port: config.smtp_port,
+            from: config.smtp_from.clone(),
+        });
+    }
+    let scheduler = Arc::new(scheduler);
+
+    // Bring saved queries and schedules in line with the definitions file
+    let definitions = Arc::new(services::definitions_service::Definitions::new(
+        config.definitions_file.clone(),
+    ));
+    let applied = definitions
+        .apply(&saved_queries, &scheduler)
+        .await
+        .unwrap_or_else(|e| panic!("{}", e));
+    for change in &applied.changes {
+        match change.action {
+            services::definitions_service::DefinitionAction::Unchanged => {}
+            services::definitions_service::DefinitionAction::Update => tracing::warn!(
+                "{:?} '{}' differs from {} ({}); restoring the file's definition",
+                change.kind,
+                change.name,
+                applied.file,
+                change.fields.join(", ")
+            ),
+            action => tracing::info!(
+                "{:?} {:?} '{}' from {}",
+                action,
+                change.kind,
+                change.name,
+                applied.file
+            ),
+        }
+    }
+
+    if demo.is_none() {
+        scheduler.spawn();
+        tracing::info!(
+            "Scheduler started with schedules from {}",
+            config.schedules_file
+        );
+    }
+
+    // Keep transaction sessions on dedicated connections, rolling back idle ones
+    let transactions = Arc::new(services::transaction_service::TransactionManager::new(
+        std::time::Duration::from_secs(config.transaction_idle_timeout_secs.max(1)),
+        read_only_mode.clone(),
+    ));
+    transactions.spawn_reaper();
+
+    // Keep server-side sessions (preferences, open transactions) in the configured store
+    let session_store = match config.session_store {
+        config::SessionBackend::Memory => services::session_service::Store::memory(),
+        config::SessionBackend::Postgres => {
+            services::session_service::Store::postgres(db_pool.clone())
+        }
+        config::SessionBackend::Redis => {
+            let url = config
+                .session_redis_url
+                .as_deref()
+                .expect("SESSION_REDIS_URL must be set when SESSION_STORE=redis");
+            services::session_service::Store::redis(url)
+                .await
+                .unwrap_or_else(|e| panic!("SESSION_REDIS_URL: {}", e))
+        }
+    };
+    session_store.spawn_purger();
+    {
+        // The Postgres store's table can only be created once the database is up
+        let db_monitor = db_monitor.clone();
+        let session_store = session_store.clone();
+        tokio::spawn(async move {
+            db_monitor.wait_available().await;
+            if let Err(e) = session_store.prepare().await {
+                tracing::error!("{}", e);
+            }
+        });
+    }
+    tracing::info!("Sessions stored in {}", session_store.describe());
+
     // Create rate limiter
     let rate_limit_config = middleware::rate_limit::RateLimitConfig {
         requests_per_minute: config.rate_limit_requests_per_minute,
@@ -99,15 +501,75 @@ async fn main() {
     let rate_limit_state = Arc::new(middleware::rate_limit::RateLimitState::new(
         rate_limit_config,
     ));
+    rate_limit_state.spawn_evictor();
     tracing::info!(
         "Rate limiting enabled: {} requests per minute per IP",
         config.rate_limit_requests_per_minute
     );
 
+    // Keep one client's queries from taking every pooled connection
+    let query_limiter = Arc::new(middleware::query_limit::QueryLimiter::new(
+        middleware::query_limit::QueryLimitConfig {
+            max_concurrent: config.max_concurrent_queries,
+            max_per_client: config.max_concurrent_queries_per_client,
+            queue_timeout: std::time::Duration::from_secs(config.query_queue_timeout_secs),
+        },
+    ));
+
+    if config.read_only_mode {
+        tracing::info!("Read-only mode enabled: all sessions default to read-only transactions");
+    }
+
+    // Apply rate limit, read-only and log filter changes on SIGHUP or the admin endpoint
+    let config_reloader = Arc::new(config::reload::ConfigReloader::new(
+        config::reload::ReloadableSettings::from_config(&config),
+        rate_limit_state.clone(),
+        read_only_mode.clone(),
+        log_filter,
+    ));
+    #[cfg(unix)]
+    spawn_reload_on_hangup(config_reloader.clone(), audit_logger.clone());
+
+    let branding = Arc::new(config::Branding::from_config(&config));
+    if let Some(banner) = &branding.environment_banner {
+        tracing::info!("Environment banner enabled: {}", banner);
+    }
+
     let state = AppState {
-        db_pool: Arc::new(db_pool),
+        db_pool: db_pool.clone(),
+        db_monitor: db_monitor.clone(),
         audit_logger: audit_logger.clone(),
         query_history: query_history.clone(),
+        saved_queries,
+        plan_history,
+        scheduler,
+        definitions,
+        transactions: transactions.clone(),
+        branding,
+        artifact_store,
+        result_store,
+        metadata_cache,
+        counter_history,
+        metrics_history,
+        connections,
+        read_only_mode,
+        rate_limit: rate_limit_state.clone(),
+        query_limiter,
+        config_reloader,
+        cost_thresholds: services::query_service::CostThresholds {
+            max_cost: config.cost_check_max_cost,
+            max_rows: config.cost_check_max_rows,
+        },
+        slow_query_threshold_ms: config.slow_query_threshold_ms,
+        restore_jobs: Arc::new(services::restore_service::RestoreJobs::new(
+            config.pg_restore_path.clone(),
+            config.restore_max_upload_mb * 1024 * 1024,
+        )),
+        maintenance: Arc::new(services::maintenance_service::MaintenanceQueue::new()),
+        backups,
+        webhooks,
+        quotas,
+        demo,
     };
 
     // Build the application with routes
@@ -116,6 +578,28 @@ async fn main() {
         .route("/", get(routes::index))
         .route("/query", get(routes::page_query))
         .route("/health", get(routes::health_check))
+        .route("/health/ready", get(routes::readiness_check))
+        .route("/favicon.ico", get(routes::branding::favicon))
+        .route("/branding/logo", get(routes::branding::logo))
+        // Versioned JSON API
+        .route("/api/openapi.json", get(routes::openapi::openapi_json))
+        .route("/api/docs", get(routes::openapi::api_docs_page))
+        .route("/api/v1/databases", get(routes::api_v1::list_databases))
+        .route("/api/v1/schemas", get(routes::api_v1::list_schemas))
+        .route(
+            "/api/v1/schemas/{schema}/tables",
+            get(routes::api_v1::list_tables),
+        )
+        .route(
+            "/api/v1/schemas/{schema}/tables/{table}",
+            get(routes::api_v1::get_table),
+        )
+        .route(
+            "/api/v1/schemas/{schema}/tables/{table}/rows",
+            get(routes::api_v1::list_rows),
+        )
+        .route("/api/v1/query", post(routes::api_v1::execute_query))
+        .route("/api/v1/query/history", get(routes::api_v1::query_history))
         // Database routes
         .route("/api/databases", get(routes::database::list_databases))
         .route(
@@ -133,7 +617,20 @@ async fn main() {
         .route("/api/databases/drop", post(routes::database::drop_database))
         // Schema routes
         .route("/api/schemas", get(routes::schema::list_schemas))
+        .route("/api/schema-tree", get(routes::schema::schema_tree))
+        .route(
+            "/api/metadata/autocomplete",
+            get(routes::schema::autocomplete),
+        )
+        .route("/api/schema-diff", get(routes::diff::schema_diff))
+        .route("/schema-diff", get(routes::diff::schema_diff_page))
+        .route("/api/data-diff", get(routes::diff::data_diff))
+        // Audit routes
+        .route("/api/audit", get(routes::audit::list_events))
+        .route("/audit", get(routes::audit::audit_page))
         .route("/api/schemas/{schema}", get(routes::schema::schema_details))
+        .route("/api/schemas/{schema}/erd", get(routes::erd::erd))
+        .route("/schemas/{schema}/erd", get(routes::erd::erd_page))
         // Table routes
         .route(
             "/api/schemas/{schema}/tables",
@@ -147,20 +644,143 @@ async fn main() {
             "/api/schemas/{schema}/tables/{table}/data",
             get(routes::tables::browse_data),
         )
+        .route(
+            "/api/schemas/{schema}/tables/{table}/duplicates",
+            get(routes::tables::table_duplicates),
+        )
+        .route(
+            "/api/schemas/{schema}/tables/{table}/profile",
+            get(routes::tables::table_profile),
+        )
+        .route(
+            "/api/schemas/{schema}/tables/{table}/policies",
+            get(routes::tables::table_policies),
+        )
+        .route(
+            "/api/schemas/{schema}/tables/{table}/ddl",
+            get(routes::tables::table_ddl),
+        )
         // Query routes
         .route("/api/query/execute", post(routes::query::execute))
+        .route("/api/query/format", post(routes::query::format_query))
+        .route("/api/query/chart", post(routes::query::chart))
+        .route("/api/query/pivot", post(routes::query::pivot))
+        .route("/api/query/compare", post(routes::query::compare))
+        .route(
+            "/api/query/results/{token}",
+            get(routes::query::stored_result),
+        )
+        .route(
+            "/api/query/results/{token}/download",
+            get(routes::query::download_stored_result),
+        )
+        .route(
+            "/api/query/transactions",
+            get(routes::transactions::list_transactions)
+                .post(routes::transactions::begin_transaction),
+        )
+        .route(
+            "/api/query/transactions/{id}/commit",
+            post(routes::transactions::commit_transaction),
+        )
+        .route(
+            "/api/query/transactions/{id}/rollback",
+            post(routes::transactions::rollback_transaction),
+        )
         .route("/api/query/history", get(routes::query::history))
         .route("/api/query/history", delete(routes::query::clear_history))
         .route(
             "/api/query/history/stats",
             get(routes::query::history_stats),
         )
+        .route(
+            "/api/query/history/groups",
+            get(routes::query::history_groups),
+        )
+        .route(
+            "/api/query/history/analytics",
+            get(routes::query::history_analytics),
+        )
+        .route(
+            "/api/query/history/export",
+            get(routes::query::export_history),
+        )
+        .route(
+            "/api/query/history/{id}/rerun",
+            post(routes::query::rerun_history),
+        )
+        .route(
+            "/api/query/history/{id}/tags",
+            put(routes::query::set_history_tags),
+        )
+        .route("/api/query/explain", post(routes::plans::explain))
+        .route("/api/query/plans", get(routes::plans::list_plans))
+        .route("/api/query/plans/compare", get(routes::plans::compare))
+        .route(
+            "/api/query/plans/{id}",
+            get(routes::plans::get_plan).delete(routes::plans::delete_plan),
+        )
+        .route("/api/queries/tags", get(routes::saved_queries::list_tags))
+        .route(
+            "/api/queries/saved",
+            get(routes::saved_queries::list_saved).post(routes::saved_queries::create_saved),
+        )
+        .route(
+            "/api/queries/saved/{id}",
+            get(routes::saved_queries::get_saved)
+                .put(routes::saved_queries::update_saved)
+                .delete(routes::saved_queries::delete_saved),
+        )
+        .route(
+            "/api/queries/saved/{id}/execute",
+            post(routes::saved_queries::execute_saved),
+        )
+        // Schedule routes
+        .route(
+            "/api/schedules",
+            get(routes::schedules::list_schedules).post(routes::schedules::create_schedule),
+        )
+        .route(
+            "/api/schedules/{id}",
+            get(routes::schedules::get_schedule)
+                .put(routes::schedules::update_schedule)
+                .delete(routes::schedules::delete_schedule),
+        )
+        .route(
+            "/api/schedules/{id}/runs",
+            get(routes::schedules::schedule_runs),
+        )
+        .route(
+            "/api/schedules/{id}/run",
+            post(routes::schedules::run_schedule),
+        )
+        // Saved query and schedule definitions file routes
+        .route(
+            "/api/definitions",
+            get(routes::definitions::definitions_report),
+        )
+        .route(
+            "/api/definitions/apply",
+            post(routes::definitions::apply_definitions),
+        )
+        .route(
+            "/api/definitions/export",
+            get(routes::definitions::export_definitions),
+        )
         .route("/api/query/export", post(routes::export::export_query))
         // Schema operations routes
         .route(
             "/api/schema/create-table",
             post(routes::schema_ops::create_table),
         )
+        .route(
+            "/api/schema/import-csv",
+            post(routes::schema_ops::import_csv),
+        )
+        .route(
+            "/api/schema/clone-table",
+            post(routes::schema_ops::clone_table),
+        )
         .route(
             "/api/schema/drop-object",
             post(routes::schema_ops::drop_object),
@@ -169,6 +789,29 @@ async fn main() {
             "/api/schema/create-index",
             post(routes::schema_ops::create_index),
         )
+        .route("/api/schema/comment", post(routes::schema_ops::set_comment))
+        .route(
+            "/api/schema/partitioning/create",
+            post(routes::schema_ops::create_partitioned_table),
+        )
+        .route(
+            "/api/schema/partitioning/convert",
+            post(routes::schema_ops::convert_to_partitioned),
+        )
+        .route(
+            "/api/schema/partitioning/extend",
+            post(routes::schema_ops::extend_partitions),
+        )
+        .route("/api/schema/grant", post(routes::privileges::grant))
+        .route("/api/schema/revoke", post(routes::privileges::revoke))
+        .route(
+            "/api/schemas/{schema}/privileges",
+            get(routes::privileges::schema_privileges),
+        )
+        .route(
+            "/api/schemas/{schema}/tables/{table}/privileges",
+            get(routes::privileges::table_privileges),
+        )
         .route(
             "/api/schema/{schema}/tables",
             get(routes::schema_ops::list_tables),
@@ -182,6 +825,24 @@ async fn main() {
         .route("/api/stats/tables", get(routes::stats::table_stats))
         .route("/api/stats/indexes", get(routes::stats::index_stats))
         .route("/api/stats/cache", get(routes::stats::cache_stats))
+        .route("/api/stats/wal", get(routes::stats::wal_stats))
+        .route("/api/stats/buffer-cache", get(routes::stats::buffer_cache))
+        .route("/api/stats/temp-files", get(routes::stats::temp_files))
+        .route(
+            "/api/stats/progress",
+            get(routes::stats::operation_progress),
+        )
+        .route("/api/stats/history", get(routes::stats::metric_history))
+        .route("/api/stats/series", get(routes::stats::metric_series))
+        .route("/api/stats/stream", get(routes::stats::metric_stream))
+        .route(
+            "/api/stats/database-counters",
+            get(routes::stats::database_counters),
+        )
+        .route(
+            "/api/stats/long-transactions",
+            get(routes::stats::long_transactions),
+        )
         .route(
             "/api/stats/overview",
             get(routes::stats::dashboard_metrics_widget),
@@ -194,6 +855,10 @@ async fn main() {
             "/api/stats/cache-stats-widget",
             get(routes::stats::cache_stats_widget),
         )
+        .route(
+            "/api/stats/wal-stats-widget",
+            get(routes::stats::wal_stats_widget),
+        )
         // Query widget routes
         .route(
             "/api/query/recent-widget",
@@ -222,6 +887,154 @@ async fn main() {
             "/api/studio/table/{schema}/{table}/indexes",
             get(routes::studio::studio_table_indexes),
         )
+        .route(
+            "/api/studio/table/{schema}/{table}/policies",
+            get(routes::studio::studio_table_policies),
+        )
+        .route(
+            "/api/studio/table/{schema}/{table}/ddl",
+            get(routes::studio::studio_table_ddl),
+        )
+        .route(
+            "/api/studio/table/{schema}/{table}/tail",
+            get(routes::studio::studio_table_tail),
+        )
+        // Connection routes
+        .route(
+            "/api/connections",
+            get(routes::connections::list_connections),
+        )
+        .route(
+            "/api/connections/select",
+            post(routes::connections::select_connection),
+        )
+        .route(
+            "/api/connections/test",
+            post(routes::connections::test_connection),
+        )
+        .route(
+            "/api/connections/{id}/capabilities",
+            get(routes::connections::capabilities),
+        )
+        .route(
+            "/api/connection/status",
+            get(routes::connections::connection_status),
+        )
+        // Admin routes
+        .route("/api/admin/artifacts", get(routes::admin::list_artifacts))
+        .route(
+            "/api/admin/artifacts/prune",
+            post(routes::admin::prune_artifacts),
+        )
+        .route(
+            "/api/admin/artifacts/{name}",
+            delete(routes::admin::delete_artifact),
+        )
+        .route("/api/admin/audit/prune", post(routes::admin::prune_audit))
+        .route(
+            "/api/admin/reload-config",
+            post(routes::admin::reload_config),
+        )
+        .route("/api/admin/plugins", get(routes::admin::list_plugins))
+        .route(
+            "/api/admin/rate-limits",
+            get(routes::admin::rate_limits).put(routes::admin::set_rate_limit),
+        )
+        .route(
+            "/api/admin/rate-limits/{ip}",
+            delete(routes::admin::reset_rate_limit),
+        )
+        .route(
+            "/api/admin/rate-limits/{ip}/exempt",
+            put(routes::admin::exempt_ip).delete(routes::admin::remove_exemption),
+        )
+        .route("/api/admin/query-limits", get(routes::admin::query_limits))
+        .route("/api/admin/quotas", get(routes::admin::quotas))
+        .route("/api/admin/webhooks", get(routes::admin::list_webhooks))
+        .route(
+            "/api/admin/webhooks/{id}/test",
+            post(routes::admin::test_webhook),
+        )
+        // Foreign data routes
+        .route("/api/fdw", get(routes::fdw::foreign_data))
+        .route(
+            "/api/fdw/import-schema",
+            post(routes::fdw::import_foreign_schema),
+        )
+        // Backup routes
+        .route(
+            "/api/backups",
+            get(routes::backups::list_backups).post(routes::backups::create_backup),
+        )
+        .route("/api/backups/runs", get(routes::backups::list_runs))
+        .route(
+            "/api/backups/schedules",
+            get(routes::backups::list_schedules).post(routes::backups::create_schedule),
+        )
+        .route(
+            "/api/backups/schedules/{id}",
+            get(routes::backups::get_schedule)
+                .put(routes::backups::update_schedule)
+                .delete(routes::backups::delete_schedule),
+        )
+        .route(
+            "/api/backups/schedules/{id}/run",
+            post(routes::backups::run_schedule),
+        )
+        .route(
+            "/api/backups/{name}",
+            get(routes::backups::download_backup).delete(routes::backups::delete_backup),
+        )
+        // Restore routes
+        .route("/api/restore", post(routes::restore::upload_restore))
+        .route("/api/restore/jobs", get(routes::restore::list_jobs))
+        .route("/api/restore/jobs/{id}", get(routes::restore::get_job))
+        .route(
+            "/api/restore/jobs/{id}/events",
+            get(routes::restore::job_events),
+        )
+        // Maintenance routes
+        .route(
+            "/api/maintenance/batches",
+            get(routes::maintenance::list_batches).post(routes::maintenance::create_batch),
+        )
+        .route(
+            "/api/maintenance/batches/{id}",
+            get(routes::maintenance::get_batch),
+        )
+        .route(
+            "/api/maintenance/batches/{id}/cancel",
+            post(routes::maintenance::cancel_batch),
+        )
+        .route(
+            "/api/maintenance/bloated-indexes",
+            get(routes::maintenance::bloated_indexes),
+        )
+        // Logical replication routes
+        .route(
+            "/api/replication",
+            get(routes::replication::replication_overview),
+        )
+        .route(
+            "/api/replication/publications",
+            post(routes::replication::create_publication),
+        )
+        .route(
+            "/api/replication/publications/{name}",
+            patch(routes::replication::alter_publication)
+                .delete(routes::replication::drop_publication),
+        )
+        // Server settings routes
+        .route("/api/settings", get(routes::settings::list_settings))
+        .route(
+            "/api/settings/{name}",
+            put(routes::settings::update_setting),
+        )
+        // Preference routes
+        .route(
+            "/api/preferences",
+            get(routes::preferences::get_preferences).put(routes::preferences::update_preferences),
+        )
         // Cell editing routes
         .route("/api/cell/edit", get(routes::cell::get_cell_edit))
         .route("/api/cell/update", post(routes::cell::update_cell))
@@ -233,22 +1046,104 @@ async fn main() {
             "/api/table/{schema}/{table}/row/{pk_value}",
             delete(routes::cell::delete_row),
         )
-        .nest_service("/static", ServeDir::new("static"))
-        .with_state(state)
-        // Apply middleware layers in order (executed bottom-to-top)
-        .layer(
-            ServiceBuilder::new()
-                .layer(axum_middleware::from_fn(
-                    middleware::security_headers::security_headers,
-                ))
-                .layer(axum_middleware::from_fn_with_state(
-                    rate_limit_state,
-                    middleware::rate_limit::rate_limit_middleware,
-                ))
-                .layer(TraceLayer::new_for_http())
-                .layer(CorsLayer::permissive())
-                .layer(DefaultBodyLimit::max(10 * 1024 * 1024)), // 10MB max body
+        // Route groups of compiled-in plugins
+        .merge(plugins::routes())
+        // Only serve files under static/ (no directory index pages)
+        .nest_service(
+            "/static",
+            ServeDir::new("static").append_index_html_on_directories(false),
+        )
+        .with_state(state);
+
+    // Unbundled scripts and live reload of static assets for working on the UI
+    let app = if config.dev_mode {
+        tracing::warn!(
+            "DEV_MODE is on: scripts are served unbundled and pages reload on static file changes"
         );
+        app.merge(routes::dev::router())
+            .layer(axum::middleware::from_fn(routes::dev::revalidate_static))
+    } else {
+        app
+    };
+
+    // Serve everything under APP_BASE_PATH when proxied from a subdirectory
+    if !config.app_base_path.is_empty() {
+        tracing::info!("Serving under base path {}", config.app_base_path);
+    }
+    let app = with_base_path(app, &config.app_base_path);
+
+    // Mutating requests must send the session's CSRF token; cross-origin access is opt-in
+    let csrf_config = Arc::new(middleware::csrf::CsrfConfig::new(
+        &config.app_base_path,
+        config.tls_cert_file.is_some(),
+    ));
+    let session_layer = middleware::session::SessionConfig {
+        cookie_path: if config.app_base_path.is_empty() {
+            "/".to_string()
+        } else {
+            config.app_base_path.clone()
+        },
+        secure: config
+            .session_cookie_secure
+            .unwrap_or(config.tls_cert_file.is_some()),
+        same_site: config.session_cookie_same_site,
+        ttl: std::time::Duration::from_secs(config.session_ttl_secs.max(1)),
+    }
+    .layer(session_store);
+    // Answer 504 rather than waiting forever on a stuck query
+    let request_timeouts = Arc::new(middleware::timeout::RequestTimeouts::new(
+        &config.app_base_path,
+        config.request_timeout_secs,
+        config.query_request_timeout_secs,
+    ));
+    let cors_layer = (!config.cors_allowed_origins.is_empty()).then(|| {
+        let origins: Vec<_> = config
+            .cors_allowed_origins
+            .iter()
+            .filter_map(|origin| origin.parse().ok())
+            .collect();
+        CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods(tower_http::cors::Any)
+            .allow_headers(tower_http::cors::Any)
+    });
+
+    // Apply middleware layers in order (executed bottom-to-top)
+    let app = app.layer(
+        ServiceBuilder::new()
+            .layer(axum_middleware::from_fn(middleware::request_id::request_id))
+            .layer(axum_middleware::from_fn_with_state(
+                Arc::new(config.trusted_proxies.clone()),
+                middleware::client_ip::client_ip,
+            ))
+            .layer(axum_middleware::from_fn(
+                middleware::security_headers::security_headers,
+            ))
+            .layer(axum_middleware::from_fn(error::htmx_error_fragments))
+            .layer(session_layer)
+            .layer(axum_middleware::from_fn_with_state(
+                csrf_config,
+                middleware::csrf::csrf,
+            ))
+            .layer(axum_middleware::from_fn(middleware::locale::locale))
+            .layer(axum_middleware::from_fn_with_state(
+                rate_limit_state,
+                middleware::rate_limit::rate_limit_middleware,
+            ))
+            .layer(
+                TraceLayer::new_for_http()
+                    .make_span_with(logging::request_span)
+                    .on_response(logging::ResponseLogger {
+                        slow_threshold_ms: config.slow_request_threshold_ms,
+                    }),
+            )
+            .option_layer(cors_layer)
+            .layer(axum_middleware::from_fn_with_state(
+                request_timeouts,
+                middleware::timeout::request_timeout,
+            ))
+            .layer(DefaultBodyLimit::max(10 * 1024 * 1024)), // 10MB max body
+    );
 
     // Parse the server address
     let addr: SocketAddr = match config.server_address.parse() {
@@ -279,16 +1174,153 @@ async fn main() {
         }
     };
 
-    tracing::info!("Server listening on {}", addr);
+    // Terminate TLS ourselves when a certificate is configured
+    let tls_config = match (&config.tls_cert_file, &config.tls_key_file) {
+        (Some(cert_file), Some(key_file)) => match tls::load_config(cert_file, key_file) {
+            Ok(tls_config) => Some(tls_config),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        },
+        (None, None) => None,
+        _ => {
+            eprintln!("Error: TLS_CERT_FILE and TLS_KEY_FILE must be set together");
+            std::process::exit(1);
+        }
+    };
+    let scheme = if tls_config.is_some() {
+        "https"
+    } else {
+        "http"
+    };
+    // A no-op tap_io lets axum provide ConnectInfo<SocketAddr> for our listener
+    let listener = match tls::AppListener::new(
+        listener,
+        tls_config,
+        std::time::Duration::from_secs(config.tls_handshake_timeout_secs),
+    ) {
+        Ok(listener) => listener.tap_io(|_| {}),
+        Err(e) => {
+            eprintln!("Error: Failed to listen on {}: {}", addr, e);
+            std::process::exit(1);
+        }
+    };
+
+    tracing::info!("Server listening on {}://{}", scheme, addr);
 
-    // Serve with ConnectInfo to extract client IP for rate limiting
-    if let Err(e) = axum::serve(
+    // Serve with ConnectInfo to extract client IP for rate limiting. On a
+    // shutdown signal stop accepting connections and give in-flight requests
+    // up to SHUTDOWN_TIMEOUT_SECS to finish.
+    let (draining_tx, mut draining_rx) = tokio::sync::watch::channel(false);
+    let server = axum::serve(
         listener,
         app.into_make_service_with_connect_info::<SocketAddr>(),
     )
-    .await
+    .with_graceful_shutdown(async move {
+        shutdown_signal().await;
+        let _ = draining_tx.send(true);
+    });
+    let shutdown_timeout = std::time::Duration::from_secs(config.shutdown_timeout_secs);
+    tokio::select! {
+        result = server.into_future() => {
+            if let Err(e) = result {
+                eprintln!("Server error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        _ = async {
+            let _ = draining_rx.wait_for(|draining| *draining).await;
+            tokio::time::sleep(shutdown_timeout).await;
+        } => {
+            tracing::warn!(
+                "In-flight requests still running after {}s; closing them",
+                shutdown_timeout.as_secs()
+            );
+        }
+    }
+
+    // Release database state and deliver queued audit events before exiting
+    for info in transactions.rollback_all().await {
+        tracing::info!("Rolled back transaction {} on shutdown", info.id);
+    }
+    if tokio::time::timeout(AUDIT_FLUSH_TIMEOUT, audit_logger.flush())
+        .await
+        .is_err()
     {
-        eprintln!("Server error: {}", e);
-        std::process::exit(1);
+        tracing::warn!("Gave up delivering queued audit events");
+    }
+    db_pool.close().await;
+    tracing::info!("Shutdown complete");
+}
+
+/// Mount the app under `base_path` (normalized, e.g. `/pgadmin`; empty for the root)
+///
+/// `/pgadmin/` redirects to `/pgadmin`, which axum's nesting would otherwise
+/// leave unmatched.
+fn with_base_path(app: Router, base_path: &str) -> Router {
+    if base_path.is_empty() {
+        return app;
+    }
+    let root = base_path.to_string();
+    Router::new().nest(base_path, app).route(
+        &format!("{}/", base_path),
+        get(move || async move { axum::response::Redirect::to(&root) }),
+    )
+}
+
+/// Longest wait for audit sinks to drain at shutdown
+const AUDIT_FLUSH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Reload the configuration whenever the process receives SIGHUP
+#[cfg(unix)]
+fn spawn_reload_on_hangup(
+    reloader: Arc<config::reload::ConfigReloader>,
+    audit_logger: Arc<services::audit_service::AuditLogger>,
+) {
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+        .expect("failed to install SIGHUP handler");
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            let outcome = reloader.reload();
+            match &outcome {
+                Ok(report) if report.changed.is_empty() => {
+                    tracing::info!("SIGHUP: configuration reloaded, nothing changed")
+                }
+                Ok(report) => tracing::info!(
+                    "SIGHUP: configuration reloaded, changed {}",
+                    report.changed.join(", ")
+                ),
+                Err(e) => tracing::error!("SIGHUP: configuration not reloaded: {}", e),
+            }
+            audit_logger
+                .log(config::reload::audit_event("local".to_string(), &outcome))
+                .await;
+        }
+    });
+}
+
+/// Resolves on Ctrl+C, or on SIGTERM (sent by Docker and Kubernetes)
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
     }
+    tracing::info!("Shutdown signal received, draining in-flight requests");
 }
@@ -0,0 +1,349 @@
+/// Client IP Extraction
+///
+/// Resolves the address of the client that made a request, for rate limiting,
+/// audit events and logs. Forwarding headers (`Forwarded`, `X-Forwarded-For`,
+/// `X-Real-IP`) are only honoured when the direct peer is a trusted proxy
+/// (`TRUSTED_PROXIES`, loopback only by default); otherwise the client could
+/// spoof them. A proxy on another host must be listed explicitly: trusting a
+/// whole private network would let anyone on it pick their own address.
+/// Proxy chains are walked from the nearest hop back to the first address
+/// that is not itself a trusted proxy.
+use axum::{
+    extract::{ConnectInfo, FromRequestParts, Request, State},
+    http::{request::Parts, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Extractor for the resolved client IP address
+///
+/// Filled in by the `client_ip` middleware; without it, falls back to the
+/// default trusted proxies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientIp(pub String);
+
+impl<S> FromRequestParts<S> for ClientIp
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(client_ip) = parts.extensions.get::<ClientIp>() {
+            return Ok(client_ip.clone());
+        }
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        Ok(ClientIp(resolve_client_ip(
+            peer,
+            &parts.headers,
+            &TrustedProxies::default(),
+        )))
+    }
+}
+
+/// Resolve the client IP once per request and store it for later layers and handlers
+pub async fn client_ip(
+    State(trusted): State<Arc<TrustedProxies>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let ip = resolve_client_ip(peer, request.headers(), &trusted);
+    request.extensions_mut().insert(ClientIp(ip));
+    next.run(request).await
+}
+
+/// A network in CIDR notation; a bare address is a single-host network
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNetwork {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNetwork {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            // IPv4 peers on a dual-stack socket show up as ::ffff:a.b.c.d
+            (IpAddr::V4(_), IpAddr::V6(ip)) => ip
+                .to_ipv4_mapped()
+                .is_some_and(|ip| self.contains(IpAddr::V4(ip))),
+            (IpAddr::V6(_), IpAddr::V4(_)) => false,
+        }
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid network '{}': expected an IP or CIDR", s);
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.trim().parse().map_err(|_| invalid())?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.trim().parse().map_err(|_| invalid())?,
+            None => max,
+        };
+        if prefix > max {
+            return Err(invalid());
+        }
+        Ok(Self { addr, prefix })
+    }
+}
+
+/// Networks whose forwarding headers are believed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrustedProxies(Vec<IpNetwork>);
+
+impl TrustedProxies {
+    /// Loopback, where a reverse proxy on the same host connects from
+    pub const DEFAULT: &'static str = "127.0.0.0/8,::1/128";
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.0.iter().any(|network| network.contains(ip))
+    }
+}
+
+impl Default for TrustedProxies {
+    fn default() -> Self {
+        Self::DEFAULT
+            .parse()
+            .expect("default trusted proxies are valid")
+    }
+}
+
+/// Comma-separated IPs and CIDR networks, or `none` to ignore forwarding headers
+impl FromStr for TrustedProxies {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().eq_ignore_ascii_case("none") {
+            return Ok(Self(Vec::new()));
+        }
+        s.split(',')
+            .map(str::trim)
+            .filter(|network| !network.is_empty())
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+/// Resolve the client address from the peer and any forwarding headers
+pub fn resolve_client_ip(
+    peer: Option<IpAddr>,
+    headers: &HeaderMap,
+    trusted: &TrustedProxies,
+) -> String {
+    // Headers are also used without a peer address (e.g. in tests)
+    if let Some(ip) = peer.filter(|ip| !trusted.contains(*ip)) {
+        return ip.to_string();
+    }
+
+    let chain = forwarded_chain(headers);
+    let resolved = if chain.is_empty() {
+        header_values(headers, "x-real-ip")
+            .next()
+            .and_then(|v| v.trim().parse().ok())
+    } else {
+        // Walk back from the nearest hop; each hop vouches for the one before
+        // it only if it is trusted. An unparseable hop ends the walk.
+        let mut resolved = None;
+        for hop in chain.iter().rev() {
+            match hop {
+                Some(ip) => {
+                    resolved = Some(*ip);
+                    if !trusted.contains(*ip) {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+        resolved
+    };
+
+    resolved
+        .or(peer)
+        .map(|ip| ip.to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Client and proxy addresses in order, from `Forwarded` if present, else
+/// `X-Forwarded-For`; `None` marks a hop that is not a usable IP
+fn forwarded_chain(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let forwarded: Vec<Option<IpAddr>> = header_values(headers, "forwarded")
+        .flat_map(|v| v.split(','))
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (key, value) = pair.split_once('=')?;
+                key.trim()
+                    .eq_ignore_ascii_case("for")
+                    .then(|| parse_node(value))
+            })
+        })
+        .collect();
+    if !forwarded.is_empty() {
+        return forwarded;
+    }
+
+    header_values(headers, "x-forwarded-for")
+        .flat_map(|v| v.split(','))
+        .map(parse_node)
+        .collect()
+}
+
+/// Parse a `Forwarded` node or `X-Forwarded-For` entry: `1.2.3.4`,
+/// `1.2.3.4:80`, `"[2001:db8::1]:443"` or a bare IPv6 address
+fn parse_node(value: &str) -> Option<IpAddr> {
+    let value = value.trim().trim_matches('"');
+    if let Some(rest) = value.strip_prefix('[') {
+        return rest.split(']').next()?.parse().ok();
+    }
+    value
+        .parse()
+        .ok()
+        .or_else(|| value.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+}
+
+fn header_values<'a>(headers: &'a HeaderMap, name: &str) -> impl Iterator<Item = &'a str> {
+    headers.get_all(name).iter().filter_map(|v| v.to_str().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.insert(*name, value.parse().unwrap());
+        }
+        map
+    }
+
+    fn trusted() -> TrustedProxies {
+        TrustedProxies::default()
+    }
+
+    #[test]
+    fn test_direct_client() {
+        let peer = Some("203.0.113.7".parse().unwrap());
+        assert_eq!(
+            resolve_client_ip(peer, &HeaderMap::new(), &trusted()),
+            "203.0.113.7"
+        );
+    }
+
+    #[test]
+    fn test_forwarded_through_local_proxy() {
+        let peer = Some("127.0.0.1".parse().unwrap());
+        let h = headers(&[("x-forwarded-for", "198.51.100.4")]);
+        assert_eq!(resolve_client_ip(peer, &h, &trusted()), "198.51.100.4");
+
+        let peer = Some("::1".parse().unwrap());
+        let h = headers(&[("x-real-ip", "198.51.100.9")]);
+        assert_eq!(resolve_client_ip(peer, &h, &trusted()), "198.51.100.9");
+    }
+
+    #[test]
+    fn test_private_network_peer_not_trusted_by_default() {
+        // Another host on the network could otherwise claim any address
+        let peer = Some("10.0.0.2".parse().unwrap());
+        let h = headers(&[("x-real-ip", "198.51.100.9")]);
+        assert_eq!(resolve_client_ip(peer, &h, &trusted()), "10.0.0.2");
+
+        let peer = Some("127.0.0.1".parse().unwrap());
+        let h = headers(&[("x-forwarded-for", "198.51.100.4, 192.168.1.7")]);
+        assert_eq!(resolve_client_ip(peer, &h, &trusted()), "192.168.1.7");
+    }
+
+    #[test]
+    fn test_spoofed_header_from_public_peer_ignored() {
+        let peer = Some("203.0.113.7".parse().unwrap());
+        let h = headers(&[("x-forwarded-for", "1.2.3.4")]);
+        assert_eq!(resolve_client_ip(peer, &h, &trusted()), "203.0.113.7");
+    }
+
+    #[test]
+    fn test_invalid_forwarded_value_falls_back_to_peer() {
+        let peer = Some("127.0.0.1".parse().unwrap());
+        let h = headers(&[("x-forwarded-for", "not-an-ip")]);
+        assert_eq!(resolve_client_ip(peer, &h, &trusted()), "127.0.0.1");
+        assert_eq!(
+            resolve_client_ip(None, &HeaderMap::new(), &trusted()),
+            "unknown"
+        );
+    }
+
+    #[test]
+    fn test_parse_trusted_proxies() {
+        let proxies: TrustedProxies = "10.1.0.0/16, 203.0.113.9, 2001:db8::/32".parse().unwrap();
+        assert!(proxies.contains("10.1.200.3".parse().unwrap()));
+        assert!(!proxies.contains("10.2.0.1".parse().unwrap()));
+        assert!(proxies.contains("203.0.113.9".parse().unwrap()));
+        assert!(proxies.contains("2001:db8:1::5".parse().unwrap()));
+        assert!(proxies.contains("::ffff:10.1.0.1".parse().unwrap()));
+
+        assert!("none".parse::<TrustedProxies>().unwrap().0.is_empty());
+        assert!("10.0.0.0/33".parse::<TrustedProxies>().is_err());
+        assert!("proxy.local".parse::<TrustedProxies>().is_err());
+    }
+
+    #[test]
+    fn test_walks_chain_past_trusted_hops() {
+        let proxies: TrustedProxies = "10.0.0.0/8".parse().unwrap();
+        let peer = Some("10.0.0.1".parse().unwrap());
+
+        // The client prepended a fake address; the first untrusted hop wins
+        let h = headers(&[("x-forwarded-for", "1.2.3.4, 198.51.100.4, 10.0.0.2")]);
+        assert_eq!(resolve_client_ip(peer, &h, &proxies), "198.51.100.4");
+
+        // With no trusted peer the headers are ignored
+        let none = "none".parse().unwrap();
+        assert_eq!(resolve_client_ip(peer, &h, &none), "10.0.0.1");
+    }
+
+    #[test]
+    fn test_forwarded_header() {
+        let peer = Some("127.0.0.1".parse().unwrap());
+        let h = headers(&[(
+            "forwarded",
+            "for=198.51.100.4;proto=https, for=\"[2001:db8::17]:4711\"",
+        )]);
+        assert_eq!(resolve_client_ip(peer, &h, &trusted()), "2001:db8::17");
+
+        // Forwarded takes precedence over X-Forwarded-For
+        let h = headers(&[
+            ("forwarded", "For=198.51.100.4:8080"),
+            ("x-forwarded-for", "192.0.2.1"),
+        ]);
+        assert_eq!(resolve_client_ip(peer, &h, &trusted()), "198.51.100.4");
+
+        // Obfuscated identifiers stop the walk at the nearest hop
+        let proxies: TrustedProxies = "127.0.0.1, 10.0.0.0/8".parse().unwrap();
+        let h = headers(&[("forwarded", "for=_hidden, for=10.0.0.5")]);
+        assert_eq!(resolve_client_ip(peer, &h, &proxies), "10.0.0.5");
+    }
+}
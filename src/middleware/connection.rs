@@ -0,0 +1,80 @@
+/// Selected Connection
+///
+/// Each browser session can pick one of the configured connection profiles
+/// from the server picker. Handlers that talk to the database extract
+/// `Connected` instead of `State<AppState>` to get the app state pointed at
+/// that connection's pool and metadata cache.
+///
+/// Without a selection (API clients, new sessions), or when the selected
+/// profile no longer exists, the `default` connection is used.
+///
+/// In demo mode there is no database: `Connected` is rejected with a 503,
+/// and the handlers that can answer from the demo catalog extract
+/// `DemoAware` instead, which lets the request through with `state.demo` set.
+use crate::error::AppError;
+use crate::middleware::session::Session;
+use crate::services::connection_service::DEFAULT_CONNECTION_ID;
+use crate::AppState;
+use axum::{extract::FromRequestParts, http::request::Parts};
+
+/// Session key holding the ID of the selected connection profile
+pub const SESSION_KEY: &str = "connection";
+
+/// The ID of the connection selected in this session (the default one
+/// outside the session layer, or if the session cannot be loaded)
+pub async fn selected_connection_id(session: Option<&Session>, state: &AppState) -> String {
+    let selected = match session {
+        Some(session) => session.get::<String>(SESSION_KEY).await.ok().flatten(),
+        None => None,
+    };
+    selected
+        .filter(|id| state.connections.handle(id).is_some())
+        .unwrap_or_else(|| DEFAULT_CONNECTION_ID.to_string())
+}
+
+/// The app state pointed at the session's selected connection
+async fn select(parts: &mut Parts, state: &AppState) -> AppState {
+    let session = parts.extensions.get::<Session>();
+    let mut state = state.clone();
+    let id = selected_connection_id(session, &state).await;
+    if let Some(handle) = state.connections.handle(&id) {
+        state.db_pool = handle.pool;
+        state.metadata_cache = handle.metadata_cache;
+        state.counter_history = handle.counter_history;
+    }
+    state
+}
+
+/// Application state for the session's selected connection
+pub struct Connected(pub AppState);
+
+impl FromRequestParts<AppState> for Connected {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        if state.demo.is_some() {
+            return Err(AppError::Unavailable(
+                "Not available in demo mode".to_string(),
+            ));
+        }
+        Ok(Connected(select(parts, state).await))
+    }
+}
+
+/// Like `Connected`, but also accepted in demo mode, where handlers answer
+/// from `state.demo` instead of the pool
+pub struct DemoAware(pub AppState);
+
+impl FromRequestParts<AppState> for DemoAware {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        Ok(DemoAware(select(parts, state).await))
+    }
+}
@@ -0,0 +1,401 @@
+/// CSRF Protection
+///
+/// Each session gets a random token, generated once and stored in the
+/// `Session`. Mutating requests (POST, PUT, PATCH, DELETE) must send it back
+/// in the `X-CSRF-Token` header or a `csrf_token` form field, and it is
+/// checked against the session's copy; another site can trigger the request
+/// but cannot read the token to copy it.
+///
+/// The token reaches the page in the `pgadmin_csrf` cookie, which the
+/// frontend reads to add the header to HTMX requests and `fetch` calls, and
+/// the field to plain form posts. The cookie is only a delivery path: a token
+/// planted in it does not match the session's. Sessions are started for HTML
+/// pages only, so health checks and API clients do not each create one.
+///
+/// The JSON API under `/api/v1` is exempt: it only accepts `application/json`
+/// bodies, which browsers will not send cross-site without a CORS preflight.
+///
+/// Runs inside the session layer.
+use crate::error::AppError;
+use crate::middleware::session::Session;
+use axum::{
+    body::Body,
+    extract::{FromRequest, Request, State},
+    http::{header, HeaderMap, HeaderValue, Method},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Form,
+};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Deserialize;
+use std::sync::Arc;
+
+pub const COOKIE_NAME: &str = "pgadmin_csrf";
+pub const HEADER_NAME: &str = "x-csrf-token";
+
+/// Session key holding the session's token
+const SESSION_KEY: &str = "csrf_token";
+
+/// Largest form body buffered to look for the token field
+const MAX_FORM_BYTES: usize = 10 * 1024 * 1024;
+
+/// Where the cookie applies and which paths skip the check
+#[derive(Debug, Clone)]
+pub struct CsrfConfig {
+    /// Cookie path, the app's base path or `/`
+    pub cookie_path: String,
+    /// Mark the cookie `Secure` (when serving HTTPS)
+    pub secure: bool,
+    /// Path prefixes exempt from the check
+    pub exempt_prefixes: Vec<String>,
+}
+
+impl CsrfConfig {
+    pub fn new(base_path: &str, secure: bool) -> Self {
+        Self {
+            cookie_path: if base_path.is_empty() {
+                "/".to_string()
+            } else {
+                base_path.to_string()
+            },
+            secure,
+            exempt_prefixes: vec![format!("{}/api/v1/", base_path)],
+        }
+    }
+
+    fn cookie(&self, token: &str) -> String {
+        format!(
+            "{}={}; Path={}; SameSite=Strict{}",
+            COOKIE_NAME,
+            token,
+            self.cookie_path,
+            if self.secure { "; Secure" } else { "" }
+        )
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenField {
+    csrf_token: Option<String>,
+}
+
+/// Reject mutating requests whose token does not match the session's, and
+/// deliver the session's token in the cookie
+pub async fn csrf(State(config): State<Arc<CsrfConfig>>, request: Request, next: Next) -> Response {
+    match check(&config, request, next).await {
+        Ok(response) => response,
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn check(config: &CsrfConfig, request: Request, next: Next) -> Result<Response, AppError> {
+    let path = request.uri().path();
+    if config
+        .exempt_prefixes
+        .iter()
+        .any(|prefix| path.starts_with(prefix.as_str()))
+    {
+        return Ok(next.run(request).await);
+    }
+    let session = request
+        .extensions()
+        .get::<Session>()
+        .cloned()
+        .ok_or_else(|| AppError::Internal("CSRF checks need the session layer".to_string()))?;
+    let stored = session
+        .get::<String>(SESSION_KEY)
+        .await?
+        .filter(|token| is_well_formed(token));
+    let cookie_token = cookie_token(request.headers());
+
+    let safe = matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    );
+    let request = if safe {
+        request
+    } else {
+        let (submitted, request) = submitted_token(request).await?;
+        let valid = matches!(
+            (&stored, &submitted),
+            (Some(expected), Some(submitted)) if tokens_match(expected, submitted)
+        );
+        if !valid {
+            return Err(AppError::Forbidden(
+                "Missing or invalid CSRF token; reload the page and try again".to_string(),
+            ));
+        }
+        request
+    };
+
+    let mut response = next.run(request).await;
+    let token = match stored {
+        Some(token) => token,
+        None if is_html(&response) => {
+            let token = generate_token();
+            session.insert(SESSION_KEY, &token).await?;
+            token
+        }
+        None => return Ok(response),
+    };
+    if cookie_token.as_deref() != Some(token.as_str()) {
+        if let Ok(value) = HeaderValue::from_str(&config.cookie(&token)) {
+            response.headers_mut().append(header::SET_COOKIE, value);
+        }
+    }
+    Ok(response)
+}
+
+fn is_html(response: &Response) -> bool {
+    response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/html"))
+}
+
+/// The token from the `X-CSRF-Token` header, or else from a `csrf_token`
+/// field of a urlencoded form body (which is buffered and put back)
+async fn submitted_token(request: Request) -> Result<(Option<String>, Request), AppError> {
+    if let Some(token) = request
+        .headers()
+        .get(HEADER_NAME)
+        .and_then(|v| v.to_str().ok())
+    {
+        return Ok((Some(token.to_string()), request));
+    }
+
+    let is_form = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/x-www-form-urlencoded"));
+    if !is_form {
+        return Ok((None, request));
+    }
+
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, MAX_FORM_BYTES)
+        .await
+        .map_err(|e| AppError::BadRequest(format!("Failed to read form: {}", e)))?;
+    let form = Request::builder()
+        .method(Method::POST)
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(bytes.clone()))
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let token = Form::<TokenField>::from_request(form, &())
+        .await
+        .ok()
+        .and_then(|Form(field)| field.csrf_token);
+    Ok((token, Request::from_parts(parts, Body::from(bytes))))
+}
+
+/// The token from the request's cookie, if it has a well-formed one
+fn cookie_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == COOKIE_NAME)
+        .map(|(_, value)| value.to_string())
+        .filter(|value| is_well_formed(value))
+}
+
+/// 256 random bits from the system's secure generator, hex encoded
+fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("the system random number generator failed");
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn is_well_formed(token: &str) -> bool {
+    token.len() == 64 && token.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Compare without returning early, so timing does not leak the token
+fn tokens_match(expected: &str, submitted: &str) -> bool {
+    expected.len() == submitted.len()
+        && expected
+            .bytes()
+            .zip(submitted.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::session::{self, SameSite, SessionConfig};
+    use crate::services::session_service::Store;
+    use axum::{
+        http::StatusCode,
+        middleware,
+        response::Html,
+        routing::{get, post},
+        Router,
+    };
+    use http_body_util::BodyExt;
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    const TOKEN: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+    fn router(store: Store) -> Router {
+        let config = Arc::new(CsrfConfig::new("", false));
+        Router::new()
+            .route("/", get(|| async { Html("page") }))
+            .route("/health", get(|| async { "ok" }))
+            .route("/api/table", post(|body: String| async move { body }))
+            .route("/api/v1/query", post(|| async { "api" }))
+            .layer(middleware::from_fn_with_state(config, csrf))
+            .layer(
+                SessionConfig {
+                    cookie_path: "/".to_string(),
+                    secure: false,
+                    same_site: SameSite::Lax,
+                    ttl: Duration::from_secs(60),
+                }
+                .layer(store),
+            )
+    }
+
+    async fn send(store: &Store, request: axum::http::request::Builder, body: &str) -> Response {
+        router(store.clone())
+            .oneshot(request.body(Body::from(body.to_string())).unwrap())
+            .await
+            .unwrap()
+    }
+
+    /// The `name=value` pair a response sets for the cookie `name`
+    fn set_cookie(response: &Response, name: &str) -> Option<String> {
+        response
+            .headers()
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .map(|v| v.to_str().unwrap().split(';').next().unwrap().to_string())
+            .find(|pair| pair.starts_with(&format!("{}=", name)))
+    }
+
+    /// Load a page, returning the cookies to send back and the session's token
+    async fn start_session(store: &Store) -> (String, String) {
+        let response = send(store, Request::builder().uri("/"), "").await;
+        let session = set_cookie(&response, session::COOKIE_NAME).unwrap();
+        let csrf = set_cookie(&response, COOKIE_NAME).unwrap();
+        let token = csrf.split_once('=').unwrap().1.to_string();
+        (format!("theme=dark; {}; {}", session, csrf), token)
+    }
+
+    fn post_with(uri: &str, cookies: &str) -> axum::http::request::Builder {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header(header::COOKIE, cookies)
+    }
+
+    #[tokio::test]
+    async fn test_issues_token_once_per_session() {
+        let store = Store::memory();
+        let response = send(&store, Request::builder().uri("/"), "").await;
+        let cookie = response
+            .headers()
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .map(|v| v.to_str().unwrap())
+            .find(|v| v.starts_with("pgadmin_csrf="))
+            .unwrap();
+        assert!(cookie.contains("SameSite=Strict"));
+        assert!(!cookie.contains("HttpOnly"));
+
+        // The same token comes back while the session lasts
+        let (cookies, token) = start_session(&store).await;
+        let request = Request::builder().uri("/").header(header::COOKIE, &cookies);
+        let response = send(&store, request, "").await;
+        assert!(set_cookie(&response, COOKIE_NAME).is_none());
+
+        // A stale cookie is replaced with the session's token
+        let stale = cookies.replace(&token, TOKEN);
+        let request = Request::builder()
+            .uri("/health")
+            .header(header::COOKIE, stale);
+        let response = send(&store, request, "").await;
+        assert_eq!(
+            set_cookie(&response, COOKIE_NAME),
+            Some(format!("{}={}", COOKIE_NAME, token))
+        );
+
+        // Responses other than pages do not start a session
+        let response = send(&store, Request::builder().uri("/health"), "").await;
+        assert!(response.headers().get(header::SET_COOKIE).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_rejects_post_without_the_sessions_token() {
+        let store = Store::memory();
+        let (cookies, _) = start_session(&store).await;
+
+        let response = send(&store, post_with("/api/table", &cookies), "").await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let request = post_with("/api/table", &cookies).header(HEADER_NAME, TOKEN);
+        assert_eq!(
+            send(&store, request, "").await.status(),
+            StatusCode::FORBIDDEN
+        );
+
+        // A token planted in the cookie and echoed back is not the session's
+        let planted = format!("{}={}", COOKIE_NAME, TOKEN);
+        let request = post_with("/api/table", &planted).header(HEADER_NAME, TOKEN);
+        assert_eq!(
+            send(&store, request, "").await.status(),
+            StatusCode::FORBIDDEN
+        );
+
+        // Nor is another session's token
+        let (_, other_token) = start_session(&store).await;
+        let request = post_with("/api/table", &cookies).header(HEADER_NAME, other_token);
+        assert_eq!(
+            send(&store, request, "").await.status(),
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[tokio::test]
+    async fn test_accepts_header_or_form_field() {
+        let store = Store::memory();
+        let (cookies, token) = start_session(&store).await;
+
+        let request = post_with("/api/table", &cookies).header(HEADER_NAME, &token);
+        assert_eq!(send(&store, request, "").await.status(), StatusCode::OK);
+
+        let request = post_with("/api/table", &cookies)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded");
+        let body = format!("query=SELECT+1&csrf_token={}", token);
+        let response = send(&store, request, &body).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        // The handler still sees the whole form
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(bytes, body.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_json_api_exempt() {
+        let store = Store::memory();
+        let request = Request::builder().method("POST").uri("/api/v1/query");
+        let response = send(&store, request, "").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(header::SET_COOKIE).is_none());
+    }
+
+    #[test]
+    fn test_tokens_match() {
+        assert!(tokens_match(TOKEN, TOKEN));
+        assert!(!tokens_match(TOKEN, &TOKEN[1..]));
+        assert!(!tokens_match(TOKEN, &"0".repeat(64)));
+        assert!(is_well_formed(&generate_token()));
+        assert_ne!(generate_token(), generate_token());
+    }
+}
@@ -0,0 +1,129 @@
+/// Request Locale
+///
+/// Picks the language a request is answered in: the session's `locale`
+/// preference when set, else the best match for the `Accept-Language`
+/// header, else English. It runs inside the session middleware and keeps the
+/// locale in scope for the rest of the request, where templates and error
+/// messages read it.
+use crate::i18n;
+use crate::middleware::session::Session;
+use crate::services::preferences_service::Preferences;
+use axum::{
+    extract::Request,
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use std::future::Future;
+
+tokio::task_local! {
+    static CURRENT: &'static str;
+}
+
+/// Resolve the request's locale, and run the rest of the request with it in scope
+pub async fn locale(request: Request, next: Next) -> Response {
+    let preferred = match request.extensions().get::<Session>() {
+        Some(session) => Preferences::load(session).await.locale,
+        None => None,
+    }
+    .and_then(|locale| i18n::supported(&locale));
+    let locale = preferred
+        .or_else(|| {
+            request
+                .headers()
+                .get(header::ACCEPT_LANGUAGE)
+                .and_then(|v| v.to_str().ok())
+                .and_then(i18n::negotiate)
+        })
+        .unwrap_or(i18n::DEFAULT_LOCALE);
+
+    let mut response = scope(locale, next.run(request)).await;
+    let headers = response.headers_mut();
+    headers.insert(header::CONTENT_LANGUAGE, HeaderValue::from_static(locale));
+    headers.append(header::VARY, HeaderValue::from_static("Accept-Language"));
+    response
+}
+
+/// Run a future with `locale` as the current locale
+pub async fn scope<F: Future>(locale: &'static str, f: F) -> F::Output {
+    CURRENT.scope(locale, f).await
+}
+
+/// The locale of the request being handled by the current task, English
+/// outside requests
+pub fn current() -> &'static str {
+    CURRENT
+        .try_with(|locale| *locale)
+        .unwrap_or(i18n::DEFAULT_LOCALE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::session_service::Store;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use http_body_util::BodyExt;
+    use serde_json::json;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    async fn greeting() -> String {
+        format!("{} {}", current(), i18n::tr("Query Editor"))
+    }
+
+    async fn send(session: Option<Session>, accept_language: Option<&str>) -> (String, String) {
+        let mut app = Router::new()
+            .route("/", get(greeting))
+            .layer(middleware::from_fn(locale));
+        if let Some(session) = session {
+            app = app.layer(axum::Extension(session));
+        }
+        let mut request = Request::builder().uri("/");
+        if let Some(value) = accept_language {
+            request = request.header("accept-language", value);
+        }
+        let response = app
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let header = response.headers()["content-language"]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        (header, String::from_utf8(bytes.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_accept_language() {
+        assert_eq!(
+            send(None, None).await,
+            ("en".into(), "en Query Editor".into())
+        );
+        assert_eq!(
+            send(None, Some("de-CH, en;q=0.5")).await,
+            ("de".into(), "de Abfrage-Editor".into())
+        );
+        assert_eq!(send(None, Some("ja")).await.0, "en");
+    }
+
+    async fn session_with(preferences: serde_json::Value) -> Session {
+        let session = Session::new(None, Arc::new(Store::memory()), None);
+        session.insert("preferences", preferences).await.unwrap();
+        session
+    }
+
+    #[tokio::test]
+    async fn test_preference_wins() {
+        let session = session_with(json!({ "locale": "es" })).await;
+        assert_eq!(send(Some(session), Some("de")).await.0, "es");
+
+        let session = session_with(json!({ "theme": "dark" })).await;
+        assert_eq!(send(Some(session), Some("fr")).await.0, "fr");
+    }
+
+    #[test]
+    fn test_english_outside_requests() {
+        assert_eq!(current(), "en");
+    }
+}
@@ -1,8 +1,28 @@
+pub mod client_ip;
+pub mod connection;
+pub mod csrf;
+pub mod locale;
+pub mod query_limit;
+pub mod quota;
 pub mod rate_limit;
+pub mod request_id;
+pub mod response_format;
 /// Middleware module
 ///
 /// Contains custom middleware for the application including:
 /// - Security headers (XSS, clickjacking, MIME sniffing prevention)
 /// - Rate limiting (per-IP request throttling)
+/// - CSRF tokens for mutating requests
+/// - Server-side sessions (preferences, open transactions)
+/// - The session's selected connection profile
+/// - Row and export quotas for the connection's role
+/// - Concurrent query limits (global and per client IP)
+/// - Request timeouts (504 instead of hanging on a stuck query)
+/// - Client IP resolution behind trusted proxies (rate limiting, audit, logs)
+/// - Request IDs for correlating logs, audit events and errors
+/// - Response format negotiation (HTML fragment or JSON)
+/// - The request's locale for translated pages and messages
 /// - Request logging and tracing
 pub mod security_headers;
+pub mod session;
+pub mod timeout;
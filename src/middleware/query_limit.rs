@@ -0,0 +1,273 @@
+/// Concurrent Query Limits
+///
+/// Caps how many user queries run at once, in total
+/// (`MAX_CONCURRENT_QUERIES`) and per client IP
+/// (`MAX_CONCURRENT_QUERIES_PER_CLIENT`), so one client sending a batch of
+/// heavy queries cannot take every pooled connection. Handlers that run user
+/// SQL or read table data (browsing, profiling, diffs) extract `QuerySlot`,
+/// which holds a slot until the response is built.
+///
+/// A query over either limit waits in line for up to
+/// `QUERY_QUEUE_TIMEOUT_SECS`, then is refused with a 429. A client waits
+/// for one of its own slots before it takes a place in the shared line, so
+/// its queued queries never hold slots others could use.
+use crate::error::AppError;
+use crate::middleware::client_ip::ClientIp;
+use crate::AppState;
+use axum::{extract::FromRequestParts, http::request::Parts};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Query concurrency limits; 0 means no limit
+#[derive(Debug, Clone, Copy)]
+pub struct QueryLimitConfig {
+    pub max_concurrent: usize,
+    pub max_per_client: usize,
+    pub queue_timeout: Duration,
+}
+
+/// Queries of one client that are running or waiting for a shared slot
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ClientQueries {
+    pub client: String,
+    pub running: usize,
+}
+
+/// Limits and what is running now, for the admin listing
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryLimitUsage {
+    pub max_concurrent: usize,
+    pub max_per_client: usize,
+    pub queue_timeout_secs: u64,
+    pub running: usize,
+    /// Queries waiting for a slot
+    pub queued: usize,
+    /// Busiest clients first
+    pub clients: Vec<ClientQueries>,
+}
+
+/// Slots for running queries, shared and per client
+pub struct QueryLimiter {
+    config: QueryLimitConfig,
+    global: Option<Arc<Semaphore>>,
+    /// A client's entry is removed once none of its queries run or wait
+    clients: parking_lot::Mutex<HashMap<String, Arc<Semaphore>>>,
+    queued: AtomicUsize,
+}
+
+impl QueryLimiter {
+    pub fn new(config: QueryLimitConfig) -> Self {
+        Self {
+            config,
+            global: (config.max_concurrent > 0)
+                .then(|| Arc::new(Semaphore::new(config.max_concurrent))),
+            clients: parking_lot::Mutex::new(HashMap::new()),
+            queued: AtomicUsize::new(0),
+        }
+    }
+
+    /// Wait for a slot for one of `client`'s queries, giving up after the
+    /// queue timeout
+    pub async fn acquire(self: &Arc<Self>, client: &str) -> Result<QueryPermit, String> {
+        let client_slots = (self.config.max_per_client > 0).then(|| {
+            self.clients
+                .lock()
+                .entry(client.to_string())
+                .or_insert_with(|| Arc::new(Semaphore::new(self.config.max_per_client)))
+                .clone()
+        });
+
+        let _queued = Queued::enter(&self.queued);
+        let slots = async {
+            let client = match client_slots {
+                Some(slots) => Some(slots.acquire_owned().await),
+                None => None,
+            };
+            let global = match &self.global {
+                Some(slots) => Some(slots.clone().acquire_owned().await),
+                None => None,
+            };
+            (client, global)
+        };
+        let acquired = tokio::time::timeout(self.config.queue_timeout, slots).await;
+        match acquired {
+            // The semaphores are never closed
+            Ok((client, global)) => Ok(QueryPermit {
+                client: client.and_then(Result::ok),
+                _global: global.and_then(Result::ok),
+                limiter: self.clone(),
+            }),
+            Err(_) => {
+                self.prune();
+                Err(format!(
+                    "Too many queries are running (at most {} at once, {} per client); try again when one finishes",
+                    limit(self.config.max_concurrent),
+                    limit(self.config.max_per_client)
+                ))
+            }
+        }
+    }
+
+    /// Forget clients with no query running or waiting
+    fn prune(&self) {
+        self.clients
+            .lock()
+            .retain(|_, slots| Arc::strong_count(slots) > 1);
+    }
+
+    pub fn usage(&self) -> QueryLimitUsage {
+        let mut clients: Vec<ClientQueries> = self
+            .clients
+            .lock()
+            .iter()
+            .map(|(client, slots)| ClientQueries {
+                client: client.clone(),
+                running: self.config.max_per_client - slots.available_permits(),
+            })
+            .filter(|client| client.running > 0)
+            .collect();
+        clients.sort_by(|a, b| b.running.cmp(&a.running).then(a.client.cmp(&b.client)));
+        QueryLimitUsage {
+            max_concurrent: self.config.max_concurrent,
+            max_per_client: self.config.max_per_client,
+            queue_timeout_secs: self.config.queue_timeout.as_secs(),
+            running: self
+                .global
+                .as_ref()
+                .map(|slots| self.config.max_concurrent - slots.available_permits())
+                .unwrap_or(0),
+            queued: self.queued.load(Ordering::Relaxed),
+            clients,
+        }
+    }
+}
+
+fn limit(max: usize) -> String {
+    match max {
+        0 => "no limit".to_string(),
+        max => max.to_string(),
+    }
+}
+
+/// Counts a query as queued until it gets its slots or gives up
+struct Queued<'a>(&'a AtomicUsize);
+
+impl<'a> Queued<'a> {
+    fn enter(queued: &'a AtomicUsize) -> Self {
+        queued.fetch_add(1, Ordering::Relaxed);
+        Queued(queued)
+    }
+}
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Slots held while a query runs, given back on drop
+pub struct QueryPermit {
+    client: Option<OwnedSemaphorePermit>,
+    _global: Option<OwnedSemaphorePermit>,
+    limiter: Arc<QueryLimiter>,
+}
+
+impl Drop for QueryPermit {
+    fn drop(&mut self) {
+        if self.client.take().is_some() {
+            self.limiter.prune();
+        }
+    }
+}
+
+/// Extractor holding a query slot for the request's client until the handler
+/// returns; rejected with a 429 when none frees up in time
+pub struct QuerySlot {
+    _permit: QueryPermit,
+}
+
+impl FromRequestParts<AppState> for QuerySlot {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let Ok(ClientIp(client_ip)) = ClientIp::from_request_parts(parts, state).await;
+        state
+            .query_limiter
+            .acquire(&client_ip)
+            .await
+            .map(|permit| QuerySlot { _permit: permit })
+            .map_err(AppError::TooManyRequests)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(max_concurrent: usize, max_per_client: usize) -> Arc<QueryLimiter> {
+        Arc::new(QueryLimiter::new(QueryLimitConfig {
+            max_concurrent,
+            max_per_client,
+            queue_timeout: Duration::from_millis(50),
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_per_client_limit() {
+        let limiter = limiter(3, 2);
+        let first = limiter.acquire("10.0.0.1").await.unwrap();
+        let _second = limiter.acquire("10.0.0.1").await.unwrap();
+        let refused = limiter.acquire("10.0.0.1").await.err().unwrap();
+        assert!(refused.contains("2 per client"));
+
+        // Others still get the slot left
+        let _other = limiter.acquire("10.0.0.2").await.unwrap();
+        let usage = limiter.usage();
+        assert_eq!(usage.running, 3);
+        assert_eq!(usage.queued, 0);
+        assert_eq!(
+            usage.clients[0],
+            ClientQueries {
+                client: "10.0.0.1".to_string(),
+                running: 2
+            }
+        );
+
+        drop(first);
+        assert!(limiter.acquire("10.0.0.1").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_queued_query_runs_when_a_slot_frees() {
+        let limiter = limiter(1, 0);
+        let running = limiter.acquire("10.0.0.1").await.unwrap();
+
+        let waiting = {
+            let limiter = limiter.clone();
+            tokio::spawn(async move { limiter.acquire("10.0.0.2").await.map(|_| ()) })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(limiter.usage().queued, 1);
+        drop(running);
+        assert!(waiting.await.unwrap().is_ok());
+        assert_eq!(limiter.usage().queued, 0);
+    }
+
+    #[tokio::test]
+    async fn test_clients_are_forgotten() {
+        let limiter = limiter(0, 1);
+        let permit = limiter.acquire("10.0.0.1").await.unwrap();
+        assert!(limiter.acquire("10.0.0.1").await.is_err());
+        assert_eq!(limiter.clients.lock().len(), 1);
+        drop(permit);
+        assert!(limiter.clients.lock().is_empty());
+        assert_eq!(limiter.usage().running, 0);
+    }
+}
@@ -0,0 +1,118 @@
+/// Quotas
+///
+/// Handlers that run user SQL, page through table rows or return files
+/// extract `Quota` to get the limits for the PostgreSQL role of the session's
+/// selected connection (see `quota_service`). Queries run through
+/// `Quota::limit_rows` are stopped while fetching once they pass the row
+/// limit. A refused check responds with a 403 when the request is too large
+/// to ever be allowed, or a 429 when the daily allowance is used up, and is
+/// recorded as an `AccessDenied` audit event.
+use crate::error::AppError;
+use crate::middleware::client_ip::ClientIp;
+use crate::middleware::connection::selected_connection_id;
+use crate::middleware::session::Session;
+use crate::services::audit_service::{AuditEvent, AuditEventType};
+use crate::services::query_service::{self, RowLimitExceeded};
+use crate::services::quota_service::{QuotaExceeded, QuotaLimits};
+use crate::AppState;
+use axum::{extract::FromRequestParts, http::request::Parts};
+use std::future::Future;
+
+/// Quota limits for the request's connection role and client
+pub struct Quota {
+    pub role: String,
+    pub limits: QuotaLimits,
+    client_ip: String,
+    state: AppState,
+}
+
+impl FromRequestParts<AppState> for Quota {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let Ok(ClientIp(client_ip)) = ClientIp::from_request_parts(parts, state).await;
+        let id = selected_connection_id(parts.extensions.get::<Session>(), state).await;
+        let role = state
+            .connections
+            .get(&id)
+            .await
+            .map(|profile| profile.user)
+            .unwrap_or_default();
+        Ok(Quota {
+            limits: state.quotas.limits(&role),
+            role,
+            client_ip,
+            state: state.clone(),
+        })
+    }
+}
+
+impl Quota {
+    /// Run `f`, stopping any query it runs as soon as it returns more rows
+    /// than the role may fetch, and refusing the request if one was stopped
+    pub async fn limit_rows<F: Future>(&self, query: &str, f: F) -> Result<F::Output, AppError> {
+        let (output, exceeded) = query_service::with_row_limit(self.limits.max_rows, f).await;
+        match (exceeded, self.limits.max_rows) {
+            (true, Some(max)) => {
+                let message = RowLimitExceeded { max }.to_string();
+                Err(self
+                    .refuse(QuotaExceeded::TooLarge(message), query, "quota:max_rows")
+                    .await)
+            }
+            _ => Ok(output),
+        }
+    }
+
+    /// Refuse an export once the client's daily allowance is used up
+    pub async fn check_export_allowance(&self, action: &str) -> Result<(), AppError> {
+        match self
+            .state
+            .quotas
+            .check_export_allowance(&self.client_ip, &self.limits)
+        {
+            Ok(()) => Ok(()),
+            Err(exceeded) => Err(self
+                .refuse(exceeded, action, "quota:max_exports_per_day")
+                .await),
+        }
+    }
+
+    /// Refuse an export file larger than the role may download, or else count
+    /// it against the client's daily allowance
+    pub async fn record_export(&self, action: &str, bytes: usize) -> Result<(), AppError> {
+        if let Err(exceeded) = self.limits.check_export_size(bytes) {
+            return Err(self
+                .refuse(exceeded, action, "quota:max_export_bytes")
+                .await);
+        }
+        self.state.quotas.record_export(&self.client_ip);
+        Ok(())
+    }
+
+    async fn refuse(&self, exceeded: QuotaExceeded, action: &str, resource: &str) -> AppError {
+        self.state
+            .audit_logger
+            .log(
+                AuditEvent::new(
+                    AuditEventType::AccessDenied,
+                    self.client_ip.clone(),
+                    action.to_string(),
+                    resource.to_string(),
+                )
+                .with_success(false)
+                .with_details(format!(
+                    "role {}: {}",
+                    self.role,
+                    exceeded.message()
+                )),
+            )
+            .await;
+        match exceeded {
+            QuotaExceeded::TooLarge(message) => AppError::Forbidden(message),
+            QuotaExceeded::Exhausted(message) => AppError::TooManyRequests(message),
+        }
+    }
+}
@@ -2,21 +2,53 @@
 ///
 /// Implements per-IP rate limiting to prevent abuse and DoS attacks.
 /// Uses a token bucket algorithm to limit the number of requests per minute.
-use axum::{extract::ConnectInfo, http::StatusCode, middleware::Next, response::IntoResponse};
+/// Limiters for IPs that have gone quiet are evicted periodically so the map
+/// does not grow with every address ever seen.
+///
+/// The limit can be changed at runtime by a configuration reload or the
+/// admin API, which starts every IP over with a fresh bucket. Admins can
+/// also see each IP's usage, reset one IP's bucket, or exempt an IP for a
+/// while (`/api/admin/rate-limits`).
+use crate::middleware::client_ip::ClientIp;
+use axum::{
+    http::{header, StatusCode},
+    middleware::Next,
+    response::IntoResponse,
+};
+use chrono::{DateTime, Utc};
 use governor::{
-    clock::DefaultClock,
+    clock::{Clock, DefaultClock},
+    middleware::StateInformationMiddleware,
     state::{InMemoryState, NotKeyed},
     Quota, RateLimiter,
 };
-use std::net::SocketAddr;
+use serde::Serialize;
+use std::collections::HashMap;
 use std::num::NonZeroU32;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+type Limiter = RateLimiter<NotKeyed, InMemoryState, DefaultClock, StateInformationMiddleware>;
+
+type LimiterMap = Arc<parking_lot::RwLock<HashMap<String, TrackedLimiter>>>;
+
+/// A bucket refills completely within a minute, so a limiter idle that long
+/// is indistinguishable from a new one and can be dropped
+const IDLE_EVICTION_AFTER: Duration = Duration::from_secs(60);
 
-type LimiterMap = Arc<
-    parking_lot::RwLock<
-        std::collections::HashMap<String, Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>>,
-    >,
->;
+/// How often idle limiters are evicted
+const EVICTION_INTERVAL: Duration = Duration::from_secs(60);
+
+struct TrackedLimiter {
+    limiter: Limiter,
+    last_seen: Instant,
+    /// Requests let through and turned away since the limiter was created
+    allowed: u64,
+    rejected: u64,
+    /// Requests left in the bucket after the last one
+    remaining: u32,
+}
 
 /// Configuration for rate limiting
 pub struct RateLimitConfig {
@@ -32,51 +64,207 @@ impl Default for RateLimitConfig {
     }
 }
 
+/// One IP's use of its bucket
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientUsage {
+    pub ip: String,
+    /// Since the IP's limiter was created (it is dropped after a minute idle)
+    pub allowed: u64,
+    pub rejected: u64,
+    /// Requests the IP could make right now
+    pub remaining: u32,
+    pub idle_secs: u64,
+}
+
+/// An IP the limiter lets through until `until`
+#[derive(Debug, Clone, Serialize)]
+pub struct Exemption {
+    pub ip: String,
+    pub until: DateTime<Utc>,
+}
+
+/// The limit in effect and every tracked IP, most rejected first
+#[derive(Debug, Clone, Serialize)]
+pub struct RateLimitUsage {
+    pub requests_per_minute: u32,
+    pub clients: Vec<ClientUsage>,
+    pub exemptions: Vec<Exemption>,
+}
+
 /// Rate limiter that tracks requests per IP address
 ///
 /// Uses the `governor` crate for efficient rate limiting with a token bucket algorithm.
 pub struct RateLimitState {
     limiters: LimiterMap,
-    config: RateLimitConfig,
+    requests_per_minute: AtomicU32,
+    /// Exempt IPs and when their exemption ends
+    exemptions: parking_lot::RwLock<HashMap<String, DateTime<Utc>>>,
 }
 
 impl RateLimitState {
     /// Create a new rate limit state with the given configuration
     pub fn new(config: RateLimitConfig) -> Self {
         Self {
-            limiters: Arc::new(parking_lot::RwLock::new(std::collections::HashMap::new())),
-            config,
+            limiters: Arc::new(parking_lot::RwLock::new(HashMap::new())),
+            requests_per_minute: AtomicU32::new(config.requests_per_minute),
+            exemptions: parking_lot::RwLock::new(HashMap::new()),
         }
     }
 
-    /// Get or create a rate limiter for the given IP address
-    fn get_or_create_limiter(
-        &self,
-        ip: &str,
-    ) -> Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>> {
+    /// Change the per-IP limit, dropping existing limiters so it applies at once
+    pub fn set_requests_per_minute(&self, requests_per_minute: u32) {
+        let mut limiters = self.limiters.write();
+        self.requests_per_minute
+            .store(requests_per_minute, Ordering::Relaxed);
+        limiters.clear();
+    }
+
+    pub fn requests_per_minute(&self) -> u32 {
+        self.requests_per_minute.load(Ordering::Relaxed)
+    }
+
+    /// Bucket size; a limit of 0 still lets one request through
+    fn burst(&self) -> NonZeroU32 {
+        NonZeroU32::new(self.requests_per_minute().max(1)).unwrap()
+    }
+
+    /// Check a request from the given IP, returning how long to wait when
+    /// the limit is exceeded
+    pub fn check(&self, ip: &str) -> Result<(), Duration> {
+        if self.is_exempt(ip) {
+            return Ok(());
+        }
+
         let mut limiters = self.limiters.write();
+        let tracked = limiters
+            .entry(ip.to_string())
+            .or_insert_with(|| TrackedLimiter {
+                limiter: RateLimiter::direct(Quota::per_minute(self.burst()))
+                    .with_middleware::<StateInformationMiddleware>(),
+                last_seen: Instant::now(),
+                allowed: 0,
+                rejected: 0,
+                remaining: 0,
+            });
+        tracked.last_seen = Instant::now();
 
-        if let Some(limiter) = limiters.get(ip) {
-            Arc::clone(limiter)
-        } else {
-            let quota =
-                Quota::per_minute(NonZeroU32::new(self.config.requests_per_minute).unwrap());
-            let limiter = Arc::new(RateLimiter::direct(quota));
-            limiters.insert(ip.to_string(), Arc::clone(&limiter));
-            limiter
+        match tracked.limiter.check() {
+            Ok(snapshot) => {
+                tracked.allowed += 1;
+                tracked.remaining = snapshot.remaining_burst_capacity();
+                Ok(())
+            }
+            Err(not_until) => {
+                tracked.rejected += 1;
+                tracked.remaining = 0;
+                Err(not_until.wait_time_from(DefaultClock::default().now()))
+            }
         }
     }
 
     /// Check if a request from the given IP should be allowed
     pub fn check_limit(&self, ip: &str) -> bool {
-        let limiter = self.get_or_create_limiter(ip);
-        limiter.check().is_ok()
+        self.check(ip).is_ok()
+    }
+
+    /// Give an IP a fresh bucket, returning whether it was tracked
+    pub fn reset(&self, ip: &str) -> bool {
+        self.limiters.write().remove(ip).is_some()
+    }
+
+    /// Let every request from an IP through until `until`
+    pub fn exempt(&self, ip: &str, until: DateTime<Utc>) {
+        self.exemptions.write().insert(ip.to_string(), until);
+    }
+
+    /// End an IP's exemption, returning whether it had one
+    pub fn remove_exemption(&self, ip: &str) -> bool {
+        self.exemptions.write().remove(ip).is_some()
+    }
+
+    fn is_exempt(&self, ip: &str) -> bool {
+        self.exemptions
+            .read()
+            .get(ip)
+            .is_some_and(|until| *until > Utc::now())
+    }
+
+    /// The limit and every tracked IP's usage
+    pub fn usage(&self) -> RateLimitUsage {
+        let burst = self.burst().get();
+        let mut clients: Vec<ClientUsage> = self
+            .limiters
+            .read()
+            .iter()
+            .map(|(ip, tracked)| {
+                // The bucket has refilled at `burst` per minute since the last request
+                let idle = tracked.last_seen.elapsed();
+                let refilled = (idle.as_secs_f64() * burst as f64 / 60.0) as u32;
+                ClientUsage {
+                    ip: ip.clone(),
+                    allowed: tracked.allowed,
+                    rejected: tracked.rejected,
+                    remaining: tracked.remaining.saturating_add(refilled).min(burst),
+                    idle_secs: idle.as_secs(),
+                }
+            })
+            .collect();
+        clients.sort_by(|a, b| (b.rejected, b.allowed, &a.ip).cmp(&(a.rejected, a.allowed, &b.ip)));
+
+        let now = Utc::now();
+        let mut exemptions: Vec<Exemption> = self
+            .exemptions
+            .read()
+            .iter()
+            .filter(|(_, until)| **until > now)
+            .map(|(ip, until)| Exemption {
+                ip: ip.clone(),
+                until: *until,
+            })
+            .collect();
+        exemptions.sort_by(|a, b| a.ip.cmp(&b.ip));
+
+        RateLimitUsage {
+            requests_per_minute: self.requests_per_minute(),
+            clients,
+            exemptions,
+        }
+    }
+
+    /// Drop limiters for IPs not seen within `max_idle`, returning how many were dropped
+    ///
+    /// Expired exemptions are dropped too.
+    pub fn evict_idle(&self, max_idle: Duration) -> usize {
+        let now = Utc::now();
+        self.exemptions.write().retain(|_, until| *until > now);
+
+        let mut limiters = self.limiters.write();
+        let before = limiters.len();
+        let now = Instant::now();
+        limiters.retain(|_, tracked| now.duration_since(tracked.last_seen) < max_idle);
+        before - limiters.len()
+    }
+
+    /// Start the background task that evicts idle limiters
+    pub fn spawn_evictor(self: &Arc<Self>) {
+        let state = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(EVICTION_INTERVAL);
+            loop {
+                interval.tick().await;
+                let evicted = state.evict_idle(IDLE_EVICTION_AFTER);
+                if evicted > 0 {
+                    tracing::debug!("Evicted {} idle rate limiters", evicted);
+                }
+            }
+        });
     }
 }
 
 /// Rate limiting middleware that checks requests against per-IP limits
 ///
-/// Extracts the client IP address and checks if the rate limit for that IP
+/// Takes the client IP resolved by the `client_ip` middleware (the real client
+/// behind a trusted proxy) and checks if the rate limit for that IP
 /// has been exceeded. If the limit is exceeded, returns 429 Too Many Requests.
 ///
 /// # Example
@@ -91,17 +279,18 @@ impl RateLimitState {
 ///     ))
 /// ```
 pub async fn rate_limit_middleware(
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    ClientIp(ip): ClientIp,
     state: axum::extract::State<Arc<RateLimitState>>,
     req: axum::extract::Request,
     next: Next,
 ) -> impl IntoResponse {
-    let ip = addr.ip().to_string();
-
     // Check rate limit
-    if !state.check_limit(&ip) {
+    if let Err(wait) = state.check(&ip) {
+        // Retry-After is in whole seconds; round up so retrying then succeeds
+        let retry_after = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
         return axum::response::Response::builder()
             .status(StatusCode::TOO_MANY_REQUESTS)
+            .header(header::RETRY_AFTER, retry_after)
             .body(axum::body::Body::from("Rate limit exceeded"))
             .unwrap()
             .into_response();
@@ -183,6 +372,97 @@ mod tests {
         assert!(!state.check_limit("192.168.1.2"));
     }
 
+    #[test]
+    fn test_evict_idle_limiters() {
+        let state = RateLimitState::new(RateLimitConfig {
+            requests_per_minute: 1,
+        });
+        assert!(state.check_limit("10.0.0.1"));
+        assert!(state.check_limit("10.0.0.2"));
+
+        // Recently seen limiters are kept, so the quota still applies
+        assert_eq!(state.evict_idle(IDLE_EVICTION_AFTER), 0);
+        assert!(!state.check_limit("10.0.0.1"));
+
+        assert_eq!(state.evict_idle(Duration::ZERO), 2);
+        assert!(state.limiters.read().is_empty());
+    }
+
+    #[test]
+    fn test_zero_limit_allows_one_request() {
+        let state = RateLimitState::new(RateLimitConfig {
+            requests_per_minute: 0,
+        });
+        assert!(state.check_limit("10.0.0.1"));
+        assert!(!state.check_limit("10.0.0.1"));
+    }
+
+    #[test]
+    fn test_set_requests_per_minute() {
+        let state = RateLimitState::new(RateLimitConfig {
+            requests_per_minute: 1,
+        });
+        assert!(state.check_limit("10.0.0.1"));
+        assert!(!state.check_limit("10.0.0.1"));
+
+        // A new limit starts every IP over
+        state.set_requests_per_minute(3);
+        assert!(state.check_limit("10.0.0.1"));
+        assert!(state.check_limit("10.0.0.1"));
+        assert!(state.check_limit("10.0.0.1"));
+        assert!(!state.check_limit("10.0.0.1"));
+    }
+
+    #[test]
+    fn test_usage_and_reset() {
+        let state = RateLimitState::new(RateLimitConfig {
+            requests_per_minute: 2,
+        });
+        assert!(state.check_limit("10.0.0.1"));
+        assert!(state.check_limit("10.0.0.2"));
+        assert!(state.check_limit("10.0.0.2"));
+        let wait = state.check("10.0.0.2").unwrap_err();
+        assert!(wait > Duration::ZERO && wait <= Duration::from_secs(30));
+
+        let usage = state.usage();
+        assert_eq!(usage.requests_per_minute, 2);
+        let clients: Vec<_> = usage
+            .clients
+            .iter()
+            .map(|c| (c.ip.as_str(), c.allowed, c.rejected, c.remaining))
+            .collect();
+        assert_eq!(clients, vec![("10.0.0.2", 2, 1, 0), ("10.0.0.1", 1, 0, 1)]);
+
+        assert!(state.reset("10.0.0.2"));
+        assert!(!state.reset("10.0.0.3"));
+        assert!(state.check_limit("10.0.0.2"));
+    }
+
+    #[test]
+    fn test_exemption() {
+        let state = RateLimitState::new(RateLimitConfig {
+            requests_per_minute: 1,
+        });
+        state.exempt("10.0.0.1", Utc::now() + chrono::Duration::minutes(5));
+        for _ in 0..5 {
+            assert!(state.check_limit("10.0.0.1"));
+        }
+        assert_eq!(state.usage().exemptions.len(), 1);
+
+        assert!(state.remove_exemption("10.0.0.1"));
+        assert!(!state.remove_exemption("10.0.0.1"));
+        assert!(state.check_limit("10.0.0.1"));
+        assert!(!state.check_limit("10.0.0.1"));
+
+        // Expired exemptions no longer apply and are dropped on eviction
+        state.exempt("10.0.0.2", Utc::now() - chrono::Duration::seconds(1));
+        assert!(state.check_limit("10.0.0.2"));
+        assert!(!state.check_limit("10.0.0.2"));
+        assert!(state.usage().exemptions.is_empty());
+        state.evict_idle(IDLE_EVICTION_AFTER);
+        assert!(state.exemptions.read().is_empty());
+    }
+
     #[test]
     fn test_default_limits() {
         let limits = EndpointRateLimits::default();
@@ -0,0 +1,131 @@
+/// Request IDs
+///
+/// Every request gets an id, taken from the client's `X-Request-Id` header when
+/// it looks sane and generated otherwise. It is echoed in the response header,
+/// recorded on the request's tracing span, audit events and error bodies, so a
+/// user can quote it when reporting a failure.
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use std::future::Future;
+
+pub const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest client-supplied id that is propagated instead of replaced
+const MAX_LENGTH: usize = 128;
+
+tokio::task_local! {
+    static CURRENT: RequestId;
+}
+
+/// The id of a request, stored in its extensions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+/// Assign a request id, and run the rest of the request with it in scope
+pub async fn request_id(mut request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(&X_REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| is_valid(v))
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let id = RequestId(id);
+    request.extensions_mut().insert(id.clone());
+
+    let mut response = scope(id.0.clone(), next.run(request)).await;
+    if let Ok(value) = HeaderValue::from_str(&id.0) {
+        response.headers_mut().insert(X_REQUEST_ID.clone(), value);
+    }
+    response
+}
+
+/// Run a future with `id` as the current request id
+pub async fn scope<F: Future>(id: String, f: F) -> F::Output {
+    CURRENT.scope(RequestId(id), f).await
+}
+
+/// The id of the request being handled by the current task, if any
+pub fn current() -> Option<String> {
+    CURRENT.try_with(|id| id.0.clone()).ok()
+}
+
+/// Accept ids made of letters, digits and `-_.:` so they are safe to log and echo
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_LENGTH
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    async fn echo() -> String {
+        current().unwrap_or_default()
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .route("/", get(echo))
+            .layer(middleware::from_fn(request_id))
+    }
+
+    async fn send(header: Option<&str>) -> (String, String) {
+        let mut request = Request::builder().uri("/");
+        if let Some(value) = header {
+            request = request.header("x-request-id", value);
+        }
+        let response = router()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let header = response.headers()["x-request-id"]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        (header, String::from_utf8(bytes.to_vec()).unwrap())
+    }
+
+    #[test]
+    fn test_valid_ids() {
+        assert!(is_valid("3f2a9c1e-7b7d-4d1a-9a53-1f0c2b9e8d11"));
+        assert!(is_valid("req_42.retry:1"));
+        assert!(!is_valid(""));
+        assert!(!is_valid("id with spaces"));
+        assert!(!is_valid("<script>"));
+        assert!(!is_valid(&"a".repeat(MAX_LENGTH + 1)));
+    }
+
+    #[tokio::test]
+    async fn test_generates_id() {
+        let (header, in_handler) = send(None).await;
+        assert!(uuid::Uuid::parse_str(&header).is_ok());
+        assert_eq!(in_handler, header);
+    }
+
+    #[tokio::test]
+    async fn test_propagates_client_id() {
+        let (header, in_handler) = send(Some("ci-build-1234")).await;
+        assert_eq!(header, "ci-build-1234");
+        assert_eq!(in_handler, "ci-build-1234");
+
+        let (header, _) = send(Some("bad id\twith tabs")).await;
+        assert_ne!(header, "bad id\twith tabs");
+    }
+
+    #[test]
+    fn test_no_current_id_outside_requests() {
+        assert!(current().is_none());
+    }
+}
@@ -0,0 +1,124 @@
+/// Response Format Negotiation
+///
+/// API routes answer HTMX with HTML fragments by default. Scripts can ask for
+/// the same data as JSON with `Accept: application/json` or `?format=json`;
+/// the query parameter wins over the header.
+use crate::error::AppError;
+use askama::Template;
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderMap},
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use std::convert::Infallible;
+
+/// Extractor for the representation the client asked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResponseFormat {
+    #[default]
+    Html,
+    Json,
+}
+
+impl<S> FromRequestParts<S> for ResponseFormat
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::negotiate(parts.uri.query(), &parts.headers))
+    }
+}
+
+impl ResponseFormat {
+    /// Pick a format from the `format` query parameter, then the Accept header
+    pub fn negotiate(query: Option<&str>, headers: &HeaderMap) -> Self {
+        let requested = query
+            .unwrap_or("")
+            .split('&')
+            .filter_map(|pair| pair.strip_prefix("format="))
+            .find_map(|value| match value {
+                "json" => Some(ResponseFormat::Json),
+                "html" => Some(ResponseFormat::Html),
+                _ => None,
+            });
+        if let Some(format) = requested {
+            return format;
+        }
+
+        // Whichever of the two media types is listed first wins
+        let accept = headers
+            .get(header::ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("");
+        accept
+            .split(',')
+            .map(|media| media.split(';').next().unwrap_or("").trim())
+            .find_map(|media| match media {
+                "application/json" => Some(ResponseFormat::Json),
+                "text/html" => Some(ResponseFormat::Html),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    /// Render the template as an HTML fragment, or serialize its fields as JSON
+    pub fn render<T: Template + Serialize>(self, template: T) -> Result<Response, AppError> {
+        match self {
+            ResponseFormat::Html => Ok(Html(template.render()?).into_response()),
+            ResponseFormat::Json => Ok(Json(template).into_response()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn accept(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn test_negotiate_from_accept_header() {
+        assert_eq!(
+            ResponseFormat::negotiate(None, &HeaderMap::new()),
+            ResponseFormat::Html
+        );
+        assert_eq!(
+            ResponseFormat::negotiate(None, &accept("application/json")),
+            ResponseFormat::Json
+        );
+        assert_eq!(
+            ResponseFormat::negotiate(None, &accept("text/html,application/json;q=0.9")),
+            ResponseFormat::Html
+        );
+        assert_eq!(
+            ResponseFormat::negotiate(None, &accept("*/*")),
+            ResponseFormat::Html
+        );
+    }
+
+    #[test]
+    fn test_format_parameter_overrides_header() {
+        assert_eq!(
+            ResponseFormat::negotiate(Some("page=2&format=json"), &HeaderMap::new()),
+            ResponseFormat::Json
+        );
+        assert_eq!(
+            ResponseFormat::negotiate(Some("format=html"), &accept("application/json")),
+            ResponseFormat::Html
+        );
+        // Unrelated format values (e.g. export formats) are ignored
+        assert_eq!(
+            ResponseFormat::negotiate(Some("format=csv"), &accept("application/json")),
+            ResponseFormat::Json
+        );
+    }
+}
@@ -0,0 +1,207 @@
+/// Sessions
+///
+/// Browser sessions come from tower-sessions, backed by the configured
+/// `session_service::Store`. Handlers extract `Session`; its data is loaded
+/// on first use and saved after the response if a handler changed it. The
+/// session ID travels in the `pgadmin_session` cookie, which is always
+/// `HttpOnly`; its `SameSite` and `Secure` attributes come from
+/// configuration.
+///
+/// A session is only created (and the cookie only set) once something is
+/// stored in it, and an unknown ID from the client is never reused, so a
+/// planted cookie cannot fix another browser's session ID.
+use crate::services::session_service::Store;
+use std::str::FromStr;
+use std::time::Duration;
+use tower_sessions::{cookie, Expiry, SessionManagerLayer};
+
+pub use tower_sessions::Session;
+
+pub const COOKIE_NAME: &str = "pgadmin_session";
+
+/// The cookie's `SameSite` attribute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    /// Sent on cross-site requests too; browsers require `Secure` with it
+    None,
+}
+
+impl FromStr for SameSite {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "strict" => Ok(SameSite::Strict),
+            "lax" | "" => Ok(SameSite::Lax),
+            "none" => Ok(SameSite::None),
+            other => Err(format!("Unknown SameSite value: {}", other)),
+        }
+    }
+}
+
+/// Cookie attributes and lifetime for sessions
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    /// Cookie path, the app's base path or `/`
+    pub cookie_path: String,
+    /// Mark the cookie `Secure` (forced on for `SameSite=None`)
+    pub secure: bool,
+    pub same_site: SameSite,
+    /// Sessions expire this long after they were last changed
+    pub ttl: Duration,
+}
+
+impl SessionConfig {
+    /// The session layer for `store`
+    pub fn layer(&self, store: Store) -> SessionManagerLayer<Store> {
+        let same_site = match self.same_site {
+            SameSite::Strict => cookie::SameSite::Strict,
+            SameSite::Lax => cookie::SameSite::Lax,
+            SameSite::None => cookie::SameSite::None,
+        };
+        let ttl = time::Duration::seconds(self.ttl.as_secs().max(1) as i64);
+        SessionManagerLayer::new(store)
+            .with_name(COOKIE_NAME)
+            .with_http_only(true)
+            .with_path(self.cookie_path.clone())
+            .with_same_site(same_site)
+            .with_secure(self.secure || self.same_site == SameSite::None)
+            .with_expiry(Expiry::OnInactivity(ttl))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Body,
+        http::{header, Request, StatusCode},
+        routing::get,
+        Router,
+    };
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+    use tower_sessions::session::Id;
+    use tower_sessions::SessionStore;
+
+    fn config(same_site: SameSite) -> SessionConfig {
+        SessionConfig {
+            cookie_path: "/".to_string(),
+            secure: false,
+            same_site,
+            ttl: Duration::from_secs(60),
+        }
+    }
+
+    fn router(store: Store, config: &SessionConfig) -> Router {
+        Router::new()
+            .route(
+                "/count",
+                get(|session: Session| async move {
+                    let count = session.get::<u32>("count").await.unwrap().unwrap_or(0) + 1;
+                    session.insert("count", count).await.unwrap();
+                    count.to_string()
+                }),
+            )
+            .route(
+                "/read",
+                get(|session: Session| async move {
+                    let count = session.get::<u32>("count").await.unwrap();
+                    count.unwrap_or(0).to_string()
+                }),
+            )
+            .route(
+                "/clear",
+                get(|session: Session| async move {
+                    session.remove::<u32>("count").await.unwrap();
+                }),
+            )
+            .layer(config.layer(store))
+    }
+
+    async fn get_with_cookie(
+        store: &Store,
+        uri: &str,
+        cookie: Option<&str>,
+    ) -> (StatusCode, Option<String>, String) {
+        let mut request = Request::builder().uri(uri);
+        if let Some(cookie) = cookie {
+            request = request.header(header::COOKIE, cookie);
+        }
+        let response = router(store.clone(), &config(SameSite::Lax))
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let set_cookie = response
+            .headers()
+            .get(header::SET_COOKIE)
+            .map(|v| v.to_str().unwrap().to_string());
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (
+            status,
+            set_cookie,
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_session_round_trip() {
+        let store = Store::memory();
+
+        // Reading alone does not create a session
+        let (_, set_cookie, _) = get_with_cookie(&store, "/read", None).await;
+        assert!(set_cookie.is_none());
+
+        let (status, set_cookie, body) = get_with_cookie(&store, "/count", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "1");
+        let set_cookie = set_cookie.unwrap();
+        assert!(set_cookie.starts_with(COOKIE_NAME));
+        assert!(set_cookie.contains("HttpOnly"));
+        assert!(set_cookie.contains("SameSite=Lax"));
+        assert!(set_cookie.contains("Max-Age=60"));
+        let cookie = set_cookie.split(';').next().unwrap().to_string();
+
+        let (_, _, body) = get_with_cookie(&store, "/count", Some(&cookie)).await;
+        assert_eq!(body, "2");
+        let (_, _, body) = get_with_cookie(&store, "/read", Some(&cookie)).await;
+        assert_eq!(body, "2");
+
+        // Removed keys stay removed
+        get_with_cookie(&store, "/clear", Some(&cookie)).await;
+        let (_, _, body) = get_with_cookie(&store, "/read", Some(&cookie)).await;
+        assert_eq!(body, "0");
+    }
+
+    #[tokio::test]
+    async fn test_unknown_session_id_not_reused() {
+        let store = Store::memory();
+        let planted = Id::default();
+        let cookie = format!("{}={}", COOKIE_NAME, planted);
+
+        let (_, set_cookie, _) = get_with_cookie(&store, "/count", Some(&cookie)).await;
+        let set_cookie = set_cookie.unwrap();
+        assert!(!set_cookie.contains(&planted.to_string()));
+        assert!(store.load(&planted).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_same_site_none_forces_secure() {
+        let config = SessionConfig {
+            cookie_path: "/admin".to_string(),
+            ..config("none".parse().unwrap())
+        };
+        let response = router(Store::memory(), &config)
+            .oneshot(Request::get("/count").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(cookie.contains("Path=/admin"));
+        assert!(cookie.contains("SameSite=None"));
+        assert!(cookie.contains("Secure"));
+        assert!("sideways".parse::<SameSite>().is_err());
+    }
+}
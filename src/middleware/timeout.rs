@@ -0,0 +1,136 @@
+/// Request Timeouts
+///
+/// Gives up on a request that has not produced a response in time, so a stuck
+/// query cannot hold a connection and a worker forever. The client gets a 504
+/// with the usual JSON error body (or an HTML alert for HTMX requests).
+///
+/// Query execution, exports, data diffs, backups and restore uploads get a
+/// longer limit than the rest of the app. The timeout covers producing the response, not streaming its
+/// body.
+use crate::error::AppError;
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Path prefixes (below the base path) that use the query timeout
+const QUERY_PREFIXES: &[&str] = &[
+    "/api/query/",
+    "/api/queries/",
+    "/api/v1/query",
+    "/api/data-diff",
+    "/api/backups",
+    "/api/restore",
+];
+
+/// Time limits by path; `None` means no limit
+#[derive(Debug, Clone)]
+pub struct RequestTimeouts {
+    pub default: Option<Duration>,
+    /// Path prefixes with their own limit, checked in order
+    pub groups: Vec<(String, Option<Duration>)>,
+}
+
+impl RequestTimeouts {
+    /// The default limit, and a longer one for query routes; 0 disables a limit
+    pub fn new(base_path: &str, default_secs: u64, query_secs: u64) -> Self {
+        let limit = |secs| (secs > 0).then(|| Duration::from_secs(secs));
+        Self {
+            default: limit(default_secs),
+            groups: QUERY_PREFIXES
+                .iter()
+                .map(|prefix| (format!("{}{}", base_path, prefix), limit(query_secs)))
+                .collect(),
+        }
+    }
+
+    fn for_path(&self, path: &str) -> Option<Duration> {
+        self.groups
+            .iter()
+            .find(|(prefix, _)| path.starts_with(prefix.as_str()))
+            .map_or(self.default, |(_, limit)| *limit)
+    }
+}
+
+/// Fail the request with 504 if it runs past its limit
+pub async fn request_timeout(
+    State(timeouts): State<Arc<RequestTimeouts>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(limit) = timeouts.for_path(request.uri().path()) else {
+        return next.run(request).await;
+    };
+
+    let path = request.uri().path().to_string();
+    match tokio::time::timeout(limit, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            tracing::warn!(path = %path, "request timed out after {}s", limit.as_secs());
+            AppError::Timeout(format!(
+                "The request did not finish within {} seconds",
+                limit.as_secs()
+            ))
+            .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::StatusCode, middleware, routing::get, Router};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    async fn slow() -> &'static str {
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        "done"
+    }
+
+    #[test]
+    fn test_timeout_for_path() {
+        let timeouts = RequestTimeouts::new("/pgadmin", 30, 0);
+        assert_eq!(
+            timeouts.for_path("/pgadmin/api/schemas"),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(timeouts.for_path("/pgadmin/api/query/execute"), None);
+        assert_eq!(timeouts.for_path("/pgadmin/api/v1/query"), None);
+        // Prefixes only match under the base path
+        assert_eq!(
+            timeouts.for_path("/api/query/execute"),
+            Some(Duration::from_secs(30))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_times_out_with_504() {
+        let timeouts = Arc::new(RequestTimeouts {
+            default: Some(Duration::from_secs(1)),
+            groups: vec![("/api/query/".to_string(), Some(Duration::from_secs(10)))],
+        });
+        let app = Router::new()
+            .route("/slow", get(slow))
+            .route("/api/query/slow", get(slow))
+            .layer(middleware::from_fn_with_state(timeouts, request_timeout));
+
+        let request = Request::builder().uri("/slow").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["code"], "timeout");
+
+        // The query group has a longer limit
+        let request = Request::builder()
+            .uri("/api/query/slow")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
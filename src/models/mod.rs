@@ -1,9 +1,10 @@
 // Data models module
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
 mod tests;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Database {
     pub name: String,
     pub owner: Option<String>,
@@ -11,28 +12,81 @@ pub struct Database {
     pub encoding: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Schema {
     pub name: String,
     pub owner: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct TableInfo {
     pub schema: String,
     pub name: String,
+    #[schema(examples("BASE TABLE", "FOREIGN", "VIEW"))]
     pub table_type: String,
     pub row_count: Option<i64>,
     pub size: Option<i64>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A schema with the names of the objects it contains, for the tree view
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SchemaTreeNode {
+    pub name: String,
+    pub tables: Vec<String>,
+    pub views: Vec<String>,
+    pub functions: Vec<String>,
+}
+
+/// Compact catalog snapshot used by the query editor for completion
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AutocompleteMetadata {
+    pub schemas: Vec<String>,
+    pub tables: Vec<AutocompleteRelation>,
+    pub functions: Vec<AutocompleteFunction>,
+    pub keywords: Vec<String>,
+}
+
+/// A table or view with its columns as `[name, type]` pairs
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AutocompleteRelation {
+    pub schema: String,
+    pub name: String,
+    /// `table` or `view`
+    pub kind: String,
+    pub columns: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AutocompleteFunction {
+    pub schema: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ColumnInfo {
     pub name: String,
     pub data_type: String,
     pub is_nullable: bool,
     pub is_pk: bool,
     pub default: Option<String>,
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyInfo {
+    pub name: String,
+    pub permissive: String,
+    pub command: String,
+    pub roles: Vec<String>,
+    pub using_expr: Option<String>,
+    pub with_check_expr: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RowSecurity {
+    pub enabled: bool,
+    pub forced: bool,
+    pub policies: Vec<PolicyInfo>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -44,10 +98,20 @@ pub struct QueryResult {
     pub execution_time_ms: Option<u128>,
 }
 
+impl QueryResult {
+    /// Rows affected by DML, otherwise rows returned (recorded in history)
+    pub fn rows_processed(&self) -> i64 {
+        self.affected_rows.unwrap_or(self.row_count as u64) as i64
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct TableDataParams {
     pub page: Option<u32>,
     pub page_size: Option<u32>,
+    /// Preview a random sample of this percentage of the table's pages
+    /// (`TABLESAMPLE SYSTEM`) instead of paging from the start
+    pub sample: Option<f64>,
 }
 
 #[derive(Debug, Serialize)]
@@ -57,3 +121,183 @@ pub struct Pagination {
     pub total_rows: i64,
     pub total_pages: u32,
 }
+
+/// Query parameters accepted by paginated JSON list endpoints
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PageParams {
+    /// 1-based page number
+    pub page: Option<u32>,
+    /// Items per page (at most 500)
+    pub page_size: Option<u32>,
+    /// Opaque cursor returned as `next_cursor` by a previous page
+    pub cursor: Option<String>,
+    /// Id of the last item already seen, on lists paged by id
+    pub after: Option<String>,
+    /// Alias of `page_size`, taking precedence over it
+    pub limit: Option<u32>,
+}
+
+impl PageParams {
+    pub const MAX_PAGE_SIZE: u32 = 500;
+
+    /// Resolve the 1-based page number, preferring the cursor when present
+    pub fn page(&self) -> u32 {
+        self.cursor
+            .as_deref()
+            .and_then(|c| c.parse().ok())
+            .or(self.page)
+            .unwrap_or(1)
+            .max(1)
+    }
+
+    /// Resolve the page size, clamped to `1..=MAX_PAGE_SIZE`
+    pub fn page_size(&self, default: u32) -> u32 {
+        self.limit
+            .or(self.page_size)
+            .unwrap_or(default)
+            .clamp(1, Self::MAX_PAGE_SIZE)
+    }
+}
+
+/// Standard envelope for paginated JSON list endpoints
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Paginated<T> {
+    pub items: Vec<T>,
+    pub page: u32,
+    pub page_size: u32,
+    pub total: u64,
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Paginated<T> {
+    /// Build a page from a fully materialized list
+    pub fn from_vec(all: Vec<T>, params: &PageParams, default_page_size: u32) -> Self {
+        let page = params.page();
+        let page_size = params.page_size(default_page_size);
+        let total = all.len() as u64;
+        let offset = (page as u64 - 1) * page_size as u64;
+
+        let items: Vec<T> = all
+            .into_iter()
+            .skip(offset as usize)
+            .take(page_size as usize)
+            .collect();
+
+        let next_cursor = if offset + (items.len() as u64) < total {
+            Some((page + 1).to_string())
+        } else {
+            None
+        };
+
+        Self {
+            items,
+            page,
+            page_size,
+            total,
+            next_cursor,
+        }
+    }
+
+    /// Build a page of a list whose items have stable ids, such as the
+    /// history or the audit log
+    ///
+    /// `after` (or `cursor`) starts the page past the item with that id, so
+    /// entries added to the front of the list while paging do not shift
+    /// later pages. `next_cursor` is the id of the page's last item. An id no
+    /// longer in the list was pruned along with everything after it, so it
+    /// gives an empty page.
+    pub fn from_keyed(
+        all: Vec<T>,
+        params: &PageParams,
+        default_page_size: u32,
+        id: impl Fn(&T) -> &str,
+    ) -> Self {
+        let page_size = params.page_size(default_page_size);
+        let total = all.len() as u64;
+        let offset = match params.after.as_deref().or(params.cursor.as_deref()) {
+            Some(after) => all
+                .iter()
+                .position(|item| id(item) == after)
+                .map_or(all.len(), |i| i + 1),
+            None => (params.page.unwrap_or(1).max(1) as usize - 1)
+                .saturating_mul(page_size as usize)
+                .min(all.len()),
+        };
+
+        let items: Vec<T> = all
+            .into_iter()
+            .skip(offset)
+            .take(page_size as usize)
+            .collect();
+        let next_cursor = if (offset + items.len()) < total as usize {
+            items.last().map(|item| id(item).to_string())
+        } else {
+            None
+        };
+
+        Self {
+            page: (offset / page_size as usize) as u32 + 1,
+            items,
+            page_size,
+            total,
+            next_cursor,
+        }
+    }
+
+    /// Build a page whose items were already fetched with LIMIT / OFFSET
+    pub fn from_page(items: Vec<T>, page: u32, page_size: u32, total: u64) -> Self {
+        let fetched = (page as u64 - 1) * page_size as u64 + items.len() as u64;
+        Self {
+            items,
+            page,
+            page_size,
+            total,
+            next_cursor: (fetched < total).then(|| (page + 1).to_string()),
+        }
+    }
+}
+
+/// Envelope for `/api/v1` response bodies
+///
+/// List endpoints return the page's items as `data` and the rest of the page
+/// as `pagination`. Errors use the `{error, details, code}` body instead.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ApiResponse<T> {
+    pub data: T,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pagination: Option<PageInfo>,
+}
+
+/// Position of a page within a paginated `/api/v1` listing
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct PageInfo {
+    pub page: u32,
+    pub page_size: u32,
+    pub total: u64,
+    /// Pass as `cursor` to fetch the next page
+    pub next_cursor: Option<String>,
+}
+
+impl<T> ApiResponse<T> {
+    pub fn new(data: T) -> Self {
+        Self {
+            data,
+            pagination: None,
+        }
+    }
+}
+
+impl<T> From<Paginated<T>> for ApiResponse<Vec<T>> {
+    fn from(page: Paginated<T>) -> Self {
+        Self {
+            data: page.items,
+            pagination: Some(PageInfo {
+                page: page.page,
+                page_size: page.page_size,
+                total: page.total,
+                next_cursor: page.next_cursor,
+            }),
+        }
+    }
+}
@@ -47,6 +47,7 @@ mod model_tests {
         };
 
         assert_eq!(result.affected_rows, Some(5));
+        assert_eq!(result.rows_processed(), 5);
     }
 
     #[test]
@@ -84,6 +85,7 @@ mod model_tests {
             is_nullable: false,
             is_pk: true,
             default: None,
+            comment: None,
         };
 
         assert_eq!(column.name, "id");
@@ -101,6 +103,7 @@ mod model_tests {
             is_nullable: true,
             is_pk: false,
             default: Some("NULL".to_string()),
+            comment: None,
         };
 
         assert!(column.is_nullable);
@@ -182,6 +185,7 @@ mod model_tests {
             is_nullable: false,
             is_pk: false,
             default: Some("CURRENT_TIMESTAMP".to_string()),
+            comment: None,
         };
 
         assert!(!column.is_nullable);
@@ -201,4 +205,199 @@ mod model_tests {
         assert_eq!(table.row_count, Some(0));
         assert!(table.size.is_none());
     }
+
+    #[test]
+    fn test_row_security_serialization() {
+        let security = RowSecurity {
+            enabled: true,
+            forced: false,
+            policies: vec![PolicyInfo {
+                name: "tenant_isolation".to_string(),
+                permissive: "PERMISSIVE".to_string(),
+                command: "SELECT".to_string(),
+                roles: vec!["app_user".to_string()],
+                using_expr: Some(
+                    "(tenant_id = current_setting('app.tenant')::integer)".to_string(),
+                ),
+                with_check_expr: None,
+            }],
+        };
+
+        let value = serde_json::to_value(&security).unwrap();
+        assert_eq!(value["enabled"], json!(true));
+        assert_eq!(value["policies"][0]["name"], json!("tenant_isolation"));
+        assert_eq!(value["policies"][0]["roles"], json!(["app_user"]));
+        assert!(value["policies"][0]["with_check_expr"].is_null());
+    }
+
+    #[test]
+    fn test_paginated_first_page() {
+        let params = PageParams {
+            page: None,
+            page_size: Some(2),
+            cursor: None,
+            ..Default::default()
+        };
+        let page = Paginated::from_vec(vec![1, 2, 3, 4, 5], &params, 50);
+
+        assert_eq!(page.items, vec![1, 2]);
+        assert_eq!(page.page, 1);
+        assert_eq!(page.page_size, 2);
+        assert_eq!(page.total, 5);
+        assert_eq!(page.next_cursor, Some("2".to_string()));
+    }
+
+    #[test]
+    fn test_paginated_follow_cursor_to_last_page() {
+        let params = PageParams {
+            page: Some(1),
+            page_size: Some(2),
+            cursor: Some("3".to_string()),
+            ..Default::default()
+        };
+        let page = Paginated::from_vec(vec![1, 2, 3, 4, 5], &params, 50);
+
+        assert_eq!(page.items, vec![5]);
+        assert_eq!(page.page, 3);
+        assert!(page.next_cursor.is_none());
+
+        let value = serde_json::to_value(&page).unwrap();
+        assert_eq!(value["total"], json!(5));
+        assert!(value["next_cursor"].is_null());
+    }
+
+    #[test]
+    fn test_paginated_keyed() {
+        let ids = || vec!["e", "d", "c", "b", "a"];
+        let params = PageParams {
+            limit: Some(2),
+            page_size: Some(50),
+            ..Default::default()
+        };
+        let first = Paginated::from_keyed(ids(), &params, 20, |id| id);
+        assert_eq!(first.items, vec!["e", "d"]);
+        assert_eq!(first.page_size, 2);
+        assert_eq!(first.next_cursor.as_deref(), Some("d"));
+
+        // A newer entry does not shift the next page
+        let mut grown = ids();
+        grown.insert(0, "f");
+        let params = PageParams {
+            after: first.next_cursor.clone(),
+            ..params
+        };
+        let second = Paginated::from_keyed(grown, &params, 20, |id| id);
+        assert_eq!(second.items, vec!["c", "b"]);
+        assert_eq!(second.page, 2);
+        assert_eq!(second.next_cursor.as_deref(), Some("b"));
+
+        let params = PageParams {
+            after: None,
+            cursor: Some("b".to_string()),
+            ..params
+        };
+        let last = Paginated::from_keyed(ids(), &params, 20, |id| id);
+        assert_eq!(last.items, vec!["a"]);
+        assert!(last.next_cursor.is_none());
+
+        let params = PageParams {
+            cursor: Some("pruned".to_string()),
+            ..params
+        };
+        assert!(Paginated::from_keyed(ids(), &params, 20, |id| id)
+            .items
+            .is_empty());
+
+        let params = PageParams {
+            page: Some(3),
+            page_size: Some(2),
+            ..Default::default()
+        };
+        let paged = Paginated::from_keyed(ids(), &params, 20, |id| id);
+        assert_eq!((paged.items, paged.page), (vec!["a"], 3));
+    }
+
+    #[test]
+    fn test_paginated_from_fetched_page() {
+        let page = Paginated::from_page(vec!["c", "d"], 2, 2, 5);
+        assert_eq!(page.next_cursor, Some("3".to_string()));
+
+        let last = Paginated::from_page(vec!["e"], 3, 2, 5);
+        assert!(last.next_cursor.is_none());
+    }
+
+    #[test]
+    fn test_api_response_envelope() {
+        let single = serde_json::to_value(ApiResponse::new("public")).unwrap();
+        assert_eq!(single, json!({ "data": "public" }));
+
+        let params = PageParams {
+            page: None,
+            page_size: Some(2),
+            cursor: None,
+            ..Default::default()
+        };
+        let list: ApiResponse<Vec<i32>> = Paginated::from_vec(vec![1, 2, 3], &params, 50).into();
+        let value = serde_json::to_value(&list).unwrap();
+        assert_eq!(value["data"], json!([1, 2]));
+        assert_eq!(
+            value["pagination"],
+            json!({ "page": 1, "page_size": 2, "total": 3, "next_cursor": "2" })
+        );
+    }
+
+    #[test]
+    fn test_page_params_clamped() {
+        let params = PageParams {
+            page: Some(0),
+            page_size: Some(10_000),
+            cursor: Some("not-a-number".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(params.page(), 1);
+        assert_eq!(params.page_size(50), PageParams::MAX_PAGE_SIZE);
+        assert_eq!(PageParams::default().page_size(20), 20);
+        let params = PageParams {
+            page_size: Some(10),
+            limit: Some(5),
+            ..Default::default()
+        };
+        assert_eq!(params.page_size(50), 5);
+    }
+
+    #[test]
+    fn test_schema_tree_node_serialization() {
+        let node = SchemaTreeNode {
+            name: "public".to_string(),
+            tables: vec!["orders".to_string(), "users".to_string()],
+            views: vec!["active_users".to_string()],
+            functions: vec![],
+        };
+
+        let value = serde_json::to_value(&node).unwrap();
+        assert_eq!(value["name"], json!("public"));
+        assert_eq!(value["tables"], json!(["orders", "users"]));
+        assert_eq!(value["functions"], json!([]));
+    }
+
+    #[test]
+    fn test_autocomplete_columns_serialize_as_pairs() {
+        let relation = AutocompleteRelation {
+            schema: "public".to_string(),
+            name: "users".to_string(),
+            kind: "table".to_string(),
+            columns: vec![
+                ("id".to_string(), "integer".to_string()),
+                ("email".to_string(), "text".to_string()),
+            ],
+        };
+
+        let value = serde_json::to_value(&relation).unwrap();
+        assert_eq!(value["kind"], json!("table"));
+        assert_eq!(
+            value["columns"],
+            json!([["id", "integer"], ["email", "text"]])
+        );
+    }
 }
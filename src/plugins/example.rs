@@ -0,0 +1,123 @@
+// Example plugin (feature `plugin-example`)
+// Shows each extension point: a route group, a tab-separated export format
+// and an audit sink that writes events to the server log when
+// EXAMPLE_AUDIT_LOG=true
+
+use super::Plugin;
+use crate::config::Config;
+use crate::models::QueryResult;
+use crate::services::audit_service::AuditEvent;
+use crate::services::audit_sink::CustomSink;
+use crate::services::export_service::Exporter;
+use crate::AppState;
+use axum::{extract::State, routing::get, Json, Router};
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+pub struct ExamplePlugin;
+
+impl Plugin for ExamplePlugin {
+    fn name(&self) -> &'static str {
+        "example"
+    }
+
+    fn description(&self) -> &'static str {
+        "Route, export format and audit sink showing the plugin hooks"
+    }
+
+    fn routes(&self) -> Router<AppState> {
+        Router::new().route("/api/example/hello", get(hello))
+    }
+
+    fn export_formats(&self) -> Vec<&'static dyn Exporter> {
+        vec![&Tsv]
+    }
+
+    fn audit_sinks(&self, _config: &Config) -> Result<Vec<Arc<dyn CustomSink>>, String> {
+        let enabled = std::env::var("EXAMPLE_AUDIT_LOG")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .map_err(|_| "EXAMPLE_AUDIT_LOG must be true or false".to_string())?;
+        Ok(match enabled {
+            true => vec![Arc::new(LogSink)],
+            false => Vec::new(),
+        })
+    }
+}
+
+/// Greets with the server's name, to show handlers get the app state
+async fn hello(State(state): State<AppState>) -> Json<Value> {
+    Json(json!({ "hello": state.branding.name }))
+}
+
+/// Tab-separated values, with tabs and newlines in values replaced by spaces
+struct Tsv;
+
+impl Exporter for Tsv {
+    fn name(&self) -> &'static str {
+        "tsv"
+    }
+
+    fn content_type(&self) -> &'static str {
+        "text/tab-separated-values; charset=utf-8"
+    }
+
+    fn export(&self, result: &QueryResult) -> Result<String, String> {
+        let clean = |s: &str| s.replace(['\t', '\n', '\r'], " ");
+        let mut out = result
+            .columns
+            .iter()
+            .map(|c| clean(c))
+            .collect::<Vec<_>>()
+            .join("\t");
+        out.push('\n');
+        for row in &result.rows {
+            let values: Vec<String> = row
+                .iter()
+                .map(|value| match value {
+                    Value::Null => String::new(),
+                    Value::String(s) => clean(s),
+                    other => clean(&other.to_string()),
+                })
+                .collect();
+            out.push_str(&values.join("\t"));
+            out.push('\n');
+        }
+        Ok(out)
+    }
+}
+
+/// Writes each audit event to the server log
+struct LogSink;
+
+impl CustomSink for LogSink {
+    fn describe(&self) -> String {
+        "server log (example plugin)".to_string()
+    }
+
+    fn send<'a>(&'a self, event: &'a AuditEvent) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            let payload = serde_json::to_string(event).map_err(|e| e.to_string())?;
+            tracing::info!(target: "audit", "{}", payload);
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tsv() {
+        let result = QueryResult {
+            columns: vec!["id".to_string(), "note".to_string()],
+            rows: vec![vec![json!(1), json!("a\tb")], vec![json!(2), Value::Null]],
+            row_count: 2,
+            affected_rows: None,
+            execution_time_ms: None,
+        };
+        assert_eq!(Tsv.export(&result).unwrap(), "id\tnote\n1\ta b\n2\t\n");
+    }
+}
@@ -0,0 +1,221 @@
+use crate::config::Config;
+use crate::services::audit_sink::CustomSink;
+use crate::services::export_service::Exporter;
+use crate::AppState;
+/// Plugins
+///
+/// Additions that live outside the core (route groups, export formats,
+/// audit sinks) implement `Plugin` in a module of their own under
+/// `src/plugins/` and are compiled in with a Cargo feature:
+///
+/// ```toml
+/// [features]
+/// plugin-example = []
+/// ```
+///
+/// Listing the plugin in `compiled_in` is the only change needed outside
+/// its module: its routes are merged into the router with every built-in
+/// middleware applied, its export formats are accepted wherever `?format=`
+/// is, and its audit sinks receive events alongside the configured ones.
+/// `GET /api/admin/plugins` shows what is loaded.
+///
+/// Plugins are registered once, on first use, and live for the whole
+/// process.
+use axum::Router;
+use serde::Serialize;
+use std::sync::{Arc, LazyLock};
+
+#[cfg(feature = "plugin-example")]
+mod example;
+
+/// An extension compiled into the server
+pub trait Plugin: Send + Sync {
+    /// Unique name, shown in logs and the plugin listing
+    fn name(&self) -> &'static str;
+
+    fn description(&self) -> &'static str {
+        ""
+    }
+
+    /// Routes merged into the app; paths must not clash with built-in ones
+    fn routes(&self) -> Router<AppState> {
+        Router::new()
+    }
+
+    /// Export formats, looked up by name after the built-in ones
+    fn export_formats(&self) -> Vec<&'static dyn Exporter> {
+        Vec::new()
+    }
+
+    /// Audit sinks to forward events to, built from the configuration (none
+    /// when the plugin's settings are absent)
+    fn audit_sinks(&self, _config: &Config) -> Result<Vec<Arc<dyn CustomSink>>, String> {
+        Ok(Vec::new())
+    }
+}
+
+/// Plugins enabled by Cargo features
+fn compiled_in() -> Vec<Box<dyn Plugin>> {
+    vec![
+        #[cfg(feature = "plugin-example")]
+        Box::new(example::ExamplePlugin),
+    ]
+}
+
+static PLUGINS: LazyLock<Vec<Box<dyn Plugin>>> = LazyLock::new(|| {
+    let plugins = compiled_in();
+    if let Err(e) = validate(&plugins) {
+        panic!("{}", e);
+    }
+    plugins
+});
+
+/// Plugin names and export formats must be unique, and formats must not
+/// shadow the built-in ones
+fn validate(plugins: &[Box<dyn Plugin>]) -> Result<(), String> {
+    let mut names = std::collections::HashSet::new();
+    let mut formats: std::collections::HashSet<&str> = ["csv", "json", "sql"].into();
+    for plugin in plugins {
+        if !names.insert(plugin.name()) {
+            return Err(format!("Plugin '{}' is registered twice", plugin.name()));
+        }
+        for exporter in plugin.export_formats() {
+            if !formats.insert(exporter.name()) {
+                return Err(format!(
+                    "Plugin '{}': export format '{}' is already defined",
+                    plugin.name(),
+                    exporter.name()
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Every compiled-in plugin
+pub fn all() -> &'static [Box<dyn Plugin>] {
+    &PLUGINS
+}
+
+/// Routes of every plugin, to merge into the app
+pub fn routes() -> Router<AppState> {
+    all().iter().fold(Router::new(), |router, plugin| {
+        router.merge(plugin.routes())
+    })
+}
+
+/// A plugin export format by (lowercase) name
+pub fn export_format(name: &str) -> Option<&'static dyn Exporter> {
+    all()
+        .iter()
+        .flat_map(|plugin| plugin.export_formats())
+        .find(|exporter| exporter.name() == name)
+}
+
+/// Audit sinks of every plugin
+pub fn audit_sinks(config: &Config) -> Result<Vec<Arc<dyn CustomSink>>, String> {
+    let mut sinks = Vec::new();
+    for plugin in all() {
+        sinks.extend(
+            plugin
+                .audit_sinks(config)
+                .map_err(|e| format!("Plugin '{}': {}", plugin.name(), e))?,
+        );
+    }
+    Ok(sinks)
+}
+
+/// What a plugin contributes, for the admin listing
+#[derive(Debug, Serialize)]
+pub struct PluginInfo {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub export_formats: Vec<&'static str>,
+}
+
+pub fn describe() -> Vec<PluginInfo> {
+    all()
+        .iter()
+        .map(|plugin| PluginInfo {
+            name: plugin.name(),
+            description: plugin.description(),
+            export_formats: plugin
+                .export_formats()
+                .iter()
+                .map(|exporter| exporter.name())
+                .collect(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::QueryResult;
+
+    struct Tsv;
+
+    impl Exporter for Tsv {
+        fn name(&self) -> &'static str {
+            "tsv"
+        }
+
+        fn content_type(&self) -> &'static str {
+            "text/tab-separated-values"
+        }
+
+        fn export(&self, result: &QueryResult) -> Result<String, String> {
+            Ok(result.columns.join("\t"))
+        }
+    }
+
+    struct Named(&'static str, Vec<&'static dyn Exporter>);
+
+    impl Plugin for Named {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn export_formats(&self) -> Vec<&'static dyn Exporter> {
+            self.1.clone()
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let plugins: Vec<Box<dyn Plugin>> = vec![
+            Box::new(Named("a", vec![&Tsv])),
+            Box::new(Named("b", Vec::new())),
+        ];
+        assert!(validate(&plugins).is_ok());
+
+        let twice: Vec<Box<dyn Plugin>> = vec![
+            Box::new(Named("a", Vec::new())),
+            Box::new(Named("a", Vec::new())),
+        ];
+        assert!(validate(&twice).unwrap_err().contains("twice"));
+
+        let clash: Vec<Box<dyn Plugin>> = vec![
+            Box::new(Named("a", vec![&Tsv])),
+            Box::new(Named("b", vec![&Tsv])),
+        ];
+        assert!(validate(&clash).unwrap_err().contains("'tsv'"));
+    }
+
+    #[test]
+    fn test_exporter_dispatch() {
+        use crate::services::export_service::{ExportFormat, ExportService};
+
+        let format = ExportFormat::Plugin(&Tsv);
+        assert_eq!(format.extension(), "tsv");
+        assert_eq!(format, ExportFormat::Plugin(&Tsv));
+        let result = QueryResult {
+            columns: vec!["a".to_string(), "b".to_string()],
+            rows: Vec::new(),
+            row_count: 0,
+            affected_rows: None,
+            execution_time_ms: None,
+        };
+        assert_eq!(ExportService::export(&result, format).unwrap(), "a\tb");
+    }
+}
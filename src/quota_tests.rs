@@ -0,0 +1,455 @@
+/// Quota Enforcement Tests
+/// Sends requests through every route that runs user SQL, pages through table
+/// rows or produces an export, with a role whose quotas are tiny, and checks
+/// each one refuses
+///
+/// Export allowance tests need no database. The row limit tests run queries
+/// against `TEST_DATABASE_URL` and are skipped when it is not set.
+#[cfg(test)]
+mod tests {
+    use crate::config::reload::{ConfigReloader, ReadOnlyMode, ReloadableSettings};
+    use crate::middleware::query_limit::{QueryLimitConfig, QueryLimiter};
+    use crate::middleware::rate_limit::{RateLimitConfig, RateLimitState};
+    use crate::middleware::session::{SameSite, SessionConfig};
+    use crate::services::artifact_service::{ArtifactStore, RetentionPolicy};
+    use crate::services::audit_service::{AuditEventType, AuditLogger};
+    use crate::services::backup_service::{BackupManager, BackupScheduleStore, DumpConnection};
+    use crate::services::connection_service::{
+        ConnectionHandle, ConnectionProfile, ConnectionRegistry, DEFAULT_CONNECTION_ID,
+    };
+    use crate::services::db_service::{self, DatabaseMonitor};
+    use crate::services::definitions_service::Definitions;
+    use crate::services::metadata_cache::MetadataCache;
+    use crate::services::metrics_history::MetricsHistory;
+    use crate::services::plan_history::PlanHistory;
+    use crate::services::query_history::{HistoryEntry, QueryHistory};
+    use crate::services::query_service::CostThresholds;
+    use crate::services::quota_service::{QuotaLimits, Quotas, QuotasFile};
+    use crate::services::result_store::ResultStore;
+    use crate::services::saved_query_service::{SavedQueryInput, SavedQueryStore};
+    use crate::services::scheduler_service::{ScheduleStore, Scheduler};
+    use crate::services::session_service::Store;
+    use crate::services::stats_service::CounterHistory;
+    use crate::services::transaction_service::TransactionManager;
+    use crate::services::{maintenance_service, restore_service, webhook_service};
+    use crate::{config, routes, AppState};
+    use axum::{
+        body::Body,
+        http::{header, Request, StatusCode},
+        routing::{get, post},
+        Router,
+    };
+    use http_body_util::BodyExt;
+    use sqlx::postgres::PgConnectOptions;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    // ============================================================================
+    // Helper Functions
+    // ============================================================================
+
+    /// Rows any query may return in these tests
+    const MAX_ROWS: usize = 10;
+
+    /// A query returning far more rows than `MAX_ROWS`
+    const BIG_QUERY: &str =
+        "SELECT g AS id, g % 3 AS label, g AS value FROM generate_series(1, 100000) g";
+
+    /// The database for the row limit tests, if one was given
+    fn database_url() -> Option<String> {
+        let url = std::env::var("TEST_DATABASE_URL").ok();
+        if url.is_none() {
+            eprintln!("TEST_DATABASE_URL is not set, skipping");
+        }
+        url
+    }
+
+    /// State with every store in a fresh temporary directory and the default
+    /// connection's pool connecting lazily to `database_url`
+    async fn app_state(database_url: &str, limits: QuotaLimits) -> AppState {
+        let dir = std::env::temp_dir().join(format!("pgadmin-rs-quota-{}", uuid::Uuid::new_v4()));
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+
+        let options = PgConnectOptions::from_str(database_url).unwrap();
+        let read_only_mode = ReadOnlyMode::new(false);
+        let db_pool = Arc::new(db_service::create_pool(
+            options.clone(),
+            read_only_mode.clone(),
+        ));
+        let metadata_cache = Arc::new(MetadataCache::new(Duration::from_secs(60)));
+        let counter_history = Arc::new(CounterHistory::new());
+        let profile = ConnectionProfile {
+            id: DEFAULT_CONNECTION_ID.to_string(),
+            name: "test".to_string(),
+            host: options.get_host().to_string(),
+            port: options.get_port(),
+            database: options.get_database().unwrap_or_default().to_string(),
+            user: options.get_username().to_string(),
+            capabilities: None,
+        };
+        let connections = Arc::new(ConnectionRegistry::new(vec![profile]).with_handle(
+            DEFAULT_CONNECTION_ID,
+            ConnectionHandle {
+                pool: db_pool.clone(),
+                metadata_cache: metadata_cache.clone(),
+                counter_history: counter_history.clone(),
+            },
+        ));
+
+        let audit_logger = Arc::new(AuditLogger::new(100));
+        let saved_queries = Arc::new(SavedQueryStore::load(path("saved.json")).await.unwrap());
+        let artifact_store = Arc::new(ArtifactStore::new(
+            path("artifacts"),
+            RetentionPolicy {
+                max_age: Duration::from_secs(3600),
+                max_total_bytes: 1024 * 1024,
+            },
+        ));
+        let rate_limit = Arc::new(RateLimitState::new(RateLimitConfig {
+            requests_per_minute: 10_000,
+        }));
+        let (_layer, log_filter) =
+            tracing_subscriber::reload::Layer::new(tracing_subscriber::EnvFilter::new("info"));
+
+        AppState {
+            db_pool: db_pool.clone(),
+            db_monitor: Arc::new(DatabaseMonitor::new(Duration::from_secs(60))),
+            audit_logger: audit_logger.clone(),
+            query_history: Arc::new(QueryHistory::new(100)),
+            saved_queries: saved_queries.clone(),
+            plan_history: Arc::new(PlanHistory::load(path("plans.json")).await.unwrap()),
+            scheduler: Arc::new(Scheduler::new(
+                ScheduleStore::load(path("schedules.json")).await.unwrap(),
+                saved_queries,
+                db_pool.clone(),
+                audit_logger.clone(),
+                read_only_mode.clone(),
+            )),
+            definitions: Arc::new(Definitions::new(path("definitions.toml"))),
+            transactions: Arc::new(TransactionManager::new(
+                Duration::from_secs(60),
+                read_only_mode.clone(),
+            )),
+            branding: Arc::new(config::Branding::from_config(&config::Config::from_env())),
+            artifact_store: artifact_store.clone(),
+            result_store: Arc::new(ResultStore::new(
+                artifact_store.clone(),
+                1000,
+                Duration::from_secs(60),
+            )),
+            metadata_cache,
+            counter_history,
+            metrics_history: Arc::new(
+                MetricsHistory::load(&path("metrics.json"), Duration::from_secs(3600))
+                    .await
+                    .unwrap(),
+            ),
+            connections,
+            read_only_mode: read_only_mode.clone(),
+            rate_limit: rate_limit.clone(),
+            query_limiter: Arc::new(QueryLimiter::new(QueryLimitConfig {
+                max_concurrent: 0,
+                max_per_client: 0,
+                queue_timeout: Duration::from_secs(1),
+            })),
+            config_reloader: Arc::new(ConfigReloader::new(
+                ReloadableSettings {
+                    rate_limit_requests_per_minute: 10_000,
+                    read_only_mode: false,
+                    log_filter: "info".to_string(),
+                },
+                rate_limit,
+                read_only_mode,
+                log_filter,
+            )),
+            cost_thresholds: CostThresholds {
+                max_cost: None,
+                max_rows: None,
+            },
+            slow_query_threshold_ms: None,
+            restore_jobs: Arc::new(restore_service::RestoreJobs::new("pg_restore", 1024)),
+            maintenance: Arc::new(maintenance_service::MaintenanceQueue::new()),
+            backups: Arc::new(BackupManager::new(
+                BackupScheduleStore::load(path("backups.json"))
+                    .await
+                    .unwrap(),
+                artifact_store,
+                audit_logger,
+                DumpConnection::Url {
+                    url: database_url.to_string(),
+                    database: String::new(),
+                },
+                "pg_dump",
+            )),
+            webhooks: Arc::new(webhook_service::Webhooks::new(&[], &[]).unwrap()),
+            quotas: Arc::new(Quotas::new(QuotasFile {
+                default: limits,
+                ..QuotasFile::default()
+            })),
+            demo: None,
+        }
+    }
+
+    /// Limits small enough for every test query to exceed
+    fn tiny_limits() -> QuotaLimits {
+        QuotaLimits {
+            max_rows: Some(MAX_ROWS),
+            max_export_bytes: None,
+            max_exports_per_day: Some(1),
+        }
+    }
+
+    fn router(state: AppState) -> Router {
+        Router::new()
+            .route("/api/v1/query", post(routes::api_v1::execute_query))
+            .route("/api/query/execute", post(routes::query::execute))
+            .route("/api/query/chart", post(routes::query::chart))
+            .route("/api/query/pivot", post(routes::query::pivot))
+            .route("/api/query/compare", post(routes::query::compare))
+            .route(
+                "/api/query/history/export",
+                get(routes::query::export_history),
+            )
+            .route(
+                "/api/query/history/{id}/rerun",
+                post(routes::query::rerun_history),
+            )
+            .route(
+                "/api/queries/saved/{id}/execute",
+                post(routes::saved_queries::execute_saved),
+            )
+            .route("/api/query/export", post(routes::export::export_query))
+            .route(
+                "/api/schemas/{schema}/tables/{table}/data",
+                get(routes::tables::browse_data),
+            )
+            .route(
+                "/api/studio/table/{schema}/{table}",
+                get(routes::studio::studio_table_data),
+            )
+            .route(
+                "/api/v1/schemas/{schema}/tables/{table}/rows",
+                get(routes::api_v1::list_rows),
+            )
+            .layer(
+                SessionConfig {
+                    cookie_path: "/".to_string(),
+                    secure: false,
+                    same_site: SameSite::Lax,
+                    ttl: Duration::from_secs(60),
+                }
+                .layer(Store::memory()),
+            )
+            .with_state(state)
+    }
+
+    async fn send(state: &AppState, request: Request<Body>) -> (StatusCode, String) {
+        let response = router(state.clone()).oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, String::from_utf8_lossy(&body).into_owned())
+    }
+
+    fn post_json(uri: &str, body: serde_json::Value) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::ACCEPT, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    /// Expect the request to be refused for fetching too many rows, and
+    /// audited as such
+    async fn assert_row_limit_refused(state: &AppState, request: Request<Body>) {
+        let (status, body) = send(state, request).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{}", body);
+        assert!(body.contains("more than 10 rows"), "{}", body);
+
+        let denied = state
+            .audit_logger
+            .get_events_by_type(AuditEventType::AccessDenied)
+            .await;
+        assert!(denied
+            .iter()
+            .any(|event| event.resource == "quota:max_rows"));
+    }
+
+    // ============================================================================
+    // Row Limit Tests
+    // ============================================================================
+
+    #[tokio::test]
+    async fn test_row_limit_on_console_and_api_queries() {
+        let Some(url) = database_url() else { return };
+        let state = app_state(&url, tiny_limits()).await;
+
+        let query = serde_json::json!({ "query": BIG_QUERY });
+        assert_row_limit_refused(&state, post_json("/api/query/execute", query.clone())).await;
+        assert_row_limit_refused(&state, post_json("/api/v1/query", query)).await;
+
+        // Results within the limit still come back
+        let small = serde_json::json!({ "query": "SELECT g FROM generate_series(1, 10) g" });
+        let (status, body) = send(&state, post_json("/api/v1/query", small)).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+
+    #[tokio::test]
+    async fn test_row_limit_on_history_rerun() {
+        let Some(url) = database_url() else { return };
+        let state = app_state(&url, tiny_limits()).await;
+        let entry = HistoryEntry::new(BIG_QUERY.to_string(), 1, Some(100_000));
+        let id = entry.id.clone();
+        state.query_history.add(entry).await;
+
+        let rerun = post_json(
+            &format!("/api/query/history/{}/rerun", id),
+            serde_json::json!({}),
+        );
+        assert_row_limit_refused(&state, rerun).await;
+    }
+
+    #[tokio::test]
+    async fn test_row_limit_on_saved_queries() {
+        let Some(url) = database_url() else { return };
+        let state = app_state(&url, tiny_limits()).await;
+        let saved = state
+            .saved_queries
+            .create(SavedQueryInput {
+                name: "Everything".to_string(),
+                description: None,
+                sql: "SELECT g FROM generate_series(1, :n) g".to_string(),
+                folder: None,
+                tags: Vec::new(),
+            })
+            .await
+            .unwrap();
+
+        let execute = post_json(
+            &format!("/api/queries/saved/{}/execute", saved.id),
+            serde_json::json!({ "params": { "n": 100000 } }),
+        );
+        assert_row_limit_refused(&state, execute).await;
+    }
+
+    #[tokio::test]
+    async fn test_row_limit_on_charts_pivots_and_comparisons() {
+        let Some(url) = database_url() else { return };
+        let state = app_state(&url, tiny_limits()).await;
+
+        let chart = serde_json::json!({ "query": BIG_QUERY });
+        assert_row_limit_refused(&state, post_json("/api/query/chart", chart)).await;
+
+        let pivot = serde_json::json!({
+            "query": BIG_QUERY,
+            "rows": "label",
+            "columns": "label",
+            "values": "value",
+            "aggregate": "sum"
+        });
+        assert_row_limit_refused(&state, post_json("/api/query/pivot", pivot)).await;
+
+        let compare = serde_json::json!({ "left": BIG_QUERY, "keys": "id" });
+        assert_row_limit_refused(&state, post_json("/api/query/compare", compare)).await;
+    }
+
+    #[tokio::test]
+    async fn test_row_limit_on_query_exports() {
+        let Some(url) = database_url() else { return };
+        let state = app_state(&url, tiny_limits()).await;
+
+        let export = |query: &str| {
+            Request::builder()
+                .method("POST")
+                .uri("/api/query/export")
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from(format!(
+                    "format=csv&query={}",
+                    query.replace(' ', "+").replace('%', "%25")
+                )))
+                .unwrap()
+        };
+        assert_row_limit_refused(&state, export(BIG_QUERY)).await;
+
+        // A refused export does not use up the daily allowance
+        let (status, body) = send(&state, export("SELECT 1 AS one")).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+
+    #[tokio::test]
+    async fn test_row_limit_on_table_browsing() {
+        let Some(url) = database_url() else { return };
+        let state = app_state(&url, tiny_limits()).await;
+        let get = |uri: &str| {
+            Request::builder()
+                .uri(uri)
+                .header(header::ACCEPT, "application/json")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        // pg_type has hundreds of rows in every database
+        for uri in [
+            "/api/schemas/pg_catalog/tables/pg_type/data?page_size=100000000",
+            "/api/schemas/pg_catalog/tables/pg_type/data?sample=100&page_size=50",
+            "/api/studio/table/pg_catalog/pg_type?page_size=50",
+            "/api/v1/schemas/pg_catalog/tables/pg_type/rows?limit=50",
+        ] {
+            assert_row_limit_refused(&state, get(uri)).await;
+        }
+
+        // A page within the limit still comes back
+        let (status, body) = send(
+            &state,
+            get("/api/schemas/pg_catalog/tables/pg_type/data?page_size=10"),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+
+    // ============================================================================
+    // Export Allowance Tests
+    // ============================================================================
+
+    #[tokio::test]
+    async fn test_history_export_counts_against_the_allowance() {
+        // Nothing here connects to the database
+        let state = app_state("postgres://nobody@127.0.0.1:1/none", tiny_limits()).await;
+        let export = || {
+            Request::builder()
+                .uri("/api/query/history/export?format=csv")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let (status, body) = send(&state, export()).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let (status, body) = send(&state, export()).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS, "{}", body);
+        assert!(body.contains("Daily export limit of 1"), "{}", body);
+    }
+
+    #[tokio::test]
+    async fn test_history_export_size_limit() {
+        let limits = QuotaLimits {
+            max_export_bytes: Some(8),
+            ..QuotaLimits::default()
+        };
+        let state = app_state("postgres://nobody@127.0.0.1:1/none", limits).await;
+        state
+            .query_history
+            .add(HistoryEntry::new("SELECT 1".to_string(), 1, Some(1)))
+            .await;
+
+        let export = Request::builder()
+            .uri("/api/query/history/export?format=json")
+            .body(Body::empty())
+            .unwrap();
+        let (status, body) = send(&state, export).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{}", body);
+        assert!(body.contains("limit of 8 bytes"), "{}", body);
+    }
+}
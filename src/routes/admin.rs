@@ -0,0 +1,310 @@
+// Admin routes
+// Operational endpoints for managing server-side state
+
+use crate::config::reload;
+use crate::error::AppError;
+use crate::middleware::client_ip::ClientIp;
+use crate::middleware::query_limit::QueryLimitUsage;
+use crate::middleware::rate_limit::{Exemption, RateLimitUsage};
+use crate::plugins::{self, PluginInfo};
+use crate::services::audit_service::{AuditEvent, AuditEventType};
+use crate::services::quota_service::QuotaSummary;
+use crate::services::webhook_service::{Delivery, WebhookInfo};
+use crate::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+/// Longest exemption from the rate limit (a week)
+const MAX_EXEMPTION_MINUTES: u32 = 7 * 24 * 60;
+
+#[derive(Deserialize)]
+pub struct PruneAuditQuery {
+    /// Override the configured retention for this run
+    pub older_than_days: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct RateLimitUpdate {
+    pub requests_per_minute: u32,
+}
+
+#[derive(Deserialize)]
+pub struct ExemptQuery {
+    /// How long the exemption lasts (60 minutes by default)
+    pub minutes: Option<u32>,
+}
+
+/// List stored export/backup artifacts with sizes and the retention policy
+pub async fn list_artifacts(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let artifacts = state.artifact_store.list().await.map_err(|e| {
+        tracing::error!("Failed to list artifacts: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let policy = state.artifact_store.policy();
+    let total_bytes: u64 = artifacts.iter().map(|a| a.size_bytes).sum();
+
+    Ok(Json(json!({
+        "artifacts": artifacts,
+        "total_bytes": total_bytes,
+        "retention": {
+            "max_age_secs": policy.max_age.as_secs(),
+            "max_total_bytes": policy.max_total_bytes,
+        },
+    })))
+}
+
+/// Apply the retention policy immediately
+pub async fn prune_artifacts(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    state
+        .artifact_store
+        .prune()
+        .await
+        .map(|report| Json(json!(report)))
+        .map_err(|e| {
+            tracing::error!("Failed to prune artifacts: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Delete a single stored artifact
+pub async fn delete_artifact(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    state
+        .artifact_store
+        .delete(&name)
+        .await
+        .map(|_| Json(json!({ "message": format!("Artifact {} deleted", name) })))
+        .map_err(|_| StatusCode::NOT_FOUND)
+}
+
+/// Prune audit events by the retention policy, or older than `?older_than_days=`
+pub async fn prune_audit(
+    State(state): State<AppState>,
+    Query(query): Query<PruneAuditQuery>,
+) -> Json<serde_json::Value> {
+    let report = match query.older_than_days {
+        Some(days) => {
+            let cutoff = chrono::Utc::now() - chrono::Duration::days(days as i64);
+            state.audit_logger.prune_before(cutoff).await
+        }
+        None => state.audit_logger.prune().await,
+    };
+    let (max_age, max_events) = state.audit_logger.retention();
+
+    Json(json!({
+        "deleted": report.deleted,
+        "remaining": report.remaining,
+        "retention": {
+            "max_age_days": max_age.map(|d| d.num_days()),
+            "max_events": max_events,
+        },
+    }))
+}
+
+/// Re-read the reloadable settings (rate limit, read-only mode, log filter)
+/// and apply them without a restart
+pub async fn reload_config(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    let outcome = state.config_reloader.reload();
+    state
+        .audit_logger
+        .log(reload::audit_event(client_ip, &outcome))
+        .await;
+
+    match outcome {
+        Ok(report) => {
+            if !report.changed.is_empty() {
+                tracing::info!(
+                    "Configuration reloaded, changed {}",
+                    report.changed.join(", ")
+                );
+            }
+            Ok(Json(json!(report)))
+        }
+        Err(e) => {
+            tracing::warn!("Configuration not reloaded: {}", e);
+            Err((StatusCode::BAD_REQUEST, Json(json!({ "error": e }))))
+        }
+    }
+}
+
+/// Compiled-in plugins and the export formats they add
+pub async fn list_plugins() -> Json<Vec<PluginInfo>> {
+    Json(plugins::describe())
+}
+
+/// Concurrent query limits, with the queries running and waiting now
+pub async fn query_limits(State(state): State<AppState>) -> Json<QueryLimitUsage> {
+    Json(state.query_limiter.usage())
+}
+
+/// Quota limits by role, with each client's exports so far today
+pub async fn quotas(State(state): State<AppState>) -> Json<QuotaSummary> {
+    Json(state.quotas.describe())
+}
+
+/// Configured webhook endpoints with their recent deliveries
+pub async fn list_webhooks(State(state): State<AppState>) -> Json<Vec<WebhookInfo>> {
+    Json(state.webhooks.list())
+}
+
+/// Send a test notification to one endpoint and report how it went
+pub async fn test_webhook(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Delivery>, AppError> {
+    state
+        .webhooks
+        .test(&id)
+        .await
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound(format!("Webhook '{}' not found", id)))
+}
+
+fn rate_limit_event(client_ip: String, action: &str, resource: String) -> AuditEvent {
+    AuditEvent::new(
+        AuditEventType::ConfigurationChange,
+        client_ip,
+        action.to_string(),
+        resource,
+    )
+}
+
+/// Normalize an IP address so it matches the form the limiter tracks
+fn parse_ip(ip: &str) -> Result<String, AppError> {
+    ip.parse::<std::net::IpAddr>()
+        .map(|ip| ip.to_string())
+        .map_err(|_| AppError::BadRequest(format!("Invalid IP address '{}'", ip)))
+}
+
+/// The rate limit in effect, each tracked IP's usage and the exempt IPs
+pub async fn rate_limits(State(state): State<AppState>) -> Json<RateLimitUsage> {
+    Json(state.rate_limit.usage())
+}
+
+/// Change the per-IP limit until the next configuration reload
+pub async fn set_rate_limit(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    Json(update): Json<RateLimitUpdate>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let requests_per_minute = update.requests_per_minute;
+    if requests_per_minute == 0 {
+        return Err(AppError::BadRequest(
+            "requests_per_minute must be at least 1".to_string(),
+        ));
+    }
+
+    let changed = state.config_reloader.set_rate_limit(requests_per_minute);
+    if changed {
+        tracing::info!(
+            "Rate limit set to {} requests per minute",
+            requests_per_minute
+        );
+        state
+            .audit_logger
+            .log(
+                rate_limit_event(client_ip, "set rate limit", "rate-limit".to_string())
+                    .with_details(format!("{} requests per minute", requests_per_minute)),
+            )
+            .await;
+    }
+    Ok(Json(json!({
+        "requests_per_minute": requests_per_minute,
+        "changed": changed,
+    })))
+}
+
+/// Give an IP a fresh bucket
+pub async fn reset_rate_limit(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    Path(ip): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let ip = parse_ip(&ip)?;
+    if !state.rate_limit.reset(&ip) {
+        return Err(AppError::NotFound(format!(
+            "No rate limit usage for {}",
+            ip
+        )));
+    }
+    state
+        .audit_logger
+        .log(rate_limit_event(
+            client_ip,
+            "reset rate limit",
+            format!("rate-limit:{}", ip),
+        ))
+        .await;
+    Ok(Json(
+        json!({ "message": format!("Rate limit for {} reset", ip) }),
+    ))
+}
+
+/// Let every request from an IP through for `?minutes=` (default 60)
+pub async fn exempt_ip(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    Path(ip): Path<String>,
+    Query(query): Query<ExemptQuery>,
+) -> Result<Json<Exemption>, AppError> {
+    let ip = parse_ip(&ip)?;
+    let minutes = query.minutes.unwrap_or(60);
+    if minutes == 0 || minutes > MAX_EXEMPTION_MINUTES {
+        return Err(AppError::BadRequest(format!(
+            "minutes must be between 1 and {}",
+            MAX_EXEMPTION_MINUTES
+        )));
+    }
+
+    let until = chrono::Utc::now() + chrono::Duration::minutes(minutes as i64);
+    state.rate_limit.exempt(&ip, until);
+    state
+        .audit_logger
+        .log(
+            rate_limit_event(
+                client_ip,
+                "exempt from rate limit",
+                format!("rate-limit:{}", ip),
+            )
+            .with_details(format!("until {}", until.to_rfc3339())),
+        )
+        .await;
+    Ok(Json(Exemption { ip, until }))
+}
+
+/// End an IP's exemption early
+pub async fn remove_exemption(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    Path(ip): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let ip = parse_ip(&ip)?;
+    if !state.rate_limit.remove_exemption(&ip) {
+        return Err(AppError::NotFound(format!("{} is not exempt", ip)));
+    }
+    state
+        .audit_logger
+        .log(rate_limit_event(
+            client_ip,
+            "remove rate limit exemption",
+            format!("rate-limit:{}", ip),
+        ))
+        .await;
+    Ok(Json(
+        json!({ "message": format!("Rate limit exemption for {} removed", ip) }),
+    ))
+}
@@ -0,0 +1,252 @@
+// Versioned JSON API routes
+// Stable JSON-in / JSON-out endpoints under /api/v1 for scripts and CI jobs,
+// separate from the HTMX fragment routes
+
+use crate::error::{AppError, ErrorBody};
+use crate::middleware::client_ip::ClientIp;
+use crate::middleware::connection::Connected;
+use crate::middleware::query_limit::QuerySlot;
+use crate::middleware::quota::Quota;
+use crate::middleware::session::Session;
+use crate::models::{ApiResponse, ColumnInfo, Database, PageParams, Paginated, Schema, TableInfo};
+use crate::routes::query::{
+    confirmation_required, run_request, ExecuteQueryRequest, HistoryQuery, QueryOutcome,
+    QueryResultsTemplate, ScriptResultsTemplate,
+};
+use crate::routes::transactions;
+use crate::services::query_history::HistoryEntry;
+use crate::services::{database_service, schema_service};
+use crate::AppState;
+use axum::{
+    extract::{rejection::JsonRejection, Path, Query, State},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const DEFAULT_ROWS_PAGE_SIZE: u32 = 100;
+
+/// A table with its columns
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TableDetails {
+    pub table: TableInfo,
+    pub columns: Vec<ColumnInfo>,
+}
+
+/// A table row keyed by column name, with values decoded by column type
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(transparent)]
+#[schema(value_type = Object)]
+pub struct Row(serde_json::Map<String, serde_json::Value>);
+
+/// Results of `POST /api/v1/query`: one query's, or each statement's of a script
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum QueryResponse {
+    Results(QueryResultsTemplate),
+    Script(ScriptResultsTemplate),
+}
+
+/// GET /api/v1/databases - List databases
+#[utoipa::path(
+    get,
+    path = "/api/v1/databases",
+    operation_id = "listDatabases",
+    summary = "List databases",
+    params(PageParams),
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Vec<Database>>),
+        (status = 500, description = "Error", body = ErrorBody),
+    )
+)]
+pub async fn list_databases(
+    Connected(state): Connected,
+    Query(params): Query<PageParams>,
+) -> Result<Json<ApiResponse<Vec<Database>>>, AppError> {
+    let databases = database_service::list_databases(&state.db_pool).await?;
+    Ok(Json(
+        Paginated::from_vec(databases, &params, DEFAULT_PAGE_SIZE).into(),
+    ))
+}
+
+/// GET /api/v1/schemas - List schemas in the current database
+#[utoipa::path(
+    get,
+    path = "/api/v1/schemas",
+    operation_id = "listSchemas",
+    summary = "List schemas",
+    params(PageParams),
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Vec<Schema>>),
+        (status = 500, description = "Error", body = ErrorBody),
+    )
+)]
+pub async fn list_schemas(
+    Connected(state): Connected,
+    Query(params): Query<PageParams>,
+) -> Result<Json<ApiResponse<Vec<Schema>>>, AppError> {
+    let schemas = schema_service::list_schemas(&state.db_pool).await?;
+    Ok(Json(
+        Paginated::from_vec(schemas, &params, DEFAULT_PAGE_SIZE).into(),
+    ))
+}
+
+/// GET /api/v1/schemas/:schema/tables - List tables and views in a schema
+#[utoipa::path(
+    get,
+    path = "/api/v1/schemas/{schema}/tables",
+    operation_id = "listTables",
+    summary = "List tables and views in a schema",
+    params(("schema" = String, Path), PageParams),
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Vec<TableInfo>>),
+        (status = 500, description = "Error", body = ErrorBody),
+    )
+)]
+pub async fn list_tables(
+    Connected(state): Connected,
+    Path(schema): Path<String>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<ApiResponse<Vec<TableInfo>>>, AppError> {
+    let tables = schema_service::list_tables(&state.db_pool, &schema).await?;
+    Ok(Json(
+        Paginated::from_vec(tables, &params, DEFAULT_PAGE_SIZE).into(),
+    ))
+}
+
+/// GET /api/v1/schemas/:schema/tables/:table - Get a table and its columns
+#[utoipa::path(
+    get,
+    path = "/api/v1/schemas/{schema}/tables/{table}",
+    operation_id = "getTable",
+    summary = "Get a table and its columns",
+    params(("schema" = String, Path), ("table" = String, Path)),
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<TableDetails>),
+        (status = 404, description = "Error", body = ErrorBody),
+        (status = 500, description = "Error", body = ErrorBody),
+    )
+)]
+pub async fn get_table(
+    Connected(state): Connected,
+    Path((schema, table)): Path<(String, String)>,
+) -> Result<Json<ApiResponse<TableDetails>>, AppError> {
+    let (table, columns) = tokio::try_join!(
+        schema_service::get_table_info(&state.db_pool, &schema, &table),
+        schema_service::get_table_columns(&state.db_pool, &schema, &table),
+    )?;
+    Ok(Json(ApiResponse::new(TableDetails { table, columns })))
+}
+
+/// GET /api/v1/schemas/:schema/tables/:table/rows - Page through table rows
+#[utoipa::path(
+    get,
+    path = "/api/v1/schemas/{schema}/tables/{table}/rows",
+    operation_id = "listRows",
+    summary = "Page through table rows",
+    description = "Rows are objects keyed by column name, with values decoded by column type. \
+        A page with more rows than the role's row quota is refused with 403.",
+    params(("schema" = String, Path), ("table" = String, Path), PageParams),
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Vec<Row>>),
+        (status = 403, description = "Error", body = ErrorBody),
+        (status = 404, description = "Error", body = ErrorBody),
+        (status = 500, description = "Error", body = ErrorBody),
+    )
+)]
+pub async fn list_rows(
+    Connected(state): Connected,
+    _slot: QuerySlot,
+    quota: Quota,
+    Path((schema, table)): Path<(String, String)>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<ApiResponse<Vec<Row>>>, AppError> {
+    let page = params.page();
+    let page_size = params.page_size(DEFAULT_ROWS_PAGE_SIZE);
+    let (rows, total) = quota
+        .limit_rows(
+            &format!("rows {}.{}", schema, table),
+            schema_service::get_table_rows(&state.db_pool, &schema, &table, page, page_size),
+        )
+        .await??;
+    let rows = rows.into_iter().map(Row).collect();
+    Ok(Json(
+        Paginated::from_page(rows, page, page_size, total.max(0) as u64).into(),
+    ))
+}
+
+/// POST /api/v1/query - Execute a query, or a script of several statements
+///
+/// Takes the same body as the console (`query`, `params`, `transaction`,
+/// `confirm`). Queries over the cost thresholds are refused with a 409 until
+/// resent with `confirm: true`. A `transaction` must have been begun with the
+/// same session cookie.
+#[utoipa::path(
+    post,
+    path = "/api/v1/query",
+    operation_id = "executeQuery",
+    summary = "Execute a query, or a script of several statements",
+    description = "Queries whose planner estimate exceeds the configured cost thresholds are \
+        refused with 409 until resent with \"confirm\": true.",
+    request_body = ExecuteQueryRequest,
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<QueryResponse>),
+        (status = 400, description = "Error", body = ErrorBody),
+        (status = 409, description = "Error", body = ErrorBody),
+        (status = 500, description = "Error", body = ErrorBody),
+    )
+)]
+pub async fn execute_query(
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
+    _slot: QuerySlot,
+    session: Session,
+    quota: Quota,
+    payload: Result<Json<ExecuteQueryRequest>, JsonRejection>,
+) -> Result<Response, AppError> {
+    let Json(payload) = payload?;
+    let owner = transactions::owner(&session).await?;
+    let query = payload.query.clone();
+    let outcome = quota
+        .limit_rows(
+            &query,
+            run_request(&state, client_ip, owner.as_deref(), payload),
+        )
+        .await?
+        .map_err(AppError::BadRequest)?;
+    let response = match outcome {
+        QueryOutcome::Results(results) => QueryResponse::Results(results),
+        QueryOutcome::Script(script) => QueryResponse::Script(script),
+        QueryOutcome::NeedsConfirmation(confirmation) => {
+            return Err(confirmation_required(&confirmation.reason()));
+        }
+    };
+    Ok(Json(ApiResponse::new(response)).into_response())
+}
+
+/// GET /api/v1/query/history - Search the query history, newest first
+#[utoipa::path(
+    get,
+    path = "/api/v1/query/history",
+    operation_id = "queryHistory",
+    summary = "Search the query history, newest first",
+    params(HistoryQuery, PageParams),
+    responses(
+        (status = 200, description = "Success", body = ApiResponse<Vec<HistoryEntry>>),
+        (status = 400, description = "Error", body = ErrorBody),
+        (status = 500, description = "Error", body = ErrorBody),
+    )
+)]
+pub async fn query_history(
+    State(state): State<AppState>,
+    Query(query): Query<HistoryQuery>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<ApiResponse<Vec<HistoryEntry>>>, AppError> {
+    let filter = query.to_filter().map_err(AppError::BadRequest)?;
+    let entries = state.query_history.search(&filter).await;
+    Ok(Json(
+        Paginated::from_keyed(entries, &params, DEFAULT_PAGE_SIZE, |e| &e.id).into(),
+    ))
+}
@@ -0,0 +1,205 @@
+// Audit routes
+// Lists recorded audit events with filtering, as JSON and as an HTML page
+
+use crate::config::Branding;
+use crate::error::AppError;
+use crate::i18n::filters;
+use crate::models::{PageParams, Paginated};
+use crate::routes::HtmlTemplate;
+use crate::services::audit_service::{AuditEvent, AuditEventType, AuditFilter};
+use crate::AppState;
+use askama::Template;
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Deserialize;
+use std::sync::Arc;
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
+
+/// Audit filter as received in the query string; blank values are ignored
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    #[serde(rename = "type")]
+    pub event_type: Option<String>,
+    pub ip: Option<String>,
+    pub user: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub success: Option<String>,
+    pub request_id: Option<String>,
+}
+
+impl AuditQuery {
+    /// Parse the query string values into a filter
+    pub fn to_filter(&self) -> Result<AuditFilter, String> {
+        let event_type = match non_empty(&self.event_type) {
+            Some(value) => Some(
+                AuditEventType::parse(value)
+                    .ok_or_else(|| format!("Unknown event type: {}", value))?,
+            ),
+            None => None,
+        };
+
+        let success = match non_empty(&self.success) {
+            Some(value) => Some(
+                value
+                    .parse::<bool>()
+                    .map_err(|_| format!("Invalid success flag: {}", value))?,
+            ),
+            None => None,
+        };
+
+        Ok(AuditFilter {
+            event_type,
+            client_ip: non_empty(&self.ip).map(str::to_string),
+            user_id: non_empty(&self.user).map(str::to_string),
+            from: non_empty(&self.from).map(parse_timestamp).transpose()?,
+            to: non_empty(&self.to).map(parse_timestamp).transpose()?,
+            success,
+            request_id: non_empty(&self.request_id).map(str::to_string),
+        })
+    }
+}
+
+pub(crate) fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
+
+/// Accept RFC 3339 timestamps or the `datetime-local` form value (UTC)
+pub(crate) fn parse_timestamp(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(ts) = DateTime::parse_from_rfc3339(value) {
+        return Ok(ts.with_timezone(&Utc));
+    }
+    ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"]
+        .iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(value, fmt).ok())
+        .map(|naive| naive.and_utc())
+        .ok_or_else(|| format!("Invalid timestamp: {}", value))
+}
+
+#[derive(Template)]
+#[template(path = "audit.html")]
+pub struct AuditTemplate {
+    pub branding: Arc<Branding>,
+    pub event_types: Vec<String>,
+    pub event_type: String,
+    pub ip: String,
+    pub user: String,
+    pub from: String,
+    pub to: String,
+    pub success: String,
+    pub request_id: String,
+    pub events: Paginated<AuditEvent>,
+    pub error: Option<String>,
+}
+
+/// List audit events matching the filters, newest first and paged by id
+/// (returns JSON)
+pub async fn list_events(
+    State(state): State<AppState>,
+    Query(query): Query<AuditQuery>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<Paginated<AuditEvent>>, AppError> {
+    let filter = query.to_filter().map_err(AppError::BadRequest)?;
+    let events = state.audit_logger.query(&filter).await;
+
+    Ok(Json(Paginated::from_keyed(
+        events,
+        &params,
+        DEFAULT_PAGE_SIZE,
+        |e| &e.id,
+    )))
+}
+
+/// Audit log page
+pub async fn audit_page(
+    State(state): State<AppState>,
+    Query(query): Query<AuditQuery>,
+    Query(params): Query<PageParams>,
+) -> impl IntoResponse {
+    let (events, error) = match query.to_filter() {
+        Ok(filter) => (state.audit_logger.query(&filter).await, None),
+        Err(e) => (Vec::new(), Some(e)),
+    };
+
+    HtmlTemplate(AuditTemplate {
+        branding: state.branding.clone(),
+        event_types: AuditEventType::ALL
+            .iter()
+            .map(|t| t.as_str().to_string())
+            .collect(),
+        event_type: query.event_type.unwrap_or_default(),
+        ip: query.ip.unwrap_or_default(),
+        user: query.user.unwrap_or_default(),
+        from: query.from.unwrap_or_default(),
+        to: query.to.unwrap_or_default(),
+        success: query.success.unwrap_or_default(),
+        request_id: query.request_id.unwrap_or_default(),
+        events: Paginated::from_vec(events, &params, DEFAULT_PAGE_SIZE),
+        error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blank_query_matches_everything() {
+        let query = AuditQuery {
+            event_type: Some(String::new()),
+            ip: Some("  ".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(query.to_filter().unwrap(), AuditFilter::default());
+    }
+
+    #[test]
+    fn test_query_to_filter() {
+        let query = AuditQuery {
+            event_type: Some("schema_modification".to_string()),
+            ip: Some("10.0.0.1".to_string()),
+            user: Some("alice".to_string()),
+            from: Some("2024-01-01T00:00".to_string()),
+            to: Some("2024-01-31T23:59:59Z".to_string()),
+            success: Some("false".to_string()),
+            request_id: Some("req-1".to_string()),
+        };
+        let filter = query.to_filter().unwrap();
+        assert_eq!(filter.event_type, Some(AuditEventType::SchemaModification));
+        assert_eq!(filter.client_ip.as_deref(), Some("10.0.0.1"));
+        assert_eq!(filter.user_id.as_deref(), Some("alice"));
+        assert_eq!(
+            filter.from.unwrap().to_rfc3339(),
+            "2024-01-01T00:00:00+00:00"
+        );
+        assert_eq!(filter.to.unwrap().to_rfc3339(), "2024-01-31T23:59:59+00:00");
+        assert_eq!(filter.success, Some(false));
+        assert_eq!(filter.request_id.as_deref(), Some("req-1"));
+    }
+
+    #[test]
+    fn test_invalid_query_values() {
+        let bad_type = AuditQuery {
+            event_type: Some("Nope".to_string()),
+            ..Default::default()
+        };
+        assert!(bad_type.to_filter().is_err());
+
+        let bad_time = AuditQuery {
+            from: Some("yesterday".to_string()),
+            ..Default::default()
+        };
+        assert!(bad_time.to_filter().is_err());
+
+        let bad_flag = AuditQuery {
+            success: Some("maybe".to_string()),
+            ..Default::default()
+        };
+        assert!(bad_flag.to_filter().is_err());
+    }
+}
@@ -0,0 +1,232 @@
+// Backup routes
+// Takes, lists, downloads and deletes pg_dump backups, and manages backup
+// schedules with their run history
+
+use crate::error::AppError;
+use crate::middleware::client_ip::ClientIp;
+use crate::models::{PageParams, Paginated};
+use crate::services::backup_service::{
+    BackupFile, BackupFormat, BackupRun, BackupSchedule, BackupScheduleInput, BackupSource,
+};
+use crate::AppState;
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    Json,
+};
+use futures::stream;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::io::AsyncReadExt;
+
+/// Chunk size when streaming a backup download
+const DOWNLOAD_CHUNK_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Deserialize)]
+pub struct BackupRequest {
+    /// The connection's own database when unset
+    #[serde(default)]
+    pub database: Option<String>,
+    #[serde(default)]
+    pub format: BackupFormat,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RunsQuery {
+    pub schedule_id: Option<String>,
+}
+
+/// Stored backups, newest first
+pub async fn list_backups(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<BackupFile>>, AppError> {
+    state
+        .backups
+        .list_backups()
+        .await
+        .map(Json)
+        .map_err(AppError::Internal)
+}
+
+/// Take a backup now; the run reports whether `pg_dump` succeeded
+pub async fn create_backup(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    Json(request): Json<BackupRequest>,
+) -> Json<BackupRun> {
+    let database = request
+        .database
+        .as_deref()
+        .map(str::trim)
+        .filter(|d| !d.is_empty());
+    Json(
+        state
+            .backups
+            .backup_now(database, request.format, client_ip)
+            .await,
+    )
+}
+
+/// Download a backup file; offloaded backups redirect to a presigned URL
+pub async fn download_backup(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Response, AppError> {
+    let path = match locate(&state, &name).await? {
+        BackupSource::Local(path) => path,
+        BackupSource::Remote(url) => return Ok(Redirect::temporary(&url).into_response()),
+    };
+    let file = tokio::fs::File::open(&path)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to open backup {}: {}", name, e)))?;
+
+    let chunks = stream::unfold(Some(file), |file| async move {
+        let mut file = file?;
+        let mut buffer = Vec::with_capacity(DOWNLOAD_CHUNK_BYTES);
+        match (&mut file)
+            .take(DOWNLOAD_CHUNK_BYTES as u64)
+            .read_to_end(&mut buffer)
+            .await
+        {
+            Ok(0) => None,
+            Ok(_) => Some((Ok(buffer), Some(file))),
+            Err(e) => Some((Err(e), None)),
+        }
+    });
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", name),
+            ),
+        ],
+        Body::from_stream(chunks),
+    )
+        .into_response())
+}
+
+/// Delete a backup file, along with its offloaded copy
+pub async fn delete_backup(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    locate(&state, &name).await?;
+    state
+        .artifact_store
+        .delete(&name)
+        .await
+        .map_err(AppError::Internal)?;
+    Ok(Json(
+        json!({ "message": format!("Backup {} deleted", name) }),
+    ))
+}
+
+/// Recent backup runs, newest first, optionally of one schedule
+pub async fn list_runs(
+    State(state): State<AppState>,
+    Query(query): Query<RunsQuery>,
+    Query(params): Query<PageParams>,
+) -> Json<Paginated<BackupRun>> {
+    let runs = state.backups.store.runs(query.schedule_id.as_deref()).await;
+    Json(Paginated::from_vec(runs, &params, 20))
+}
+
+/// List backup schedules, ordered by name
+pub async fn list_schedules(State(state): State<AppState>) -> Json<Vec<BackupSchedule>> {
+    Json(state.backups.store.list().await)
+}
+
+/// Get a single backup schedule
+pub async fn get_schedule(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<BackupSchedule>, AppError> {
+    state
+        .backups
+        .store
+        .get(&id)
+        .await
+        .map(Json)
+        .ok_or_else(|| schedule_not_found(&id))
+}
+
+/// Create a backup schedule
+pub async fn create_schedule(
+    State(state): State<AppState>,
+    Json(input): Json<BackupScheduleInput>,
+) -> Result<(StatusCode, Json<BackupSchedule>), AppError> {
+    let input = state
+        .backups
+        .validate(input)
+        .map_err(AppError::BadRequest)?;
+    state
+        .backups
+        .store
+        .create(input)
+        .await
+        .map(|schedule| (StatusCode::CREATED, Json(schedule)))
+        .map_err(AppError::Internal)
+}
+
+/// Update a backup schedule
+pub async fn update_schedule(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(input): Json<BackupScheduleInput>,
+) -> Result<Json<BackupSchedule>, AppError> {
+    let input = state
+        .backups
+        .validate(input)
+        .map_err(AppError::BadRequest)?;
+    state
+        .backups
+        .store
+        .update(&id, input)
+        .await
+        .map_err(AppError::Internal)?
+        .map(Json)
+        .ok_or_else(|| schedule_not_found(&id))
+}
+
+/// Delete a backup schedule; the backups it took are kept
+pub async fn delete_schedule(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    match state.backups.store.delete(&id).await {
+        Ok(true) => Ok(Json(json!({ "message": "Backup schedule deleted" }))),
+        Ok(false) => Err(schedule_not_found(&id)),
+        Err(e) => Err(AppError::Internal(e)),
+    }
+}
+
+/// Run a backup schedule immediately, outside its cron expression
+pub async fn run_schedule(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<BackupRun>, AppError> {
+    let schedule = state
+        .backups
+        .store
+        .get(&id)
+        .await
+        .ok_or_else(|| schedule_not_found(&id))?;
+    Ok(Json(state.backups.run_schedule(&schedule).await))
+}
+
+async fn locate(state: &AppState, name: &str) -> Result<BackupSource, AppError> {
+    state
+        .backups
+        .locate(name)
+        .await
+        .map_err(AppError::Internal)?
+        .ok_or_else(|| AppError::NotFound(format!("Backup {} not found", name)))
+}
+
+fn schedule_not_found(id: &str) -> AppError {
+    AppError::NotFound(format!("Backup schedule {} not found", id))
+}
@@ -0,0 +1,85 @@
+// Branding routes
+// Serves the configured logo and favicon without exposing arbitrary files
+
+use crate::AppState;
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+use std::path::Path;
+
+/// Maps a logo file extension to its content type; anything else is refused
+fn image_content_type(path: &str) -> Option<&'static str> {
+    let ext = Path::new(path).extension()?.to_str()?.to_lowercase();
+    match ext.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        "ico" => Some("image/x-icon"),
+        // SVG can carry scripts; the CSP sandbox below neutralizes them
+        "svg" => Some("image/svg+xml"),
+        _ => None,
+    }
+}
+
+/// GET /branding/logo - Serve the configured logo file
+pub async fn logo(State(state): State<AppState>) -> Result<impl IntoResponse, StatusCode> {
+    let path = state
+        .branding
+        .logo_path
+        .as_deref()
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let content_type = image_content_type(path).ok_or_else(|| {
+        tracing::warn!(
+            "Refusing to serve logo with unsupported extension: {}",
+            path
+        );
+        StatusCode::NOT_FOUND
+    })?;
+
+    let bytes = tokio::fs::read(path).await.map_err(|e| {
+        tracing::error!("Failed to read logo file {}: {}", path, e);
+        StatusCode::NOT_FOUND
+    })?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::CACHE_CONTROL, "public, max-age=3600"),
+            (
+                header::CONTENT_SECURITY_POLICY,
+                "default-src 'none'; sandbox",
+            ),
+        ],
+        bytes,
+    ))
+}
+
+/// GET /favicon.ico - Use the logo as favicon when configured
+pub async fn favicon(State(state): State<AppState>) -> impl IntoResponse {
+    if state.branding.logo_path.is_some() {
+        return logo(State(state)).await.into_response();
+    }
+    StatusCode::NO_CONTENT.into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_content_type() {
+        assert_eq!(image_content_type("/etc/brand/logo.PNG"), Some("image/png"));
+        assert_eq!(image_content_type("logo.svg"), Some("image/svg+xml"));
+        assert_eq!(image_content_type("favicon.ico"), Some("image/x-icon"));
+    }
+
+    #[test]
+    fn test_non_image_files_refused() {
+        assert_eq!(image_content_type("/etc/passwd"), None);
+        assert_eq!(image_content_type(".env"), None);
+        assert_eq!(image_content_type("logo.html"), None);
+    }
+}
@@ -1,13 +1,29 @@
 use askama::Template;
 use axum::{
-    extract::{Path, Query, State},
+    extract::{Path, Query},
     http::StatusCode,
     response::{IntoResponse, Response},
     Json,
 };
 use serde::{Deserialize, Serialize};
 
-use crate::{routes::HtmlTemplate, services::cell_service, AppState};
+use crate::middleware::connection::Connected;
+use crate::{
+    i18n::filters,
+    middleware::client_ip::ClientIp,
+    routes::HtmlTemplate,
+    services::audit_service::{AuditEvent, AuditEventType},
+    services::cell_service,
+};
+
+fn data_event(client_ip: String, action: String, schema: &str, table: &str) -> AuditEvent {
+    AuditEvent::new(
+        AuditEventType::DataModification,
+        client_ip,
+        action,
+        format!("{}.{}", schema, table),
+    )
+}
 
 #[derive(Template)]
 #[template(path = "components/cell-edit.html")]
@@ -62,7 +78,7 @@ pub struct CellUpdateResponse {
 
 /// GET /api/cell/edit - Get the edit form for a cell
 pub async fn get_cell_edit(
-    State(state): State<AppState>,
+    Connected(state): Connected,
     Query(params): Query<CellEditQuery>,
 ) -> impl IntoResponse {
     // Get current value
@@ -91,7 +107,8 @@ pub async fn get_cell_edit(
 
 /// POST /api/cell/update - Update a cell value
 pub async fn update_cell(
-    State(state): State<AppState>,
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
     Json(request): Json<CellUpdateRequest>,
 ) -> Response {
     let result = cell_service::update_cell(
@@ -104,6 +121,21 @@ pub async fn update_cell(
         request.value.as_deref(),
     )
     .await;
+    state
+        .audit_logger
+        .log_result(
+            data_event(
+                client_ip,
+                format!(
+                    "UPDATE {} WHERE {} = {}",
+                    request.column, request.pk_column, request.pk_value
+                ),
+                &request.schema,
+                &request.table,
+            ),
+            &result,
+        )
+        .await;
 
     match result {
         Ok(()) => {
@@ -132,10 +164,20 @@ pub async fn update_cell(
 
 /// POST /api/table/:schema/:table/row - Add a new row
 pub async fn add_row(
-    State(state): State<AppState>,
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
     Path((schema, table)): Path<(String, String)>,
 ) -> impl IntoResponse {
-    match cell_service::insert_row(&state.db_pool, &schema, &table).await {
+    let result = cell_service::insert_row(&state.db_pool, &schema, &table).await;
+    state
+        .audit_logger
+        .log_result(
+            data_event(client_ip, "INSERT row".to_string(), &schema, &table),
+            &result,
+        )
+        .await;
+
+    match result {
         Ok(pk_value) => (
             StatusCode::OK,
             Json(serde_json::json!({
@@ -156,7 +198,8 @@ pub async fn add_row(
 
 /// DELETE /api/table/:schema/:table/row/:pk_value - Delete a row
 pub async fn delete_row(
-    State(state): State<AppState>,
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
     Path((schema, table, pk_value)): Path<(String, String, String)>,
     Query(params): Query<std::collections::HashMap<String, String>>,
 ) -> impl IntoResponse {
@@ -177,7 +220,22 @@ pub async fn delete_row(
         },
     };
 
-    match cell_service::delete_row(&state.db_pool, &schema, &table, &pk_column, &pk_value).await {
+    let result =
+        cell_service::delete_row(&state.db_pool, &schema, &table, &pk_column, &pk_value).await;
+    state
+        .audit_logger
+        .log_result(
+            data_event(
+                client_ip,
+                format!("DELETE WHERE {} = {}", pk_column, pk_value),
+                &schema,
+                &table,
+            ),
+            &result,
+        )
+        .await;
+
+    match result {
         Ok(rows) => (
             StatusCode::OK,
             Json(serde_json::json!({
@@ -0,0 +1,187 @@
+// Connection routes
+// Exposes connection profiles and the server picker that selects one for the
+// browser session, their probed server capabilities, and whether the
+// database is currently reachable
+
+use crate::error::AppError;
+use crate::i18n::{self, filters};
+use crate::middleware::connection::{self, selected_connection_id};
+use crate::middleware::response_format::ResponseFormat;
+use crate::middleware::session::Session;
+use crate::services::connection_service::{
+    self, ConnectionConfig, ConnectionProfile, ConnectionTest,
+};
+use crate::services::db_service::DatabaseState;
+use crate::AppState;
+use askama::Template;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    Form, Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+#[derive(Template, Serialize)]
+#[template(path = "components/connection-picker.html")]
+pub struct ConnectionPickerTemplate {
+    pub connections: Vec<ConnectionProfile>,
+    /// ID of the connection this session uses
+    pub selected: String,
+}
+
+/// List connection profiles: the server picker for the navbar (empty when
+/// there is only the default connection), or as JSON the profiles and the
+/// selected ID
+pub async fn list_connections(
+    format: ResponseFormat,
+    State(state): State<AppState>,
+    session: Session,
+) -> Result<Response, AppError> {
+    format.render(ConnectionPickerTemplate {
+        connections: state.connections.list().await,
+        selected: selected_connection_id(Some(&session), &state).await,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct SelectConnectionRequest {
+    pub id: String,
+}
+
+/// Use a connection profile for the rest of this browser session
+///
+/// HTMX requests get `HX-Refresh` so the page reloads against the new server.
+pub async fn select_connection(
+    State(state): State<AppState>,
+    session: Session,
+    Form(request): Form<SelectConnectionRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    if state.connections.handle(&request.id).is_none() {
+        return Err(AppError::NotFound(format!(
+            "Connection '{}' not found",
+            request.id
+        )));
+    }
+    session.insert(connection::SESSION_KEY, &request.id).await?;
+    Ok((
+        [("HX-Refresh", "true")],
+        Json(json!({ "selected": request.id })),
+    ))
+}
+
+/// Try connecting with the supplied parameters without saving them
+///
+/// Takes the same fields as a `CONNECTIONS_FILE` entry (the `id` is
+/// optional). The test ran either way, so a failed connection is still a 200
+/// with `success: false` and the error; only unusable parameters are a 400.
+pub async fn test_connection(
+    Json(request): Json<ConnectionConfig>,
+) -> Result<Json<ConnectionTest>, AppError> {
+    if request.password_env.is_some() {
+        // Would let a client send a server-side secret to a host of its choosing
+        return Err(AppError::BadRequest(
+            "password_env is not accepted here; send the password itself".to_string(),
+        ));
+    }
+    let options = request.connect_options().map_err(AppError::BadRequest)?;
+    Ok(Json(connection_service::try_connect(&options).await))
+}
+
+#[derive(Deserialize)]
+pub struct CapabilitiesQuery {
+    #[serde(default)]
+    pub refresh: bool,
+}
+
+/// Get the cached capabilities of a connection (`?refresh=true` re-probes)
+pub async fn capabilities(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<CapabilitiesQuery>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let handle = state.connections.handle(&id).ok_or(StatusCode::NOT_FOUND)?;
+    let capabilities = state
+        .connections
+        .capabilities(&id, &handle.pool, query.refresh)
+        .await
+        .map_err(|e| {
+            tracing::error!("{}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(json!({
+        "connection_id": id,
+        "features": capabilities.features(),
+        "capabilities": capabilities,
+    })))
+}
+
+#[derive(Template)]
+#[template(path = "components/connection-status.html")]
+struct ConnectionStatusTemplate {
+    /// `available`, `connecting`, `unavailable` or `demo`
+    state: &'static str,
+    /// Banner text, none while the database is reachable
+    message: Option<String>,
+    last_error: Option<String>,
+}
+
+/// Database reachability: a banner fragment for the page header (empty while
+/// available), or as JSON the state, last check time and recent outages
+pub async fn connection_status(
+    format: ResponseFormat,
+    State(state): State<AppState>,
+) -> Result<Response, AppError> {
+    // No database to watch; say so, since the data shown is made up
+    if state.demo.is_some() {
+        if format == ResponseFormat::Json {
+            return Ok(Json(json!({ "state": "demo" })).into_response());
+        }
+        let template = ConnectionStatusTemplate {
+            state: "demo",
+            message: Some(i18n::tr(
+                "Demo mode: showing sample data, no database is connected",
+            )),
+            last_error: None,
+        };
+        return Ok(Html(template.render()?).into_response());
+    }
+
+    let status = state.db_monitor.status();
+    if format == ResponseFormat::Json {
+        return Ok(Json(status).into_response());
+    }
+
+    let template = match status.state {
+        DatabaseState::Available => ConnectionStatusTemplate {
+            state: "available",
+            message: None,
+            last_error: None,
+        },
+        DatabaseState::Connecting {
+            attempts,
+            last_error,
+        } => ConnectionStatusTemplate {
+            state: "connecting",
+            message: Some(format!(
+                "Waiting for the database to become available ({} attempts so far)",
+                attempts
+            )),
+            last_error,
+        },
+        DatabaseState::Unavailable {
+            since, last_error, ..
+        } => ConnectionStatusTemplate {
+            state: "unavailable",
+            message: Some(format!(
+                "Database unavailable since {}; queries will fail until it reconnects",
+                since.format("%Y-%m-%d %H:%M:%S UTC")
+            )),
+            last_error: Some(last_error),
+        },
+    };
+    Ok(Html(template.render()?).into_response())
+}
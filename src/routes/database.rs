@@ -1,45 +1,42 @@
 // Database routes
 // Handles routes for database-level operations
 
+use crate::error::AppError;
+use crate::i18n::filters;
+use crate::middleware::client_ip::ClientIp;
+use crate::middleware::connection::Connected;
+use crate::middleware::response_format::ResponseFormat;
+use crate::services::audit_service::{AuditEvent, AuditEventType};
 use crate::services::database_service;
-use crate::AppState;
 use askama::Template;
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::{Html, IntoResponse},
+    extract::Path,
+    response::{IntoResponse, Response},
     Json,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Template)]
+#[derive(Template, Serialize)]
 #[template(path = "components/database-list.html")]
 pub struct DatabaseListTemplate {
     pub databases: Vec<crate::models::Database>,
 }
 
-/// Lists all databases on the PostgreSQL server (returns HTML)
+/// Lists all databases on the PostgreSQL server (returns HTML or JSON)
 pub async fn list_databases(
-    State(state): State<AppState>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let databases = database_service::list_databases(&state.db_pool)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let template = DatabaseListTemplate { databases };
-    match template.render() {
-        Ok(html) => Ok(Html(html)),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
+    format: ResponseFormat,
+    Connected(state): Connected,
+) -> Result<Response, AppError> {
+    let databases = database_service::list_databases(&state.db_pool).await?;
+
+    format.render(DatabaseListTemplate { databases })
 }
 
 /// Lists all databases (returns JSON)
 pub async fn list_databases_json(
-    State(state): State<AppState>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let databases = database_service::list_databases(&state.db_pool)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Connected(state): Connected,
+) -> Result<impl IntoResponse, AppError> {
+    let databases = database_service::list_databases(&state.db_pool).await?;
 
     Ok(Json(databases))
 }
@@ -47,11 +44,9 @@ pub async fn list_databases_json(
 /// Gets details about a specific database
 pub async fn get_database(
     Path(db_name): Path<String>,
-    State(state): State<AppState>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let database = database_service::get_database_info(&state.db_pool, &db_name)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Connected(state): Connected,
+) -> Result<impl IntoResponse, AppError> {
+    let database = database_service::get_database_info(&state.db_pool, &db_name).await?;
 
     Ok(Json(database))
 }
@@ -64,17 +59,27 @@ pub struct CreateDatabaseRequest {
 
 /// Creates a new database
 pub async fn create_database(
-    State(state): State<AppState>,
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
     Json(req): Json<CreateDatabaseRequest>,
-) -> Result<impl IntoResponse, StatusCode> {
+) -> Result<impl IntoResponse, AppError> {
     let owner = req.owner.as_deref();
 
-    database_service::create_database(&state.db_pool, &req.name, owner)
-        .await
-        .map_err(|e| {
-            tracing::error!("Failed to create database: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+    let result = database_service::create_database(&state.db_pool, &req.name, owner).await;
+    state
+        .audit_logger
+        .log_result(
+            AuditEvent::new(
+                AuditEventType::SchemaModification,
+                client_ip,
+                "CREATE DATABASE".to_string(),
+                req.name.clone(),
+            ),
+            &result,
+        )
+        .await;
+
+    result?;
 
     tracing::info!("Database created: {}", req.name);
 
@@ -91,15 +96,25 @@ pub struct DropDatabaseRequest {
 
 /// Drops a database
 pub async fn drop_database(
-    State(state): State<AppState>,
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
     Json(req): Json<DropDatabaseRequest>,
-) -> Result<impl IntoResponse, StatusCode> {
-    database_service::drop_database(&state.db_pool, &req.name)
-        .await
-        .map_err(|e| {
-            tracing::error!("Failed to drop database: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+) -> Result<impl IntoResponse, AppError> {
+    let result = database_service::drop_database(&state.db_pool, &req.name).await;
+    state
+        .audit_logger
+        .log_result(
+            AuditEvent::new(
+                AuditEventType::SchemaModification,
+                client_ip,
+                "DROP DATABASE".to_string(),
+                req.name.clone(),
+            ),
+            &result,
+        )
+        .await;
+
+    result?;
 
     tracing::info!("Database dropped: {}", req.name);
 
@@ -0,0 +1,101 @@
+// Definitions routes
+// Drift report, apply and export for the saved query and schedule definitions file
+
+use crate::error::AppError;
+use crate::middleware::client_ip::ClientIp;
+use crate::services::audit_service::{AuditEvent, AuditEventType};
+use crate::services::definitions_service::{
+    self, DefinitionAction, DefinitionsFile, DefinitionsFormat, DefinitionsReport,
+};
+use crate::AppState;
+use axum::{
+    extract::{Query, State},
+    http::header,
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct ExportParams {
+    #[serde(default)]
+    pub format: Option<String>,
+}
+
+/// How the saved queries and schedules differ from the definitions file
+pub async fn definitions_report(
+    State(state): State<AppState>,
+) -> Result<Json<DefinitionsReport>, AppError> {
+    state
+        .definitions
+        .report(&state.saved_queries, &state.scheduler)
+        .await
+        .map(Json)
+        .map_err(AppError::BadRequest)
+}
+
+/// Re-read the definitions file and apply it, undoing any drift
+pub async fn apply_definitions(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+) -> Result<Json<DefinitionsReport>, AppError> {
+    let report = state
+        .definitions
+        .apply(&state.saved_queries, &state.scheduler)
+        .await
+        .map_err(AppError::BadRequest)?;
+
+    let changed: Vec<String> = report
+        .changes
+        .iter()
+        .filter(|c| c.action != DefinitionAction::Unchanged)
+        .map(|c| format!("{:?} {:?} '{}'", c.action, c.kind, c.name).to_lowercase())
+        .collect();
+    if !changed.is_empty() {
+        tracing::info!("Applied {}: {}", report.file, changed.join(", "));
+        state
+            .audit_logger
+            .log(
+                AuditEvent::new(
+                    AuditEventType::ConfigurationChange,
+                    client_ip,
+                    "apply definitions".to_string(),
+                    report.file.clone(),
+                )
+                .with_details(changed.join(", ")),
+            )
+            .await;
+    }
+    Ok(Json(report))
+}
+
+/// Download every schedule and the saved queries it runs as a definitions
+/// file, TOML unless `?format=yaml`
+pub async fn export_definitions(
+    State(state): State<AppState>,
+    Query(params): Query<ExportParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let format = match params.format.as_deref() {
+        None | Some("") => DefinitionsFormat::Toml,
+        Some(name) => DefinitionsFormat::from_name(name).ok_or_else(|| {
+            AppError::BadRequest(format!("Unsupported definitions format: {}", name))
+        })?,
+    };
+    let (saved, schedules) =
+        definitions_service::current(&state.saved_queries, &state.scheduler).await;
+    let content = format
+        .render(&DefinitionsFile::export(&saved, &schedules))
+        .map_err(AppError::Internal)?;
+    let disposition = format!(
+        "attachment; filename=\"definitions.{}\"",
+        format.extension()
+    );
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        content,
+    ))
+}
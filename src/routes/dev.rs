@@ -0,0 +1,125 @@
+// Development routes (DEV_MODE)
+// Serve the scripts unbundled and tell open pages when static assets change,
+// so scripts and styles can be worked on without npm or a rebuild. Templates
+// are compiled in by askama, so they are not covered: a template edit needs a
+// rebuild, and pages reload once the restarted server is back
+
+use axum::{
+    extract::Request,
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::get,
+    Router,
+};
+use futures::stream::{self, Stream, StreamExt};
+use std::convert::Infallible;
+use std::path::Path;
+use std::sync::LazyLock;
+use std::time::{Duration, SystemTime};
+
+/// How often open pages' streams look for changed files
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Static assets, watched for changes
+const STATIC_DIR: &str = "static";
+
+/// Bundled output, which the unbundled scripts replace in development
+const BUNDLE_DIR: &str = "static/js/dist";
+
+/// Identifies this process, so pages can tell the server was restarted
+static BOOT_ID: LazyLock<String> = LazyLock::new(|| uuid::Uuid::new_v4().to_string());
+
+/// Routes only mounted in development mode
+pub fn router() -> Router {
+    Router::new()
+        .route("/dev/htmx.js", get(htmx_module))
+        .route("/dev/events", get(events))
+}
+
+/// Lets the unbundled scripts `import htmx from "htmx.org"` (mapped here by
+/// the page's import map) while htmx itself loads as a classic script
+async fn htmx_module() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/javascript")],
+        "export default window.htmx;\n",
+    )
+}
+
+/// Static files are revalidated on every load, so edits show on refresh
+pub async fn revalidate_static(request: Request, next: Next) -> Response {
+    let is_static = request.uri().path().starts_with("/static/");
+    let mut response = next.run(request).await;
+    if is_static {
+        response
+            .headers_mut()
+            .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    }
+    response
+}
+
+/// Newest modification time of the files under `dir`, skipping the bundle
+fn last_modified(dir: &Path) -> Option<SystemTime> {
+    if dir == Path::new(BUNDLE_DIR) {
+        return None;
+    }
+    std::fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            if metadata.is_dir() {
+                last_modified(&entry.path())
+            } else {
+                metadata.modified().ok()
+            }
+        })
+        .max()
+}
+
+/// A `hello` event with the server's boot id, then a `static` event whenever
+/// a static file changes
+async fn events() -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let seen = last_modified(Path::new(STATIC_DIR));
+    let hello = Event::default()
+        .event("hello")
+        .json_data(serde_json::json!({ "boot_id": *BOOT_ID }))
+        .unwrap_or_default();
+
+    let changes = stream::unfold(seen, |seen| async move {
+        tokio::time::sleep(WATCH_INTERVAL).await;
+        let modified = last_modified(Path::new(STATIC_DIR));
+        let event = (modified != seen).then(|| Event::default().event("static").data(""));
+        Some((event, modified))
+    })
+    .filter_map(|event| async move { event.map(Ok) });
+
+    Sse::new(stream::once(async { Ok(hello) }).chain(changes)).keep_alive(KeepAlive::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_last_modified() {
+        let dir = std::env::temp_dir().join(format!("pgadmin-dev-{}", uuid::Uuid::new_v4()));
+        let nested = dir.join("css");
+        std::fs::create_dir_all(&nested).unwrap();
+        assert_eq!(last_modified(&dir), None);
+
+        std::fs::write(dir.join("a.js"), "a").unwrap();
+        let first = last_modified(&dir).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        std::fs::write(nested.join("b.css"), "b").unwrap();
+        let second = last_modified(&dir).unwrap();
+        assert!(second > first);
+
+        assert_eq!(last_modified(&dir.join("missing")), None);
+        assert_eq!(last_modified(Path::new(BUNDLE_DIR)), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,123 @@
+// Schema diff routes
+// Compares the structure of two schemas, or the rows of two tables, and reports the differences
+
+use crate::config::Branding;
+use crate::error::AppError;
+use crate::i18n::filters;
+use crate::middleware::connection::Connected;
+use crate::middleware::query_limit::QuerySlot;
+use crate::middleware::quota::Quota;
+use crate::routes::HtmlTemplate;
+use crate::services::data_diff_service::{
+    DataDiff, DataDiffService, TableRef, DEFAULT_SAMPLE_LIMIT,
+};
+use crate::services::diff_service::{DiffService, SchemaDiff};
+use crate::services::schema_service;
+use askama::Template;
+use axum::{extract::Query, http::StatusCode, response::IntoResponse, Json};
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+pub struct SchemaDiffQuery {
+    pub left: Option<String>,
+    pub right: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DataDiffQuery {
+    pub left_schema: String,
+    pub left_table: String,
+    pub right_schema: String,
+    pub right_table: String,
+    pub limit: Option<usize>,
+}
+
+#[derive(Template)]
+#[template(path = "schema-diff.html")]
+pub struct SchemaDiffTemplate {
+    pub branding: Arc<Branding>,
+    pub schemas: Vec<crate::models::Schema>,
+    pub left: String,
+    pub right: String,
+    pub diff: Option<SchemaDiff>,
+    pub error: Option<String>,
+}
+
+/// Compare two schemas (returns JSON)
+pub async fn schema_diff(
+    Connected(state): Connected,
+    Query(query): Query<SchemaDiffQuery>,
+) -> Result<Json<SchemaDiff>, StatusCode> {
+    let (Some(left), Some(right)) = (query.left, query.right) else {
+        return Err(StatusCode::BAD_REQUEST);
+    };
+
+    DiffService::diff_schemas(&state.db_pool, &left, &right)
+        .await
+        .map(Json)
+        .map_err(|_| StatusCode::BAD_REQUEST)
+}
+
+/// Schema diff page with an HTML report
+pub async fn schema_diff_page(
+    Connected(state): Connected,
+    Query(query): Query<SchemaDiffQuery>,
+) -> impl IntoResponse {
+    let schemas = schema_service::list_schemas(&state.db_pool)
+        .await
+        .unwrap_or_default();
+    let left = query.left.unwrap_or_default();
+    let right = query.right.unwrap_or_default();
+
+    let (diff, error) = if left.is_empty() || right.is_empty() {
+        (None, None)
+    } else {
+        match DiffService::diff_schemas(&state.db_pool, &left, &right).await {
+            Ok(diff) => (Some(diff), None),
+            Err(e) => (None, Some(e)),
+        }
+    };
+
+    HtmlTemplate(SchemaDiffTemplate {
+        branding: state.branding.clone(),
+        schemas,
+        left,
+        right,
+        diff,
+        error,
+    })
+}
+
+/// Compare the rows of two tables by primary key (returns JSON); tables with
+/// more rows than the role's row quota are refused
+pub async fn data_diff(
+    Connected(state): Connected,
+    _slot: QuerySlot,
+    quota: Quota,
+    Query(query): Query<DataDiffQuery>,
+) -> Result<Json<DataDiff>, AppError> {
+    let left = TableRef {
+        pool: &state.db_pool,
+        schema: &query.left_schema,
+        table: &query.left_table,
+    };
+    let right = TableRef {
+        pool: &state.db_pool,
+        schema: &query.right_schema,
+        table: &query.right_table,
+    };
+
+    let action = format!(
+        "data diff {}.{} {}.{}",
+        query.left_schema, query.left_table, query.right_schema, query.right_table
+    );
+    quota
+        .limit_rows(
+            &action,
+            DataDiffService::diff(left, right, query.limit.unwrap_or(DEFAULT_SAMPLE_LIMIT)),
+        )
+        .await?
+        .map(Json)
+        .map_err(AppError::BadRequest)
+}
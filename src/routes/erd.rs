@@ -0,0 +1,89 @@
+// ER diagram routes
+// Renders entity-relationship diagrams of a schema as Mermaid, DOT or JSON
+
+use crate::config::Branding;
+use crate::i18n::filters;
+use crate::middleware::connection::Connected;
+use crate::routes::HtmlTemplate;
+use crate::services::erd_service::{ErdFormat, ErdService};
+use askama::Template;
+use axum::{
+    extract::{Path, Query},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+pub struct ErdQuery {
+    pub format: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "erd.html")]
+pub struct ErdTemplate {
+    pub branding: Arc<Branding>,
+    pub schema: String,
+    pub table_count: usize,
+    pub relationship_count: usize,
+    pub mermaid: String,
+    pub error: Option<String>,
+}
+
+/// Get the ER diagram for a schema (`?format=mermaid|dot|json`, default mermaid)
+pub async fn erd(
+    Connected(state): Connected,
+    Path(schema): Path<String>,
+    Query(query): Query<ErdQuery>,
+) -> Result<Response, StatusCode> {
+    let format = ErdFormat::parse(query.format.as_deref()).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let model = ErdService::build(&state.db_pool, &schema)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to build ER diagram: {}", e);
+            StatusCode::BAD_REQUEST
+        })?;
+
+    Ok(match format {
+        ErdFormat::Json => Json(model).into_response(),
+        ErdFormat::Mermaid => (
+            [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+            ErdService::to_mermaid(&model),
+        )
+            .into_response(),
+        ErdFormat::Dot => (
+            [(header::CONTENT_TYPE, "text/vnd.graphviz; charset=utf-8")],
+            ErdService::to_dot(&model),
+        )
+            .into_response(),
+    })
+}
+
+/// ER diagram page for a schema
+pub async fn erd_page(
+    Connected(state): Connected,
+    Path(schema): Path<String>,
+) -> impl IntoResponse {
+    let template = match ErdService::build(&state.db_pool, &schema).await {
+        Ok(model) => ErdTemplate {
+            branding: state.branding.clone(),
+            table_count: model.tables.len(),
+            relationship_count: model.relationships.len(),
+            mermaid: ErdService::to_mermaid(&model),
+            schema,
+            error: None,
+        },
+        Err(e) => ErdTemplate {
+            branding: state.branding.clone(),
+            schema,
+            table_count: 0,
+            relationship_count: 0,
+            mermaid: String::new(),
+            error: Some(e),
+        },
+    };
+
+    HtmlTemplate(template)
+}
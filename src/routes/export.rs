@@ -1,12 +1,18 @@
 // Export routes
 // Handles exporting query results and table data in various formats
 
+use crate::error::AppError;
+use crate::middleware::client_ip::ClientIp;
+use crate::middleware::connection::Connected;
+use crate::middleware::query_limit::QuerySlot;
+use crate::middleware::quota::Quota;
+use crate::routes::query::query_audit_event;
+use crate::services::audit_service::AuditEventType;
 use crate::services::export_service::{ExportFormat, ExportService};
 use crate::services::query_service;
-use crate::AppState;
+use crate::services::webhook_service::Notification;
 use axum::{
-    extract::State,
-    http::{HeaderMap, HeaderValue, StatusCode},
+    http::{HeaderMap, HeaderValue},
     response::IntoResponse,
     Form,
 };
@@ -20,23 +26,78 @@ pub struct ExportQueryRequest {
 }
 
 /// Executes a query and exports the results in the specified format
+///
+/// Subject to the role's quotas: refused with a 429 once the client's daily
+/// exports are used up, and with a 403 when the result has too many rows or
+/// the file would be too large.
 pub async fn export_query(
-    State(state): State<AppState>,
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
+    _slot: QuerySlot,
+    quota: Quota,
     Form(payload): Form<ExportQueryRequest>,
-) -> Result<impl IntoResponse, StatusCode> {
+) -> Result<impl IntoResponse, AppError> {
     let format = ExportFormat::from_str(&payload.format).unwrap_or(ExportFormat::Csv);
+    let resource = format!("export:{}", format.extension());
 
     // Validate query
-    if query_service::validate_query(&payload.query).is_err() {
-        return Err(StatusCode::BAD_REQUEST);
+    if let Err(e) = query_service::validate_query(&payload.query) {
+        state
+            .audit_logger
+            .log(query_audit_event(
+                AuditEventType::DangerousQueryDetected,
+                client_ip,
+                &payload.query,
+                &resource,
+                Some(&e),
+            ))
+            .await;
+        return Err(AppError::BadRequest(e));
     }
+    quota.check_export_allowance(&payload.query).await?;
 
     // Execute query
-    match query_service::execute_query(&state.db_pool, &payload.query).await {
+    // Box<dyn Error> is not Send, so convert it before awaiting the audit log
+    let result = quota
+        .limit_rows(&payload.query, async {
+            query_service::execute_query(
+                &state.db_pool,
+                &payload.query,
+                state.read_only_mode.is_enabled(),
+            )
+            .await
+            .map_err(|e| e.to_string())
+        })
+        .await?;
+    let (event_type, error) = match &result {
+        Ok(_) => (AuditEventType::QueryExecution, None),
+        Err(e) => (AuditEventType::SqlError, Some(e.as_str())),
+    };
+    state
+        .audit_logger
+        .log(query_audit_event(
+            event_type,
+            client_ip.clone(),
+            &payload.query,
+            &resource,
+            error,
+        ))
+        .await;
+
+    match result {
         Ok(result) => {
             // Export the result
             match ExportService::export(&result, format) {
                 Ok(content) => {
+                    quota.record_export(&payload.query, content.len()).await?;
+                    state.webhooks.emit(Notification::export_completed(
+                        "a query",
+                        format.extension(),
+                        result.row_count,
+                        content.len(),
+                        &client_ip,
+                    ));
+
                     let mut headers = HeaderMap::new();
 
                     // Set Content-Type header
@@ -54,9 +115,9 @@ pub async fn export_query(
 
                     Ok((headers, content))
                 }
-                Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+                Err(e) => Err(AppError::Internal(e)),
             }
         }
-        Err(_) => Err(StatusCode::BAD_REQUEST),
+        Err(e) => Err(AppError::BadRequest(e)),
     }
 }
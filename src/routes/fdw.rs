@@ -0,0 +1,75 @@
+// Foreign data routes
+// Lists foreign servers, user mappings and foreign tables, and imports a
+// remote schema's tables as foreign tables
+
+use crate::error::AppError;
+use crate::i18n::filters;
+use crate::middleware::client_ip::ClientIp;
+use crate::middleware::connection::Connected;
+use crate::middleware::response_format::ResponseFormat;
+use crate::services::audit_service::{AuditEvent, AuditEventType};
+use crate::services::fdw_service::{
+    self, ForeignServer, ForeignTable, ImportForeignSchemaRequest, UserMapping,
+};
+use askama::Template;
+use axum::{response::Response, Json};
+use serde::Serialize;
+use serde_json::json;
+
+#[derive(Template, Serialize)]
+#[template(path = "components/foreign-data.html")]
+pub struct ForeignDataTemplate {
+    pub servers: Vec<ForeignServer>,
+    pub user_mappings: Vec<UserMapping>,
+    pub foreign_tables: Vec<ForeignTable>,
+}
+
+/// Foreign servers, user mappings and foreign tables
+pub async fn foreign_data(
+    format: ResponseFormat,
+    Connected(state): Connected,
+) -> Result<Response, AppError> {
+    let servers = fdw_service::list_servers(&state.db_pool).await?;
+    let user_mappings = fdw_service::list_user_mappings(&state.db_pool).await?;
+    let foreign_tables = fdw_service::list_foreign_tables(&state.db_pool).await?;
+
+    format.render(ForeignDataTemplate {
+        servers,
+        user_mappings,
+        foreign_tables,
+    })
+}
+
+/// Create foreign tables for a remote schema with `IMPORT FOREIGN SCHEMA`
+pub async fn import_foreign_schema(
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
+    Json(payload): Json<ImportForeignSchemaRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let result = fdw_service::import_foreign_schema(&state.db_pool, &payload).await;
+    state
+        .audit_logger
+        .log_result(
+            AuditEvent::new(
+                AuditEventType::SchemaModification,
+                client_ip,
+                format!(
+                    "IMPORT FOREIGN SCHEMA {} FROM SERVER {}",
+                    payload.remote_schema, payload.server
+                ),
+                payload.local_schema.clone(),
+            ),
+            &result,
+        )
+        .await;
+    let imported = result.map_err(AppError::BadRequest)?;
+    state.metadata_cache.invalidate(&payload.local_schema).await;
+
+    Ok(Json(json!({
+        "message": format!(
+            "Imported {} foreign tables into {}",
+            imported, payload.local_schema
+        ),
+        "tables": imported,
+    })))
+}
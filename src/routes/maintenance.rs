@@ -0,0 +1,119 @@
+// Maintenance routes
+// Queues batches of VACUUM, ANALYZE and REINDEX commands that run one at a
+// time in the background, reports each task's status and lists bloated
+// indexes worth rebuilding
+
+use crate::error::AppError;
+use crate::middleware::client_ip::ClientIp;
+use crate::middleware::connection::Connected;
+use crate::services::audit_service::{AuditEvent, AuditEventType};
+use crate::services::maintenance_service::{self, BatchRequest, IndexBloat, MaintenanceBatch};
+use crate::AppState;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Deserialize;
+
+/// Queue a batch; answers 202 with it once its targets are found
+pub async fn create_batch(
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
+    Json(payload): Json<BatchRequest>,
+) -> Result<(StatusCode, Json<MaintenanceBatch>), AppError> {
+    if state.read_only_mode.is_enabled() {
+        return Err(AppError::Forbidden(
+            "Maintenance is not allowed in read-only mode".to_string(),
+        ));
+    }
+    let tasks = maintenance_service::plan_tasks(&state.db_pool, &payload)
+        .await
+        .map_err(AppError::BadRequest)?;
+
+    let connect = (*state.db_pool.connect_options()).clone();
+    let database = connect.get_database().unwrap_or_default().to_string();
+    let audit_logger = state.audit_logger.clone();
+    let batch = state
+        .maintenance
+        .enqueue(connect, &payload, tasks, move |task| {
+            let audit_logger = audit_logger.clone();
+            let client_ip = client_ip.clone();
+            let database = database.clone();
+            async move {
+                let result = match task.error {
+                    Some(e) => Err(e),
+                    None => Ok(()),
+                };
+                audit_logger
+                    .log_result(
+                        AuditEvent::new(
+                            AuditEventType::SchemaModification,
+                            client_ip,
+                            task.statement,
+                            database,
+                        ),
+                        &result,
+                    )
+                    .await;
+            }
+        })
+        .await;
+
+    Ok((StatusCode::ACCEPTED, Json(batch)))
+}
+
+/// Queued, running and recent batches, newest first
+pub async fn list_batches(State(state): State<AppState>) -> Json<Vec<MaintenanceBatch>> {
+    Json(state.maintenance.list().await)
+}
+
+/// One batch with the status of each task
+pub async fn get_batch(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<MaintenanceBatch>, AppError> {
+    state
+        .maintenance
+        .get(&id)
+        .await
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound(format!("Maintenance batch '{}' not found", id)))
+}
+
+/// Cancel a queued or running batch
+pub async fn cancel_batch(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<MaintenanceBatch>, AppError> {
+    match state.maintenance.cancel(&id).await {
+        Some(result) => result.map(Json).map_err(AppError::Conflict),
+        None => Err(AppError::NotFound(format!(
+            "Maintenance batch '{}' not found",
+            id
+        ))),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct BloatParams {
+    pub schema: Option<String>,
+    /// Smallest estimated bloat reported, in MB (default 1)
+    pub min_size_mb: Option<f64>,
+    /// Smallest estimated share of the index that is bloat (default 0.3)
+    pub min_ratio: Option<f64>,
+}
+
+/// Btree indexes with estimated bloat, most bloated first
+pub async fn bloated_indexes(
+    Connected(state): Connected,
+    Query(params): Query<BloatParams>,
+) -> Result<Json<Vec<IndexBloat>>, AppError> {
+    let min_bytes = (params.min_size_mb.unwrap_or(1.0).max(0.0) * 1024.0 * 1024.0) as i64;
+    let indexes = maintenance_service::bloated_indexes(
+        &state.db_pool,
+        params.schema.as_deref().filter(|s| !s.is_empty()),
+        min_bytes,
+        params.min_ratio.unwrap_or(0.3),
+    )
+    .await?;
+    Ok(Json(indexes))
+}
@@ -1,40 +1,127 @@
 // Route modules
+pub mod admin;
+pub mod api_v1;
+pub mod audit;
+pub mod backups;
+pub mod branding;
 pub mod cell;
+pub mod connections;
 pub mod database;
+pub mod definitions;
+pub mod dev;
+pub mod diff;
+pub mod erd;
 pub mod export;
+pub mod fdw;
+pub mod maintenance;
+pub mod openapi;
+pub mod plans;
+pub mod preferences;
+pub mod privileges;
 pub mod query;
+pub mod replication;
+pub mod restore;
+pub mod saved_queries;
+pub mod schedules;
 pub mod schema;
 pub mod schema_ops;
+pub mod settings;
 pub mod stats;
 pub mod studio;
 pub mod tables;
+pub mod transactions;
 
+use crate::config::Branding;
+use crate::error::AppError;
+use crate::i18n::filters;
+use crate::middleware::session::Session;
+use crate::services::db_service;
+use crate::services::result_store::{self, LastResult};
+use crate::AppState;
 use askama::Template;
 use axum::{
+    extract::State,
     http::StatusCode,
     response::{Html, IntoResponse, Response},
+    Json,
 };
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long the readiness probe waits for the database
+const READINESS_TIMEOUT: Duration = Duration::from_secs(2);
 
 #[derive(Template)]
 #[template(path = "dashboard.html")]
-pub struct DashboardTemplate;
+pub struct DashboardTemplate {
+    pub branding: Arc<Branding>,
+}
 
 #[derive(Template)]
 #[template(path = "query.html")]
-pub struct QueryTemplate;
+pub struct QueryTemplate {
+    pub branding: Arc<Branding>,
+    /// The session's open transaction, resumed on load
+    pub transaction: Option<String>,
+    /// The session's last kept result, shown again on load
+    pub last_result: Option<LastResult>,
+}
 
-pub async fn index() -> impl IntoResponse {
-    HtmlTemplate(DashboardTemplate)
+pub async fn index(State(state): State<AppState>) -> impl IntoResponse {
+    HtmlTemplate(DashboardTemplate {
+        branding: state.branding.clone(),
+    })
 }
 
-pub async fn page_query() -> impl IntoResponse {
-    HtmlTemplate(QueryTemplate)
+pub async fn page_query(
+    State(state): State<AppState>,
+    session: Session,
+) -> Result<impl IntoResponse, AppError> {
+    let owner = transactions::owner(&session).await?.unwrap_or_default();
+    let transaction = match session.get::<String>(transactions::SESSION_KEY).await? {
+        Some(id) if state.transactions.is_open(&id, &owner).await => Some(id),
+        Some(_) => {
+            // Committed elsewhere or rolled back when idle
+            session.remove_value(transactions::SESSION_KEY).await?;
+            None
+        }
+        None => None,
+    };
+    let last_result = match session.get::<LastResult>(result_store::SESSION_KEY).await? {
+        Some(last) if state.result_store.contains(&last.token).await => Some(last),
+        Some(_) => {
+            session.remove_value(result_store::SESSION_KEY).await?;
+            None
+        }
+        None => None,
+    };
+    Ok(HtmlTemplate(QueryTemplate {
+        branding: state.branding.clone(),
+        transaction,
+        last_result,
+    }))
 }
 
 pub async fn health_check() -> impl IntoResponse {
     (StatusCode::OK, "OK")
 }
 
+/// Readiness probe: pings the database and reports latency and pool stats,
+/// with a 503 while the database is unreachable (always ready in demo mode)
+pub async fn readiness_check(State(state): State<AppState>) -> Response {
+    if state.demo.is_some() {
+        return Json(serde_json::json!({ "ready": true, "demo": true })).into_response();
+    }
+    let readiness =
+        db_service::check_readiness(&state.db_pool, &state.db_monitor, READINESS_TIMEOUT).await;
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness)).into_response()
+}
+
 pub struct HtmlTemplate<T>(pub T);
 
 impl<T> IntoResponse for HtmlTemplate<T>
@@ -44,11 +131,7 @@ where
     fn into_response(self) -> Response {
         match self.0.render() {
             Ok(html) => Html(html).into_response(),
-            Err(err) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to render template: {}", err),
-            )
-                .into_response(),
+            Err(err) => AppError::from(err).into_response(),
         }
     }
 }
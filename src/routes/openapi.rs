@@ -0,0 +1,246 @@
+// OpenAPI routes
+// Serves the OpenAPI document for the /api/v1 JSON API and a Swagger UI page to browse it
+
+use crate::config::Branding;
+use crate::error::ErrorBody;
+use crate::i18n::filters;
+use crate::models::{ColumnInfo, Database, PageInfo, Schema, TableInfo};
+use crate::routes::api_v1::{self, QueryResponse, TableDetails};
+use crate::routes::query::{
+    ExecuteQueryRequest, QueryResultsTemplate, ScriptResultsTemplate, ScriptStatement,
+};
+use crate::routes::HtmlTemplate;
+use crate::services::query_history::HistoryEntry;
+use crate::services::result_store::ResultDownload;
+use crate::AppState;
+use askama::Template;
+use axum::{extract::State, response::IntoResponse, Json};
+use std::sync::Arc;
+use utoipa::openapi::{server::Server, OpenApi as Spec};
+use utoipa::OpenApi;
+
+#[derive(Template)]
+#[template(path = "api-docs.html")]
+pub struct ApiDocsTemplate {
+    pub branding: Arc<Branding>,
+}
+
+/// GET /api/openapi.json - OpenAPI 3.1 document for the JSON API
+pub async fn openapi_json(State(state): State<AppState>) -> Json<Spec> {
+    Json(spec(&state.branding.base_path))
+}
+
+/// GET /api/docs - Swagger UI for the JSON API
+pub async fn api_docs_page(State(state): State<AppState>) -> impl IntoResponse {
+    HtmlTemplate(ApiDocsTemplate {
+        branding: state.branding.clone(),
+    })
+}
+
+/// The `/api/v1` handlers and the models they exchange
+///
+/// Paths come from the `#[utoipa::path]` attribute of each handler and
+/// component schemas from the `ToSchema` derives on the models, so the
+/// document follows the code as it changes.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "pgAdmin-rs API",
+        description = "JSON API for scripts and CI jobs. Successful responses are wrapped as \
+            {\"data\": ...}; list endpoints add \"pagination\"."
+    ),
+    paths(
+        api_v1::list_databases,
+        api_v1::list_schemas,
+        api_v1::list_tables,
+        api_v1::get_table,
+        api_v1::list_rows,
+        api_v1::execute_query,
+        api_v1::query_history,
+    ),
+    components(schemas(
+        Database,
+        Schema,
+        TableInfo,
+        ColumnInfo,
+        TableDetails,
+        ExecuteQueryRequest,
+        QueryResponse,
+        QueryResultsTemplate,
+        ResultDownload,
+        ScriptResultsTemplate,
+        ScriptStatement,
+        HistoryEntry,
+        PageInfo,
+        ErrorBody,
+    ))
+)]
+struct ApiDoc;
+
+/// Build the OpenAPI document
+///
+/// Paths are relative to the server URL, which carries any `APP_BASE_PATH`
+/// prefix.
+pub fn spec(base_path: &str) -> Spec {
+    let mut spec = ApiDoc::openapi();
+    // The crate declares no license for the document to name
+    spec.info.license = None;
+    spec.servers = Some(vec![Server::new(if base_path.is_empty() {
+        "/"
+    } else {
+        base_path
+    })]);
+    spec
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::AppError;
+    use serde_json::{json, Value};
+
+    fn document(base_path: &str) -> Value {
+        serde_json::to_value(spec(base_path)).unwrap()
+    }
+
+    fn schema_ref(name: &str) -> Value {
+        json!({ "$ref": format!("#/components/schemas/{}", name) })
+    }
+
+    /// Property names documented for a component schema
+    fn documented(spec: &Value, name: &str) -> Vec<String> {
+        let mut keys: Vec<String> = spec["components"]["schemas"][name]["properties"]
+            .as_object()
+            .unwrap_or_else(|| panic!("{} is not documented", name))
+            .keys()
+            .cloned()
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    fn serialized(value: impl serde::Serialize) -> Vec<String> {
+        let mut keys: Vec<String> = serde_json::to_value(value)
+            .unwrap()
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn test_component_schemas_match_models() {
+        let spec = document("");
+        let table = TableInfo {
+            schema: "public".to_string(),
+            name: "users".to_string(),
+            table_type: "BASE TABLE".to_string(),
+            row_count: None,
+            size: None,
+        };
+        let results = QueryResultsTemplate {
+            columns: vec![],
+            rows: vec![],
+            row_count: 0,
+            affected_rows: None,
+            execution_time_ms: None,
+            error: None,
+            download: None,
+        };
+
+        let cases = [
+            (
+                "Database",
+                serialized(Database {
+                    name: "app".to_string(),
+                    owner: None,
+                    size: None,
+                    encoding: None,
+                }),
+            ),
+            (
+                "Schema",
+                serialized(Schema {
+                    name: "public".to_string(),
+                    owner: None,
+                }),
+            ),
+            ("TableInfo", serialized(&table)),
+            (
+                "ColumnInfo",
+                serialized(ColumnInfo {
+                    name: "id".to_string(),
+                    data_type: "integer".to_string(),
+                    is_nullable: false,
+                    is_pk: true,
+                    default: None,
+                    comment: None,
+                }),
+            ),
+            ("QueryResults", serialized(&results)),
+            (
+                "ScriptResults",
+                serialized(ScriptResultsTemplate {
+                    statements: vec![],
+                    skipped: 0,
+                }),
+            ),
+            (
+                "HistoryEntry",
+                serialized(HistoryEntry::new("SELECT 1".to_string(), 1, Some(1))),
+            ),
+            (
+                "PageInfo",
+                serialized(PageInfo {
+                    page: 1,
+                    page_size: 50,
+                    total: 0,
+                    next_cursor: None,
+                }),
+            ),
+            (
+                "Error",
+                serialized(AppError::BadRequest("bad".to_string()).body()),
+            ),
+        ];
+        for (name, fields) in cases {
+            assert_eq!(
+                documented(&spec, name),
+                fields,
+                "{} schema is out of date",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_spec_references_resolve() {
+        let spec = document("");
+        let text = spec.to_string();
+        for reference in text.split("\"#/components/schemas/").skip(1) {
+            let name = &reference[..reference.find('"').unwrap()];
+            assert!(
+                spec["components"]["schemas"].get(name).is_some(),
+                "dangling reference to {}",
+                name
+            );
+        }
+
+        let paths = spec["paths"].as_object().unwrap();
+        assert!(paths.keys().all(|path| path.starts_with("/api/v1/")));
+        assert_eq!(
+            paths["/api/v1/query"]["post"]["responses"]["409"]["content"]["application/json"]
+                ["schema"],
+            schema_ref("Error")
+        );
+    }
+
+    #[test]
+    fn test_server_url_includes_base_path() {
+        assert_eq!(document("")["servers"][0]["url"], "/");
+        assert_eq!(document("/pgadmin")["servers"][0]["url"], "/pgadmin");
+    }
+}
@@ -0,0 +1,223 @@
+// Query plan routes
+// Captures EXPLAIN plans into the plan history, grouped by query
+// fingerprint, and compares two captured plans node by node
+
+use crate::error::AppError;
+use crate::i18n::filters;
+use crate::middleware::client_ip::ClientIp;
+use crate::middleware::connection::Connected;
+use crate::middleware::response_format::ResponseFormat;
+use crate::routes::audit::non_empty;
+use crate::routes::query::{query_audit_event, QueryParams};
+use crate::services::audit_service::AuditEventType;
+use crate::services::plan_history::{
+    compare_plans, CapturedPlan, NodeChange, NodeDiff, PlanSummary,
+};
+use crate::services::query_service;
+use crate::AppState;
+use askama::Template;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Response,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+#[derive(Deserialize)]
+pub struct ExplainRequest {
+    pub query: String,
+    /// Values bound to `$1`, `$2`, ... in order
+    #[serde(default)]
+    pub params: Option<QueryParams>,
+    /// Run the statement (`EXPLAIN ANALYZE`) inside a rolled-back transaction
+    #[serde(default)]
+    pub analyze: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PlanListQuery {
+    pub fingerprint: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CompareQuery {
+    pub before: String,
+    pub after: String,
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "components/plan-comparison.html")]
+pub struct PlanComparisonTemplate {
+    pub before: PlanSummary,
+    pub after: PlanSummary,
+    /// Both plans were captured for the same query fingerprint
+    pub same_query: bool,
+    pub nodes: Vec<NodeDiff>,
+}
+
+/// Capture a statement's plan and store it in the plan history
+pub async fn explain(
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
+    Json(payload): Json<ExplainRequest>,
+) -> Result<(StatusCode, Json<CapturedPlan>), AppError> {
+    let params = match payload.params.as_ref().map(QueryParams::to_params) {
+        Some(params) => params.map_err(AppError::BadRequest)?,
+        None => Vec::new(),
+    };
+    let resource = if payload.analyze {
+        "explain-analyze"
+    } else {
+        "explain"
+    };
+    if let Err(e) = query_service::validate_query(&payload.query) {
+        state
+            .audit_logger
+            .log(query_audit_event(
+                AuditEventType::DangerousQueryDetected,
+                client_ip,
+                &payload.query,
+                resource,
+                Some(&e),
+            ))
+            .await;
+        return Err(AppError::BadRequest(e));
+    }
+
+    let result =
+        query_service::explain_json(&state.db_pool, &payload.query, &params, payload.analyze).await;
+    let (event_type, error) = match &result {
+        Ok(_) => (AuditEventType::QueryExecution, None),
+        Err(e) => (AuditEventType::SqlError, Some(e.as_str())),
+    };
+    state
+        .audit_logger
+        .log(query_audit_event(
+            event_type,
+            client_ip,
+            &payload.query,
+            resource,
+            error,
+        ))
+        .await;
+    let plan = result.map_err(AppError::BadRequest)?;
+
+    let captured = state
+        .plan_history
+        .record(&payload.query, payload.analyze, plan)
+        .await
+        .map_err(AppError::Internal)?;
+    Ok((StatusCode::CREATED, Json(captured)))
+}
+
+/// Captured plans, newest first, optionally only those of one `fingerprint`
+pub async fn list_plans(
+    State(state): State<AppState>,
+    Query(query): Query<PlanListQuery>,
+) -> Json<Vec<PlanSummary>> {
+    Json(state.plan_history.list(non_empty(&query.fingerprint)).await)
+}
+
+/// Get a captured plan with its full tree
+pub async fn get_plan(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<CapturedPlan>, AppError> {
+    find_plan(&state, &id).await.map(Json)
+}
+
+/// Delete a captured plan
+pub async fn delete_plan(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if !state
+        .plan_history
+        .delete(&id)
+        .await
+        .map_err(AppError::Internal)?
+    {
+        return Err(AppError::NotFound(format!("Plan {} not found", id)));
+    }
+    Ok(Json(
+        json!({ "status": "success", "message": "Plan deleted" }),
+    ))
+}
+
+/// Compare two captured plans (`before` and `after` are plan ids)
+pub async fn compare(
+    format: ResponseFormat,
+    State(state): State<AppState>,
+    Query(query): Query<CompareQuery>,
+) -> Result<Response, AppError> {
+    let before = find_plan(&state, &query.before).await?;
+    let after = find_plan(&state, &query.after).await?;
+
+    format.render(PlanComparisonTemplate {
+        nodes: compare_plans(&before.plan, &after.plan),
+        same_query: before.fingerprint == after.fingerprint,
+        before: before.summary(),
+        after: after.summary(),
+    })
+}
+
+async fn find_plan(state: &AppState, id: &str) -> Result<CapturedPlan, AppError> {
+    state
+        .plan_history
+        .get(id)
+        .await
+        .ok_or_else(|| AppError::NotFound(format!("Plan {} not found", id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::plan_history::PlanNode;
+
+    fn summary(query: &str) -> PlanSummary {
+        PlanSummary {
+            id: "p1".to_string(),
+            fingerprint: "SELECT * FROM users".to_string(),
+            query: query.to_string(),
+            analyze: false,
+            root_node: Some("Seq Scan on users".to_string()),
+            total_cost: Some(170.0),
+            execution_time_ms: None,
+            captured_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_plan_comparison_render() {
+        let node = |node_type: &str| PlanNode {
+            node_type: node_type.to_string(),
+            relation: Some("users".to_string()),
+            index: None,
+            total_cost: None,
+            plan_rows: None,
+            actual_total_time: None,
+            actual_rows: None,
+        };
+        let html = PlanComparisonTemplate {
+            before: summary("SELECT * FROM users WHERE id = 1"),
+            after: summary("SELECT * FROM users WHERE id = 2"),
+            same_query: false,
+            nodes: vec![NodeDiff {
+                depth: 1,
+                change: NodeChange::Changed,
+                before: Some(node("Seq Scan")),
+                after: Some(node("Bitmap Heap Scan")),
+                differences: vec!["node type: Seq Scan → Bitmap Heap Scan".to_string()],
+            }],
+        }
+        .render()
+        .unwrap();
+        assert!(html.contains("captured for different queries"));
+        assert!(html.contains("Cost 170.00"));
+        assert!(html.contains("padding-left: 24px"));
+        assert!(html.contains(">Bitmap Heap Scan on users</td>"));
+        assert!(html.contains("badge-warning badge-xs\">changed"));
+    }
+}
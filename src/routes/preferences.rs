@@ -0,0 +1,54 @@
+// Preference routes
+// Per-browser preferences kept in the server-side session, as a flat JSON
+// object: the keys handlers act on are checked by the preferences service,
+// and the frontend may keep keys of its own (editor settings, ...)
+
+use crate::middleware::session::Session;
+use crate::services::preferences_service::{self, SESSION_KEY};
+use crate::services::session_service::SessionData;
+use axum::{http::StatusCode, Json};
+use serde_json::json;
+
+type ApiError = (StatusCode, Json<serde_json::Value>);
+
+/// The session's preferences (empty if none were saved)
+pub async fn get_preferences(session: Session) -> Result<Json<SessionData>, ApiError> {
+    let preferences = session.get(SESSION_KEY).await.map_err(session_error)?;
+    Ok(Json(preferences.unwrap_or_default()))
+}
+
+/// Merge changes into the session's preferences; a `null` value removes a key
+pub async fn update_preferences(
+    session: Session,
+    Json(changes): Json<SessionData>,
+) -> Result<Json<SessionData>, ApiError> {
+    let mut preferences: SessionData = session
+        .get(SESSION_KEY)
+        .await
+        .map_err(session_error)?
+        .unwrap_or_default();
+    preferences_service::merge(&mut preferences, changes)
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({ "error": e }))))?;
+    preferences_service::check_size(&preferences)
+        .map_err(|e| (StatusCode::PAYLOAD_TOO_LARGE, Json(json!({ "error": e }))))?;
+
+    if preferences.is_empty() {
+        session
+            .remove_value(SESSION_KEY)
+            .await
+            .map_err(session_error)?;
+    } else {
+        session
+            .insert(SESSION_KEY, &preferences)
+            .await
+            .map_err(session_error)?;
+    }
+    Ok(Json(preferences))
+}
+
+fn session_error(e: tower_sessions::session::Error) -> ApiError {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({ "error": format!("Session error: {}", e) })),
+    )
+}
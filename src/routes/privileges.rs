@@ -0,0 +1,93 @@
+// Privileges routes
+// Handles viewing and changing GRANT/REVOKE privileges on schemas and tables
+
+use crate::middleware::client_ip::ClientIp;
+use crate::middleware::connection::Connected;
+use crate::services::audit_service::{AuditEvent, AuditEventType};
+use crate::services::privileges_service::{PrivilegeAction, PrivilegeRequest, PrivilegesService};
+use crate::AppState;
+use axum::{extract::Path, http::StatusCode, Json};
+use serde_json::json;
+
+/// Get schema-level privileges and all table grants within the schema
+pub async fn schema_privileges(
+    Connected(state): Connected,
+    Path(schema): Path<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let schema_grants = PrivilegesService::schema_privileges(&state.db_pool, &schema)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+    let table_grants = PrivilegesService::table_privileges(&state.db_pool, &schema, None)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+
+    Ok(Json(json!({
+        "schema": schema,
+        "schema_privileges": schema_grants,
+        "table_privileges": table_grants,
+    })))
+}
+
+/// Get the grants on a single table
+pub async fn table_privileges(
+    Connected(state): Connected,
+    Path((schema, table)): Path<(String, String)>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    PrivilegesService::table_privileges(&state.db_pool, &schema, Some(&table))
+        .await
+        .map(|grants| Json(json!(grants)))
+        .map_err(|_| StatusCode::NOT_FOUND)
+}
+
+/// Apply a grant or revoke and record it as an audit event
+async fn apply(
+    state: &AppState,
+    client_ip: String,
+    action: PrivilegeAction,
+    payload: &PrivilegeRequest,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let result = PrivilegesService::apply(&state.db_pool, action, payload).await;
+    let verb = match action {
+        PrivilegeAction::Grant => "GRANT",
+        PrivilegeAction::Revoke => "REVOKE",
+    };
+    state
+        .audit_logger
+        .log_result(
+            AuditEvent::new(
+                AuditEventType::SchemaModification,
+                client_ip,
+                format!(
+                    "{} {} {}",
+                    verb,
+                    payload.privileges.join(", "),
+                    payload.role
+                ),
+                format!("{}.{}", payload.schema, payload.tables.join(",")),
+            ),
+            &result,
+        )
+        .await;
+
+    result
+        .map(|msg| Json(json!({ "message": msg })))
+        .map_err(|_| StatusCode::BAD_REQUEST)
+}
+
+/// Grant privileges on tables to a role
+pub async fn grant(
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
+    Json(payload): Json<PrivilegeRequest>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    apply(&state, client_ip, PrivilegeAction::Grant, &payload).await
+}
+
+/// Revoke privileges on tables from a role
+pub async fn revoke(
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
+    Json(payload): Json<PrivilegeRequest>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    apply(&state, client_ip, PrivilegeAction::Revoke, &payload).await
+}
@@ -1,118 +1,1399 @@
 // Query execution routes
 // Handles routes for executing SQL queries
 
-use crate::services::query_history::HistoryEntry;
-use crate::services::query_service;
+use crate::error::AppError;
+use crate::i18n::{self, filters};
+use crate::logging;
+use crate::middleware::client_ip::ClientIp;
+use crate::middleware::connection::{Connected, DemoAware};
+use crate::middleware::query_limit::QuerySlot;
+use crate::middleware::quota::Quota;
+use crate::middleware::response_format::ResponseFormat;
+use crate::middleware::session::Session;
+use crate::models::{PageParams, Paginated};
+use crate::routes::audit::{non_empty, parse_timestamp};
+use crate::routes::transactions;
+use crate::services::audit_service::{AuditEvent, AuditEventType};
+use crate::services::chart_service::{self, Aggregate, AggregateSpec, ChartData};
+use crate::services::data_diff_service::{self, ResultRows};
+use crate::services::duplicate_service;
+use crate::services::export_service::{ExportFormat, ExportService};
+use crate::services::pivot_service::{self, Pivot, PivotSpec};
+use crate::services::preferences_service::Preferences;
+use crate::services::query_history::{
+    normalize_tags, GroupOrder, HistoryAnalytics, HistoryEntry, HistoryFilter, QueryGroup,
+};
+use crate::services::query_service::{self, StatementKind};
+use crate::services::result_store::{self, LastResult, ResultDownload, StoredResult};
+use crate::services::sql_format::format_sql;
+use crate::services::sql_lexer::split_statements;
+use crate::services::transaction_service;
+use crate::services::webhook_service::Notification;
 use crate::AppState;
 use askama::Template;
 use axum::{
-    extract::State,
-    http::StatusCode,
-    response::{Html, IntoResponse},
+    extract::{FromRequest, Path, Query, Request, State},
+    http::header,
+    response::{IntoResponse, Response},
     Form, Json,
 };
-use serde::Deserialize;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::time::Instant;
+use utoipa::{IntoParams, ToSchema};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct ExecuteQueryRequest {
     pub query: String,
+    /// Values bound to `$1`, `$2`, ... in order
+    #[serde(default)]
+    #[schema(value_type = Option<Vec<serde_json::Value>>)]
+    pub params: Option<QueryParams>,
+    /// Run inside this open transaction session instead of autocommit
+    #[serde(default)]
+    pub transaction: Option<String>,
+    /// Run even if the planner's estimate exceeds the cost thresholds, or
+    /// the statement changes data when changes must be confirmed
+    #[serde(default)]
+    #[schema(default = false)]
+    pub confirm: bool,
+    /// Ask before data or schema changes in a transaction, from the
+    /// session's `confirm_dangerous` preference
+    #[serde(skip)]
+    pub confirm_changes: bool,
+}
+
+/// A query to chart, or a table with what to aggregate per group
+#[derive(Deserialize)]
+pub struct ChartRequest {
+    #[serde(default)]
+    pub query: Option<String>,
+    #[serde(default)]
+    pub params: Option<QueryParams>,
+    #[serde(default)]
+    pub confirm: bool,
+    #[serde(default)]
+    pub schema: Option<String>,
+    #[serde(default)]
+    pub table: Option<String>,
+    /// Column whose values become the labels
+    #[serde(default)]
+    pub group_by: Option<String>,
+    /// count (default), sum, avg, min or max
+    #[serde(default)]
+    pub aggregate: Option<String>,
+    /// Column to aggregate; optional for count
+    #[serde(default)]
+    pub column: Option<String>,
+    #[serde(default)]
+    pub limit: Option<u32>,
+}
+
+impl ChartRequest {
+    /// Check the aggregate spec against the table's columns
+    async fn aggregate_spec(
+        &self,
+        state: &AppState,
+        schema: &str,
+        table: &str,
+    ) -> Result<AggregateSpec, AppError> {
+        let existing = duplicate_service::column_names(&state.db_pool, schema, table).await?;
+        let column_exists = |name: &str| {
+            if existing.iter().any(|c| c == name) {
+                Ok(name.to_string())
+            } else {
+                Err(AppError::BadRequest(format!(
+                    "Column '{}' does not exist",
+                    name
+                )))
+            }
+        };
+
+        let group_by = non_empty(&self.group_by)
+            .ok_or_else(|| AppError::BadRequest("group_by is required".to_string()))?;
+        let aggregate = Aggregate::parse(self.aggregate.as_deref().unwrap_or_default())
+            .map_err(AppError::BadRequest)?;
+        let column = non_empty(&self.column).map(column_exists).transpose()?;
+        if column.is_none() && aggregate != Aggregate::Count {
+            return Err(AppError::BadRequest(format!(
+                "{} needs a column",
+                aggregate.as_str()
+            )));
+        }
+
+        Ok(AggregateSpec {
+            group_by: column_exists(group_by)?,
+            aggregate,
+            column,
+            limit: self
+                .limit
+                .unwrap_or(chart_service::DEFAULT_LIMIT)
+                .clamp(1, chart_service::MAX_POINTS),
+        })
+    }
+}
+
+/// A query or a table to pivot, and which of its columns go where
+#[derive(Deserialize)]
+pub struct PivotRequest {
+    #[serde(default)]
+    pub query: Option<String>,
+    #[serde(default)]
+    pub params: Option<QueryParams>,
+    #[serde(default)]
+    pub confirm: bool,
+    #[serde(default)]
+    pub schema: Option<String>,
+    #[serde(default)]
+    pub table: Option<String>,
+    /// Column whose values become the output rows
+    #[serde(default)]
+    pub rows: Option<String>,
+    /// Column whose values become the output columns
+    #[serde(default)]
+    pub columns: Option<String>,
+    /// Column aggregated into the cells; optional for count
+    #[serde(default)]
+    pub values: Option<String>,
+    /// count (default), sum, avg, min or max
+    #[serde(default)]
+    pub aggregate: Option<String>,
+    #[serde(default)]
+    pub limit: Option<u32>,
+}
+
+impl PivotRequest {
+    /// Check the spec, and its columns against `existing` when the source's
+    /// columns are known up front
+    fn spec(&self, existing: Option<&[String]>) -> Result<PivotSpec, String> {
+        let column = |field: &Option<String>, name: &str| -> Result<Option<String>, String> {
+            let Some(column) = non_empty(field) else {
+                return Ok(None);
+            };
+            match existing {
+                Some(existing) if !existing.iter().any(|c| c == column) => {
+                    Err(format!("Column '{}' does not exist", column))
+                }
+                _ => Ok(Some(column.to_string())),
+            }
+            .map_err(|e| format!("{}: {}", name, e))
+        };
+        let rows = column(&self.rows, "rows")?.ok_or("rows is required")?;
+        let columns = column(&self.columns, "columns")?.ok_or("columns is required")?;
+        let values = column(&self.values, "values")?;
+        let aggregate = Aggregate::parse(self.aggregate.as_deref().unwrap_or_default())?;
+        if values.is_none() && aggregate != Aggregate::Count {
+            return Err(format!("{} needs a values column", aggregate.as_str()));
+        }
+
+        Ok(PivotSpec {
+            rows,
+            columns,
+            values,
+            aggregate,
+            limit: self
+                .limit
+                .unwrap_or(pivot_service::DEFAULT_ROW_LIMIT)
+                .clamp(1, pivot_service::MAX_ROW_LIMIT),
+        })
+    }
+}
+
+/// Two queries, or one query before and inside an open transaction, whose
+/// results are compared by `keys`
+#[derive(Deserialize)]
+pub struct CompareQueryRequest {
+    pub left: String,
+    /// Defaults to `left`, to see what the transaction changed
+    #[serde(default)]
+    pub right: Option<String>,
+    /// Comma-separated columns identifying a row in both results
+    pub keys: String,
+    /// Run the right query inside this open transaction session
+    #[serde(default)]
+    pub transaction: Option<String>,
+    /// Sample rows reported per category
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct DownloadParams {
+    pub format: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct FormatQueryRequest {
+    pub query: String,
+}
+
+/// Positional parameters: a JSON array, or its JSON text when sent from the HTML form
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum QueryParams {
+    List(Vec<serde_json::Value>),
+    Encoded(String),
+}
+
+impl QueryParams {
+    /// Convert to bind parameters; blank form text means no parameters
+    pub fn to_params(&self) -> Result<Vec<query_service::QueryParam>, String> {
+        let values = match self {
+            QueryParams::List(values) => values.clone(),
+            QueryParams::Encoded(text) if text.trim().is_empty() => Vec::new(),
+            QueryParams::Encoded(text) => serde_json::from_str(text)
+                .map_err(|e| format!("Parameters must be a JSON array: {}", e))?,
+        };
+        Ok(values
+            .iter()
+            .map(query_service::QueryParam::from_json)
+            .collect())
+    }
+}
+
+/// History search parameters as received in the query string; blank values are ignored
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HistoryQuery {
+    /// Text to search for in the query
+    pub q: Option<String>,
+    /// Executed at or after (RFC 3339 or YYYY-MM-DD)
+    pub from: Option<String>,
+    /// Executed at or before (RFC 3339 or YYYY-MM-DD)
+    pub to: Option<String>,
+    /// Only successful (true) or failed (false) queries
+    #[param(value_type = Option<bool>)]
+    pub success: Option<String>,
+    /// Only queries at least this slow
+    #[param(value_type = Option<u64>)]
+    pub min_duration_ms: Option<String>,
+    /// Only entries with this tag
+    pub tag: Option<String>,
+    /// Only runs of this query fingerprint
+    pub fingerprint: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryExportParams {
+    /// `csv` (default) or `json`
+    #[serde(default)]
+    pub format: String,
+}
+
+/// New tags for a history entry
+#[derive(Debug, Deserialize)]
+pub struct TagsRequest {
+    pub tags: Vec<String>,
+}
+
+impl HistoryQuery {
+    /// Parse the query string values into a filter
+    pub fn to_filter(&self) -> Result<HistoryFilter, String> {
+        let success = non_empty(&self.success)
+            .map(|v| {
+                v.parse::<bool>()
+                    .map_err(|_| format!("Invalid success flag: {}", v))
+            })
+            .transpose()?;
+        let min_duration_ms = non_empty(&self.min_duration_ms)
+            .map(|v| {
+                v.parse::<u64>()
+                    .map_err(|_| format!("Invalid min_duration_ms: {}", v))
+            })
+            .transpose()?;
+
+        Ok(HistoryFilter {
+            search: non_empty(&self.q).map(str::to_string),
+            from: non_empty(&self.from).map(parse_timestamp).transpose()?,
+            to: non_empty(&self.to).map(parse_timestamp).transpose()?,
+            success,
+            min_duration_ms,
+            tag: non_empty(&self.tag).map(str::to_string),
+            fingerprint: non_empty(&self.fingerprint).map(str::to_string),
+        })
+    }
 }
 
-#[derive(Template)]
+#[derive(Template, Serialize, ToSchema)]
 #[template(path = "components/query-results.html")]
+#[schema(as = QueryResults)]
 pub struct QueryResultsTemplate {
     pub columns: Vec<String>,
     pub rows: Vec<Vec<serde_json::Value>>,
     pub row_count: usize,
+    /// Set for INSERT / UPDATE / DELETE without RETURNING
+    pub affected_rows: Option<u64>,
     pub execution_time_ms: Option<u128>,
     pub error: Option<String>,
+    /// Set when only the first rows are shown and the full result is kept
+    /// on disk for download
+    pub download: Option<ResultDownload>,
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "components/query-chart.html")]
+pub struct QueryChartTemplate {
+    #[serde(flatten)]
+    pub chart: ChartData,
+}
+
+impl QueryChartTemplate {
+    /// Bar length as a percentage of the largest value
+    pub fn bar_width(&self, value: &f64) -> f64 {
+        let max = self.chart.max_abs();
+        if max == 0.0 {
+            return 0.0;
+        }
+        (value.abs() * 1000.0 / max).round() / 10.0
+    }
+
+    /// Bar color of the n-th series
+    pub fn series_class(&self, index: &usize) -> &'static str {
+        const CLASSES: [&str; 4] = ["bg-primary", "bg-secondary", "bg-accent", "bg-info"];
+        CLASSES[index % CLASSES.len()]
+    }
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "components/pivot-results.html")]
+pub struct PivotResultsTemplate {
+    #[serde(flatten)]
+    pub pivot: Pivot,
+}
+
+/// One statement of a script with its results
+#[derive(Serialize, ToSchema)]
+pub struct ScriptStatement {
+    pub sql: String,
+    pub result: QueryResultsTemplate,
+}
+
+#[derive(Template, Serialize, ToSchema)]
+#[template(path = "components/script-results.html")]
+#[schema(as = ScriptResults)]
+pub struct ScriptResultsTemplate {
+    pub statements: Vec<ScriptStatement>,
+    /// Statements not run because an earlier one failed
+    pub skipped: usize,
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "components/cost-warning.html")]
+pub struct CostWarningTemplate {
+    pub title: &'static str,
+    /// Why the query was not run, as a sentence
+    pub reason: String,
 }
 
-/// Executes a SQL query and returns results as HTML
+/// Why a query was held back until it is resent with `confirm`
+pub(crate) enum Confirmation {
+    /// Which thresholds the planner's estimate exceeds
+    Cost(String),
+    /// A data or schema change, with changes to be confirmed
+    Change(StatementKind),
+}
+
+impl Confirmation {
+    pub(crate) fn reason(&self) -> String {
+        match self {
+            Confirmation::Cost(reason) => reason.clone(),
+            Confirmation::Change(kind) => format!(
+                "it is a {} statement and your preferences ask to confirm changes",
+                kind.as_str()
+            ),
+        }
+    }
+
+    fn warning(&self) -> CostWarningTemplate {
+        match self {
+            Confirmation::Cost(reason) => CostWarningTemplate {
+                title: "Expensive query",
+                reason: i18n::tr(&format!(
+                    "The planner's {}. Check for a missing filter or index before running it.",
+                    reason
+                )),
+            },
+            Confirmation::Change(kind) => CostWarningTemplate {
+                title: "Confirm change",
+                reason: i18n::tr(&format!(
+                    "This {} statement changes the database. Check it before running it.",
+                    kind.as_str()
+                )),
+            },
+        }
+    }
+}
+
+/// What running a console query produced, before it is rendered
+pub(crate) enum QueryOutcome {
+    Results(QueryResultsTemplate),
+    Script(ScriptResultsTemplate),
+    /// Not run until confirmed
+    NeedsConfirmation(Confirmation),
+}
+
+impl QueryOutcome {
+    /// Render as an HTML fragment, or as JSON with a 409 for unconfirmed queries
+    pub(crate) fn render(self, format: ResponseFormat) -> Result<Response, AppError> {
+        match self {
+            QueryOutcome::Results(results) => format.render(results),
+            QueryOutcome::Script(script) => format.render(script),
+            QueryOutcome::NeedsConfirmation(confirmation) => match format {
+                ResponseFormat::Html => format.render(confirmation.warning()),
+                ResponseFormat::Json => Err(confirmation_required(&confirmation.reason())),
+            },
+        }
+    }
+}
+
+/// Error telling a JSON client to resend an expensive query with `confirm`
+pub(crate) fn confirmation_required(reason: &str) -> AppError {
+    AppError::Conflict(format!(
+        "Query not run: {}. Resend with \"confirm\": true to run it anyway",
+        reason
+    ))
+}
+
+/// Audit event for a console or export query, tagged with its statement kind
+pub(crate) fn query_audit_event(
+    event_type: AuditEventType,
+    client_ip: String,
+    query: &str,
+    resource: &str,
+    error: Option<&str>,
+) -> AuditEvent {
+    let kind = query_service::classify_statement(query).as_str();
+    let event = AuditEvent::new(
+        event_type,
+        client_ip,
+        query.to_string(),
+        resource.to_string(),
+    );
+    match error {
+        Some(e) => event
+            .with_success(false)
+            .with_details(format!("{} statement: {}", kind, e)),
+        None => event.with_details(format!("{} statement", kind)),
+    }
+}
+
+/// Executes a SQL query (form or JSON body) and returns results as HTML or JSON
 pub async fn execute(
+    DemoAware(state): DemoAware,
+    ClientIp(client_ip): ClientIp,
+    _slot: QuerySlot,
+    format: ResponseFormat,
+    session: Session,
+    quota: Quota,
+    request: Request,
+) -> Result<Response, AppError> {
+    let mut payload: ExecuteQueryRequest = match read_payload(request, &state).await {
+        Ok(payload) => payload,
+        Err(rejection) => return Ok(rejection),
+    };
+    payload.confirm_changes = Preferences::load(&session).await.confirm_dangerous;
+
+    let owner = transactions::owner(&session).await?;
+
+    let query = payload.query.clone();
+    let outcome = quota
+        .limit_rows(
+            &query,
+            run_request(&state, client_ip, owner.as_deref(), payload),
+        )
+        .await?;
+    respond(
+        format,
+        keep_large_result(&state, &session, &query, outcome).await,
+    )
+}
+
+/// Keep a result with more rows than the display threshold on disk, cutting
+/// it down to the rows shown, and remember it in the session so reloading
+/// the editor brings it back
+pub(crate) async fn keep_large_result(
+    state: &AppState,
+    session: &Session,
+    query: &str,
+    outcome: Result<QueryOutcome, String>,
+) -> Result<QueryOutcome, String> {
+    let Ok(QueryOutcome::Results(mut results)) = outcome else {
+        return outcome;
+    };
+    if !state.result_store.exceeds(results.rows.len()) {
+        if let Err(e) = session.remove_value(result_store::SESSION_KEY).await {
+            tracing::warn!("Failed to forget the last result: {}", e);
+        }
+        return Ok(QueryOutcome::Results(results));
+    }
+
+    match state
+        .result_store
+        .persist(query, &results.columns, &mut results.rows)
+        .await
+    {
+        Ok(download) => {
+            let last = LastResult {
+                token: download.token.clone(),
+                query: query.to_string(),
+            };
+            if let Err(e) = session.insert(result_store::SESSION_KEY, &last).await {
+                tracing::warn!("Failed to remember the last result: {}", e);
+            }
+            results.download = Some(download);
+        }
+        // Sending every row is slow but still correct
+        Err(e) => tracing::warn!("Failed to keep a large result: {}", e),
+    }
+    Ok(QueryOutcome::Results(results))
+}
+
+/// Decode a JSON or form body, whichever the content type says it is
+async fn read_payload<T: DeserializeOwned>(
+    request: Request,
+    state: &AppState,
+) -> Result<T, Response> {
+    let is_json = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if is_json {
+        Json::<T>::from_request(request, state)
+            .await
+            .map(|Json(payload)| payload)
+            .map_err(IntoResponse::into_response)
+    } else {
+        Form::<T>::from_request(request, state)
+            .await
+            .map(|Form(payload)| payload)
+            .map_err(IntoResponse::into_response)
+    }
+}
+
+/// Charts a query's results (label column then numeric columns) or a
+/// table's groups (`schema`, `table`, `group_by`, `aggregate`, `column`),
+/// returning an HTML bar chart or the label/value series as JSON
+pub async fn chart(
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
+    _slot: QuerySlot,
+    format: ResponseFormat,
+    quota: Quota,
+    request: Request,
+) -> Result<Response, AppError> {
+    let payload: ChartRequest = match read_payload(request, &state).await {
+        Ok(payload) => payload,
+        Err(rejection) => return Ok(rejection),
+    };
+
+    let chart = match (non_empty(&payload.schema), non_empty(&payload.table)) {
+        (Some(schema), Some(table)) => {
+            let spec = payload.aggregate_spec(&state, schema, table).await?;
+            chart_service::aggregate_chart(&state.db_pool, schema, table, &spec).await?
+        }
+        (None, None) => {
+            let Some(query) = non_empty(&payload.query) else {
+                return render_error(
+                    format,
+                    "Either a query or a schema and table is required".to_string(),
+                );
+            };
+            let request = ExecuteQueryRequest {
+                query: query.to_string(),
+                params: payload.params,
+                transaction: None,
+                confirm: payload.confirm,
+                confirm_changes: false,
+            };
+            let outcome = quota
+                .limit_rows(query, run_request(&state, client_ip, None, request))
+                .await?;
+            let results = match outcome {
+                Ok(QueryOutcome::Results(results)) => results,
+                Ok(QueryOutcome::Script(_)) => {
+                    return render_error(format, "Charts need a single statement".to_string())
+                }
+                outcome => return respond(format, outcome),
+            };
+            match chart_service::from_rows(&results.columns, &results.rows) {
+                Ok(chart) => chart,
+                Err(e) => return render_error(format, e),
+            }
+        }
+        _ => {
+            return Err(AppError::BadRequest(
+                "Both schema and table are required for an aggregate chart".to_string(),
+            ))
+        }
+    };
+
+    format.render(QueryChartTemplate { chart })
+}
+
+/// Run an execute request: in its transaction if it names one, otherwise
+/// after checking the planner's cost estimate unless `confirm` is set
+///
+/// `owner` is the caller's transaction owner token; only transactions it
+/// began can be used.
+pub(crate) async fn run_request(
+    state: &AppState,
+    client_ip: String,
+    owner: Option<&str>,
+    payload: ExecuteQueryRequest,
+) -> Result<QueryOutcome, String> {
+    let params = match payload.params.as_ref().map(QueryParams::to_params) {
+        Some(params) => params?,
+        None => Vec::new(),
+    };
+    if let Some(id) = non_empty(&payload.transaction) {
+        let kind = query_service::classify_statement(&payload.query);
+        let is_change = matches!(
+            kind,
+            StatementKind::Write | StatementKind::Ddl | StatementKind::Dcl
+        );
+        if payload.confirm_changes && is_change && !payload.confirm {
+            return Ok(QueryOutcome::NeedsConfirmation(Confirmation::Change(kind)));
+        }
+        return run_in_transaction(state, client_ip, owner, id, payload.query, &params).await;
+    }
+    if !payload.confirm {
+        if let Some(reason) = cost_warning(state, &payload.query, &params).await {
+            return Ok(QueryOutcome::NeedsConfirmation(Confirmation::Cost(reason)));
+        }
+    }
+    run_query(state, client_ip, payload.query, None, &params, "query").await
+}
+
+/// Explains why the query needs confirmation when the planner expects it to
+/// be too expensive; planning failures are left for execution to report
+async fn cost_warning(
+    state: &AppState,
+    query: &str,
+    params: &[query_service::QueryParam],
+) -> Option<String> {
+    let thresholds = state.cost_thresholds;
+    if state.demo.is_some()
+        || !thresholds.is_enabled()
+        || query_service::validate_query(query).is_err()
+        || split_statements(query).len() > 1
+    {
+        return None;
+    }
+
+    let estimate = match query_service::estimate_cost(&state.db_pool, query, params).await {
+        Ok(estimate) => estimate?,
+        Err(e) => {
+            tracing::debug!("Skipping cost check: {}", e);
+            return None;
+        }
+    };
+    thresholds.exceeded(&estimate)
+}
+
+/// Pivots a query's results or a table into a crosstab: a row per `rows`
+/// value, a column per `columns` value and `aggregate(values)` in the cells
+/// (returns HTML or JSON)
+pub async fn pivot(
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
+    _slot: QuerySlot,
+    format: ResponseFormat,
+    quota: Quota,
+    request: Request,
+) -> Result<Response, AppError> {
+    let payload: PivotRequest = match read_payload(request, &state).await {
+        Ok(payload) => payload,
+        Err(rejection) => return Ok(rejection),
+    };
+
+    let pivot = match (non_empty(&payload.schema), non_empty(&payload.table)) {
+        (Some(schema), Some(table)) => {
+            let existing = duplicate_service::column_names(&state.db_pool, schema, table).await?;
+            let spec = payload
+                .spec(Some(&existing))
+                .map_err(AppError::BadRequest)?;
+            pivot_service::pivot_table(&state.db_pool, schema, table, &spec)
+                .await?
+                .map_err(AppError::BadRequest)?
+        }
+        (None, None) => {
+            let Some(query) = non_empty(&payload.query) else {
+                return render_error(
+                    format,
+                    "Either a query or a schema and table is required".to_string(),
+                );
+            };
+            let spec = match payload.spec(None) {
+                Ok(spec) => spec,
+                Err(e) => return render_error(format, e),
+            };
+            let request = ExecuteQueryRequest {
+                query: query.to_string(),
+                params: payload.params,
+                transaction: None,
+                confirm: payload.confirm,
+                confirm_changes: false,
+            };
+            let outcome = quota
+                .limit_rows(query, run_request(&state, client_ip, None, request))
+                .await?;
+            let results = match outcome {
+                Ok(QueryOutcome::Results(results)) => results,
+                Ok(QueryOutcome::Script(_)) => {
+                    return render_error(format, "Pivots need a single statement".to_string())
+                }
+                outcome => return respond(format, outcome),
+            };
+            match pivot_service::pivot_rows(&results.columns, &results.rows, &spec) {
+                Ok(pivot) => pivot,
+                Err(e) => return render_error(format, e),
+            }
+        }
+        _ => {
+            return Err(AppError::BadRequest(
+                "Both schema and table are required to pivot a table".to_string(),
+            ))
+        }
+    };
+
+    format.render(PivotResultsTemplate { pivot })
+}
+
+/// Runs two queries and reports the rows added, removed and changed
+/// between their results, matched by the `keys` columns (returns JSON)
+///
+/// With a `transaction`, the right query runs inside it, so comparing a
+/// query with itself shows what the transaction's statements did to its
+/// rows before they are committed.
+pub async fn compare(
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
+    _slot: QuerySlot,
+    session: Session,
+    quota: Quota,
+    request: Request,
+) -> Result<Response, AppError> {
+    let payload: CompareQueryRequest = match read_payload(request, &state).await {
+        Ok(payload) => payload,
+        Err(rejection) => return Ok(rejection),
+    };
+    let owner = transactions::owner(&session).await?;
+    let keys: Vec<String> = payload
+        .keys
+        .split(',')
+        .map(str::trim)
+        .filter(|k| !k.is_empty())
+        .map(str::to_string)
+        .collect();
+    let transaction = non_empty(&payload.transaction);
+    let right = non_empty(&payload.right).unwrap_or(&payload.left);
+    let (left_name, right_name) = match transaction {
+        Some(_) if right == payload.left => {
+            ("query before the transaction", "query in the transaction")
+        }
+        _ => ("left query", "right query"),
+    };
+
+    let left = quota
+        .limit_rows(
+            &payload.left,
+            compared_results(&state, client_ip.clone(), &payload.left, None, None),
+        )
+        .await?
+        .map_err(|e| AppError::BadRequest(format!("Left query failed: {}", e)))?;
+    let right = quota
+        .limit_rows(
+            right,
+            compared_results(&state, client_ip, right, owner.as_deref(), transaction),
+        )
+        .await?
+        .map_err(|e| AppError::BadRequest(format!("Right query failed: {}", e)))?;
+
+    let diff = data_diff_service::diff_results(
+        ResultRows {
+            name: left_name,
+            columns: &left.columns,
+            rows: &left.rows,
+        },
+        ResultRows {
+            name: right_name,
+            columns: &right.columns,
+            rows: &right.rows,
+        },
+        &keys,
+        payload
+            .limit
+            .unwrap_or(data_diff_service::DEFAULT_SAMPLE_LIMIT),
+    )
+    .map_err(AppError::BadRequest)?;
+
+    Ok(Json(diff).into_response())
+}
+
+/// Run one side of a comparison, which must be a single statement
+async fn compared_results(
+    state: &AppState,
+    client_ip: String,
+    query: &str,
+    owner: Option<&str>,
+    transaction: Option<&str>,
+) -> Result<QueryResultsTemplate, String> {
+    let outcome = match transaction {
+        Some(id) => run_in_transaction(state, client_ip, owner, id, query.to_string(), &[]).await?,
+        None => run_query(state, client_ip, query.to_string(), None, &[], "compare").await?,
+    };
+    match outcome {
+        QueryOutcome::Results(results) => Ok(results),
+        _ => Err("only a single statement can be compared".to_string()),
+    }
+}
+
+/// Reformats SQL for the editor's Format button
+pub async fn format_query(Json(payload): Json<FormatQueryRequest>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "query": format_sql(&payload.query) }))
+}
+
+/// Re-validates and re-executes a query from the history by id (returns HTML or JSON)
+pub async fn rerun_history(
+    Connected(state): Connected,
+    Path(id): Path<String>,
+    ClientIp(client_ip): ClientIp,
+    _slot: QuerySlot,
+    format: ResponseFormat,
+    session: Session,
+    quota: Quota,
+) -> Result<Response, AppError> {
+    let entry = state
+        .query_history
+        .get_by_id(&id)
+        .await
+        .ok_or_else(|| AppError::NotFound(format!("History entry {} not found", id)))?;
+    let outcome = quota
+        .limit_rows(
+            &entry.query,
+            run_query(&state, client_ip, entry.query.clone(), None, &[], "query"),
+        )
+        .await?;
+    respond(
+        format,
+        keep_large_result(&state, &session, &entry.query, outcome).await,
+    )
+}
+
+/// Shows a kept result again, its first rows with the download link (returns
+/// HTML or JSON)
+pub async fn stored_result(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+    format: ResponseFormat,
+) -> Result<Response, AppError> {
+    let stored = load_stored_result(&state, &token).await?;
+    let download = state.result_store.download(&stored);
+    let mut rows = stored.rows;
+    rows.truncate(download.shown_rows);
+    format.render(QueryResultsTemplate {
+        columns: stored.columns,
+        row_count: download.total_rows,
+        rows,
+        affected_rows: None,
+        execution_time_ms: None,
+        error: None,
+        download: Some(download),
+    })
+}
+
+/// Downloads a kept result in full as `?format=` csv (default), json or sql
+pub async fn download_stored_result(
     State(state): State<AppState>,
-    Form(payload): Form<ExecuteQueryRequest>,
-) -> Result<impl IntoResponse, StatusCode> {
+    ClientIp(client_ip): ClientIp,
+    quota: Quota,
+    Path(token): Path<String>,
+    Query(params): Query<DownloadParams>,
+) -> Result<Response, AppError> {
+    let stored = load_stored_result(&state, &token).await?;
+    let format = match params.format.as_deref() {
+        None => ExportFormat::Csv,
+        Some(name) => ExportFormat::from_str(name)
+            .ok_or_else(|| AppError::BadRequest(format!("Unknown format: {}", name)))?,
+    };
+    quota.check_export_allowance(&stored.query).await?;
+    let result = stored.to_query_result();
+    let content = ExportService::export(&result, format).map_err(AppError::Internal)?;
+    quota.record_export(&stored.query, content.len()).await?;
+    state.webhooks.emit(Notification::export_completed(
+        "a stored result",
+        format.extension(),
+        result.row_count,
+        content.len(),
+        &client_ip,
+    ));
+    let disposition = format!(
+        "attachment; filename=\"query_results.{}\"",
+        format.extension()
+    );
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        content,
+    )
+        .into_response())
+}
+
+async fn load_stored_result(state: &AppState, token: &str) -> Result<StoredResult, AppError> {
+    state
+        .result_store
+        .load(token)
+        .await
+        .map_err(AppError::Internal)?
+        .ok_or_else(|| {
+            AppError::NotFound("This result has expired; run the query again".to_string())
+        })
+}
+
+/// Validate, execute, audit and record a console query
+///
+/// `compiled` is the statement actually sent to the server when it differs
+/// from `query` (e.g. after rewriting named placeholders); `query` is what
+/// gets recorded in the history and audit log.
+pub(crate) async fn run_query(
+    state: &AppState,
+    client_ip: String,
+    query: String,
+    compiled: Option<&str>,
+    params: &[query_service::QueryParam],
+    resource: &str,
+) -> Result<QueryOutcome, String> {
     let start = Instant::now();
-    let query = payload.query.clone();
 
     // Validate query
     if let Err(e) = query_service::validate_query(&query) {
+        state
+            .audit_logger
+            .log(query_audit_event(
+                AuditEventType::DangerousQueryDetected,
+                client_ip,
+                &query,
+                resource,
+                Some(&e),
+            ))
+            .await;
+
         let duration = start.elapsed().as_millis() as u64;
         let entry = HistoryEntry::failed(query, duration, e.clone());
+        logging::record_query_id(&entry.id);
         let history = state.query_history.clone();
         // Record failed validation asynchronously
         tokio::spawn(async move {
             history.add(entry).await;
         });
 
-        let template = QueryResultsTemplate {
-            columns: vec![],
-            rows: vec![],
-            row_count: 0,
-            execution_time_ms: None,
-            error: Some(e),
-        };
-        return match template.render() {
-            Ok(html) => Ok(Html(html)),
-            Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-        };
+        return Err(e);
+    }
+
+    // Scripts run statement by statement so each gets its own result
+    if state.demo.is_none() && compiled.is_none() && params.is_empty() {
+        let statements = split_statements(&query);
+        if statements.len() > 1 {
+            return run_script(state, client_ip, statements, resource)
+                .await
+                .map(QueryOutcome::Script);
+        }
     }
 
     // Execute query
-    match query_service::execute_query(&state.db_pool, &query).await {
+    // Box<dyn Error> is not Send, so convert it before awaiting the audit log
+    let result = match &state.demo {
+        Some(demo) => demo.execute(compiled.unwrap_or(&query)).and_then(|result| {
+            query_service::check_row_limit(result.rows.len())
+                .map(|()| result)
+                .map_err(|e| e.to_string())
+        }),
+        None => query_service::execute_query_with_params(
+            &state.db_pool,
+            compiled.unwrap_or(&query),
+            params,
+            state.read_only_mode.is_enabled(),
+        )
+        .await
+        .map_err(|e| e.to_string()),
+    };
+
+    match result {
         Ok(result) => {
+            state
+                .audit_logger
+                .log(query_audit_event(
+                    AuditEventType::QueryExecution,
+                    client_ip,
+                    &query,
+                    resource,
+                    None,
+                ))
+                .await;
+
             let duration = start.elapsed().as_millis() as u64;
-            let row_count = Some(result.row_count as i64);
+            logging::log_slow_query(&query, duration, state.slow_query_threshold_ms);
+            let row_count = Some(result.rows_processed());
             let entry = HistoryEntry::new(query, duration, row_count);
+            logging::record_query_id(&entry.id);
             let history = state.query_history.clone();
             // Record successful query asynchronously
             tokio::spawn(async move {
                 history.add(entry).await;
             });
 
-            let template = QueryResultsTemplate {
+            Ok(QueryOutcome::Results(QueryResultsTemplate {
                 columns: result.columns,
                 rows: result.rows,
                 row_count: result.row_count,
+                affected_rows: result.affected_rows,
                 execution_time_ms: result.execution_time_ms,
                 error: None,
-            };
-            match template.render() {
-                Ok(html) => Ok(Html(html)),
-                Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-            }
+                download: None,
+            }))
         }
-        Err(e) => {
+        Err(error_msg) => {
+            state
+                .audit_logger
+                .log(query_audit_event(
+                    AuditEventType::SqlError,
+                    client_ip,
+                    &query,
+                    resource,
+                    Some(&error_msg),
+                ))
+                .await;
+
             let duration = start.elapsed().as_millis() as u64;
-            let error_msg = e.to_string();
+            logging::log_slow_query(&query, duration, state.slow_query_threshold_ms);
             let entry = HistoryEntry::failed(query, duration, error_msg.clone());
+            logging::record_query_id(&entry.id);
             let history = state.query_history.clone();
             // Record failed query asynchronously
             tokio::spawn(async move {
                 history.add(entry).await;
             });
 
-            let template = QueryResultsTemplate {
-                columns: vec![],
-                rows: vec![],
-                row_count: 0,
-                execution_time_ms: None,
-                error: Some(error_msg),
-            };
-            match template.render() {
-                Ok(html) => Ok(Html(html)),
-                Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-            }
+            Err(error_msg)
         }
     }
 }
 
+/// Runs a statement inside an open transaction session
+async fn run_in_transaction(
+    state: &AppState,
+    client_ip: String,
+    owner: Option<&str>,
+    id: &str,
+    query: String,
+    params: &[query_service::QueryParam],
+) -> Result<QueryOutcome, String> {
+    let resource = format!("transaction:{}", id);
+    let start = Instant::now();
+
+    if let Err(e) = transaction_service::validate_statement(&query) {
+        state
+            .audit_logger
+            .log(query_audit_event(
+                AuditEventType::DangerousQueryDetected,
+                client_ip,
+                &query,
+                &resource,
+                Some(&e),
+            ))
+            .await;
+        state
+            .query_history
+            .add(HistoryEntry::failed(query, 0, e.clone()))
+            .await;
+        return Err(e);
+    }
+
+    let result = match owner {
+        Some(owner) => state.transactions.execute(id, owner, &query, params).await,
+        None => None,
+    };
+    let Some(result) = result else {
+        return Err(format!(
+            "Transaction {} is not open; it may have been committed, rolled back or timed out",
+            id
+        ));
+    };
+    let duration = start.elapsed().as_millis() as u64;
+    logging::log_slow_query(&query, duration, state.slow_query_threshold_ms);
+
+    let (event_type, error, entry) = match &result {
+        Ok(result) => (
+            AuditEventType::QueryExecution,
+            None,
+            HistoryEntry::new(query.clone(), duration, Some(result.rows_processed())),
+        ),
+        Err(e) => (
+            AuditEventType::SqlError,
+            Some(e.as_str()),
+            HistoryEntry::failed(query.clone(), duration, e.clone()),
+        ),
+    };
+    state
+        .audit_logger
+        .log(query_audit_event(
+            event_type, client_ip, &query, &resource, error,
+        ))
+        .await;
+    logging::record_query_id(&entry.id);
+    state.query_history.add(entry).await;
+
+    result.map(|result| {
+        QueryOutcome::Results(QueryResultsTemplate {
+            columns: result.columns,
+            rows: result.rows,
+            row_count: result.row_count,
+            affected_rows: result.affected_rows,
+            execution_time_ms: result.execution_time_ms,
+            error: None,
+            download: None,
+        })
+    })
+}
+
+/// Validates every statement up front, then runs them in order and renders one result per statement
+async fn run_script(
+    state: &AppState,
+    client_ip: String,
+    statements: Vec<String>,
+    resource: &str,
+) -> Result<ScriptResultsTemplate, String> {
+    for (index, statement) in statements.iter().enumerate() {
+        if let Err(e) = query_service::validate_query(statement) {
+            state
+                .audit_logger
+                .log(query_audit_event(
+                    AuditEventType::DangerousQueryDetected,
+                    client_ip,
+                    statement,
+                    resource,
+                    Some(&e),
+                ))
+                .await;
+            state
+                .query_history
+                .add(HistoryEntry::failed(statement.clone(), 0, e.clone()))
+                .await;
+            return Err(format!("Statement {}: {}", index + 1, e));
+        }
+    }
+
+    // Box<dyn Error> is not Send, so convert it before awaiting the audit log
+    let results = query_service::execute_script(
+        &state.db_pool,
+        &statements,
+        state.read_only_mode.is_enabled(),
+    )
+    .await
+    .map_err(|e| e.to_string());
+    let results = results?;
+
+    let skipped = statements.len() - results.len();
+    let mut rendered = Vec::with_capacity(results.len());
+    for statement in results {
+        logging::log_slow_query(
+            &statement.sql,
+            statement.duration_ms,
+            state.slow_query_threshold_ms,
+        );
+        let (event_type, error, entry, template) = match statement.result {
+            Ok(result) => (
+                AuditEventType::QueryExecution,
+                None,
+                HistoryEntry::new(
+                    statement.sql.clone(),
+                    statement.duration_ms,
+                    Some(result.rows_processed()),
+                ),
+                QueryResultsTemplate {
+                    columns: result.columns,
+                    rows: result.rows,
+                    row_count: result.row_count,
+                    affected_rows: result.affected_rows,
+                    execution_time_ms: result.execution_time_ms,
+                    error: None,
+                    download: None,
+                },
+            ),
+            Err(e) => (
+                AuditEventType::SqlError,
+                Some(e.clone()),
+                HistoryEntry::failed(statement.sql.clone(), statement.duration_ms, e.clone()),
+                QueryResultsTemplate {
+                    columns: vec![],
+                    rows: vec![],
+                    row_count: 0,
+                    affected_rows: None,
+                    execution_time_ms: None,
+                    error: Some(e),
+                    download: None,
+                },
+            ),
+        };
+
+        state
+            .audit_logger
+            .log(query_audit_event(
+                event_type,
+                client_ip.clone(),
+                &statement.sql,
+                resource,
+                error.as_deref(),
+            ))
+            .await;
+        // A script's request span keeps the id of its last statement
+        logging::record_query_id(&entry.id);
+        state.query_history.add(entry).await;
+
+        rendered.push(ScriptStatement {
+            sql: statement.sql,
+            result: template,
+        });
+    }
+
+    Ok(ScriptResultsTemplate {
+        statements: rendered,
+        skipped,
+    })
+}
+
+/// Render a query outcome, or its error message
+pub(crate) fn respond(
+    format: ResponseFormat,
+    outcome: Result<QueryOutcome, String>,
+) -> Result<Response, AppError> {
+    match outcome {
+        Ok(outcome) => outcome.render(format),
+        Err(e) => render_error(format, e),
+    }
+}
+
+/// Render the results fragment with an error message, or a 400 for JSON clients
+pub(crate) fn render_error(format: ResponseFormat, error: String) -> Result<Response, AppError> {
+    match format {
+        ResponseFormat::Html => format.render(QueryResultsTemplate {
+            columns: vec![],
+            rows: vec![],
+            row_count: 0,
+            affected_rows: None,
+            execution_time_ms: None,
+            error: Some(error),
+            download: None,
+        }),
+        ResponseFormat::Json => Err(AppError::BadRequest(error)),
+    }
+}
+
 /// Gets recent query history
-pub async fn history(State(state): State<AppState>) -> impl IntoResponse {
-    let entries = state.query_history.get_recent(20).await;
-    Json(entries)
+///
+/// Filters: `q` (text search), `from`/`to` (timestamps), `success`, `min_duration_ms`, `tag`
+pub async fn history(
+    State(state): State<AppState>,
+    Query(query): Query<HistoryQuery>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<Paginated<HistoryEntry>>, AppError> {
+    let filter = query.to_filter().map_err(AppError::BadRequest)?;
+    let entries = state.query_history.search(&filter).await;
+    Ok(Json(Paginated::from_keyed(entries, &params, 20, |e| &e.id)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GroupParams {
+    /// `executions` (default), `total_duration` or `recent`
+    pub sort: Option<String>,
+}
+
+/// Lists the history grouped by query fingerprint, with per-query counts
+/// and durations (returns JSON)
+///
+/// Accepts the same filters as the history listing. The runs of a group are
+/// listed by `GET /api/query/history?fingerprint=...`.
+pub async fn history_groups(
+    State(state): State<AppState>,
+    Query(query): Query<HistoryQuery>,
+    Query(group): Query<GroupParams>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<Paginated<QueryGroup>>, AppError> {
+    let filter = query.to_filter().map_err(AppError::BadRequest)?;
+    let order = match non_empty(&group.sort) {
+        Some(name) => GroupOrder::parse(name).map_err(AppError::BadRequest)?,
+        None => GroupOrder::default(),
+    };
+    let groups = state.query_history.groups(&filter, order).await;
+    Ok(Json(Paginated::from_vec(groups, &params, 20)))
+}
+
+/// Downloads the (optionally filtered) query history as CSV or JSON
+///
+/// Accepts the same filters as the history listing, newest entries first.
+/// Counts against the role's export quotas like any other download.
+pub async fn export_history(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    quota: Quota,
+    Query(query): Query<HistoryQuery>,
+    Query(params): Query<HistoryExportParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let format = match params.format.as_str() {
+        "" => ExportFormat::Csv,
+        f => match ExportFormat::from_str(f) {
+            Some(ExportFormat::Sql) | None => {
+                return Err(AppError::BadRequest(format!(
+                    "Unsupported history export format: {}",
+                    f
+                )))
+            }
+            Some(format) => format,
+        },
+    };
+    let filter = query.to_filter().map_err(AppError::BadRequest)?;
+    quota.check_export_allowance("query history").await?;
+    let entries = state.query_history.search(&filter).await;
+
+    let content = ExportService::export(&ExportService::history_result(&entries), format)
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    quota.record_export("query history", content.len()).await?;
+    state.webhooks.emit(Notification::export_completed(
+        "the query history",
+        format.extension(),
+        entries.len(),
+        content.len(),
+        &client_ip,
+    ));
+    let disposition = format!(
+        "attachment; filename=\"query_history.{}\"",
+        format.extension()
+    );
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        content,
+    ))
+}
+
+/// Replaces the tags on a history entry
+pub async fn set_history_tags(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(payload): Json<TagsRequest>,
+) -> Result<Json<HistoryEntry>, AppError> {
+    let tags = normalize_tags(payload.tags).map_err(AppError::BadRequest)?;
+    state
+        .query_history
+        .set_tags(&id, tags)
+        .await
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound(format!("History entry {} not found", id)))
 }
 
 /// Clears all query history
@@ -130,22 +1411,200 @@ pub async fn history_stats(State(state): State<AppState>) -> impl IntoResponse {
     Json(stats)
 }
 
-#[derive(Template)]
+/// Days and slowest queries in the history analytics when not given
+const DEFAULT_ANALYTICS_DAYS: u32 = 14;
+const DEFAULT_SLOWEST: usize = 10;
+
+#[derive(Debug, Deserialize)]
+pub struct AnalyticsParams {
+    /// Days of activity, ending today (at most 90)
+    pub days: Option<u32>,
+    /// Slowest queries to list (at most 50)
+    pub top: Option<usize>,
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "components/history-analytics.html")]
+pub struct HistoryAnalyticsTemplate {
+    #[serde(flatten)]
+    pub analytics: HistoryAnalytics,
+}
+
+impl HistoryAnalyticsTemplate {
+    /// Height of a day's bar, in percent of the busiest day
+    fn bar_height(&self, queries: &usize) -> f64 {
+        let busiest = self.analytics.days.iter().map(|d| d.queries).max();
+        match busiest {
+            Some(max) if max > 0 => *queries as f64 * 100.0 / max as f64,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Query counts per day, duration percentiles, failure rate over time and
+/// the slowest queries - returns the dashboard chart or JSON
+pub async fn history_analytics(
+    format: ResponseFormat,
+    State(state): State<AppState>,
+    Query(params): Query<AnalyticsParams>,
+) -> Result<Response, AppError> {
+    let days = params.days.unwrap_or(DEFAULT_ANALYTICS_DAYS).clamp(1, 90);
+    let top = params.top.unwrap_or(DEFAULT_SLOWEST).min(50);
+    let analytics = state.query_history.analytics(days, top).await;
+    format.render(HistoryAnalyticsTemplate { analytics })
+}
+
+#[derive(Template, Serialize)]
 #[template(path = "components/recent-queries.html")]
 pub struct RecentQueriesTemplate {
     pub queries: Vec<HistoryEntry>,
 }
 
-/// Recent queries widget - returns HTML
+/// Recent queries widget - returns HTML or JSON
 pub async fn recent_queries_widget(
+    format: ResponseFormat,
     State(state): State<AppState>,
-) -> Result<Html<String>, StatusCode> {
+) -> Result<Response, AppError> {
     let queries = state.query_history.get_recent(5).await;
 
-    let template = RecentQueriesTemplate { queries };
+    format.render(RecentQueriesTemplate { queries })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::query_service::QueryParam;
+
+    #[test]
+    fn test_params_from_json_body() {
+        let payload: ExecuteQueryRequest = serde_json::from_value(serde_json::json!({
+            "query": "SELECT $1, $2, $3",
+            "params": [42, "active", null],
+        }))
+        .unwrap();
+        assert_eq!(
+            payload.params.unwrap().to_params().unwrap(),
+            vec![
+                QueryParam::Int(42),
+                QueryParam::Text("active".to_string()),
+                QueryParam::Null
+            ]
+        );
+    }
+
+    async fn form(body: &'static str) -> ExecuteQueryRequest {
+        let request = Request::builder()
+            .method("POST")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(axum::body::Body::from(body))
+            .unwrap();
+        let Form(payload) = Form::<ExecuteQueryRequest>::from_request(request, &())
+            .await
+            .unwrap();
+        payload
+    }
 
-    template
+    #[tokio::test]
+    async fn test_params_from_form() {
+        let payload = form("query=SELECT+%241&params=%5B1.5%5D").await;
+        assert_eq!(
+            payload.params.unwrap().to_params().unwrap(),
+            vec![QueryParam::Float(1.5)]
+        );
+
+        let blank = form("query=SELECT+1&params=").await;
+        assert!(blank.params.unwrap().to_params().unwrap().is_empty());
+
+        let invalid = form("query=SELECT+%241&params=oops").await;
+        assert!(invalid.params.unwrap().to_params().is_err());
+
+        assert!(form("query=SELECT+1").await.params.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_confirm_flag_from_form() {
+        assert!(form("query=SELECT+1&confirm=true").await.confirm);
+        assert!(!form("query=SELECT+1").await.confirm);
+    }
+
+    #[test]
+    fn test_affected_rows_render() {
+        let html = QueryResultsTemplate {
+            columns: vec![],
+            rows: vec![],
+            row_count: 0,
+            affected_rows: Some(3),
+            execution_time_ms: Some(4),
+            error: None,
+            download: None,
+        }
+        .render()
+        .unwrap();
+        assert!(html.contains("3 rows affected"));
+        assert!(html.contains("Statement executed successfully"));
+    }
+
+    #[test]
+    fn test_unsupported_type_render() {
+        let html = QueryResultsTemplate {
+            columns: vec!["doc".to_string()],
+            rows: vec![vec![crate::services::pg_value::unsupported("tsvector")]],
+            row_count: 1,
+            affected_rows: None,
+            execution_time_ms: None,
+            error: None,
+            download: None,
+        }
         .render()
-        .map(Html)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+        .unwrap();
+        assert!(html.contains(">tsvector</span>"));
+    }
+
+    #[test]
+    fn test_cost_warning_render() {
+        let html = Confirmation::Cost("estimated cost 150000 exceeds 100000".to_string())
+            .warning()
+            .render()
+            .unwrap();
+        assert!(html.contains("Expensive query"));
+        assert!(html.contains("estimated cost 150000 exceeds 100000. Check for a missing filter"));
+        assert!(html.contains(r#"hx-vals='{"confirm": "true"}'"#));
+
+        let change = Confirmation::Change(StatementKind::Ddl);
+        assert!(change
+            .warning()
+            .render()
+            .unwrap()
+            .contains("Confirm change"));
+        assert_eq!(
+            change.reason(),
+            "it is a ddl statement and your preferences ask to confirm changes"
+        );
+    }
+
+    #[test]
+    fn test_script_results_render() {
+        let html = ScriptResultsTemplate {
+            statements: vec![ScriptStatement {
+                sql: "SELECT '<b>'".to_string(),
+                result: QueryResultsTemplate {
+                    columns: vec![],
+                    rows: vec![],
+                    row_count: 0,
+                    affected_rows: None,
+                    execution_time_ms: None,
+                    error: Some("relation \"missing\" does not exist".to_string()),
+                    download: None,
+                },
+            }],
+            skipped: 2,
+        }
+        .render()
+        .unwrap();
+        assert!(html.contains("Statement 1"));
+        assert!(html.contains("SELECT &#39;&#60;b&#62;&#39;"));
+        assert!(!html.contains("<b>"));
+        assert!(html.contains("relation &#34;missing&#34; does not exist"));
+        assert!(html.contains("2 remaining statements were not run"));
+    }
 }
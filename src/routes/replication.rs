@@ -0,0 +1,108 @@
+// Logical replication routes
+// Lists publications and subscriptions of the current database and creates,
+// alters and drops publications
+
+use crate::error::AppError;
+use crate::i18n::filters;
+use crate::middleware::client_ip::ClientIp;
+use crate::middleware::connection::Connected;
+use crate::middleware::response_format::ResponseFormat;
+use crate::services::audit_service::{AuditEvent, AuditEventType};
+use crate::services::replication_service::{
+    self, AlterPublicationRequest, CreatePublicationRequest, Publication, Subscription,
+};
+use askama::Template;
+use axum::{extract::Path, http::StatusCode, response::Response, Json};
+use serde::Serialize;
+use serde_json::json;
+
+#[derive(Template, Serialize)]
+#[template(path = "components/replication.html")]
+pub struct ReplicationTemplate {
+    pub publications: Vec<Publication>,
+    pub subscriptions: Vec<Subscription>,
+}
+
+fn publication_event(client_ip: String, action: &str, name: &str) -> AuditEvent {
+    AuditEvent::new(
+        AuditEventType::SchemaModification,
+        client_ip,
+        action.to_string(),
+        format!("publication:{}", name),
+    )
+}
+
+/// Publications with their tables, and subscriptions with their worker status
+pub async fn replication_overview(
+    format: ResponseFormat,
+    Connected(state): Connected,
+) -> Result<Response, AppError> {
+    let publications = replication_service::list_publications(&state.db_pool).await?;
+    let subscriptions = replication_service::list_subscriptions(&state.db_pool).await?;
+
+    format.render(ReplicationTemplate {
+        publications,
+        subscriptions,
+    })
+}
+
+/// Create a publication
+pub async fn create_publication(
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
+    Json(payload): Json<CreatePublicationRequest>,
+) -> Result<(StatusCode, Json<serde_json::Value>), AppError> {
+    let result = replication_service::create_publication(&state.db_pool, &payload).await;
+    state
+        .audit_logger
+        .log_result(
+            publication_event(client_ip, "CREATE PUBLICATION", &payload.name),
+            &result,
+        )
+        .await;
+
+    result
+        .map(|msg| (StatusCode::CREATED, Json(json!({ "message": msg }))))
+        .map_err(AppError::BadRequest)
+}
+
+/// Add or drop tables and change the published operations
+pub async fn alter_publication(
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
+    Path(name): Path<String>,
+    Json(payload): Json<AlterPublicationRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let result = replication_service::alter_publication(&state.db_pool, &name, &payload).await;
+    state
+        .audit_logger
+        .log_result(
+            publication_event(client_ip, "ALTER PUBLICATION", &name),
+            &result,
+        )
+        .await;
+
+    result
+        .map(|msg| Json(json!({ "message": msg })))
+        .map_err(AppError::BadRequest)
+}
+
+/// Drop a publication
+pub async fn drop_publication(
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
+    Path(name): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let result = replication_service::drop_publication(&state.db_pool, &name).await;
+    state
+        .audit_logger
+        .log_result(
+            publication_event(client_ip, "DROP PUBLICATION", &name),
+            &result,
+        )
+        .await;
+
+    result
+        .map(|msg| Json(json!({ "message": msg })))
+        .map_err(AppError::BadRequest)
+}
@@ -0,0 +1,193 @@
+// Restore routes
+// Accepts an uploaded dump, restores it as a background job and reports the
+// job's progress and log, also as a server-sent event stream
+
+use crate::error::AppError;
+use crate::middleware::client_ip::ClientIp;
+use crate::middleware::connection::Connected;
+use crate::services::audit_service::{AuditEvent, AuditEventType};
+use crate::services::restore_service::{JobStatus, RestoreJob, RestoreOptions};
+use crate::AppState;
+use axum::body::Body;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::Json;
+use futures::stream::{self, Stream, StreamExt};
+use std::convert::Infallible;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+/// How often the event stream checks the job
+const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Restore the request body (a custom-format dump or a SQL file) into a
+/// database; answers 202 with the job once the upload is stored
+pub async fn upload_restore(
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
+    Query(options): Query<RestoreOptions>,
+    body: Body,
+) -> Result<(StatusCode, Json<RestoreJob>), AppError> {
+    if state.read_only_mode.is_enabled() {
+        return Err(AppError::Forbidden(
+            "Restoring dumps is not allowed in read-only mode".to_string(),
+        ));
+    }
+
+    let mut connect = (*state.db_pool.connect_options()).clone();
+    if let Some(database) = options.database.as_deref().filter(|d| !d.is_empty()) {
+        let exists: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM pg_catalog.pg_database \
+             WHERE datname = $1 AND datallowconn)",
+        )
+        .bind(database)
+        .fetch_one(state.db_pool.as_ref())
+        .await?;
+        if !exists {
+            return Err(AppError::NotFound(format!(
+                "Database '{}' not found",
+                database
+            )));
+        }
+        connect = connect.database(database);
+    }
+
+    let path = std::env::temp_dir().join(format!("pgadmin-restore-{}", uuid::Uuid::new_v4()));
+    if let Err(e) = receive_upload(body, &path, state.restore_jobs.max_upload_bytes()).await {
+        let _ = tokio::fs::remove_file(&path).await;
+        return Err(e);
+    }
+
+    let audit_logger = state.audit_logger.clone();
+    let metadata_cache = state.metadata_cache.clone();
+    let same_database = options.database.as_deref().is_none_or(str::is_empty);
+    let job = state
+        .restore_jobs
+        .start(path, connect, options, move |job: RestoreJob| async move {
+            let result = match job.error {
+                Some(e) => Err(e),
+                None => Ok(()),
+            };
+            audit_logger
+                .log_result(
+                    AuditEvent::new(
+                        AuditEventType::SchemaModification,
+                        client_ip,
+                        format!("RESTORE {}", job.file_name),
+                        job.database,
+                    ),
+                    &result,
+                )
+                .await;
+            if same_database {
+                metadata_cache.invalidate_all().await;
+            }
+        })
+        .await
+        .map_err(AppError::BadRequest)?;
+
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+/// Stream the request body into a file, refusing more than `limit` bytes
+async fn receive_upload(body: Body, path: &std::path::Path, limit: u64) -> Result<(), AppError> {
+    let write_failed =
+        |e: std::io::Error| AppError::Internal(format!("Failed to store upload: {}", e));
+    let mut file = tokio::fs::File::create(path).await.map_err(write_failed)?;
+    let mut received = 0u64;
+    let mut chunks = body.into_data_stream();
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk.map_err(|e| AppError::BadRequest(format!("Upload failed: {}", e)))?;
+        received += chunk.len() as u64;
+        if received > limit {
+            return Err(AppError::BadRequest(format!(
+                "Upload is larger than the {} MB limit",
+                limit / (1024 * 1024)
+            )));
+        }
+        file.write_all(&chunk).await.map_err(write_failed)?;
+    }
+    file.flush().await.map_err(write_failed)?;
+    if received == 0 {
+        return Err(AppError::BadRequest("The upload is empty".to_string()));
+    }
+    Ok(())
+}
+
+/// Running and recent restore jobs, newest first
+pub async fn list_jobs(State(state): State<AppState>) -> Json<Vec<RestoreJob>> {
+    Json(state.restore_jobs.list().await)
+}
+
+/// One job with its log
+pub async fn get_job(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<RestoreJob>, AppError> {
+    state
+        .restore_jobs
+        .get(&id)
+        .await
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound(format!("Restore job '{}' not found", id)))
+}
+
+/// Follow a job: `log` events carry new log lines, `progress` events the job
+/// without its log, and a final `done` event the finished job
+pub async fn job_events(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    if state.restore_jobs.get(&id).await.is_none() {
+        return Err(AppError::NotFound(format!(
+            "Restore job '{}' not found",
+            id
+        )));
+    }
+
+    // State: log lines already sent, and whether this is the first check
+    let events = stream::unfold(Some((0usize, true)), move |next| {
+        let state = state.clone();
+        let id = id.clone();
+        async move {
+            let (seen, first) = next?;
+            if !first {
+                tokio::time::sleep(EVENT_POLL_INTERVAL).await;
+            }
+            let Some(job) = state.restore_jobs.get(&id).await else {
+                return Some((Vec::new(), None));
+            };
+
+            let mut events = Vec::new();
+            let lines = job.log_since(seen);
+            if !lines.is_empty() {
+                events.push(json_event("log", &lines));
+            }
+            let written = job.log_written();
+            let running = job.status == JobStatus::Running;
+            let summary = RestoreJob {
+                log: Vec::new(),
+                ..job
+            };
+            if !running {
+                events.push(json_event("done", &summary));
+                return Some((events, None));
+            }
+            if first || written > seen {
+                events.push(json_event("progress", &summary));
+            }
+            Some((events, Some((written, false))))
+        }
+    })
+    .flat_map(|events| stream::iter(events.into_iter().map(Ok)));
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+fn json_event<T: serde::Serialize>(name: &str, data: &T) -> Event {
+    Event::default()
+        .event(name)
+        .json_data(data)
+        .unwrap_or_default()
+}
@@ -0,0 +1,179 @@
+// Saved query routes
+// CRUD for named queries shown in the query editor sidebar, and running them
+// with bound placeholder values
+
+use crate::error::AppError;
+use crate::middleware::client_ip::ClientIp;
+use crate::middleware::connection::Connected;
+use crate::middleware::query_limit::QuerySlot;
+use crate::middleware::quota::Quota;
+use crate::middleware::response_format::ResponseFormat;
+use crate::middleware::session::Session;
+use crate::routes::audit::non_empty;
+use crate::routes::query::{keep_large_result, render_error, respond, run_query};
+use crate::services::saved_query_service::{
+    compile_placeholders, SavedQuery, SavedQueryFilter, SavedQueryInput,
+};
+use crate::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Response,
+    Json,
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+
+/// Saved query filters as received in the query string; blank values are ignored
+#[derive(Debug, Default, Deserialize)]
+pub struct SavedQueryListQuery {
+    pub tag: Option<String>,
+    pub folder: Option<String>,
+}
+
+impl SavedQueryListQuery {
+    pub fn to_filter(&self) -> SavedQueryFilter {
+        SavedQueryFilter {
+            tag: non_empty(&self.tag).map(str::to_string),
+            folder: non_empty(&self.folder).map(str::to_string),
+        }
+    }
+}
+
+/// Values for a saved query's named placeholders
+#[derive(Debug, Default, Deserialize)]
+pub struct ExecuteSavedRequest {
+    #[serde(default)]
+    pub params: HashMap<String, serde_json::Value>,
+}
+
+/// List saved queries, ordered by name, optionally filtered by `tag` and `folder`
+pub async fn list_saved(
+    State(state): State<AppState>,
+    Query(query): Query<SavedQueryListQuery>,
+) -> Json<Vec<SavedQuery>> {
+    Json(state.saved_queries.list(&query.to_filter()).await)
+}
+
+/// Tags used in the history and saved queries, and saved query folders, with counts
+pub async fn list_tags(State(state): State<AppState>) -> Json<serde_json::Value> {
+    Json(json!({
+        "saved_tags": state.saved_queries.tag_counts().await,
+        "history_tags": state.query_history.tag_counts().await,
+        "folders": state.saved_queries.folder_counts().await,
+    }))
+}
+
+/// Get a single saved query
+pub async fn get_saved(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<SavedQuery>, StatusCode> {
+    state
+        .saved_queries
+        .get(&id)
+        .await
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Save a new query
+pub async fn create_saved(
+    State(state): State<AppState>,
+    Json(input): Json<SavedQueryInput>,
+) -> Result<(StatusCode, Json<SavedQuery>), (StatusCode, Json<serde_json::Value>)> {
+    let input = input.normalize().map_err(bad_request)?;
+    state
+        .saved_queries
+        .create(input)
+        .await
+        .map(|query| (StatusCode::CREATED, Json(query)))
+        .map_err(storage_error)
+}
+
+/// Update the name, description and SQL of a saved query
+pub async fn update_saved(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(input): Json<SavedQueryInput>,
+) -> Result<Json<SavedQuery>, (StatusCode, Json<serde_json::Value>)> {
+    let input = input.normalize().map_err(bad_request)?;
+    match state.saved_queries.update(&id, input).await {
+        Ok(Some(query)) => Ok(Json(query)),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("Saved query {} not found", id) })),
+        )),
+        Err(e) => Err(storage_error(e)),
+    }
+}
+
+/// Delete a saved query
+pub async fn delete_saved(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    match state.saved_queries.delete(&id).await {
+        Ok(true) => Ok(Json(json!({ "message": "Saved query deleted" }))),
+        Ok(false) => Err(StatusCode::NOT_FOUND),
+        Err(e) => Err(storage_error(e).0),
+    }
+}
+
+/// Run a saved query with its placeholders bound to the supplied values (returns HTML or JSON)
+#[allow(clippy::too_many_arguments)]
+pub async fn execute_saved(
+    Connected(state): Connected,
+    Path(id): Path<String>,
+    ClientIp(client_ip): ClientIp,
+    _slot: QuerySlot,
+    format: ResponseFormat,
+    session: Session,
+    quota: Quota,
+    Json(payload): Json<ExecuteSavedRequest>,
+) -> Result<Response, AppError> {
+    let saved = state
+        .saved_queries
+        .get(&id)
+        .await
+        .ok_or_else(|| AppError::NotFound(format!("Saved query {} not found", id)))?;
+    let compiled = match compile_placeholders(&saved.sql) {
+        Ok(compiled) => compiled,
+        Err(e) => return render_error(format, e),
+    };
+    let params = match compiled.bind(&payload.params) {
+        Ok(params) => params,
+        Err(e) => return render_error(format, e),
+    };
+
+    let outcome = quota
+        .limit_rows(
+            &saved.sql,
+            run_query(
+                &state,
+                client_ip,
+                saved.sql.clone(),
+                Some(&compiled.sql),
+                &params,
+                &format!("saved_query:{}", saved.id),
+            ),
+        )
+        .await?;
+    respond(
+        format,
+        keep_large_result(&state, &session, &saved.sql, outcome).await,
+    )
+}
+
+fn bad_request(error: String) -> (StatusCode, Json<serde_json::Value>) {
+    (StatusCode::BAD_REQUEST, Json(json!({ "error": error })))
+}
+
+fn storage_error(error: String) -> (StatusCode, Json<serde_json::Value>) {
+    tracing::error!("{}", error);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({ "error": error })),
+    )
+}
@@ -0,0 +1,120 @@
+// Schedule routes
+// CRUD for scheduled saved queries, their run history, and manual runs
+
+use crate::models::{PageParams, Paginated};
+use crate::services::scheduler_service::{Schedule, ScheduleInput, ScheduleRun};
+use crate::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde_json::json;
+
+type ApiError = (StatusCode, Json<serde_json::Value>);
+
+/// List schedules, ordered by name
+pub async fn list_schedules(State(state): State<AppState>) -> Json<Vec<Schedule>> {
+    Json(state.scheduler.store.list().await)
+}
+
+/// Get a single schedule
+pub async fn get_schedule(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Schedule>, StatusCode> {
+    state
+        .scheduler
+        .store
+        .get(&id)
+        .await
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Create a schedule for a saved query
+pub async fn create_schedule(
+    State(state): State<AppState>,
+    Json(input): Json<ScheduleInput>,
+) -> Result<(StatusCode, Json<Schedule>), ApiError> {
+    let input = state.scheduler.validate(input).await.map_err(bad_request)?;
+    state
+        .scheduler
+        .store
+        .create(input)
+        .await
+        .map(|schedule| (StatusCode::CREATED, Json(schedule)))
+        .map_err(storage_error)
+}
+
+/// Update a schedule
+pub async fn update_schedule(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(input): Json<ScheduleInput>,
+) -> Result<Json<Schedule>, ApiError> {
+    let input = state.scheduler.validate(input).await.map_err(bad_request)?;
+    match state.scheduler.store.update(&id, input).await {
+        Ok(Some(schedule)) => Ok(Json(schedule)),
+        Ok(None) => Err(not_found(&id)),
+        Err(e) => Err(storage_error(e)),
+    }
+}
+
+/// Delete a schedule and its run history
+pub async fn delete_schedule(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    match state.scheduler.store.delete(&id).await {
+        Ok(true) => Ok(Json(json!({ "message": "Schedule deleted" }))),
+        Ok(false) => Err(not_found(&id)),
+        Err(e) => Err(storage_error(e)),
+    }
+}
+
+/// Run history of a schedule, newest first
+pub async fn schedule_runs(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<Paginated<ScheduleRun>>, StatusCode> {
+    if state.scheduler.store.get(&id).await.is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let runs = state.scheduler.store.runs(&id).await;
+    Ok(Json(Paginated::from_vec(runs, &params, 20)))
+}
+
+/// Run a schedule immediately, outside its cron expression
+pub async fn run_schedule(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<ScheduleRun>, ApiError> {
+    let schedule = state
+        .scheduler
+        .store
+        .get(&id)
+        .await
+        .ok_or_else(|| not_found(&id))?;
+    Ok(Json(state.scheduler.run(&schedule).await))
+}
+
+fn not_found(id: &str) -> ApiError {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({ "error": format!("Schedule {} not found", id) })),
+    )
+}
+
+fn bad_request(error: String) -> ApiError {
+    (StatusCode::BAD_REQUEST, Json(json!({ "error": error })))
+}
+
+fn storage_error(error: String) -> ApiError {
+    tracing::error!("{}", error);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({ "error": error })),
+    )
+}
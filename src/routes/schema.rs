@@ -1,46 +1,162 @@
 // Schema routes
 // Handles routes for database schema inspection
 
+use crate::error::AppError;
+use crate::i18n::filters;
+use crate::middleware::connection::{Connected, DemoAware};
+use crate::middleware::response_format::ResponseFormat;
 use crate::services::schema_service;
-use crate::AppState;
 use askama::Template;
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::{Html, IntoResponse},
+    extract::{Path, Query},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
+use std::hash::{DefaultHasher, Hash, Hasher};
 
-#[derive(Template)]
+#[derive(serde::Deserialize)]
+pub struct SchemaTreeQuery {
+    /// `single` fetches the whole tree in one query; otherwise schemas are
+    /// introspected concurrently
+    pub mode: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct AutocompleteQuery {
+    /// Limit tables and functions to one schema
+    pub schema: Option<String>,
+}
+
+#[derive(Template, serde::Serialize)]
 #[template(path = "components/schema-list.html")]
 pub struct SchemaListTemplate {
     pub schemas: Vec<crate::models::Schema>,
 }
 
-/// Lists all schemas in the current database (returns HTML)
-pub async fn list_schemas(State(state): State<AppState>) -> Result<impl IntoResponse, StatusCode> {
-    let schemas = schema_service::list_schemas(&state.db_pool)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+/// Lists all schemas in the current database (returns HTML or JSON)
+pub async fn list_schemas(
+    format: ResponseFormat,
+    DemoAware(state): DemoAware,
+) -> Result<Response, AppError> {
+    let schemas = match &state.demo {
+        Some(demo) => demo.schemas(),
+        None => schema_service::list_schemas(&state.db_pool).await?,
+    };
 
-    let template = SchemaListTemplate { schemas };
-    match template.render() {
-        Ok(html) => Ok(Html(html)),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
+    format.render(SchemaListTemplate { schemas })
 }
 
 /// Gets details about a specific schema
 pub async fn schema_details(
     Path(schema_name): Path<String>,
-    State(state): State<AppState>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let tables = schema_service::list_tables(&state.db_pool, &schema_name)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    DemoAware(state): DemoAware,
+) -> Result<impl IntoResponse, AppError> {
+    let tables = match &state.demo {
+        Some(demo) => demo.tables(&schema_name),
+        None => schema_service::list_tables(&state.db_pool, &schema_name).await?,
+    };
 
     Ok(Json(serde_json::json!({
         "name": schema_name,
         "tables": tables
     })))
 }
+
+/// Gets every schema with its tables, views and functions (returns JSON)
+pub async fn schema_tree(
+    DemoAware(state): DemoAware,
+    Query(query): Query<SchemaTreeQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    if let Some(demo) = &state.demo {
+        return Ok(Json(demo.tree()));
+    }
+    let tree = match query.mode.as_deref() {
+        Some("single") => schema_service::get_schema_tree_single_query(&state.db_pool).await,
+        _ => schema_service::get_schema_tree(&state.db_pool).await,
+    }?;
+
+    Ok(Json(tree))
+}
+
+/// Gets schemas, tables with columns, functions and keywords for editor
+/// completion (JSON, revalidated with an ETag)
+pub async fn autocomplete(
+    Connected(state): Connected,
+    Query(query): Query<AutocompleteQuery>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let schema = query.schema.as_deref().filter(|s| !s.is_empty());
+    let metadata = state
+        .metadata_cache
+        .autocomplete(&state.db_pool, schema)
+        .await?;
+
+    let body =
+        serde_json::to_vec(metadata.as_ref()).map_err(|e| AppError::Internal(e.to_string()))?;
+    let etag = etag(&body);
+    let cache_headers = [
+        (header::ETAG, etag.clone()),
+        (header::CACHE_CONTROL, "private, no-cache".to_string()),
+    ];
+
+    if if_none_match(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+    }
+
+    Ok((
+        cache_headers,
+        [(header::CONTENT_TYPE, "application/json")],
+        body,
+    )
+        .into_response())
+}
+
+/// Strong ETag derived from the response body
+fn etag(body: &[u8]) -> String {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+/// Whether the client's If-None-Match header already names this ETag
+fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| {
+            v.split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/"))
+                .any(|tag| tag == etag || tag == "*")
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_etag_is_stable_and_content_sensitive() {
+        assert_eq!(etag(b"{}"), etag(b"{}"));
+        assert_ne!(etag(b"{}"), etag(b"[]"));
+        assert!(etag(b"{}").starts_with('"') && etag(b"{}").ends_with('"'));
+    }
+
+    #[test]
+    fn test_if_none_match() {
+        let tag = etag(b"{}");
+        let mut headers = HeaderMap::new();
+        assert!(!if_none_match(&headers, &tag));
+
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_str(&tag).unwrap());
+        assert!(if_none_match(&headers, &tag));
+
+        let list = format!("\"other\", W/{}", tag);
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_str(&list).unwrap());
+        assert!(if_none_match(&headers, &tag));
+
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("\"other\""));
+        assert!(!if_none_match(&headers, &tag));
+    }
+}
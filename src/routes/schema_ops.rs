@@ -1,63 +1,317 @@
 // Schema operations routes
 // Handles DDL operations like CREATE/DROP tables, views, indexes
 
+use crate::error::AppError;
+use crate::middleware::client_ip::ClientIp;
+use crate::middleware::connection::Connected;
+use crate::services::audit_service::{AuditEvent, AuditEventType};
+use crate::services::csv_import_service::{self, CsvImportRequest};
+use crate::services::partition_service::{
+    self, ConvertRequest, CreatePartitionedRequest, ExtendRequest, PartitionPlan,
+};
 use crate::services::schema_ops_service::{
-    CreateIndexRequest, CreateTableRequest, DropObjectRequest, SchemaOpsService,
+    CloneTableRequest, CommentRequest, CreateIndexRequest, CreateTableRequest, DropObjectRequest,
+    SchemaOpsService,
 };
 use crate::AppState;
-use axum::{extract::State, http::StatusCode, Json};
+use axum::Json;
+
+fn schema_event(client_ip: String, action: String, resource: String) -> AuditEvent {
+    AuditEvent::new(
+        AuditEventType::SchemaModification,
+        client_ip,
+        action,
+        resource,
+    )
+}
 
 /// Create a new table
 pub async fn create_table(
-    State(state): State<AppState>,
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
     Json(payload): Json<CreateTableRequest>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    SchemaOpsService::create_table(&state.db_pool, &payload)
+) -> Result<Json<serde_json::Value>, AppError> {
+    let result = SchemaOpsService::create_table(&state.db_pool, &payload).await;
+    state
+        .audit_logger
+        .log_result(
+            schema_event(
+                client_ip,
+                "CREATE TABLE".to_string(),
+                format!("{}.{}", payload.schema, payload.table_name),
+            ),
+            &result,
+        )
+        .await;
+    let msg = result.map_err(AppError::BadRequest)?;
+    state.metadata_cache.invalidate(&payload.schema).await;
+
+    Ok(Json(serde_json::json!({ "message": msg })))
+}
+
+/// Create a table from an uploaded CSV file
+///
+/// Without `confirm` this only returns the proposed table (inferred column
+/// names and types, the `CREATE TABLE` statement and a few sample rows).
+/// Resend with `confirm: true`, optionally with edited `columns`, to create
+/// the table and copy the rows in.
+pub async fn import_csv(
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
+    Json(payload): Json<CsvImportRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let proposal = csv_import_service::propose(&payload).map_err(AppError::BadRequest)?;
+    if !payload.confirm {
+        return Ok(Json(serde_json::json!({ "proposal": proposal })));
+    }
+
+    let result = csv_import_service::import(&state.db_pool, &payload).await;
+    state
+        .audit_logger
+        .log_result(
+            schema_event(
+                client_ip,
+                format!("CREATE TABLE FROM CSV ({} rows)", proposal.row_count),
+                format!("{}.{}", payload.schema, payload.table_name),
+            ),
+            &result,
+        )
+        .await;
+    let copied = result.map_err(AppError::BadRequest)?;
+    state.metadata_cache.invalidate(&payload.schema).await;
+
+    Ok(Json(serde_json::json!({
+        "message": format!(
+            "Table {}.{} created with {} rows",
+            payload.schema, payload.table_name, copied
+        ),
+        "rows": copied,
+    })))
+}
+
+/// Apply a confirmed partitioning plan and record it in the audit log
+async fn apply_partition_plan(
+    state: &AppState,
+    client_ip: String,
+    action: String,
+    plan: PartitionPlan,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let result = partition_service::apply(&state.db_pool, &plan).await;
+    state
+        .audit_logger
+        .log_result(
+            schema_event(
+                client_ip,
+                action,
+                format!("{}.{}", plan.schema, plan.table_name),
+            ),
+            &result,
+        )
+        .await;
+    result.map_err(AppError::BadRequest)?;
+    state.metadata_cache.invalidate(&plan.schema).await;
+
+    Ok(Json(serde_json::json!({
+        "message": format!(
+            "Created {} partitions of {}.{}",
+            plan.partitions.len(),
+            plan.schema,
+            plan.table_name
+        ),
+        "plan": plan,
+    })))
+}
+
+/// Create a range-partitioned table with its first partitions
+///
+/// Without `confirm` this only returns the plan: the partitions and the
+/// statements that would create them. Resend with `confirm: true` to run it.
+pub async fn create_partitioned_table(
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
+    Json(payload): Json<CreatePartitionedRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let today = chrono::Utc::now().date_naive();
+    let plan = partition_service::plan_create(&payload, today).map_err(AppError::BadRequest)?;
+    partition_service::check_create(&state.db_pool, &payload)
         .await
-        .map(|msg| Json(serde_json::json!({ "message": msg })))
-        .map_err(|_| StatusCode::BAD_REQUEST)
+        .map_err(AppError::BadRequest)?;
+    if !payload.confirm {
+        return Ok(Json(serde_json::json!({ "plan": plan })));
+    }
+    let action = format!("CREATE PARTITIONED TABLE BY RANGE ({})", plan.partition_key);
+    apply_partition_plan(&state, client_ip, action, plan).await
+}
+
+/// Convert a table into a range-partitioned one
+///
+/// Without `confirm` this only returns the plan. Once confirmed, the table is
+/// renamed to `<table>_unpartitioned` and its rows are copied into a
+/// partitioned table of the original name, in one transaction.
+pub async fn convert_to_partitioned(
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
+    Json(payload): Json<ConvertRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let source = partition_service::convert_source(&state.db_pool, &payload)
+        .await
+        .map_err(AppError::BadRequest)?;
+    let today = chrono::Utc::now().date_naive();
+    let plan =
+        partition_service::plan_convert(&payload, &source, today).map_err(AppError::BadRequest)?;
+    if !payload.confirm {
+        return Ok(Json(serde_json::json!({ "plan": plan })));
+    }
+    let action = format!("CONVERT TO PARTITIONED BY RANGE ({})", plan.partition_key);
+    apply_partition_plan(&state, client_ip, action, plan).await
+}
+
+/// Create the next partitions of a range-partitioned table
+///
+/// Without `confirm` this only returns the plan, which is empty when enough
+/// future partitions exist.
+pub async fn extend_partitions(
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
+    Json(payload): Json<ExtendRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let source = partition_service::extend_source(&state.db_pool, &payload)
+        .await
+        .map_err(AppError::BadRequest)?;
+    let today = chrono::Utc::now().date_naive();
+    let plan =
+        partition_service::plan_extend(&payload, &source, today).map_err(AppError::BadRequest)?;
+    if !payload.confirm || plan.statements.is_empty() {
+        return Ok(Json(serde_json::json!({ "plan": plan })));
+    }
+    let action = format!("CREATE PARTITIONS ({})", plan.partitions.len());
+    apply_partition_plan(&state, client_ip, action, plan).await
 }
 
 /// Drop a table, view, or other object
 pub async fn drop_object(
-    State(state): State<AppState>,
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
     Json(payload): Json<DropObjectRequest>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    SchemaOpsService::drop_object(&state.db_pool, &payload)
-        .await
-        .map(|msg| Json(serde_json::json!({ "message": msg })))
-        .map_err(|_| StatusCode::BAD_REQUEST)
+) -> Result<Json<serde_json::Value>, AppError> {
+    let result = SchemaOpsService::drop_object(&state.db_pool, &payload).await;
+    state
+        .audit_logger
+        .log_result(
+            schema_event(
+                client_ip,
+                format!(
+                    "DROP {}{}",
+                    payload.object_type.to_uppercase(),
+                    if payload.cascade { " CASCADE" } else { "" }
+                ),
+                format!("{}.{}", payload.schema, payload.object_name),
+            ),
+            &result,
+        )
+        .await;
+    let msg = result.map_err(AppError::BadRequest)?;
+    state.metadata_cache.invalidate(&payload.schema).await;
+
+    Ok(Json(serde_json::json!({ "message": msg })))
+}
+
+/// Clone a table, optionally with its data
+pub async fn clone_table(
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
+    Json(payload): Json<CloneTableRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let result = SchemaOpsService::clone_table(&state.db_pool, &payload).await;
+    let target_schema = payload.target_schema.as_deref().unwrap_or(&payload.schema);
+    state
+        .audit_logger
+        .log_result(
+            schema_event(
+                client_ip,
+                format!(
+                    "CLONE TABLE {}.{}{}",
+                    payload.schema,
+                    payload.table_name,
+                    if payload.copy_data { " WITH DATA" } else { "" }
+                ),
+                format!("{}.{}", target_schema, payload.target_name),
+            ),
+            &result,
+        )
+        .await;
+    let msg = result.map_err(AppError::BadRequest)?;
+    state.metadata_cache.invalidate(target_schema).await;
+
+    Ok(Json(serde_json::json!({ "message": msg })))
 }
 
 /// Create an index
 pub async fn create_index(
-    State(state): State<AppState>,
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
     Json(payload): Json<CreateIndexRequest>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    SchemaOpsService::create_index(&state.db_pool, &payload)
-        .await
+) -> Result<Json<serde_json::Value>, AppError> {
+    let result = SchemaOpsService::create_index(&state.db_pool, &payload).await;
+    state
+        .audit_logger
+        .log_result(
+            schema_event(
+                client_ip,
+                format!("CREATE INDEX {}", payload.index_name),
+                format!("{}.{}", payload.schema, payload.table_name),
+            ),
+            &result,
+        )
+        .await;
+
+    result
+        .map(|msg| Json(serde_json::json!({ "message": msg })))
+        .map_err(AppError::BadRequest)
+}
+
+/// Set or remove a table/column comment
+pub async fn set_comment(
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
+    Json(payload): Json<CommentRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let result = SchemaOpsService::set_comment(&state.db_pool, &payload).await;
+    let resource = match &payload.column {
+        Some(column) => format!("{}.{}.{}", payload.schema, payload.table_name, column),
+        None => format!("{}.{}", payload.schema, payload.table_name),
+    };
+    state
+        .audit_logger
+        .log_result(
+            schema_event(client_ip, "COMMENT".to_string(), resource),
+            &result,
+        )
+        .await;
+
+    result
         .map(|msg| Json(serde_json::json!({ "message": msg })))
-        .map_err(|_| StatusCode::BAD_REQUEST)
+        .map_err(AppError::BadRequest)
 }
 
 /// List tables in a schema
 pub async fn list_tables(
-    State(state): State<AppState>,
+    Connected(state): Connected,
     axum::extract::Path(schema): axum::extract::Path<String>,
-) -> Result<Json<Vec<crate::services::schema_ops_service::TableInfo>>, StatusCode> {
+) -> Result<Json<Vec<crate::services::schema_ops_service::TableInfo>>, AppError> {
     SchemaOpsService::list_tables(&state.db_pool, &schema)
         .await
         .map(Json)
-        .map_err(|_| StatusCode::NOT_FOUND)
+        .map_err(AppError::NotFound)
 }
 
 /// Get table column definitions
 pub async fn get_table_columns(
-    State(state): State<AppState>,
+    Connected(state): Connected,
     axum::extract::Path((schema, table)): axum::extract::Path<(String, String)>,
-) -> Result<Json<Vec<crate::services::schema_ops_service::ColumnDef>>, StatusCode> {
+) -> Result<Json<Vec<crate::services::schema_ops_service::ColumnDef>>, AppError> {
     SchemaOpsService::get_table_columns(&state.db_pool, &schema, &table)
         .await
         .map(Json)
-        .map_err(|_| StatusCode::NOT_FOUND)
+        .map_err(AppError::NotFound)
 }
@@ -0,0 +1,118 @@
+// Server settings routes
+// Lists server parameters changed from their defaults and, for superuser
+// connections, changes selected ones with ALTER SYSTEM after confirmation
+
+use crate::error::AppError;
+use crate::i18n::filters;
+use crate::middleware::client_ip::ClientIp;
+use crate::middleware::connection::Connected;
+use crate::middleware::response_format::ResponseFormat;
+use crate::services::audit_service::{AuditEvent, AuditEventType};
+use crate::services::settings_service::{self, Setting};
+use askama::Template;
+use axum::{extract::Path, response::Response, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+#[derive(Template, Serialize)]
+#[template(path = "components/server-settings.html")]
+pub struct ServerSettingsTemplate {
+    /// Parameters whose value is not the built-in default
+    pub changed: Vec<Setting>,
+    /// Parameters that can be changed through `PUT /api/settings/{name}`
+    pub editable: Vec<Setting>,
+    /// The connected role may change them
+    pub can_edit: bool,
+}
+
+/// Server parameters: those changed from defaults and the editable ones
+pub async fn list_settings(
+    format: ResponseFormat,
+    Connected(state): Connected,
+) -> Result<Response, AppError> {
+    let changed = settings_service::list_changed(&state.db_pool).await?;
+    let editable = settings_service::list_editable(&state.db_pool).await?;
+    let can_edit = !state.read_only_mode.is_enabled()
+        && settings_service::is_superuser(&state.db_pool).await?;
+
+    format.render(ServerSettingsTemplate {
+        changed,
+        editable,
+        can_edit,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct UpdateSettingRequest {
+    /// New value; `null` resets the parameter to its default
+    pub value: Option<String>,
+    /// Apply the change; without it the response only describes it
+    #[serde(default)]
+    pub confirm: bool,
+}
+
+/// Change a parameter with `ALTER SYSTEM SET` (or `RESET`) and reload
+///
+/// Without `confirm: true` nothing is changed: the response is a 409 that
+/// spells out the statement and the current value, to resend once confirmed.
+pub async fn update_setting(
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
+    Path(name): Path<String>,
+    Json(request): Json<UpdateSettingRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let value = request.value.as_deref();
+    settings_service::validate_change(&name, value).map_err(AppError::BadRequest)?;
+    if state.read_only_mode.is_enabled() {
+        return Err(AppError::Forbidden(
+            "Server settings cannot be changed in read-only mode".to_string(),
+        ));
+    }
+    if !settings_service::is_superuser(&state.db_pool).await? {
+        return Err(AppError::Forbidden(
+            "Changing server settings requires a superuser connection".to_string(),
+        ));
+    }
+    let current = settings_service::get(&state.db_pool, &name)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Parameter '{}' not found", name)))?;
+
+    let sql = settings_service::alter_system_sql(&name, value);
+    if !request.confirm {
+        return Err(AppError::Conflict(format!(
+            "Not applied: {} (currently {}{}){}. Resend with \"confirm\": true to apply it",
+            sql,
+            current.setting,
+            current.unit.as_deref().unwrap_or(""),
+            if current.requires_restart() {
+                "; takes effect after a server restart"
+            } else {
+                ""
+            }
+        )));
+    }
+
+    let result = settings_service::apply(&state.db_pool, &name, value).await;
+    let previous = format!("previous value: {}", current.setting);
+    let event = AuditEvent::new(
+        AuditEventType::ConfigurationChange,
+        client_ip,
+        sql.clone(),
+        format!("setting:{}", name),
+    );
+    let event = match &result {
+        Ok(()) => event.with_details(previous),
+        Err(e) => event
+            .with_success(false)
+            .with_details(format!("{}; {}", previous, e)),
+    };
+    state.audit_logger.log(event).await;
+    result.map_err(AppError::BadRequest)?;
+
+    let updated = settings_service::get(&state.db_pool, &name).await?;
+    Ok(Json(json!({
+        "message": format!("{} applied and configuration reloaded", sql),
+        "restart_required": current.requires_restart(),
+        "setting": updated,
+    })))
+}
@@ -1,46 +1,66 @@
 // Statistics routes
 // Provides database performance and usage statistics
 
-use crate::services::stats_service::StatsService;
+use crate::error::AppError;
+use crate::i18n::filters;
+use crate::middleware::connection::Connected;
+use crate::middleware::response_format::ResponseFormat;
+use crate::models::{PageParams, Paginated};
+use crate::services::metrics_history::{self, Bucket, Metric, MetricPoint, Series};
+use crate::services::stats_service::{
+    BufferCacheRelation, BufferCacheSummary, CheckpointStats, CounterTrend, LongTransaction,
+    OperationProgress, StatsService, TempUsageReport, WalStats, COUNTER_SAMPLE_INTERVAL,
+    DEFAULT_LONG_TRANSACTION_SECS,
+};
 use crate::AppState;
 use askama::Template;
-use axum::{extract::State, http::StatusCode, response::Html, Json};
+use axum::{
+    extract::{Query, State},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Response,
+    },
+    Json,
+};
+use futures::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::convert::Infallible;
 
 /// Get overall database statistics
 pub async fn database_stats(
-    State(state): State<AppState>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+    Connected(state): Connected,
+) -> Result<Json<serde_json::Value>, AppError> {
     StatsService::database_stats(&state.db_pool, "postgres")
         .await
         .map(|stats| Json(json!(stats)))
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+        .map_err(AppError::Internal)
 }
 
 /// Get statistics for all tables
 pub async fn table_stats(
-    State(state): State<AppState>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+    Connected(state): Connected,
+    Query(params): Query<PageParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
     StatsService::table_stats(&state.db_pool)
         .await
-        .map(|tables| Json(json!(tables)))
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+        .map(|tables| Json(json!(Paginated::from_vec(tables, &params, 50))))
+        .map_err(AppError::Internal)
 }
 
 /// Get statistics for all indexes
 pub async fn index_stats(
-    State(state): State<AppState>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+    Connected(state): Connected,
+    Query(params): Query<PageParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
     StatsService::index_stats(&state.db_pool)
         .await
-        .map(|indexes| Json(json!(indexes)))
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+        .map(|indexes| Json(json!(Paginated::from_vec(indexes, &params, 50))))
+        .map_err(AppError::Internal)
 }
 
 /// Get cache hit statistics
-pub async fn cache_stats(
-    State(state): State<AppState>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+pub async fn cache_stats(Connected(state): Connected) -> Result<Json<serde_json::Value>, AppError> {
     StatsService::cache_stats(&state.db_pool)
         .await
         .map(|stats| {
@@ -56,14 +76,43 @@ pub async fn cache_stats(
                 "index_hit_ratio": format!("{:.2}%", idx_ratio),
             }))
         })
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+        .map_err(AppError::Internal)
+}
+
+/// Get checkpoint, background writer and WAL statistics
+pub async fn wal_stats(Connected(state): Connected) -> Result<Json<serde_json::Value>, AppError> {
+    let checkpoints = StatsService::checkpoint_stats(&state.db_pool)
+        .await
+        .map_err(AppError::Internal)?;
+    let wal = StatsService::wal_stats(&state.db_pool)
+        .await
+        .map_err(AppError::Internal)?;
+
+    Ok(Json(json!({
+        "requested_checkpoint_ratio": format!(
+            "{:.2}%",
+            StatsService::requested_checkpoint_ratio(&checkpoints)
+        ),
+        "checkpoints": checkpoints,
+        "wal": wal,
+    })))
+}
+
+/// Get deadlock, rollback and conflict counters per database, with how
+/// they moved over the last hour of samples
+pub async fn database_counters(
+    Connected(state): Connected,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let trends = state.counter_history.trends();
+    Ok(Json(json!({
+        "sample_interval_secs": COUNTER_SAMPLE_INTERVAL.as_secs(),
+        "databases": trends,
+    })))
 }
 
 /// Get comprehensive database overview
 #[allow(dead_code)]
-pub async fn overview(
-    State(state): State<AppState>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+pub async fn overview(Connected(state): Connected) -> Result<Json<serde_json::Value>, AppError> {
     let db_stats = StatsService::database_stats(&state.db_pool, "postgres")
         .await
         .ok();
@@ -85,24 +134,24 @@ pub async fn overview(
     })))
 }
 
-#[derive(Template)]
+#[derive(Template, Serialize)]
 #[template(path = "components/dashboard-metrics.html")]
 struct DashboardMetricsTemplate {
     database: crate::services::stats_service::DatabaseStats,
     total_tables: usize,
     cache_hit_ratio: String,
+    /// Databases with new deadlocks in the sampled window
+    rising_deadlocks: Vec<CounterTrend>,
 }
 
-/// Dashboard metrics widget - returns HTML
+/// Dashboard metrics widget - returns HTML or JSON
 pub async fn dashboard_metrics_widget(
-    State(state): State<AppState>,
-) -> Result<Html<String>, StatusCode> {
+    format: ResponseFormat,
+    Connected(state): Connected,
+) -> Result<Response, AppError> {
     let db_stats = StatsService::database_stats(&state.db_pool, "postgres")
         .await
-        .map_err(|e| {
-            tracing::error!("Failed to get database stats: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+        .map_err(AppError::Internal)?;
 
     let table_stats = StatsService::table_stats(&state.db_pool)
         .await
@@ -115,41 +164,42 @@ pub async fn dashboard_metrics_widget(
         .map(|s| format!("{:.2}%", StatsService::cache_hit_ratio(s)))
         .unwrap_or_else(|| "N/A".to_string());
 
-    let template = DashboardMetricsTemplate {
+    let rising_deadlocks = state
+        .counter_history
+        .trends()
+        .into_iter()
+        .filter(|trend| trend.deadlocks_rising)
+        .collect();
+
+    format.render(DashboardMetricsTemplate {
         database: db_stats,
         total_tables: table_stats.len(),
         cache_hit_ratio: heap_ratio,
-    };
-
-    template.render().map(Html).map_err(|e| {
-        tracing::error!("Failed to render dashboard metrics template: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
+        rising_deadlocks,
     })
 }
 
-#[derive(Template)]
+#[derive(Template, Serialize)]
 #[template(path = "components/table-stats.html")]
 struct TableStatsTemplate {
     tables: Vec<crate::services::stats_service::TableStats>,
 }
 
-/// Table stats widget - returns HTML
-pub async fn table_stats_widget(State(state): State<AppState>) -> Result<Html<String>, StatusCode> {
+/// Table stats widget - returns HTML or JSON
+pub async fn table_stats_widget(
+    format: ResponseFormat,
+    Connected(state): Connected,
+) -> Result<Response, AppError> {
     let tables = StatsService::table_stats(&state.db_pool)
         .await
         .unwrap_or_default();
 
-    let template = TableStatsTemplate {
+    format.render(TableStatsTemplate {
         tables: tables.into_iter().take(10).collect(),
-    };
-
-    template
-        .render()
-        .map(Html)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+    })
 }
 
-#[derive(Template)]
+#[derive(Template, Serialize)]
 #[template(path = "components/cache-stats.html")]
 struct CacheStatsTemplate {
     cache_hit_ratio: String,
@@ -174,11 +224,14 @@ fn get_performance_class(ratio_str: &str) -> String {
     }
 }
 
-/// Cache stats widget - returns HTML
-pub async fn cache_stats_widget(State(state): State<AppState>) -> Result<Html<String>, StatusCode> {
+/// Cache stats widget - returns HTML or JSON
+pub async fn cache_stats_widget(
+    format: ResponseFormat,
+    Connected(state): Connected,
+) -> Result<Response, AppError> {
     let stats = StatsService::cache_stats(&state.db_pool)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(AppError::Internal)?;
 
     let heap_ratio = StatsService::cache_hit_ratio(&stats);
     let idx_ratio = StatsService::index_hit_ratio(&stats);
@@ -186,17 +239,321 @@ pub async fn cache_stats_widget(State(state): State<AppState>) -> Result<Html<St
     let cache_ratio_str = format!("{:.2}%", heap_ratio);
     let index_ratio_str = format!("{:.2}%", idx_ratio);
 
-    let template = CacheStatsTemplate {
+    format.render(CacheStatsTemplate {
         cache_hit_ratio: cache_ratio_str.clone(),
         index_hit_ratio: index_ratio_str.clone(),
         heap_blks_read: stats.heap_blks_read,
         heap_blks_hit: stats.heap_blks_hit,
         cache_class: get_performance_class(&cache_ratio_str),
         index_class: get_performance_class(&index_ratio_str),
+    })
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "components/wal-stats.html")]
+struct WalStatsTemplate {
+    checkpoints: CheckpointStats,
+    wal: Option<WalStats>,
+    requested_ratio: String,
+    requested_class: String,
+}
+
+/// Requested checkpoints above this share suggest raising `max_wal_size`
+fn get_checkpoint_class(requested_ratio: f64) -> String {
+    if requested_ratio <= 10.0 {
+        "good".to_string()
+    } else if requested_ratio <= 30.0 {
+        "warning".to_string()
+    } else {
+        "bad".to_string()
+    }
+}
+
+/// WAL and checkpoint stats widget - returns HTML or JSON
+pub async fn wal_stats_widget(
+    format: ResponseFormat,
+    Connected(state): Connected,
+) -> Result<Response, AppError> {
+    let checkpoints = StatsService::checkpoint_stats(&state.db_pool)
+        .await
+        .map_err(AppError::Internal)?;
+    let wal = StatsService::wal_stats(&state.db_pool)
+        .await
+        .map_err(AppError::Internal)?;
+
+    let ratio = StatsService::requested_checkpoint_ratio(&checkpoints);
+
+    format.render(WalStatsTemplate {
+        checkpoints,
+        wal,
+        requested_ratio: format!("{:.2}%", ratio),
+        requested_class: get_checkpoint_class(ratio),
+    })
+}
+
+#[derive(Deserialize)]
+pub struct LongTransactionParams {
+    /// Report transactions open at least this long (default 300)
+    pub min_age_secs: Option<u64>,
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "components/long-transactions.html")]
+struct LongTransactionsTemplate {
+    min_age_secs: u64,
+    transactions: Vec<LongTransaction>,
+}
+
+/// Long-running and idle-in-transaction sessions - returns HTML or JSON
+///
+/// The HTML is a dashboard warning, empty when there is nothing to report.
+pub async fn long_transactions(
+    format: ResponseFormat,
+    Connected(state): Connected,
+    Query(params): Query<LongTransactionParams>,
+) -> Result<Response, AppError> {
+    let min_age_secs = params.min_age_secs.unwrap_or(DEFAULT_LONG_TRANSACTION_SECS);
+    let transactions = StatsService::long_transactions(&state.db_pool, min_age_secs)
+        .await
+        .map_err(AppError::Internal)?;
+
+    format.render(LongTransactionsTemplate {
+        min_age_secs,
+        transactions,
+    })
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "components/buffer-cache.html")]
+struct BufferCacheTemplate {
+    /// `pg_buffercache` is installed in this database
+    available: bool,
+    summary: Option<BufferCacheSummary>,
+    relations: Vec<BufferCacheRelation>,
+}
+
+/// Shared buffer contents by relation - returns HTML or JSON
+pub async fn buffer_cache(
+    format: ResponseFormat,
+    Connected(state): Connected,
+) -> Result<Response, AppError> {
+    if !StatsService::buffercache_available(&state.db_pool)
+        .await
+        .map_err(AppError::Internal)?
+    {
+        return format.render(BufferCacheTemplate {
+            available: false,
+            summary: None,
+            relations: Vec::new(),
+        });
+    }
+
+    let summary = StatsService::buffercache_summary(&state.db_pool)
+        .await
+        .map_err(AppError::Internal)?;
+    let relations = StatsService::buffercache_relations(&state.db_pool, 20)
+        .await
+        .map_err(AppError::Internal)?;
+
+    format.render(BufferCacheTemplate {
+        available: true,
+        summary: Some(summary),
+        relations,
+    })
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "components/temp-files.html")]
+struct TempFilesTemplate {
+    report: TempUsageReport,
+    starved_queries: usize,
+}
+
+/// Temporary file usage per database and per query - returns HTML or JSON
+pub async fn temp_files(
+    format: ResponseFormat,
+    Connected(state): Connected,
+) -> Result<Response, AppError> {
+    let report = StatsService::temp_usage(&state.db_pool)
+        .await
+        .map_err(AppError::Internal)?;
+
+    format.render(TempFilesTemplate {
+        starved_queries: report.starved_query_count(),
+        report,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct MetricHistoryParams {
+    /// `database_size`, `connections`, `cache_hit_ratio` or `tps`
+    pub metric: Option<String>,
+    /// How far back to go, e.g. `30m`, `6h`, `7d` (default `1h`)
+    pub range: Option<String>,
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "components/metric-history.html")]
+struct MetricHistoryTemplate {
+    metric: Metric,
+    label: &'static str,
+    range: String,
+    points: Vec<MetricPoint>,
+    latest: Option<String>,
+    min: Option<String>,
+    max: Option<String>,
+    #[serde(skip)]
+    sparkline: String,
+}
+
+/// Recorded history of a metric of the default connection - returns an
+/// SVG sparkline or JSON
+pub async fn metric_history(
+    format: ResponseFormat,
+    State(state): State<AppState>,
+    Query(params): Query<MetricHistoryParams>,
+) -> Result<Response, AppError> {
+    let metric = params
+        .metric
+        .as_deref()
+        .ok_or_else(|| AppError::BadRequest("metric is required".to_string()))
+        .and_then(|name| Metric::parse(name).map_err(AppError::BadRequest))?;
+    let range = params.range.unwrap_or_else(|| "1h".to_string());
+    let duration = metrics_history::parse_range(&range).map_err(AppError::BadRequest)?;
+
+    let points = state.metrics_history.query(metric, duration).await;
+    let values = || points.iter().map(|p| p.value);
+    let min = values().reduce(f64::min).map(|v| metric.format(v));
+    let max = values().reduce(f64::max).map(|v| metric.format(v));
+
+    format.render(MetricHistoryTemplate {
+        metric,
+        label: metric.label(),
+        range,
+        latest: points.last().map(|p| metric.format(p.value)),
+        min,
+        max,
+        sparkline: metrics_history::sparkline_points(&points, 300.0, 60.0),
+        points,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct SeriesParams {
+    /// `database_size`, `connections`, `cache_hit_ratio` or `tps`
+    pub metric: Option<String>,
+    /// How far back to go, e.g. `30m`, `6h`, `7d` (default `1h`)
+    pub range: Option<String>,
+    /// `minute` or `hour`; by default minutes up to 6 hours, hours beyond
+    pub bucket: Option<String>,
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "components/series-chart.html")]
+struct SeriesChartTemplate {
+    label: &'static str,
+    range: String,
+    #[serde(flatten)]
+    series: Series,
+    #[serde(skip)]
+    latest: Option<String>,
+    #[serde(skip)]
+    low: Option<String>,
+    #[serde(skip)]
+    high: Option<String>,
+    #[serde(skip)]
+    line: String,
+    #[serde(skip)]
+    band: String,
+}
+
+/// A metric of the default connection downsampled into per-minute or
+/// per-hour buckets - returns an SVG chart or chart-ready JSON arrays
+pub async fn metric_series(
+    format: ResponseFormat,
+    State(state): State<AppState>,
+    Query(params): Query<SeriesParams>,
+) -> Result<Response, AppError> {
+    let metric = params
+        .metric
+        .as_deref()
+        .ok_or_else(|| AppError::BadRequest("metric is required".to_string()))
+        .and_then(|name| Metric::parse(name).map_err(AppError::BadRequest))?;
+    let range = params.range.unwrap_or_else(|| "1h".to_string());
+    let duration = metrics_history::parse_range(&range).map_err(AppError::BadRequest)?;
+    let bucket = match params.bucket.as_deref() {
+        Some(name) => Bucket::parse(name).map_err(AppError::BadRequest)?,
+        None => Bucket::for_range(duration),
     };
 
-    template
-        .render()
-        .map(Html)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+    let series = state
+        .metrics_history
+        .series(metric, duration, bucket)
+        .await
+        .map_err(AppError::BadRequest)?;
+    let (line, band) = metrics_history::chart_paths(&series, 300.0, 80.0);
+    let low = series.min.iter().flatten().copied().reduce(f64::min);
+    let high = series.max.iter().flatten().copied().reduce(f64::max);
+
+    format.render(SeriesChartTemplate {
+        label: metric.label(),
+        range,
+        latest: series
+            .avg
+            .iter()
+            .rev()
+            .flatten()
+            .next()
+            .map(|v| metric.format(*v)),
+        low: low.map(|v| metric.format(v)),
+        high: high.map(|v| metric.format(v)),
+        line,
+        band,
+        series,
+    })
+}
+
+/// Server-sent events: a `sample` event with each new metrics sample, so
+/// dashboard charts refresh as soon as there is data
+pub async fn metric_stream(
+    State(state): State<AppState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let samples = state.metrics_history.subscribe();
+    let events = stream::unfold(samples, |mut samples| async move {
+        loop {
+            match samples.recv().await {
+                Ok(sample) => {
+                    let event = Event::default()
+                        .event("sample")
+                        .json_data(&sample)
+                        .unwrap_or_default();
+                    return Some((Ok(event), samples));
+                }
+                // A slow client skipped some samples; the next one will do
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "components/operation-progress.html")]
+struct OperationProgressTemplate {
+    operations: Vec<OperationProgress>,
+}
+
+/// Running VACUUM, CREATE INDEX and CLUSTER commands - returns HTML or JSON
+///
+/// The HTML is a list of progress bars, empty when nothing is running.
+pub async fn operation_progress(
+    format: ResponseFormat,
+    Connected(state): Connected,
+) -> Result<Response, AppError> {
+    let operations = StatsService::operation_progress(&state.db_pool)
+        .await
+        .map_err(AppError::Internal)?;
+
+    format.render(OperationProgressTemplate { operations })
 }
@@ -1,32 +1,52 @@
 use askama::Template;
-use axum::extract::{Path, Query, State};
-use serde::Deserialize;
+use axum::extract::{Path, Query};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::time::Duration;
 
+use crate::middleware::connection::{Connected, DemoAware};
+use crate::middleware::query_limit::QuerySlot;
+use crate::middleware::quota::Quota;
 use crate::{
+    error::AppError,
+    i18n::filters,
+    middleware::{response_format::ResponseFormat, session::Session},
     models::ColumnInfo,
     routes::HtmlTemplate,
-    services::{cell_service, schema_service},
+    services::{
+        cell_service,
+        ddl_service::DdlService,
+        metadata_cache::TableSort,
+        preferences_service::Preferences,
+        schema_service,
+        table_tail::{TailBatch, TailCursor},
+    },
     AppState,
 };
 
 #[derive(Template)]
 #[template(path = "studio.html")]
 pub struct StudioTemplate {
+    pub branding: std::sync::Arc<crate::config::Branding>,
     pub schema_name: Option<String>,
     pub table_name: Option<String>,
     pub active_table: Option<String>,
     pub tables: Vec<crate::models::TableInfo>,
     pub views: Vec<crate::models::TableInfo>,
     pub active_view: String,
+    pub sort: String,
 }
 
 /// A row with its PK value for editing
+#[derive(Serialize)]
 pub struct EditableRow {
     pub pk_value: Option<String>,
     pub cells: Vec<serde_json::Value>,
 }
 
-#[derive(Template)]
+#[derive(Template, Serialize)]
 #[template(path = "components/studio-data.html")]
 pub struct StudioDataTemplate {
     pub schema: String,
@@ -35,146 +55,253 @@ pub struct StudioDataTemplate {
     pub rows: Vec<EditableRow>,
     pub pagination: crate::models::Pagination,
     pub pk_column: Option<String>,
+    /// Sampled percentage when showing a sample; the row count is then the
+    /// planner's estimate
+    pub sample: Option<f64>,
 }
 
-#[derive(Template)]
+#[derive(Template, Serialize)]
 #[template(path = "components/studio-structure.html")]
 pub struct StudioStructureTemplate {
     pub schema: String,
     pub table: String,
     pub columns: Vec<ColumnInfo>,
     pub row_count: i64,
+    pub table_comment: Option<String>,
 }
 
-#[derive(Template)]
+#[derive(Template, Serialize)]
 #[template(path = "components/studio-indexes.html")]
 pub struct StudioIndexesTemplate {
     pub indexes: Vec<serde_json::Value>,
 }
 
+#[derive(Template, Serialize)]
+#[template(path = "components/studio-policies.html")]
+pub struct StudioPoliciesTemplate {
+    pub security: Option<crate::models::RowSecurity>,
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "components/studio-ddl.html")]
+pub struct StudioDdlTemplate {
+    pub ddl: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SidebarQuery {
+    pub sort: Option<String>,
+}
+
 #[derive(Deserialize)]
 pub struct PaginationQuery {
     pub page: Option<u32>,
     pub page_size: Option<u32>,
+    /// Percentage of pages to sample instead of paging
+    pub sample: Option<f64>,
+}
+
+#[derive(Deserialize)]
+pub struct TailQuery {
+    /// Ordering column; new rows are those with a higher value
+    pub column: String,
+    /// Poll interval in milliseconds (at least 250, default 1000)
+    pub interval_ms: Option<u64>,
+    /// Rows before the current end to send first (default 0)
+    pub backlog: Option<i64>,
 }
 
 /// GET /studio - Studio main page (default schema)
-pub async fn studio_index(State(state): State<AppState>) -> impl axum::response::IntoResponse {
-    // Get tables from public schema by default
-    let schema_name = "public".to_string();
-    let all_tables = schema_service::list_tables(&state.db_pool, &schema_name)
-        .await
-        .unwrap_or_default();
-    let (tables, views) = split_tables_and_views(all_tables);
+pub async fn studio_index(
+    DemoAware(state): DemoAware,
+    session: Session,
+    Query(query): Query<SidebarQuery>,
+) -> impl axum::response::IntoResponse {
+    // Open the preferred schema, public by default
+    let schema_name = Preferences::load(&session).await.schema().to_string();
+    let sort = TableSort::parse(query.sort.as_deref());
+    let (tables, views) = load_sidebar(&state, &schema_name, sort).await;
 
     HtmlTemplate(StudioTemplate {
+        branding: state.branding.clone(),
         schema_name: Some(schema_name),
         table_name: None,
         active_table: None,
         tables,
         views,
         active_view: "data".to_string(),
+        sort: sort.as_str().to_string(),
     })
 }
 
 /// GET /studio/:schema - Studio for a specific schema
 pub async fn studio_schema(
-    State(state): State<AppState>,
+    DemoAware(state): DemoAware,
     Path(schema): Path<String>,
+    Query(query): Query<SidebarQuery>,
 ) -> impl axum::response::IntoResponse {
-    let all_tables = schema_service::list_tables(&state.db_pool, &schema)
-        .await
-        .unwrap_or_default();
-    let (tables, views) = split_tables_and_views(all_tables);
+    let sort = TableSort::parse(query.sort.as_deref());
+    let (tables, views) = load_sidebar(&state, &schema, sort).await;
 
     HtmlTemplate(StudioTemplate {
+        branding: state.branding.clone(),
         schema_name: Some(schema),
         table_name: None,
         active_table: None,
         tables,
         views,
         active_view: "data".to_string(),
+        sort: sort.as_str().to_string(),
     })
 }
 
 /// GET /studio/:schema/:table - Studio with a table selected
 pub async fn studio_table(
-    State(state): State<AppState>,
+    DemoAware(state): DemoAware,
     Path((schema, table)): Path<(String, String)>,
+    Query(query): Query<SidebarQuery>,
 ) -> impl axum::response::IntoResponse {
-    let all_tables = schema_service::list_tables(&state.db_pool, &schema)
-        .await
-        .unwrap_or_default();
-    let (tables, views) = split_tables_and_views(all_tables);
+    let sort = TableSort::parse(query.sort.as_deref());
+    let (tables, views) = load_sidebar(&state, &schema, sort).await;
 
     HtmlTemplate(StudioTemplate {
+        branding: state.branding.clone(),
         schema_name: Some(schema),
         table_name: Some(table.clone()),
         active_table: Some(table),
         tables,
         views,
         active_view: "data".to_string(),
+        sort: sort.as_str().to_string(),
     })
 }
 
 /// GET /studio/:schema/:table/structure - Studio with structure selected
 pub async fn studio_table_structure_page(
-    State(state): State<AppState>,
+    DemoAware(state): DemoAware,
     Path((schema, table)): Path<(String, String)>,
+    Query(query): Query<SidebarQuery>,
 ) -> impl axum::response::IntoResponse {
-    let all_tables = schema_service::list_tables(&state.db_pool, &schema)
-        .await
-        .unwrap_or_default();
-    let (tables, views) = split_tables_and_views(all_tables);
+    let sort = TableSort::parse(query.sort.as_deref());
+    let (tables, views) = load_sidebar(&state, &schema, sort).await;
 
     HtmlTemplate(StudioTemplate {
+        branding: state.branding.clone(),
         schema_name: Some(schema),
         table_name: Some(table.clone()),
         active_table: Some(table),
         tables,
         views,
         active_view: "structure".to_string(),
+        sort: sort.as_str().to_string(),
     })
 }
 
+/// Load the sidebar table list from the metadata cache (or the demo
+/// catalog), sorted as requested
+async fn load_sidebar(
+    state: &AppState,
+    schema: &str,
+    sort: TableSort,
+) -> (Vec<crate::models::TableInfo>, Vec<crate::models::TableInfo>) {
+    let all_tables = match &state.demo {
+        Some(demo) => demo.tables(schema),
+        None => state
+            .metadata_cache
+            .tables(&state.db_pool, schema)
+            .await
+            .unwrap_or_default(),
+    };
+    let (mut tables, mut views) = split_tables_and_views(all_tables);
+    sort.apply(&mut tables);
+    sort.apply(&mut views);
+    (tables, views)
+}
+
 fn split_tables_and_views(
     all_tables: Vec<crate::models::TableInfo>,
 ) -> (Vec<crate::models::TableInfo>, Vec<crate::models::TableInfo>) {
-    let (views, tables): (Vec<_>, Vec<_>) =
-        all_tables.into_iter().partition(|table| table.table_type == "VIEW");
+    let (views, tables): (Vec<_>, Vec<_>) = all_tables
+        .into_iter()
+        .partition(|table| table.table_type == "VIEW");
     (tables, views)
 }
 
 /// GET /api/studio/table/:schema/:table - Get table data for studio (HTMX fragment)
 pub async fn studio_table_data(
-    State(state): State<AppState>,
+    format: ResponseFormat,
+    DemoAware(state): DemoAware,
+    _slot: QuerySlot,
     Path((schema, table)): Path<(String, String)>,
     Query(pagination): Query<PaginationQuery>,
-) -> impl axum::response::IntoResponse {
+    session: Session,
+    quota: Quota,
+) -> Result<axum::response::Response, AppError> {
     let page = pagination.page.unwrap_or(1);
-    let page_size = pagination.page_size.unwrap_or(100);
-
-    // Get columns
-    let columns = schema_service::get_table_columns(&state.db_pool, &schema, &table)
+    let page_size = Preferences::load(&session)
         .await
-        .unwrap_or_default();
+        .page_size(pagination.page_size, 100);
+    let sample = pagination
+        .sample
+        .map(schema_service::validate_sample_percent)
+        .transpose()
+        .map_err(AppError::BadRequest)?;
 
-    // Get primary key column for editing
-    let pk_column = cell_service::get_primary_key_column(&state.db_pool, &schema, &table)
-        .await
-        .ok()
-        .flatten();
+    // Get columns and the primary key column for editing
+    let (columns, pk_column) = match &state.demo {
+        Some(demo) => (
+            demo.columns(&schema, &table).unwrap_or_default(),
+            // Sample rows are not editable
+            None,
+        ),
+        None => (
+            schema_service::get_table_columns(&state.db_pool, &schema, &table)
+                .await
+                .unwrap_or_default(),
+            cell_service::get_primary_key_column(&state.db_pool, &schema, &table)
+                .await
+                .ok()
+                .flatten(),
+        ),
+    };
 
     // Find PK column index
     let pk_idx = pk_column
         .as_ref()
         .and_then(|pk| columns.iter().position(|c| &c.name == pk));
 
-    // Get data with pagination
-    let (raw_rows, total_rows) =
-        schema_service::get_table_data(&state.db_pool, &schema, &table, page, page_size)
-            .await
-            .unwrap_or_default();
+    // Get a sample, or a page of data
+    let (raw_rows, total_rows) = match (&state.demo, sample) {
+        (Some(demo), None) => demo
+            .page(&schema, &table, page, page_size)
+            .unwrap_or_default(),
+        (Some(_), Some(_)) => {
+            return Err(AppError::Unavailable(
+                "Sampling is not available in demo mode".to_string(),
+            ))
+        }
+        (None, Some(percent)) => {
+            quota
+                .limit_rows(
+                    &format!("browse {}.{}", schema, table),
+                    schema_service::get_table_sample(
+                        &state.db_pool,
+                        &schema,
+                        &table,
+                        percent,
+                        page_size,
+                    ),
+                )
+                .await??
+        }
+        (None, None) => quota
+            .limit_rows(
+                &format!("browse {}.{}", schema, table),
+                schema_service::get_table_data(&state.db_pool, &schema, &table, page, page_size),
+            )
+            .await?
+            .unwrap_or_default(),
+    };
 
     // Convert to EditableRow with PK values
     let rows: Vec<EditableRow> = raw_rows
@@ -196,13 +323,13 @@ pub async fn studio_table_data(
         })
         .collect();
 
-    let total_pages = if total_rows > 0 {
+    let total_pages = if total_rows > 0 && sample.is_none() {
         ((total_rows as f64) / (page_size as f64)).ceil() as u32
     } else {
         1
     };
 
-    HtmlTemplate(StudioDataTemplate {
+    format.render(StudioDataTemplate {
         schema,
         table,
         columns,
@@ -214,14 +341,27 @@ pub async fn studio_table_data(
             total_pages,
         },
         pk_column,
+        sample,
     })
 }
 
 /// GET /api/studio/structure/:schema/:table - Get table structure for studio (HTMX fragment)
 pub async fn studio_table_structure(
-    State(state): State<AppState>,
+    format: ResponseFormat,
+    DemoAware(state): DemoAware,
     Path((schema, table)): Path<(String, String)>,
-) -> impl axum::response::IntoResponse {
+) -> Result<axum::response::Response, AppError> {
+    if let Some(demo) = &state.demo {
+        let info = demo.table_info(&schema, &table).ok();
+        return format.render(StudioStructureTemplate {
+            columns: demo.columns(&schema, &table).unwrap_or_default(),
+            row_count: info.and_then(|t| t.row_count).unwrap_or(0),
+            table_comment: demo.comment(&schema, &table).ok().flatten(),
+            schema,
+            table,
+        });
+    }
+
     let columns = schema_service::get_table_columns(&state.db_pool, &schema, &table)
         .await
         .unwrap_or_default();
@@ -230,22 +370,110 @@ pub async fn studio_table_structure(
         .await
         .unwrap_or(0);
 
-    HtmlTemplate(StudioStructureTemplate {
+    let table_comment = schema_service::get_table_comment(&state.db_pool, &schema, &table)
+        .await
+        .ok()
+        .flatten();
+
+    format.render(StudioStructureTemplate {
         schema,
         table,
         columns,
         row_count,
+        table_comment,
     })
 }
 
 /// GET /api/studio/table/:schema/:table/indexes - Get table indexes for studio (HTMX fragment)
 pub async fn studio_table_indexes(
-    State(state): State<AppState>,
+    format: ResponseFormat,
+    Connected(state): Connected,
     Path((schema, table)): Path<(String, String)>,
-) -> impl axum::response::IntoResponse {
+) -> Result<axum::response::Response, AppError> {
     let indexes = schema_service::get_table_indexes(&state.db_pool, &schema, &table)
         .await
         .unwrap_or_default();
 
-    HtmlTemplate(StudioIndexesTemplate { indexes })
+    format.render(StudioIndexesTemplate { indexes })
+}
+
+/// GET /api/studio/table/:schema/:table/policies - Get RLS policies for studio (HTMX fragment)
+pub async fn studio_table_policies(
+    format: ResponseFormat,
+    Connected(state): Connected,
+    Path((schema, table)): Path<(String, String)>,
+) -> Result<axum::response::Response, AppError> {
+    let security = schema_service::get_table_policies(&state.db_pool, &schema, &table)
+        .await
+        .ok();
+
+    format.render(StudioPoliciesTemplate { security })
+}
+
+/// GET /api/studio/table/:schema/:table/ddl - Get CREATE TABLE statement for studio (HTMX fragment)
+pub async fn studio_table_ddl(
+    format: ResponseFormat,
+    Connected(state): Connected,
+    Path((schema, table)): Path<(String, String)>,
+) -> Result<axum::response::Response, AppError> {
+    let ddl = DdlService::table_ddl(&state.db_pool, &schema, &table)
+        .await
+        .ok();
+
+    format.render(StudioDdlTemplate { ddl })
+}
+
+/// GET /api/studio/table/:schema/:table/tail - Server-sent events with rows
+/// added to the table
+///
+/// Polls on the `column` ordering column and sends a `rows` event with each
+/// non-empty batch (`{columns, rows}`). A `tail-error` event ends the stream
+/// when a poll fails.
+pub async fn studio_table_tail(
+    Connected(state): Connected,
+    Path((schema, table)): Path<(String, String)>,
+    Query(query): Query<TailQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    let pool = state.db_pool.clone();
+    let (cursor, backlog) = TailCursor::open(
+        &pool,
+        &schema,
+        &table,
+        &query.column,
+        query.backlog.unwrap_or(0),
+    )
+    .await
+    .map_err(AppError::BadRequest)?;
+    let interval = Duration::from_millis(query.interval_ms.unwrap_or(1000).max(250));
+
+    let first = (!backlog.rows.is_empty()).then_some(backlog);
+    let events = stream::unfold(
+        (pool, Some(cursor), first),
+        move |(pool, cursor, pending)| async move {
+            if let Some(batch) = pending {
+                return Some((tail_event(Ok(batch)), (pool, cursor, None)));
+            }
+            let mut cursor = cursor?;
+            loop {
+                tokio::time::sleep(interval).await;
+                match cursor.poll(&pool).await {
+                    Ok(batch) if batch.rows.is_empty() => continue,
+                    Ok(batch) => return Some((tail_event(Ok(batch)), (pool, Some(cursor), None))),
+                    // Send the error, then end the stream
+                    Err(e) => return Some((tail_event(Err(e)), (pool, None, None))),
+                }
+            }
+        },
+    );
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+fn tail_event(batch: Result<TailBatch, String>) -> Result<Event, Infallible> {
+    Ok(match batch {
+        Ok(batch) => Event::default()
+            .event("rows")
+            .json_data(&batch)
+            .unwrap_or_default(),
+        Err(e) => Event::default().event("tail-error").data(e),
+    })
 }
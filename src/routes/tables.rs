@@ -1,30 +1,41 @@
 // Table management routes
 // Handles routes for viewing and managing database tables
 
+use crate::error::AppError;
+use crate::i18n::filters;
+use crate::middleware::connection::{Connected, DemoAware};
+use crate::middleware::query_limit::QuerySlot;
+use crate::middleware::quota::Quota;
+use crate::middleware::response_format::ResponseFormat;
+use crate::middleware::session::Session;
 use crate::models::{ColumnInfo, Pagination, TableDataParams};
+use crate::services::ddl_service::DdlService;
+use crate::services::duplicate_service::{self, DuplicateOptions, DuplicateReport};
+use crate::services::preferences_service::Preferences;
+use crate::services::profile_service::{self, TableProfile};
 use crate::services::schema_service;
-use crate::AppState;
 use askama::Template;
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
-    response::{Html, IntoResponse},
+    extract::{Path, Query},
+    response::{IntoResponse, Response},
+    Json,
 };
+use serde::{Deserialize, Serialize};
 
-#[derive(Template)]
+#[derive(Template, Serialize)]
 #[template(path = "components/tables-list.html")]
 pub struct TablesListTemplate {
     pub tables: Vec<crate::models::TableInfo>,
 }
 
-#[derive(Template)]
+#[derive(Template, Serialize)]
 #[template(path = "components/table-display.html")]
 pub struct TableDisplayTemplate {
     pub table: crate::models::TableInfo,
     pub columns: Vec<ColumnInfo>,
 }
 
-#[derive(Template)]
+#[derive(Template, Serialize)]
 #[template(path = "components/table-data.html")]
 pub struct TableDataTemplate {
     pub schema: String,
@@ -32,67 +43,145 @@ pub struct TableDataTemplate {
     pub columns: Vec<ColumnInfo>,
     pub rows: Vec<Vec<serde_json::Value>>,
     pub pagination: Pagination,
+    /// Sampled percentage when showing a sample; the row count is then the
+    /// planner's estimate
+    pub sample: Option<f64>,
 }
 
-/// Lists all tables in a schema (returns HTML)
+#[derive(Template, Serialize)]
+#[template(path = "components/table-profile.html")]
+pub struct TableProfileTemplate {
+    pub profile: TableProfile,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProfileParams {
+    /// Percentage of pages to sample; by default just enough for `max_rows`
+    pub sample: Option<f64>,
+    /// Rows to profile at most (default 10000)
+    pub max_rows: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DuplicatesParams {
+    /// Comma-separated columns that should be unique together
+    pub columns: String,
+    /// Treat NULLs as equal, like a `NULLS NOT DISTINCT` constraint
+    #[serde(default)]
+    pub include_nulls: bool,
+    /// Largest groups to return (default 50)
+    pub limit: Option<u32>,
+    /// Rows shown per group (default 3)
+    pub sample_rows: Option<u32>,
+}
+
+/// Lists all tables in a schema (returns HTML or JSON)
 pub async fn list_tables(
+    format: ResponseFormat,
     Path(schema): Path<String>,
-    State(state): State<AppState>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let tables = schema_service::list_tables(&state.db_pool, &schema)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    DemoAware(state): DemoAware,
+) -> Result<Response, AppError> {
+    let tables = match &state.demo {
+        Some(demo) => demo.tables(&schema),
+        None => schema_service::list_tables(&state.db_pool, &schema).await?,
+    };
 
-    let template = TablesListTemplate { tables };
-    match template.render() {
-        Ok(html) => Ok(Html(html)),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
+    format.render(TablesListTemplate { tables })
 }
 
-/// Gets details about a specific table (returns HTML)
+/// Gets details about a specific table (returns HTML or JSON)
 pub async fn table_details(
+    format: ResponseFormat,
     Path((schema, table)): Path<(String, String)>,
-    State(state): State<AppState>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let table_info = schema_service::get_table_info(&state.db_pool, &schema, &table)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    DemoAware(state): DemoAware,
+) -> Result<Response, AppError> {
+    if let Some(demo) = &state.demo {
+        return format.render(TableDisplayTemplate {
+            table: demo
+                .table_info(&schema, &table)
+                .map_err(AppError::NotFound)?,
+            columns: demo.columns(&schema, &table).map_err(AppError::NotFound)?,
+        });
+    }
+    let table_info = schema_service::get_table_info(&state.db_pool, &schema, &table).await?;
 
-    let columns = schema_service::get_table_columns(&state.db_pool, &schema, &table)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let columns = schema_service::get_table_columns(&state.db_pool, &schema, &table).await?;
 
-    let template = TableDisplayTemplate {
+    format.render(TableDisplayTemplate {
         table: table_info,
         columns,
-    };
-
-    match template.render() {
-        Ok(html) => Ok(Html(html)),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
+    })
 }
 
-/// Browses table data with pagination (returns HTML)
+/// Browses table data with pagination, or a sample of it with `?sample=`
+/// (returns HTML or JSON); a page over the role's row quota is refused
 pub async fn browse_data(
+    format: ResponseFormat,
     Path((schema, table)): Path<(String, String)>,
     Query(params): Query<TableDataParams>,
-    State(state): State<AppState>,
-) -> Result<impl IntoResponse, StatusCode> {
+    DemoAware(state): DemoAware,
+    _slot: QuerySlot,
+    session: Session,
+    quota: Quota,
+) -> Result<Response, AppError> {
     let page = params.page.unwrap_or(1);
-    let page_size = params.page_size.unwrap_or(100);
+    let page_size = Preferences::load(&session)
+        .await
+        .page_size(params.page_size, 100);
+    let sample = params
+        .sample
+        .map(schema_service::validate_sample_percent)
+        .transpose()
+        .map_err(AppError::BadRequest)?;
 
-    let (rows, total_rows) =
-        schema_service::get_table_data(&state.db_pool, &schema, &table, page, page_size)
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let (rows, total_rows) = match (&state.demo, sample) {
+        (Some(demo), None) => demo
+            .page(&schema, &table, page, page_size)
+            .map_err(AppError::NotFound)?,
+        (Some(_), Some(_)) => {
+            return Err(AppError::Unavailable(
+                "Sampling is not available in demo mode".to_string(),
+            ))
+        }
+        (None, Some(percent)) => {
+            quota
+                .limit_rows(
+                    &format!("browse {}.{}", schema, table),
+                    schema_service::get_table_sample(
+                        &state.db_pool,
+                        &schema,
+                        &table,
+                        percent,
+                        page_size,
+                    ),
+                )
+                .await??
+        }
+        (None, None) => {
+            quota
+                .limit_rows(
+                    &format!("browse {}.{}", schema, table),
+                    schema_service::get_table_data(
+                        &state.db_pool,
+                        &schema,
+                        &table,
+                        page,
+                        page_size,
+                    ),
+                )
+                .await??
+        }
+    };
 
-    let columns = schema_service::get_table_columns(&state.db_pool, &schema, &table)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let columns = match &state.demo {
+        Some(demo) => demo.columns(&schema, &table).map_err(AppError::NotFound)?,
+        None => schema_service::get_table_columns(&state.db_pool, &schema, &table).await?,
+    };
 
-    let total_pages = (total_rows as f64 / page_size as f64).ceil() as u32;
+    let total_pages = match sample {
+        Some(_) => 1,
+        None => (total_rows as f64 / page_size as f64).ceil() as u32,
+    };
 
     let pagination = Pagination {
         page,
@@ -114,16 +203,111 @@ pub async fn browse_data(
         })
         .collect();
 
-    let template = TableDataTemplate {
+    format.render(TableDataTemplate {
         schema,
         table,
         columns,
         rows: json_rows,
         pagination,
+        sample,
+    })
+}
+
+/// Profiles each column over a sample of the rows: NULL share, distinct
+/// values, min/max and average length (returns HTML or JSON)
+///
+/// No more rows are read than the role's row quota allows.
+pub async fn table_profile(
+    format: ResponseFormat,
+    Path((schema, table)): Path<(String, String)>,
+    Query(params): Query<ProfileParams>,
+    Connected(state): Connected,
+    _slot: QuerySlot,
+    quota: Quota,
+) -> Result<Response, AppError> {
+    let sample = params
+        .sample
+        .map(schema_service::validate_sample_percent)
+        .transpose()
+        .map_err(AppError::BadRequest)?;
+    let max_rows = match params.max_rows {
+        Some(0) => {
+            return Err(AppError::BadRequest(
+                "max_rows must be at least 1".to_string(),
+            ))
+        }
+        Some(rows) => rows.min(profile_service::MAX_ROWS_LIMIT),
+        None => profile_service::DEFAULT_MAX_ROWS,
+    };
+    let max_rows = match quota.limits.max_rows {
+        Some(limit) => max_rows.min(u32::try_from(limit).unwrap_or(u32::MAX).max(1)),
+        None => max_rows,
     };
 
-    match template.render() {
-        Ok(html) => Ok(Html(html)),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-    }
+    let profile =
+        profile_service::profile_table(&state.db_pool, &schema, &table, sample, max_rows).await?;
+
+    format.render(TableProfileTemplate { profile })
+}
+
+/// Finds groups of rows with equal values in `?columns=`, largest first, with
+/// a few rows of each (returns JSON); more sample rows than the role's row
+/// quota are refused
+pub async fn table_duplicates(
+    Path((schema, table)): Path<(String, String)>,
+    Query(params): Query<DuplicatesParams>,
+    Connected(state): Connected,
+    _slot: QuerySlot,
+    quota: Quota,
+) -> Result<Json<DuplicateReport>, AppError> {
+    let existing = duplicate_service::column_names(&state.db_pool, &schema, &table).await?;
+    let requested: Vec<String> = params.columns.split(',').map(str::to_string).collect();
+    let columns =
+        duplicate_service::validate_columns(&requested, &existing).map_err(AppError::BadRequest)?;
+
+    let options = DuplicateOptions {
+        include_nulls: params.include_nulls,
+        group_limit: params
+            .limit
+            .unwrap_or(duplicate_service::DEFAULT_GROUP_LIMIT)
+            .clamp(1, duplicate_service::MAX_GROUP_LIMIT),
+        sample_rows: params
+            .sample_rows
+            .unwrap_or(duplicate_service::DEFAULT_SAMPLE_ROWS)
+            .clamp(1, duplicate_service::MAX_SAMPLE_ROWS),
+    };
+    let report = quota
+        .limit_rows(
+            &format!("duplicates {}.{}", schema, table),
+            duplicate_service::find_duplicates(&state.db_pool, &schema, &table, columns, options),
+        )
+        .await??;
+
+    Ok(Json(report))
+}
+
+/// Gets row-level security status and policies for a table (returns JSON)
+pub async fn table_policies(
+    Path((schema, table)): Path<(String, String)>,
+    Connected(state): Connected,
+) -> Result<impl IntoResponse, AppError> {
+    let security = schema_service::get_table_policies(&state.db_pool, &schema, &table).await?;
+
+    Ok(Json(security))
+}
+
+/// Gets the reconstructed CREATE TABLE statement for a table (returns JSON)
+pub async fn table_ddl(
+    Path((schema, table)): Path<(String, String)>,
+    Connected(state): Connected,
+) -> Result<impl IntoResponse, AppError> {
+    let ddl = DdlService::table_ddl(&state.db_pool, &schema, &table)
+        .await
+        .map_err(AppError::NotFound)?;
+
+    Ok(Json(serde_json::json!({
+        "schema": schema,
+        "table": table,
+        "ddl": ddl,
+    })))
 }
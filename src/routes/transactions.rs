@@ -0,0 +1,186 @@
+// Transaction routes
+// Begin, list, commit and roll back transaction sessions; statements run via
+// /api/query/execute with a `transaction` ID. The browser session remembers
+// its open transaction so the query page can pick it up again after a reload.
+// Transactions belong to the browser session that began them: other sessions
+// do not see them and get a 404 if they try to use, commit or roll them back.
+
+use crate::middleware::client_ip::ClientIp;
+use crate::middleware::connection::Connected;
+use crate::middleware::session::Session;
+use crate::services::audit_service::{AuditEvent, AuditEventType};
+use crate::services::transaction_service::TransactionInfo;
+use crate::AppState;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde_json::json;
+
+type ApiError = (StatusCode, Json<serde_json::Value>);
+
+/// Session key holding the ID of the browser's open transaction
+pub const SESSION_KEY: &str = "transaction";
+
+/// Session key holding the token the session's transactions are owned by
+///
+/// A random token rather than the session ID, which is only assigned once the
+/// session is first saved.
+const OWNER_KEY: &str = "transaction_owner";
+
+/// The token of the transactions this session owns, if it ever began one
+pub async fn owner(session: &Session) -> Result<Option<String>, tower_sessions::session::Error> {
+    session.get(OWNER_KEY).await
+}
+
+/// The session's owner token, created on first use
+async fn owner_or_create(session: &Session) -> Result<String, tower_sessions::session::Error> {
+    if let Some(owner) = owner(session).await? {
+        return Ok(owner);
+    }
+    let owner = uuid::Uuid::new_v4().to_string();
+    session.insert(OWNER_KEY, &owner).await?;
+    Ok(owner)
+}
+
+fn session_error(e: tower_sessions::session::Error) -> ApiError {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({ "error": format!("Session error: {}", e) })),
+    )
+}
+
+/// List the session's open transactions, oldest first
+pub async fn list_transactions(
+    State(state): State<AppState>,
+    session: Session,
+) -> Result<Json<Vec<TransactionInfo>>, ApiError> {
+    let transactions = match owner(&session).await.map_err(session_error)? {
+        Some(owner) => state.transactions.list(&owner).await,
+        None => Vec::new(),
+    };
+    Ok(Json(transactions))
+}
+
+/// Open a transaction on a dedicated connection to the selected server
+pub async fn begin_transaction(
+    Connected(state): Connected,
+    ClientIp(client_ip): ClientIp,
+    session: Session,
+) -> Result<(StatusCode, Json<serde_json::Value>), ApiError> {
+    let owner = owner_or_create(&session).await.map_err(session_error)?;
+    let info = state
+        .transactions
+        .begin(&state.db_pool, client_ip.clone(), owner.clone())
+        .await
+        .map_err(|e| (StatusCode::CONFLICT, Json(json!({ "error": e }))))?;
+
+    if let Err(e) = session.insert(SESSION_KEY, &info.id).await {
+        // Without the session's record, nobody could find the transaction again
+        state.transactions.rollback(&info.id, &owner).await.ok();
+        return Err(session_error(e));
+    }
+    audit(&state, client_ip, "BEGIN", &info.id, None).await;
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "transaction": info,
+            "idle_timeout_secs": state.transactions.idle_timeout().as_secs(),
+        })),
+    ))
+}
+
+/// Commit a transaction
+pub async fn commit_transaction(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    session: Session,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let result = match owner(&session).await.map_err(session_error)? {
+        Some(owner) => state.transactions.commit(&id, &owner).await,
+        None => Ok(false),
+    };
+    forget(&session, &id).await;
+    finish(
+        &state,
+        client_ip,
+        "COMMIT",
+        &id,
+        result,
+        "Transaction committed",
+    )
+    .await
+}
+
+/// Roll back a transaction
+pub async fn rollback_transaction(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    session: Session,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let result = match owner(&session).await.map_err(session_error)? {
+        Some(owner) => state.transactions.rollback(&id, &owner).await,
+        None => Ok(false),
+    };
+    forget(&session, &id).await;
+    finish(
+        &state,
+        client_ip,
+        "ROLLBACK",
+        &id,
+        result,
+        "Transaction rolled back",
+    )
+    .await
+}
+
+/// Drop the session's record of a transaction once it has ended
+async fn forget(session: &Session, id: &str) {
+    if let Ok(Some(current)) = session.get::<String>(SESSION_KEY).await {
+        if current == id {
+            // The record is loaded by now, so removing cannot fail
+            session.remove_value(SESSION_KEY).await.ok();
+        }
+    }
+}
+
+async fn finish(
+    state: &AppState,
+    client_ip: String,
+    command: &str,
+    id: &str,
+    result: Result<bool, String>,
+    message: &str,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    match result {
+        Ok(true) => {
+            audit(state, client_ip, command, id, None).await;
+            Ok(Json(json!({ "message": message })))
+        }
+        Ok(false) => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("Transaction {} is not open", id) })),
+        )),
+        Err(e) => {
+            audit(state, client_ip, command, id, Some(&e)).await;
+            Err((StatusCode::CONFLICT, Json(json!({ "error": e }))))
+        }
+    }
+}
+
+async fn audit(state: &AppState, client_ip: String, command: &str, id: &str, error: Option<&str>) {
+    let event = AuditEvent::new(
+        AuditEventType::QueryExecution,
+        client_ip,
+        command.to_string(),
+        format!("transaction:{}", id),
+    );
+    let event = match error {
+        Some(e) => event.with_success(false).with_details(e.to_string()),
+        None => event,
+    };
+    state.audit_logger.log(event).await;
+}
@@ -231,6 +231,7 @@ mod tests {
             is_nullable: false,
             is_pk: true,
             default: Some("nextval('users_id_seq')".to_string()),
+            comment: None,
         };
 
         assert_eq!(col.name, "id");
@@ -397,7 +398,17 @@ mod tests {
     fn test_valid_schema_names() {
         use crate::services::schema_ops_service::SchemaOpsService;
 
-        let valid_names = vec!["public", "user_data", "schema_v2", "_private", "ABC123"];
+        let valid_names = vec![
+            "public",
+            "user_data",
+            "schema_v2",
+            "_private",
+            "ABC123",
+            "user-data",
+            "user.data",
+            "user data",
+            "123abc",
+        ];
 
         for name in valid_names {
             assert!(
@@ -415,10 +426,7 @@ mod tests {
         let too_long = "a".repeat(64);
         let invalid_names = vec![
             "",                // empty
-            "user-data",       // hyphen
-            "user.data",       // dot
-            "user data",       // space
-            "123abc",          // starts with digit
+            "user\0data",      // NUL
             too_long.as_str(), // too long
         ];
 
@@ -2,6 +2,7 @@
 /// Tests for SQL injection, XSS, and other security concerns
 #[cfg(test)]
 mod tests {
+    use crate::services::identifier::{quote_ident, quote_qualified};
     use crate::services::query_service;
     use crate::services::schema_ops_service::SchemaOpsService;
 
@@ -171,27 +172,32 @@ mod tests {
     // ============================================================================
 
     #[test]
-    fn test_identifier_path_traversal_blocked() {
-        // Path traversal attempts in identifiers should be rejected
-        assert!(SchemaOpsService::validate_identifier("../etc/passwd").is_err());
-        assert!(SchemaOpsService::validate_identifier("..\\windows").is_err());
-        assert!(SchemaOpsService::validate_identifier("foo/../bar").is_err());
-    }
-
-    #[test]
-    fn test_identifier_sql_injection_blocked() {
-        // SQL injection in identifiers should be rejected
-        assert!(SchemaOpsService::validate_identifier("users; DROP TABLE--").is_err());
-        assert!(SchemaOpsService::validate_identifier("users'--").is_err());
-        assert!(SchemaOpsService::validate_identifier("users\"--").is_err());
+    fn test_identifier_sql_injection_quoted() {
+        // Any name is accepted; quoting keeps it a single identifier
+        for name in [
+            "users; DROP TABLE--",
+            "users'--",
+            "users\"--",
+            "../etc/passwd",
+        ] {
+            assert!(SchemaOpsService::validate_identifier(name).is_ok());
+        }
+        assert_eq!(quote_ident("users\"--"), "\"users\"\"--\"");
+        assert_eq!(
+            quote_ident("users; DROP TABLE--"),
+            "\"users; DROP TABLE--\""
+        );
+        assert_eq!(
+            quote_qualified("public", "x\"; DROP TABLE y; --"),
+            "public.\"x\"\"; DROP TABLE y; --\""
+        );
     }
 
     #[test]
-    fn test_identifier_special_chars_blocked() {
-        assert!(SchemaOpsService::validate_identifier("user-name").is_err());
-        assert!(SchemaOpsService::validate_identifier("user.name").is_err());
-        assert!(SchemaOpsService::validate_identifier("user name").is_err());
-        assert!(SchemaOpsService::validate_identifier("user;name").is_err());
+    fn test_identifier_special_chars_quoted() {
+        assert_eq!(quote_ident("user-name"), "\"user-name\"");
+        assert_eq!(quote_ident("user.name"), "\"user.name\"");
+        assert_eq!(quote_ident("User Name"), "\"User Name\"");
     }
 
     #[test]
@@ -214,9 +220,8 @@ mod tests {
     }
 
     #[test]
-    fn test_identifier_starts_with_digit_blocked() {
-        assert!(SchemaOpsService::validate_identifier("123users").is_err());
-        assert!(SchemaOpsService::validate_identifier("1table").is_err());
+    fn test_identifier_nul_blocked() {
+        assert!(SchemaOpsService::validate_identifier("users\0").is_err());
     }
 
     // ============================================================================
@@ -241,6 +246,7 @@ mod tests {
                 is_nullable: true,
                 is_pk: false,
                 default: None,
+                comment: None,
             }],
             rows: vec![vec![serde_json::Value::String(xss_payload.to_string())]],
             pagination: Pagination {
@@ -249,6 +255,7 @@ mod tests {
                 total_rows: 1,
                 total_pages: 1,
             },
+            sample: None,
         };
 
         let html = template.render().expect("Template should render");
@@ -285,6 +292,7 @@ mod tests {
                 is_nullable: true,
                 is_pk: false,
                 default: None,
+                comment: None,
             }],
             rows: vec![vec![serde_json::Value::String(xss_payload.to_string())]],
             pagination: Pagination {
@@ -293,6 +301,7 @@ mod tests {
                 total_rows: 1,
                 total_pages: 1,
             },
+            sample: None,
         };
 
         let html = template.render().expect("Template should render");
@@ -326,6 +335,7 @@ mod tests {
                 is_nullable: true,
                 is_pk: false,
                 default: None,
+                comment: None,
             }],
             rows: vec![],
             pagination: Pagination {
@@ -334,6 +344,7 @@ mod tests {
                 total_rows: 0,
                 total_pages: 0,
             },
+            sample: None,
         };
 
         let html = template.render().expect("Template should render");
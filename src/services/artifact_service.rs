@@ -0,0 +1,405 @@
+use crate::services::object_store::ObjectStore;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+/// Artifact Storage Service
+///
+/// Manages files written to disk by exports and backups:
+/// - Stores artifacts in a single configured directory
+/// - Lists stored artifacts with sizes and ages
+/// - Enforces a retention policy (max age, max total bytes)
+/// - Optionally offloads artifacts to an S3-compatible bucket, removing the
+///   local copy once uploaded
+///
+/// A background task calls `prune` periodically so the container volume
+/// doesn't silently fill up. Retention of offloaded copies is left to the
+/// bucket's lifecycle rules.
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How long a presigned download link stays valid
+const DOWNLOAD_URL_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// Retention policy for stored artifacts
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    /// Artifacts older than this are deleted
+    pub max_age: Duration,
+    /// When the directory exceeds this size, oldest artifacts are deleted first
+    pub max_total_bytes: u64,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_age: Duration::from_secs(7 * 24 * 60 * 60), // 7 days
+            max_total_bytes: 1024 * 1024 * 1024,            // 1 GiB
+        }
+    }
+}
+
+/// A file stored in the artifact directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtifactInfo {
+    pub name: String,
+    pub size_bytes: u64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Summary of a pruning pass
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PruneReport {
+    pub deleted: Vec<String>,
+    pub freed_bytes: u64,
+    pub remaining_bytes: u64,
+}
+
+/// Disk-backed store for export and backup artifacts
+pub struct ArtifactStore {
+    dir: PathBuf,
+    policy: RetentionPolicy,
+    object_store: Option<ObjectStore>,
+    /// Keep the local copy after offloading
+    keep_local: bool,
+}
+
+impl ArtifactStore {
+    /// Create a new artifact store rooted at `dir`
+    pub fn new(dir: impl Into<PathBuf>, policy: RetentionPolicy) -> Self {
+        Self {
+            dir: dir.into(),
+            policy,
+            object_store: None,
+            keep_local: true,
+        }
+    }
+
+    /// Offload artifacts to `object_store`, keeping local copies only if
+    /// `keep_local` is set
+    pub fn with_object_store(mut self, object_store: ObjectStore, keep_local: bool) -> Self {
+        self.object_store = Some(object_store);
+        self.keep_local = keep_local;
+        self
+    }
+
+    /// Whether artifacts are offloaded to object storage
+    pub fn offloads(&self) -> bool {
+        self.object_store.is_some()
+    }
+
+    /// Whether the local copy is kept after offloading
+    pub fn keeps_local(&self) -> bool {
+        self.keep_local
+    }
+
+    /// The retention policy in effect
+    pub fn policy(&self) -> RetentionPolicy {
+        self.policy
+    }
+
+    /// Write an artifact to disk, returning its stored info
+    pub async fn store(&self, name: &str, contents: &[u8]) -> Result<ArtifactInfo, String> {
+        let info = self.store_local(name, contents).await?;
+        if let Err(e) = self.offload(name).await {
+            tracing::warn!("{}; keeping the local copy", e);
+        }
+        Ok(info)
+    }
+
+    /// Write an artifact to disk only, for short-lived files that are read
+    /// back by the app and never worth offloading
+    pub async fn store_local(&self, name: &str, contents: &[u8]) -> Result<ArtifactInfo, String> {
+        let path = self.path_for(name)?;
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| format!("Failed to create artifact directory: {}", e))?;
+        tokio::fs::write(&path, contents)
+            .await
+            .map_err(|e| format!("Failed to write artifact {}: {}", name, e))?;
+
+        Ok(ArtifactInfo {
+            name: name.to_string(),
+            size_bytes: contents.len() as u64,
+            created_at: Utc::now(),
+        })
+    }
+
+    /// Path for a new artifact written by another program (e.g. `pg_dump`),
+    /// creating the directory
+    pub async fn create_path(&self, name: &str) -> Result<PathBuf, String> {
+        let path = self.path_for(name)?;
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| format!("Failed to create artifact directory: {}", e))?;
+        Ok(path)
+    }
+
+    /// Resolve the on-disk path of an artifact, rejecting path traversal
+    pub fn path_for(&self, name: &str) -> Result<PathBuf, String> {
+        Self::validate_name(name)?;
+        Ok(self.dir.join(name))
+    }
+
+    /// List all stored artifacts, oldest first
+    pub async fn list(&self) -> Result<Vec<ArtifactInfo>, String> {
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(format!("Failed to read artifact directory: {}", e)),
+        };
+
+        let mut artifacts = Vec::new();
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|e| format!("Failed to read artifact directory: {}", e))?
+        {
+            let metadata = match entry.metadata().await {
+                Ok(m) if m.is_file() => m,
+                _ => continue,
+            };
+            let created_at = metadata
+                .modified()
+                .map(DateTime::<Utc>::from)
+                .unwrap_or_else(|_| Utc::now());
+
+            artifacts.push(ArtifactInfo {
+                name: entry.file_name().to_string_lossy().to_string(),
+                size_bytes: metadata.len(),
+                created_at,
+            });
+        }
+
+        artifacts.sort_by_key(|a| a.created_at);
+        Ok(artifacts)
+    }
+
+    /// Upload a stored artifact to object storage, then delete the local
+    /// copy unless it is kept; `false` when there is no object storage
+    pub async fn offload(&self, name: &str) -> Result<bool, String> {
+        let Some(object_store) = &self.object_store else {
+            return Ok(false);
+        };
+        let path = self.path_for(name)?;
+        object_store.put_file(name, &path).await?;
+        if !self.keep_local {
+            tokio::fs::remove_file(&path)
+                .await
+                .map_err(|e| format!("Failed to delete offloaded artifact {}: {}", name, e))?;
+        }
+        Ok(true)
+    }
+
+    /// Offloaded artifacts whose name starts with `prefix`; empty when there
+    /// is no object storage
+    pub async fn list_offloaded(&self, prefix: &str) -> Result<Vec<ArtifactInfo>, String> {
+        match &self.object_store {
+            Some(object_store) => object_store.list(prefix).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// A temporary download link for an offloaded artifact
+    pub fn download_url(&self, name: &str) -> Option<String> {
+        Self::validate_name(name).ok()?;
+        Some(
+            self.object_store
+                .as_ref()?
+                .presigned_url(name, DOWNLOAD_URL_TTL),
+        )
+    }
+
+    /// Delete a single artifact, along with its offloaded copy
+    pub async fn delete(&self, name: &str) -> Result<(), String> {
+        let path = self.path_for(name)?;
+        let removed = tokio::fs::remove_file(&path).await;
+        let Some(object_store) = &self.object_store else {
+            return removed.map_err(|e| format!("Failed to delete artifact {}: {}", name, e));
+        };
+        object_store.delete(name).await?;
+        match removed {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!("Failed to delete artifact {}: {}", name, e))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Delete only the local copy of an artifact
+    pub async fn delete_local(&self, name: &str) -> Result<(), String> {
+        let path = self.path_for(name)?;
+        tokio::fs::remove_file(&path)
+            .await
+            .map_err(|e| format!("Failed to delete artifact {}: {}", name, e))
+    }
+
+    /// Apply the retention policy, deleting expired and over-quota artifacts
+    /// from disk (offloaded copies are left alone)
+    pub async fn prune(&self) -> Result<PruneReport, String> {
+        let artifacts = self.list().await?;
+        let to_delete = Self::select_for_pruning(&artifacts, &self.policy, Utc::now());
+
+        let mut report = PruneReport::default();
+        for artifact in &artifacts {
+            if !to_delete.contains(&artifact.name) {
+                report.remaining_bytes += artifact.size_bytes;
+                continue;
+            }
+            match self.delete_local(&artifact.name).await {
+                Ok(()) => {
+                    report.freed_bytes += artifact.size_bytes;
+                    report.deleted.push(artifact.name.clone());
+                }
+                Err(e) => {
+                    tracing::warn!("{}", e);
+                    report.remaining_bytes += artifact.size_bytes;
+                }
+            }
+        }
+
+        if !report.deleted.is_empty() {
+            tracing::info!(
+                "Pruned {} artifact(s), freed {} bytes",
+                report.deleted.len(),
+                report.freed_bytes
+            );
+        }
+
+        Ok(report)
+    }
+
+    /// Decide which artifacts violate the retention policy
+    ///
+    /// Expired artifacts are always removed; then the oldest remaining
+    /// artifacts are removed until the total fits within the byte quota.
+    pub fn select_for_pruning(
+        artifacts: &[ArtifactInfo],
+        policy: &RetentionPolicy,
+        now: DateTime<Utc>,
+    ) -> Vec<String> {
+        let max_age = chrono::Duration::from_std(policy.max_age).unwrap_or(chrono::Duration::MAX);
+
+        let mut sorted: Vec<&ArtifactInfo> = artifacts.iter().collect();
+        sorted.sort_by_key(|a| a.created_at);
+
+        let mut to_delete = Vec::new();
+        let mut kept = Vec::new();
+        for artifact in sorted {
+            if now - artifact.created_at > max_age {
+                to_delete.push(artifact.name.clone());
+            } else {
+                kept.push(artifact);
+            }
+        }
+
+        let mut total: u64 = kept.iter().map(|a| a.size_bytes).sum();
+        for artifact in kept {
+            if total <= policy.max_total_bytes {
+                break;
+            }
+            total -= artifact.size_bytes;
+            to_delete.push(artifact.name.clone());
+        }
+
+        to_delete
+    }
+
+    /// Artifact names must be plain file names
+    fn validate_name(name: &str) -> Result<(), String> {
+        if name.is_empty()
+            || name.starts_with('.')
+            || name.contains(['/', '\\'])
+            || Path::new(name).components().count() != 1
+        {
+            return Err(format!("Invalid artifact name: {}", name));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn artifact(name: &str, size_bytes: u64, age_hours: i64, now: DateTime<Utc>) -> ArtifactInfo {
+        ArtifactInfo {
+            name: name.to_string(),
+            size_bytes,
+            created_at: now - chrono::Duration::hours(age_hours),
+        }
+    }
+
+    #[test]
+    fn test_prune_expired_artifacts() {
+        let now = Utc::now();
+        let policy = RetentionPolicy {
+            max_age: Duration::from_secs(24 * 60 * 60),
+            max_total_bytes: u64::MAX,
+        };
+        let artifacts = vec![
+            artifact("old.csv", 10, 48, now),
+            artifact("new.csv", 10, 1, now),
+        ];
+
+        let deleted = ArtifactStore::select_for_pruning(&artifacts, &policy, now);
+        assert_eq!(deleted, vec!["old.csv".to_string()]);
+    }
+
+    #[test]
+    fn test_prune_oldest_when_over_quota() {
+        let now = Utc::now();
+        let policy = RetentionPolicy {
+            max_age: Duration::from_secs(365 * 24 * 60 * 60),
+            max_total_bytes: 250,
+        };
+        let artifacts = vec![
+            artifact("c.csv", 100, 1, now),
+            artifact("a.csv", 100, 3, now),
+            artifact("b.csv", 100, 2, now),
+        ];
+
+        let deleted = ArtifactStore::select_for_pruning(&artifacts, &policy, now);
+        assert_eq!(deleted, vec!["a.csv".to_string()]);
+    }
+
+    #[test]
+    fn test_prune_nothing_within_policy() {
+        let now = Utc::now();
+        let artifacts = vec![artifact("a.csv", 100, 1, now)];
+
+        let deleted =
+            ArtifactStore::select_for_pruning(&artifacts, &RetentionPolicy::default(), now);
+        assert!(deleted.is_empty());
+    }
+
+    #[test]
+    fn test_artifact_name_validation() {
+        let store = ArtifactStore::new("/tmp/artifacts", RetentionPolicy::default());
+        assert!(store.path_for("export_2024.csv").is_ok());
+        assert!(store.path_for("../etc/passwd").is_err());
+        assert!(store.path_for("nested/file.csv").is_err());
+        assert!(store.path_for(".hidden").is_err());
+        assert!(store.path_for("").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_store_list_and_prune() {
+        let dir = std::env::temp_dir().join(format!("pgadmin-artifacts-{}", uuid::Uuid::new_v4()));
+        let store = ArtifactStore::new(
+            &dir,
+            RetentionPolicy {
+                max_age: Duration::from_secs(3600),
+                max_total_bytes: 5,
+            },
+        );
+
+        store.store("first.csv", b"1234").await.unwrap();
+        store.store("second.csv", b"5678").await.unwrap();
+        assert_eq!(store.list().await.unwrap().len(), 2);
+
+        let report = store.prune().await.unwrap();
+        assert_eq!(report.deleted.len(), 1);
+        assert_eq!(report.remaining_bytes, 4);
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
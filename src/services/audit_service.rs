@@ -8,6 +8,9 @@
 /// - Compliance auditing (GDPR, HIPAA, SOC 2, etc.)
 /// - Forensic analysis
 /// - Performance troubleshooting
+use crate::middleware::request_id;
+use crate::services::audit_sink::AuditForwarder;
+use crate::services::webhook_service::{Notification, Webhooks};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
@@ -38,6 +41,53 @@ pub enum AuditEventType {
     AccessDenied,
     /// Configuration change
     ConfigurationChange,
+    /// Database backup (pg_dump)
+    Backup,
+}
+
+impl AuditEventType {
+    /// Every event type, in declaration order
+    pub const ALL: [AuditEventType; 12] = [
+        AuditEventType::QueryExecution,
+        AuditEventType::AuthenticationAttempt,
+        AuditEventType::AuthenticationSuccess,
+        AuditEventType::AuthenticationFailure,
+        AuditEventType::SchemaModification,
+        AuditEventType::DataModification,
+        AuditEventType::RateLimitExceeded,
+        AuditEventType::SqlError,
+        AuditEventType::DangerousQueryDetected,
+        AuditEventType::AccessDenied,
+        AuditEventType::ConfigurationChange,
+        AuditEventType::Backup,
+    ];
+
+    /// Name as serialized (e.g. `QueryExecution`)
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditEventType::QueryExecution => "QueryExecution",
+            AuditEventType::AuthenticationAttempt => "AuthenticationAttempt",
+            AuditEventType::AuthenticationSuccess => "AuthenticationSuccess",
+            AuditEventType::AuthenticationFailure => "AuthenticationFailure",
+            AuditEventType::SchemaModification => "SchemaModification",
+            AuditEventType::DataModification => "DataModification",
+            AuditEventType::RateLimitExceeded => "RateLimitExceeded",
+            AuditEventType::SqlError => "SqlError",
+            AuditEventType::DangerousQueryDetected => "DangerousQueryDetected",
+            AuditEventType::AccessDenied => "AccessDenied",
+            AuditEventType::ConfigurationChange => "ConfigurationChange",
+            AuditEventType::Backup => "Backup",
+        }
+    }
+
+    /// Parse an event type name, ignoring case and underscores
+    /// (`QueryExecution`, `query_execution`)
+    pub fn parse(value: &str) -> Option<Self> {
+        let normalized = value.replace('_', "");
+        Self::ALL
+            .into_iter()
+            .find(|t| t.as_str().eq_ignore_ascii_case(&normalized))
+    }
 }
 
 /// Audit event that gets logged
@@ -61,6 +111,9 @@ pub struct AuditEvent {
     pub success: bool,
     /// Detailed information about the event
     pub details: Option<String>,
+    /// Id of the HTTP request that triggered the event
+    #[serde(default)]
+    pub request_id: Option<String>,
 }
 
 impl AuditEvent {
@@ -81,6 +134,7 @@ impl AuditEvent {
             resource,
             success: true,
             details: None,
+            request_id: None,
         }
     }
 
@@ -103,6 +157,42 @@ impl AuditEvent {
     }
 }
 
+/// Criteria for selecting audit events; unset fields match everything
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditFilter {
+    pub event_type: Option<AuditEventType>,
+    pub client_ip: Option<String>,
+    pub user_id: Option<String>,
+    /// Inclusive lower bound on the event timestamp
+    pub from: Option<DateTime<Utc>>,
+    /// Inclusive upper bound on the event timestamp
+    pub to: Option<DateTime<Utc>>,
+    pub success: Option<bool>,
+    pub request_id: Option<String>,
+}
+
+impl AuditFilter {
+    /// Check whether an event satisfies every set criterion
+    pub fn matches(&self, event: &AuditEvent) -> bool {
+        self.event_type.is_none_or(|t| event.event_type == t)
+            && self
+                .client_ip
+                .as_deref()
+                .is_none_or(|ip| event.client_ip == ip)
+            && self
+                .user_id
+                .as_deref()
+                .is_none_or(|user| event.user_id.as_deref() == Some(user))
+            && self.from.is_none_or(|from| event.timestamp >= from)
+            && self.to.is_none_or(|to| event.timestamp <= to)
+            && self.success.is_none_or(|success| event.success == success)
+            && self
+                .request_id
+                .as_deref()
+                .is_none_or(|id| event.request_id.as_deref() == Some(id))
+    }
+}
+
 /// Audit logger that stores events in memory and can be extended to persist to database
 ///
 /// This implementation stores logs in memory for development and testing.
@@ -112,6 +202,19 @@ pub struct AuditLogger {
     events: Arc<RwLock<Vec<AuditEvent>>>,
     /// Maximum number of events to keep in memory
     max_events: usize,
+    /// Events older than this are removed by `prune`
+    max_age: Option<chrono::Duration>,
+    /// Optional forwarding to external sinks (syslog, webhook)
+    forwarder: Option<AuditForwarder>,
+    /// Endpoints notified of schema changes, failed queries and backups
+    webhooks: Option<Arc<Webhooks>>,
+}
+
+/// Outcome of an audit pruning run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditPruneReport {
+    pub deleted: usize,
+    pub remaining: usize,
 }
 
 impl AuditLogger {
@@ -120,11 +223,71 @@ impl AuditLogger {
         Self {
             events: Arc::new(RwLock::new(Vec::with_capacity(max_events))),
             max_events,
+            max_age: None,
+            forwarder: None,
+            webhooks: None,
+        }
+    }
+
+    /// Remove events older than `max_age` when pruning
+    pub fn with_max_age(mut self, max_age: chrono::Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Retention settings in effect: (max age, max events)
+    pub fn retention(&self) -> (Option<chrono::Duration>, usize) {
+        (self.max_age, self.max_events)
+    }
+
+    /// Apply the age-based retention policy
+    pub async fn prune(&self) -> AuditPruneReport {
+        match self.max_age {
+            Some(max_age) => self.prune_before(Utc::now() - max_age).await,
+            None => AuditPruneReport {
+                deleted: 0,
+                remaining: self.events.read().await.len(),
+            },
+        }
+    }
+
+    /// Remove every event recorded before `cutoff`
+    pub async fn prune_before(&self, cutoff: DateTime<Utc>) -> AuditPruneReport {
+        let mut events = self.events.write().await;
+        let before = events.len();
+        events.retain(|e| e.timestamp >= cutoff);
+
+        AuditPruneReport {
+            deleted: before - events.len(),
+            remaining: events.len(),
+        }
+    }
+
+    /// Forward every logged event to external sinks
+    pub fn with_forwarder(mut self, forwarder: AuditForwarder) -> Self {
+        self.forwarder = Some(forwarder);
+        self
+    }
+
+    /// Notify webhooks of the events they subscribe to (DDL, SQL errors,
+    /// backups)
+    pub fn with_webhooks(mut self, webhooks: Arc<Webhooks>) -> Self {
+        self.webhooks = Some(webhooks);
+        self
+    }
+
+    /// Wait for events queued to external sinks to be delivered
+    pub async fn flush(&self) {
+        if let Some(forwarder) = &self.forwarder {
+            forwarder.flush().await;
         }
     }
 
     /// Log an audit event
-    pub async fn log(&self, event: AuditEvent) {
+    pub async fn log(&self, mut event: AuditEvent) {
+        if event.request_id.is_none() {
+            event.request_id = request_id::current();
+        }
         let mut events = self.events.write().await;
 
         // Log to standard error for immediate visibility (important for security)
@@ -138,9 +301,19 @@ impl AuditLogger {
             resource = %event.resource,
             success = event.success,
             details = ?event.details,
+            request_id = ?event.request_id,
             "Audit event logged"
         );
 
+        if let Some(forwarder) = &self.forwarder {
+            forwarder.forward(event.clone());
+        }
+        if let Some(webhooks) = &self.webhooks {
+            if let Some(notification) = Notification::from_audit(&event) {
+                webhooks.emit(notification);
+            }
+        }
+
         events.push(event);
 
         // Keep only the last max_events in memory
@@ -150,6 +323,19 @@ impl AuditLogger {
         }
     }
 
+    /// Log the outcome of an operation, recording the error as details on failure
+    pub async fn log_result<T, E: std::fmt::Display>(
+        &self,
+        event: AuditEvent,
+        result: &Result<T, E>,
+    ) {
+        let event = match result {
+            Ok(_) => event,
+            Err(e) => event.with_success(false).with_details(e.to_string()),
+        };
+        self.log(event).await;
+    }
+
     /// Get all audit events (for testing/debugging)
     pub async fn get_events(&self) -> Vec<AuditEvent> {
         self.events.read().await.clone()
@@ -181,6 +367,17 @@ impl AuditLogger {
             .collect()
     }
 
+    /// Get events matching a filter, newest first
+    pub async fn query(&self, filter: &AuditFilter) -> Vec<AuditEvent> {
+        let events = self.events.read().await;
+        events
+            .iter()
+            .rev()
+            .filter(|e| filter.matches(e))
+            .cloned()
+            .collect()
+    }
+
     /// Clear all events (useful for testing)
     #[cfg(test)]
     pub async fn clear(&self) {
@@ -339,4 +536,184 @@ mod tests {
         assert_eq!(recent.len(), 2);
         // Most recent should be last in the list (reversed)
     }
+
+    #[tokio::test]
+    async fn test_audit_logger_log_result() {
+        let logger = AuditLogger::new(10);
+        let event = || {
+            AuditEvent::new(
+                AuditEventType::SchemaModification,
+                "127.0.0.1".to_string(),
+                "create_table".to_string(),
+                "public.t".to_string(),
+            )
+        };
+
+        logger.log_result(event(), &Ok::<(), String>(())).await;
+        logger
+            .log_result(event(), &Err::<(), _>("permission denied".to_string()))
+            .await;
+
+        let events = logger.get_events().await;
+        assert!(events[0].success);
+        assert!(!events[1].success);
+        assert_eq!(events[1].details.as_deref(), Some("permission denied"));
+    }
+
+    #[tokio::test]
+    async fn test_audit_logger_prune() {
+        let logger = AuditLogger::new(10).with_max_age(chrono::Duration::days(30));
+
+        let mut old = AuditEvent::new(
+            AuditEventType::QueryExecution,
+            "127.0.0.1".to_string(),
+            "Old query".to_string(),
+            "test".to_string(),
+        );
+        old.timestamp = Utc::now() - chrono::Duration::days(31);
+        logger.log(old).await;
+        logger
+            .log(AuditEvent::new(
+                AuditEventType::QueryExecution,
+                "127.0.0.1".to_string(),
+                "New query".to_string(),
+                "test".to_string(),
+            ))
+            .await;
+
+        let report = logger.prune().await;
+        assert_eq!(report.deleted, 1);
+        assert_eq!(report.remaining, 1);
+        assert_eq!(logger.get_events().await[0].action, "New query");
+
+        let report = logger
+            .prune_before(Utc::now() + chrono::Duration::seconds(1))
+            .await;
+        assert_eq!(report.deleted, 1);
+        assert_eq!(report.remaining, 0);
+    }
+
+    #[tokio::test]
+    async fn test_audit_logger_prune_without_max_age() {
+        let logger = AuditLogger::new(10);
+        logger
+            .log(AuditEvent::new(
+                AuditEventType::QueryExecution,
+                "127.0.0.1".to_string(),
+                "Query".to_string(),
+                "test".to_string(),
+            ))
+            .await;
+
+        let report = logger.prune().await;
+        assert_eq!(report.deleted, 0);
+        assert_eq!(report.remaining, 1);
+    }
+
+    #[test]
+    fn test_audit_event_type_parse() {
+        assert_eq!(
+            AuditEventType::parse("QueryExecution"),
+            Some(AuditEventType::QueryExecution)
+        );
+        assert_eq!(
+            AuditEventType::parse("rate_limit_exceeded"),
+            Some(AuditEventType::RateLimitExceeded)
+        );
+        assert_eq!(AuditEventType::parse("unknown"), None);
+
+        for event_type in AuditEventType::ALL {
+            let json = serde_json::to_value(event_type).unwrap();
+            assert_eq!(json, event_type.as_str());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_audit_logger_query_filter() {
+        let logger = AuditLogger::new(10);
+
+        logger
+            .log(AuditEvent::new(
+                AuditEventType::QueryExecution,
+                "10.0.0.1".to_string(),
+                "Query 1".to_string(),
+                "test".to_string(),
+            ))
+            .await;
+        logger
+            .log(
+                AuditEvent::new(
+                    AuditEventType::QueryExecution,
+                    "10.0.0.2".to_string(),
+                    "Query 2".to_string(),
+                    "test".to_string(),
+                )
+                .with_success(false)
+                .with_user("alice".to_string()),
+            )
+            .await;
+        logger
+            .log(AuditEvent::new(
+                AuditEventType::SchemaModification,
+                "10.0.0.1".to_string(),
+                "DROP TABLE t".to_string(),
+                "public.t".to_string(),
+            ))
+            .await;
+
+        let all = logger.query(&AuditFilter::default()).await;
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[0].action, "DROP TABLE t"); // newest first
+
+        let failed = logger
+            .query(&AuditFilter {
+                success: Some(false),
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].user_id.as_deref(), Some("alice"));
+
+        let queries_from_ip = logger
+            .query(&AuditFilter {
+                event_type: Some(AuditEventType::QueryExecution),
+                client_ip: Some("10.0.0.1".to_string()),
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(queries_from_ip.len(), 1);
+        assert_eq!(queries_from_ip[0].action, "Query 1");
+
+        let future = logger
+            .query(&AuditFilter {
+                from: Some(Utc::now() + chrono::Duration::hours(1)),
+                ..Default::default()
+            })
+            .await;
+        assert!(future.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_events_tagged_with_request_id() {
+        let logger = AuditLogger::new(100);
+        let event = || {
+            AuditEvent::new(
+                AuditEventType::QueryExecution,
+                "10.0.0.1".to_string(),
+                "SELECT 1".to_string(),
+                "query".to_string(),
+            )
+        };
+        request_id::scope("req-1".to_string(), logger.log(event())).await;
+        logger.log(event()).await;
+
+        let tagged = logger
+            .query(&AuditFilter {
+                request_id: Some("req-1".to_string()),
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(tagged.len(), 1);
+        assert_eq!(tagged[0].request_id.as_deref(), Some("req-1"));
+    }
 }
@@ -0,0 +1,289 @@
+use crate::services::audit_service::AuditEvent;
+/// Audit Sinks
+///
+/// Forward audit events to external collectors so they land in a SIEM
+/// (Splunk, ELK, ...) without custom glue:
+/// - Syslog: RFC 5424 messages over UDP with the event as JSON payload
+/// - Webhook: JSON `POST` of each event to an HTTP endpoint
+/// - Plugin: any destination a plugin implements with `CustomSink`
+///
+/// Delivery happens on a background task fed by a bounded channel, so a slow
+/// or unreachable collector never delays request handling. Events are dropped
+/// (with a warning) when the channel is full.
+use crate::services::notify;
+use futures::future::BoxFuture;
+use hyper::Uri;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, oneshot};
+
+/// Events waiting for delivery before new ones are dropped
+const SINK_QUEUE_CAPACITY: usize = 1024;
+
+/// Per-event delivery timeout
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// RFC 5424 facility 13 ("log audit")
+const SYSLOG_FACILITY: u8 = 13;
+
+/// An audit event destination contributed by a plugin
+pub trait CustomSink: Send + Sync {
+    /// Short description for logs
+    fn describe(&self) -> String;
+
+    /// Deliver a single event; the forwarder applies the delivery timeout
+    fn send<'a>(&'a self, event: &'a AuditEvent) -> BoxFuture<'a, Result<(), String>>;
+}
+
+impl std::fmt::Debug for dyn CustomSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CustomSink({})", self.describe())
+    }
+}
+
+impl PartialEq for dyn CustomSink {
+    fn eq(&self, other: &Self) -> bool {
+        self.describe() == other.describe()
+    }
+}
+
+/// A destination for audit events
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuditSink {
+    /// Syslog collector address (`host:port`, UDP)
+    Syslog {
+        addr: String,
+        app_name: String,
+    },
+    /// HTTP endpoint that receives each event as a JSON body
+    Webhook {
+        url: Uri,
+    },
+    Plugin(Arc<dyn CustomSink>),
+}
+
+impl AuditSink {
+    /// Build a webhook sink, accepting only `http://` URLs
+    pub fn webhook(url: &str) -> Result<Self, String> {
+        let url = notify::webhook_url(url).map_err(|e| format!("Audit sink: {}", e))?;
+        Ok(AuditSink::Webhook { url })
+    }
+
+    /// Short description for logs
+    pub fn describe(&self) -> String {
+        match self {
+            AuditSink::Syslog { addr, .. } => format!("syslog udp://{}", addr),
+            AuditSink::Webhook { url } => format!("webhook {}", url),
+            AuditSink::Plugin(sink) => sink.describe(),
+        }
+    }
+
+    /// Deliver a single event
+    pub async fn send(&self, event: &AuditEvent) -> Result<(), String> {
+        let delivery = async {
+            match self {
+                AuditSink::Syslog { addr, app_name } => send_syslog(addr, app_name, event).await,
+                AuditSink::Webhook { url } => send_webhook(url, event).await,
+                AuditSink::Plugin(sink) => sink.send(event).await,
+            }
+        };
+
+        tokio::time::timeout(DELIVERY_TIMEOUT, delivery)
+            .await
+            .map_err(|_| format!("Timed out delivering audit event to {}", self.describe()))?
+    }
+}
+
+/// Work for the background forwarder
+enum Message {
+    Event(AuditEvent),
+    /// Answered once every event queued before it has been handled
+    Flush(oneshot::Sender<()>),
+}
+
+/// Handle for queueing events to the background forwarder
+#[derive(Clone)]
+pub struct AuditForwarder {
+    sender: mpsc::Sender<Message>,
+}
+
+impl AuditForwarder {
+    /// Spawn the background task that delivers events to every sink
+    pub fn spawn(sinks: Vec<AuditSink>) -> Self {
+        let (sender, mut receiver) = mpsc::channel::<Message>(SINK_QUEUE_CAPACITY);
+
+        tokio::spawn(async move {
+            while let Some(message) = receiver.recv().await {
+                match message {
+                    Message::Event(event) => {
+                        for sink in &sinks {
+                            if let Err(e) = sink.send(&event).await {
+                                tracing::warn!("Audit forwarding failed: {}", e);
+                            }
+                        }
+                    }
+                    Message::Flush(done) => {
+                        let _ = done.send(());
+                    }
+                }
+            }
+        });
+
+        Self { sender }
+    }
+
+    /// Queue an event without waiting for delivery
+    pub fn forward(&self, event: AuditEvent) {
+        if let Err(e) = self.sender.try_send(Message::Event(event)) {
+            tracing::warn!("Dropping audit event for forwarding: {}", e);
+        }
+    }
+
+    /// Wait until every event queued so far has been delivered (or failed)
+    pub async fn flush(&self) {
+        let (done, flushed) = oneshot::channel();
+        if self.sender.send(Message::Flush(done)).await.is_ok() {
+            let _ = flushed.await;
+        }
+    }
+}
+
+/// Format an event as an RFC 5424 syslog message
+pub fn syslog_message(app_name: &str, event: &AuditEvent) -> String {
+    // Informational for successful actions, warning for failures
+    let severity = if event.success { 6 } else { 4 };
+    let priority = SYSLOG_FACILITY * 8 + severity;
+    let hostname = std::env::var("HOSTNAME").unwrap_or_else(|_| "-".to_string());
+    let payload = serde_json::to_string(event).unwrap_or_default();
+
+    format!(
+        "<{}>1 {} {} {} {} {} - {}",
+        priority,
+        event
+            .timestamp
+            .to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        hostname,
+        app_name,
+        std::process::id(),
+        event.event_type.as_str(),
+        payload
+    )
+}
+
+async fn send_syslog(addr: &str, app_name: &str, event: &AuditEvent) -> Result<(), String> {
+    let socket = UdpSocket::bind("0.0.0.0:0")
+        .await
+        .map_err(|e| format!("Failed to open syslog socket: {}", e))?;
+    socket
+        .send_to(syslog_message(app_name, event).as_bytes(), addr)
+        .await
+        .map_err(|e| format!("Failed to send syslog message to {}: {}", addr, e))?;
+    Ok(())
+}
+
+async fn send_webhook(url: &Uri, event: &AuditEvent) -> Result<(), String> {
+    let body =
+        serde_json::to_vec(event).map_err(|e| format!("Failed to serialize audit event: {}", e))?;
+    notify::post_json(url, body).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::audit_service::AuditEventType;
+
+    fn event(success: bool) -> AuditEvent {
+        AuditEvent::new(
+            AuditEventType::SchemaModification,
+            "10.0.0.1".to_string(),
+            "DROP TABLE t".to_string(),
+            "public.t".to_string(),
+        )
+        .with_success(success)
+    }
+
+    #[test]
+    fn test_syslog_message_format() {
+        let message = syslog_message("pgadmin-rs", &event(true));
+        assert!(message.starts_with("<110>1 "));
+        assert!(message.contains(" pgadmin-rs "));
+        assert!(message.contains(" SchemaModification - {"));
+        assert!(message.ends_with('}'));
+
+        let failed = syslog_message("pgadmin-rs", &event(false));
+        assert!(failed.starts_with("<108>1 "));
+    }
+
+    #[test]
+    fn test_webhook_url_validation() {
+        assert!(AuditSink::webhook("http://collector:8080/audit").is_ok());
+        assert!(AuditSink::webhook("https://collector/audit").is_err());
+        assert!(AuditSink::webhook("collector:8080").is_err());
+        assert!(AuditSink::webhook("not a url").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_syslog_delivery() {
+        let collector = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let sink = AuditSink::Syslog {
+            addr: collector.local_addr().unwrap().to_string(),
+            app_name: "pgadmin-rs".to_string(),
+        };
+
+        sink.send(&event(true)).await.unwrap();
+
+        let mut buf = [0u8; 4096];
+        let len = collector.recv(&mut buf).await.unwrap();
+        let received = String::from_utf8_lossy(&buf[..len]);
+        assert!(received.contains("DROP TABLE t"));
+    }
+
+    /// Keeps the actions of delivered events
+    struct Recorder(parking_lot::Mutex<Vec<String>>);
+
+    impl CustomSink for Recorder {
+        fn describe(&self) -> String {
+            "recorder".to_string()
+        }
+
+        fn send<'a>(&'a self, event: &'a AuditEvent) -> BoxFuture<'a, Result<(), String>> {
+            Box::pin(async move {
+                self.0.lock().push(event.action.clone());
+                Ok(())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_plugin_sink_delivery() {
+        let recorder = Arc::new(Recorder(parking_lot::Mutex::new(Vec::new())));
+        let sink = AuditSink::Plugin(recorder.clone());
+        assert_eq!(sink.describe(), "recorder");
+
+        let forwarder = AuditForwarder::spawn(vec![sink]);
+        forwarder.forward(event(true));
+        forwarder.flush().await;
+        assert_eq!(*recorder.0.lock(), ["DROP TABLE t"]);
+    }
+
+    #[tokio::test]
+    async fn test_flush_waits_for_queued_events() {
+        let collector = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let forwarder = AuditForwarder::spawn(vec![AuditSink::Syslog {
+            addr: collector.local_addr().unwrap().to_string(),
+            app_name: "pgadmin-rs".to_string(),
+        }]);
+
+        forwarder.forward(event(true));
+        forwarder.forward(event(false));
+        forwarder.flush().await;
+
+        // Both datagrams were sent before the flush returned
+        let mut buf = [0u8; 4096];
+        for _ in 0..2 {
+            let len = collector.try_recv(&mut buf).unwrap();
+            assert!(String::from_utf8_lossy(&buf[..len]).contains("DROP TABLE t"));
+        }
+    }
+}
@@ -0,0 +1,1005 @@
+use crate::config::Config;
+use crate::services::artifact_service::{ArtifactInfo, ArtifactStore};
+use crate::services::audit_service::{AuditEvent, AuditEventType, AuditLogger};
+use crate::services::cron::CronSchedule;
+use crate::services::json_file;
+use crate::services::notify;
+use crate::services::scheduler_service::TICK_INTERVAL;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+/// Backup Service
+///
+/// Dumps databases of the default connection with `pg_dump`, on demand or on
+/// cron schedules (e.g. nightly):
+/// - Dumps are written to the artifact directory as
+///   `backup_<tag>_<UTC timestamp>_<database>.<dump|sql>`, where the tag is
+///   the schedule's ID or `manual`
+/// - After each scheduled dump, older dumps of the same schedule are rotated
+///   out by count and age; the artifact retention policy still applies on top
+/// - Every dump is recorded as a `Backup` audit event, and a schedule can
+///   post each outcome to a webhook
+/// - With object storage configured, each dump is uploaded after it is taken
+///   and listings, downloads, deletion and rotation cover the offloaded copies
+///
+/// Schedules and recent runs are persisted to a JSON file. Like query
+/// schedules, runs missed while the server was down are skipped.
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::RwLock;
+
+/// Runs kept in the history (oldest are dropped first)
+const MAX_RUNS: usize = 200;
+
+/// Tag of backups taken outside a schedule
+pub const MANUAL_TAG: &str = "manual";
+
+const NAME_PREFIX: &str = "backup_";
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// `pg_dump` output format
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupFormat {
+    /// `pg_dump -Fc`, restorable with `pg_restore` or `POST /api/restore`
+    #[default]
+    Custom,
+    /// A SQL script
+    Plain,
+}
+
+impl BackupFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            BackupFormat::Custom => "dump",
+            BackupFormat::Plain => "sql",
+        }
+    }
+
+    fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "dump" => Some(BackupFormat::Custom),
+            "sql" => Some(BackupFormat::Plain),
+            _ => None,
+        }
+    }
+
+    fn pg_dump_arg(self) -> &'static str {
+        match self {
+            BackupFormat::Custom => "--format=custom",
+            BackupFormat::Plain => "--format=plain",
+        }
+    }
+}
+
+/// How many of a schedule's backups are kept; unset limits keep everything
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Rotation {
+    /// Keep only the newest N backups
+    #[serde(default)]
+    pub keep_last: Option<usize>,
+    /// Delete backups older than this many days
+    #[serde(default)]
+    pub max_age_days: Option<u32>,
+}
+
+/// A database dumped on a cron expression
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackupSchedule {
+    pub id: String,
+    pub name: String,
+    /// Database to dump; the connection's own when unset
+    pub database: Option<String>,
+    /// Five-field cron expression, evaluated in UTC
+    pub cron: String,
+    #[serde(default)]
+    pub format: BackupFormat,
+    #[serde(default)]
+    pub rotation: Rotation,
+    /// Every run is posted here as JSON
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub next_run_at: Option<DateTime<Utc>>,
+}
+
+/// Fields supplied when creating or updating a backup schedule
+#[derive(Debug, Clone, Deserialize)]
+pub struct BackupScheduleInput {
+    pub name: String,
+    #[serde(default)]
+    pub database: Option<String>,
+    pub cron: String,
+    #[serde(default)]
+    pub format: BackupFormat,
+    #[serde(default)]
+    pub rotation: Rotation,
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Where the copies of a backup are
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackupLocation {
+    /// Only in the artifact directory
+    #[default]
+    Local,
+    /// Only in object storage
+    Remote,
+    /// In both
+    Both,
+}
+
+/// A backup file in the artifact directory or object storage
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackupFile {
+    pub name: String,
+    pub database: String,
+    /// Schedule that took it, unset for manual backups
+    pub schedule_id: Option<String>,
+    pub format: BackupFormat,
+    pub size_bytes: u64,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub location: BackupLocation,
+}
+
+impl BackupFile {
+    /// Recognize a backup among the stored artifacts
+    pub fn from_artifact(artifact: &ArtifactInfo) -> Option<Self> {
+        let (tag, created_at, database, format) = parse_backup_name(&artifact.name)?;
+        Some(BackupFile {
+            name: artifact.name.clone(),
+            database,
+            schedule_id: (tag != MANUAL_TAG).then_some(tag),
+            format,
+            size_bytes: artifact.size_bytes,
+            created_at,
+            location: BackupLocation::Local,
+        })
+    }
+}
+
+/// Where a backup can be downloaded from
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackupSource {
+    Local(PathBuf),
+    /// A presigned object storage URL
+    Remote(String),
+}
+
+/// The outcome of one backup
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackupRun {
+    pub id: String,
+    pub schedule_id: Option<String>,
+    pub database: String,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    pub success: bool,
+    pub file: Option<BackupFile>,
+    pub error: Option<String>,
+    /// Older backups deleted by the schedule's rotation
+    #[serde(default)]
+    pub rotated: Vec<String>,
+    /// Why the webhook could not be notified, if it was not
+    #[serde(default)]
+    pub delivery_error: Option<String>,
+    /// Why the dump could not be offloaded; the local copy is kept
+    #[serde(default)]
+    pub upload_error: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct BackupData {
+    schedules: Vec<BackupSchedule>,
+    runs: Vec<BackupRun>,
+}
+
+/// Backup schedules and recent runs, persisted to a JSON file
+pub struct BackupScheduleStore {
+    path: PathBuf,
+    data: RwLock<BackupData>,
+}
+
+impl BackupScheduleStore {
+    /// Load schedules from `path`, starting empty if the file does not exist
+    pub async fn load(path: impl Into<PathBuf>) -> Result<Self, String> {
+        let path = path.into();
+        let mut data: BackupData = json_file::load(&path, "backup schedules").await?;
+
+        // Skip runs that were missed while the server was down
+        let now = Utc::now();
+        for schedule in &mut data.schedules {
+            if schedule.next_run_at.is_none_or(|next| next <= now) {
+                schedule.next_run_at = next_run(schedule, now);
+            }
+        }
+
+        Ok(Self {
+            path,
+            data: RwLock::new(data),
+        })
+    }
+
+    /// All schedules, ordered by name
+    pub async fn list(&self) -> Vec<BackupSchedule> {
+        let mut schedules = self.data.read().await.schedules.clone();
+        schedules.sort_by_key(|s| s.name.to_lowercase());
+        schedules
+    }
+
+    pub async fn get(&self, id: &str) -> Option<BackupSchedule> {
+        let data = self.data.read().await;
+        data.schedules.iter().find(|s| s.id == id).cloned()
+    }
+
+    /// Create a schedule from validated input
+    pub async fn create(&self, input: BackupScheduleInput) -> Result<BackupSchedule, String> {
+        let now = Utc::now();
+        let mut schedule = BackupSchedule {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: input.name,
+            database: input.database,
+            cron: input.cron,
+            format: input.format,
+            rotation: input.rotation,
+            webhook_url: input.webhook_url,
+            enabled: input.enabled,
+            created_at: now,
+            updated_at: now,
+            last_run_at: None,
+            next_run_at: None,
+        };
+        schedule.next_run_at = next_run(&schedule, now);
+
+        let mut data = self.data.write().await;
+        data.schedules.push(schedule.clone());
+        self.persist(&data).await?;
+        Ok(schedule)
+    }
+
+    /// Replace a schedule's fields from validated input, returning `None` if it does not exist
+    pub async fn update(
+        &self,
+        id: &str,
+        input: BackupScheduleInput,
+    ) -> Result<Option<BackupSchedule>, String> {
+        let now = Utc::now();
+        let mut data = self.data.write().await;
+        let Some(schedule) = data.schedules.iter_mut().find(|s| s.id == id) else {
+            return Ok(None);
+        };
+
+        schedule.name = input.name;
+        schedule.database = input.database;
+        schedule.cron = input.cron;
+        schedule.format = input.format;
+        schedule.rotation = input.rotation;
+        schedule.webhook_url = input.webhook_url;
+        schedule.enabled = input.enabled;
+        schedule.updated_at = now;
+        schedule.next_run_at = next_run(schedule, now);
+        let updated = schedule.clone();
+
+        self.persist(&data).await?;
+        Ok(Some(updated))
+    }
+
+    /// Delete a schedule, returning whether it existed; its backups are kept
+    pub async fn delete(&self, id: &str) -> Result<bool, String> {
+        let mut data = self.data.write().await;
+        let before = data.schedules.len();
+        data.schedules.retain(|s| s.id != id);
+        if data.schedules.len() == before {
+            return Ok(false);
+        }
+        self.persist(&data).await?;
+        Ok(true)
+    }
+
+    /// Recent runs, newest first, optionally of one schedule
+    pub async fn runs(&self, schedule_id: Option<&str>) -> Vec<BackupRun> {
+        let data = self.data.read().await;
+        data.runs
+            .iter()
+            .rev()
+            .filter(|r| schedule_id.is_none_or(|id| r.schedule_id.as_deref() == Some(id)))
+            .cloned()
+            .collect()
+    }
+
+    /// Claim the schedules due at `now`, advancing their next run time
+    pub async fn take_due(&self, now: DateTime<Utc>) -> Result<Vec<BackupSchedule>, String> {
+        let mut data = self.data.write().await;
+        let mut due = Vec::new();
+        for schedule in &mut data.schedules {
+            if schedule.enabled && schedule.next_run_at.is_some_and(|next| next <= now) {
+                schedule.next_run_at = next_run(schedule, now);
+                due.push(schedule.clone());
+            }
+        }
+
+        if !due.is_empty() {
+            self.persist(&data).await?;
+        }
+        Ok(due)
+    }
+
+    /// Store a run, keeping only the most recent runs
+    pub async fn record_run(&self, run: BackupRun) -> Result<(), String> {
+        let mut data = self.data.write().await;
+        if let Some(id) = &run.schedule_id {
+            if let Some(schedule) = data.schedules.iter_mut().find(|s| &s.id == id) {
+                schedule.last_run_at = Some(run.started_at);
+            }
+        }
+        data.runs.push(run);
+        if data.runs.len() > MAX_RUNS {
+            let excess = data.runs.len() - MAX_RUNS;
+            data.runs.drain(..excess);
+        }
+        self.persist(&data).await
+    }
+
+    async fn persist(&self, data: &BackupData) -> Result<(), String> {
+        json_file::save(&self.path, data, "backup schedules").await
+    }
+}
+
+/// Next fire time of an enabled schedule after `after`
+fn next_run(schedule: &BackupSchedule, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    if !schedule.enabled {
+        return None;
+    }
+    CronSchedule::parse(&schedule.cron)
+        .ok()
+        .and_then(|cron| cron.next_after(after))
+}
+
+/// How `pg_dump` reaches the default connection's server
+#[derive(Debug, Clone)]
+pub enum DumpConnection {
+    /// `DATABASE_URL`, passed as a libpq connection URI
+    Url { url: String, database: String },
+    /// The `POSTGRES_*` settings, passed as libpq environment variables
+    Params {
+        host: String,
+        port: u16,
+        user: String,
+        password: String,
+        database: String,
+    },
+}
+
+impl DumpConnection {
+    pub fn from_config(config: &Config) -> Self {
+        match &config.database_url {
+            Some(url) => DumpConnection::Url {
+                url: url.clone(),
+                database: config.postgres_db.clone(),
+            },
+            None => DumpConnection::Params {
+                host: config.postgres_host.clone(),
+                port: config.postgres_port,
+                user: config.postgres_user.clone(),
+                password: config.postgres_password.clone(),
+                database: config.postgres_db.clone(),
+            },
+        }
+    }
+
+    /// The database dumped when none is given
+    pub fn default_database(&self) -> &str {
+        match self {
+            DumpConnection::Url { database, .. } | DumpConnection::Params { database, .. } => {
+                database
+            }
+        }
+    }
+
+    /// `pg_dump` arguments and environment for dumping `database`
+    fn libpq(&self, database: &str) -> (Vec<String>, Vec<(&'static str, String)>) {
+        match self {
+            // A dbname query parameter overrides the database in the path
+            DumpConnection::Url { url, .. } => {
+                let separator = if url.contains('?') { '&' } else { '?' };
+                let uri = format!("{}{}dbname={}", url, separator, uri_encode(database));
+                (vec![format!("--dbname={}", uri)], Vec::new())
+            }
+            // The database goes through the environment too, so a name
+            // containing `=` is not taken for a connection string
+            DumpConnection::Params {
+                host,
+                port,
+                user,
+                password,
+                ..
+            } => (
+                Vec::new(),
+                vec![
+                    ("PGHOST", host.clone()),
+                    ("PGPORT", port.to_string()),
+                    ("PGUSER", user.clone()),
+                    ("PGPASSWORD", password.clone()),
+                    ("PGDATABASE", database.to_string()),
+                ],
+            ),
+        }
+    }
+}
+
+/// Percent-encode everything but unreserved URI characters
+fn uri_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// File name of a backup
+pub fn backup_name(
+    tag: &str,
+    created_at: DateTime<Utc>,
+    database: &str,
+    format: BackupFormat,
+) -> String {
+    let database: String = database
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!(
+        "{}{}_{}_{}.{}",
+        NAME_PREFIX,
+        tag,
+        created_at.format(TIMESTAMP_FORMAT),
+        database,
+        format.extension()
+    )
+}
+
+/// Tag, creation time, database and format from a backup's file name
+fn parse_backup_name(name: &str) -> Option<(String, DateTime<Utc>, String, BackupFormat)> {
+    let (stem, extension) = name.strip_prefix(NAME_PREFIX)?.rsplit_once('.')?;
+    let format = BackupFormat::from_extension(extension)?;
+    let mut parts = stem.splitn(3, '_');
+    let tag = parts.next()?;
+    let created_at = NaiveDateTime::parse_from_str(parts.next()?, TIMESTAMP_FORMAT)
+        .ok()?
+        .and_utc();
+    let database = parts.next()?;
+    if tag.is_empty() || database.is_empty() {
+        return None;
+    }
+    Some((tag.to_string(), created_at, database.to_string(), format))
+}
+
+/// Backups to delete so a schedule's backups fit its rotation
+///
+/// The newest backup is always kept, even when it is older than the age
+/// limit, so a schedule that stopped succeeding still leaves one behind.
+pub fn select_for_rotation(
+    backups: &[BackupFile],
+    rotation: &Rotation,
+    now: DateTime<Utc>,
+) -> Vec<String> {
+    let mut sorted: Vec<&BackupFile> = backups.iter().collect();
+    sorted.sort_by_key(|b| std::cmp::Reverse(b.created_at));
+
+    sorted
+        .into_iter()
+        .enumerate()
+        .skip(1)
+        .filter(|(position, backup)| {
+            rotation.keep_last.is_some_and(|keep| *position >= keep)
+                || rotation.max_age_days.is_some_and(|days| {
+                    now - backup.created_at > chrono::Duration::days(i64::from(days))
+                })
+        })
+        .map(|(_, backup)| backup.name.clone())
+        .collect()
+}
+
+/// Takes backups, on demand and on schedule
+pub struct BackupManager {
+    pub store: BackupScheduleStore,
+    artifacts: Arc<ArtifactStore>,
+    audit_logger: Arc<AuditLogger>,
+    connection: DumpConnection,
+    pg_dump: String,
+}
+
+impl BackupManager {
+    pub fn new(
+        store: BackupScheduleStore,
+        artifacts: Arc<ArtifactStore>,
+        audit_logger: Arc<AuditLogger>,
+        connection: DumpConnection,
+        pg_dump: impl Into<String>,
+    ) -> Self {
+        Self {
+            store,
+            artifacts,
+            audit_logger,
+            connection,
+            pg_dump: pg_dump.into(),
+        }
+    }
+
+    /// Trim the input and check the cron expression, rotation and webhook
+    pub fn validate(&self, input: BackupScheduleInput) -> Result<BackupScheduleInput, String> {
+        let name = input.name.trim().to_string();
+        if name.is_empty() {
+            return Err("Name cannot be empty".to_string());
+        }
+        let cron = CronSchedule::parse(&input.cron)?;
+        if cron.next_after(Utc::now()).is_none() {
+            return Err(format!("Cron expression '{}' never fires", cron));
+        }
+        if input.rotation.keep_last == Some(0) {
+            return Err("keep_last must be at least 1".to_string());
+        }
+        if let Some(url) = &input.webhook_url {
+            notify::webhook_url(url)?;
+        }
+
+        Ok(BackupScheduleInput {
+            name,
+            database: input
+                .database
+                .map(|d| d.trim().to_string())
+                .filter(|d| !d.is_empty()),
+            cron: cron.to_string(),
+            ..input
+        })
+    }
+
+    /// Start the background task that runs due schedules
+    pub fn spawn(self: &Arc<Self>) {
+        let manager = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(TICK_INTERVAL);
+            loop {
+                interval.tick().await;
+                match manager.store.take_due(Utc::now()).await {
+                    Ok(due) => {
+                        for schedule in due {
+                            let manager = manager.clone();
+                            tokio::spawn(async move {
+                                manager.run_schedule(&schedule).await;
+                            });
+                        }
+                    }
+                    Err(e) => tracing::warn!("Failed to claim due backup schedules: {}", e),
+                }
+            }
+        });
+    }
+
+    /// Backups in the artifact directory and object storage, newest first;
+    /// only local ones when object storage cannot be reached
+    pub async fn list_backups(&self) -> Result<Vec<BackupFile>, String> {
+        let local = self.artifacts.list().await?;
+        let remote = self
+            .artifacts
+            .list_offloaded(NAME_PREFIX)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!("Failed to list offloaded backups: {}", e);
+                Vec::new()
+            });
+        Ok(merge_locations(&local, &remote))
+    }
+
+    /// Where to download a backup from, if `name` is one
+    pub async fn locate(&self, name: &str) -> Result<Option<BackupSource>, String> {
+        if parse_backup_name(name).is_none() {
+            return Ok(None);
+        }
+        let path = self.artifacts.path_for(name)?;
+        if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+            return Ok(Some(BackupSource::Local(path)));
+        }
+        let offloaded = self.artifacts.list_offloaded(name).await?;
+        Ok(offloaded
+            .iter()
+            .any(|a| a.name == name)
+            .then(|| self.artifacts.download_url(name))
+            .flatten()
+            .map(BackupSource::Remote))
+    }
+
+    /// Take a backup now, outside any schedule
+    pub async fn backup_now(
+        &self,
+        database: Option<&str>,
+        format: BackupFormat,
+        client_ip: String,
+    ) -> BackupRun {
+        let run = self.dump(None, database, format).await;
+        self.audit(&run, client_ip).await;
+        self.record(run).await
+    }
+
+    /// Dump a schedule's database, rotate its older backups and notify its webhook
+    pub async fn run_schedule(&self, schedule: &BackupSchedule) -> BackupRun {
+        let mut run = self
+            .dump(
+                Some(&schedule.id),
+                schedule.database.as_deref(),
+                schedule.format,
+            )
+            .await;
+
+        if run.success {
+            match self.rotate(schedule).await {
+                Ok(rotated) => run.rotated = rotated,
+                Err(e) => tracing::warn!("Failed to rotate backups of {}: {}", schedule.name, e),
+            }
+        }
+
+        if let Some(url) = &schedule.webhook_url {
+            let body = webhook_payload(schedule, &run);
+            let delivered = match notify::webhook_url(url) {
+                Ok(url) => {
+                    tokio::time::timeout(notify::DELIVERY_TIMEOUT, notify::post_json(&url, body))
+                        .await
+                        .unwrap_or_else(|_| Err("Timed out notifying the webhook".to_string()))
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = delivered {
+                tracing::warn!(
+                    "Failed to notify webhook of backup {}: {}",
+                    schedule.name,
+                    e
+                );
+                run.delivery_error = Some(e);
+            }
+        }
+
+        self.audit(&run, "scheduler".to_string()).await;
+        self.record(run).await
+    }
+
+    async fn dump(
+        &self,
+        schedule_id: Option<&str>,
+        database: Option<&str>,
+        format: BackupFormat,
+    ) -> BackupRun {
+        let started_at = Utc::now();
+        let start = Instant::now();
+        let database = database
+            .map(str::to_string)
+            .unwrap_or_else(|| self.connection.default_database().to_string());
+        let name = backup_name(
+            schedule_id.unwrap_or(MANUAL_TAG),
+            started_at,
+            &database,
+            format,
+        );
+
+        let mut result = self.run_pg_dump(&name, &database, format).await;
+        let mut upload_error = None;
+        if let Ok(file) = &mut result {
+            match self.artifacts.offload(&name).await {
+                Ok(true) if self.artifacts.keeps_local() => file.location = BackupLocation::Both,
+                Ok(true) => file.location = BackupLocation::Remote,
+                Ok(false) => {}
+                Err(e) => {
+                    tracing::warn!("Failed to offload backup {}: {}", name, e);
+                    upload_error = Some(e);
+                }
+            }
+        }
+        if let Err(e) = &result {
+            tracing::warn!("Backup of {} failed: {}", database, e);
+        }
+        BackupRun {
+            id: uuid::Uuid::new_v4().to_string(),
+            schedule_id: schedule_id.map(str::to_string),
+            database: database.clone(),
+            started_at,
+            duration_ms: start.elapsed().as_millis() as u64,
+            success: result.is_ok(),
+            file: result.as_ref().ok().cloned(),
+            error: result.err(),
+            rotated: Vec::new(),
+            delivery_error: None,
+            upload_error,
+        }
+    }
+
+    /// Run `pg_dump` into a partial file and move it into place once it succeeds
+    async fn run_pg_dump(
+        &self,
+        name: &str,
+        database: &str,
+        format: BackupFormat,
+    ) -> Result<BackupFile, String> {
+        let path = self.artifacts.create_path(name).await?;
+        if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+            return Err(format!("Backup {} already exists", name));
+        }
+        let partial = self.artifacts.path_for(&format!("{}.partial", name))?;
+
+        let (args, env) = self.connection.libpq(database);
+        let output = tokio::process::Command::new(&self.pg_dump)
+            .arg(format.pg_dump_arg())
+            .arg(format!("--file={}", partial.display()))
+            .arg("--no-password")
+            .args(args)
+            .envs(env)
+            .stdin(std::process::Stdio::null())
+            .kill_on_drop(true)
+            .output()
+            .await
+            .map_err(|e| format!("Failed to run {}: {}", self.pg_dump, e))?;
+
+        if !output.status.success() {
+            let _ = tokio::fs::remove_file(&partial).await;
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(match stderr.trim() {
+                "" => format!("pg_dump exited with {}", output.status),
+                message => message.to_string(),
+            });
+        }
+        tokio::fs::rename(&partial, &path)
+            .await
+            .map_err(|e| format!("Failed to store backup {}: {}", name, e))?;
+        let size_bytes = tokio::fs::metadata(&path)
+            .await
+            .map(|m| m.len())
+            .unwrap_or(0);
+
+        BackupFile::from_artifact(&ArtifactInfo {
+            name: name.to_string(),
+            size_bytes,
+            created_at: Utc::now(),
+        })
+        .ok_or_else(|| format!("Unexpected backup name {}", name))
+    }
+
+    /// Delete the schedule's backups that fall outside its rotation
+    async fn rotate(&self, schedule: &BackupSchedule) -> Result<Vec<String>, String> {
+        let backups: Vec<BackupFile> = self
+            .list_backups()
+            .await?
+            .into_iter()
+            .filter(|b| b.schedule_id.as_deref() == Some(schedule.id.as_str()))
+            .collect();
+
+        let mut rotated = Vec::new();
+        for name in select_for_rotation(&backups, &schedule.rotation, Utc::now()) {
+            match self.artifacts.delete(&name).await {
+                Ok(()) => rotated.push(name),
+                Err(e) => tracing::warn!("{}", e),
+            }
+        }
+        Ok(rotated)
+    }
+
+    async fn audit(&self, run: &BackupRun, user: String) {
+        let event = AuditEvent::new(
+            AuditEventType::Backup,
+            user,
+            format!("Backup of {}", run.database),
+            run.file
+                .as_ref()
+                .map_or_else(|| run.database.clone(), |f| f.name.clone()),
+        )
+        .with_success(run.success);
+        let event = match run.error.as_ref().or(run.upload_error.as_ref()) {
+            Some(e) => event.with_details(e.clone()),
+            None => event,
+        };
+        self.audit_logger.log(event).await;
+    }
+
+    async fn record(&self, run: BackupRun) -> BackupRun {
+        if let Err(e) = self.store.record_run(run.clone()).await {
+            tracing::warn!("Failed to store backup run: {}", e);
+        }
+        run
+    }
+}
+
+/// Combine local and offloaded artifacts into backups, newest first
+fn merge_locations(local: &[ArtifactInfo], remote: &[ArtifactInfo]) -> Vec<BackupFile> {
+    let mut backups: Vec<BackupFile> = local.iter().filter_map(BackupFile::from_artifact).collect();
+    for artifact in remote {
+        match backups.iter_mut().find(|b| b.name == artifact.name) {
+            Some(backup) => backup.location = BackupLocation::Both,
+            None => backups.extend(
+                BackupFile::from_artifact(artifact).map(|backup| BackupFile {
+                    location: BackupLocation::Remote,
+                    ..backup
+                }),
+            ),
+        }
+    }
+    backups.sort_by_key(|b| std::cmp::Reverse(b.created_at));
+    backups
+}
+
+/// JSON body posted to a schedule's webhook
+fn webhook_payload(schedule: &BackupSchedule, run: &BackupRun) -> Vec<u8> {
+    let payload = serde_json::json!({
+        "event": if run.success { "backup_succeeded" } else { "backup_failed" },
+        "schedule": {
+            "id": schedule.id,
+            "name": schedule.name,
+            "cron": schedule.cron,
+        },
+        "run": run,
+    });
+    serde_json::to_vec(&payload).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backup(name: &str, age_days: i64, now: DateTime<Utc>) -> BackupFile {
+        BackupFile {
+            name: name.to_string(),
+            database: "app".to_string(),
+            schedule_id: Some("s1".to_string()),
+            format: BackupFormat::Custom,
+            size_bytes: 10,
+            created_at: now - chrono::Duration::days(age_days),
+            location: BackupLocation::Local,
+        }
+    }
+
+    #[test]
+    fn test_backup_name_round_trip() {
+        let at = DateTime::parse_from_rfc3339("2026-03-01T02:00:05Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let name = backup_name("manual", at, "my app/db", BackupFormat::Plain);
+        assert_eq!(name, "backup_manual_20260301T020005Z_my-app-db.sql");
+
+        let file = BackupFile::from_artifact(&ArtifactInfo {
+            name,
+            size_bytes: 42,
+            created_at: Utc::now(),
+        })
+        .unwrap();
+        assert_eq!(file.database, "my-app-db");
+        assert_eq!(file.schedule_id, None);
+        assert_eq!(file.format, BackupFormat::Plain);
+        assert_eq!(file.created_at, at);
+
+        let name = backup_name("0f3c-9a", at, "app_db", BackupFormat::Custom);
+        let (tag, _, database, _) = parse_backup_name(&name).unwrap();
+        assert_eq!((tag.as_str(), database.as_str()), ("0f3c-9a", "app_db"));
+    }
+
+    #[test]
+    fn test_other_artifacts_are_not_backups() {
+        for name in [
+            "export.csv",
+            "backup_manual_20260301T020005Z_app.dump.partial",
+            "backup_manual_yesterday_app.dump",
+        ] {
+            assert!(parse_backup_name(name).is_none(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_merge_locations() {
+        let artifact = |name: &str, size_bytes: u64| ArtifactInfo {
+            name: name.to_string(),
+            size_bytes,
+            created_at: Utc::now(),
+        };
+        let local = vec![
+            artifact("backup_manual_20260301T020005Z_app.dump", 10),
+            artifact("backup_manual_20260302T020005Z_app.dump", 20),
+            artifact("export.csv", 5),
+        ];
+        let remote = vec![
+            artifact("backup_manual_20260302T020005Z_app.dump", 20),
+            artifact("backup_s1_20260303T020005Z_app.sql", 30),
+        ];
+
+        let backups = merge_locations(&local, &remote);
+        let summary: Vec<(&str, BackupLocation)> = backups
+            .iter()
+            .map(|b| (b.name.as_str(), b.location))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("backup_s1_20260303T020005Z_app.sql", BackupLocation::Remote),
+                (
+                    "backup_manual_20260302T020005Z_app.dump",
+                    BackupLocation::Both
+                ),
+                (
+                    "backup_manual_20260301T020005Z_app.dump",
+                    BackupLocation::Local
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_select_for_rotation() {
+        let now = Utc::now();
+        let backups = vec![
+            backup("d3", 3, now),
+            backup("d0", 0, now),
+            backup("d10", 10, now),
+            backup("d1", 1, now),
+        ];
+
+        let keep_two = Rotation {
+            keep_last: Some(2),
+            max_age_days: None,
+        };
+        assert_eq!(
+            select_for_rotation(&backups, &keep_two, now),
+            vec!["d3", "d10"]
+        );
+
+        let week = Rotation {
+            keep_last: None,
+            max_age_days: Some(7),
+        };
+        assert_eq!(select_for_rotation(&backups, &week, now), vec!["d10"]);
+
+        assert!(select_for_rotation(&backups, &Rotation::default(), now).is_empty());
+    }
+
+    #[test]
+    fn test_rotation_keeps_newest_backup() {
+        let now = Utc::now();
+        let backups = vec![backup("old", 30, now), backup("older", 40, now)];
+        let rotation = Rotation {
+            keep_last: None,
+            max_age_days: Some(7),
+        };
+        assert_eq!(select_for_rotation(&backups, &rotation, now), vec!["older"]);
+    }
+
+    #[test]
+    fn test_url_connection_overrides_database() {
+        let url = |url: &str| DumpConnection::Url {
+            url: url.to_string(),
+            database: "app".to_string(),
+        };
+        let (args, env) = url("postgres://app:pw@db:5432/app").libpq("sales db");
+        assert_eq!(
+            args,
+            vec!["--dbname=postgres://app:pw@db:5432/app?dbname=sales%20db"]
+        );
+        assert!(env.is_empty());
+
+        let (args, _) = url("postgres://db/app?sslmode=require").libpq("x");
+        assert!(args[0].ends_with("?sslmode=require&dbname=x"));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::services::identifier::{quote_ident, resolve_relation};
 use sqlx::{Pool, Postgres, Row};
 
 /// Update a single cell value in a table
@@ -21,14 +22,19 @@ pub async fn update_cell(
 ) -> Result<(), sqlx::Error> {
     // Build the UPDATE query
     // Using quoted identifiers to handle special characters
+    let relation = resolve_relation(pool, schema, table).await?;
     let query = match value {
         Some(_) => format!(
-            r#"UPDATE "{}"."{}" SET "{}" = $1 WHERE "{}" = $2"#,
-            schema, table, column, pk_column
+            "UPDATE {} SET {} = $1 WHERE {} = $2",
+            relation,
+            quote_ident(column),
+            quote_ident(pk_column)
         ),
         None => format!(
-            r#"UPDATE "{}"."{}" SET "{}" = NULL WHERE "{}" = $1"#,
-            schema, table, column, pk_column
+            "UPDATE {} SET {} = NULL WHERE {} = $1",
+            relation,
+            quote_ident(column),
+            quote_ident(pk_column)
         ),
     };
 
@@ -86,9 +92,12 @@ pub async fn get_cell_value(
     pk_value: &str,
     column: &str,
 ) -> Result<Option<String>, sqlx::Error> {
+    let relation = resolve_relation(pool, schema, table).await?;
     let query = format!(
-        r#"SELECT "{}"::text as value FROM "{}"."{}" WHERE "{}" = $1"#,
-        column, schema, table, pk_column
+        "SELECT {}::text as value FROM {} WHERE {} = $1",
+        quote_ident(column),
+        relation,
+        quote_ident(pk_column)
     );
 
     let result = sqlx::query(&query)
@@ -112,8 +121,9 @@ pub async fn insert_row(
 
     // Insert a row with DEFAULT values and return the PK
     let query = format!(
-        r#"INSERT INTO "{}"."{}" DEFAULT VALUES RETURNING "{}"::text"#,
-        schema, table, pk_column
+        "INSERT INTO {} DEFAULT VALUES RETURNING {}::text",
+        resolve_relation(pool, schema, table).await?,
+        quote_ident(&pk_column)
     );
 
     let result = sqlx::query(&query).fetch_one(pool).await?;
@@ -131,8 +141,9 @@ pub async fn delete_row(
     pk_value: &str,
 ) -> Result<u64, sqlx::Error> {
     let query = format!(
-        r#"DELETE FROM "{}"."{}" WHERE "{}" = $1"#,
-        schema, table, pk_column
+        "DELETE FROM {} WHERE {} = $1",
+        resolve_relation(pool, schema, table).await?,
+        quote_ident(pk_column)
     );
 
     let result = sqlx::query(&query).bind(pk_value).execute(pool).await?;
@@ -0,0 +1,296 @@
+use crate::services::identifier::{self, quote_ident};
+use serde::Serialize;
+/// Chart Service
+///
+/// Turns data into label/value series for ad-hoc charts, from either:
+/// - A query's results: the first column gives the labels and every numeric
+///   column after it becomes a series
+/// - A table and an aggregate spec (e.g. `count(*)` or `avg(price)` grouped
+///   by a column), computed in the database
+///
+/// Charts are meant to be glanced at, so they are capped at `MAX_POINTS`
+/// labels.
+use serde_json::Value;
+use sqlx::{PgPool, Row};
+
+/// Groups returned by an aggregate chart when no limit is given
+pub const DEFAULT_LIMIT: u32 = 50;
+
+/// Upper bound on the labels of any chart
+pub const MAX_POINTS: u32 = 1000;
+
+/// Labels with one value per label in each series
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChartData {
+    /// What the labels are, e.g. the grouped column
+    pub label_column: String,
+    pub labels: Vec<String>,
+    pub series: Vec<ChartSeries>,
+    /// Set when rows past `MAX_POINTS` were left out
+    pub truncated: bool,
+}
+
+/// Values of one numeric column, NULLs and non-numbers left empty
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChartSeries {
+    pub name: String,
+    pub values: Vec<Option<f64>>,
+}
+
+impl ChartData {
+    /// Largest absolute value across the series, for scaling bars
+    pub fn max_abs(&self) -> f64 {
+        self.series
+            .iter()
+            .flat_map(|s| s.values.iter().flatten())
+            .fold(0.0, |max: f64, v| max.max(v.abs()))
+    }
+}
+
+/// Aggregate functions an aggregate chart can apply
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "" | "count" => Ok(Aggregate::Count),
+            "sum" => Ok(Aggregate::Sum),
+            "avg" => Ok(Aggregate::Avg),
+            "min" => Ok(Aggregate::Min),
+            "max" => Ok(Aggregate::Max),
+            other => Err(format!(
+                "Unknown aggregate '{}' (expected count, sum, avg, min or max)",
+                other
+            )),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Aggregate::Count => "count",
+            Aggregate::Sum => "sum",
+            Aggregate::Avg => "avg",
+            Aggregate::Min => "min",
+            Aggregate::Max => "max",
+        }
+    }
+}
+
+/// What to aggregate, with column names already checked against the table
+#[derive(Debug, Clone)]
+pub struct AggregateSpec {
+    pub group_by: String,
+    pub aggregate: Aggregate,
+    /// Aggregated column; `count` without one counts rows
+    pub column: Option<String>,
+    pub limit: u32,
+}
+
+impl AggregateSpec {
+    /// Name of the resulting series, e.g. `avg(price)`
+    pub fn series_name(&self) -> String {
+        format!(
+            "{}({})",
+            self.aggregate.as_str(),
+            self.column.as_deref().unwrap_or("*")
+        )
+    }
+}
+
+/// Build a chart from query results, keeping at most `MAX_POINTS` rows
+pub fn from_rows(columns: &[String], rows: &[Vec<Value>]) -> Result<ChartData, String> {
+    let Some((label_column, value_columns)) = columns.split_first() else {
+        return Err("The query returned no columns".to_string());
+    };
+    let kept = &rows[..rows.len().min(MAX_POINTS as usize)];
+
+    let labels = kept
+        .iter()
+        .map(|row| row.first().map(label).unwrap_or_default())
+        .collect();
+    let series: Vec<ChartSeries> = value_columns
+        .iter()
+        .enumerate()
+        .filter_map(|(i, name)| {
+            let cells = || kept.iter().filter_map(move |row| row.get(i + 1));
+            // A column is charted if every non-NULL value in it is a number
+            let numeric = cells().all(|v| v.is_null() || number(v).is_some())
+                && cells().any(|v| !v.is_null());
+            numeric.then(|| ChartSeries {
+                name: name.clone(),
+                values: kept
+                    .iter()
+                    .map(|row| row.get(i + 1).and_then(number))
+                    .collect(),
+            })
+        })
+        .collect();
+    if series.is_empty() {
+        return Err(
+            "Nothing to chart: the query needs a label column followed by at least one numeric column"
+                .to_string(),
+        );
+    }
+
+    Ok(ChartData {
+        label_column: label_column.clone(),
+        labels,
+        series,
+        truncated: rows.len() > kept.len(),
+    })
+}
+
+/// Aggregate `schema.table` per value of the group column
+pub async fn aggregate_chart(
+    pool: &PgPool,
+    schema: &str,
+    table: &str,
+    spec: &AggregateSpec,
+) -> Result<ChartData, sqlx::Error> {
+    let relation = identifier::resolve_relation(pool, schema, table).await?;
+    let rows = sqlx::query(&aggregate_query(&relation, spec))
+        .fetch_all(pool)
+        .await?;
+
+    let mut labels = Vec::with_capacity(rows.len());
+    let mut values = Vec::with_capacity(rows.len());
+    for row in rows.iter().take(spec.limit as usize) {
+        let group: Option<String> = row.try_get("label")?;
+        labels.push(group.unwrap_or_else(|| "NULL".to_string()));
+        values.push(row.try_get("value")?);
+    }
+
+    Ok(ChartData {
+        label_column: spec.group_by.clone(),
+        truncated: rows.len() > labels.len(),
+        labels,
+        series: vec![ChartSeries {
+            name: spec.series_name(),
+            values,
+        }],
+    })
+}
+
+/// One row per group in the group column's own order; one row past the
+/// limit is fetched to tell whether groups were left out
+fn aggregate_query(relation: &str, spec: &AggregateSpec) -> String {
+    let group = format!("t.{}", quote_ident(&spec.group_by));
+    let argument = match &spec.column {
+        Some(column) => format!("t.{}", quote_ident(column)),
+        None => "*".to_string(),
+    };
+    format!(
+        "SELECT ({group})::text AS label, {aggregate}({argument})::float8 AS value \
+         FROM {relation} t GROUP BY {group} ORDER BY {group} NULLS LAST LIMIT {limit}",
+        aggregate = spec.aggregate.as_str(),
+        limit = spec.limit + 1,
+    )
+}
+
+/// A value as a label, NULL spelled out
+pub fn label(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Numbers, and numeric values decoded as strings to keep their precision
+pub fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.parse::<f64>().ok().filter(|n| n.is_finite()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_from_rows() {
+        let rows = vec![
+            vec![json!("2024-01"), json!(3), json!("12.50"), json!("north")],
+            vec![json!(null), json!(null), json!("7"), json!("south")],
+        ];
+        let chart = from_rows(&names(&["month", "orders", "revenue", "region"]), &rows).unwrap();
+        assert_eq!(chart.label_column, "month");
+        assert_eq!(chart.labels, names(&["2024-01", "NULL"]));
+        assert_eq!(
+            chart.series,
+            vec![
+                ChartSeries {
+                    name: "orders".to_string(),
+                    values: vec![Some(3.0), None],
+                },
+                ChartSeries {
+                    name: "revenue".to_string(),
+                    values: vec![Some(12.5), Some(7.0)],
+                },
+            ]
+        );
+        assert!(!chart.truncated);
+        assert_eq!(chart.max_abs(), 12.5);
+    }
+
+    #[test]
+    fn test_from_rows_errors_and_truncation() {
+        assert!(from_rows(&[], &[]).is_err());
+        let rows = vec![vec![json!("a"), json!("x")]];
+        assert!(from_rows(&names(&["k", "v"]), &rows)
+            .unwrap_err()
+            .contains("Nothing to chart"));
+
+        let rows: Vec<Vec<Value>> = (0..MAX_POINTS + 5)
+            .map(|i| vec![json!(i), json!(i)])
+            .collect();
+        let chart = from_rows(&names(&["k", "v"]), &rows).unwrap();
+        assert_eq!(chart.labels.len(), MAX_POINTS as usize);
+        assert!(chart.truncated);
+    }
+
+    #[test]
+    fn test_aggregate_parse() {
+        assert_eq!(Aggregate::parse("").unwrap(), Aggregate::Count);
+        assert_eq!(Aggregate::parse(" AVG ").unwrap(), Aggregate::Avg);
+        assert!(Aggregate::parse("median").is_err());
+    }
+
+    #[test]
+    fn test_aggregate_query() {
+        let spec = AggregateSpec {
+            group_by: "Region".to_string(),
+            aggregate: Aggregate::Sum,
+            column: Some("total".to_string()),
+            limit: 20,
+        };
+        assert_eq!(spec.series_name(), "sum(total)");
+        assert_eq!(
+            aggregate_query("public.orders", &spec),
+            "SELECT (t.\"Region\")::text AS label, sum(t.total)::float8 AS value \
+             FROM public.orders t GROUP BY t.\"Region\" ORDER BY t.\"Region\" NULLS LAST LIMIT 21"
+        );
+
+        let spec = AggregateSpec {
+            aggregate: Aggregate::Count,
+            column: None,
+            ..spec
+        };
+        assert_eq!(spec.series_name(), "count(*)");
+        assert!(aggregate_query("public.orders", &spec).contains("count(*)::float8 AS value"));
+    }
+}
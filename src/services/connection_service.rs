@@ -0,0 +1,596 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+/// Connection Service
+///
+/// Tracks the database connections known to the server:
+/// - Connection profiles: the `default` profile from the environment, plus
+///   named profiles (prod, staging, analytics...) declared in `CONNECTIONS_FILE`
+///   with their own credentials and TLS settings
+/// - A lazily connecting pool and metadata cache per profile
+/// - Optional server capabilities probed once per connection and cached
+///   on the profile (extensions, superuser, replication role)
+/// - One-off connection tests for parameters that are not saved anywhere
+///
+/// Features that depend on an extension or role check the cached
+/// capabilities instead of re-querying the catalogs on every request.
+use sqlx::postgres::{PgConnectOptions, PgSslMode};
+use sqlx::{ConnectOptions, Connection, PgPool, Row};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+use crate::config::Config;
+use crate::services::json_file;
+use crate::services::metadata_cache::MetadataCache;
+use crate::services::stats_service::CounterHistory;
+
+/// Id of the profile built from the environment configuration
+pub const DEFAULT_CONNECTION_ID: &str = "default";
+
+/// Optional server features that some functionality depends on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    PgStatStatements,
+    HypoPg,
+    PostGis,
+    PgCron,
+    PgBuffercache,
+    Superuser,
+    Replication,
+}
+
+/// Result of probing a server for optional capabilities
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub server_version: String,
+    pub server_version_num: i32,
+    pub pg_stat_statements: bool,
+    pub hypopg: bool,
+    pub postgis: bool,
+    pub pg_cron: bool,
+    #[serde(default)]
+    pub pg_buffercache: bool,
+    pub is_superuser: bool,
+    pub has_replication: bool,
+    pub probed_at: DateTime<Utc>,
+}
+
+impl Capabilities {
+    /// Check whether a capability is available on this connection
+    pub fn has(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::PgStatStatements => self.pg_stat_statements,
+            Capability::HypoPg => self.hypopg,
+            Capability::PostGis => self.postgis,
+            Capability::PgCron => self.pg_cron,
+            Capability::PgBuffercache => self.pg_buffercache,
+            Capability::Superuser => self.is_superuser,
+            Capability::Replication => self.is_superuser || self.has_replication,
+        }
+    }
+
+    /// Application features and whether this connection can support them
+    pub fn features(&self) -> serde_json::Value {
+        serde_json::json!({
+            "query_statistics": self.has(Capability::PgStatStatements),
+            "hypothetical_indexes": self.has(Capability::HypoPg),
+            "spatial_types": self.has(Capability::PostGis),
+            "scheduled_jobs": self.has(Capability::PgCron),
+            "buffer_cache_inspection": self.has(Capability::PgBuffercache),
+            "server_settings": self.has(Capability::Superuser),
+            "replication_status": self.has(Capability::Replication),
+        })
+    }
+}
+
+/// A named database connection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionProfile {
+    pub id: String,
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    pub database: String,
+    pub user: String,
+    pub capabilities: Option<Capabilities>,
+}
+
+impl ConnectionProfile {
+    /// Build the default profile from the environment configuration
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            id: DEFAULT_CONNECTION_ID.to_string(),
+            name: config.postgres_db.clone(),
+            host: config.postgres_host.clone(),
+            port: config.postgres_port,
+            database: config.postgres_db.clone(),
+            user: config.postgres_user.clone(),
+            capabilities: None,
+        }
+    }
+}
+
+/// A named connection declared in `CONNECTIONS_FILE`
+///
+/// Either `url` or the individual fields (which also override parts of the
+/// URL) describe the server. The password can be read from another
+/// environment variable so the file itself holds no secrets.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConnectionConfig {
+    /// Required in `CONNECTIONS_FILE`; optional when only testing parameters
+    #[serde(default)]
+    pub id: String,
+    /// Shown in the server picker; defaults to the id
+    pub name: Option<String>,
+    /// `postgres://` connection URL
+    pub url: Option<String>,
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub database: Option<String>,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    /// Environment variable holding the password
+    pub password_env: Option<String>,
+    /// `disable`, `allow`, `prefer`, `require`, `verify-ca` or `verify-full`
+    pub ssl_mode: Option<String>,
+    /// CA certificate file for `verify-ca` / `verify-full`
+    pub ssl_root_cert: Option<String>,
+}
+
+/// Contents of `CONNECTIONS_FILE`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConnectionsFile {
+    connections: Vec<ConnectionConfig>,
+}
+
+impl ConnectionConfig {
+    /// Connection options for this profile, with the password resolved
+    pub fn connect_options(&self) -> Result<PgConnectOptions, String> {
+        let invalid = |what: &str, e: &dyn std::fmt::Display| {
+            format!("{}: invalid {}: {}", self.label(), what, e)
+        };
+
+        let mut options = match &self.url {
+            Some(url) if !url.starts_with("postgres://") && !url.starts_with("postgresql://") => {
+                return Err(invalid("url", &"must start with postgres://"));
+            }
+            Some(url) => PgConnectOptions::from_str(url).map_err(|e| invalid("url", &e))?,
+            None => PgConnectOptions::new()
+                .host("localhost")
+                .port(5432)
+                .username("postgres")
+                .database("postgres"),
+        };
+        if let Some(host) = &self.host {
+            options = options.host(host);
+        }
+        if let Some(port) = self.port {
+            options = options.port(port);
+        }
+        if let Some(database) = &self.database {
+            options = options.database(database);
+        }
+        if let Some(user) = &self.user {
+            options = options.username(user);
+        }
+        if let Some(name) = &self.password_env {
+            let password = env::var(name).map_err(|_| {
+                format!("{}: environment variable {} is not set", self.label(), name)
+            })?;
+            options = options.password(&password);
+        } else if let Some(password) = &self.password {
+            options = options.password(password);
+        }
+        if let Some(mode) = &self.ssl_mode {
+            options =
+                options.ssl_mode(PgSslMode::from_str(mode).map_err(|e| invalid("ssl_mode", &e))?);
+        }
+        if let Some(cert) = &self.ssl_root_cert {
+            options = options.ssl_root_cert(cert);
+        }
+        Ok(options)
+    }
+
+    fn label(&self) -> String {
+        if self.id.is_empty() {
+            "Connection".to_string()
+        } else {
+            format!("Connection '{}'", self.id)
+        }
+    }
+
+    /// The profile shown for this connection
+    pub fn profile(&self, options: &PgConnectOptions) -> ConnectionProfile {
+        ConnectionProfile {
+            id: self.id.clone(),
+            name: self.name.clone().unwrap_or_else(|| self.id.clone()),
+            host: options.get_host().to_string(),
+            port: options.get_port(),
+            database: options.get_database().unwrap_or_default().to_string(),
+            user: options.get_username().to_string(),
+            capabilities: None,
+        }
+    }
+}
+
+/// Load the named connections from `CONNECTIONS_FILE` (none if it does not exist)
+pub async fn load_connections(path: &str) -> Result<Vec<ConnectionConfig>, String> {
+    let file: ConnectionsFile = json_file::load(Path::new(path), "connections file").await?;
+    validate_connections(&file.connections)?;
+    Ok(file.connections)
+}
+
+fn validate_connections(connections: &[ConnectionConfig]) -> Result<(), String> {
+    let mut seen = HashSet::new();
+    for connection in connections {
+        let id = connection.id.as_str();
+        if id.is_empty()
+            || !id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!(
+                "Connection id '{}' must be letters, digits, '-' or '_'",
+                id
+            ));
+        }
+        if id == DEFAULT_CONNECTION_ID {
+            return Err(format!(
+                "Connection id '{}' is reserved for the POSTGRES_* / DATABASE_URL connection",
+                id
+            ));
+        }
+        if !seen.insert(id) {
+            return Err(format!("Connection id '{}' is declared twice", id));
+        }
+    }
+    Ok(())
+}
+
+/// A connection's pool and the caches that belong to it
+#[derive(Clone)]
+pub struct ConnectionHandle {
+    pub pool: Arc<PgPool>,
+    pub metadata_cache: Arc<MetadataCache>,
+    pub counter_history: Arc<CounterHistory>,
+}
+
+/// Registry of connection profiles
+pub struct ConnectionRegistry {
+    profiles: Arc<RwLock<Vec<ConnectionProfile>>>,
+    handles: HashMap<String, ConnectionHandle>,
+}
+
+impl ConnectionRegistry {
+    pub fn new(profiles: Vec<ConnectionProfile>) -> Self {
+        Self {
+            profiles: Arc::new(RwLock::new(profiles)),
+            handles: HashMap::new(),
+        }
+    }
+
+    /// Attach the pool and caches used for a profile
+    pub fn with_handle(mut self, id: &str, handle: ConnectionHandle) -> Self {
+        self.handles.insert(id.to_string(), handle);
+        self
+    }
+
+    /// The pool and caches of a profile
+    pub fn handle(&self, id: &str) -> Option<ConnectionHandle> {
+        self.handles.get(id).cloned()
+    }
+
+    /// All profiles, in the order they were declared
+    pub async fn list(&self) -> Vec<ConnectionProfile> {
+        self.profiles.read().await.clone()
+    }
+
+    /// Get a profile by id
+    pub async fn get(&self, id: &str) -> Option<ConnectionProfile> {
+        self.profiles
+            .read()
+            .await
+            .iter()
+            .find(|p| p.id == id)
+            .cloned()
+    }
+
+    /// Cache probed capabilities on a profile
+    pub async fn set_capabilities(&self, id: &str, capabilities: Capabilities) -> bool {
+        let mut profiles = self.profiles.write().await;
+        match profiles.iter_mut().find(|p| p.id == id) {
+            Some(profile) => {
+                profile.capabilities = Some(capabilities);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Get cached capabilities, probing the server on first use or when forced
+    pub async fn capabilities(
+        &self,
+        id: &str,
+        pool: &PgPool,
+        refresh: bool,
+    ) -> Result<Option<Capabilities>, String> {
+        let Some(profile) = self.get(id).await else {
+            return Ok(None);
+        };
+
+        if let (Some(cached), false) = (profile.capabilities, refresh) {
+            return Ok(Some(cached));
+        }
+
+        let capabilities = probe_capabilities(pool).await?;
+        self.set_capabilities(id, capabilities.clone()).await;
+        Ok(Some(capabilities))
+    }
+}
+
+/// How long a connection test may take before it is reported as timed out
+const CONNECTION_TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Outcome of a one-off connection attempt
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionTest {
+    pub success: bool,
+    /// Time to connect and read the server version
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// SQLSTATE of a server-side failure, e.g. `28P01` for a wrong password
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+}
+
+/// Connect with the given options, read the server version and disconnect
+pub async fn try_connect(options: &PgConnectOptions) -> ConnectionTest {
+    let started = Instant::now();
+    let attempt = async {
+        let mut conn = options.connect().await?;
+        let version: String = sqlx::query_scalar("SELECT current_setting('server_version')")
+            .fetch_one(&mut conn)
+            .await?;
+        // The test already succeeded; a failed goodbye is not worth reporting
+        let _ = conn.close().await;
+        Ok::<_, sqlx::Error>(version)
+    };
+    let outcome = tokio::time::timeout(CONNECTION_TEST_TIMEOUT, attempt).await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    let (error, error_code) = match outcome {
+        Ok(Ok(version)) => {
+            return ConnectionTest {
+                success: true,
+                latency_ms,
+                server_version: Some(version),
+                error: None,
+                error_code: None,
+            }
+        }
+        Ok(Err(e)) => describe_connect_error(options, &e),
+        Err(_) => (
+            format!(
+                "Timed out after {}s connecting to {}:{}",
+                CONNECTION_TEST_TIMEOUT.as_secs(),
+                options.get_host(),
+                options.get_port()
+            ),
+            None,
+        ),
+    };
+    ConnectionTest {
+        success: false,
+        latency_ms,
+        server_version: None,
+        error: Some(error),
+        error_code,
+    }
+}
+
+/// A message that says which step failed, and the server's SQLSTATE if any
+fn describe_connect_error(options: &PgConnectOptions, e: &sqlx::Error) -> (String, Option<String>) {
+    let target = format!("{}:{}", options.get_host(), options.get_port());
+    match e {
+        sqlx::Error::Database(db) => (
+            format!(
+                "Server at {} refused the connection: {}",
+                target,
+                db.message()
+            ),
+            db.code().map(|code| code.to_string()),
+        ),
+        sqlx::Error::Io(io) => (format!("Could not reach {}: {}", target, io), None),
+        sqlx::Error::Tls(tls) => (
+            format!("TLS handshake with {} failed: {}", target, tls),
+            None,
+        ),
+        other => (format!("Connecting to {} failed: {}", target, other), None),
+    }
+}
+
+/// Probe a server for optional capabilities in a single round trip
+pub async fn probe_capabilities(pool: &PgPool) -> Result<Capabilities, String> {
+    let row = sqlx::query(
+        r#"
+        SELECT
+            current_setting('server_version') as server_version,
+            current_setting('server_version_num')::int as server_version_num,
+            EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'pg_stat_statements') as pg_stat_statements,
+            EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'hypopg') as hypopg,
+            EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'postgis') as postgis,
+            EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'pg_cron') as pg_cron,
+            EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'pg_buffercache') as pg_buffercache,
+            r.rolsuper as is_superuser,
+            r.rolreplication as has_replication
+        FROM pg_roles r
+        WHERE r.rolname = current_user
+        "#,
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| format!("Failed to probe server capabilities: {}", e))?;
+
+    Ok(Capabilities {
+        server_version: row.get("server_version"),
+        server_version_num: row.get("server_version_num"),
+        pg_stat_statements: row.get("pg_stat_statements"),
+        hypopg: row.get("hypopg"),
+        postgis: row.get("postgis"),
+        pg_cron: row.get("pg_cron"),
+        pg_buffercache: row.get("pg_buffercache"),
+        is_superuser: row.get("is_superuser"),
+        has_replication: row.get("has_replication"),
+        probed_at: Utc::now(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            server_version: "16.2".to_string(),
+            server_version_num: 160002,
+            pg_stat_statements: true,
+            hypopg: false,
+            postgis: false,
+            pg_cron: false,
+            pg_buffercache: false,
+            is_superuser: false,
+            has_replication: true,
+            probed_at: Utc::now(),
+        }
+    }
+
+    fn profile(id: &str) -> ConnectionProfile {
+        ConnectionProfile {
+            id: id.to_string(),
+            name: id.to_string(),
+            host: "localhost".to_string(),
+            port: 5432,
+            database: "postgres".to_string(),
+            user: "postgres".to_string(),
+            capabilities: None,
+        }
+    }
+
+    #[test]
+    fn test_capability_checks() {
+        let caps = capabilities();
+        assert!(caps.has(Capability::PgStatStatements));
+        assert!(!caps.has(Capability::HypoPg));
+        assert!(!caps.has(Capability::Superuser));
+        assert!(caps.has(Capability::Replication));
+
+        let features = caps.features();
+        assert_eq!(features["query_statistics"], true);
+        assert_eq!(features["server_settings"], false);
+    }
+
+    #[tokio::test]
+    async fn test_registry_caches_capabilities() {
+        let registry = ConnectionRegistry::new(vec![profile(DEFAULT_CONNECTION_ID)]);
+        assert!(registry
+            .get(DEFAULT_CONNECTION_ID)
+            .await
+            .unwrap()
+            .capabilities
+            .is_none());
+
+        assert!(
+            registry
+                .set_capabilities(DEFAULT_CONNECTION_ID, capabilities())
+                .await
+        );
+        assert!(!registry.set_capabilities("missing", capabilities()).await);
+
+        let cached = registry.get(DEFAULT_CONNECTION_ID).await.unwrap();
+        assert_eq!(cached.capabilities.unwrap().server_version_num, 160002);
+    }
+
+    #[test]
+    fn test_connection_config_options() {
+        std::env::set_var("PGADMIN_TEST_STAGING_PASSWORD", "s3cret");
+        let file: ConnectionsFile = serde_json::from_str(
+            r#"{"connections": [
+                {"id": "staging", "name": "Staging", "host": "staging.internal",
+                 "database": "app", "user": "reader",
+                 "password_env": "PGADMIN_TEST_STAGING_PASSWORD", "ssl_mode": "require"},
+                {"id": "analytics", "url": "postgres://bi@warehouse:6432/metrics", "port": 7000}
+            ]}"#,
+        )
+        .unwrap();
+        validate_connections(&file.connections).unwrap();
+
+        let staging = &file.connections[0];
+        let options = staging.connect_options().unwrap();
+        let profile = staging.profile(&options);
+        assert_eq!(profile.name, "Staging");
+        assert_eq!(profile.host, "staging.internal");
+        assert_eq!(profile.port, 5432);
+        assert_eq!(profile.database, "app");
+        assert_eq!(profile.user, "reader");
+
+        // URL parts can be overridden field by field
+        let analytics = &file.connections[1];
+        let profile = analytics.profile(&analytics.connect_options().unwrap());
+        assert_eq!(profile.name, "analytics");
+        assert_eq!(profile.host, "warehouse");
+        assert_eq!(profile.port, 7000);
+        assert_eq!(profile.database, "metrics");
+        assert_eq!(profile.user, "bi");
+    }
+
+    #[tokio::test]
+    async fn test_try_connect_reports_unreachable_server() {
+        let options = PgConnectOptions::new()
+            .host("127.0.0.1")
+            .port(1)
+            .username("postgres")
+            .ssl_mode(PgSslMode::Disable);
+        let test = try_connect(&options).await;
+        assert!(!test.success);
+        assert!(test.server_version.is_none());
+        assert!(test
+            .error
+            .unwrap()
+            .starts_with("Could not reach 127.0.0.1:1"));
+    }
+
+    #[test]
+    fn test_connection_config_errors() {
+        let parse = |json: &str| serde_json::from_str::<ConnectionConfig>(json).unwrap();
+
+        let bad_mode = parse(r#"{"id": "prod", "ssl_mode": "sometimes"}"#);
+        assert!(bad_mode.connect_options().unwrap_err().contains("ssl_mode"));
+        let missing_env = parse(r#"{"id": "prod", "password_env": "PGADMIN_TEST_UNSET_VAR"}"#);
+        assert!(missing_env
+            .connect_options()
+            .unwrap_err()
+            .contains("PGADMIN_TEST_UNSET_VAR"));
+        let mysql = parse(r#"{"id": "prod", "url": "mysql://root@db/app"}"#);
+        assert!(mysql.connect_options().is_err());
+        assert!(
+            serde_json::from_str::<ConnectionConfig>(r#"{"id": "prod", "pasword": "x"}"#).is_err()
+        );
+
+        assert!(validate_connections(&[parse(r#"{"id": "default"}"#)]).is_err());
+        assert!(validate_connections(&[parse(r#"{"id": "prod db"}"#)]).is_err());
+        assert!(
+            validate_connections(&[parse(r#"{"id": "prod"}"#), parse(r#"{"id": "prod"}"#)])
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, Timelike, Utc};
+/// Cron Expressions
+///
+/// Parses standard five-field cron expressions (`minute hour day-of-month
+/// month day-of-week`, evaluated in UTC) and computes the next fire time.
+/// Supports `*`, lists (`1,15`), ranges (`1-5`), steps (`*/15`, `0-30/10`),
+/// month and weekday names (`jan`, `mon`) and the `@hourly`, `@daily`,
+/// `@weekly`, `@monthly` and `@yearly` shorthands.
+///
+/// As in Vixie cron, when both day-of-month and day-of-week are restricted a
+/// day matches if either field does.
+use std::fmt;
+
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const DAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// How far ahead to search before deciding an expression never fires (e.g. `0 0 30 2 *`)
+const MAX_SEARCH_YEARS: i32 = 5;
+
+/// A parsed cron expression
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronSchedule {
+    expression: String,
+    minutes: Field,
+    hours: Field,
+    days_of_month: Field,
+    months: Field,
+    days_of_week: Field,
+}
+
+/// Allowed values of one field as a bitmask
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Field {
+    mask: u64,
+    /// Written as `*` (matters for the day-of-month / day-of-week rule)
+    unrestricted: bool,
+}
+
+impl Field {
+    fn contains(&self, value: u32) -> bool {
+        self.mask & (1 << value) != 0
+    }
+}
+
+impl CronSchedule {
+    /// Parse a cron expression
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let trimmed = expression.trim();
+        let expanded = match trimmed.to_lowercase().as_str() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            _ => trimmed,
+        };
+
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!(
+                "Invalid cron expression '{}': expected 5 fields (minute hour day month weekday)",
+                expression
+            ));
+        }
+
+        let parse = |index: usize, min: u32, max: u32, names: &[&str], label: &str| {
+            parse_field(fields[index], min, max, names)
+                .map_err(|e| format!("Invalid cron expression '{}': {} {}", expression, label, e))
+        };
+
+        let mut days_of_week = parse(4, 0, 7, &DAY_NAMES, "weekday")?;
+        // 7 is an alias for Sunday
+        if days_of_week.contains(7) {
+            days_of_week.mask = (days_of_week.mask & !(1 << 7)) | 1;
+        }
+
+        Ok(Self {
+            expression: trimmed.to_string(),
+            minutes: parse(0, 0, 59, &[], "minute")?,
+            hours: parse(1, 0, 23, &[], "hour")?,
+            days_of_month: parse(2, 1, 31, &[], "day")?,
+            months: parse(3, 1, 12, &MONTH_NAMES, "month")?,
+            days_of_week,
+        })
+    }
+
+    /// The first fire time strictly after `after`, or `None` if it never fires
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = after.naive_utc().with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let limit_year = start.year() + MAX_SEARCH_YEARS;
+        let mut t = start;
+
+        while t.year() <= limit_year {
+            if !self.months.contains(t.month()) {
+                t = first_of_next_month(t.date())?;
+            } else if !self.day_matches(t.date()) {
+                t = (t.date() + Duration::days(1)).and_hms_opt(0, 0, 0)?;
+            } else if !self.hours.contains(t.hour()) {
+                t = t.with_minute(0)? + Duration::hours(1);
+            } else if !self.minutes.contains(t.minute()) {
+                t += Duration::minutes(1);
+            } else {
+                return Some(t.and_utc());
+            }
+        }
+        None
+    }
+
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        let dom = self.days_of_month.contains(date.day());
+        let dow = self
+            .days_of_week
+            .contains(date.weekday().num_days_from_sunday());
+
+        match (
+            self.days_of_month.unrestricted,
+            self.days_of_week.unrestricted,
+        ) {
+            (false, false) => dom || dow,
+            (true, false) => dow,
+            (false, true) => dom,
+            (true, true) => true,
+        }
+    }
+}
+
+impl fmt::Display for CronSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+fn first_of_next_month(date: NaiveDate) -> Option<NaiveDateTime> {
+    let (year, month) = if date.month() == 12 {
+        (date.year() + 1, 1)
+    } else {
+        (date.year(), date.month() + 1)
+    };
+    NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)
+}
+
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<Field, String> {
+    let mut mask = 0u64;
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .ok()
+                    .filter(|s| *s > 0)
+                    .ok_or_else(|| format!("has an invalid step '{}'", step))?;
+                (range, step)
+            }
+            None => (part, 1),
+        };
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((a, b)) = range.split_once('-') {
+            (
+                parse_value(a, min, max, names)?,
+                parse_value(b, min, max, names)?,
+            )
+        } else {
+            let value = parse_value(range, min, max, names)?;
+            // `5/15` means "from 5 to the end, every 15"
+            (value, if part.contains('/') { max } else { value })
+        };
+
+        if start > end {
+            return Err(format!("has a reversed range '{}'", range));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+
+    Ok(Field {
+        mask,
+        unrestricted: field == "*",
+    })
+}
+
+fn parse_value(value: &str, min: u32, max: u32, names: &[&str]) -> Result<u32, String> {
+    let lower = value.to_lowercase();
+    // Names map to their position: months start at 1, weekdays at 0
+    let parsed = match names.iter().position(|n| *n == lower) {
+        Some(index) => Some(index as u32 + min),
+        None => value.parse::<u32>().ok(),
+    };
+
+    parsed
+        .filter(|v| (min..=max).contains(v))
+        .ok_or_else(|| format!("value '{}' is not between {} and {}", value, min, max))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn next(expr: &str, after: &str) -> String {
+        CronSchedule::parse(expr)
+            .unwrap()
+            .next_after(at(after))
+            .unwrap()
+            .to_rfc3339()
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(CronSchedule::parse("* * * *").is_err());
+        assert!(CronSchedule::parse("60 * * * *").is_err());
+        assert!(CronSchedule::parse("* 24 * * *").is_err());
+        assert!(CronSchedule::parse("* * 0 * *").is_err());
+        assert!(CronSchedule::parse("*/0 * * * *").is_err());
+        assert!(CronSchedule::parse("30-10 * * * *").is_err());
+        assert!(CronSchedule::parse("* * * foo *").is_err());
+        assert!(CronSchedule::parse("@daily").is_ok());
+    }
+
+    #[test]
+    fn test_next_after() {
+        // Every 15 minutes
+        assert_eq!(
+            next("*/15 * * * *", "2024-03-10T10:07:30Z"),
+            "2024-03-10T10:15:00+00:00"
+        );
+        // Strictly after: a matching instant moves to the next one
+        assert_eq!(
+            next("0 2 * * *", "2024-03-10T02:00:00Z"),
+            "2024-03-11T02:00:00+00:00"
+        );
+        // Nightly at 02:30, rolling over the end of the year
+        assert_eq!(
+            next("30 2 * * *", "2024-12-31T03:00:00Z"),
+            "2025-01-01T02:30:00+00:00"
+        );
+        // Weekdays at 09:00 (2024-03-09 is a Saturday)
+        assert_eq!(
+            next("0 9 * * mon-fri", "2024-03-09T12:00:00Z"),
+            "2024-03-11T09:00:00+00:00"
+        );
+        // Leap day only
+        assert_eq!(
+            next("0 0 29 feb *", "2024-03-01T00:00:00Z"),
+            "2028-02-29T00:00:00+00:00"
+        );
+        assert_eq!(
+            next("@monthly", "2024-01-31T12:00:00Z"),
+            "2024-02-01T00:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn test_day_of_month_or_day_of_week() {
+        // The 15th or any Sunday (7 = Sunday); 2024-03-10 is a Sunday
+        assert_eq!(
+            next("0 0 15 * 7", "2024-03-01T00:00:00Z"),
+            "2024-03-03T00:00:00+00:00"
+        );
+        assert_eq!(
+            next("0 0 15 * 7", "2024-03-10T00:00:00Z"),
+            "2024-03-15T00:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn test_never_fires() {
+        let schedule = CronSchedule::parse("0 0 30 2 *").unwrap();
+        assert!(schedule.next_after(at("2024-01-01T00:00:00Z")).is_none());
+    }
+}
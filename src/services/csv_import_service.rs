@@ -0,0 +1,432 @@
+use crate::services::identifier::{quote_ident, quote_qualified};
+use crate::services::schema_ops_service::{ColumnDefinition, CreateTableRequest, SchemaOpsService};
+use serde::{Deserialize, Serialize};
+/// CSV Import Service
+///
+/// Creates a table from an uploaded CSV file. Column names come from the
+/// header row and types are inferred from the values, giving a proposed
+/// `CREATE TABLE` that the client confirms (or edits) before the table is
+/// created and the rows are loaded with `COPY ... FROM STDIN`.
+use sqlx::PgPool;
+
+/// Rows returned with a proposal so the client can check the inferred types
+const SAMPLE_ROWS: usize = 5;
+
+/// A CSV upload and where to put it
+#[derive(Debug, Clone, Deserialize)]
+pub struct CsvImportRequest {
+    pub schema: String,
+    pub table_name: String,
+    /// The CSV file contents
+    pub csv: String,
+    /// Single-byte field separator
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+    /// The first row holds column names
+    #[serde(default = "default_has_header")]
+    pub has_header: bool,
+    /// Column definitions replacing the inferred ones, one per CSV column
+    #[serde(default)]
+    pub columns: Option<Vec<ColumnDefinition>>,
+    /// Create the table and load the rows; without it only the proposal is returned
+    #[serde(default)]
+    pub confirm: bool,
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+fn default_has_header() -> bool {
+    true
+}
+
+/// The table an import would create
+#[derive(Debug, Clone, Serialize)]
+pub struct CsvImportProposal {
+    pub table: CreateTableRequest,
+    pub sql: String,
+    /// Data rows in the file, excluding the header
+    pub row_count: usize,
+    pub sample: Vec<Vec<String>>,
+}
+
+/// Column type inferred from CSV values, from most to least specific
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum InferredType {
+    Boolean,
+    Bigint,
+    Numeric,
+    Date,
+    Timestamp,
+    Timestamptz,
+    Text,
+}
+
+impl InferredType {
+    fn of(value: &str) -> Self {
+        let value = value.trim();
+        if matches!(
+            value.to_lowercase().as_str(),
+            "true" | "false" | "t" | "f" | "yes" | "no"
+        ) {
+            InferredType::Boolean
+        } else if value.parse::<i64>().is_ok() {
+            InferredType::Bigint
+        } else if value.parse::<f64>().is_ok_and(f64::is_finite) {
+            InferredType::Numeric
+        } else if chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok() {
+            InferredType::Date
+        } else if chrono::DateTime::parse_from_rfc3339(value).is_ok() {
+            InferredType::Timestamptz
+        } else if ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"]
+            .iter()
+            .any(|f| chrono::NaiveDateTime::parse_from_str(value, f).is_ok())
+        {
+            InferredType::Timestamp
+        } else {
+            InferredType::Text
+        }
+    }
+
+    /// A type that holds values of both
+    fn widen(self, other: Self) -> Self {
+        use InferredType::*;
+        match (self.min(other), self.max(other)) {
+            (a, b) if a == b => a,
+            (Bigint, Numeric) => Numeric,
+            (Date, Timestamp) => Timestamp,
+            (Date | Timestamp, Timestamptz) => Timestamptz,
+            _ => Text,
+        }
+    }
+
+    fn sql(&self) -> &'static str {
+        match self {
+            InferredType::Boolean => "boolean",
+            InferredType::Bigint => "bigint",
+            InferredType::Numeric => "numeric",
+            InferredType::Date => "date",
+            InferredType::Timestamp => "timestamp",
+            InferredType::Timestamptz => "timestamptz",
+            InferredType::Text => "text",
+        }
+    }
+}
+
+/// Turn a header into a lower-case identifier, e.g. `Order Date` → `order_date`
+fn column_name(header: &str, position: usize) -> String {
+    let mut name = String::new();
+    for c in header.trim().chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            name.push(c);
+        } else if !name.ends_with('_') {
+            name.push('_');
+        }
+    }
+    let name = name.trim_matches('_');
+    let name = if name.is_empty() {
+        format!("column_{}", position + 1)
+    } else if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("c_{}", name)
+    } else {
+        name.to_string()
+    };
+    name.chars().take(63).collect()
+}
+
+fn reader(request: &CsvImportRequest) -> Result<csv::Reader<&[u8]>, String> {
+    if !request.delimiter.is_ascii() || matches!(request.delimiter, '"' | '\'' | '\\' | '\n' | '\r')
+    {
+        return Err(format!("Invalid delimiter: {:?}", request.delimiter));
+    }
+    Ok(csv::ReaderBuilder::new()
+        .delimiter(request.delimiter as u8)
+        .has_headers(request.has_header)
+        .from_reader(request.csv.as_bytes()))
+}
+
+/// Infer the table from the upload, or check the client's column definitions against it
+pub fn propose(request: &CsvImportRequest) -> Result<CsvImportProposal, String> {
+    let mut reader = reader(request)?;
+    let headers: Vec<String> = if request.has_header {
+        reader
+            .headers()
+            .map_err(|e| format!("Failed to read CSV header: {}", e))?
+            .iter()
+            .map(str::to_string)
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let mut types: Vec<Option<InferredType>> = vec![None; headers.len()];
+    let mut has_empty = vec![false; headers.len()];
+    let mut sample = Vec::new();
+    let mut row_count = 0;
+    for record in reader.records() {
+        let record = record.map_err(|e| format!("Invalid CSV: {}", e))?;
+        if types.is_empty() {
+            types = vec![None; record.len()];
+            has_empty = vec![false; record.len()];
+        }
+        for (i, value) in record.iter().enumerate() {
+            // COPY reads empty fields as NULL
+            if value.is_empty() {
+                has_empty[i] = true;
+                continue;
+            }
+            let inferred = InferredType::of(value);
+            types[i] = Some(types[i].map_or(inferred, |t: InferredType| t.widen(inferred)));
+        }
+        if sample.len() < SAMPLE_ROWS {
+            sample.push(record.iter().map(str::to_string).collect());
+        }
+        row_count += 1;
+    }
+    if types.is_empty() {
+        return Err("The CSV file has no columns".to_string());
+    }
+
+    let columns = match &request.columns {
+        Some(columns) => {
+            if columns.len() != types.len() {
+                return Err(format!(
+                    "The CSV file has {} columns but {} were defined",
+                    types.len(),
+                    columns.len()
+                ));
+            }
+            columns.clone()
+        }
+        None => {
+            let mut names: Vec<String> = Vec::new();
+            for i in 0..types.len() {
+                let base = column_name(headers.get(i).map_or("", String::as_str), i);
+                let mut name = base.clone();
+                let mut suffix = 2;
+                while names.contains(&name) {
+                    name = format!("{}_{}", base, suffix);
+                    suffix += 1;
+                }
+                names.push(name);
+            }
+            names
+                .into_iter()
+                .zip(types.iter().zip(&has_empty))
+                .map(|(name, (inferred, has_empty))| ColumnDefinition {
+                    name,
+                    data_type: inferred.unwrap_or(InferredType::Text).sql().to_string(),
+                    nullable: *has_empty || inferred.is_none(),
+                    default: None,
+                    primary_key: false,
+                    unique: false,
+                    identity: None,
+                })
+                .collect()
+        }
+    };
+
+    let table = CreateTableRequest {
+        table_name: request.table_name.clone(),
+        schema: request.schema.clone(),
+        columns,
+        primary_key: Vec::new(),
+        unique: Vec::new(),
+        checks: Vec::new(),
+        foreign_keys: Vec::new(),
+    };
+    let sql = SchemaOpsService::create_table_sql(&table)?;
+
+    Ok(CsvImportProposal {
+        table,
+        sql,
+        row_count,
+        sample,
+    })
+}
+
+/// `COPY` statement loading the upload into the proposed table
+fn copy_sql(request: &CsvImportRequest, table: &CreateTableRequest) -> String {
+    let columns = table
+        .columns
+        .iter()
+        .map(|c| quote_ident(&c.name))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "COPY {} ({}) FROM STDIN WITH (FORMAT csv, HEADER {}, DELIMITER '{}')",
+        quote_qualified(&table.schema, &table.table_name),
+        columns,
+        request.has_header,
+        request.delimiter
+    )
+}
+
+/// Create the proposed table and load the rows in one transaction
+///
+/// Refuses to load into an existing table. Returns the number of rows copied.
+pub async fn import(pool: &PgPool, request: &CsvImportRequest) -> Result<u64, String> {
+    let proposal = propose(request)?;
+    let exists: bool =
+        sqlx::query_scalar("SELECT to_regclass(format('%I.%I', $1::text, $2::text)) IS NOT NULL")
+            .bind(&request.schema)
+            .bind(&request.table_name)
+            .fetch_one(pool)
+            .await
+            .map_err(|e| format!("Failed to check for table: {}", e))?;
+    if exists {
+        return Err(format!(
+            "Table {}.{} already exists",
+            request.schema, request.table_name
+        ));
+    }
+
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| format!("Failed to start transaction: {}", e))?;
+    sqlx::query(&proposal.sql)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to create table: {}", e))?;
+
+    let mut copy = tx
+        .copy_in_raw(&copy_sql(request, &proposal.table))
+        .await
+        .map_err(|e| format!("Failed to load rows: {}", e))?;
+    copy.send(request.csv.as_bytes())
+        .await
+        .map_err(|e| format!("Failed to load rows: {}", e))?;
+    let copied = copy
+        .finish()
+        .await
+        .map_err(|e| format!("Failed to load rows: {}", e))?;
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("Failed to commit import: {}", e))?;
+    Ok(copied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(csv: &str) -> CsvImportRequest {
+        serde_json::from_value(serde_json::json!({
+            "schema": "public",
+            "table_name": "imported",
+            "csv": csv
+        }))
+        .unwrap()
+    }
+
+    fn columns(proposal: &CsvImportProposal) -> Vec<(&str, &str, bool)> {
+        proposal
+            .table
+            .columns
+            .iter()
+            .map(|c| (c.name.as_str(), c.data_type.as_str(), c.nullable))
+            .collect()
+    }
+
+    #[test]
+    fn test_infer_columns() {
+        let proposal = propose(&request(
+            "Order ID,Total ($),Paid,Ordered On,Shipped At,Note,Order ID\n\
+             1,9.5,true,2024-01-02,2024-01-02 10:00:00,\"a, b\",x\n\
+             2,10,f,2024-01-03,2024-01-03T11:30:00Z,,y\n",
+        ))
+        .unwrap();
+        assert_eq!(
+            columns(&proposal),
+            vec![
+                ("order_id", "bigint", false),
+                ("total", "numeric", false),
+                ("paid", "boolean", false),
+                ("ordered_on", "date", false),
+                ("shipped_at", "timestamptz", false),
+                ("note", "text", true),
+                ("order_id_2", "text", false),
+            ]
+        );
+        assert_eq!(proposal.row_count, 2);
+        assert_eq!(proposal.sample[0][5], "a, b");
+        assert!(proposal
+            .sql
+            .starts_with("CREATE TABLE IF NOT EXISTS public.imported"));
+    }
+
+    #[test]
+    fn test_without_header() {
+        let proposal = propose(&CsvImportRequest {
+            has_header: false,
+            delimiter: ';',
+            ..request("1;x\n2;y\n")
+        })
+        .unwrap();
+        assert_eq!(
+            columns(&proposal),
+            vec![("column_1", "bigint", false), ("column_2", "text", false)]
+        );
+        assert_eq!(
+            copy_sql(
+                &CsvImportRequest {
+                    has_header: false,
+                    delimiter: ';',
+                    ..request("")
+                },
+                &proposal.table
+            ),
+            "COPY public.imported (column_1, column_2) FROM STDIN \
+             WITH (FORMAT csv, HEADER false, DELIMITER ';')"
+        );
+    }
+
+    #[test]
+    fn test_edited_columns_must_match() {
+        let mut edited = propose(&request("a,b\n1,2\n")).unwrap().table.columns;
+        edited[0].data_type = "integer".to_string();
+        edited[0].primary_key = true;
+        let proposal = propose(&CsvImportRequest {
+            columns: Some(edited.clone()),
+            ..request("a,b\n1,2\n")
+        })
+        .unwrap();
+        assert!(proposal.sql.contains("a integer PRIMARY KEY"));
+
+        edited.pop();
+        assert!(propose(&CsvImportRequest {
+            columns: Some(edited),
+            ..request("a,b\n1,2\n")
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_rejects_bad_input() {
+        assert!(propose(&request("")).is_err());
+        assert!(propose(&request("a,b\n1,2,3\n")).is_err());
+        assert!(propose(&CsvImportRequest {
+            delimiter: '\'',
+            ..request("a\n1\n")
+        })
+        .is_err());
+        assert!(propose(&CsvImportRequest {
+            table_name: String::new(),
+            ..request("a\n1\n")
+        })
+        .is_err());
+
+        // Names that need quoting are fine
+        let proposal = propose(&CsvImportRequest {
+            table_name: "Imported Rows".to_string(),
+            ..request("a\n1\n")
+        })
+        .unwrap();
+        assert!(proposal
+            .sql
+            .starts_with("CREATE TABLE IF NOT EXISTS public.\"Imported Rows\""));
+    }
+}
@@ -0,0 +1,551 @@
+use serde::{Deserialize, Serialize};
+/// Data Diff Service
+///
+/// Compares the rows of two tables by primary key:
+/// - Each side is reduced to `(key, md5(row))` pairs in the database, so only
+///   keys and hashes cross the wire for the full comparison
+/// - Rows present only on the right are reported as inserted, rows present
+///   only on the left as deleted, and rows whose hash differs as updated
+/// - Full rows are fetched only for a capped sample of each category
+///
+/// Both sides take their own pool, so the comparison works across
+/// connections as well as within one.
+///
+/// Query results already fetched for the console are compared in memory by
+/// chosen key columns instead (`diff_results`).
+use serde_json::Value;
+use sqlx::{PgPool, Row};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::services::identifier::{quote_ident, resolve_relation};
+use crate::services::query_service;
+
+/// Default number of sample rows reported per category
+pub const DEFAULT_SAMPLE_LIMIT: usize = 20;
+
+/// Upper bound on sample rows reported per category
+pub const MAX_SAMPLE_LIMIT: usize = 100;
+
+/// One side of a data comparison
+#[derive(Debug, Clone)]
+pub struct TableRef<'a> {
+    pub pool: &'a PgPool,
+    pub schema: &'a str,
+    pub table: &'a str,
+}
+
+/// A row whose key exists on both sides with different contents
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RowChange {
+    pub key: Value,
+    pub changed_columns: Vec<String>,
+    pub left: Value,
+    pub right: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DataDiff {
+    pub left: String,
+    pub right: String,
+    pub key_columns: Vec<String>,
+    pub identical: bool,
+    pub left_rows: u64,
+    pub right_rows: u64,
+    pub unchanged: u64,
+    /// Rows present only on the right side
+    pub inserted: u64,
+    pub updated: u64,
+    /// Rows present only on the left side
+    pub deleted: u64,
+    pub sample_limit: usize,
+    pub inserted_sample: Vec<Value>,
+    pub updated_sample: Vec<RowChange>,
+    pub deleted_sample: Vec<Value>,
+}
+
+/// Keys grouped by how they differ, sorted for stable sampling
+#[derive(Debug, Default, PartialEq)]
+pub struct KeyComparison {
+    pub inserted: Vec<String>,
+    pub updated: Vec<String>,
+    pub deleted: Vec<String>,
+    pub unchanged: u64,
+}
+
+impl KeyComparison {
+    pub fn is_identical(&self) -> bool {
+        self.inserted.is_empty() && self.updated.is_empty() && self.deleted.is_empty()
+    }
+}
+
+pub struct DataDiffService;
+
+impl DataDiffService {
+    /// Compare the rows of two tables by primary key
+    pub async fn diff(
+        left: TableRef<'_>,
+        right: TableRef<'_>,
+        sample_limit: usize,
+    ) -> Result<DataDiff, String> {
+        let sample_limit = sample_limit.min(MAX_SAMPLE_LIMIT);
+
+        let left_name = resolve_relation(left.pool, left.schema, left.table)
+            .await
+            .map_err(|e| format!("Failed to resolve left table: {}", e))?;
+        let right_name = resolve_relation(right.pool, right.schema, right.table)
+            .await
+            .map_err(|e| format!("Failed to resolve right table: {}", e))?;
+
+        let key_columns = Self::primary_key(left.pool, left.schema, left.table).await?;
+        if key_columns.is_empty() {
+            return Err(format!("Table {} has no primary key", left_name));
+        }
+        let right_key = Self::primary_key(right.pool, right.schema, right.table).await?;
+        if right_key != key_columns {
+            return Err(format!(
+                "Primary keys differ: ({}) vs ({})",
+                key_columns.join(", "),
+                right_key.join(", ")
+            ));
+        }
+
+        let key_expr = key_expression(&key_columns);
+        let (left_hashes, right_hashes) = tokio::try_join!(
+            Self::row_hashes(left.pool, &left_name, &key_expr),
+            Self::row_hashes(right.pool, &right_name, &key_expr),
+        )?;
+
+        let comparison = compare_hashes(&left_hashes, &right_hashes);
+
+        let inserted_keys: Vec<String> = sample(&comparison.inserted, sample_limit);
+        let deleted_keys: Vec<String> = sample(&comparison.deleted, sample_limit);
+        let updated_keys: Vec<String> = sample(&comparison.updated, sample_limit);
+
+        let inserted_sample =
+            Self::fetch_rows(right.pool, &right_name, &key_expr, &inserted_keys).await?;
+        let deleted_sample =
+            Self::fetch_rows(left.pool, &left_name, &key_expr, &deleted_keys).await?;
+        let updated_left =
+            Self::fetch_rows(left.pool, &left_name, &key_expr, &updated_keys).await?;
+        let updated_right =
+            Self::fetch_rows(right.pool, &right_name, &key_expr, &updated_keys).await?;
+
+        let updated_sample = updated_keys
+            .iter()
+            .filter_map(|key| {
+                let left = updated_left.get(key)?.clone();
+                let right = updated_right.get(key)?.clone();
+                Some(RowChange {
+                    key: serde_json::from_str(key).unwrap_or(Value::Null),
+                    changed_columns: changed_columns(&left, &right),
+                    left,
+                    right,
+                })
+            })
+            .collect();
+
+        Ok(DataDiff {
+            left: left_name,
+            right: right_name,
+            key_columns,
+            identical: comparison.is_identical(),
+            left_rows: left_hashes.len() as u64,
+            right_rows: right_hashes.len() as u64,
+            unchanged: comparison.unchanged,
+            inserted: comparison.inserted.len() as u64,
+            updated: comparison.updated.len() as u64,
+            deleted: comparison.deleted.len() as u64,
+            sample_limit,
+            inserted_sample: ordered(inserted_sample, &inserted_keys),
+            updated_sample,
+            deleted_sample: ordered(deleted_sample, &deleted_keys),
+        })
+    }
+
+    /// Primary key columns of a table in key order
+    async fn primary_key(pool: &PgPool, schema: &str, table: &str) -> Result<Vec<String>, String> {
+        sqlx::query_scalar(
+            r#"
+            SELECT a.attname::text
+            FROM pg_catalog.pg_index i
+            JOIN pg_catalog.pg_class c ON c.oid = i.indrelid
+            JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+            CROSS JOIN LATERAL unnest(i.indkey) WITH ORDINALITY AS k(attnum, ord)
+            JOIN pg_catalog.pg_attribute a ON a.attrelid = c.oid AND a.attnum = k.attnum
+            WHERE n.nspname = $1 AND c.relname = $2 AND i.indisprimary
+            ORDER BY k.ord
+            "#,
+        )
+        .bind(schema)
+        .bind(table)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to get primary key: {}", e))
+    }
+
+    /// Canonical key and content hash of every row, each counting against the
+    /// row limit since the keys are the table's values
+    async fn row_hashes(
+        pool: &PgPool,
+        relation: &str,
+        key_expr: &str,
+    ) -> Result<HashMap<String, String>, String> {
+        let query = format!(
+            "SELECT ({})::text AS row_key, md5(to_jsonb(t)::text) AS row_hash FROM {} t",
+            key_expr, relation
+        );
+
+        let rows = query_service::fetch_all_limited(sqlx::query(&query), pool)
+            .await
+            .map_err(|e| format!("Failed to hash rows of {}: {}", relation, e))?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get("row_key"), row.get("row_hash")))
+            .collect())
+    }
+
+    /// Fetch full rows for a set of canonical keys
+    async fn fetch_rows(
+        pool: &PgPool,
+        relation: &str,
+        key_expr: &str,
+        keys: &[String],
+    ) -> Result<HashMap<String, Value>, String> {
+        if keys.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let query = format!(
+            "SELECT ({key})::text AS row_key, to_jsonb(t) AS row_data FROM {relation} t \
+             WHERE ({key}) = ANY($1::text[]::jsonb[])",
+            key = key_expr,
+            relation = relation
+        );
+
+        let rows = query_service::fetch_all_limited(sqlx::query(&query).bind(keys), pool)
+            .await
+            .map_err(|e| format!("Failed to fetch rows of {}: {}", relation, e))?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get("row_key"), row.get("row_data")))
+            .collect())
+    }
+}
+
+/// Rows of a query result, as returned to the console
+#[derive(Debug, Clone, Copy)]
+pub struct ResultRows<'a> {
+    /// How the side is named in the report, e.g. the query
+    pub name: &'a str,
+    pub columns: &'a [String],
+    pub rows: &'a [Vec<Value>],
+}
+
+/// Compare two query results by the values of `key_columns`
+///
+/// Results are already in memory, so rows are compared as JSON objects
+/// rather than hashed in the database. Both sides must have the key columns
+/// and unique keys.
+pub fn diff_results(
+    left: ResultRows<'_>,
+    right: ResultRows<'_>,
+    key_columns: &[String],
+    sample_limit: usize,
+) -> Result<DataDiff, String> {
+    if key_columns.is_empty() {
+        return Err("At least one key column is required".to_string());
+    }
+    let sample_limit = sample_limit.min(MAX_SAMPLE_LIMIT);
+    let left_rows = keyed_rows(left, key_columns)?;
+    let right_rows = keyed_rows(right, key_columns)?;
+
+    // The serialized row stands in for the hash: equal rows serialize equally
+    let serialized = |rows: &HashMap<String, Value>| -> HashMap<String, String> {
+        rows.iter()
+            .map(|(key, row)| (key.clone(), row.to_string()))
+            .collect()
+    };
+    let comparison = compare_hashes(&serialized(&left_rows), &serialized(&right_rows));
+
+    let rows_for = |rows: &HashMap<String, Value>, keys: &[String]| -> Vec<Value> {
+        sample(keys, sample_limit)
+            .iter()
+            .filter_map(|key| rows.get(key).cloned())
+            .collect()
+    };
+    let updated_sample = sample(&comparison.updated, sample_limit)
+        .iter()
+        .map(|key| {
+            let left = left_rows[key].clone();
+            let right = right_rows[key].clone();
+            RowChange {
+                key: serde_json::from_str(key).unwrap_or(Value::Null),
+                changed_columns: changed_columns(&left, &right),
+                left,
+                right,
+            }
+        })
+        .collect();
+
+    Ok(DataDiff {
+        left: left.name.to_string(),
+        right: right.name.to_string(),
+        key_columns: key_columns.to_vec(),
+        identical: comparison.is_identical(),
+        left_rows: left_rows.len() as u64,
+        right_rows: right_rows.len() as u64,
+        unchanged: comparison.unchanged,
+        inserted: comparison.inserted.len() as u64,
+        updated: comparison.updated.len() as u64,
+        deleted: comparison.deleted.len() as u64,
+        sample_limit,
+        inserted_sample: rows_for(&right_rows, &comparison.inserted),
+        updated_sample,
+        deleted_sample: rows_for(&left_rows, &comparison.deleted),
+    })
+}
+
+/// Rows as JSON objects by the canonical text of their key
+fn keyed_rows(
+    side: ResultRows<'_>,
+    key_columns: &[String],
+) -> Result<HashMap<String, Value>, String> {
+    let key_indexes = key_columns
+        .iter()
+        .map(|key| {
+            side.columns
+                .iter()
+                .position(|c| c == key)
+                .ok_or_else(|| format!("The {} has no column '{}'", side.name, key))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut keyed = HashMap::with_capacity(side.rows.len());
+    for row in side.rows {
+        let key: serde_json::Map<String, Value> = key_columns
+            .iter()
+            .zip(&key_indexes)
+            .map(|(name, &i)| (name.clone(), row.get(i).cloned().unwrap_or(Value::Null)))
+            .collect();
+        let key = Value::Object(key).to_string();
+        let object: serde_json::Map<String, Value> = side
+            .columns
+            .iter()
+            .cloned()
+            .zip(row.iter().cloned())
+            .collect();
+        if keyed.insert(key.clone(), Value::Object(object)).is_some() {
+            return Err(format!(
+                "The {} returns more than one row for key {}",
+                side.name, key
+            ));
+        }
+    }
+    Ok(keyed)
+}
+
+/// `jsonb_build_object(...)` over the key columns, giving a canonical text form
+fn key_expression(key_columns: &[String]) -> String {
+    let pairs: Vec<String> = key_columns
+        .iter()
+        .map(|c| format!("'{}', t.{}", c.replace('\'', "''"), quote_ident(c)))
+        .collect();
+    format!("jsonb_build_object({})", pairs.join(", "))
+}
+
+/// Classify keys by comparing the row hashes of both sides
+pub fn compare_hashes(
+    left: &HashMap<String, String>,
+    right: &HashMap<String, String>,
+) -> KeyComparison {
+    let mut comparison = KeyComparison::default();
+
+    for (key, left_hash) in left {
+        match right.get(key) {
+            None => comparison.deleted.push(key.clone()),
+            Some(right_hash) if right_hash != left_hash => comparison.updated.push(key.clone()),
+            Some(_) => comparison.unchanged += 1,
+        }
+    }
+    comparison.inserted = right
+        .keys()
+        .filter(|k| !left.contains_key(*k))
+        .cloned()
+        .collect();
+
+    comparison.inserted.sort();
+    comparison.updated.sort();
+    comparison.deleted.sort();
+    comparison
+}
+
+/// Names of the columns whose values differ between two JSON rows
+pub fn changed_columns(left: &Value, right: &Value) -> Vec<String> {
+    let empty = serde_json::Map::new();
+    let left = left.as_object().unwrap_or(&empty);
+    let right = right.as_object().unwrap_or(&empty);
+
+    let columns: BTreeMap<&String, ()> = left.keys().chain(right.keys()).map(|k| (k, ())).collect();
+    columns
+        .into_keys()
+        .filter(|c| left.get(*c) != right.get(*c))
+        .cloned()
+        .collect()
+}
+
+fn sample(keys: &[String], limit: usize) -> Vec<String> {
+    keys.iter().take(limit).cloned().collect()
+}
+
+/// Return fetched rows in the order of the sampled keys
+fn ordered(mut rows: HashMap<String, Value>, keys: &[String]) -> Vec<Value> {
+    keys.iter().filter_map(|k| rows.remove(k)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn hashes(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_compare_hashes() {
+        let left = hashes(&[
+            (r#"{"id": 1}"#, "a"),
+            (r#"{"id": 2}"#, "b"),
+            (r#"{"id": 3}"#, "c"),
+        ]);
+        let right = hashes(&[
+            (r#"{"id": 1}"#, "a"),
+            (r#"{"id": 2}"#, "changed"),
+            (r#"{"id": 4}"#, "d"),
+        ]);
+
+        let comparison = compare_hashes(&left, &right);
+        assert_eq!(comparison.unchanged, 1);
+        assert_eq!(comparison.updated, vec![r#"{"id": 2}"#.to_string()]);
+        assert_eq!(comparison.deleted, vec![r#"{"id": 3}"#.to_string()]);
+        assert_eq!(comparison.inserted, vec![r#"{"id": 4}"#.to_string()]);
+        assert!(!comparison.is_identical());
+    }
+
+    #[test]
+    fn test_compare_identical() {
+        let side = hashes(&[(r#"{"id": 1}"#, "a"), (r#"{"id": 2}"#, "b")]);
+        let comparison = compare_hashes(&side, &side);
+        assert_eq!(comparison.unchanged, 2);
+        assert!(comparison.is_identical());
+    }
+
+    #[test]
+    fn test_changed_columns() {
+        let left = json!({"id": 1, "email": "a@example.com", "name": "A", "old": true});
+        let right = json!({"id": 1, "email": "b@example.com", "name": "A", "new": 1});
+        assert_eq!(changed_columns(&left, &right), vec!["email", "new", "old"]);
+    }
+
+    #[test]
+    fn test_diff_results() {
+        let columns: Vec<String> = ["id", "region", "total"]
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        let before = vec![
+            vec![json!(1), json!("north"), json!("10.00")],
+            vec![json!(2), json!("south"), json!("5.00")],
+            vec![json!(3), json!("east"), json!(null)],
+        ];
+        let after = vec![
+            vec![json!(2), json!("south"), json!("7.50")],
+            vec![json!(1), json!("north"), json!("10.00")],
+            vec![json!(4), json!("west"), json!("1.00")],
+        ];
+        let side = |name, rows| ResultRows {
+            name,
+            columns: &columns,
+            rows,
+        };
+
+        let diff = diff_results(
+            side("before", &before),
+            side("after", &after),
+            &["id".to_string()],
+            20,
+        )
+        .unwrap();
+        assert!(!diff.identical);
+        assert_eq!((diff.left_rows, diff.right_rows), (3, 3));
+        assert_eq!(
+            (diff.unchanged, diff.inserted, diff.updated, diff.deleted),
+            (1, 1, 1, 1)
+        );
+        assert_eq!(
+            diff.inserted_sample,
+            vec![json!({"id": 4, "region": "west", "total": "1.00"})]
+        );
+        assert_eq!(diff.deleted_sample[0]["id"], json!(3));
+        assert_eq!(diff.updated_sample[0].key, json!({"id": 2}));
+        assert_eq!(diff.updated_sample[0].changed_columns, vec!["total"]);
+
+        let same = diff_results(
+            side("before", &before),
+            side("after", &before),
+            &["region".to_string(), "id".to_string()],
+            20,
+        )
+        .unwrap();
+        assert!(same.identical);
+        assert_eq!(same.unchanged, 3);
+    }
+
+    #[test]
+    fn test_diff_results_errors() {
+        let columns = vec!["id".to_string()];
+        let rows = vec![vec![json!(1)], vec![json!(1)]];
+        let side = ResultRows {
+            name: "left query",
+            columns: &columns,
+            rows: &rows,
+        };
+        assert!(diff_results(side, side, &[], 20).is_err());
+        assert!(diff_results(side, side, &["code".to_string()], 20)
+            .unwrap_err()
+            .contains("The left query has no column 'code'"));
+        assert!(diff_results(side, side, &columns, 20)
+            .unwrap_err()
+            .contains("more than one row for key {\"id\":1}"));
+    }
+
+    #[test]
+    fn test_key_expression() {
+        assert_eq!(
+            key_expression(&["id".to_string()]),
+            "jsonb_build_object('id', t.id)"
+        );
+        assert_eq!(
+            key_expression(&["Tenant Id".to_string(), "it's".to_string()]),
+            "jsonb_build_object('Tenant Id', t.\"Tenant Id\", 'it''s', t.\"it's\")"
+        );
+    }
+
+    #[test]
+    fn test_sample_and_order() {
+        let keys = vec!["b".to_string(), "a".to_string(), "c".to_string()];
+        let sampled = sample(&keys, 2);
+        assert_eq!(sampled, vec!["b".to_string(), "a".to_string()]);
+
+        let rows: HashMap<String, Value> =
+            [("a".to_string(), json!(1)), ("b".to_string(), json!(2))]
+                .into_iter()
+                .collect();
+        assert_eq!(ordered(rows, &sampled), vec![json!(2), json!(1)]);
+    }
+}
@@ -0,0 +1,235 @@
+use serde::{Deserialize, Serialize};
+/// DDL Service
+///
+/// Reconstructs CREATE statements from the system catalogs:
+/// - Columns with types, defaults, and NOT NULL
+/// - Table constraints (primary key, unique, check, foreign key, exclusion)
+/// - Indexes that are not backing a constraint
+use sqlx::{PgPool, Row};
+
+use crate::services::identifier::quote_ident;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DdlColumn {
+    pub name: String,
+    pub data_type: String,
+    pub not_null: bool,
+    pub default: Option<String>,
+    pub identity: Option<String>,
+    pub generated: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DdlConstraint {
+    pub name: String,
+    pub definition: String,
+}
+
+pub struct DdlService;
+
+impl DdlService {
+    /// Build the full CREATE TABLE statement (plus indexes) for a table
+    pub async fn table_ddl(pool: &PgPool, schema: &str, table: &str) -> Result<String, String> {
+        let oid: Option<i64> = sqlx::query_scalar(
+            r#"
+            SELECT c.oid::bigint
+            FROM pg_catalog.pg_class c
+            JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+            WHERE n.nspname = $1 AND c.relname = $2 AND c.relkind IN ('r', 'p')
+            "#,
+        )
+        .bind(schema)
+        .bind(table)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| format!("Failed to look up table: {}", e))?;
+        let oid = oid.ok_or_else(|| format!("Table {}.{} not found", schema, table))?;
+
+        let columns = sqlx::query(
+            r#"
+            SELECT
+                a.attname::text as name,
+                pg_catalog.format_type(a.atttypid, a.atttypmod) as data_type,
+                a.attnotnull as not_null,
+                CASE WHEN a.attgenerated = '' THEN pg_catalog.pg_get_expr(d.adbin, d.adrelid) END as default_expr,
+                CASE a.attidentity WHEN 'a' THEN 'ALWAYS' WHEN 'd' THEN 'BY DEFAULT' END as identity,
+                CASE WHEN a.attgenerated = 's' THEN pg_catalog.pg_get_expr(d.adbin, d.adrelid) END as generated
+            FROM pg_catalog.pg_attribute a
+            LEFT JOIN pg_catalog.pg_attrdef d ON d.adrelid = a.attrelid AND d.adnum = a.attnum
+            WHERE a.attrelid = $1::bigint::oid AND a.attnum > 0 AND NOT a.attisdropped
+            ORDER BY a.attnum
+            "#,
+        )
+        .bind(oid)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to get columns: {}", e))?
+        .iter()
+        .map(|row| DdlColumn {
+            name: row.get("name"),
+            data_type: row.get("data_type"),
+            not_null: row.get("not_null"),
+            default: row.get("default_expr"),
+            identity: row.get("identity"),
+            generated: row.get("generated"),
+        })
+        .collect::<Vec<_>>();
+
+        let constraints = sqlx::query(
+            r#"
+            SELECT conname::text as name, pg_catalog.pg_get_constraintdef(oid, true) as definition
+            FROM pg_catalog.pg_constraint
+            WHERE conrelid = $1::bigint::oid AND contype IN ('p', 'u', 'c', 'f', 'x')
+            ORDER BY CASE contype WHEN 'p' THEN 0 WHEN 'u' THEN 1 WHEN 'c' THEN 2 WHEN 'f' THEN 3 ELSE 4 END,
+                     conname
+            "#,
+        )
+        .bind(oid)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to get constraints: {}", e))?
+        .iter()
+        .map(|row| DdlConstraint {
+            name: row.get("name"),
+            definition: row.get("definition"),
+        })
+        .collect::<Vec<_>>();
+
+        let indexes: Vec<String> = sqlx::query_scalar(
+            r#"
+            SELECT pg_catalog.pg_get_indexdef(i.indexrelid)
+            FROM pg_catalog.pg_index i
+            WHERE i.indrelid = $1::bigint::oid
+              AND NOT EXISTS (
+                  SELECT 1 FROM pg_catalog.pg_constraint c WHERE c.conindid = i.indexrelid
+              )
+            ORDER BY i.indexrelid
+            "#,
+        )
+        .bind(oid)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to get indexes: {}", e))?;
+
+        Ok(Self::build_create_table(
+            schema,
+            table,
+            &columns,
+            &constraints,
+            &indexes,
+        ))
+    }
+
+    /// Assemble a CREATE TABLE statement from catalog information
+    pub fn build_create_table(
+        schema: &str,
+        table: &str,
+        columns: &[DdlColumn],
+        constraints: &[DdlConstraint],
+        indexes: &[String],
+    ) -> String {
+        let mut lines: Vec<String> = columns
+            .iter()
+            .map(|col| {
+                let mut def = format!("    {} {}", quote_ident(&col.name), col.data_type);
+                if let Some(identity) = &col.identity {
+                    def.push_str(&format!(" GENERATED {} AS IDENTITY", identity));
+                }
+                if let Some(expr) = &col.generated {
+                    def.push_str(&format!(" GENERATED ALWAYS AS ({}) STORED", expr));
+                }
+                if let Some(default) = &col.default {
+                    def.push_str(&format!(" DEFAULT {}", default));
+                }
+                if col.not_null {
+                    def.push_str(" NOT NULL");
+                }
+                def
+            })
+            .collect();
+
+        lines.extend(
+            constraints
+                .iter()
+                .map(|c| format!("    CONSTRAINT {} {}", quote_ident(&c.name), c.definition)),
+        );
+
+        let mut ddl = format!(
+            "CREATE TABLE {}.{} (\n{}\n);\n",
+            quote_ident(schema),
+            quote_ident(table),
+            lines.join(",\n")
+        );
+
+        for index in indexes {
+            ddl.push('\n');
+            ddl.push_str(index);
+            ddl.push_str(";\n");
+        }
+
+        ddl
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_create_table() {
+        let columns = vec![
+            DdlColumn {
+                name: "id".to_string(),
+                data_type: "integer".to_string(),
+                not_null: true,
+                default: None,
+                identity: Some("BY DEFAULT".to_string()),
+                generated: None,
+            },
+            DdlColumn {
+                name: "email".to_string(),
+                data_type: "character varying(255)".to_string(),
+                not_null: false,
+                default: Some("'unknown'::character varying".to_string()),
+                identity: None,
+                generated: None,
+            },
+        ];
+        let constraints = vec![DdlConstraint {
+            name: "users_pkey".to_string(),
+            definition: "PRIMARY KEY (id)".to_string(),
+        }];
+        let indexes =
+            vec!["CREATE INDEX users_email_idx ON public.users USING btree (email)".to_string()];
+
+        let ddl =
+            DdlService::build_create_table("public", "users", &columns, &constraints, &indexes);
+
+        assert_eq!(
+            ddl,
+            "CREATE TABLE public.users (\n\
+             \x20   id integer GENERATED BY DEFAULT AS IDENTITY NOT NULL,\n\
+             \x20   email character varying(255) DEFAULT 'unknown'::character varying,\n\
+             \x20   CONSTRAINT users_pkey PRIMARY KEY (id)\n\
+             );\n\
+             \n\
+             CREATE INDEX users_email_idx ON public.users USING btree (email);\n"
+        );
+    }
+
+    #[test]
+    fn test_build_create_table_generated_column() {
+        let columns = vec![DdlColumn {
+            name: "total".to_string(),
+            data_type: "numeric".to_string(),
+            not_null: false,
+            default: None,
+            identity: None,
+            generated: Some("(price * qty)".to_string()),
+        }];
+
+        let ddl = DdlService::build_create_table("sales", "Orders", &columns, &[], &[]);
+        assert!(ddl.starts_with("CREATE TABLE sales.\"Orders\" ("));
+        assert!(ddl.contains("total numeric GENERATED ALWAYS AS ((price * qty)) STORED"));
+    }
+}
@@ -0,0 +1,970 @@
+use crate::services::saved_query_service::{
+    SavedQuery, SavedQueryFilter, SavedQueryInput, SavedQueryStore,
+};
+use crate::services::scheduler_service::{
+    AlertCondition, Delivery, Schedule, ScheduleInput, Scheduler,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+/// Definitions as Code
+///
+/// Saved queries and their schedules (with alert conditions and delivery)
+/// declared in a TOML or YAML file, `DEFINITIONS_FILE`, so they can be kept
+/// in version control next to the rest of a deployment:
+/// - The file is validated as a whole and applied at startup; a file that
+///   does not validate stops the server from starting
+/// - Queries and schedules created from the file are tagged with its path,
+///   and matched to their definitions by name
+/// - Changes made to them through the API are drift: listed by the report,
+///   and undone the next time the file is applied
+/// - Definitions removed from the file are deleted when it is applied;
+///   queries and schedules made through the API are never touched
+/// - Existing schedules and their queries can be exported in the same format
+///   to start a file from
+use std::path::Path;
+
+/// Format of a definitions file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefinitionsFormat {
+    Toml,
+    Yaml,
+}
+
+impl DefinitionsFormat {
+    /// The format named by a file extension or `?format=` value
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "toml" => Some(DefinitionsFormat::Toml),
+            "yaml" | "yml" => Some(DefinitionsFormat::Yaml),
+            _ => None,
+        }
+    }
+
+    /// The format of a file, from its extension
+    pub fn from_path(path: &Path) -> Result<Self, String> {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(Self::from_name)
+            .ok_or_else(|| {
+                format!(
+                    "Definitions file {} must end in .toml, .yaml or .yml",
+                    path.display()
+                )
+            })
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            DefinitionsFormat::Toml => "toml",
+            DefinitionsFormat::Yaml => "yaml",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            DefinitionsFormat::Toml => "application/toml",
+            DefinitionsFormat::Yaml => "application/yaml",
+        }
+    }
+
+    pub fn parse(self, text: &str) -> Result<DefinitionsFile, String> {
+        match self {
+            DefinitionsFormat::Toml => toml::from_str(text).map_err(|e| e.to_string()),
+            DefinitionsFormat::Yaml => serde_norway::from_str(text).map_err(|e| e.to_string()),
+        }
+    }
+
+    pub fn render(self, file: &DefinitionsFile) -> Result<String, String> {
+        match self {
+            DefinitionsFormat::Toml => toml::to_string_pretty(file).map_err(|e| e.to_string()),
+            DefinitionsFormat::Yaml => serde_norway::to_string(file).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Contents of a definitions file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DefinitionsFile {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub queries: Vec<QueryDefinition>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schedules: Vec<ScheduleDefinition>,
+}
+
+/// A saved query, as declared in the file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QueryDefinition {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub sql: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub folder: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+/// A schedule, as declared in the file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleDefinition {
+    pub name: String,
+    /// Name of a query declared in the file, or else of a query saved
+    /// through the API
+    pub query: String,
+    pub cron: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub params: BTreeMap<String, serde_json::Value>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delivery: Option<Delivery>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alert: Option<AlertCondition>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DefinitionKind {
+    Query,
+    Schedule,
+}
+
+/// What applying the file does to one definition
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DefinitionAction {
+    /// Matches the file
+    Unchanged,
+    /// Declared in the file, but not there (new, or deleted through the API)
+    Create,
+    /// Differs from the file, in `fields`
+    Update,
+    /// Made from the file, but no longer declared in it
+    Delete,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DefinitionChange {
+    pub kind: DefinitionKind,
+    pub name: String,
+    /// The saved query or schedule, when it exists
+    pub id: Option<String>,
+    pub action: DefinitionAction,
+    /// Fields that differ from the file, for an update
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<&'static str>,
+}
+
+/// How the saved queries and schedules compare with the file
+#[derive(Debug, Clone, Serialize)]
+pub struct DefinitionsReport {
+    pub file: String,
+    /// Whether the file exists; without it, nothing is managed
+    pub found: bool,
+    /// Whether every definition matches the file
+    pub in_sync: bool,
+    pub changes: Vec<DefinitionChange>,
+}
+
+/// Which saved query a schedule definition runs
+#[derive(Debug, Clone, PartialEq)]
+enum QueryTarget {
+    /// Declared in the file, by index
+    Declared(usize),
+    /// Saved through the API, by id
+    Saved(String),
+}
+
+/// The validated file, and what applying it changes
+struct Plan {
+    queries: Vec<(SavedQueryInput, DefinitionChange)>,
+    schedules: Vec<(ScheduleInput, QueryTarget, DefinitionChange)>,
+    deletions: Vec<DefinitionChange>,
+}
+
+impl Plan {
+    fn changes(&self) -> Vec<DefinitionChange> {
+        self.queries
+            .iter()
+            .map(|(_, change)| change)
+            .chain(self.schedules.iter().map(|(_, _, change)| change))
+            .chain(&self.deletions)
+            .cloned()
+            .collect()
+    }
+}
+
+impl DefinitionsFile {
+    /// Validate every definition and compare it with the saved queries and
+    /// schedules the file `source` manages, collecting every error found
+    fn plan(
+        &self,
+        source: &str,
+        saved: &[SavedQuery],
+        schedules: &[Schedule],
+        scheduler: &Scheduler,
+    ) -> Result<Plan, String> {
+        let mut errors = Vec::new();
+        let managed_query = |name: &str| {
+            saved
+                .iter()
+                .find(|q| q.managed_by.as_deref() == Some(source) && q.name == name)
+        };
+        let managed_schedule = |name: &str| {
+            schedules
+                .iter()
+                .find(|s| s.managed_by.as_deref() == Some(source) && s.name == name)
+        };
+
+        let mut queries = Vec::with_capacity(self.queries.len());
+        let mut names = HashSet::new();
+        for definition in &self.queries {
+            let input = SavedQueryInput {
+                name: definition.name.clone(),
+                description: definition.description.clone(),
+                sql: definition.sql.clone(),
+                folder: definition.folder.clone(),
+                tags: definition.tags.clone(),
+            };
+            let input = match input.normalize() {
+                Ok(input) => input,
+                Err(e) => {
+                    errors.push(format!("Query '{}': {}", definition.name, e));
+                    continue;
+                }
+            };
+            if !names.insert(input.name.clone()) {
+                errors.push(format!("Query '{}' is declared twice", input.name));
+                continue;
+            }
+            let change = match managed_query(&input.name) {
+                Some(existing) => {
+                    let mut fields = Vec::new();
+                    if existing.description != input.description {
+                        fields.push("description");
+                    }
+                    if existing.sql != input.sql {
+                        fields.push("sql");
+                    }
+                    if existing.folder != input.folder {
+                        fields.push("folder");
+                    }
+                    if existing.tags != input.tags {
+                        fields.push("tags");
+                    }
+                    DefinitionChange::found(
+                        DefinitionKind::Query,
+                        &existing.name,
+                        &existing.id,
+                        fields,
+                    )
+                }
+                None => DefinitionChange::create(DefinitionKind::Query, &input.name),
+            };
+            queries.push((input, change));
+        }
+
+        let mut planned = Vec::with_capacity(self.schedules.len());
+        let mut names = HashSet::new();
+        for definition in &self.schedules {
+            let name = definition.name.trim();
+            let declared = queries
+                .iter()
+                .position(|(input, _)| input.name == definition.query.trim());
+            // Queries made from the file but no longer in it are about to go
+            let api_saved: Vec<&SavedQuery> = saved
+                .iter()
+                .filter(|q| {
+                    q.managed_by.as_deref() != Some(source) && q.name == definition.query.trim()
+                })
+                .collect();
+            let (target, sql) = match (declared, api_saved.as_slice()) {
+                (Some(index), _) => (QueryTarget::Declared(index), &queries[index].0.sql),
+                (None, [query]) => (QueryTarget::Saved(query.id.clone()), &query.sql),
+                (None, []) => {
+                    errors.push(format!(
+                        "Schedule '{}': no query named '{}'",
+                        name, definition.query
+                    ));
+                    continue;
+                }
+                (None, _) => {
+                    errors.push(format!(
+                        "Schedule '{}': several saved queries are named '{}'; declare the query in the file",
+                        name, definition.query
+                    ));
+                    continue;
+                }
+            };
+
+            let input = ScheduleInput {
+                name: definition.name.clone(),
+                saved_query_id: match &target {
+                    QueryTarget::Saved(id) => id.clone(),
+                    // Filled in once the query is saved
+                    QueryTarget::Declared(_) => String::new(),
+                },
+                cron: definition.cron.clone(),
+                params: definition.params.clone().into_iter().collect(),
+                enabled: definition.enabled,
+                delivery: definition.delivery.clone(),
+                alert: definition.alert.clone(),
+            };
+            let input = match scheduler.check(input, sql) {
+                Ok(input) => input,
+                Err(e) => {
+                    errors.push(format!("Schedule '{}': {}", name, e));
+                    continue;
+                }
+            };
+            if !names.insert(input.name.clone()) {
+                errors.push(format!("Schedule '{}' is declared twice", input.name));
+                continue;
+            }
+
+            let change = match managed_schedule(&input.name) {
+                Some(existing) => {
+                    let query_id = match &target {
+                        QueryTarget::Saved(id) => Some(id.as_str()),
+                        QueryTarget::Declared(index) => queries[*index].1.id.as_deref(),
+                    };
+                    let mut fields = Vec::new();
+                    if query_id != Some(existing.saved_query_id.as_str()) {
+                        fields.push("query");
+                    }
+                    if existing.cron != input.cron {
+                        fields.push("cron");
+                    }
+                    if existing.params != input.params {
+                        fields.push("params");
+                    }
+                    if existing.enabled != input.enabled {
+                        fields.push("enabled");
+                    }
+                    if existing.delivery != input.delivery {
+                        fields.push("delivery");
+                    }
+                    if existing.alert != input.alert {
+                        fields.push("alert");
+                    }
+                    DefinitionChange::found(
+                        DefinitionKind::Schedule,
+                        &existing.name,
+                        &existing.id,
+                        fields,
+                    )
+                }
+                None => DefinitionChange::create(DefinitionKind::Schedule, &input.name),
+            };
+            planned.push((input, target, change));
+        }
+
+        if !errors.is_empty() {
+            return Err(format!(
+                "Invalid definitions in {}: {}",
+                source,
+                errors.join("; ")
+            ));
+        }
+
+        // Schedules go first, so no schedule is left on a deleted query
+        let declared_schedules: HashSet<&str> = planned
+            .iter()
+            .map(|(input, _, _)| input.name.as_str())
+            .collect();
+        let declared_queries: HashSet<&str> = queries
+            .iter()
+            .map(|(input, _)| input.name.as_str())
+            .collect();
+        let deletions = schedules
+            .iter()
+            .filter(|s| s.managed_by.as_deref() == Some(source))
+            .filter(|s| !declared_schedules.contains(s.name.as_str()))
+            .map(|s| DefinitionChange::delete(DefinitionKind::Schedule, &s.name, &s.id))
+            .chain(
+                saved
+                    .iter()
+                    .filter(|q| q.managed_by.as_deref() == Some(source))
+                    .filter(|q| !declared_queries.contains(q.name.as_str()))
+                    .map(|q| DefinitionChange::delete(DefinitionKind::Query, &q.name, &q.id)),
+            )
+            .collect();
+
+        Ok(Plan {
+            queries,
+            schedules: planned,
+            deletions,
+        })
+    }
+
+    /// Schedules and the saved queries they run, as a file declaring them
+    pub fn export(saved: &[SavedQuery], schedules: &[Schedule]) -> Self {
+        let by_id: HashMap<&str, &SavedQuery> = saved.iter().map(|q| (q.id.as_str(), q)).collect();
+        let mut queries: Vec<QueryDefinition> = Vec::new();
+        let mut definitions = Vec::with_capacity(schedules.len());
+        for schedule in schedules {
+            let Some(query) = by_id.get(schedule.saved_query_id.as_str()) else {
+                continue;
+            };
+            if !queries.iter().any(|q| q.name == query.name) {
+                queries.push(QueryDefinition {
+                    name: query.name.clone(),
+                    description: query.description.clone(),
+                    sql: query.sql.clone(),
+                    folder: query.folder.clone(),
+                    tags: query.tags.clone(),
+                });
+            }
+            definitions.push(ScheduleDefinition {
+                name: schedule.name.clone(),
+                query: query.name.clone(),
+                cron: schedule.cron.clone(),
+                params: schedule.params.clone().into_iter().collect(),
+                enabled: schedule.enabled,
+                delivery: schedule.delivery.clone(),
+                alert: schedule.alert.clone(),
+            });
+        }
+        queries.sort_by_key(|q| q.name.to_lowercase());
+        DefinitionsFile {
+            queries,
+            schedules: definitions,
+        }
+    }
+}
+
+impl DefinitionChange {
+    fn create(kind: DefinitionKind, name: &str) -> Self {
+        Self {
+            kind,
+            name: name.to_string(),
+            id: None,
+            action: DefinitionAction::Create,
+            fields: Vec::new(),
+        }
+    }
+
+    fn found(kind: DefinitionKind, name: &str, id: &str, fields: Vec<&'static str>) -> Self {
+        Self {
+            kind,
+            name: name.to_string(),
+            id: Some(id.to_string()),
+            action: if fields.is_empty() {
+                DefinitionAction::Unchanged
+            } else {
+                DefinitionAction::Update
+            },
+            fields,
+        }
+    }
+
+    fn delete(kind: DefinitionKind, name: &str, id: &str) -> Self {
+        Self {
+            kind,
+            name: name.to_string(),
+            id: Some(id.to_string()),
+            action: DefinitionAction::Delete,
+            fields: Vec::new(),
+        }
+    }
+}
+
+/// The definitions file, applied to the saved query and schedule stores
+pub struct Definitions {
+    path: String,
+}
+
+impl Definitions {
+    pub fn new(path: impl Into<String>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Read and parse the file, `None` if it does not exist
+    pub async fn read(&self) -> Result<Option<DefinitionsFile>, String> {
+        let path = Path::new(&self.path);
+        let format = DefinitionsFormat::from_path(path)?;
+        match tokio::fs::read_to_string(path).await {
+            Ok(text) => format
+                .parse(&text)
+                .map(Some)
+                .map_err(|e| format!("Failed to parse definitions file {}: {}", self.path, e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!(
+                "Failed to read definitions file {}: {}",
+                self.path, e
+            )),
+        }
+    }
+
+    /// Compare the saved queries and schedules with the file, changing nothing
+    pub async fn report(
+        &self,
+        saved_queries: &SavedQueryStore,
+        scheduler: &Scheduler,
+    ) -> Result<DefinitionsReport, String> {
+        let Some(file) = self.read().await? else {
+            return Ok(self.not_found());
+        };
+        let (saved, schedules) = current(saved_queries, scheduler).await;
+        let plan = file.plan(&self.path, &saved, &schedules, scheduler)?;
+        Ok(self.report_of(plan.changes()))
+    }
+
+    /// Bring the saved queries and schedules in line with the file, returning
+    /// what was changed; nothing is changed unless the whole file is valid
+    pub async fn apply(
+        &self,
+        saved_queries: &SavedQueryStore,
+        scheduler: &Scheduler,
+    ) -> Result<DefinitionsReport, String> {
+        let Some(file) = self.read().await? else {
+            return Ok(self.not_found());
+        };
+        let (saved, schedules) = current(saved_queries, scheduler).await;
+        let plan = file.plan(&self.path, &saved, &schedules, scheduler)?;
+        let mut changes = plan.changes();
+
+        for change in &plan.deletions {
+            if let Some(id) = &change.id {
+                match change.kind {
+                    DefinitionKind::Schedule => scheduler.store.delete(id).await?,
+                    DefinitionKind::Query => saved_queries.delete(id).await?,
+                };
+            }
+        }
+
+        let mut query_ids = Vec::with_capacity(plan.queries.len());
+        for (index, (input, change)) in plan.queries.into_iter().enumerate() {
+            let id = match (&change.action, &change.id) {
+                (DefinitionAction::Update, Some(id)) => {
+                    saved_queries.update(id, input).await?;
+                    id.clone()
+                }
+                (DefinitionAction::Create, _) => {
+                    let id = saved_queries.create_managed(input, &self.path).await?.id;
+                    changes[index].id = Some(id.clone());
+                    id
+                }
+                (_, id) => id.clone().unwrap_or_default(),
+            };
+            query_ids.push(id);
+        }
+
+        let offset = query_ids.len();
+        for (index, (mut input, target, change)) in plan.schedules.into_iter().enumerate() {
+            if let QueryTarget::Declared(query) = target {
+                input.saved_query_id = query_ids[query].clone();
+            }
+            match (&change.action, &change.id) {
+                (DefinitionAction::Update, Some(id)) => {
+                    scheduler.store.update(id, input).await?;
+                }
+                (DefinitionAction::Create, _) => {
+                    let id = scheduler.store.create_managed(input, &self.path).await?.id;
+                    changes[offset + index].id = Some(id);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(self.report_of(changes))
+    }
+
+    fn report_of(&self, changes: Vec<DefinitionChange>) -> DefinitionsReport {
+        DefinitionsReport {
+            file: self.path.clone(),
+            found: true,
+            in_sync: changes
+                .iter()
+                .all(|c| c.action == DefinitionAction::Unchanged),
+            changes,
+        }
+    }
+
+    fn not_found(&self) -> DefinitionsReport {
+        DefinitionsReport {
+            file: self.path.clone(),
+            found: false,
+            in_sync: true,
+            changes: Vec::new(),
+        }
+    }
+}
+
+/// Every saved query and schedule
+pub async fn current(
+    saved_queries: &SavedQueryStore,
+    scheduler: &Scheduler,
+) -> (Vec<SavedQuery>, Vec<Schedule>) {
+    (
+        saved_queries.list(&SavedQueryFilter::default()).await,
+        scheduler.store.list().await,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::reload::ReadOnlyMode;
+    use crate::services::audit_service::AuditLogger;
+    use crate::services::scheduler_service::{AlertMetric, AlertOp, DeliveryTarget, ScheduleStore};
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    const TOML: &str = r#"
+[[queries]]
+name = "Orphaned orders"
+sql = "SELECT id FROM orders WHERE customer_id IS NULL AND created_at > now() - :days * interval '1 day'"
+tags = ["ops"]
+
+[[schedules]]
+name = "Nightly orphan check"
+query = "Orphaned orders"
+cron = "0 3 * * *"
+params = { days = 7 }
+
+[schedules.alert]
+metric = "row_count"
+op = "gt"
+value = 0
+
+[schedules.delivery]
+type = "webhook"
+url = "http://hooks.internal/pgadmin"
+include_rows = true
+"#;
+
+    const YAML: &str = r#"
+queries:
+  - name: Orphaned orders
+    sql: SELECT id FROM orders WHERE customer_id IS NULL AND created_at > now() - :days * interval '1 day'
+    tags: [ops]
+schedules:
+  - name: Nightly orphan check
+    query: Orphaned orders
+    cron: 0 3 * * *
+    params:
+      days: 7
+    alert: { metric: row_count, op: gt, value: 0 }
+    delivery:
+      type: webhook
+      url: http://hooks.internal/pgadmin
+      include_rows: true
+"#;
+
+    struct Stores {
+        dir: PathBuf,
+        saved: Arc<SavedQueryStore>,
+        scheduler: Scheduler,
+    }
+
+    impl Drop for Stores {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    async fn stores() -> Stores {
+        let dir = std::env::temp_dir().join(format!("definitions-{}", uuid::Uuid::new_v4()));
+        let saved = Arc::new(SavedQueryStore::load(dir.join("saved.json")).await.unwrap());
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://nobody@127.0.0.1:1/none")
+            .unwrap();
+        let scheduler = Scheduler::new(
+            ScheduleStore::load(dir.join("schedules.json"))
+                .await
+                .unwrap(),
+            saved.clone(),
+            Arc::new(pool),
+            Arc::new(AuditLogger::new(10)),
+            ReadOnlyMode::new(false),
+        );
+        Stores {
+            dir,
+            saved,
+            scheduler,
+        }
+    }
+
+    /// A definitions file holding `contents`, in the stores' directory
+    async fn definitions(stores: &Stores, name: &str, contents: &str) -> Definitions {
+        let path = stores.dir.join(name);
+        tokio::fs::create_dir_all(&stores.dir).await.unwrap();
+        tokio::fs::write(&path, contents).await.unwrap();
+        Definitions::new(path.to_string_lossy())
+    }
+
+    fn actions(report: &DefinitionsReport) -> Vec<(DefinitionKind, &str, DefinitionAction)> {
+        report
+            .changes
+            .iter()
+            .map(|c| (c.kind, c.name.as_str(), c.action))
+            .collect()
+    }
+
+    #[test]
+    fn test_toml_and_yaml_declare_the_same() {
+        let toml = DefinitionsFormat::Toml.parse(TOML).unwrap();
+        assert_eq!(toml, DefinitionsFormat::Yaml.parse(YAML).unwrap());
+
+        let schedule = &toml.schedules[0];
+        assert!(schedule.enabled);
+        assert_eq!(schedule.params["days"], serde_json::json!(7));
+        assert_eq!(
+            schedule.alert,
+            Some(AlertCondition {
+                metric: AlertMetric::RowCount,
+                op: AlertOp::Gt,
+                value: 0.0
+            })
+        );
+        assert_eq!(
+            schedule.delivery.as_ref().unwrap().target,
+            DeliveryTarget::Webhook {
+                url: "http://hooks.internal/pgadmin".to_string()
+            }
+        );
+
+        // Both formats write back what they read
+        for format in [DefinitionsFormat::Toml, DefinitionsFormat::Yaml] {
+            let rendered = format.render(&toml).unwrap();
+            assert_eq!(format.parse(&rendered).unwrap(), toml, "{}", rendered);
+        }
+
+        assert!(DefinitionsFormat::Toml
+            .parse("[[schedules]]\nname = \"x\"\nquery = \"q\"\ncron = \"@daily\"\ncolour = 1")
+            .unwrap_err()
+            .contains("colour"));
+        assert!(DefinitionsFormat::from_path(Path::new("defs.json")).is_err());
+        assert_eq!(
+            DefinitionsFormat::from_path(Path::new("defs.YML")),
+            Ok(DefinitionsFormat::Yaml)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_file_changes_nothing() {
+        let stores = stores().await;
+        let invalid = r#"
+[[queries]]
+name = "Orphaned orders"
+sql = "SELECT :days"
+
+[[schedules]]
+name = "Never"
+query = "Orphaned orders"
+cron = "0 3 31 2 *"
+params = { days = 7 }
+
+[[schedules]]
+name = "Unbound"
+query = "Orphaned orders"
+cron = "@daily"
+
+[[schedules]]
+name = "Dangling"
+query = "Nothing by this name"
+cron = "@daily"
+"#;
+        let definitions = definitions(&stores, "definitions.toml", invalid).await;
+        let error = definitions
+            .apply(&stores.saved, &stores.scheduler)
+            .await
+            .unwrap_err();
+        assert!(
+            error.contains("Schedule 'Never': Cron expression"),
+            "{}",
+            error
+        );
+        assert!(
+            error.contains("Schedule 'Unbound': Missing value for parameter :days"),
+            "{}",
+            error
+        );
+        assert!(
+            error.contains("Schedule 'Dangling': no query named"),
+            "{}",
+            error
+        );
+
+        let (saved, schedules) = current(&stores.saved, &stores.scheduler).await;
+        assert!(saved.is_empty());
+        assert!(schedules.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_apply_and_report_drift() {
+        let stores = stores().await;
+        let definitions = definitions(&stores, "definitions.toml", TOML).await;
+
+        let applied = definitions
+            .apply(&stores.saved, &stores.scheduler)
+            .await
+            .unwrap();
+        assert_eq!(
+            actions(&applied),
+            vec![
+                (
+                    DefinitionKind::Query,
+                    "Orphaned orders",
+                    DefinitionAction::Create
+                ),
+                (
+                    DefinitionKind::Schedule,
+                    "Nightly orphan check",
+                    DefinitionAction::Create
+                ),
+            ]
+        );
+        let (saved, schedules) = current(&stores.saved, &stores.scheduler).await;
+        assert_eq!(schedules[0].saved_query_id, saved[0].id);
+        assert_eq!(schedules[0].managed_by.as_deref(), Some(definitions.path()));
+        assert_eq!(saved[0].managed_by.as_deref(), Some(definitions.path()));
+
+        // Applying again changes nothing
+        let report = definitions
+            .report(&stores.saved, &stores.scheduler)
+            .await
+            .unwrap();
+        assert!(report.in_sync);
+
+        // An edit through the API is drift, and the file wins when applied
+        let schedule = &schedules[0];
+        stores
+            .scheduler
+            .store
+            .update(
+                &schedule.id,
+                ScheduleInput {
+                    name: schedule.name.clone(),
+                    saved_query_id: schedule.saved_query_id.clone(),
+                    cron: "0 4 * * *".to_string(),
+                    params: schedule.params.clone(),
+                    enabled: false,
+                    delivery: schedule.delivery.clone(),
+                    alert: None,
+                },
+            )
+            .await
+            .unwrap();
+        let report = definitions
+            .report(&stores.saved, &stores.scheduler)
+            .await
+            .unwrap();
+        assert!(!report.in_sync);
+        let drift = &report.changes[1];
+        assert_eq!(drift.action, DefinitionAction::Update);
+        assert_eq!(drift.fields, vec!["cron", "enabled", "alert"]);
+
+        definitions
+            .apply(&stores.saved, &stores.scheduler)
+            .await
+            .unwrap();
+        let restored = stores.scheduler.store.get(&schedule.id).await.unwrap();
+        assert_eq!(restored.cron, "0 3 * * *");
+        assert!(restored.enabled);
+        assert!(restored.alert.is_some());
+        assert!(
+            definitions
+                .report(&stores.saved, &stores.scheduler)
+                .await
+                .unwrap()
+                .in_sync
+        );
+    }
+
+    #[tokio::test]
+    async fn test_removed_definitions_are_deleted() {
+        let stores = stores().await;
+        let api_query = stores
+            .saved
+            .create(SavedQueryInput {
+                name: "Slow queries".to_string(),
+                description: None,
+                sql: "SELECT 1".to_string(),
+                folder: None,
+                tags: Vec::new(),
+            })
+            .await
+            .unwrap();
+        let definitions = definitions(&stores, "definitions.yaml", YAML).await;
+        definitions
+            .apply(&stores.saved, &stores.scheduler)
+            .await
+            .unwrap();
+
+        // The schedule moves to a query saved through the API
+        let moved = r#"
+schedules:
+  - name: Nightly orphan check
+    query: Slow queries
+    cron: "@daily"
+"#;
+        tokio::fs::write(definitions.path(), moved).await.unwrap();
+        let report = definitions
+            .apply(&stores.saved, &stores.scheduler)
+            .await
+            .unwrap();
+        assert_eq!(
+            actions(&report),
+            vec![
+                (
+                    DefinitionKind::Schedule,
+                    "Nightly orphan check",
+                    DefinitionAction::Update
+                ),
+                (
+                    DefinitionKind::Query,
+                    "Orphaned orders",
+                    DefinitionAction::Delete
+                ),
+            ]
+        );
+        let (saved, schedules) = current(&stores.saved, &stores.scheduler).await;
+        assert_eq!(saved, vec![api_query.clone()]);
+        assert_eq!(schedules[0].saved_query_id, api_query.id);
+
+        // Emptying the file leaves what the API made
+        tokio::fs::write(definitions.path(), "{}").await.unwrap();
+        definitions
+            .apply(&stores.saved, &stores.scheduler)
+            .await
+            .unwrap();
+        let (saved, schedules) = current(&stores.saved, &stores.scheduler).await;
+        assert_eq!(saved, vec![api_query]);
+        assert!(schedules.is_empty());
+
+        // A missing file manages nothing
+        let missing = Definitions::new(stores.dir.join("missing.toml").to_string_lossy());
+        let report = missing
+            .apply(&stores.saved, &stores.scheduler)
+            .await
+            .unwrap();
+        assert!(!report.found);
+    }
+
+    #[tokio::test]
+    async fn test_export_round_trips() {
+        let stores = stores().await;
+        let definitions = definitions(&stores, "definitions.toml", TOML).await;
+        definitions
+            .apply(&stores.saved, &stores.scheduler)
+            .await
+            .unwrap();
+
+        let (saved, schedules) = current(&stores.saved, &stores.scheduler).await;
+        let exported = DefinitionsFile::export(&saved, &schedules);
+        assert_eq!(exported, DefinitionsFormat::Toml.parse(TOML).unwrap());
+    }
+}
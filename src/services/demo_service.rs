@@ -0,0 +1,470 @@
+use crate::models::{ColumnInfo, QueryResult, Schema, SchemaTreeNode, TableInfo};
+use crate::services::sql_lexer::split_statements;
+/// Demo Catalog
+///
+/// In demo mode (`DEMO_MODE=true`) the server runs without a database: the
+/// schema browser, studio and query console answer from a small set of
+/// sample schemas compiled into the binary from `demo/catalog.json`, and
+/// everything else that needs a connection reports that it is unavailable.
+///
+/// The console understands just enough SQL to show the sample rows:
+/// `SELECT <columns | *> FROM [schema.]table [LIMIT n]`.
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::Instant;
+
+/// Schema used for unqualified table names
+const DEFAULT_SCHEMA: &str = "public";
+
+/// Bytes reported per page of a sample table, like the server's block size
+const PAGE_BYTES: i64 = 8192;
+
+/// Sample rows that fit on one reported page
+const ROWS_PER_PAGE: i64 = 64;
+
+/// Rows as text (`None` for NULL) and the table's total row count
+type Page = (Vec<Vec<Option<String>>>, i64);
+
+#[derive(Debug, Deserialize)]
+struct DemoSchema {
+    name: String,
+    owner: Option<String>,
+    tables: Vec<DemoTable>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DemoTable {
+    name: String,
+    /// `BASE TABLE` or `VIEW`
+    #[serde(rename = "type", default = "base_table")]
+    table_type: String,
+    comment: Option<String>,
+    columns: Vec<DemoColumn>,
+    /// Values in column order; JSON `null` is SQL NULL
+    rows: Vec<Vec<Value>>,
+}
+
+fn base_table() -> String {
+    "BASE TABLE".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+struct DemoColumn {
+    name: String,
+    data_type: String,
+    #[serde(default)]
+    is_nullable: bool,
+    #[serde(default)]
+    is_pk: bool,
+    default: Option<String>,
+    comment: Option<String>,
+}
+
+/// The sample schemas served in demo mode
+#[derive(Debug, Deserialize)]
+pub struct DemoCatalog {
+    schemas: Vec<DemoSchema>,
+}
+
+impl DemoCatalog {
+    /// The catalog compiled into the binary
+    pub fn load() -> Result<Self, String> {
+        Self::parse(include_str!("../../demo/catalog.json"))
+    }
+
+    fn parse(source: &str) -> Result<Self, String> {
+        let catalog: DemoCatalog =
+            serde_json::from_str(source).map_err(|e| format!("Invalid demo catalog: {}", e))?;
+        for schema in &catalog.schemas {
+            for table in &schema.tables {
+                if let Some(row) = table.rows.iter().find(|r| r.len() != table.columns.len()) {
+                    return Err(format!(
+                        "Invalid demo catalog: a row of {}.{} has {} values for {} columns",
+                        schema.name,
+                        table.name,
+                        row.len(),
+                        table.columns.len()
+                    ));
+                }
+            }
+        }
+        Ok(catalog)
+    }
+
+    pub fn schemas(&self) -> Vec<Schema> {
+        self.schemas
+            .iter()
+            .map(|schema| Schema {
+                name: schema.name.clone(),
+                owner: schema.owner.clone(),
+            })
+            .collect()
+    }
+
+    /// Tables then views of a schema, by name; none for an unknown schema
+    pub fn tables(&self, schema: &str) -> Vec<TableInfo> {
+        let Some(demo) = self.schemas.iter().find(|s| s.name == schema) else {
+            return Vec::new();
+        };
+        let mut tables: Vec<TableInfo> = demo
+            .tables
+            .iter()
+            .map(|table| table_info(schema, table))
+            .collect();
+        tables.sort_by(|a, b| (&a.table_type, &a.name).cmp(&(&b.table_type, &b.name)));
+        tables
+    }
+
+    /// Every schema with its tables and views, for the tree view
+    pub fn tree(&self) -> Vec<SchemaTreeNode> {
+        self.schemas
+            .iter()
+            .map(|schema| {
+                let (views, tables): (Vec<_>, Vec<_>) = schema
+                    .tables
+                    .iter()
+                    .partition(|table| table.table_type == "VIEW");
+                let names = |tables: Vec<&DemoTable>| {
+                    let mut names: Vec<String> = tables.iter().map(|t| t.name.clone()).collect();
+                    names.sort();
+                    names
+                };
+                SchemaTreeNode {
+                    name: schema.name.clone(),
+                    tables: names(tables),
+                    views: names(views),
+                    functions: Vec::new(),
+                }
+            })
+            .collect()
+    }
+
+    fn table(&self, schema: &str, table: &str) -> Result<&DemoTable, String> {
+        self.schemas
+            .iter()
+            .find(|s| s.name == schema)
+            .and_then(|s| s.tables.iter().find(|t| t.name == table))
+            .ok_or_else(|| format!("Table {}.{} not found", schema, table))
+    }
+
+    pub fn table_info(&self, schema: &str, table: &str) -> Result<TableInfo, String> {
+        self.table(schema, table).map(|t| table_info(schema, t))
+    }
+
+    pub fn columns(&self, schema: &str, table: &str) -> Result<Vec<ColumnInfo>, String> {
+        let table = self.table(schema, table)?;
+        Ok(table
+            .columns
+            .iter()
+            .map(|column| ColumnInfo {
+                name: column.name.clone(),
+                data_type: column.data_type.clone(),
+                is_nullable: column.is_nullable,
+                is_pk: column.is_pk,
+                default: column.default.clone(),
+                comment: column.comment.clone(),
+            })
+            .collect())
+    }
+
+    pub fn comment(&self, schema: &str, table: &str) -> Result<Option<String>, String> {
+        self.table(schema, table).map(|t| t.comment.clone())
+    }
+
+    /// A page of rows as text, the way the server returns them for browsing,
+    /// with the table's total row count
+    pub fn page(
+        &self,
+        schema: &str,
+        table: &str,
+        page: u32,
+        page_size: u32,
+    ) -> Result<Page, String> {
+        let table = self.table(schema, table)?;
+        let offset = (page.max(1) as usize - 1) * page_size as usize;
+        let rows = table
+            .rows
+            .iter()
+            .skip(offset)
+            .take(page_size as usize)
+            .map(|row| row.iter().map(as_text).collect())
+            .collect();
+        Ok((rows, table.rows.len() as i64))
+    }
+
+    /// Run a console query against the sample rows
+    pub fn execute(&self, query: &str) -> Result<QueryResult, String> {
+        let start = Instant::now();
+        let statements = split_statements(query);
+        let [statement] = statements.as_slice() else {
+            return Err(match statements.len() {
+                0 => "Query cannot be empty".to_string(),
+                _ => "Demo mode runs one statement at a time".to_string(),
+            });
+        };
+        let select = parse_select(statement)?;
+        let schema = select.schema.as_deref().unwrap_or(DEFAULT_SCHEMA);
+        let table = self
+            .table(schema, &select.table)
+            .map_err(|_| format!("relation \"{}\" does not exist", select.relation()))?;
+
+        let indexes = match &select.columns {
+            None => (0..table.columns.len()).collect(),
+            Some(names) => names
+                .iter()
+                .map(|name| {
+                    table
+                        .columns
+                        .iter()
+                        .position(|c| &c.name == name)
+                        .ok_or_else(|| format!("column \"{}\" does not exist", name))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        };
+        let rows: Vec<Vec<Value>> = table
+            .rows
+            .iter()
+            .take(select.limit.unwrap_or(usize::MAX))
+            .map(|row| indexes.iter().map(|&i| row[i].clone()).collect())
+            .collect();
+
+        Ok(QueryResult {
+            columns: indexes
+                .iter()
+                .map(|&i| table.columns[i].name.clone())
+                .collect(),
+            row_count: rows.len(),
+            rows,
+            affected_rows: None,
+            execution_time_ms: Some(start.elapsed().as_millis()),
+        })
+    }
+}
+
+fn table_info(schema: &str, table: &DemoTable) -> TableInfo {
+    let is_view = table.table_type == "VIEW";
+    let rows = table.rows.len() as i64;
+    TableInfo {
+        schema: schema.to_string(),
+        name: table.name.clone(),
+        table_type: table.table_type.clone(),
+        row_count: (!is_view).then_some(rows),
+        size: (!is_view).then_some(PAGE_BYTES * (1 + rows / ROWS_PER_PAGE)),
+    }
+}
+
+/// A value's text form, `None` for NULL
+fn as_text(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+/// The parts of a `SELECT ... FROM ... [LIMIT n]` the demo console runs
+#[derive(Debug, PartialEq)]
+struct Select {
+    /// `None` for `*`
+    columns: Option<Vec<String>>,
+    schema: Option<String>,
+    table: String,
+    limit: Option<usize>,
+}
+
+impl Select {
+    fn relation(&self) -> String {
+        match &self.schema {
+            Some(schema) => format!("{}.{}", schema, self.table),
+            None => self.table.clone(),
+        }
+    }
+}
+
+const UNSUPPORTED: &str =
+    "Demo mode only runs SELECT <columns | *> FROM [schema.]table [LIMIT n] on the sample data";
+
+fn parse_select(statement: &str) -> Result<Select, String> {
+    let lower = statement.to_ascii_lowercase();
+    let after_select = lower
+        .strip_prefix("select")
+        .filter(|rest| rest.starts_with(char::is_whitespace))
+        .ok_or(UNSUPPORTED)?;
+    let select_end = statement.len() - after_select.len();
+    let from = after_select
+        .match_indices("from")
+        .map(|(at, _)| at)
+        .find(|&at| {
+            let before = after_select[..at].chars().next_back();
+            let after = after_select[at + 4..].chars().next();
+            before.is_some_and(char::is_whitespace) && after.is_some_and(char::is_whitespace)
+        })
+        .ok_or(UNSUPPORTED)?
+        + select_end;
+
+    let list = statement[select_end..from].trim();
+    let columns = match list {
+        "*" => None,
+        _ => Some(
+            list.split(',')
+                .map(identifier)
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+    };
+
+    let mut words = statement[from + "from".len()..].split_whitespace();
+    let relation = words.next().ok_or(UNSUPPORTED)?;
+    let (schema, table) = match relation.split_once('.') {
+        Some((schema, table)) => (Some(identifier(schema)?), identifier(table)?),
+        None => (None, identifier(relation)?),
+    };
+    let limit = match (words.next(), words.next(), words.next()) {
+        (None, _, _) => None,
+        (Some(keyword), Some(n), None) if keyword.eq_ignore_ascii_case("limit") => {
+            Some(n.parse().map_err(|_| format!("Invalid LIMIT '{}'", n))?)
+        }
+        _ => return Err(UNSUPPORTED.to_string()),
+    };
+
+    Ok(Select {
+        columns,
+        schema,
+        table,
+        limit,
+    })
+}
+
+/// An identifier as the server would fold it: quoted ones kept as written,
+/// others lowercased
+fn identifier(text: &str) -> Result<String, String> {
+    let text = text.trim();
+    if let Some(quoted) = text
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+    {
+        return Ok(quoted.replace("\"\"", "\""));
+    }
+    if text.is_empty() || !text.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return Err(UNSUPPORTED.to_string());
+    }
+    Ok(text.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalog_loads() {
+        let catalog = DemoCatalog::load().unwrap();
+        let schemas: Vec<_> = catalog.schemas().into_iter().map(|s| s.name).collect();
+        assert_eq!(schemas, ["public", "analytics"]);
+
+        let tables = catalog.tables("public");
+        assert_eq!(tables.last().unwrap().table_type, "VIEW");
+        assert!(tables.iter().any(|t| t.name == "customers"));
+        assert!(catalog.tables("missing").is_empty());
+
+        let view = catalog.table_info("public", "order_totals").unwrap();
+        assert_eq!((view.row_count, view.size), (None, None));
+        let columns = catalog.columns("public", "orders").unwrap();
+        assert!(columns[0].is_pk && !columns[1].is_pk);
+        assert!(catalog.columns("public", "missing").is_err());
+
+        let tree = catalog.tree();
+        assert_eq!(tree[0].views, ["order_totals"]);
+        assert_eq!(
+            tree[0].tables,
+            ["customers", "order_items", "orders", "products"]
+        );
+    }
+
+    #[test]
+    fn test_catalog_rejects_ragged_rows() {
+        let error = DemoCatalog::parse(
+            r#"{"schemas": [{"name": "s", "tables": [{"name": "t",
+                "columns": [{"name": "a", "data_type": "int"}], "rows": [[1, 2]]}]}]}"#,
+        )
+        .unwrap_err();
+        assert!(error.contains("s.t"), "{}", error);
+    }
+
+    #[test]
+    fn test_page() {
+        let catalog = DemoCatalog::load().unwrap();
+        let (rows, total) = catalog.page("public", "customers", 2, 5).unwrap();
+        assert_eq!(total, 12);
+        assert_eq!(rows.len(), 5);
+        assert_eq!(rows[0][0].as_deref(), Some("6"));
+        // NULL stays NULL, numbers and booleans become text
+        let (rows, _) = catalog.page("public", "customers", 4, 2).unwrap();
+        assert_eq!(rows[0][3], None);
+        let (rows, _) = catalog.page("public", "products", 1, 1).unwrap();
+        assert_eq!(rows[0][4].as_deref(), Some("true"));
+        assert!(catalog
+            .page("public", "customers", 9, 5)
+            .unwrap()
+            .0
+            .is_empty());
+    }
+
+    #[test]
+    fn test_parse_select() {
+        assert_eq!(
+            parse_select("select * from customers").unwrap(),
+            Select {
+                columns: None,
+                schema: None,
+                table: "customers".to_string(),
+                limit: None,
+            }
+        );
+        assert_eq!(
+            parse_select("SELECT Name, \"email\"\nFROM public.Customers LIMIT 3").unwrap(),
+            Select {
+                columns: Some(vec!["name".to_string(), "email".to_string()]),
+                schema: Some("public".to_string()),
+                table: "customers".to_string(),
+                limit: Some(3),
+            }
+        );
+        assert!(parse_select("selectx from t").is_err());
+        assert!(parse_select("SELECT count(*) FROM t").is_err());
+        assert!(parse_select("SELECT * FROM t WHERE id = 1").is_err());
+        assert!(parse_select("DELETE FROM t").is_err());
+        assert!(parse_select("SELECT * FROM t LIMIT many")
+            .unwrap_err()
+            .contains("LIMIT"));
+    }
+
+    #[test]
+    fn test_execute() {
+        let catalog = DemoCatalog::load().unwrap();
+        let result = catalog
+            .execute("SELECT id, name FROM customers LIMIT 2;")
+            .unwrap();
+        assert_eq!(result.columns, ["id", "name"]);
+        assert_eq!(result.row_count, 2);
+        assert_eq!(
+            result.rows[1],
+            [Value::from(2), Value::from("Grace Hopper")]
+        );
+
+        let result = catalog
+            .execute("select * from analytics.page_views")
+            .unwrap();
+        assert_eq!(result.columns.len(), 4);
+        assert_eq!(result.row_count, 8);
+
+        assert_eq!(
+            catalog.execute("SELECT * FROM nope").unwrap_err(),
+            "relation \"nope\" does not exist"
+        );
+        assert_eq!(
+            catalog.execute("SELECT age FROM customers").unwrap_err(),
+            "column \"age\" does not exist"
+        );
+        assert!(catalog.execute("SELECT 1; SELECT 2").is_err());
+        assert!(catalog.execute("  ").is_err());
+    }
+}
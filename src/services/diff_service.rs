@@ -0,0 +1,408 @@
+use serde::{Deserialize, Serialize};
+/// Schema Diff Service
+///
+/// Compares two schemas structurally:
+/// - Tables present on only one side
+/// - Column additions, removals and type/nullability/default changes
+/// - Index and constraint additions, removals and definition changes
+///
+/// Schema names are stripped from index and constraint definitions so that
+/// `a.users` and `b.users` compare equal when their structure matches.
+use sqlx::{PgPool, Row};
+use std::collections::BTreeMap;
+
+use crate::services::identifier::quote_ident;
+use crate::services::schema_ops_service::SchemaOpsService;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColumnSnapshot {
+    pub data_type: String,
+    pub is_nullable: bool,
+    pub default: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TableSnapshot {
+    pub columns: BTreeMap<String, ColumnSnapshot>,
+    pub indexes: BTreeMap<String, String>,
+    pub constraints: BTreeMap<String, String>,
+}
+
+/// Structure of every table in a schema
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SchemaSnapshot {
+    pub schema: String,
+    pub tables: BTreeMap<String, TableSnapshot>,
+}
+
+/// A value that differs between the left and right side
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Change<T> {
+    pub name: String,
+    pub left: T,
+    pub right: T,
+}
+
+/// Differences in named items (columns, indexes or constraints)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItemDiff<T> {
+    /// Present only on the right side
+    pub added: Vec<String>,
+    /// Present only on the left side
+    pub removed: Vec<String>,
+    pub changed: Vec<Change<T>>,
+}
+
+impl<T> ItemDiff<T> {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TableDiff {
+    pub name: String,
+    pub columns: ItemDiff<ColumnSnapshot>,
+    pub indexes: ItemDiff<String>,
+    pub constraints: ItemDiff<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SchemaDiff {
+    pub left: String,
+    pub right: String,
+    pub tables_added: Vec<String>,
+    pub tables_removed: Vec<String>,
+    pub tables_changed: Vec<TableDiff>,
+}
+
+impl SchemaDiff {
+    pub fn is_identical(&self) -> bool {
+        self.tables_added.is_empty()
+            && self.tables_removed.is_empty()
+            && self.tables_changed.is_empty()
+    }
+}
+
+pub struct DiffService;
+
+impl DiffService {
+    /// Capture the structure of all tables in a schema
+    pub async fn snapshot(pool: &PgPool, schema: &str) -> Result<SchemaSnapshot, String> {
+        SchemaOpsService::validate_identifier(schema)?;
+
+        let mut snapshot = SchemaSnapshot {
+            schema: schema.to_string(),
+            tables: BTreeMap::new(),
+        };
+
+        let columns = sqlx::query(
+            r#"
+            SELECT
+                c.relname::text as table_name,
+                a.attname::text as column_name,
+                pg_catalog.format_type(a.atttypid, a.atttypmod) as data_type,
+                NOT a.attnotnull as is_nullable,
+                pg_catalog.pg_get_expr(d.adbin, d.adrelid) as default_expr
+            FROM pg_catalog.pg_class c
+            JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+            JOIN pg_catalog.pg_attribute a
+              ON a.attrelid = c.oid AND a.attnum > 0 AND NOT a.attisdropped
+            LEFT JOIN pg_catalog.pg_attrdef d ON d.adrelid = a.attrelid AND d.adnum = a.attnum
+            WHERE n.nspname = $1 AND c.relkind IN ('r', 'p')
+            ORDER BY c.relname, a.attnum
+            "#,
+        )
+        .bind(schema)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to get columns: {}", e))?;
+
+        for row in &columns {
+            let table: String = row.get("table_name");
+            snapshot.tables.entry(table).or_default().columns.insert(
+                row.get("column_name"),
+                ColumnSnapshot {
+                    data_type: row.get("data_type"),
+                    is_nullable: row.get("is_nullable"),
+                    default: row
+                        .get::<Option<String>, _>("default_expr")
+                        .map(|d| strip_schema(&d, schema)),
+                },
+            );
+        }
+
+        let indexes = sqlx::query(
+            r#"
+            SELECT
+                t.relname::text as table_name,
+                i.relname::text as index_name,
+                pg_catalog.pg_get_indexdef(i.oid) as definition
+            FROM pg_catalog.pg_index x
+            JOIN pg_catalog.pg_class t ON t.oid = x.indrelid
+            JOIN pg_catalog.pg_class i ON i.oid = x.indexrelid
+            JOIN pg_catalog.pg_namespace n ON n.oid = t.relnamespace
+            WHERE n.nspname = $1 AND t.relkind IN ('r', 'p')
+            "#,
+        )
+        .bind(schema)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to get indexes: {}", e))?;
+
+        for row in &indexes {
+            let table: String = row.get("table_name");
+            let definition: String = row.get("definition");
+            snapshot
+                .tables
+                .entry(table)
+                .or_default()
+                .indexes
+                .insert(row.get("index_name"), strip_schema(&definition, schema));
+        }
+
+        let constraints = sqlx::query(
+            r#"
+            SELECT
+                c.relname::text as table_name,
+                con.conname::text as constraint_name,
+                pg_catalog.pg_get_constraintdef(con.oid, true) as definition
+            FROM pg_catalog.pg_constraint con
+            JOIN pg_catalog.pg_class c ON c.oid = con.conrelid
+            JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+            WHERE n.nspname = $1 AND c.relkind IN ('r', 'p')
+            "#,
+        )
+        .bind(schema)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to get constraints: {}", e))?;
+
+        for row in &constraints {
+            let table: String = row.get("table_name");
+            let definition: String = row.get("definition");
+            snapshot
+                .tables
+                .entry(table)
+                .or_default()
+                .constraints
+                .insert(
+                    row.get("constraint_name"),
+                    strip_schema(&definition, schema),
+                );
+        }
+
+        Ok(snapshot)
+    }
+
+    /// Compare two schemas on the same connection
+    pub async fn diff_schemas(
+        pool: &PgPool,
+        left: &str,
+        right: &str,
+    ) -> Result<SchemaDiff, String> {
+        let left = Self::snapshot(pool, left).await?;
+        let right = Self::snapshot(pool, right).await?;
+        Ok(Self::diff(&left, &right))
+    }
+
+    /// Compute the structural differences between two snapshots
+    pub fn diff(left: &SchemaSnapshot, right: &SchemaSnapshot) -> SchemaDiff {
+        let mut diff = SchemaDiff {
+            left: left.schema.clone(),
+            right: right.schema.clone(),
+            tables_added: right
+                .tables
+                .keys()
+                .filter(|name| !left.tables.contains_key(*name))
+                .cloned()
+                .collect(),
+            tables_removed: left
+                .tables
+                .keys()
+                .filter(|name| !right.tables.contains_key(*name))
+                .cloned()
+                .collect(),
+            tables_changed: Vec::new(),
+        };
+
+        for (name, left_table) in &left.tables {
+            let Some(right_table) = right.tables.get(name) else {
+                continue;
+            };
+            let table_diff = TableDiff {
+                name: name.clone(),
+                columns: diff_items(&left_table.columns, &right_table.columns),
+                indexes: diff_items(&left_table.indexes, &right_table.indexes),
+                constraints: diff_items(&left_table.constraints, &right_table.constraints),
+            };
+            if !(table_diff.columns.is_empty()
+                && table_diff.indexes.is_empty()
+                && table_diff.constraints.is_empty())
+            {
+                diff.tables_changed.push(table_diff);
+            }
+        }
+
+        diff
+    }
+}
+
+fn diff_items<T: Clone + PartialEq>(
+    left: &BTreeMap<String, T>,
+    right: &BTreeMap<String, T>,
+) -> ItemDiff<T> {
+    ItemDiff {
+        added: right
+            .keys()
+            .filter(|k| !left.contains_key(*k))
+            .cloned()
+            .collect(),
+        removed: left
+            .keys()
+            .filter(|k| !right.contains_key(*k))
+            .cloned()
+            .collect(),
+        changed: left
+            .iter()
+            .filter_map(|(name, l)| {
+                right.get(name).filter(|r| *r != l).map(|r| Change {
+                    name: name.clone(),
+                    left: l.clone(),
+                    right: r.clone(),
+                })
+            })
+            .collect(),
+    }
+}
+
+/// Remove `schema.` qualifiers so definitions from different schemas compare equal
+fn strip_schema(definition: &str, schema: &str) -> String {
+    definition.replace(&format!("{}.", quote_ident(schema)), "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(data_type: &str, is_nullable: bool) -> ColumnSnapshot {
+        ColumnSnapshot {
+            data_type: data_type.to_string(),
+            is_nullable,
+            default: None,
+        }
+    }
+
+    fn users_table() -> TableSnapshot {
+        let mut table = TableSnapshot::default();
+        table
+            .columns
+            .insert("id".to_string(), column("integer", false));
+        table
+            .columns
+            .insert("email".to_string(), column("text", true));
+        table
+            .constraints
+            .insert("users_pkey".to_string(), "PRIMARY KEY (id)".to_string());
+        table.indexes.insert(
+            "users_pkey".to_string(),
+            "CREATE UNIQUE INDEX users_pkey ON users USING btree (id)".to_string(),
+        );
+        table
+    }
+
+    fn snapshot(schema: &str, tables: Vec<(&str, TableSnapshot)>) -> SchemaSnapshot {
+        SchemaSnapshot {
+            schema: schema.to_string(),
+            tables: tables
+                .into_iter()
+                .map(|(name, t)| (name.to_string(), t))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_identical_schemas() {
+        let left = snapshot("staging", vec![("users", users_table())]);
+        let right = snapshot("production", vec![("users", users_table())]);
+
+        let diff = DiffService::diff(&left, &right);
+        assert!(diff.is_identical());
+        assert_eq!(diff.left, "staging");
+        assert_eq!(diff.right, "production");
+    }
+
+    #[test]
+    fn test_table_added_and_removed() {
+        let left = snapshot(
+            "a",
+            vec![
+                ("users", users_table()),
+                ("legacy", TableSnapshot::default()),
+            ],
+        );
+        let right = snapshot(
+            "b",
+            vec![
+                ("users", users_table()),
+                ("orders", TableSnapshot::default()),
+            ],
+        );
+
+        let diff = DiffService::diff(&left, &right);
+        assert_eq!(diff.tables_added, vec!["orders".to_string()]);
+        assert_eq!(diff.tables_removed, vec!["legacy".to_string()]);
+        assert!(diff.tables_changed.is_empty());
+    }
+
+    #[test]
+    fn test_column_index_and_constraint_changes() {
+        let mut changed = users_table();
+        changed
+            .columns
+            .insert("email".to_string(), column("character varying(255)", false));
+        changed.columns.remove("id");
+        changed
+            .columns
+            .insert("created_at".to_string(), column("timestamp", true));
+        changed.indexes.insert(
+            "users_email_idx".to_string(),
+            "CREATE INDEX users_email_idx ON users USING btree (email)".to_string(),
+        );
+        changed
+            .constraints
+            .insert("users_pkey".to_string(), "PRIMARY KEY (email)".to_string());
+
+        let left = snapshot("a", vec![("users", users_table())]);
+        let right = snapshot("b", vec![("users", changed)]);
+
+        let diff = DiffService::diff(&left, &right);
+        assert_eq!(diff.tables_changed.len(), 1);
+        let table = &diff.tables_changed[0];
+        assert_eq!(table.columns.added, vec!["created_at".to_string()]);
+        assert_eq!(table.columns.removed, vec!["id".to_string()]);
+        assert_eq!(table.columns.changed.len(), 1);
+        assert_eq!(table.columns.changed[0].name, "email");
+        assert_eq!(
+            table.columns.changed[0].right.data_type,
+            "character varying(255)"
+        );
+        assert_eq!(table.indexes.added, vec!["users_email_idx".to_string()]);
+        assert_eq!(table.constraints.changed[0].right, "PRIMARY KEY (email)");
+    }
+
+    #[test]
+    fn test_strip_schema() {
+        assert_eq!(
+            strip_schema(
+                "CREATE INDEX i ON staging.users USING btree (email)",
+                "staging"
+            ),
+            "CREATE INDEX i ON users USING btree (email)"
+        );
+        assert_eq!(
+            strip_schema("FOREIGN KEY (uid) REFERENCES \"Sales\".users(id)", "Sales"),
+            "FOREIGN KEY (uid) REFERENCES users(id)"
+        );
+    }
+}
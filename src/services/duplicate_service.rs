@@ -0,0 +1,250 @@
+use crate::services::identifier::{self, quote_ident};
+use crate::services::query_service;
+use serde::Serialize;
+/// Duplicate Row Service
+///
+/// Finds groups of rows sharing the same values in a set of columns, the
+/// usual chore before adding a unique constraint:
+/// - Groups are counted in the database; only the largest groups are
+///   returned, each with a few of its rows
+/// - Rows with a NULL in any of the columns are skipped by default, since a
+///   unique constraint does not consider them equal (unless it is declared
+///   `NULLS NOT DISTINCT`)
+///
+/// The whole table is scanned once to group it, and once more to pick the
+/// sample rows of the returned groups.
+use serde_json::Value;
+use sqlx::{PgPool, Row};
+
+/// Groups returned when no limit is given
+pub const DEFAULT_GROUP_LIMIT: u32 = 50;
+
+/// Upper bound on returned groups
+pub const MAX_GROUP_LIMIT: u32 = 1000;
+
+/// Sample rows per group when none is given
+pub const DEFAULT_SAMPLE_ROWS: u32 = 3;
+
+/// Upper bound on sample rows per group
+pub const MAX_SAMPLE_ROWS: u32 = 20;
+
+/// Duplicate groups of a table over a set of columns
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateReport {
+    pub schema: String,
+    pub table: String,
+    pub columns: Vec<String>,
+    pub include_nulls: bool,
+    /// Key values that occur more than once
+    pub total_groups: i64,
+    /// Rows in those groups
+    pub duplicate_rows: i64,
+    /// Rows to remove, keeping one per group, before the columns are unique
+    pub excess_rows: i64,
+    /// The largest groups, largest first
+    pub groups: Vec<DuplicateGroup>,
+}
+
+/// One set of key values with the rows sharing it
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateGroup {
+    /// Values of the columns, in the requested order
+    pub key: Value,
+    pub count: i64,
+    pub sample_rows: Vec<Value>,
+}
+
+/// Options for a duplicate search
+#[derive(Debug, Clone, Copy)]
+pub struct DuplicateOptions {
+    /// Treat NULLs as equal to each other
+    pub include_nulls: bool,
+    pub group_limit: u32,
+    pub sample_rows: u32,
+}
+
+/// Column names of `schema.table`, which may also be a view or materialized view
+pub async fn column_names(
+    pool: &PgPool,
+    schema: &str,
+    table: &str,
+) -> Result<Vec<String>, sqlx::Error> {
+    let relation = identifier::resolve_relation(pool, schema, table).await?;
+    sqlx::query_scalar(
+        "SELECT attname::text FROM pg_catalog.pg_attribute \
+         WHERE attrelid = $1::regclass AND attnum > 0 AND NOT attisdropped ORDER BY attnum",
+    )
+    .bind(relation)
+    .fetch_all(pool)
+    .await
+}
+
+/// Check the requested columns against the table's, dropping repeats
+pub fn validate_columns(requested: &[String], existing: &[String]) -> Result<Vec<String>, String> {
+    let mut columns: Vec<String> = Vec::new();
+    for name in requested.iter().map(|c| c.trim()).filter(|c| !c.is_empty()) {
+        if !existing.iter().any(|c| c == name) {
+            return Err(format!("Column '{}' does not exist", name));
+        }
+        if !columns.iter().any(|c| c == name) {
+            columns.push(name.to_string());
+        }
+    }
+    if columns.is_empty() {
+        return Err("At least one column is required".to_string());
+    }
+    Ok(columns)
+}
+
+/// Find the groups of rows of `schema.table` with equal `columns`, which
+/// must have been checked with `validate_columns`
+pub async fn find_duplicates(
+    pool: &PgPool,
+    schema: &str,
+    table: &str,
+    columns: Vec<String>,
+    options: DuplicateOptions,
+) -> Result<DuplicateReport, sqlx::Error> {
+    let relation = identifier::resolve_relation(pool, schema, table).await?;
+    let rows = sqlx::query(&duplicates_query(&relation, &columns, &options))
+        .fetch_all(pool)
+        .await?;
+
+    let (total_groups, duplicate_rows) = match rows.first() {
+        Some(row) => (row.try_get("total_groups")?, row.try_get("total_rows")?),
+        None => (0, 0),
+    };
+    let groups = rows
+        .iter()
+        .map(|row| {
+            Ok(DuplicateGroup {
+                key: row.try_get("key")?,
+                count: row.try_get("dup_count")?,
+                sample_rows: row.try_get::<sqlx::types::Json<Vec<Value>>, _>("rows")?.0,
+            })
+        })
+        .collect::<Result<Vec<_>, sqlx::Error>>()?;
+    // The sample rows are the table rows this hands out
+    let sampled = groups.iter().map(|g| g.sample_rows.len()).sum();
+    query_service::check_row_limit(sampled).map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+
+    Ok(DuplicateReport {
+        schema: schema.to_string(),
+        table: table.to_string(),
+        columns,
+        include_nulls: options.include_nulls,
+        total_groups,
+        duplicate_rows,
+        excess_rows: duplicate_rows - total_groups,
+        groups,
+    })
+}
+
+/// Group by the key columns (aliased `k1`..`kN` so they cannot clash with the
+/// counters), keep the largest groups and join their sample rows back
+fn duplicates_query(relation: &str, columns: &[String], options: &DuplicateOptions) -> String {
+    let quoted: Vec<String> = columns.iter().map(|c| quote_ident(c)).collect();
+    let keys: Vec<String> = (1..=columns.len()).map(|i| format!("k{}", i)).collect();
+
+    let key_select = quoted
+        .iter()
+        .zip(&keys)
+        .map(|(column, key)| format!("{} AS {}", column, key))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let not_null = if options.include_nulls {
+        String::new()
+    } else {
+        format!(
+            " WHERE {}",
+            quoted
+                .iter()
+                .map(|c| format!("{} IS NOT NULL", c))
+                .collect::<Vec<_>>()
+                .join(" AND ")
+        )
+    };
+    // Without NULLs plain equality works, and lets the join hash
+    let matches = if options.include_nulls {
+        "IS NOT DISTINCT FROM"
+    } else {
+        "="
+    };
+    let join = quoted
+        .iter()
+        .zip(&keys)
+        .map(|(column, key)| format!("t.{} {} top.{}", column, matches, key))
+        .collect::<Vec<_>>()
+        .join(" AND ");
+    let keys = keys.join(", ");
+
+    format!(
+        "WITH groups AS (\
+            SELECT {key_select}, count(*) AS dup_count FROM {relation}{not_null} \
+            GROUP BY {keys} HAVING count(*) > 1\
+         ), top AS (\
+            SELECT *, count(*) OVER () AS total_groups, (sum(dup_count) OVER ())::int8 AS total_rows \
+            FROM groups ORDER BY dup_count DESC LIMIT {limit}\
+         ), matched AS (\
+            SELECT top.*, to_jsonb(t) AS sample_row, row_number() OVER (PARTITION BY {keys}) AS rn \
+            FROM top JOIN {relation} t ON {join}\
+         ) \
+         SELECT jsonb_build_array({keys}) AS key, dup_count, total_groups, total_rows, \
+            jsonb_agg(sample_row) AS rows \
+         FROM matched WHERE rn <= {samples} \
+         GROUP BY {keys}, dup_count, total_groups, total_rows \
+         ORDER BY dup_count DESC, key",
+        limit = options.group_limit,
+        samples = options.sample_rows,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_validate_columns() {
+        let existing = names(&["id", "email", "Tenant"]);
+        assert_eq!(
+            validate_columns(&names(&["email", " Tenant ", "email"]), &existing).unwrap(),
+            names(&["email", "Tenant"])
+        );
+        assert!(validate_columns(&names(&["tenant"]), &existing)
+            .unwrap_err()
+            .contains("'tenant' does not exist"));
+        assert!(validate_columns(&names(&["", " "]), &existing).is_err());
+    }
+
+    #[test]
+    fn test_duplicates_query() {
+        let options = DuplicateOptions {
+            include_nulls: false,
+            group_limit: 50,
+            sample_rows: 3,
+        };
+        let query = duplicates_query("public.users", &names(&["email", "Tenant"]), &options);
+        assert!(query.contains(
+            "SELECT email AS k1, \"Tenant\" AS k2, count(*) AS dup_count FROM public.users \
+             WHERE email IS NOT NULL AND \"Tenant\" IS NOT NULL GROUP BY k1, k2 HAVING count(*) > 1"
+        ));
+        assert!(query.contains("ON t.email = top.k1 AND t.\"Tenant\" = top.k2"));
+        assert!(query.contains("LIMIT 50"));
+        assert!(query.contains("WHERE rn <= 3"));
+
+        let query = duplicates_query(
+            "public.users",
+            &names(&["email"]),
+            &DuplicateOptions {
+                include_nulls: true,
+                ..options
+            },
+        );
+        assert!(!query.contains("IS NOT NULL"));
+        assert!(query.contains("ON t.email IS NOT DISTINCT FROM top.k1"));
+    }
+}
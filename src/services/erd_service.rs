@@ -0,0 +1,409 @@
+use serde::{Deserialize, Serialize};
+/// ER Diagram Service
+///
+/// Builds an entity-relationship model of a schema from the catalogs:
+/// - Tables with their columns, primary keys and foreign key columns
+/// - Foreign key relationships (including references into other schemas)
+///
+/// The model can be rendered as Mermaid (`erDiagram`) or Graphviz DOT.
+use sqlx::{PgPool, Row};
+
+use crate::services::schema_ops_service::SchemaOpsService;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErdColumn {
+    pub name: String,
+    pub data_type: String,
+    pub is_nullable: bool,
+    pub is_pk: bool,
+    pub is_fk: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErdTable {
+    pub name: String,
+    pub columns: Vec<ErdColumn>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErdRelationship {
+    pub name: String,
+    pub from_table: String,
+    pub from_columns: Vec<String>,
+    pub to_schema: String,
+    pub to_table: String,
+    pub to_columns: Vec<String>,
+    /// True when every referencing column is NOT NULL (mandatory parent)
+    pub required: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErdModel {
+    pub schema: String,
+    pub tables: Vec<ErdTable>,
+    pub relationships: Vec<ErdRelationship>,
+}
+
+/// Output formats for a rendered diagram
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErdFormat {
+    Mermaid,
+    Dot,
+    Json,
+}
+
+impl ErdFormat {
+    pub fn parse(value: Option<&str>) -> Result<Self, String> {
+        match value.map(|v| v.to_lowercase()).as_deref() {
+            None | Some("mermaid") => Ok(ErdFormat::Mermaid),
+            Some("dot") | Some("graphviz") => Ok(ErdFormat::Dot),
+            Some("json") => Ok(ErdFormat::Json),
+            Some(other) => Err(format!("Unsupported ERD format: {}", other)),
+        }
+    }
+}
+
+pub struct ErdService;
+
+impl ErdService {
+    /// Build the ER model for all tables in a schema
+    pub async fn build(pool: &PgPool, schema: &str) -> Result<ErdModel, String> {
+        SchemaOpsService::validate_identifier(schema)?;
+
+        let column_rows = sqlx::query(
+            r#"
+            SELECT
+                c.relname::text as table_name,
+                a.attname::text as column_name,
+                pg_catalog.format_type(a.atttypid, a.atttypmod) as data_type,
+                NOT a.attnotnull as is_nullable,
+                EXISTS (
+                    SELECT 1 FROM pg_catalog.pg_index i
+                    WHERE i.indrelid = c.oid AND i.indisprimary AND a.attnum = ANY(i.indkey)
+                ) as is_pk
+            FROM pg_catalog.pg_class c
+            JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+            JOIN pg_catalog.pg_attribute a
+              ON a.attrelid = c.oid AND a.attnum > 0 AND NOT a.attisdropped
+            WHERE n.nspname = $1 AND c.relkind IN ('r', 'p')
+            ORDER BY c.relname, a.attnum
+            "#,
+        )
+        .bind(schema)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to get columns: {}", e))?;
+
+        let fk_rows = sqlx::query(
+            r#"
+            SELECT
+                con.conname::text as name,
+                cl.relname::text as from_table,
+                fn.nspname::text as to_schema,
+                fcl.relname::text as to_table,
+                ARRAY(
+                    SELECT a.attname::text
+                    FROM unnest(con.conkey) WITH ORDINALITY k(attnum, ord)
+                    JOIN pg_catalog.pg_attribute a
+                      ON a.attrelid = con.conrelid AND a.attnum = k.attnum
+                    ORDER BY k.ord
+                ) as from_columns,
+                ARRAY(
+                    SELECT a.attname::text
+                    FROM unnest(con.confkey) WITH ORDINALITY k(attnum, ord)
+                    JOIN pg_catalog.pg_attribute a
+                      ON a.attrelid = con.confrelid AND a.attnum = k.attnum
+                    ORDER BY k.ord
+                ) as to_columns,
+                NOT EXISTS (
+                    SELECT 1 FROM pg_catalog.pg_attribute a
+                    WHERE a.attrelid = con.conrelid
+                      AND a.attnum = ANY(con.conkey)
+                      AND NOT a.attnotnull
+                ) as required
+            FROM pg_catalog.pg_constraint con
+            JOIN pg_catalog.pg_class cl ON cl.oid = con.conrelid
+            JOIN pg_catalog.pg_namespace n ON n.oid = cl.relnamespace
+            JOIN pg_catalog.pg_class fcl ON fcl.oid = con.confrelid
+            JOIN pg_catalog.pg_namespace fn ON fn.oid = fcl.relnamespace
+            WHERE con.contype = 'f' AND n.nspname = $1
+            ORDER BY cl.relname, con.conname
+            "#,
+        )
+        .bind(schema)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to get foreign keys: {}", e))?;
+
+        let relationships: Vec<ErdRelationship> = fk_rows
+            .iter()
+            .map(|row| ErdRelationship {
+                name: row.get("name"),
+                from_table: row.get("from_table"),
+                from_columns: row.get("from_columns"),
+                to_schema: row.get("to_schema"),
+                to_table: row.get("to_table"),
+                to_columns: row.get("to_columns"),
+                required: row.get("required"),
+            })
+            .collect();
+
+        let mut tables: Vec<ErdTable> = Vec::new();
+        for row in &column_rows {
+            let table_name: String = row.get("table_name");
+            let column_name: String = row.get("column_name");
+            let is_fk = relationships
+                .iter()
+                .any(|r| r.from_table == table_name && r.from_columns.contains(&column_name));
+            let column = ErdColumn {
+                name: column_name,
+                data_type: row.get("data_type"),
+                is_nullable: row.get("is_nullable"),
+                is_pk: row.get("is_pk"),
+                is_fk,
+            };
+
+            match tables.last_mut() {
+                Some(table) if table.name == table_name => table.columns.push(column),
+                _ => tables.push(ErdTable {
+                    name: table_name,
+                    columns: vec![column],
+                }),
+            }
+        }
+
+        Ok(ErdModel {
+            schema: schema.to_string(),
+            tables,
+            relationships,
+        })
+    }
+
+    /// Render the model as a Mermaid `erDiagram`
+    pub fn to_mermaid(model: &ErdModel) -> String {
+        let mut out = String::from("erDiagram\n");
+
+        for table in &model.tables {
+            out.push_str(&format!("    {} {{\n", mermaid_id(&table.name)));
+            for column in &table.columns {
+                let mut keys = Vec::new();
+                if column.is_pk {
+                    keys.push("PK");
+                }
+                if column.is_fk {
+                    keys.push("FK");
+                }
+                out.push_str(&format!(
+                    "        {} {}",
+                    mermaid_id(&column.data_type),
+                    mermaid_id(&column.name)
+                ));
+                if !keys.is_empty() {
+                    out.push(' ');
+                    out.push_str(&keys.join(","));
+                }
+                out.push('\n');
+            }
+            out.push_str("    }\n");
+        }
+
+        for rel in &model.relationships {
+            let parent = if rel.required { "||" } else { "|o" };
+            out.push_str(&format!(
+                "    {} {}--o{{ {} : \"{}\"\n",
+                mermaid_id(&Self::target_name(model, rel)),
+                parent,
+                mermaid_id(&rel.from_table),
+                rel.from_columns.join(", ").replace('"', "'")
+            ));
+        }
+
+        out
+    }
+
+    /// Render the model as a Graphviz DOT digraph
+    pub fn to_dot(model: &ErdModel) -> String {
+        let mut out = format!(
+            "digraph \"{}\" {{\n    graph [rankdir=LR];\n    node [shape=plaintext];\n",
+            dot_escape(&model.schema)
+        );
+
+        for table in &model.tables {
+            let mut label = format!(
+                "<table border=\"0\" cellborder=\"1\" cellspacing=\"0\"><tr><td bgcolor=\"lightgrey\"><b>{}</b></td></tr>",
+                html_escape(&table.name)
+            );
+            for column in &table.columns {
+                let marker = match (column.is_pk, column.is_fk) {
+                    (true, true) => " (PK, FK)",
+                    (true, false) => " (PK)",
+                    (false, true) => " (FK)",
+                    (false, false) => "",
+                };
+                label.push_str(&format!(
+                    "<tr><td port=\"{}\" align=\"left\">{} : {}{}</td></tr>",
+                    html_escape(&column.name),
+                    html_escape(&column.name),
+                    html_escape(&column.data_type),
+                    marker
+                ));
+            }
+            label.push_str("</table>");
+            out.push_str(&format!(
+                "    \"{}\" [label=<{}>];\n",
+                dot_escape(&table.name),
+                label
+            ));
+        }
+
+        for rel in &model.relationships {
+            out.push_str(&format!(
+                "    \"{}\" -> \"{}\" [label=\"{}\"{}];\n",
+                dot_escape(&rel.from_table),
+                dot_escape(&Self::target_name(model, rel)),
+                dot_escape(&rel.name),
+                if rel.required { "" } else { ", style=dashed" }
+            ));
+        }
+
+        out.push_str("}\n");
+        out
+    }
+
+    /// Referenced tables outside the diagrammed schema are qualified
+    fn target_name(model: &ErdModel, rel: &ErdRelationship) -> String {
+        if rel.to_schema == model.schema {
+            rel.to_table.clone()
+        } else {
+            format!("{}.{}", rel.to_schema, rel.to_table)
+        }
+    }
+}
+
+/// Mermaid entity/attribute names may only contain word characters and dashes
+fn mermaid_id(name: &str) -> String {
+    let id: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if id.is_empty() {
+        "_".to_string()
+    } else {
+        id
+    }
+}
+
+fn dot_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(name: &str, data_type: &str, is_pk: bool, is_fk: bool) -> ErdColumn {
+        ErdColumn {
+            name: name.to_string(),
+            data_type: data_type.to_string(),
+            is_nullable: !is_pk,
+            is_pk,
+            is_fk,
+        }
+    }
+
+    fn sample_model() -> ErdModel {
+        ErdModel {
+            schema: "public".to_string(),
+            tables: vec![
+                ErdTable {
+                    name: "customers".to_string(),
+                    columns: vec![
+                        column("id", "integer", true, false),
+                        column("full name", "character varying(100)", false, false),
+                    ],
+                },
+                ErdTable {
+                    name: "orders".to_string(),
+                    columns: vec![
+                        column("id", "integer", true, false),
+                        column("customer_id", "integer", false, true),
+                    ],
+                },
+            ],
+            relationships: vec![
+                ErdRelationship {
+                    name: "orders_customer_id_fkey".to_string(),
+                    from_table: "orders".to_string(),
+                    from_columns: vec!["customer_id".to_string()],
+                    to_schema: "public".to_string(),
+                    to_table: "customers".to_string(),
+                    to_columns: vec!["id".to_string()],
+                    required: true,
+                },
+                ErdRelationship {
+                    name: "orders_region_fkey".to_string(),
+                    from_table: "orders".to_string(),
+                    from_columns: vec!["region_id".to_string()],
+                    to_schema: "geo".to_string(),
+                    to_table: "regions".to_string(),
+                    to_columns: vec!["id".to_string()],
+                    required: false,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_erd_format_parse() {
+        assert_eq!(ErdFormat::parse(None).unwrap(), ErdFormat::Mermaid);
+        assert_eq!(ErdFormat::parse(Some("DOT")).unwrap(), ErdFormat::Dot);
+        assert_eq!(ErdFormat::parse(Some("json")).unwrap(), ErdFormat::Json);
+        assert!(ErdFormat::parse(Some("svg")).is_err());
+    }
+
+    #[test]
+    fn test_to_mermaid() {
+        let mermaid = ErdService::to_mermaid(&sample_model());
+
+        assert!(mermaid.starts_with("erDiagram\n"));
+        assert!(mermaid.contains("    customers {\n        integer id PK\n"));
+        assert!(mermaid.contains("        character_varying_100_ full_name\n"));
+        assert!(mermaid.contains("        integer customer_id FK\n"));
+        assert!(mermaid.contains("    customers ||--o{ orders : \"customer_id\"\n"));
+        assert!(mermaid.contains("    geo_regions |o--o{ orders : \"region_id\"\n"));
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let dot = ErdService::to_dot(&sample_model());
+
+        assert!(dot.starts_with("digraph \"public\" {\n"));
+        assert!(dot.contains("\"orders\" -> \"customers\" [label=\"orders_customer_id_fkey\"];"));
+        assert!(dot.contains(
+            "\"orders\" -> \"geo.regions\" [label=\"orders_region_fkey\", style=dashed];"
+        ));
+        assert!(dot.contains("customer_id : integer (FK)"));
+        assert!(dot.trim_end().ends_with('}'));
+    }
+
+    #[test]
+    fn test_escaping() {
+        assert_eq!(mermaid_id("a b\"c"), "a_b_c");
+        assert_eq!(dot_escape("x\"y"), "x\\\"y");
+        assert_eq!(html_escape("<b>&"), "&lt;b&gt;&amp;");
+    }
+}
@@ -4,23 +4,50 @@
 /// - CSV (comma-separated values)
 /// - JSON (JavaScript Object Notation)
 /// - SQL (INSERT statements)
+///
+/// Plugins can add formats of their own by implementing `Exporter`.
 use crate::models::QueryResult;
-use serde_json::Value;
+use crate::services::query_history::HistoryEntry;
+use serde_json::{json, Value};
+
+/// An export format contributed by a plugin
+pub trait Exporter: Send + Sync {
+    /// Name used in `?format=`, lowercase; also the file extension
+    fn name(&self) -> &'static str;
+
+    fn content_type(&self) -> &'static str;
+
+    fn export(&self, result: &QueryResult) -> Result<String, String>;
+}
+
+impl std::fmt::Debug for dyn Exporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Exporter({})", self.name())
+    }
+}
+
+impl PartialEq for dyn Exporter {
+    fn eq(&self, other: &Self) -> bool {
+        self.name() == other.name()
+    }
+}
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ExportFormat {
     Csv,
     Json,
     Sql,
+    Plugin(&'static dyn Exporter),
 }
 
 impl ExportFormat {
     pub fn from_str(s: &str) -> Option<Self> {
-        match s.to_lowercase().as_str() {
+        let name = s.to_lowercase();
+        match name.as_str() {
             "csv" => Some(ExportFormat::Csv),
             "json" => Some(ExportFormat::Json),
             "sql" => Some(ExportFormat::Sql),
-            _ => None,
+            _ => crate::plugins::export_format(&name).map(ExportFormat::Plugin),
         }
     }
 
@@ -29,6 +56,7 @@ impl ExportFormat {
             ExportFormat::Csv => "csv",
             ExportFormat::Json => "json",
             ExportFormat::Sql => "sql",
+            ExportFormat::Plugin(exporter) => exporter.name(),
         }
     }
 
@@ -37,6 +65,7 @@ impl ExportFormat {
             ExportFormat::Csv => "text/csv; charset=utf-8",
             ExportFormat::Json => "application/json; charset=utf-8",
             ExportFormat::Sql => "text/plain; charset=utf-8",
+            ExportFormat::Plugin(exporter) => exporter.content_type(),
         }
     }
 }
@@ -50,6 +79,48 @@ impl ExportService {
             ExportFormat::Csv => Self::export_csv(result),
             ExportFormat::Json => Self::export_json(result),
             ExportFormat::Sql => Self::export_sql(result),
+            ExportFormat::Plugin(exporter) => exporter.export(result),
+        }
+    }
+
+    /// Tabulate query history entries so they can be exported like query results
+    pub fn history_result(entries: &[HistoryEntry]) -> QueryResult {
+        let columns = [
+            "id",
+            "executed_at",
+            "query",
+            "duration_ms",
+            "row_count",
+            "success",
+            "error",
+            "tags",
+        ]
+        .iter()
+        .map(|c| c.to_string())
+        .collect();
+
+        let rows = entries
+            .iter()
+            .map(|e| {
+                vec![
+                    json!(e.id),
+                    json!(e.executed_at.to_rfc3339()),
+                    json!(e.query),
+                    json!(e.duration_ms),
+                    json!(e.row_count),
+                    json!(e.success),
+                    json!(e.error),
+                    json!(e.tags.join(",")),
+                ]
+            })
+            .collect();
+
+        QueryResult {
+            columns,
+            rows,
+            row_count: entries.len(),
+            affected_rows: None,
+            execution_time_ms: None,
         }
     }
 
@@ -85,7 +156,7 @@ impl ExportService {
             data.push(Value::Object(obj));
         }
 
-        serde_json::to_string_pretty(&serde_json::json!({
+        serde_json::to_string_pretty(&json!({
             "columns": result.columns,
             "row_count": result.row_count,
             "execution_time_ms": result.execution_time_ms,
@@ -163,7 +234,6 @@ impl ExportService {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use serde_json::json;
 
     #[test]
     fn test_export_format_from_str() {
@@ -297,4 +367,27 @@ mod tests {
         let sql = ExportService::export(&result, ExportFormat::Sql).unwrap();
         assert!(sql.contains("No data to insert"));
     }
+
+    #[test]
+    fn test_history_export() {
+        let mut entry = HistoryEntry::new("SELECT 1, 2".to_string(), 12, Some(1));
+        entry.tags = vec!["perf".to_string(), "JIRA-7".to_string()];
+        let failed = HistoryEntry::failed("SELEC".to_string(), 3, "syntax error".to_string());
+        let result = ExportService::history_result(&[entry.clone(), failed]);
+
+        assert_eq!(result.row_count, 2);
+        let csv = ExportService::export(&result, ExportFormat::Csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "id,executed_at,query,duration_ms,row_count,success,error,tags"
+        );
+        assert!(lines[1].contains(",\"SELECT 1, 2\",12,1,true,,\"perf,JIRA-7\""));
+        assert!(lines[2].ends_with(",SELEC,3,,false,syntax error,"));
+
+        let json = ExportService::export(&result, ExportFormat::Json).unwrap();
+        let parsed: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed["data"][0]["id"], json!(entry.id));
+        assert_eq!(parsed["data"][1]["error"], json!("syntax error"));
+    }
 }
@@ -0,0 +1,280 @@
+use crate::services::identifier::quote_ident;
+use serde::{Deserialize, Serialize};
+/// Foreign Data Service
+///
+/// Foreign servers, user mappings and foreign tables of the current
+/// database, and `IMPORT FOREIGN SCHEMA` to create foreign tables for the
+/// tables of a remote schema. Foreign tables are browsed like any other
+/// table through the studio.
+///
+/// Options are reported as `key=value` strings; values of options that hold
+/// secrets (passwords) are replaced with `********`.
+use sqlx::PgPool;
+
+const REDACTED: &str = "********";
+
+/// A foreign server from `pg_foreign_server`
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ForeignServer {
+    pub name: String,
+    pub wrapper: String,
+    pub owner: String,
+    pub server_type: Option<String>,
+    pub server_version: Option<String>,
+    pub options: Vec<String>,
+}
+
+/// A user mapping from `pg_user_mappings`
+///
+/// Options are only visible to the server owner and superusers; they are
+/// empty for everyone else.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct UserMapping {
+    pub server: String,
+    /// Local role, or `public`
+    pub user_name: String,
+    pub options: Vec<String>,
+}
+
+/// A foreign table from `information_schema.foreign_tables`
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ForeignTable {
+    pub schema: String,
+    pub name: String,
+    pub server: String,
+    pub options: Vec<String>,
+}
+
+/// Create foreign tables for the tables of a remote schema
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImportForeignSchemaRequest {
+    pub server: String,
+    pub remote_schema: String,
+    /// Local schema the foreign tables are created in
+    pub local_schema: String,
+    /// Only import these remote tables
+    #[serde(default)]
+    pub limit_to: Vec<String>,
+    /// Import all remote tables except these
+    #[serde(default)]
+    pub except: Vec<String>,
+}
+
+/// Foreign servers of the current database
+pub async fn list_servers(pool: &PgPool) -> Result<Vec<ForeignServer>, sqlx::Error> {
+    let servers: Vec<ForeignServer> = sqlx::query_as(
+        r#"
+        SELECT s.srvname::text AS name,
+               w.fdwname::text AS wrapper,
+               pg_get_userbyid(s.srvowner)::text AS owner,
+               s.srvtype AS server_type,
+               s.srvversion AS server_version,
+               COALESCE(s.srvoptions, '{}') AS options
+        FROM pg_catalog.pg_foreign_server s
+        JOIN pg_catalog.pg_foreign_data_wrapper w ON w.oid = s.srvfdw
+        ORDER BY s.srvname
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(servers
+        .into_iter()
+        .map(|s| ForeignServer {
+            options: redact_options(s.options),
+            ..s
+        })
+        .collect())
+}
+
+/// User mappings of the foreign servers
+pub async fn list_user_mappings(pool: &PgPool) -> Result<Vec<UserMapping>, sqlx::Error> {
+    let mappings: Vec<UserMapping> = sqlx::query_as(
+        r#"
+        SELECT srvname::text AS server,
+               usename::text AS user_name,
+               COALESCE(umoptions, '{}') AS options
+        FROM pg_catalog.pg_user_mappings
+        ORDER BY srvname, usename
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(mappings
+        .into_iter()
+        .map(|m| UserMapping {
+            options: redact_options(m.options),
+            ..m
+        })
+        .collect())
+}
+
+/// Foreign tables the connected role can see
+pub async fn list_foreign_tables(pool: &PgPool) -> Result<Vec<ForeignTable>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT ft.foreign_table_schema::text AS schema,
+               ft.foreign_table_name::text AS name,
+               ft.foreign_server_name::text AS server,
+               COALESCE(pft.ftoptions, '{}') AS options
+        FROM information_schema.foreign_tables ft
+        JOIN pg_catalog.pg_namespace n ON n.nspname = ft.foreign_table_schema
+        JOIN pg_catalog.pg_class c
+          ON c.relnamespace = n.oid AND c.relname = ft.foreign_table_name
+        JOIN pg_catalog.pg_foreign_table pft ON pft.ftrelid = c.oid
+        ORDER BY ft.foreign_table_schema, ft.foreign_table_name
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Run `IMPORT FOREIGN SCHEMA`, returning how many foreign tables the local
+/// schema gained
+pub async fn import_foreign_schema(
+    pool: &PgPool,
+    req: &ImportForeignSchemaRequest,
+) -> Result<i64, String> {
+    let sql = import_foreign_schema_sql(req)?;
+    let count_sql = "SELECT count(*) FROM pg_catalog.pg_class c \
+                     JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace \
+                     WHERE n.nspname = $1 AND c.relkind = 'f'";
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    let before: i64 = sqlx::query_scalar(count_sql)
+        .bind(&req.local_schema)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    sqlx::query(&sql)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to import foreign schema: {}", e))?;
+    let after: i64 = sqlx::query_scalar(count_sql)
+        .bind(&req.local_schema)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(after - before)
+}
+
+/// Build the `IMPORT FOREIGN SCHEMA` statement
+pub fn import_foreign_schema_sql(req: &ImportForeignSchemaRequest) -> Result<String, String> {
+    for (what, name) in [
+        ("Server", &req.server),
+        ("Remote schema", &req.remote_schema),
+        ("Local schema", &req.local_schema),
+    ] {
+        if name.trim().is_empty() {
+            return Err(format!("{} cannot be empty", what));
+        }
+    }
+    if !req.limit_to.is_empty() && !req.except.is_empty() {
+        return Err("Give either limit_to or except, not both".to_string());
+    }
+
+    let table_list = |tables: &[String]| {
+        tables
+            .iter()
+            .map(|t| quote_ident(t))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut sql = format!("IMPORT FOREIGN SCHEMA {}", quote_ident(&req.remote_schema));
+    if !req.limit_to.is_empty() {
+        sql.push_str(&format!(" LIMIT TO ({})", table_list(&req.limit_to)));
+    } else if !req.except.is_empty() {
+        sql.push_str(&format!(" EXCEPT ({})", table_list(&req.except)));
+    }
+    sql.push_str(&format!(
+        " FROM SERVER {} INTO {}",
+        quote_ident(&req.server),
+        quote_ident(&req.local_schema)
+    ));
+    Ok(sql)
+}
+
+/// Hide the values of options that hold secrets
+fn redact_options(options: Vec<String>) -> Vec<String> {
+    options
+        .into_iter()
+        .map(|option| match option.split_once('=') {
+            Some((key, _)) if key.to_ascii_lowercase().contains("password") => {
+                format!("{}={}", key, REDACTED)
+            }
+            _ => option,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> ImportForeignSchemaRequest {
+        ImportForeignSchemaRequest {
+            server: "warehouse".to_string(),
+            remote_schema: "public".to_string(),
+            local_schema: "Remote Data".to_string(),
+            limit_to: Vec::new(),
+            except: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_import_foreign_schema_sql() {
+        assert_eq!(
+            import_foreign_schema_sql(&request()).unwrap(),
+            "IMPORT FOREIGN SCHEMA public FROM SERVER warehouse INTO \"Remote Data\""
+        );
+
+        let req = ImportForeignSchemaRequest {
+            limit_to: vec!["orders".to_string(), "Line Items".to_string()],
+            ..request()
+        };
+        assert_eq!(
+            import_foreign_schema_sql(&req).unwrap(),
+            "IMPORT FOREIGN SCHEMA public LIMIT TO (orders, \"Line Items\") \
+             FROM SERVER warehouse INTO \"Remote Data\""
+        );
+
+        let req = ImportForeignSchemaRequest {
+            except: vec!["audit".to_string()],
+            ..request()
+        };
+        assert!(import_foreign_schema_sql(&req)
+            .unwrap()
+            .contains(" EXCEPT (audit) "));
+    }
+
+    #[test]
+    fn test_import_foreign_schema_rejects_bad_input() {
+        let req = ImportForeignSchemaRequest {
+            limit_to: vec!["a".to_string()],
+            except: vec!["b".to_string()],
+            ..request()
+        };
+        assert!(import_foreign_schema_sql(&req).is_err());
+
+        let req = ImportForeignSchemaRequest {
+            server: " ".to_string(),
+            ..request()
+        };
+        assert!(import_foreign_schema_sql(&req).is_err());
+    }
+
+    #[test]
+    fn test_redact_options() {
+        assert_eq!(
+            redact_options(vec![
+                "user=app".to_string(),
+                "password=s3cret=x".to_string(),
+                "sslpassword=abc".to_string(),
+            ]),
+            vec!["user=app", "password=********", "sslpassword=********"]
+        );
+    }
+}
@@ -0,0 +1,132 @@
+/// Identifier Helpers
+///
+/// Consistent handling of schema, table and column names that are
+/// interpolated into SQL:
+/// - Quoting that matches PostgreSQL's `quote_ident()` (mixed case, spaces,
+///   dots, unicode, embedded quotes and reserved keywords)
+/// - Catalog verification that a schema/table pair names an existing relation
+///
+/// Path parameters arrive percent-decoded from axum, so a request for
+/// `/studio/public/Weird%20Table.Name` resolves to the table `Weird Table.Name`.
+use sqlx::Pool;
+use sqlx::Postgres;
+
+/// Keywords that PostgreSQL requires to be quoted when used as identifiers
+/// (reserved, type/function-name and column-name keywords)
+#[rustfmt::skip]
+const QUOTED_KEYWORDS: &[&str] = &[
+    "all", "analyse", "analyze", "and", "any", "array", "as", "asc", "asymmetric",
+    "authorization", "between", "bigint", "binary", "bit", "boolean", "both", "case", "cast",
+    "char", "character", "check", "coalesce", "collate", "collation", "column",
+    "concurrently", "constraint", "create", "cross", "current_catalog", "current_date",
+    "current_role", "current_schema", "current_time", "current_timestamp", "current_user",
+    "dec", "decimal", "default", "deferrable", "desc", "distinct", "do", "else", "end",
+    "except", "exists", "extract", "false", "fetch", "float", "for", "foreign", "freeze",
+    "from", "full", "grant", "greatest", "group", "grouping", "having", "ilike", "in",
+    "initially", "inner", "inout", "int", "integer", "intersect", "interval", "into", "is",
+    "isnull", "join", "lateral", "leading", "least", "left", "like", "limit", "localtime",
+    "localtimestamp", "national", "natural", "nchar", "none", "normalize", "not", "notnull",
+    "null", "nullif", "numeric", "offset", "on", "only", "or", "order", "out", "outer",
+    "overlaps", "overlay", "placing", "position", "precision", "primary", "real",
+    "references", "returning", "right", "row", "select", "session_user", "setof",
+    "similar", "smallint", "some", "substring", "symmetric", "system_user", "table",
+    "tablesample", "then", "time", "timestamp", "to", "trailing", "treat", "trim", "true",
+    "union", "unique", "user", "using", "values", "varchar", "variadic", "verbose", "when",
+    "where", "window", "with",
+];
+
+/// Quote an identifier only when PostgreSQL would require it
+pub fn quote_ident(name: &str) -> String {
+    let is_plain = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_lowercase() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '$')
+        && !QUOTED_KEYWORDS.contains(&name);
+
+    if is_plain {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+}
+
+/// Quote a schema-qualified relation name
+pub fn quote_qualified(schema: &str, name: &str) -> String {
+    format!("{}.{}", quote_ident(schema), quote_ident(name))
+}
+
+/// Verify that `schema.table` names an existing table, view or materialized view
+///
+/// Returns `sqlx::Error::RowNotFound` when it doesn't, so callers never
+/// interpolate a name that wasn't read back from the catalog.
+pub async fn resolve_relation(
+    pool: &Pool<Postgres>,
+    schema: &str,
+    table: &str,
+) -> Result<String, sqlx::Error> {
+    let query = r#"
+        SELECT c.relname::text
+        FROM pg_catalog.pg_class c
+        JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+        WHERE n.nspname = $1 AND c.relname = $2
+          AND c.relkind IN ('r', 'p', 'v', 'm', 'f')
+    "#;
+
+    let found: Option<String> = sqlx::query_scalar(query)
+        .bind(schema)
+        .bind(table)
+        .fetch_optional(pool)
+        .await?;
+
+    found
+        .map(|_| quote_qualified(schema, table))
+        .ok_or(sqlx::Error::RowNotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_ident_plain_names() {
+        assert_eq!(quote_ident("users"), "users");
+        assert_eq!(quote_ident("user_id2"), "user_id2");
+        assert_eq!(quote_ident("_private"), "_private");
+    }
+
+    #[test]
+    fn test_quote_ident_exotic_names() {
+        assert_eq!(quote_ident("UserId"), "\"UserId\"");
+        assert_eq!(quote_ident("my table"), "\"my table\"");
+        assert_eq!(quote_ident("Weird Table.Name"), "\"Weird Table.Name\"");
+        assert_eq!(quote_ident("a\"b"), "\"a\"\"b\"");
+        assert_eq!(quote_ident("1abc"), "\"1abc\"");
+        assert_eq!(quote_ident("café"), "\"café\"");
+        assert_eq!(quote_ident(""), "\"\"");
+    }
+
+    #[test]
+    fn test_quote_ident_keywords() {
+        assert_eq!(quote_ident("user"), "\"user\"");
+        assert_eq!(quote_ident("select"), "\"select\"");
+        assert_eq!(quote_ident("order"), "\"order\"");
+        // Unreserved keywords are valid bare identifiers
+        assert_eq!(quote_ident("name"), "name");
+    }
+
+    #[test]
+    fn test_quote_qualified() {
+        assert_eq!(quote_qualified("public", "users"), "public.users");
+        assert_eq!(
+            quote_qualified("Sales Data", "Weird Table.Name"),
+            "\"Sales Data\".\"Weird Table.Name\""
+        );
+        assert_eq!(
+            quote_qualified("public", "x\"; DROP TABLE users; --"),
+            "public.\"x\"\"; DROP TABLE users; --\""
+        );
+    }
+}
@@ -0,0 +1,35 @@
+use serde::{de::DeserializeOwned, Serialize};
+/// JSON File Persistence
+///
+/// Small helpers for stores that keep their state in memory and mirror it to
+/// a JSON file (saved queries, schedules).
+use std::path::Path;
+
+/// Read and parse a JSON file, returning `T::default()` if it does not exist
+pub async fn load<T: DeserializeOwned + Default>(path: &Path, what: &str) -> Result<T, String> {
+    match tokio::fs::read(path).await {
+        Ok(contents) => serde_json::from_slice(&contents)
+            .map_err(|e| format!("Failed to parse {} {}: {}", what, path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(format!("Failed to read {} {}: {}", what, path.display(), e)),
+    }
+}
+
+/// Write a value as JSON via a temporary file so a crash never leaves it truncated
+pub async fn save<T: Serialize + ?Sized>(path: &Path, value: &T, what: &str) -> Result<(), String> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("Failed to create {} directory: {}", what, e))?;
+    }
+
+    let contents = serde_json::to_vec_pretty(value)
+        .map_err(|e| format!("Failed to serialize {}: {}", what, e))?;
+    let tmp = path.with_extension("json.tmp");
+    tokio::fs::write(&tmp, contents)
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", what, e))?;
+    tokio::fs::rename(&tmp, path)
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", what, e))
+}
@@ -0,0 +1,784 @@
+use crate::services::identifier::quote_qualified;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+/// Maintenance Service
+///
+/// Runs batches of maintenance commands (`VACUUM`, `ANALYZE`, `REINDEX`) in
+/// the background, one command at a time:
+/// - Targets are checked against the catalog when a batch is queued, and the
+///   commands are built from the names read back
+/// - Batches run in the order they were queued, never two at once, each on a
+///   dedicated connection without a statement timeout
+/// - A failed command is recorded on its task and the batch moves on, since
+///   maintenance of one table does not depend on another
+/// - An optional pause between commands gives the server room to catch up
+///
+/// Batches are kept in memory with the status of each task, so the client can
+/// follow them while they run. Bloated btree indexes, estimated from planner
+/// statistics, can be listed to pick what to reindex.
+use sqlx::postgres::{PgConnectOptions, PgConnection};
+use sqlx::{Connection, FromRow, PgPool};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{oneshot, Notify, RwLock};
+
+/// Finished batches kept for listing; the oldest are forgotten first
+const MAX_BATCHES: usize = 20;
+
+/// Most tasks in one batch
+pub const MAX_TASKS: usize = 200;
+
+/// Longest pause between tasks
+pub const MAX_PAUSE_MS: u64 = 10 * 60 * 1000;
+
+/// What a task does to its target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MaintenanceAction {
+    Vacuum,
+    VacuumAnalyze,
+    Analyze,
+    /// Rebuild one index, or every index of a table
+    Reindex,
+}
+
+/// One task of a batch as requested
+#[derive(Debug, Clone, Deserialize)]
+pub struct TaskRequest {
+    pub action: MaintenanceAction,
+    pub schema: String,
+    pub table: Option<String>,
+    /// Index to rebuild (`reindex` only)
+    pub index: Option<String>,
+}
+
+/// A batch as requested
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatchRequest {
+    pub name: Option<String>,
+    pub tasks: Vec<TaskRequest>,
+    /// Wait this long after each task before starting the next
+    #[serde(default)]
+    pub pause_ms: u64,
+    /// Rebuild indexes with `REINDEX ... CONCURRENTLY`, which does not block
+    /// writes (PostgreSQL 12 or later)
+    #[serde(default = "default_true")]
+    pub concurrently: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchStatus {
+    Queued,
+    Running,
+    /// Every task succeeded
+    Succeeded,
+    /// At least one task failed
+    Failed,
+    Cancelled,
+}
+
+impl BatchStatus {
+    pub fn is_finished(self) -> bool {
+        !matches!(self, BatchStatus::Queued | BatchStatus::Running)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    /// Not run because the batch was cancelled or could not connect
+    Skipped,
+}
+
+/// One command of a batch and its outcome
+#[derive(Debug, Clone, Serialize)]
+pub struct MaintenanceTask {
+    pub action: MaintenanceAction,
+    pub schema: String,
+    /// Table or index the command runs on
+    pub target: String,
+    pub statement: String,
+    pub status: TaskStatus,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub duration_ms: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// A queued, running or recent batch
+#[derive(Debug, Clone, Serialize)]
+pub struct MaintenanceBatch {
+    pub id: String,
+    pub name: String,
+    pub database: String,
+    pub status: BatchStatus,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub pause_ms: u64,
+    /// Server process running the batch's commands, while it runs
+    pub backend_pid: Option<i32>,
+    pub cancel_requested: bool,
+    /// Why no task could run
+    pub error: Option<String>,
+    pub tasks: Vec<MaintenanceTask>,
+    #[serde(skip)]
+    connect: Option<PgConnectOptions>,
+    /// Wakes the runner from its pause between tasks when cancelled
+    #[serde(skip)]
+    wake: Arc<Notify>,
+}
+
+impl MaintenanceBatch {
+    /// The status once the runner is done with the batch
+    fn final_status(&self) -> BatchStatus {
+        if self.cancel_requested {
+            BatchStatus::Cancelled
+        } else if self.error.is_some() || self.tasks.iter().any(|t| t.status == TaskStatus::Failed)
+        {
+            BatchStatus::Failed
+        } else {
+            BatchStatus::Succeeded
+        }
+    }
+
+    fn skip_remaining(&mut self) {
+        for task in &mut self.tasks {
+            if task.status == TaskStatus::Queued {
+                task.status = TaskStatus::Skipped;
+            }
+        }
+    }
+}
+
+/// The command for an action on a target (already quoted)
+pub fn statement(
+    action: MaintenanceAction,
+    target: &str,
+    is_index: bool,
+    concurrently: bool,
+) -> String {
+    match action {
+        MaintenanceAction::Vacuum => format!("VACUUM {}", target),
+        MaintenanceAction::VacuumAnalyze => format!("VACUUM (ANALYZE) {}", target),
+        MaintenanceAction::Analyze => format!("ANALYZE {}", target),
+        MaintenanceAction::Reindex => format!(
+            "REINDEX {}{} {}",
+            if is_index { "INDEX" } else { "TABLE" },
+            if concurrently { " CONCURRENTLY" } else { "" },
+            target
+        ),
+    }
+}
+
+/// Check a request's shape before looking anything up
+pub fn validate(request: &BatchRequest) -> Result<(), String> {
+    if request.tasks.is_empty() {
+        return Err("A batch needs at least one task".to_string());
+    }
+    if request.tasks.len() > MAX_TASKS {
+        return Err(format!("A batch is limited to {} tasks", MAX_TASKS));
+    }
+    if request.pause_ms > MAX_PAUSE_MS {
+        return Err(format!(
+            "pause_ms is limited to {} ({} minutes)",
+            MAX_PAUSE_MS,
+            MAX_PAUSE_MS / 60_000
+        ));
+    }
+    for (i, task) in request.tasks.iter().enumerate() {
+        let table = task.table.as_deref().is_some_and(|t| !t.is_empty());
+        let index = task.index.as_deref().is_some_and(|t| !t.is_empty());
+        let valid = match task.action {
+            MaintenanceAction::Reindex => table != index,
+            _ => table && !index,
+        };
+        if task.schema.is_empty() || !valid {
+            let needs = match task.action {
+                MaintenanceAction::Reindex => "a schema and either a table or an index",
+                _ => "a schema and a table",
+            };
+            return Err(format!("Task {} needs {}", i + 1, needs));
+        }
+    }
+    Ok(())
+}
+
+/// Resolve a request's targets in the catalog and build its tasks
+pub async fn plan_tasks(
+    pool: &PgPool,
+    request: &BatchRequest,
+) -> Result<Vec<MaintenanceTask>, String> {
+    validate(request)?;
+    let mut tasks = Vec::with_capacity(request.tasks.len());
+    for task in &request.tasks {
+        let (name, kinds, what) = match (&task.index, &task.table) {
+            (Some(index), _) if !index.is_empty() => (index, &["i", "I"][..], "Index"),
+            (_, Some(table)) if task.action == MaintenanceAction::Reindex => {
+                (table, &["r", "p", "m", "t"][..], "Table")
+            }
+            (_, Some(table)) => (table, &["r", "p", "m"][..], "Table"),
+            _ => unreachable!("checked by validate"),
+        };
+        let found: Option<(String, String)> = sqlx::query_as(
+            "SELECT n.nspname::text, c.relname::text \
+             FROM pg_catalog.pg_class c \
+             JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace \
+             WHERE n.nspname = $1 AND c.relname = $2 AND c.relkind::text = ANY($3)",
+        )
+        .bind(&task.schema)
+        .bind(name)
+        .bind(kinds)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| format!("Failed to look up {}.{}: {}", task.schema, name, e))?;
+        let Some((schema, target)) = found else {
+            return Err(format!("{} '{}.{}' not found", what, task.schema, name));
+        };
+        tasks.push(MaintenanceTask {
+            action: task.action,
+            statement: statement(
+                task.action,
+                &quote_qualified(&schema, &target),
+                what == "Index",
+                request.concurrently,
+            ),
+            schema,
+            target,
+            status: TaskStatus::Queued,
+            started_at: None,
+            finished_at: None,
+            duration_ms: None,
+            error: None,
+        });
+    }
+    Ok(tasks)
+}
+
+/// A btree index larger than its statistics suggest
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct IndexBloat {
+    pub schema: String,
+    pub table_name: String,
+    pub index_name: String,
+    pub size_bytes: i64,
+    pub estimated_bloat_bytes: i64,
+    /// Share of the index estimated to be free space, 0 to 1
+    pub bloat_ratio: f64,
+}
+
+/// Btree indexes with at least `min_bloat_bytes` and `min_ratio` of estimated
+/// bloat, most bloated first
+///
+/// The estimate compares each index's size with the size its row count and
+/// average key width (from `pg_stats`) would need when freshly built at its
+/// fill factor. It is rough, and only covers indexes on plain columns of
+/// analyzed tables.
+pub async fn bloated_indexes(
+    pool: &PgPool,
+    schema: Option<&str>,
+    min_bloat_bytes: i64,
+    min_ratio: f64,
+) -> Result<Vec<IndexBloat>, sqlx::Error> {
+    let query = r#"
+        WITH idx AS (
+            SELECT n.nspname::text AS schema, t.relname::text AS table_name,
+                   i.relname::text AS index_name, x.indexrelid, x.indrelid,
+                   x.indkey::int2[] AS indkey, i.relpages::numeric AS relpages,
+                   i.reltuples::numeric AS reltuples,
+                   current_setting('block_size')::numeric AS block_size,
+                   coalesce(substring(array_to_string(i.reloptions, ' ')
+                       FROM 'fillfactor=([0-9]+)')::numeric, 90) AS fillfactor
+            FROM pg_catalog.pg_index x
+            JOIN pg_catalog.pg_class i ON i.oid = x.indexrelid
+            JOIN pg_catalog.pg_class t ON t.oid = x.indrelid
+            JOIN pg_catalog.pg_namespace n ON n.oid = i.relnamespace
+            JOIN pg_catalog.pg_am a ON a.oid = i.relam
+            WHERE a.amname = 'btree' AND i.relpages > 1 AND i.reltuples >= 0
+              AND 0 <> ALL (x.indkey::int2[])
+              AND n.nspname NOT IN ('pg_catalog', 'information_schema')
+              AND n.nspname !~ '^pg_toast'
+              AND ($1::text IS NULL OR n.nspname = $1)
+        ),
+        widths AS (
+            SELECT idx.indexrelid, sum(s.avg_width)::numeric AS key_width,
+                   count(s.avg_width) = cardinality(idx.indkey) AS has_stats
+            FROM idx
+            CROSS JOIN LATERAL unnest(idx.indkey) AS k(attnum)
+            JOIN pg_catalog.pg_attribute att
+              ON att.attrelid = idx.indrelid AND att.attnum = k.attnum
+            LEFT JOIN pg_catalog.pg_stats s
+              ON s.schemaname = idx.schema AND s.tablename = idx.table_name
+             AND s.attname = att.attname AND NOT s.inherited
+            GROUP BY idx.indexrelid, idx.indkey
+        ),
+        estimate AS (
+            SELECT idx.*,
+                   -- Meta page plus leaf pages: each tuple is an 8-byte header
+                   -- and the key, aligned to 8 bytes, with a 4-byte line pointer
+                   1 + ceil(idx.reltuples * (8 + ceil(w.key_width / 8) * 8 + 4)
+                       / ((idx.block_size - 24 - 16) * idx.fillfactor / 100)) AS expected_pages
+            FROM idx JOIN widths w USING (indexrelid)
+            WHERE w.has_stats
+        )
+        SELECT schema, table_name, index_name,
+               (relpages * block_size)::bigint AS size_bytes,
+               ((relpages - expected_pages) * block_size)::bigint AS estimated_bloat_bytes,
+               round((relpages - expected_pages) / relpages, 3)::float8 AS bloat_ratio
+        FROM estimate
+        WHERE relpages > expected_pages
+          AND (relpages - expected_pages) * block_size >= $2
+          AND (relpages - expected_pages) / relpages >= $3
+        ORDER BY estimated_bloat_bytes DESC
+        LIMIT 100
+    "#;
+
+    sqlx::query_as(query)
+        .bind(schema)
+        .bind(min_bloat_bytes)
+        .bind(min_ratio)
+        .fetch_all(pool)
+        .await
+}
+
+/// Queued, running and recent maintenance batches
+pub struct MaintenanceQueue {
+    batches: Arc<RwLock<Vec<MaintenanceBatch>>>,
+    /// Completes when the last queued batch is done; the next batch waits on it
+    tail: Mutex<Option<oneshot::Receiver<()>>>,
+}
+
+impl Default for MaintenanceQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MaintenanceQueue {
+    pub fn new() -> Self {
+        Self {
+            batches: Arc::new(RwLock::new(Vec::new())),
+            tail: Mutex::new(None),
+        }
+    }
+
+    /// Batches, newest first
+    pub async fn list(&self) -> Vec<MaintenanceBatch> {
+        self.batches.read().await.iter().rev().cloned().collect()
+    }
+
+    pub async fn get(&self, id: &str) -> Option<MaintenanceBatch> {
+        self.batches
+            .read()
+            .await
+            .iter()
+            .find(|b| b.id == id)
+            .cloned()
+    }
+
+    /// Queue `tasks` to run on the database `connect` points at, after the
+    /// batches queued before; `on_task` gets each finished task
+    pub async fn enqueue<F, Fut>(
+        &self,
+        connect: PgConnectOptions,
+        request: &BatchRequest,
+        tasks: Vec<MaintenanceTask>,
+        on_task: F,
+    ) -> MaintenanceBatch
+    where
+        F: Fn(MaintenanceTask) -> Fut + Send + 'static,
+        Fut: std::future::Future<Output = ()> + Send,
+    {
+        let batch = MaintenanceBatch {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: request
+                .name
+                .clone()
+                .filter(|n| !n.trim().is_empty())
+                .unwrap_or_else(|| match tasks.len() {
+                    1 => "1 task".to_string(),
+                    n => format!("{} tasks", n),
+                }),
+            database: connect.get_database().unwrap_or_default().to_string(),
+            status: BatchStatus::Queued,
+            created_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+            pause_ms: request.pause_ms,
+            backend_pid: None,
+            cancel_requested: false,
+            error: None,
+            tasks,
+            connect: Some(connect.clone()),
+            wake: Arc::new(Notify::new()),
+        };
+
+        let (done, next) = oneshot::channel();
+        let previous = {
+            let mut batches = self.batches.write().await;
+            batches.push(batch.clone());
+            while batches.len() > MAX_BATCHES {
+                match batches.iter().position(|b| b.status.is_finished()) {
+                    Some(oldest) => batches.remove(oldest),
+                    None => break,
+                };
+            }
+            self.tail
+                .lock()
+                .expect("maintenance queue lock poisoned")
+                .replace(next)
+        };
+
+        let runner = BatchRunner {
+            batches: self.batches.clone(),
+            id: batch.id.clone(),
+        };
+        tokio::spawn(async move {
+            if let Some(previous) = previous {
+                let _ = previous.await;
+            }
+            runner.run(&connect, on_task).await;
+            drop(done);
+        });
+
+        batch
+    }
+
+    /// Cancel a batch: a queued one never starts, and a running one has its
+    /// current command interrupted and runs no further tasks
+    pub async fn cancel(&self, id: &str) -> Option<Result<MaintenanceBatch, String>> {
+        let (batch, running) = {
+            let mut batches = self.batches.write().await;
+            let batch = batches.iter_mut().find(|b| b.id == id)?;
+            if batch.status.is_finished() {
+                return Some(Err(format!("Batch '{}' has already finished", batch.name)));
+            }
+            batch.cancel_requested = true;
+            if batch.status == BatchStatus::Queued {
+                batch.status = BatchStatus::Cancelled;
+                batch.finished_at = Some(Utc::now());
+                batch.skip_remaining();
+            }
+            batch.wake.notify_one();
+            // Only a running command needs interrupting; between tasks the
+            // runner wakes up and stops by itself
+            let running = batch
+                .tasks
+                .iter()
+                .any(|t| t.status == TaskStatus::Running)
+                .then(|| batch.backend_pid.zip(batch.connect.clone()))
+                .flatten();
+            (batch.clone(), running)
+        };
+
+        // Interrupt from another connection to the batch's own server
+        if let Some((pid, connect)) = running {
+            if let Ok(mut conn) = PgConnection::connect_with(&connect).await {
+                let _ = sqlx::query("SELECT pg_cancel_backend($1)")
+                    .bind(pid)
+                    .execute(&mut conn)
+                    .await;
+                let _ = conn.close().await;
+            }
+        }
+        Some(Ok(batch))
+    }
+}
+
+/// Executes one batch and records its progress
+struct BatchRunner {
+    batches: Arc<RwLock<Vec<MaintenanceBatch>>>,
+    id: String,
+}
+
+impl BatchRunner {
+    async fn update<T>(&self, f: impl FnOnce(&mut MaintenanceBatch) -> T) -> Option<T> {
+        let mut batches = self.batches.write().await;
+        batches.iter_mut().find(|b| b.id == self.id).map(f)
+    }
+
+    /// The next task to run, marked running, unless the batch was cancelled
+    async fn next_task(&self) -> Option<(usize, String)> {
+        self.update(|batch| {
+            if batch.cancel_requested {
+                return None;
+            }
+            let (i, task) = batch
+                .tasks
+                .iter_mut()
+                .enumerate()
+                .find(|(_, t)| t.status == TaskStatus::Queued)?;
+            task.status = TaskStatus::Running;
+            task.started_at = Some(Utc::now());
+            Some((i, task.statement.clone()))
+        })
+        .await
+        .flatten()
+    }
+
+    async fn run<F, Fut>(&self, connect: &PgConnectOptions, on_task: F)
+    where
+        F: Fn(MaintenanceTask) -> Fut,
+        Fut: std::future::Future<Output = ()>,
+    {
+        let started = self
+            .update(|batch| {
+                if batch.status != BatchStatus::Queued {
+                    return None;
+                }
+                batch.status = BatchStatus::Running;
+                batch.started_at = Some(Utc::now());
+                Some((batch.pause_ms, batch.wake.clone()))
+            })
+            .await
+            .flatten();
+        // Cancelled while queued, or forgotten
+        let Some((pause_ms, wake)) = started else {
+            return;
+        };
+
+        match connect_for_maintenance(connect).await {
+            Ok((mut conn, pid)) => {
+                self.update(|batch| batch.backend_pid = Some(pid)).await;
+                while let Some((i, sql)) = self.next_task().await {
+                    let clock = std::time::Instant::now();
+                    let result = execute(&mut conn, &sql).await;
+                    let task = self
+                        .update(|batch| {
+                            let task = &mut batch.tasks[i];
+                            task.finished_at = Some(Utc::now());
+                            task.duration_ms = Some(clock.elapsed().as_millis() as u64);
+                            match result {
+                                Ok(_) => task.status = TaskStatus::Succeeded,
+                                Err(e) => {
+                                    task.status = TaskStatus::Failed;
+                                    task.error = Some(e);
+                                }
+                            }
+                            let more = !batch.cancel_requested
+                                && batch.tasks.iter().any(|t| t.status == TaskStatus::Queued);
+                            (batch.tasks[i].clone(), more)
+                        })
+                        .await;
+                    let Some((task, more)) = task else {
+                        break;
+                    };
+                    on_task(task).await;
+                    if more && pause_ms > 0 {
+                        let pause = tokio::time::sleep(std::time::Duration::from_millis(pause_ms));
+                        tokio::select! {
+                            _ = pause => {}
+                            _ = wake.notified() => {}
+                        }
+                    }
+                }
+                let _ = conn.close().await;
+            }
+            Err(e) => {
+                self.update(|batch| batch.error = Some(e)).await;
+            }
+        }
+
+        self.update(|batch| {
+            batch.skip_remaining();
+            batch.status = batch.final_status();
+            batch.finished_at = Some(Utc::now());
+            batch.backend_pid = None;
+            batch.connect = None;
+        })
+        .await;
+    }
+}
+
+/// A connection whose commands may run as long as they need, with its
+/// backend process id
+async fn connect_for_maintenance(
+    connect: &PgConnectOptions,
+) -> Result<(PgConnection, i32), String> {
+    let mut conn = PgConnection::connect_with(connect)
+        .await
+        .map_err(|e| format!("Failed to connect: {}", e))?;
+    for setting in ["SET statement_timeout = 0", "SET lock_timeout = 0"] {
+        execute(&mut conn, setting)
+            .await
+            .map_err(|e| format!("Failed to prepare the connection: {}", e))?;
+    }
+    let pid: i32 = sqlx::query_scalar("SELECT pg_backend_pid()")
+        .fetch_one(&mut conn)
+        .await
+        .map_err(|e| format!("Failed to prepare the connection: {}", e))?;
+    Ok((conn, pid))
+}
+
+/// Run one statement on its own, outside any transaction block, as `VACUUM`
+/// and `REINDEX CONCURRENTLY` require
+async fn execute(conn: &mut PgConnection, sql: &str) -> Result<(), String> {
+    sqlx::query(sql)
+        .persistent(false)
+        .execute(conn)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(value: serde_json::Value) -> BatchRequest {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn task(status: TaskStatus) -> MaintenanceTask {
+        MaintenanceTask {
+            action: MaintenanceAction::Analyze,
+            schema: "public".to_string(),
+            target: "orders".to_string(),
+            statement: "ANALYZE public.orders".to_string(),
+            status,
+            started_at: None,
+            finished_at: None,
+            duration_ms: None,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_statement() {
+        let target = quote_qualified("public", "Order Items");
+        assert_eq!(
+            statement(MaintenanceAction::Vacuum, &target, false, true),
+            r#"VACUUM public."Order Items""#
+        );
+        assert_eq!(
+            statement(MaintenanceAction::VacuumAnalyze, "s.t", false, true),
+            "VACUUM (ANALYZE) s.t"
+        );
+        assert_eq!(
+            statement(MaintenanceAction::Analyze, "s.t", false, false),
+            "ANALYZE s.t"
+        );
+        assert_eq!(
+            statement(MaintenanceAction::Reindex, "s.t_pkey", true, true),
+            "REINDEX INDEX CONCURRENTLY s.t_pkey"
+        );
+        assert_eq!(
+            statement(MaintenanceAction::Reindex, "s.t", false, false),
+            "REINDEX TABLE s.t"
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let valid = request(json!({
+            "tasks": [
+                { "action": "vacuum_analyze", "schema": "public", "table": "orders" },
+                { "action": "reindex", "schema": "public", "index": "orders_pkey" },
+                { "action": "reindex", "schema": "public", "table": "orders" },
+            ],
+            "pause_ms": 5000,
+        }));
+        assert!(valid.concurrently);
+        assert!(validate(&valid).is_ok());
+
+        let error = |value| validate(&request(value)).unwrap_err();
+        assert!(error(json!({ "tasks": [] })).contains("at least one"));
+        assert_eq!(
+            error(json!({ "tasks": [
+                { "action": "analyze", "schema": "public", "table": "orders" },
+                { "action": "vacuum", "schema": "public", "index": "orders_pkey" },
+            ] })),
+            "Task 2 needs a schema and a table"
+        );
+        assert!(error(json!({ "tasks": [
+            { "action": "reindex", "schema": "public", "table": "t", "index": "i" },
+        ] }))
+        .contains("either a table or an index"));
+        assert!(error(json!({
+            "tasks": [{ "action": "analyze", "schema": "public", "table": "t" }],
+            "pause_ms": MAX_PAUSE_MS + 1,
+        }))
+        .contains("pause_ms"));
+        let too_many: Vec<_> = (0..=MAX_TASKS)
+            .map(|_| json!({ "action": "analyze", "schema": "public", "table": "t" }))
+            .collect();
+        assert!(error(json!({ "tasks": too_many })).contains("limited"));
+    }
+
+    #[test]
+    fn test_final_status() {
+        let mut batch = MaintenanceBatch {
+            id: "b".to_string(),
+            name: "nightly".to_string(),
+            database: "app".to_string(),
+            status: BatchStatus::Running,
+            created_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+            pause_ms: 0,
+            backend_pid: None,
+            cancel_requested: false,
+            error: None,
+            tasks: vec![task(TaskStatus::Succeeded), task(TaskStatus::Succeeded)],
+            connect: None,
+            wake: Arc::new(Notify::new()),
+        };
+        assert_eq!(batch.final_status(), BatchStatus::Succeeded);
+
+        batch.tasks[1].status = TaskStatus::Failed;
+        assert_eq!(batch.final_status(), BatchStatus::Failed);
+
+        batch.tasks.push(task(TaskStatus::Queued));
+        batch.cancel_requested = true;
+        batch.skip_remaining();
+        assert_eq!(batch.tasks[2].status, TaskStatus::Skipped);
+        assert_eq!(batch.final_status(), BatchStatus::Cancelled);
+    }
+
+    #[tokio::test]
+    async fn test_cancel() {
+        let queue = MaintenanceQueue::new();
+        // Nothing listens there, so the batch fails if it ever starts
+        let connect = PgConnectOptions::new()
+            .host("127.0.0.1")
+            .port(1)
+            .database("app");
+        let request = request(json!({
+            "name": "nightly",
+            "tasks": [{ "action": "analyze", "schema": "public", "table": "orders" }],
+        }));
+        let batch = queue
+            .enqueue(
+                connect,
+                &request,
+                vec![task(TaskStatus::Queued)],
+                |_| async {},
+            )
+            .await;
+        assert_eq!(batch.database, "app");
+        // Cancelled whether or not the runner has picked the batch up yet
+        let cancelled = queue.cancel(&batch.id).await.unwrap().unwrap();
+        assert!(cancelled.cancel_requested);
+
+        for _ in 0..100 {
+            if queue.get(&batch.id).await.unwrap().status.is_finished() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        let finished = queue.get(&batch.id).await.unwrap();
+        assert_eq!(finished.status, BatchStatus::Cancelled);
+        assert_eq!(finished.tasks[0].status, TaskStatus::Skipped);
+        assert!(queue.cancel(&batch.id).await.unwrap().is_err());
+        assert!(queue.cancel("missing").await.is_none());
+    }
+}
@@ -0,0 +1,250 @@
+use sqlx::{Pool, Postgres};
+/// Metadata Cache
+///
+/// Short-lived in-memory cache for catalog metadata that is expensive to
+/// recompute on every page load (e.g. the Studio sidebar table list with
+/// approximate row counts and sizes). Entries expire after a TTL and are
+/// invalidated explicitly when DDL changes a schema.
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+use crate::models::{AutocompleteMetadata, TableInfo};
+use crate::services::schema_service;
+
+/// How a table list should be ordered
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TableSort {
+    /// Alphabetical by name
+    #[default]
+    Name,
+    /// Largest total relation size first
+    Size,
+}
+
+impl TableSort {
+    /// Parse a `sort` query parameter, defaulting to name order
+    pub fn parse(value: Option<&str>) -> Self {
+        match value {
+            Some(v) if v.eq_ignore_ascii_case("size") => TableSort::Size,
+            _ => TableSort::Name,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TableSort::Name => "name",
+            TableSort::Size => "size",
+        }
+    }
+
+    /// Sort tables in place
+    pub fn apply(&self, tables: &mut [TableInfo]) {
+        match self {
+            TableSort::Name => tables.sort_by(|a, b| a.name.cmp(&b.name)),
+            TableSort::Size => tables.sort_by(|a, b| {
+                b.size
+                    .unwrap_or(0)
+                    .cmp(&a.size.unwrap_or(0))
+                    .then_with(|| a.name.cmp(&b.name))
+            }),
+        }
+    }
+}
+
+struct CachedTables {
+    fetched_at: Instant,
+    tables: Vec<TableInfo>,
+}
+
+struct CachedAutocomplete {
+    fetched_at: Instant,
+    metadata: Arc<AutocompleteMetadata>,
+}
+
+/// Per-schema cache of table listings and autocomplete snapshots
+pub struct MetadataCache {
+    tables: Arc<RwLock<HashMap<String, CachedTables>>>,
+    /// Keyed by schema scope; `None` is the whole database
+    autocomplete: Arc<RwLock<HashMap<Option<String>, CachedAutocomplete>>>,
+    ttl: Duration,
+}
+
+impl MetadataCache {
+    /// Create a new cache whose entries expire after `ttl`
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            tables: Arc::new(RwLock::new(HashMap::new())),
+            autocomplete: Arc::new(RwLock::new(HashMap::new())),
+            ttl,
+        }
+    }
+
+    /// Get the tables and views of a schema, querying the catalog on a miss
+    pub async fn tables(
+        &self,
+        pool: &Pool<Postgres>,
+        schema: &str,
+    ) -> Result<Vec<TableInfo>, sqlx::Error> {
+        if let Some(tables) = self.get_fresh(schema).await {
+            return Ok(tables);
+        }
+
+        let tables = schema_service::list_tables(pool, schema).await?;
+        self.tables.write().await.insert(
+            schema.to_string(),
+            CachedTables {
+                fetched_at: Instant::now(),
+                tables: tables.clone(),
+            },
+        );
+
+        Ok(tables)
+    }
+
+    /// Get the autocomplete snapshot for a schema (or every schema),
+    /// querying the catalog on a miss
+    pub async fn autocomplete(
+        &self,
+        pool: &Pool<Postgres>,
+        schema: Option<&str>,
+    ) -> Result<Arc<AutocompleteMetadata>, sqlx::Error> {
+        let key = schema.map(str::to_string);
+        if let Some(entry) = self.autocomplete.read().await.get(&key) {
+            if entry.fetched_at.elapsed() < self.ttl {
+                return Ok(entry.metadata.clone());
+            }
+        }
+
+        let metadata = Arc::new(schema_service::get_autocomplete_metadata(pool, schema).await?);
+        self.autocomplete.write().await.insert(
+            key,
+            CachedAutocomplete {
+                fetched_at: Instant::now(),
+                metadata: metadata.clone(),
+            },
+        );
+
+        Ok(metadata)
+    }
+
+    /// Drop the cached listing for a schema, along with the autocomplete
+    /// snapshots that include it
+    pub async fn invalidate(&self, schema: &str) {
+        self.tables.write().await.remove(schema);
+        let mut autocomplete = self.autocomplete.write().await;
+        autocomplete.remove(&None);
+        autocomplete.remove(&Some(schema.to_string()));
+    }
+
+    /// Drop everything, after changes that may touch any schema
+    pub async fn invalidate_all(&self) {
+        self.tables.write().await.clear();
+        self.autocomplete.write().await.clear();
+    }
+
+    async fn get_fresh(&self, schema: &str) -> Option<Vec<TableInfo>> {
+        let cache = self.tables.read().await;
+        cache
+            .get(schema)
+            .filter(|entry| entry.fetched_at.elapsed() < self.ttl)
+            .map(|entry| entry.tables.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(name: &str, size: Option<i64>) -> TableInfo {
+        TableInfo {
+            schema: "public".to_string(),
+            name: name.to_string(),
+            table_type: "BASE TABLE".to_string(),
+            row_count: None,
+            size,
+        }
+    }
+
+    #[test]
+    fn test_table_sort_parse() {
+        assert_eq!(TableSort::parse(Some("size")), TableSort::Size);
+        assert_eq!(TableSort::parse(Some("SIZE")), TableSort::Size);
+        assert_eq!(TableSort::parse(Some("name")), TableSort::Name);
+        assert_eq!(TableSort::parse(Some("bogus")), TableSort::Name);
+        assert_eq!(TableSort::parse(None), TableSort::Name);
+    }
+
+    #[test]
+    fn test_sort_by_size_largest_first() {
+        let mut tables = vec![
+            table("small", Some(10)),
+            table("unknown", None),
+            table("big", Some(1000)),
+            table("also_small", Some(10)),
+        ];
+
+        TableSort::Size.apply(&mut tables);
+        let names: Vec<_> = tables.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["big", "also_small", "small", "unknown"]);
+
+        TableSort::Name.apply(&mut tables);
+        let names: Vec<_> = tables.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["also_small", "big", "small", "unknown"]);
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_and_expiry() {
+        let cache = MetadataCache::new(Duration::from_secs(60));
+        cache.tables.write().await.insert(
+            "public".to_string(),
+            CachedTables {
+                fetched_at: Instant::now(),
+                tables: vec![table("users", Some(1))],
+            },
+        );
+        assert_eq!(cache.get_fresh("public").await.unwrap().len(), 1);
+
+        cache.invalidate("public").await;
+        assert!(cache.get_fresh("public").await.is_none());
+
+        let expired = MetadataCache::new(Duration::ZERO);
+        expired.tables.write().await.insert(
+            "public".to_string(),
+            CachedTables {
+                fetched_at: Instant::now(),
+                tables: vec![table("users", Some(1))],
+            },
+        );
+        assert!(expired.get_fresh("public").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_drops_autocomplete_snapshots() {
+        let cache = MetadataCache::new(Duration::from_secs(60));
+        let metadata = Arc::new(AutocompleteMetadata {
+            schemas: vec![],
+            tables: vec![],
+            functions: vec![],
+            keywords: vec![],
+        });
+        {
+            let mut autocomplete = cache.autocomplete.write().await;
+            for key in [None, Some("public"), Some("audit")] {
+                autocomplete.insert(
+                    key.map(str::to_string),
+                    CachedAutocomplete {
+                        fetched_at: Instant::now(),
+                        metadata: metadata.clone(),
+                    },
+                );
+            }
+        }
+
+        cache.invalidate("public").await;
+        let autocomplete = cache.autocomplete.read().await;
+        assert_eq!(autocomplete.len(), 1);
+        assert!(autocomplete.contains_key(&Some("audit".to_string())));
+    }
+}
@@ -0,0 +1,752 @@
+use crate::services::json_file;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+/// Metrics History
+///
+/// Samples a few key statistics of the default connection at a fixed
+/// interval so the dashboard can show trends, not just current values:
+/// - Database size
+/// - Connections to the database
+/// - Cache hit ratio over the last interval
+/// - Transactions per second over the last interval
+///
+/// Samples are kept in memory for the retention period and mirrored to a
+/// JSON file, so the history survives restarts. For charts, a metric can be
+/// downsampled into per-minute or per-hour buckets, and each new sample is
+/// broadcast to subscribers (the dashboard's event stream).
+use sqlx::PgPool;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, Mutex};
+
+/// A statistic with a recorded history
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    DatabaseSize,
+    Connections,
+    CacheHitRatio,
+    Tps,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 4] = [
+        Metric::DatabaseSize,
+        Metric::Connections,
+        Metric::CacheHitRatio,
+        Metric::Tps,
+    ];
+
+    pub fn parse(name: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|metric| metric.name() == name)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown metric '{}'; expected one of: database_size, connections, cache_hit_ratio, tps",
+                    name
+                )
+            })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::DatabaseSize => "database_size",
+            Metric::Connections => "connections",
+            Metric::CacheHitRatio => "cache_hit_ratio",
+            Metric::Tps => "tps",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Metric::DatabaseSize => "Database Size",
+            Metric::Connections => "Connections",
+            Metric::CacheHitRatio => "Cache Hit Ratio",
+            Metric::Tps => "Transactions per Second",
+        }
+    }
+
+    /// The metric's value in a sample, if it was measured
+    pub fn value(&self, sample: &MetricSample) -> Option<f64> {
+        match self {
+            Metric::DatabaseSize => Some(sample.database_size_bytes as f64),
+            Metric::Connections => Some(sample.connections as f64),
+            Metric::CacheHitRatio => sample.cache_hit_ratio,
+            Metric::Tps => sample.tps,
+        }
+    }
+
+    /// A value formatted for display
+    pub fn format(&self, value: f64) -> String {
+        match self {
+            Metric::DatabaseSize => format_bytes(value),
+            Metric::Connections => format!("{:.0}", value),
+            Metric::CacheHitRatio => format!("{:.2}%", value),
+            Metric::Tps => format!("{:.1}", value),
+        }
+    }
+}
+
+fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "kB", "MB", "GB", "TB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{:.0} {}", value, UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Statistics recorded at one point in time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricSample {
+    pub at: DateTime<Utc>,
+    pub database_size_bytes: i64,
+    pub connections: i64,
+    /// Share of block reads served from shared buffers since the previous
+    /// sample, as a percentage
+    pub cache_hit_ratio: Option<f64>,
+    /// Committed and rolled back transactions per second since the
+    /// previous sample; unknown for the first sample
+    pub tps: Option<f64>,
+}
+
+/// Cumulative counters read from the server at each sample
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct RawCounters {
+    pub database_size_bytes: i64,
+    pub connections: i64,
+    pub transactions: i64,
+    pub blks_hit: i64,
+    pub blks_read: i64,
+}
+
+impl RawCounters {
+    /// Read the counters of the current database
+    pub async fn read(pool: &PgPool) -> Result<Self, String> {
+        sqlx::query_as(
+            r#"
+            SELECT
+                pg_database_size(datid) as database_size_bytes,
+                numbackends::bigint as connections,
+                xact_commit + xact_rollback as transactions,
+                blks_hit,
+                blks_read
+            FROM pg_stat_database
+            WHERE datname = current_database()
+            "#,
+        )
+        .fetch_one(pool)
+        .await
+        .map_err(|e| format!("Failed to sample database metrics: {}", e))
+    }
+}
+
+/// Build a sample from the counters, with rates relative to the previous
+/// counters when there are any (and the stats were not reset since)
+pub fn sample_from(
+    previous: Option<&(DateTime<Utc>, RawCounters)>,
+    at: DateTime<Utc>,
+    current: &RawCounters,
+) -> MetricSample {
+    let (hits, reads, tps) = match previous {
+        Some((previous_at, previous))
+            if current.transactions >= previous.transactions
+                && current.blks_hit >= previous.blks_hit
+                && current.blks_read >= previous.blks_read =>
+        {
+            let secs = (at - *previous_at).num_milliseconds() as f64 / 1000.0;
+            let tps =
+                (secs > 0.0).then(|| (current.transactions - previous.transactions) as f64 / secs);
+            (
+                current.blks_hit - previous.blks_hit,
+                current.blks_read - previous.blks_read,
+                tps,
+            )
+        }
+        _ => (current.blks_hit, current.blks_read, None),
+    };
+    let cache_hit_ratio = if hits + reads == 0 {
+        Some(100.0)
+    } else {
+        Some(hits as f64 / (hits + reads) as f64 * 100.0)
+    };
+
+    MetricSample {
+        at,
+        database_size_bytes: current.database_size_bytes,
+        connections: current.connections,
+        cache_hit_ratio,
+        tps,
+    }
+}
+
+/// Parse a range such as `30m`, `6h` or `7d`
+pub fn parse_range(range: &str) -> Result<Duration, String> {
+    let range = range.trim();
+    let invalid = || {
+        format!(
+            "Invalid range '{}'; use a number followed by s, m, h or d (e.g. 1h)",
+            range
+        )
+    };
+    let (split, _) = range.char_indices().last().ok_or_else(invalid)?;
+    let (amount, unit) = range.split_at(split);
+    let amount: u64 = amount.parse().map_err(|_| invalid())?;
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err(invalid()),
+    };
+    if amount == 0 {
+        return Err(invalid());
+    }
+    amount
+        .checked_mul(unit_secs)
+        .map(Duration::from_secs)
+        .ok_or_else(invalid)
+}
+
+/// One value of a metric's history
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetricPoint {
+    pub at: DateTime<Utc>,
+    pub value: f64,
+}
+
+/// Width of the buckets a series is downsampled into
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Bucket {
+    Minute,
+    Hour,
+}
+
+impl Bucket {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "minute" => Ok(Bucket::Minute),
+            "hour" => Ok(Bucket::Hour),
+            _ => Err(format!(
+                "Unknown bucket '{}'; expected minute or hour",
+                name
+            )),
+        }
+    }
+
+    pub fn secs(&self) -> i64 {
+        match self {
+            Bucket::Minute => 60,
+            Bucket::Hour => 3600,
+        }
+    }
+
+    /// Minutes for ranges up to 6 hours, hours beyond
+    pub fn for_range(range: Duration) -> Self {
+        if range <= Duration::from_secs(6 * 3600) {
+            Bucket::Minute
+        } else {
+            Bucket::Hour
+        }
+    }
+}
+
+/// Most buckets a series may have, to keep responses chartable
+pub const MAX_SERIES_BUCKETS: i64 = 1500;
+
+/// A metric downsampled into fixed buckets, as parallel arrays ready for
+/// charting; buckets without samples are `null` so the time axis stays even
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Series {
+    pub metric: Metric,
+    pub bucket: Bucket,
+    /// Start of each bucket
+    pub timestamps: Vec<DateTime<Utc>>,
+    pub avg: Vec<Option<f64>>,
+    pub min: Vec<Option<f64>>,
+    pub max: Vec<Option<f64>>,
+}
+
+impl Series {
+    /// Buckets that have samples
+    pub fn filled(&self) -> usize {
+        self.avg.iter().filter(|v| v.is_some()).count()
+    }
+}
+
+/// Downsample `points` into `bucket`-wide buckets covering `start..=end`,
+/// aligned to whole minutes or hours
+pub fn downsample(
+    points: &[MetricPoint],
+    metric: Metric,
+    bucket: Bucket,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Series {
+    let width = bucket.secs();
+    let first = start.timestamp().div_euclid(width) * width;
+    let count = ((end.timestamp().div_euclid(width) * width - first) / width + 1).max(0) as usize;
+
+    let mut sums = vec![(0.0, 0usize, f64::INFINITY, f64::NEG_INFINITY); count];
+    for point in points {
+        let offset = point.at.timestamp() - first;
+        if offset < 0 {
+            continue;
+        }
+        if let Some((sum, n, min, max)) = sums.get_mut((offset / width) as usize) {
+            *sum += point.value;
+            *n += 1;
+            *min = min.min(point.value);
+            *max = max.max(point.value);
+        }
+    }
+
+    let mut series = Series {
+        metric,
+        bucket,
+        timestamps: Vec::with_capacity(count),
+        avg: Vec::with_capacity(count),
+        min: Vec::with_capacity(count),
+        max: Vec::with_capacity(count),
+    };
+    for (i, (sum, n, min, max)) in sums.into_iter().enumerate() {
+        let filled = n > 0;
+        series
+            .timestamps
+            .push(DateTime::from_timestamp(first + i as i64 * width, 0).unwrap_or_default());
+        series.avg.push(filled.then(|| sum / n as f64));
+        series.min.push(filled.then_some(min));
+        series.max.push(filled.then_some(max));
+    }
+    series
+}
+
+struct HistoryState {
+    samples: VecDeque<MetricSample>,
+    last_counters: Option<(DateTime<Utc>, RawCounters)>,
+}
+
+/// Recorded samples, oldest first
+pub struct MetricsHistory {
+    state: Mutex<HistoryState>,
+    retention: Duration,
+    path: PathBuf,
+    new_samples: broadcast::Sender<MetricSample>,
+}
+
+impl MetricsHistory {
+    /// Load the history kept in `path`, dropping samples past `retention`
+    pub async fn load(path: &str, retention: Duration) -> Result<Self, String> {
+        let path = PathBuf::from(path);
+        let samples: Vec<MetricSample> = json_file::load(&path, "metrics history").await?;
+        let history = Self {
+            state: Mutex::new(HistoryState {
+                samples: samples.into(),
+                last_counters: None,
+            }),
+            retention,
+            path,
+            new_samples: broadcast::channel(16).0,
+        };
+        history.prune(&mut *history.state.lock().await, Utc::now());
+        Ok(history)
+    }
+
+    fn prune(&self, state: &mut HistoryState, now: DateTime<Utc>) {
+        let retention = chrono::Duration::from_std(self.retention).unwrap_or(chrono::Duration::MAX);
+        while state
+            .samples
+            .front()
+            .is_some_and(|sample| now - sample.at > retention)
+        {
+            state.samples.pop_front();
+        }
+    }
+
+    /// Record a sample from the counters read at `at`, and save the history
+    pub async fn record(&self, at: DateTime<Utc>, counters: RawCounters) -> MetricSample {
+        let (sample, snapshot) = {
+            let mut state = self.state.lock().await;
+            let sample = sample_from(state.last_counters.as_ref(), at, &counters);
+            state.last_counters = Some((at, counters));
+            state.samples.push_back(sample.clone());
+            self.prune(&mut state, at);
+            (sample, Vec::from(state.samples.clone()))
+        };
+        if let Err(e) = json_file::save(&self.path, &snapshot, "metrics history").await {
+            tracing::warn!("{}", e);
+        }
+        // Nobody listening is fine
+        let _ = self.new_samples.send(sample.clone());
+        sample
+    }
+
+    /// Receive each sample as it is recorded
+    pub fn subscribe(&self) -> broadcast::Receiver<MetricSample> {
+        self.new_samples.subscribe()
+    }
+
+    /// `metric` over the last `range`, downsampled into `bucket`s
+    pub async fn series(
+        &self,
+        metric: Metric,
+        range: Duration,
+        bucket: Bucket,
+    ) -> Result<Series, String> {
+        let buckets = range.as_secs() as i64 / bucket.secs();
+        if buckets > MAX_SERIES_BUCKETS {
+            return Err(format!(
+                "A {}s range has {} {} buckets (at most {}); use a shorter range or a wider bucket",
+                range.as_secs(),
+                buckets,
+                bucket.secs(),
+                MAX_SERIES_BUCKETS
+            ));
+        }
+        let end = Utc::now();
+        let start = end - chrono::Duration::from_std(range).unwrap_or(chrono::Duration::MAX);
+        let points = self.query(metric, range).await;
+        Ok(downsample(&points, metric, bucket, start, end))
+    }
+
+    /// Values of `metric` recorded within `range` of now, oldest first
+    pub async fn query(&self, metric: Metric, range: Duration) -> Vec<MetricPoint> {
+        let since = Utc::now() - chrono::Duration::from_std(range).unwrap_or(chrono::Duration::MAX);
+        self.state
+            .lock()
+            .await
+            .samples
+            .iter()
+            .filter(|sample| sample.at >= since)
+            .filter_map(|sample| {
+                metric.value(sample).map(|value| MetricPoint {
+                    at: sample.at,
+                    value,
+                })
+            })
+            .collect()
+    }
+
+    /// Sample `pool` every `interval`; failed samples (database down) are
+    /// skipped and leave a gap
+    pub fn spawn_sampler(self: &Arc<Self>, pool: Arc<PgPool>, interval: Duration) {
+        let history = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                match RawCounters::read(&pool).await {
+                    Ok(counters) => {
+                        history.record(Utc::now(), counters).await;
+                    }
+                    Err(e) => tracing::debug!("{}", e),
+                }
+            }
+        });
+    }
+}
+
+/// SVG polyline points for `points` scaled into a `width` x `height` box,
+/// with time on the x axis and the lowest value at the bottom
+pub fn sparkline_points(points: &[MetricPoint], width: f64, height: f64) -> String {
+    let (Some(first), Some(last)) = (points.first(), points.last()) else {
+        return String::new();
+    };
+    let span_ms = (last.at - first.at).num_milliseconds().max(1) as f64;
+    let min = points.iter().map(|p| p.value).fold(f64::INFINITY, f64::min);
+    let max = points
+        .iter()
+        .map(|p| p.value)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let value_span = if max > min { max - min } else { 1.0 };
+
+    points
+        .iter()
+        .map(|p| {
+            let x = (p.at - first.at).num_milliseconds() as f64 / span_ms * width;
+            let y = height - (p.value - min) / value_span * height;
+            format!("{:.1},{:.1}", x, y)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// SVG path data for a series scaled into a `width` x `height` box: the
+/// average as a line broken at empty buckets, and the min-max band around it
+pub fn chart_paths(series: &Series, width: f64, height: f64) -> (String, String) {
+    let values = || series.min.iter().chain(&series.max).flatten().copied();
+    let (Some(low), Some(high)) = (values().reduce(f64::min), values().reduce(f64::max)) else {
+        return (String::new(), String::new());
+    };
+    let span = if high > low { high - low } else { 1.0 };
+    let last = series.avg.len().saturating_sub(1).max(1) as f64;
+    let x = |i: usize| i as f64 / last * width;
+    let y = |v: f64| height - (v - low) / span * height;
+
+    let mut line = String::new();
+    let mut band = String::new();
+    let mut run: Vec<usize> = Vec::new();
+    // Indexes past the end close the final run
+    for i in 0..=series.avg.len() {
+        if let Some(Some(avg)) = series.avg.get(i) {
+            line.push_str(&format!(
+                "{}{:.1},{:.1} ",
+                if run.is_empty() { "M" } else { "L" },
+                x(i),
+                y(*avg)
+            ));
+            run.push(i);
+            continue;
+        }
+        if !run.is_empty() {
+            let upper = run.iter().map(|&j| (j, series.max[j]));
+            let lower = run.iter().rev().map(|&j| (j, series.min[j]));
+            for (k, (j, v)) in upper.chain(lower).enumerate() {
+                band.push_str(&format!(
+                    "{}{:.1},{:.1} ",
+                    if k == 0 { "M" } else { "L" },
+                    x(j),
+                    y(v.unwrap_or(low))
+                ));
+            }
+            band.push_str("Z ");
+            run.clear();
+        }
+    }
+    (line.trim_end().to_string(), band.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counters(transactions: i64, blks_hit: i64, blks_read: i64) -> RawCounters {
+        RawCounters {
+            database_size_bytes: 8192,
+            connections: 3,
+            transactions,
+            blks_hit,
+            blks_read,
+        }
+    }
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap()
+    }
+
+    #[test]
+    fn test_sample_rates() {
+        let first = sample_from(None, at(0), &counters(100, 90, 10));
+        assert_eq!(first.tps, None);
+        assert_eq!(first.cache_hit_ratio, Some(90.0));
+
+        let previous = (at(0), counters(100, 90, 10));
+        let next = sample_from(Some(&previous), at(10), &counters(150, 189, 11));
+        assert_eq!(next.tps, Some(5.0));
+        assert_eq!(next.cache_hit_ratio, Some(99.0));
+
+        // Counters going backwards mean a stats reset: no rate for this one
+        let reset = sample_from(Some(&previous), at(10), &counters(5, 0, 0));
+        assert_eq!(reset.tps, None);
+        assert_eq!(reset.cache_hit_ratio, Some(100.0));
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_range("30m").unwrap(), Duration::from_secs(1800));
+        assert_eq!(parse_range("6h").unwrap(), Duration::from_secs(21600));
+        assert_eq!(parse_range("7d").unwrap(), Duration::from_secs(604800));
+        for invalid in [
+            "",
+            "h",
+            "0h",
+            "1w",
+            "-1h",
+            "1.5h",
+            "1é",
+            "99999999999999999999d",
+        ] {
+            assert!(parse_range(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_metric_names() {
+        for metric in Metric::ALL {
+            assert_eq!(Metric::parse(metric.name()).unwrap(), metric);
+        }
+        assert!(Metric::parse("uptime").is_err());
+        assert_eq!(Metric::DatabaseSize.format(1536.0), "1.5 kB");
+        assert_eq!(Metric::DatabaseSize.format(512.0), "512 B");
+        assert_eq!(Metric::CacheHitRatio.format(99.5), "99.50%");
+    }
+
+    #[tokio::test]
+    async fn test_record_query_and_reload() {
+        let dir = std::env::temp_dir().join(format!("metrics-history-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("history.json");
+        let path = path.to_str().unwrap();
+
+        let history = MetricsHistory::load(path, Duration::from_secs(3600))
+            .await
+            .unwrap();
+        let now = Utc::now();
+        // Older than the retention period: pruned on the next record
+        history
+            .record(now - chrono::Duration::hours(2), counters(0, 0, 0))
+            .await;
+        history
+            .record(now - chrono::Duration::seconds(20), counters(100, 10, 0))
+            .await;
+        history.record(now, counters(300, 20, 0)).await;
+
+        let tps = history.query(Metric::Tps, Duration::from_secs(10)).await;
+        assert_eq!(tps.len(), 1);
+        assert_eq!(tps[0].value, 10.0);
+        let connections = history
+            .query(Metric::Connections, Duration::from_secs(10))
+            .await;
+        assert_eq!(connections.len(), 1);
+
+        // The history survives a restart
+        let reloaded = MetricsHistory::load(path, Duration::from_secs(3600))
+            .await
+            .unwrap();
+        assert_eq!(
+            reloaded
+                .query(Metric::Connections, Duration::from_secs(3600))
+                .await
+                .len(),
+            2
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_downsample() {
+        let point = |secs, value| MetricPoint {
+            at: at(secs),
+            value,
+        };
+        // at(0) is 20s past a whole minute
+        let points = vec![
+            point(0, 1.0),
+            point(30, 3.0),
+            point(50, 5.0),
+            point(200, 7.0),
+        ];
+        let series = downsample(&points, Metric::Tps, Bucket::Minute, at(0), at(200));
+        assert_eq!(series.timestamps.len(), 4);
+        assert_eq!(series.timestamps[0], at(-20));
+        assert_eq!(series.avg, vec![Some(2.0), Some(5.0), None, Some(7.0)]);
+        assert_eq!(series.min, vec![Some(1.0), Some(5.0), None, Some(7.0)]);
+        assert_eq!(series.max, vec![Some(3.0), Some(5.0), None, Some(7.0)]);
+        assert_eq!(series.filled(), 3);
+
+        let hourly = downsample(&points, Metric::Tps, Bucket::Hour, at(0), at(200));
+        assert_eq!(hourly.avg, vec![Some(4.0)]);
+    }
+
+    #[test]
+    fn test_chart_paths() {
+        let series = Series {
+            metric: Metric::Tps,
+            bucket: Bucket::Minute,
+            timestamps: (0..3).map(|i| at(i * 60)).collect(),
+            avg: vec![Some(1.0), None, Some(3.0)],
+            min: vec![Some(0.0), None, Some(2.0)],
+            max: vec![Some(2.0), None, Some(4.0)],
+        };
+        let (line, band) = chart_paths(&series, 100.0, 40.0);
+        // The gap splits the line into two segments
+        assert_eq!(line, "M0.0,30.0 M100.0,10.0");
+        assert_eq!(band, "M0.0,20.0 L0.0,40.0 Z M100.0,0.0 L100.0,20.0 Z");
+
+        let empty = Series {
+            avg: vec![None],
+            min: vec![None],
+            max: vec![None],
+            ..series
+        };
+        assert_eq!(
+            chart_paths(&empty, 100.0, 40.0),
+            (String::new(), String::new())
+        );
+    }
+
+    #[test]
+    fn test_bucket_for_range() {
+        assert_eq!(Bucket::parse("minute").unwrap(), Bucket::Minute);
+        assert!(Bucket::parse("day").is_err());
+        assert_eq!(Bucket::for_range(Duration::from_secs(3600)), Bucket::Minute);
+        assert_eq!(
+            Bucket::for_range(Duration::from_secs(7 * 86400)),
+            Bucket::Hour
+        );
+    }
+
+    #[tokio::test]
+    async fn test_series_bucket_limit_and_broadcast() {
+        let dir = std::env::temp_dir().join(format!("metrics-series-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("history.json");
+        let history = MetricsHistory::load(path.to_str().unwrap(), Duration::from_secs(3600))
+            .await
+            .unwrap();
+
+        assert!(history
+            .series(Metric::Tps, Duration::from_secs(7 * 86400), Bucket::Minute)
+            .await
+            .is_err());
+
+        let mut samples = history.subscribe();
+        history.record(Utc::now(), counters(1, 1, 1)).await;
+        assert_eq!(samples.recv().await.unwrap().connections, 3);
+
+        let series = history
+            .series(
+                Metric::Connections,
+                Duration::from_secs(600),
+                Bucket::Minute,
+            )
+            .await
+            .unwrap();
+        assert_eq!(series.filled(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_sparkline_points() {
+        let points = vec![
+            MetricPoint {
+                at: at(0),
+                value: 1.0,
+            },
+            MetricPoint {
+                at: at(5),
+                value: 3.0,
+            },
+            MetricPoint {
+                at: at(10),
+                value: 2.0,
+            },
+        ];
+        assert_eq!(
+            sparkline_points(&points, 100.0, 20.0),
+            "0.0,20.0 50.0,0.0 100.0,10.0"
+        );
+        assert_eq!(sparkline_points(&[], 100.0, 20.0), "");
+        // A flat line sits at the bottom
+        assert_eq!(sparkline_points(&points[..1], 100.0, 20.0), "0.0,20.0");
+    }
+}
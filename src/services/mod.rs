@@ -1,13 +1,55 @@
+pub mod artifact_service;
 pub mod audit_service;
+pub mod audit_sink;
+pub mod backup_service;
 pub mod cell_service;
+pub mod chart_service;
+pub mod connection_service;
+pub mod cron;
+pub mod csv_import_service;
+pub mod data_diff_service;
 pub mod database_service;
 /// Services module
 ///
 /// Contains the business logic layer for database operations and application services
 pub mod db_service;
+pub mod ddl_service;
+pub mod definitions_service;
+pub mod demo_service;
+pub mod diff_service;
+pub mod duplicate_service;
+pub mod erd_service;
 pub mod export_service;
+pub mod fdw_service;
+pub mod identifier;
+pub mod json_file;
+pub mod maintenance_service;
+pub mod metadata_cache;
+pub mod metrics_history;
+pub mod notify;
+pub mod object_store;
+pub mod partition_service;
+pub mod pg_value;
+pub mod pivot_service;
+pub mod plan_history;
+pub mod preferences_service;
+pub mod privileges_service;
+pub mod profile_service;
 pub mod query_history;
 pub mod query_service;
+pub mod quota_service;
+pub mod replication_service;
+pub mod restore_service;
+pub mod result_store;
+pub mod saved_query_service;
+pub mod scheduler_service;
 pub mod schema_ops_service;
 pub mod schema_service;
+pub mod session_service;
+pub mod settings_service;
+pub mod sql_format;
+pub mod sql_lexer;
 pub mod stats_service;
+pub mod table_tail;
+pub mod transaction_service;
+pub mod webhook_service;
@@ -0,0 +1,349 @@
+use crate::tls;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::{Method, Request, StatusCode, Uri};
+use hyper_util::rt::TokioIo;
+/// Outbound Notifications
+///
+/// Minimal clients for pushing data out of pgAdmin-rs:
+/// - `post`: `POST` to an `http://` or `https://` endpoint
+/// - `post_json`: JSON `POST` that must be answered with a 2xx
+/// - `send_email`: plain-text mail through an SMTP relay
+///
+/// Mail does not speak TLS; point it at a local relay (postfix, an SMTP
+/// sidecar) when the final destination needs it.
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+/// Per-request timeout for webhooks and mail delivery
+pub const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Parse a webhook URL, accepting only `http://` URLs
+pub fn webhook_url(url: &str) -> Result<Uri, String> {
+    let uri: Uri = url
+        .parse()
+        .map_err(|e| format!("Invalid webhook URL '{}': {}", url, e))?;
+    if uri.scheme_str() != Some("http") || uri.host().is_none() {
+        return Err(format!(
+            "Invalid webhook URL '{}': expected http://host[:port]/path",
+            url
+        ));
+    }
+    Ok(uri)
+}
+
+/// Parse an endpoint URL, accepting `http://` and `https://` URLs
+pub fn endpoint_url(url: &str) -> Result<Uri, String> {
+    let uri: Uri = url
+        .parse()
+        .map_err(|e| format!("Invalid URL '{}': {}", url, e))?;
+    if !matches!(uri.scheme_str(), Some("http" | "https")) || uri.host().is_none() {
+        return Err(format!(
+            "Invalid URL '{}': expected http(s)://host[:port]/path",
+            url
+        ));
+    }
+    Ok(uri)
+}
+
+/// Client config for `https://` endpoints, built once
+static TLS_CONFIG: LazyLock<Arc<rustls::ClientConfig>> = LazyLock::new(tls::client_config);
+
+/// POST a JSON body with extra headers and return the response status
+pub async fn post(
+    url: &Uri,
+    headers: &[(&str, String)],
+    body: Vec<u8>,
+) -> Result<StatusCode, String> {
+    let host = url.host().unwrap_or_default();
+    let https = url.scheme_str() == Some("https");
+    let port = url.port_u16().unwrap_or(if https { 443 } else { 80 });
+
+    let stream = TcpStream::connect((host, port))
+        .await
+        .map_err(|e| format!("Failed to connect to {}: {}", url, e))?;
+    let stream = if https {
+        tls::Connection::Tls(Box::new(
+            tls::connect(TLS_CONFIG.clone(), host, stream).await?,
+        ))
+    } else {
+        tls::Connection::Plain(stream)
+    };
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .map_err(|e| format!("Failed to connect to {}: {}", url, e))?;
+    tokio::spawn(connection);
+
+    let authority = url.authority().map(|a| a.as_str()).unwrap_or(host);
+    let path = url.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    let mut request = Request::builder()
+        .method(Method::POST)
+        .uri(path)
+        .header("Host", authority)
+        .header("Content-Type", "application/json")
+        .header("User-Agent", "pgadmin-rs");
+    for (name, value) in headers {
+        request = request.header(*name, value);
+    }
+    let request = request
+        .body(Full::new(Bytes::from(body)))
+        .map_err(|e| format!("Failed to build webhook request: {}", e))?;
+
+    let response = sender
+        .send_request(request)
+        .await
+        .map_err(|e| format!("Failed to post to {}: {}", url, e))?;
+
+    let status = response.status();
+    // Drain the body so the connection closes cleanly
+    let _ = response.into_body().collect().await;
+    Ok(status)
+}
+
+/// POST a JSON body and require a 2xx response
+pub async fn post_json(url: &Uri, body: Vec<u8>) -> Result<(), String> {
+    let status = post(url, &[], body).await?;
+    if status.is_success() {
+        Ok(())
+    } else {
+        Err(format!("Webhook {} responded with {}", url, status))
+    }
+}
+
+/// SMTP relay used for email delivery
+#[derive(Debug, Clone, PartialEq)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub from: String,
+}
+
+/// A plain-text email
+#[derive(Debug, Clone, PartialEq)]
+pub struct Email {
+    pub to: Vec<String>,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Check that an address is usable in SMTP commands and headers
+pub fn validate_email_address(address: &str) -> Result<(), String> {
+    let valid = address
+        .split_once('@')
+        .is_some_and(|(local, domain)| !local.is_empty() && !domain.is_empty())
+        && !address
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || matches!(c, '<' | '>' | ','));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid email address '{}'", address))
+    }
+}
+
+/// Deliver an email through the relay
+pub async fn send_email(config: &SmtpConfig, email: &Email) -> Result<(), String> {
+    for address in email.to.iter().chain(std::iter::once(&config.from)) {
+        validate_email_address(address)?;
+    }
+
+    let stream = TcpStream::connect((config.host.as_str(), config.port))
+        .await
+        .map_err(|e| {
+            format!(
+                "Failed to connect to SMTP {}:{}: {}",
+                config.host, config.port, e
+            )
+        })?;
+    let (read, mut write) = stream.into_split();
+    let mut reader = BufReader::new(read);
+
+    expect_reply(&mut reader, 220).await?;
+    command(&mut write, &mut reader, "EHLO pgadmin-rs", 250).await?;
+    command(
+        &mut write,
+        &mut reader,
+        &format!("MAIL FROM:<{}>", config.from),
+        250,
+    )
+    .await?;
+    for to in &email.to {
+        command(&mut write, &mut reader, &format!("RCPT TO:<{}>", to), 250).await?;
+    }
+    command(&mut write, &mut reader, "DATA", 354).await?;
+
+    let message = format_message(&config.from, email);
+    write
+        .write_all(message.as_bytes())
+        .await
+        .map_err(|e| format!("SMTP write failed: {}", e))?;
+    command(&mut write, &mut reader, ".", 250).await?;
+    let _ = command(&mut write, &mut reader, "QUIT", 221).await;
+    Ok(())
+}
+
+/// Build the message headers and dot-stuffed body, ending with CRLF
+fn format_message(from: &str, email: &Email) -> String {
+    // Header values must stay on one line
+    let subject: String = email
+        .subject
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+
+    let mut message = format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n",
+        from,
+        email.to.join(", "),
+        subject,
+        chrono::Utc::now().to_rfc2822()
+    );
+    for line in email.body.lines() {
+        if line.starts_with('.') {
+            message.push('.');
+        }
+        message.push_str(line);
+        message.push_str("\r\n");
+    }
+    message
+}
+
+async fn command<W, R>(
+    writer: &mut W,
+    reader: &mut R,
+    line: &str,
+    expected: u16,
+) -> Result<(), String>
+where
+    W: AsyncWriteExt + Unpin,
+    R: AsyncBufReadExt + Unpin,
+{
+    writer
+        .write_all(format!("{}\r\n", line).as_bytes())
+        .await
+        .map_err(|e| format!("SMTP write failed: {}", e))?;
+    expect_reply(reader, expected).await
+}
+
+/// Read a (possibly multi-line) reply and check its status code
+async fn expect_reply<R: AsyncBufReadExt + Unpin>(
+    reader: &mut R,
+    expected: u16,
+) -> Result<(), String> {
+    loop {
+        let mut line = String::new();
+        let read = reader
+            .read_line(&mut line)
+            .await
+            .map_err(|e| format!("SMTP read failed: {}", e))?;
+        if read == 0 {
+            return Err("SMTP server closed the connection".to_string());
+        }
+
+        let code: u16 = line
+            .get(..3)
+            .and_then(|c| c.parse().ok())
+            .ok_or_else(|| format!("Malformed SMTP reply: {}", line.trim_end()))?;
+        // "250-..." continues a multi-line reply, "250 ..." ends it
+        if line.as_bytes().get(3) == Some(&b'-') {
+            continue;
+        }
+        if code != expected {
+            return Err(format!("SMTP server replied: {}", line.trim_end()));
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_webhook_url_validation() {
+        assert!(webhook_url("http://collector:8080/hook").is_ok());
+        assert!(webhook_url("https://collector/hook").is_err());
+        assert!(webhook_url("collector:8080").is_err());
+
+        assert!(endpoint_url("https://hooks.example.com/services/T0/B0").is_ok());
+        assert!(endpoint_url("http://collector:8080/hook").is_ok());
+        assert!(endpoint_url("ftp://collector/hook").is_err());
+    }
+
+    #[test]
+    fn test_validate_email_address() {
+        assert!(validate_email_address("ops@example.com").is_ok());
+        assert!(validate_email_address("ops").is_err());
+        assert!(validate_email_address("@example.com").is_err());
+        assert!(validate_email_address("a@b.com>\r\nRCPT TO:<x@y.com").is_err());
+    }
+
+    #[test]
+    fn test_format_message() {
+        let email = Email {
+            to: vec!["ops@example.com".to_string()],
+            subject: "Nightly\r\nBcc: x@y.com".to_string(),
+            body: "line one\n.hidden\nend".to_string(),
+        };
+        let message = format_message("pgadmin@example.com", &email);
+        assert!(message.contains("Subject: Nightly  Bcc: x@y.com\r\n"));
+        assert!(message.ends_with("\r\n\r\nline one\r\n..hidden\r\nend\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_send_email() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read, mut write) = stream.into_split();
+            let mut reader = BufReader::new(read);
+            let mut transcript = String::new();
+
+            write.write_all(b"220 test ESMTP\r\n").await.unwrap();
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).await.unwrap() == 0 {
+                    break;
+                }
+                transcript.push_str(&line);
+                let reply: &[u8] = if line.starts_with("EHLO") {
+                    b"250-test\r\n250 OK\r\n"
+                } else if line.starts_with("DATA") {
+                    b"354 go ahead\r\n"
+                } else if line.starts_with("QUIT") {
+                    write.write_all(b"221 bye\r\n").await.unwrap();
+                    break;
+                } else if line == ".\r\n" || line.starts_with("MAIL") || line.starts_with("RCPT") {
+                    b"250 OK\r\n"
+                } else {
+                    continue; // message content
+                };
+                write.write_all(reply).await.unwrap();
+            }
+            transcript
+        });
+
+        let config = SmtpConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            from: "pgadmin@example.com".to_string(),
+        };
+        let email = Email {
+            to: vec!["ops@example.com".to_string()],
+            subject: "Nightly check".to_string(),
+            body: "3 rows".to_string(),
+        };
+        send_email(&config, &email).await.unwrap();
+
+        let transcript = server.await.unwrap();
+        assert!(transcript.contains("MAIL FROM:<pgadmin@example.com>\r\n"));
+        assert!(transcript.contains("RCPT TO:<ops@example.com>\r\n"));
+        assert!(transcript.contains("Subject: Nightly check\r\n"));
+        assert!(transcript.contains("\r\n3 rows\r\n.\r\n"));
+    }
+}
@@ -0,0 +1,1077 @@
+use crate::services::identifier::{quote_ident, quote_qualified};
+use crate::services::schema_ops_service::{CreateTableRequest, SchemaOpsService};
+use chrono::{Datelike, Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+/// Partitioning Service
+///
+/// Plans the DDL for range-partitioned tables on a single date, timestamp or
+/// integer column, split by a fixed interval:
+/// - Creating a new partitioned table with its first partitions
+/// - Converting an existing table: it is renamed to `<table>_unpartitioned`,
+///   a partitioned copy takes its name and the rows are copied over
+/// - Pre-creating future partitions of a partitioned table
+///
+/// Each operation first returns a plan (partitions, statements and notes
+/// about anything not carried over), which is applied in one transaction once
+/// the client confirms it.
+use sqlx::PgPool;
+
+/// Most partitions one plan may create
+pub const MAX_PARTITIONS: usize = 1000;
+
+/// Partitions created past the current one unless asked otherwise
+pub const DEFAULT_PREMAKE: u32 = 3;
+
+/// Suffix of the original table's new name after a conversion
+const UNPARTITIONED_SUFFIX: &str = "unpartitioned";
+
+fn default_premake() -> u32 {
+    DEFAULT_PREMAKE
+}
+
+/// Create a new range-partitioned table
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreatePartitionedRequest {
+    #[serde(flatten)]
+    pub table: CreateTableRequest,
+    #[serde(flatten)]
+    pub partitioning: PartitionOptions,
+    /// Apply the plan; without it only the plan is returned
+    #[serde(default)]
+    pub confirm: bool,
+}
+
+/// Convert an existing table into a range-partitioned one
+#[derive(Debug, Clone, Deserialize)]
+pub struct ConvertRequest {
+    pub schema: String,
+    pub table_name: String,
+    #[serde(flatten)]
+    pub partitioning: PartitionOptions,
+    #[serde(default)]
+    pub confirm: bool,
+}
+
+/// Create the next partitions of a range-partitioned table
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExtendRequest {
+    pub schema: String,
+    pub table_name: String,
+    /// Size of each new partition, as for `PartitionOptions::interval`
+    pub interval: String,
+    #[serde(default = "default_premake")]
+    pub premake: u32,
+    #[serde(default)]
+    pub confirm: bool,
+}
+
+/// How to partition a table
+#[derive(Debug, Clone, Deserialize)]
+pub struct PartitionOptions {
+    /// Column to partition by: a date, timestamp or integer column
+    pub partition_key: String,
+    /// `day`, `week`, `month`, `quarter` or `year` for date and timestamp
+    /// keys, or a positive number for integer keys
+    pub interval: String,
+    /// Lower bound of the first partition (a date or an integer); defaults to
+    /// the current period, or the smallest key when converting
+    pub start: Option<String>,
+    /// Partitions to create past the current period (date keys) or the
+    /// partition holding the largest key (integer keys)
+    #[serde(default = "default_premake")]
+    pub premake: u32,
+    /// Also create a `<table>_default` partition for rows outside every range
+    #[serde(default)]
+    pub default_partition: bool,
+}
+
+/// The DDL an operation would run
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct PartitionPlan {
+    pub schema: String,
+    pub table_name: String,
+    pub partition_key: String,
+    pub partitions: Vec<PartitionRange>,
+    /// Statements run in order in one transaction
+    pub statements: Vec<String>,
+    /// Things to check or finish by hand
+    pub notes: Vec<String>,
+}
+
+/// A partition covering `from` (inclusive) to `to` (exclusive)
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct PartitionRange {
+    pub name: String,
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntervalUnit {
+    Day,
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+/// The span of each partition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionInterval {
+    Time(IntervalUnit),
+    Integer(i64),
+}
+
+/// A partition bound
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Bound {
+    Date(NaiveDate),
+    Integer(i64),
+}
+
+impl PartitionInterval {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let unit = match s.trim().to_lowercase().as_str() {
+            "day" | "daily" => IntervalUnit::Day,
+            "week" | "weekly" => IntervalUnit::Week,
+            "month" | "monthly" => IntervalUnit::Month,
+            "quarter" | "quarterly" => IntervalUnit::Quarter,
+            "year" | "yearly" => IntervalUnit::Year,
+            other => {
+                return match other.parse::<i64>() {
+                    Ok(step) if step > 0 => Ok(PartitionInterval::Integer(step)),
+                    _ => Err(format!(
+                        "Unknown interval: {}. Use day, week, month, quarter, year or a positive number",
+                        s
+                    )),
+                }
+            }
+        };
+        Ok(PartitionInterval::Time(unit))
+    }
+
+    /// Check that the interval suits a key of type `data_type`
+    fn check_key(&self, key: &str, data_type: &str) -> Result<(), String> {
+        let data_type = data_type.trim().to_lowercase();
+        let is_time = data_type == "date" || data_type.starts_with("timestamp");
+        let is_integer = matches!(
+            data_type.as_str(),
+            "smallint" | "integer" | "int" | "bigint" | "int2" | "int4" | "int8"
+        );
+        match self {
+            PartitionInterval::Time(_) if is_time => Ok(()),
+            PartitionInterval::Integer(_) if is_integer => Ok(()),
+            PartitionInterval::Time(_) if is_integer => Err(format!(
+                "{} is an integer column; give the interval as a number",
+                key
+            )),
+            PartitionInterval::Integer(_) if is_time => Err(format!(
+                "{} is a {} column; give the interval as day, week, month, quarter or year",
+                key, data_type
+            )),
+            _ => Err(format!(
+                "Cannot partition by {}: {} is not a date, timestamp or integer type",
+                key, data_type
+            )),
+        }
+    }
+
+    /// Read a bound, e.g. `start` or a value of the key column
+    fn parse_bound(&self, value: &str) -> Result<Bound, String> {
+        let value = value.trim().trim_matches('\'');
+        match self {
+            PartitionInterval::Time(_) => value
+                .get(..10)
+                .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+                .map(Bound::Date)
+                .ok_or_else(|| format!("Expected a date like 2024-01-01, got {}", value)),
+            PartitionInterval::Integer(_) => value
+                .parse()
+                .map(Bound::Integer)
+                .map_err(|_| format!("Expected an integer, got {}", value)),
+        }
+    }
+
+    /// The start of the partition holding `bound`
+    fn floor(&self, bound: Bound) -> Bound {
+        match (self, bound) {
+            (PartitionInterval::Time(unit), Bound::Date(date)) => Bound::Date(match unit {
+                IntervalUnit::Day => date,
+                IntervalUnit::Week => {
+                    date - chrono::Days::new(date.weekday().num_days_from_monday() as u64)
+                }
+                IntervalUnit::Month => date.with_day(1).unwrap_or(date),
+                IntervalUnit::Quarter => {
+                    NaiveDate::from_ymd_opt(date.year(), (date.month0() / 3) * 3 + 1, 1)
+                        .unwrap_or(date)
+                }
+                IntervalUnit::Year => NaiveDate::from_ymd_opt(date.year(), 1, 1).unwrap_or(date),
+            }),
+            (PartitionInterval::Integer(step), Bound::Integer(n)) => {
+                Bound::Integer(n.div_euclid(*step) * step)
+            }
+            (_, bound) => bound,
+        }
+    }
+
+    /// The bound one interval after `bound`
+    fn next(&self, bound: Bound) -> Option<Bound> {
+        match (self, bound) {
+            (PartitionInterval::Time(unit), Bound::Date(date)) => match unit {
+                IntervalUnit::Day => date.checked_add_days(chrono::Days::new(1)),
+                IntervalUnit::Week => date.checked_add_days(chrono::Days::new(7)),
+                IntervalUnit::Month => date.checked_add_months(Months::new(1)),
+                IntervalUnit::Quarter => date.checked_add_months(Months::new(3)),
+                IntervalUnit::Year => date.checked_add_months(Months::new(12)),
+            }
+            .map(Bound::Date),
+            (PartitionInterval::Integer(step), Bound::Integer(n)) => {
+                n.checked_add(*step).map(Bound::Integer)
+            }
+            _ => None,
+        }
+    }
+
+    /// `count` intervals after `bound`
+    fn advance(&self, bound: Bound, count: u32) -> Result<Bound, String> {
+        (0..count).try_fold(bound, |b, _| {
+            self.next(b)
+                .ok_or_else(|| "Partition bounds out of range".to_string())
+        })
+    }
+
+    /// Partition name suffix for the partition starting at `from`
+    fn suffix(&self, from: Bound) -> String {
+        match (self, from) {
+            (PartitionInterval::Time(unit), Bound::Date(date)) => match unit {
+                IntervalUnit::Day | IntervalUnit::Week => date.format("p%Y%m%d").to_string(),
+                IntervalUnit::Month => date.format("p%Y%m").to_string(),
+                IntervalUnit::Quarter => format!("p{}q{}", date.year(), date.month0() / 3 + 1),
+                IntervalUnit::Year => date.format("p%Y").to_string(),
+            },
+            (_, Bound::Integer(n)) if n < 0 => format!("pm{}", n.unsigned_abs()),
+            (_, Bound::Integer(n)) => format!("p{}", n),
+            (_, Bound::Date(date)) => date.format("p%Y%m%d").to_string(),
+        }
+    }
+}
+
+impl Bound {
+    fn display(&self) -> String {
+        match self {
+            Bound::Date(date) => date.to_string(),
+            Bound::Integer(n) => n.to_string(),
+        }
+    }
+}
+
+/// `<table>_<suffix>`, shortening the table part to fit the 63-byte name limit
+fn child_name(table: &str, suffix: &str) -> String {
+    let mut base = table.to_string();
+    while base.len() + 1 + suffix.len() > 63 {
+        base.pop();
+    }
+    format!("{}_{}", base, suffix)
+}
+
+/// Partitions of `interval` from `from` until they cover everything before `until`
+fn ranges(
+    table: &str,
+    interval: PartitionInterval,
+    from: Bound,
+    until: Bound,
+) -> Result<Vec<PartitionRange>, String> {
+    let mut ranges = Vec::new();
+    let mut lower = from;
+    while lower < until {
+        if ranges.len() == MAX_PARTITIONS {
+            return Err(format!(
+                "This would create more than {} partitions; use a larger interval or a later start",
+                MAX_PARTITIONS
+            ));
+        }
+        let upper = interval
+            .next(lower)
+            .ok_or_else(|| "Partition bounds out of range".to_string())?;
+        ranges.push(PartitionRange {
+            name: child_name(table, &interval.suffix(lower)),
+            from: lower.display(),
+            to: upper.display(),
+        });
+        lower = upper;
+    }
+    Ok(ranges)
+}
+
+/// Bounds from the first partition through `premake` partitions past `current`
+fn span(
+    interval: PartitionInterval,
+    first: Bound,
+    current: Bound,
+    premake: u32,
+) -> Result<(Bound, Bound), String> {
+    let current = interval.floor(current.max(first));
+    Ok((first, interval.advance(current, premake.saturating_add(1))?))
+}
+
+fn partition_statements(schema: &str, table: &str, ranges: &[PartitionRange]) -> Vec<String> {
+    let quote = |bound: &str| match bound.parse::<i64>() {
+        Ok(n) => n.to_string(),
+        Err(_) => format!("'{}'", bound),
+    };
+    ranges
+        .iter()
+        .map(|range| {
+            format!(
+                "CREATE TABLE {} PARTITION OF {} FOR VALUES FROM ({}) TO ({})",
+                quote_qualified(schema, &range.name),
+                quote_qualified(schema, table),
+                quote(&range.from),
+                quote(&range.to)
+            )
+        })
+        .collect()
+}
+
+fn default_partition_statement(schema: &str, table: &str) -> String {
+    format!(
+        "CREATE TABLE {} PARTITION OF {} DEFAULT",
+        quote_qualified(schema, &child_name(table, "default")),
+        quote_qualified(schema, table)
+    )
+}
+
+/// Plan a new partitioned table, partitioned from `start` (or the current
+/// period) through `premake` periods after `today`
+pub fn plan_create(
+    request: &CreatePartitionedRequest,
+    today: NaiveDate,
+) -> Result<PartitionPlan, String> {
+    let table = &request.table;
+    let options = &request.partitioning;
+    let interval = PartitionInterval::parse(&options.interval)?;
+    let key = &options.partition_key;
+    let column = table
+        .columns
+        .iter()
+        .find(|c| &c.name == key)
+        .ok_or_else(|| format!("Partition key {} is not one of the columns", key))?;
+    interval.check_key(key, &column.data_type)?;
+
+    // Postgres enforces uniqueness per partition, so it needs the key in every unique constraint
+    let single = table
+        .columns
+        .iter()
+        .filter(|c| (c.primary_key || c.unique) && &c.name != key)
+        .map(|c| c.name.clone());
+    let composite = std::iter::once(&table.primary_key)
+        .filter(|columns| !columns.is_empty())
+        .chain(&table.unique)
+        .filter(|columns| !columns.contains(key))
+        .map(|columns| columns.join(", "));
+    if let Some(columns) = single.chain(composite).next() {
+        return Err(format!(
+            "The primary key and unique constraints must include the partition key {}; ({}) does not",
+            key, columns
+        ));
+    }
+
+    let mut create = SchemaOpsService::create_table_sql(table)?;
+    create = create.replacen("CREATE TABLE IF NOT EXISTS", "CREATE TABLE", 1);
+    create.push_str(&format!(" PARTITION BY RANGE ({})", quote_ident(key)));
+
+    let first = match &options.start {
+        Some(start) => interval.parse_bound(start)?,
+        None if matches!(interval, PartitionInterval::Time(_)) => Bound::Date(today),
+        None => Bound::Integer(0),
+    };
+    let first = interval.floor(first);
+    let current = match interval {
+        PartitionInterval::Time(_) => Bound::Date(today),
+        PartitionInterval::Integer(_) => first,
+    };
+    let (from, until) = span(interval, first, current, options.premake)?;
+    let partitions = ranges(&table.table_name, interval, from, until)?;
+
+    let mut statements = vec![create];
+    statements.extend(partition_statements(
+        &table.schema,
+        &table.table_name,
+        &partitions,
+    ));
+    if options.default_partition {
+        statements.push(default_partition_statement(
+            &table.schema,
+            &table.table_name,
+        ));
+    }
+    let mut notes = Vec::new();
+    if !options.default_partition {
+        notes.push(format!(
+            "Rows with {} from {} on are rejected until more partitions are created",
+            key,
+            partitions.last().map(|p| p.to.as_str()).unwrap_or_default()
+        ));
+    }
+
+    Ok(PartitionPlan {
+        schema: table.schema.clone(),
+        table_name: table.table_name.clone(),
+        partition_key: key.clone(),
+        partitions,
+        statements,
+        notes,
+    })
+}
+
+/// What the catalog says about a table about to be converted
+#[derive(Debug, Clone, Default)]
+pub struct ConvertSource {
+    /// Type of the key column
+    pub key_type: String,
+    /// Smallest and largest key in the table, as text
+    pub min_key: Option<String>,
+    pub max_key: Option<String>,
+    /// Columns that can be copied (all but generated ones)
+    pub columns: Vec<String>,
+    pub identity_columns: Vec<String>,
+    /// `(column, sequence)` pairs of serial columns
+    pub owned_sequences: Vec<(String, String)>,
+    /// Unique indexes without the key, which a partitioned table cannot have
+    pub unique_without_key: Vec<String>,
+    /// Foreign keys in other tables that reference this one
+    pub referencing: Vec<String>,
+}
+
+/// Plan converting a table, covering its rows and `premake` periods after
+/// `today` (or after the largest integer key)
+pub fn plan_convert(
+    request: &ConvertRequest,
+    source: &ConvertSource,
+    today: NaiveDate,
+) -> Result<PartitionPlan, String> {
+    let options = &request.partitioning;
+    let interval = PartitionInterval::parse(&options.interval)?;
+    let key = &options.partition_key;
+    interval.check_key(key, &source.key_type)?;
+    let (schema, table) = (&request.schema, &request.table_name);
+
+    let min = source
+        .min_key
+        .as_deref()
+        .map(|v| interval.parse_bound(v))
+        .transpose()?;
+    let max = source
+        .max_key
+        .as_deref()
+        .map(|v| interval.parse_bound(v))
+        .transpose()?;
+    let current = match interval {
+        PartitionInterval::Time(_) => Bound::Date(today),
+        PartitionInterval::Integer(_) => max.unwrap_or(Bound::Integer(0)),
+    };
+    let first = match &options.start {
+        Some(start) => interval.parse_bound(start)?,
+        None => min.unwrap_or(current),
+    };
+    let first = interval.floor(first);
+    let current = max.map_or(current, |max| current.max(max));
+    let (from, until) = span(interval, first, current, options.premake)?;
+    let partitions = ranges(table, interval, from, until)?;
+
+    let old = child_name(table, UNPARTITIONED_SUFFIX);
+    let mut notes = vec![format!(
+        "The original table is kept as {}.{}; drop it once you have checked the data",
+        schema, old
+    )];
+    if min.is_some_and(|min| min < from) && !options.default_partition {
+        return Err(format!(
+            "Rows with {} before {} would have no partition; set an earlier start or add a default partition",
+            key,
+            from.display()
+        ));
+    }
+    let mut including = String::from(
+        "INCLUDING DEFAULTS INCLUDING CONSTRAINTS INCLUDING GENERATED INCLUDING IDENTITY INCLUDING STORAGE INCLUDING COMMENTS",
+    );
+    if source.unique_without_key.is_empty() {
+        including.push_str(" INCLUDING INDEXES");
+    } else {
+        notes.push(format!(
+            "Indexes are not copied because these unique indexes do not include {}: {}",
+            key,
+            source.unique_without_key.join(", ")
+        ));
+    }
+    if !source.referencing.is_empty() {
+        notes.push(format!(
+            "These foreign keys still reference {}: {}",
+            old,
+            source.referencing.join(", ")
+        ));
+    }
+
+    let qualified = quote_qualified(schema, table);
+    let mut statements = vec![
+        format!("ALTER TABLE {} RENAME TO {}", qualified, quote_ident(&old)),
+        format!(
+            "CREATE TABLE {} (LIKE {} {}) PARTITION BY RANGE ({})",
+            qualified,
+            quote_qualified(schema, &old),
+            including,
+            quote_ident(key)
+        ),
+    ];
+    statements.extend(partition_statements(schema, table, &partitions));
+    if options.default_partition {
+        statements.push(default_partition_statement(schema, table));
+    }
+    let columns = source
+        .columns
+        .iter()
+        .map(|c| quote_ident(c))
+        .collect::<Vec<_>>()
+        .join(", ");
+    statements.push(format!(
+        "INSERT INTO {} ({}){} SELECT {} FROM {}",
+        qualified,
+        columns,
+        if source.identity_columns.is_empty() {
+            ""
+        } else {
+            " OVERRIDING SYSTEM VALUE"
+        },
+        columns,
+        quote_qualified(schema, &old)
+    ));
+    for column in &source.identity_columns {
+        statements.push(format!(
+            "SELECT setval(pg_get_serial_sequence('{}', '{}'), max({})) FROM {}",
+            qualified.replace('\'', "''"),
+            column.replace('\'', "''"),
+            quote_ident(column),
+            qualified
+        ));
+    }
+    for (column, sequence) in &source.owned_sequences {
+        statements.push(format!(
+            "ALTER SEQUENCE {} OWNED BY {}.{}",
+            sequence,
+            qualified,
+            quote_ident(column)
+        ));
+    }
+
+    Ok(PartitionPlan {
+        schema: schema.clone(),
+        table_name: table.clone(),
+        partition_key: key.clone(),
+        partitions,
+        statements,
+        notes,
+    })
+}
+
+/// What the catalog says about a partitioned table about to be extended
+#[derive(Debug, Clone, Default)]
+pub struct ExtendSource {
+    pub partition_key: String,
+    pub key_type: String,
+    /// Highest upper bound of the existing partitions, as text
+    pub last_bound: Option<String>,
+}
+
+/// Plan the partitions after the last one through `premake` periods after
+/// `today` (date keys), or `premake` more partitions (integer keys)
+pub fn plan_extend(
+    request: &ExtendRequest,
+    source: &ExtendSource,
+    today: NaiveDate,
+) -> Result<PartitionPlan, String> {
+    let interval = PartitionInterval::parse(&request.interval)?;
+    let key = &source.partition_key;
+    interval.check_key(key, &source.key_type)?;
+
+    let last = source
+        .last_bound
+        .as_deref()
+        .map(|v| interval.parse_bound(v))
+        .transpose()?;
+    let (from, until) = match (interval, last) {
+        (PartitionInterval::Time(_), last) => {
+            let from = last.unwrap_or_else(|| interval.floor(Bound::Date(today)));
+            let until = interval.advance(
+                interval.floor(Bound::Date(today)),
+                request.premake.saturating_add(1),
+            )?;
+            (from, until)
+        }
+        (PartitionInterval::Integer(_), Some(last)) => {
+            (last, interval.advance(last, request.premake)?)
+        }
+        (PartitionInterval::Integer(_), None) => {
+            return Err(format!(
+                "{}.{} has no range partitions to continue from",
+                request.schema, request.table_name
+            ))
+        }
+    };
+    // Unaligned bounds are continued as they are, not rounded
+    let partitions = ranges(&request.table_name, interval, from, until)?;
+    let mut notes = Vec::new();
+    if partitions.is_empty() {
+        notes.push(format!(
+            "Partitions already cover {} up to {}",
+            key,
+            from.display()
+        ));
+    }
+
+    Ok(PartitionPlan {
+        schema: request.schema.clone(),
+        table_name: request.table_name.clone(),
+        partition_key: key.clone(),
+        statements: partition_statements(&request.schema, &request.table_name, &partitions),
+        partitions,
+        notes,
+    })
+}
+
+/// Check names and that no relation is already called `schema.table`
+async fn check_new_table(pool: &PgPool, schema: &str, table: &str) -> Result<(), String> {
+    let exists: bool =
+        sqlx::query_scalar("SELECT to_regclass(format('%I.%I', $1::text, $2::text)) IS NOT NULL")
+            .bind(schema)
+            .bind(table)
+            .fetch_one(pool)
+            .await
+            .map_err(|e| format!("Failed to check for table: {}", e))?;
+    if exists {
+        return Err(format!("Table {}.{} already exists", schema, table));
+    }
+    Ok(())
+}
+
+#[derive(sqlx::FromRow)]
+struct SourceColumn {
+    name: String,
+    data_type: String,
+    number: i16,
+    generated: bool,
+    identity: bool,
+    /// Sequence the column owns (serial) or uses (identity)
+    sequence: Option<String>,
+}
+
+/// Look up the table a conversion starts from
+pub async fn convert_source(
+    pool: &PgPool,
+    request: &ConvertRequest,
+) -> Result<ConvertSource, String> {
+    let (schema, table) = (&request.schema, &request.table_name);
+    let key = &request.partitioning.partition_key;
+    SchemaOpsService::validate_identifier(schema)?;
+    SchemaOpsService::validate_identifier(table)?;
+    SchemaOpsService::validate_identifier(key)?;
+    let old = child_name(table, UNPARTITIONED_SUFFIX);
+    check_new_table(pool, schema, &old).await?;
+
+    let relation = quote_qualified(schema, table);
+    let kind: Option<(String, bool)> = sqlx::query_as(
+        "SELECT relkind::text, relispartition FROM pg_class WHERE oid = to_regclass($1)",
+    )
+    .bind(&relation)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Failed to look up table: {}", e))?;
+    match kind {
+        None => return Err(format!("Table {}.{} not found", schema, table)),
+        Some((kind, _)) if kind == "p" => {
+            return Err(format!("{}.{} is already partitioned", schema, table))
+        }
+        Some((_, true)) => return Err(format!("{}.{} is a partition", schema, table)),
+        Some((kind, _)) if kind != "r" => {
+            return Err(format!("{}.{} is not a table", schema, table))
+        }
+        _ => {}
+    }
+
+    let columns: Vec<SourceColumn> = sqlx::query_as(
+        r#"
+        SELECT a.attname::text AS name, format_type(a.atttypid, a.atttypmod) AS data_type,
+               a.attnum AS number, a.attgenerated <> '' AS generated,
+               a.attidentity <> '' AS identity,
+               pg_get_serial_sequence($1, a.attname) AS sequence
+        FROM pg_attribute a
+        WHERE a.attrelid = $1::regclass AND a.attnum > 0 AND NOT a.attisdropped
+        ORDER BY a.attnum
+        "#,
+    )
+    .bind(&relation)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Failed to read columns: {}", e))?;
+    let Some(key_column) = columns.iter().find(|c| &c.name == key) else {
+        return Err(format!("Column {} not found in {}.{}", key, schema, table));
+    };
+
+    let (min_key, max_key): (Option<String>, Option<String>) = sqlx::query_as(&format!(
+        "SELECT min({key})::text, max({key})::text FROM {relation}",
+        key = quote_ident(key)
+    ))
+    .fetch_one(pool)
+    .await
+    .map_err(|e| format!("Failed to read the range of {}: {}", key, e))?;
+
+    let unique_without_key: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT i.relname::text
+        FROM pg_index x JOIN pg_class i ON i.oid = x.indexrelid
+        WHERE x.indrelid = $1::regclass AND x.indisunique
+          AND NOT ($2 = ANY (x.indkey::int2[]))
+        ORDER BY 1
+        "#,
+    )
+    .bind(&relation)
+    .bind(key_column.number)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Failed to read indexes: {}", e))?;
+
+    let referencing: Vec<String> = sqlx::query_scalar(
+        "SELECT conname || ' on ' || conrelid::regclass::text FROM pg_constraint \
+         WHERE contype = 'f' AND confrelid = $1::regclass AND conrelid <> confrelid ORDER BY 1",
+    )
+    .bind(&relation)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Failed to read foreign keys: {}", e))?;
+
+    Ok(ConvertSource {
+        key_type: key_column.data_type.clone(),
+        min_key,
+        max_key,
+        columns: columns
+            .iter()
+            .filter(|c| !c.generated)
+            .map(|c| c.name.clone())
+            .collect(),
+        identity_columns: columns
+            .iter()
+            .filter(|c| c.identity)
+            .map(|c| c.name.clone())
+            .collect(),
+        owned_sequences: columns
+            .iter()
+            .filter(|c| !c.identity)
+            .filter_map(|c| Some((c.name.clone(), c.sequence.clone()?)))
+            .collect(),
+        unique_without_key,
+        referencing,
+    })
+}
+
+/// Look up the partition key and last bound of a range-partitioned table
+pub async fn extend_source(pool: &PgPool, request: &ExtendRequest) -> Result<ExtendSource, String> {
+    let (schema, table) = (&request.schema, &request.table_name);
+    SchemaOpsService::validate_identifier(schema)?;
+    SchemaOpsService::validate_identifier(table)?;
+    let relation = quote_qualified(schema, table);
+
+    let key: Option<(String, String)> = sqlx::query_as(
+        r#"
+        SELECT a.attname::text, format_type(a.atttypid, a.atttypmod)
+        FROM pg_partitioned_table p
+        JOIN pg_attribute a ON a.attrelid = p.partrelid AND a.attnum = p.partattrs[0]
+        WHERE p.partrelid = to_regclass($1) AND p.partstrat = 'r' AND p.partnatts = 1
+        "#,
+    )
+    .bind(&relation)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Failed to read the partition key: {}", e))?;
+    let Some((partition_key, key_type)) = key else {
+        return Err(format!(
+            "{}.{} is not range-partitioned on a single column",
+            schema, table
+        ));
+    };
+
+    // Upper bounds read back as `FOR VALUES FROM (...) TO ('2024-02-01')`
+    let bounds: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT substring(pg_get_expr(c.relpartbound, c.oid) FROM 'TO \((.*)\)$')
+        FROM pg_inherits i JOIN pg_class c ON c.oid = i.inhrelid
+        WHERE i.inhparent = to_regclass($1)
+          AND pg_get_expr(c.relpartbound, c.oid) LIKE 'FOR VALUES FROM%'
+        "#,
+    )
+    .bind(&relation)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Failed to read partitions: {}", e))?;
+    let interval = PartitionInterval::parse(&request.interval)?;
+    let last_bound = bounds
+        .into_iter()
+        .filter(|b| b != "MAXVALUE")
+        .filter_map(|b| interval.parse_bound(&b).ok().map(|bound| (bound, b)))
+        .max()
+        .map(|(_, b)| b);
+
+    Ok(ExtendSource {
+        partition_key,
+        key_type,
+        last_bound,
+    })
+}
+
+/// Check that a new partitioned table does not exist yet
+pub async fn check_create(pool: &PgPool, request: &CreatePartitionedRequest) -> Result<(), String> {
+    check_new_table(pool, &request.table.schema, &request.table.table_name).await
+}
+
+/// Run a plan's statements in one transaction
+pub async fn apply(pool: &PgPool, plan: &PartitionPlan) -> Result<(), String> {
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| format!("Failed to start transaction: {}", e))?;
+    for statement in &plan.statements {
+        sqlx::query(statement)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("{}: {}", statement, e))?;
+    }
+    tx.commit()
+        .await
+        .map_err(|e| format!("Failed to commit: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn names(plan: &PartitionPlan) -> Vec<&str> {
+        plan.partitions.iter().map(|p| p.name.as_str()).collect()
+    }
+
+    fn create_request(options: serde_json::Value) -> CreatePartitionedRequest {
+        let mut request = json!({
+            "schema": "public",
+            "table_name": "events",
+            "columns": [
+                {"name": "id", "data_type": "bigint", "nullable": false},
+                {"name": "created_at", "data_type": "timestamptz", "nullable": false},
+                {"name": "payload", "data_type": "jsonb", "nullable": true}
+            ],
+            "primary_key": ["id", "created_at"],
+            "partition_key": "created_at",
+            "interval": "month"
+        });
+        request
+            .as_object_mut()
+            .unwrap()
+            .extend(options.as_object().unwrap().clone());
+        serde_json::from_value(request).unwrap()
+    }
+
+    #[test]
+    fn test_interval() {
+        let month = PartitionInterval::parse("Month").unwrap();
+        assert_eq!(month, PartitionInterval::Time(IntervalUnit::Month));
+        assert_eq!(
+            PartitionInterval::parse("100000").unwrap(),
+            PartitionInterval::Integer(100000)
+        );
+        assert!(PartitionInterval::parse("0").is_err());
+        assert!(PartitionInterval::parse("fortnight").is_err());
+
+        let bound = month.parse_bound("2024-05-17 13:45:00+02").unwrap();
+        assert_eq!(month.floor(bound), Bound::Date(date("2024-05-01")));
+        assert_eq!(
+            month.next(Bound::Date(date("2024-12-01"))),
+            Some(Bound::Date(date("2025-01-01")))
+        );
+        let week = PartitionInterval::Time(IntervalUnit::Week);
+        assert_eq!(
+            week.floor(Bound::Date(date("2024-05-17"))),
+            Bound::Date(date("2024-05-13"))
+        );
+        let quarter = PartitionInterval::Time(IntervalUnit::Quarter);
+        assert_eq!(
+            quarter.floor(Bound::Date(date("2024-08-31"))),
+            Bound::Date(date("2024-07-01"))
+        );
+        assert_eq!(quarter.suffix(Bound::Date(date("2024-07-01"))), "p2024q3");
+        let step = PartitionInterval::Integer(1000);
+        assert_eq!(step.floor(Bound::Integer(-1)), Bound::Integer(-1000));
+        assert_eq!(step.suffix(Bound::Integer(-1000)), "pm1000");
+
+        assert!(month
+            .check_key("created_at", "timestamp with time zone")
+            .is_ok());
+        assert!(month.check_key("id", "bigint").is_err());
+        assert!(step.check_key("id", "bigint").is_ok());
+        assert!(step.check_key("name", "text").is_err());
+    }
+
+    #[test]
+    fn test_plan_create() {
+        let plan = plan_create(&create_request(json!({})), date("2024-05-17")).unwrap();
+        assert_eq!(
+            names(&plan),
+            [
+                "events_p202405",
+                "events_p202406",
+                "events_p202407",
+                "events_p202408"
+            ]
+        );
+        assert!(plan.statements[0].starts_with("CREATE TABLE public.events ("));
+        assert!(plan.statements[0].ends_with(") PARTITION BY RANGE (created_at)"));
+        assert_eq!(
+            plan.statements[1],
+            "CREATE TABLE public.events_p202405 PARTITION OF public.events \
+             FOR VALUES FROM ('2024-05-01') TO ('2024-06-01')"
+        );
+        assert_eq!(plan.statements.len(), 5);
+        assert_eq!(plan.notes.len(), 1);
+
+        let plan = plan_create(
+            &create_request(
+                json!({"start": "2024-01-15", "premake": 0, "default_partition": true}),
+            ),
+            date("2024-03-02"),
+        )
+        .unwrap();
+        assert_eq!(
+            names(&plan),
+            ["events_p202401", "events_p202402", "events_p202403"]
+        );
+        assert_eq!(
+            plan.statements.last().unwrap(),
+            "CREATE TABLE public.events_default PARTITION OF public.events DEFAULT"
+        );
+        assert!(plan.notes.is_empty());
+
+        let error = plan_create(
+            &create_request(json!({"primary_key": ["id"]})),
+            date("2024-05-17"),
+        )
+        .unwrap_err();
+        assert!(error.contains("must include the partition key"));
+        let error = plan_create(
+            &create_request(json!({"partition_key": "payload"})),
+            date("2024-05-17"),
+        )
+        .unwrap_err();
+        assert!(error.contains("jsonb"));
+        let error = plan_create(
+            &create_request(json!({"interval": "day", "start": "2000-01-01"})),
+            date("2024-05-17"),
+        )
+        .unwrap_err();
+        assert!(error.contains("more than 1000 partitions"));
+    }
+
+    #[test]
+    fn test_plan_convert() {
+        let request: ConvertRequest = serde_json::from_value(json!({
+            "schema": "public",
+            "table_name": "orders",
+            "partition_key": "id",
+            "interval": "1000",
+            "premake": 1
+        }))
+        .unwrap();
+        let source = ConvertSource {
+            key_type: "bigint".to_string(),
+            min_key: Some("1".to_string()),
+            max_key: Some("2500".to_string()),
+            columns: vec!["id".to_string(), "total".to_string()],
+            identity_columns: vec!["id".to_string()],
+            owned_sequences: vec![],
+            unique_without_key: vec!["orders_code_key".to_string()],
+            referencing: vec!["items_order_id_fkey on items".to_string()],
+        };
+        let plan = plan_convert(&request, &source, date("2024-05-17")).unwrap();
+        assert_eq!(
+            names(&plan),
+            ["orders_p0", "orders_p1000", "orders_p2000", "orders_p3000"]
+        );
+        assert_eq!(
+            plan.statements[0],
+            "ALTER TABLE public.orders RENAME TO orders_unpartitioned"
+        );
+        assert!(!plan.statements[1].contains("INCLUDING INDEXES"));
+        assert!(plan.statements.contains(
+            &"INSERT INTO public.orders (id, total) OVERRIDING SYSTEM VALUE \
+                SELECT id, total FROM public.orders_unpartitioned"
+                .to_string()
+        ));
+        assert!(plan
+            .statements
+            .last()
+            .unwrap()
+            .starts_with("SELECT setval("));
+        assert_eq!(plan.notes.len(), 3);
+
+        let empty = ConvertSource {
+            min_key: None,
+            max_key: None,
+            unique_without_key: vec![],
+            referencing: vec![],
+            ..source
+        };
+        let plan = plan_convert(&request, &empty, date("2024-05-17")).unwrap();
+        assert_eq!(names(&plan), ["orders_p0", "orders_p1000"]);
+        assert!(plan.statements[1].contains("INCLUDING INDEXES"));
+    }
+
+    #[test]
+    fn test_plan_extend() {
+        let request: ExtendRequest = serde_json::from_value(json!({
+            "schema": "public",
+            "table_name": "events",
+            "interval": "month",
+            "premake": 2
+        }))
+        .unwrap();
+        let source = ExtendSource {
+            partition_key: "created_at".to_string(),
+            key_type: "timestamp with time zone".to_string(),
+            last_bound: Some("'2024-06-01 00:00:00+00'".to_string()),
+        };
+        let plan = plan_extend(&request, &source, date("2024-05-17")).unwrap();
+        assert_eq!(names(&plan), ["events_p202406", "events_p202407"]);
+
+        let plan = plan_extend(&request, &source, date("2024-03-01")).unwrap();
+        assert!(plan.partitions.is_empty());
+        assert!(plan.statements.is_empty());
+        assert_eq!(plan.notes.len(), 1);
+
+        let none = ExtendSource {
+            last_bound: None,
+            ..source
+        };
+        let plan = plan_extend(&request, &none, date("2024-05-17")).unwrap();
+        assert_eq!(
+            names(&plan),
+            ["events_p202405", "events_p202406", "events_p202407"]
+        );
+    }
+
+    #[test]
+    fn test_child_name() {
+        assert_eq!(child_name("events", "p2024"), "events_p2024");
+        let long = "t".repeat(63);
+        let name = child_name(&long, "p202401");
+        assert_eq!(name.len(), 63);
+        assert!(name.ends_with("_p202401"));
+    }
+}
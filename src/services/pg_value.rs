@@ -0,0 +1,502 @@
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use serde_json::{json, Value};
+/// Result Value Decoding
+///
+/// Converts query result cells to JSON by column type OID, reading the
+/// binary wire format directly. This covers types with no Rust mapping in
+/// this build (numeric, interval, inet, money) and arrays of any supported
+/// element type. Cells of types that can't be decoded come back as
+/// `{"unsupported_type": "<name>"}` rather than a silent `null`.
+use sqlx::postgres::{PgRow, PgTypeInfo, PgTypeKind, PgValueFormat};
+use sqlx::{Row, TypeInfo, ValueRef};
+use std::fmt::Write;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// Decode one cell of a result row
+pub fn cell_to_json(row: &PgRow, index: usize) -> Value {
+    let Ok(raw) = row.try_get_raw(index) else {
+        return Value::Null;
+    };
+    if raw.is_null() {
+        return Value::Null;
+    }
+
+    let type_info = raw.type_info().into_owned();
+    let decoded = match raw.format() {
+        PgValueFormat::Text => raw.as_str().ok().map(|s| json!(s)),
+        PgValueFormat::Binary => raw
+            .as_bytes()
+            .ok()
+            .and_then(|bytes| decode(&type_info, bytes)),
+    };
+    decoded.unwrap_or_else(|| unsupported(type_info.name()))
+}
+
+/// Marker for a value whose type can't be decoded
+pub fn unsupported(type_name: &str) -> Value {
+    json!({ "unsupported_type": type_name })
+}
+
+fn decode(type_info: &PgTypeInfo, bytes: &[u8]) -> Option<Value> {
+    if let Some(value) = type_info.oid().and_then(|oid| decode_builtin(oid.0, bytes)) {
+        return Some(value);
+    }
+
+    // Arrays, enums and domains, including user-defined ones resolved by the connection
+    match type_info.kind() {
+        PgTypeKind::Array(elem) => decode_array(bytes, &|oid, bytes| {
+            decode_builtin(oid, bytes).or_else(|| decode(elem, bytes))
+        }),
+        PgTypeKind::Enum(_) => text(bytes),
+        PgTypeKind::Domain(base) => decode(base, bytes),
+        _ => None,
+    }
+}
+
+/// Decode a built-in scalar type from its binary representation
+fn decode_builtin(oid: u32, bytes: &[u8]) -> Option<Value> {
+    match oid {
+        // bool
+        16 => Some(json!(*bytes.first()? != 0)),
+        // bytea, rendered like Postgres' hex output
+        17 => {
+            let mut hex = String::with_capacity(2 + bytes.len() * 2);
+            hex.push_str("\\x");
+            for b in bytes {
+                let _ = write!(hex, "{:02x}", b);
+            }
+            Some(json!(hex))
+        }
+        // "char"
+        18 => Some(json!(String::from_utf8_lossy(bytes))),
+        // name, text, varchar, bpchar, unknown, xml
+        19 | 25 | 1043 | 1042 | 705 | 142 => text(bytes),
+        // int8, int2, int4, oid
+        20 => Some(json!(i64::from_be_bytes(bytes.try_into().ok()?))),
+        21 => Some(json!(i16::from_be_bytes(bytes.try_into().ok()?))),
+        23 => Some(json!(i32::from_be_bytes(bytes.try_into().ok()?))),
+        26 => Some(json!(u32::from_be_bytes(bytes.try_into().ok()?))),
+        // float4, float8
+        700 => Some(float(f32::from_be_bytes(bytes.try_into().ok()?) as f64)),
+        701 => Some(float(f64::from_be_bytes(bytes.try_into().ok()?))),
+        // json, jsonb (version byte first), jsonpath (version byte first)
+        114 => json_text(bytes),
+        3802 => json_text(bytes.strip_prefix(&[1])?),
+        4072 => text(bytes.strip_prefix(&[1])?),
+        // inet, cidr
+        869 | 650 => decode_inet(bytes).map(|s| json!(s)),
+        // macaddr, macaddr8
+        829 | 774 => Some(json!(bytes
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(":"))),
+        // money: an int8 count of cents
+        790 => {
+            let cents = i64::from_be_bytes(bytes.try_into().ok()?);
+            let sign = if cents < 0 { "-" } else { "" };
+            let cents = cents.unsigned_abs();
+            Some(json!(format!("{}{}.{:02}", sign, cents / 100, cents % 100)))
+        }
+        // numeric, kept as a string so no precision is lost
+        1700 => decode_numeric(bytes).map(|s| json!(s)),
+        // date, time, timestamp, timestamptz, timetz, interval
+        1082 => decode_date(bytes).map(|s| json!(s)),
+        1083 => {
+            let time = time_from_micros(i64::from_be_bytes(bytes.try_into().ok()?))?;
+            Some(json!(time.format("%H:%M:%S%.f").to_string()))
+        }
+        1114 => decode_timestamp(bytes, false).map(|s| json!(s)),
+        1184 => decode_timestamp(bytes, true).map(|s| json!(s)),
+        1266 => decode_timetz(bytes).map(|s| json!(s)),
+        1186 => decode_interval(bytes).map(|s| json!(s)),
+        // uuid
+        2950 => Some(json!(uuid::Uuid::from_slice(bytes).ok()?.to_string())),
+        _ => None,
+    }
+}
+
+fn text(bytes: &[u8]) -> Option<Value> {
+    std::str::from_utf8(bytes).ok().map(|s| json!(s))
+}
+
+fn json_text(bytes: &[u8]) -> Option<Value> {
+    let text = std::str::from_utf8(bytes).ok()?;
+    Some(serde_json::from_str(text).unwrap_or_else(|_| json!(text)))
+}
+
+/// JSON has no NaN or infinities, so those become strings
+fn float(value: f64) -> Value {
+    if value.is_nan() {
+        json!("NaN")
+    } else if value.is_infinite() {
+        json!(if value > 0.0 { "Infinity" } else { "-Infinity" })
+    } else {
+        json!(value)
+    }
+}
+
+fn be_i16(bytes: &[u8], at: usize) -> Option<i16> {
+    Some(i16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn be_i32(bytes: &[u8], at: usize) -> Option<i32> {
+    Some(i32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn be_i64(bytes: &[u8], at: usize) -> Option<i64> {
+    Some(i64::from_be_bytes(bytes.get(at..at + 8)?.try_into().ok()?))
+}
+
+/// Decode an array (any number of dimensions) into nested JSON arrays
+///
+/// Layout: ndim, has-nulls flag, element OID, then (length, lower bound) per
+/// dimension, then each element as a length (-1 for NULL) and its bytes.
+fn decode_array(bytes: &[u8], element: &dyn Fn(u32, &[u8]) -> Option<Value>) -> Option<Value> {
+    let ndim = usize::try_from(be_i32(bytes, 0)?).ok()?;
+    let elem_oid = be_i32(bytes, 8)? as u32;
+    let dims = (0..ndim)
+        .map(|d| usize::try_from(be_i32(bytes, 12 + d * 8)?).ok())
+        .collect::<Option<Vec<_>>>()?;
+
+    let count = if ndim == 0 { 0 } else { dims.iter().product() };
+
+    let mut offset = 12 + ndim * 8;
+    let mut elements = Vec::new();
+    for _ in 0..count {
+        let len = be_i32(bytes, offset)?;
+        offset += 4;
+        if len < 0 {
+            elements.push(Value::Null);
+            continue;
+        }
+        let data = bytes.get(offset..offset + len as usize)?;
+        offset += len as usize;
+        elements.push(element(elem_oid, data)?);
+    }
+
+    Some(nest(&dims, &mut elements.into_iter()))
+}
+
+/// Group a flat element list into one nested array per dimension
+fn nest(dims: &[usize], elements: &mut impl Iterator<Item = Value>) -> Value {
+    match dims.split_first() {
+        None => json!([]),
+        Some((len, [])) => Value::Array(elements.take(*len).collect()),
+        Some((len, rest)) => Value::Array((0..*len).map(|_| nest(rest, elements)).collect()),
+    }
+}
+
+/// Decode numeric: ndigits, weight, sign, display scale, then base-10000 digits
+fn decode_numeric(bytes: &[u8]) -> Option<String> {
+    let ndigits = usize::try_from(be_i16(bytes, 0)?).ok()?;
+    let weight = be_i16(bytes, 2)? as i32;
+    let sign = be_i16(bytes, 4)? as u16;
+    let dscale = usize::try_from(be_i16(bytes, 6)?).ok()?;
+
+    match sign {
+        0xC000 => return Some("NaN".to_string()),
+        0xD000 => return Some("Infinity".to_string()),
+        0xF000 => return Some("-Infinity".to_string()),
+        _ => {}
+    }
+
+    let digits = (0..ndigits)
+        .map(|i| be_i16(bytes, 8 + i * 2))
+        .collect::<Option<Vec<_>>>()?;
+    // digits[i] is worth 10000^(weight - i)
+    let digit = |i: i32| {
+        usize::try_from(i)
+            .ok()
+            .and_then(|i| digits.get(i).copied())
+            .unwrap_or(0)
+    };
+
+    let mut out = String::new();
+    if sign == 0x4000 {
+        out.push('-');
+    }
+    if weight < 0 {
+        out.push('0');
+    } else {
+        let _ = write!(out, "{}", digit(0));
+        for i in 1..=weight {
+            let _ = write!(out, "{:04}", digit(i));
+        }
+    }
+
+    if dscale > 0 {
+        let mut fraction = String::new();
+        let mut i = weight + 1;
+        while fraction.len() < dscale {
+            let _ = write!(fraction, "{:04}", digit(i));
+            i += 1;
+        }
+        fraction.truncate(dscale);
+        out.push('.');
+        out.push_str(&fraction);
+    }
+    Some(out)
+}
+
+/// Postgres dates and timestamps count from 2000-01-01
+fn epoch() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2000, 1, 1)
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .expect("valid epoch")
+}
+
+fn decode_date(bytes: &[u8]) -> Option<String> {
+    match i32::from_be_bytes(bytes.try_into().ok()?) {
+        i32::MAX => Some("infinity".to_string()),
+        i32::MIN => Some("-infinity".to_string()),
+        days => {
+            let date = epoch().date() + Duration::days(days as i64);
+            Some(date.format("%Y-%m-%d").to_string())
+        }
+    }
+}
+
+fn decode_timestamp(bytes: &[u8], with_zone: bool) -> Option<String> {
+    match i64::from_be_bytes(bytes.try_into().ok()?) {
+        i64::MAX => Some("infinity".to_string()),
+        i64::MIN => Some("-infinity".to_string()),
+        micros => {
+            let timestamp = epoch().checked_add_signed(Duration::microseconds(micros))?;
+            Some(if with_zone {
+                DateTime::<Utc>::from_naive_utc_and_offset(timestamp, Utc).to_rfc3339()
+            } else {
+                timestamp.format("%Y-%m-%dT%H:%M:%S%.f").to_string()
+            })
+        }
+    }
+}
+
+fn time_from_micros(micros: i64) -> Option<NaiveTime> {
+    let seconds = u32::try_from(micros / 1_000_000).ok()?;
+    let nanos = u32::try_from(micros % 1_000_000).ok()? * 1000;
+    NaiveTime::from_num_seconds_from_midnight_opt(seconds, nanos)
+}
+
+/// timetz: microseconds since midnight, then the zone offset in seconds west of UTC
+fn decode_timetz(bytes: &[u8]) -> Option<String> {
+    let time = time_from_micros(be_i64(bytes, 0)?)?;
+    let east = -be_i32(bytes, 8)?;
+    let sign = if east < 0 { '-' } else { '+' };
+    let east = east.unsigned_abs();
+    Some(format!(
+        "{}{}{:02}:{:02}",
+        time.format("%H:%M:%S%.f"),
+        sign,
+        east / 3600,
+        east % 3600 / 60
+    ))
+}
+
+/// interval: microseconds, days, months, rendered like Postgres' default style
+fn decode_interval(bytes: &[u8]) -> Option<String> {
+    let micros = be_i64(bytes, 0)?;
+    let days = be_i32(bytes, 8)?;
+    let months = be_i32(bytes, 12)?;
+
+    let plural = |n: i64| if n.abs() == 1 { "" } else { "s" };
+    let mut parts = Vec::new();
+    let (years, months) = (months as i64 / 12, months as i64 % 12);
+    if years != 0 {
+        parts.push(format!("{} year{}", years, plural(years)));
+    }
+    if months != 0 {
+        parts.push(format!("{} mon{}", months, plural(months)));
+    }
+    if days != 0 {
+        parts.push(format!("{} day{}", days, plural(days as i64)));
+    }
+    if micros != 0 || parts.is_empty() {
+        let sign = if micros < 0 { "-" } else { "" };
+        let total = micros.unsigned_abs();
+        let seconds = total / 1_000_000;
+        let mut time = format!(
+            "{}{:02}:{:02}:{:02}",
+            sign,
+            seconds / 3600,
+            seconds % 3600 / 60,
+            seconds % 60
+        );
+        let fraction = total % 1_000_000;
+        if fraction != 0 {
+            let digits = format!("{:06}", fraction);
+            time.push('.');
+            time.push_str(digits.trim_end_matches('0'));
+        }
+        parts.push(time);
+    }
+    Some(parts.join(" "))
+}
+
+/// inet / cidr: family, mask bits, is-cidr flag, address length, address
+fn decode_inet(bytes: &[u8]) -> Option<String> {
+    let (&family, rest) = bytes.split_first()?;
+    let (&bits, rest) = rest.split_first()?;
+    let (&is_cidr, rest) = rest.split_first()?;
+    let (_, address) = rest.split_first()?;
+
+    let (address, max_bits) = match family {
+        2 => (
+            Ipv4Addr::from(<[u8; 4]>::try_from(address).ok()?).to_string(),
+            32,
+        ),
+        3 => (
+            Ipv6Addr::from(<[u8; 16]>::try_from(address).ok()?).to_string(),
+            128,
+        ),
+        _ => return None,
+    };
+
+    // Like Postgres, inet omits the mask for single hosts
+    if is_cidr != 0 || bits != max_bits {
+        Some(format!("{}/{}", address, bits))
+    } else {
+        Some(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numeric(weight: i16, sign: u16, dscale: i16, digits: &[i16]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend((digits.len() as i16).to_be_bytes());
+        bytes.extend(weight.to_be_bytes());
+        bytes.extend(sign.to_be_bytes());
+        bytes.extend(dscale.to_be_bytes());
+        for d in digits {
+            bytes.extend(d.to_be_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_decode_scalars() {
+        assert_eq!(decode_builtin(16, &[1]), Some(json!(true)));
+        assert_eq!(decode_builtin(21, &(-7i16).to_be_bytes()), Some(json!(-7)));
+        assert_eq!(
+            decode_builtin(20, &9_007_199_254_740_993i64.to_be_bytes()),
+            Some(json!(9_007_199_254_740_993i64))
+        );
+        assert_eq!(
+            decode_builtin(701, &f64::NAN.to_be_bytes()),
+            Some(json!("NaN"))
+        );
+        assert_eq!(decode_builtin(17, &[0xde, 0xad]), Some(json!("\\xdead")));
+        assert_eq!(
+            decode_builtin(3802, b"\x01{\"a\": [1, 2]}"),
+            Some(json!({"a": [1, 2]}))
+        );
+        assert_eq!(
+            decode_builtin(790, &(-1234i64).to_be_bytes()),
+            Some(json!("-12.34"))
+        );
+        assert_eq!(decode_builtin(600, &[0; 16]), None);
+    }
+
+    #[test]
+    fn test_decode_numeric() {
+        // 12345.678
+        let bytes = numeric(1, 0, 3, &[1, 2345, 6780]);
+        assert_eq!(decode_numeric(&bytes).unwrap(), "12345.678");
+        // -0.0012
+        let bytes = numeric(-1, 0x4000, 4, &[12]);
+        assert_eq!(decode_numeric(&bytes).unwrap(), "-0.0012");
+        // 20000 (trailing zero groups are omitted on the wire)
+        let bytes = numeric(1, 0, 0, &[2]);
+        assert_eq!(decode_numeric(&bytes).unwrap(), "20000");
+        assert_eq!(decode_numeric(&numeric(0, 0, 2, &[])).unwrap(), "0.00");
+        assert_eq!(decode_numeric(&numeric(0, 0xC000, 0, &[])).unwrap(), "NaN");
+    }
+
+    #[test]
+    fn test_decode_dates_and_times() {
+        assert_eq!(
+            decode_builtin(1082, &366i32.to_be_bytes()),
+            Some(json!("2001-01-01"))
+        );
+        assert_eq!(
+            decode_builtin(1114, &3_723_500_000i64.to_be_bytes()),
+            Some(json!("2000-01-01T01:02:03.500"))
+        );
+        assert_eq!(
+            decode_builtin(1184, &0i64.to_be_bytes()),
+            Some(json!("2000-01-01T00:00:00+00:00"))
+        );
+        assert_eq!(
+            decode_builtin(1184, &i64::MAX.to_be_bytes()),
+            Some(json!("infinity"))
+        );
+
+        let mut timetz = 3_600_000_000i64.to_be_bytes().to_vec();
+        timetz.extend((-19800i32).to_be_bytes());
+        assert_eq!(decode_timetz(&timetz).unwrap(), "01:00:00+05:30");
+    }
+
+    #[test]
+    fn test_decode_interval() {
+        let interval = |micros: i64, days: i32, months: i32| {
+            let mut bytes = micros.to_be_bytes().to_vec();
+            bytes.extend(days.to_be_bytes());
+            bytes.extend(months.to_be_bytes());
+            decode_interval(&bytes).unwrap()
+        };
+        assert_eq!(
+            interval(14_706_250_000, 3, 14),
+            "1 year 2 mons 3 days 04:05:06.25"
+        );
+        assert_eq!(interval(0, 1, 0), "1 day");
+        assert_eq!(interval(-90_000_000, 0, 0), "-00:01:30");
+        assert_eq!(interval(0, 0, 0), "00:00:00");
+    }
+
+    #[test]
+    fn test_decode_inet() {
+        assert_eq!(
+            decode_inet(&[2, 32, 0, 4, 10, 0, 0, 1]).unwrap(),
+            "10.0.0.1"
+        );
+        assert_eq!(
+            decode_inet(&[2, 24, 1, 4, 10, 0, 0, 0]).unwrap(),
+            "10.0.0.0/24"
+        );
+        let mut v6 = vec![3, 128, 0, 16];
+        v6.extend(Ipv6Addr::LOCALHOST.octets());
+        assert_eq!(decode_inet(&v6).unwrap(), "::1");
+    }
+
+    #[test]
+    fn test_decode_array() {
+        // int4[][] = {{1,NULL},{3,4}}
+        let mut bytes = Vec::new();
+        for v in [2i32, 1, 23, 2, 1, 2, 1] {
+            bytes.extend(v.to_be_bytes());
+        }
+        for element in [Some(1i32), None, Some(3), Some(4)] {
+            match element {
+                Some(v) => {
+                    bytes.extend(4i32.to_be_bytes());
+                    bytes.extend(v.to_be_bytes());
+                }
+                None => bytes.extend((-1i32).to_be_bytes()),
+            }
+        }
+        assert_eq!(
+            decode_array(&bytes, &decode_builtin),
+            Some(json!([[1, null], [3, 4]]))
+        );
+
+        // Empty arrays have no dimensions
+        let mut empty = Vec::new();
+        for v in [0i32, 0, 25] {
+            empty.extend(v.to_be_bytes());
+        }
+        assert_eq!(decode_array(&empty, &decode_builtin), Some(json!([])));
+    }
+}
@@ -0,0 +1,423 @@
+use crate::services::chart_service::{label, number, Aggregate};
+use crate::services::identifier::{self, quote_ident};
+use serde::Serialize;
+/// Pivot Service
+///
+/// Turns rows into a crosstab: one output row per value of the row column,
+/// one output column per value of the column column, and in each cell an
+/// aggregate of the value column (or a row count).
+///
+/// A table is grouped in the database and only the aggregated cells are
+/// fetched. A query's results are pivoted in Rust, keeping the rows and
+/// columns in the order their keys first appear. The `tablefunc`
+/// extension's `crosstab()` is not used, since it needs the output columns
+/// spelled out in advance and may not be installed.
+use serde_json::Value;
+use sqlx::{PgPool, Row};
+use std::collections::HashMap;
+
+/// Output rows when no limit is given
+pub const DEFAULT_ROW_LIMIT: u32 = 100;
+
+/// Upper bound on output rows
+pub const MAX_ROW_LIMIT: u32 = 1000;
+
+/// Distinct values of the column column a pivot may spread into columns
+pub const MAX_COLUMNS: usize = 200;
+
+/// What to pivot, with names already checked against the source
+#[derive(Debug, Clone)]
+pub struct PivotSpec {
+    /// Column whose values become the output rows
+    pub rows: String,
+    /// Column whose values become the output columns
+    pub columns: String,
+    /// Aggregated column; `count` without one counts rows
+    pub values: Option<String>,
+    pub aggregate: Aggregate,
+    pub limit: u32,
+}
+
+impl PivotSpec {
+    /// Name of what the cells hold, e.g. `sum(total)`
+    pub fn value_name(&self) -> String {
+        format!(
+            "{}({})",
+            self.aggregate.as_str(),
+            self.values.as_deref().unwrap_or("*")
+        )
+    }
+}
+
+/// A crosstab: `rows[i].values[j]` aggregates the source rows with the i-th
+/// row key and the j-th column key
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Pivot {
+    pub row_column: String,
+    pub column_column: String,
+    pub value: String,
+    /// Keys of the output columns
+    pub columns: Vec<String>,
+    pub rows: Vec<PivotRow>,
+    /// Set when rows past the limit were left out
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PivotRow {
+    pub key: String,
+    /// Empty where no source row has both keys
+    pub values: Vec<Option<f64>>,
+}
+
+/// Pivot `schema.table`, grouping it in the database
+pub async fn pivot_table(
+    pool: &PgPool,
+    schema: &str,
+    table: &str,
+    spec: &PivotSpec,
+) -> Result<Result<Pivot, String>, sqlx::Error> {
+    let relation = identifier::resolve_relation(pool, schema, table).await?;
+    let cells = sqlx::query(&pivot_query(&relation, spec))
+        .fetch_all(pool)
+        .await?;
+
+    let mut row_keys: Vec<String> = Vec::new();
+    let mut column_keys: Vec<String> = Vec::new();
+    let mut grid: Vec<(usize, usize, Option<f64>)> = Vec::with_capacity(cells.len());
+    for cell in &cells {
+        // Ranks are dense and 1-based, so a rank past the known keys is a new key
+        let row_rank = cell.try_get::<i64, _>("row_rank")? as usize - 1;
+        let column_rank = cell.try_get::<i64, _>("column_rank")? as usize - 1;
+        if row_rank == row_keys.len() {
+            row_keys.push(key(cell.try_get("row_key")?));
+        }
+        if column_rank >= column_keys.len() {
+            column_keys.resize(column_rank + 1, String::new());
+            column_keys[column_rank] = key(cell.try_get("column_key")?);
+        }
+        grid.push((row_rank, column_rank, cell.try_get("value")?));
+    }
+    if column_keys.len() > MAX_COLUMNS {
+        return Ok(Err(too_many_columns(&spec.columns)));
+    }
+    let truncated = match cells.first() {
+        Some(cell) => cell.try_get::<i64, _>("row_count")? > spec.limit as i64,
+        None => false,
+    };
+
+    let mut rows: Vec<PivotRow> = row_keys
+        .into_iter()
+        .map(|key| PivotRow {
+            key,
+            values: vec![None; column_keys.len()],
+        })
+        .collect();
+    for (row, column, value) in grid {
+        rows[row].values[column] = value;
+    }
+
+    Ok(Ok(Pivot {
+        row_column: spec.rows.clone(),
+        column_column: spec.columns.clone(),
+        value: spec.value_name(),
+        columns: column_keys,
+        rows,
+        truncated,
+    }))
+}
+
+/// Cells of the kept rows, with dense ranks of their row and column keys in
+/// the keys' own order and the number of row keys before the limit
+fn pivot_query(relation: &str, spec: &PivotSpec) -> String {
+    let row = format!("t.{}", quote_ident(&spec.rows));
+    let column = format!("t.{}", quote_ident(&spec.columns));
+    let argument = match &spec.values {
+        Some(values) => format!("t.{}", quote_ident(values)),
+        None => "*".to_string(),
+    };
+    // Columns are ranked after the row limit, so only the kept rows' columns count
+    format!(
+        "SELECT row_key, column_key, value, row_rank, row_count, \
+            dense_rank() OVER (ORDER BY column_value NULLS LAST) AS column_rank \
+         FROM (\
+            SELECT *, max(row_rank) OVER () AS row_count FROM (\
+                SELECT ({row})::text AS row_key, {column} AS column_value, \
+                ({column})::text AS column_key, {aggregate}({argument})::float8 AS value, \
+                dense_rank() OVER (ORDER BY {row} NULLS LAST) AS row_rank \
+                FROM {relation} t GROUP BY {row}, {column}\
+            ) grouped\
+         ) cells WHERE row_rank <= {limit} ORDER BY row_rank, column_rank",
+        aggregate = spec.aggregate.as_str(),
+        limit = spec.limit,
+    )
+}
+
+/// Pivot query results, whose columns must include the spec's
+pub fn pivot_rows(
+    columns: &[String],
+    rows: &[Vec<Value>],
+    spec: &PivotSpec,
+) -> Result<Pivot, String> {
+    let index = |name: &str| {
+        columns
+            .iter()
+            .position(|c| c == name)
+            .ok_or_else(|| format!("The query has no column '{}'", name))
+    };
+    let row_index = index(&spec.rows)?;
+    let column_index = index(&spec.columns)?;
+    let value_index = spec.values.as_deref().map(index).transpose()?;
+
+    let mut row_keys: Vec<String> = Vec::new();
+    let mut column_keys: Vec<String> = Vec::new();
+    let mut row_positions: HashMap<String, usize> = HashMap::new();
+    let mut column_positions: HashMap<String, usize> = HashMap::new();
+    let mut cells: HashMap<(usize, usize), Accumulator> = HashMap::new();
+    let mut truncated = false;
+
+    for row in rows {
+        let row_key = row
+            .get(row_index)
+            .map(label)
+            .unwrap_or_else(|| "NULL".to_string());
+        let row_position = match row_positions.get(&row_key) {
+            Some(&position) => position,
+            None if row_keys.len() >= spec.limit as usize => {
+                truncated = true;
+                continue;
+            }
+            None => {
+                row_positions.insert(row_key.clone(), row_keys.len());
+                row_keys.push(row_key);
+                row_keys.len() - 1
+            }
+        };
+        let column_key = row
+            .get(column_index)
+            .map(label)
+            .unwrap_or_else(|| "NULL".to_string());
+        let column_position = match column_positions.get(&column_key) {
+            Some(&position) => position,
+            None if column_keys.len() >= MAX_COLUMNS => {
+                return Err(too_many_columns(&spec.columns))
+            }
+            None => {
+                column_positions.insert(column_key.clone(), column_keys.len());
+                column_keys.push(column_key);
+                column_keys.len() - 1
+            }
+        };
+
+        let value = match value_index {
+            Some(i) => match row.get(i) {
+                None | Some(Value::Null) => None,
+                Some(value) => Some(number(value).ok_or_else(|| {
+                    format!(
+                        "Column '{}' has a non-numeric value: {}",
+                        spec.values.as_deref().unwrap_or_default(),
+                        value
+                    )
+                })?),
+            },
+            // Counting rows: any placeholder value will do
+            None => Some(0.0),
+        };
+        cells
+            .entry((row_position, column_position))
+            .or_default()
+            .add(value, spec.aggregate);
+    }
+
+    let mut pivot_rows: Vec<PivotRow> = row_keys
+        .into_iter()
+        .map(|key| PivotRow {
+            key,
+            values: vec![None; column_keys.len()],
+        })
+        .collect();
+    for ((row, column), accumulator) in cells {
+        pivot_rows[row].values[column] = accumulator.finish(spec.aggregate);
+    }
+
+    Ok(Pivot {
+        row_column: spec.rows.clone(),
+        column_column: spec.columns.clone(),
+        value: spec.value_name(),
+        columns: column_keys,
+        rows: pivot_rows,
+        truncated,
+    })
+}
+
+/// Running aggregate of one cell; NULLs are skipped like in SQL
+#[derive(Debug, Default)]
+struct Accumulator {
+    count: u64,
+    sum: f64,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl Accumulator {
+    fn add(&mut self, value: Option<f64>, aggregate: Aggregate) {
+        let Some(value) = value else {
+            return;
+        };
+        self.count += 1;
+        match aggregate {
+            Aggregate::Count => {}
+            Aggregate::Sum | Aggregate::Avg => self.sum += value,
+            Aggregate::Min => self.min = Some(self.min.map_or(value, |m| m.min(value))),
+            Aggregate::Max => self.max = Some(self.max.map_or(value, |m| m.max(value))),
+        }
+    }
+
+    fn finish(&self, aggregate: Aggregate) -> Option<f64> {
+        match aggregate {
+            Aggregate::Count => Some(self.count as f64),
+            _ if self.count == 0 => None,
+            Aggregate::Sum => Some(self.sum),
+            Aggregate::Avg => Some(self.sum / self.count as f64),
+            Aggregate::Min => self.min,
+            Aggregate::Max => self.max,
+        }
+    }
+}
+
+fn too_many_columns(column: &str) -> String {
+    format!(
+        "Column '{}' has more than {} distinct values, too many to pivot into columns",
+        column, MAX_COLUMNS
+    )
+}
+
+fn key(value: Option<String>) -> String {
+    value.unwrap_or_else(|| "NULL".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn spec(aggregate: Aggregate, values: Option<&str>) -> PivotSpec {
+        PivotSpec {
+            rows: "region".to_string(),
+            columns: "quarter".to_string(),
+            values: values.map(str::to_string),
+            aggregate,
+            limit: 100,
+        }
+    }
+
+    fn sales() -> (Vec<String>, Vec<Vec<Value>>) {
+        let columns = ["region", "quarter", "total"]
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        let rows = vec![
+            vec![json!("north"), json!("Q1"), json!("10.5")],
+            vec![json!("south"), json!("Q2"), json!(4)],
+            vec![json!("north"), json!("Q1"), json!(2)],
+            vec![json!("north"), json!("Q2"), json!(null)],
+            vec![json!(null), json!("Q1"), json!(1)],
+        ];
+        (columns, rows)
+    }
+
+    #[test]
+    fn test_pivot_rows() {
+        let (columns, rows) = sales();
+        let pivot = pivot_rows(&columns, &rows, &spec(Aggregate::Sum, Some("total"))).unwrap();
+        assert_eq!(pivot.value, "sum(total)");
+        assert_eq!(pivot.columns, vec!["Q1", "Q2"]);
+        assert_eq!(
+            pivot.rows,
+            vec![
+                PivotRow {
+                    key: "north".to_string(),
+                    values: vec![Some(12.5), None],
+                },
+                PivotRow {
+                    key: "south".to_string(),
+                    values: vec![None, Some(4.0)],
+                },
+                PivotRow {
+                    key: "NULL".to_string(),
+                    values: vec![Some(1.0), None],
+                },
+            ]
+        );
+        assert!(!pivot.truncated);
+    }
+
+    #[test]
+    fn test_pivot_rows_aggregates() {
+        let (columns, rows) = sales();
+        let north = |aggregate, values| {
+            pivot_rows(&columns, &rows, &spec(aggregate, values))
+                .unwrap()
+                .rows[0]
+                .values
+                .clone()
+        };
+        // Counting rows includes the NULL total, counting a column does not
+        assert_eq!(north(Aggregate::Count, None), vec![Some(2.0), Some(1.0)]);
+        assert_eq!(
+            north(Aggregate::Count, Some("total")),
+            vec![Some(2.0), Some(0.0)]
+        );
+        assert_eq!(north(Aggregate::Avg, Some("total")), vec![Some(6.25), None]);
+        assert_eq!(north(Aggregate::Min, Some("total")), vec![Some(2.0), None]);
+        assert_eq!(north(Aggregate::Max, Some("total")), vec![Some(10.5), None]);
+    }
+
+    #[test]
+    fn test_pivot_rows_limits_and_errors() {
+        let (columns, rows) = sales();
+        let pivot = pivot_rows(
+            &columns,
+            &rows,
+            &PivotSpec {
+                limit: 1,
+                ..spec(Aggregate::Count, None)
+            },
+        )
+        .unwrap();
+        assert_eq!(pivot.rows.len(), 1);
+        assert!(pivot.truncated);
+
+        let missing = PivotSpec {
+            columns: "month".to_string(),
+            ..spec(Aggregate::Count, None)
+        };
+        assert!(pivot_rows(&columns, &rows, &missing)
+            .unwrap_err()
+            .contains("no column 'month'"));
+        let text = spec(Aggregate::Sum, Some("region"));
+        assert!(pivot_rows(&columns, &rows, &text)
+            .unwrap_err()
+            .contains("non-numeric"));
+
+        let wide: Vec<Vec<Value>> = (0..=MAX_COLUMNS)
+            .map(|i| vec![json!("north"), json!(i), json!(1)])
+            .collect();
+        assert!(pivot_rows(&columns, &wide, &spec(Aggregate::Count, None))
+            .unwrap_err()
+            .contains("too many to pivot"));
+    }
+
+    #[test]
+    fn test_pivot_query() {
+        let query = pivot_query("public.sales", &spec(Aggregate::Avg, Some("Total")));
+        assert!(query.contains(
+            "SELECT (t.region)::text AS row_key, t.quarter AS column_value, \
+             (t.quarter)::text AS column_key, avg(t.\"Total\")::float8 AS value, \
+             dense_rank() OVER (ORDER BY t.region NULLS LAST) AS row_rank \
+             FROM public.sales t GROUP BY t.region, t.quarter"
+        ));
+        assert!(query.ends_with("WHERE row_rank <= 100 ORDER BY row_rank, column_rank"));
+        assert!(pivot_query("public.sales", &spec(Aggregate::Count, None)).contains("count(*)"));
+    }
+}
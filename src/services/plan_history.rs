@@ -0,0 +1,498 @@
+use crate::services::json_file;
+use crate::services::sql_lexer::fingerprint;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+/// Plan History Service
+///
+/// Keeps `EXPLAIN (FORMAT JSON)` plans captured from the query editor,
+/// grouped by statement fingerprint, so two plans of the same query can be
+/// compared node by node (e.g. before and after adding an index). Plans are
+/// held in memory and written to a JSON file after every change.
+use std::path::PathBuf;
+use tokio::sync::RwLock;
+
+/// Plans kept per fingerprint; older ones are dropped first
+pub const MAX_PLANS_PER_QUERY: usize = 20;
+
+/// A plan captured for a statement
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CapturedPlan {
+    pub id: String,
+    /// Normalized statement text shared by every plan of the same query
+    pub fingerprint: String,
+    pub query: String,
+    /// Whether the statement was run (`EXPLAIN ANALYZE`)
+    pub analyze: bool,
+    /// The `EXPLAIN (FORMAT JSON)` output
+    pub plan: Value,
+    pub captured_at: DateTime<Utc>,
+}
+
+impl CapturedPlan {
+    /// The top node of the plan tree
+    pub fn root(&self) -> Option<&Value> {
+        root_node(&self.plan)
+    }
+
+    pub fn summary(&self) -> PlanSummary {
+        let root = self.root();
+        PlanSummary {
+            id: self.id.clone(),
+            fingerprint: self.fingerprint.clone(),
+            query: self.query.clone(),
+            analyze: self.analyze,
+            root_node: root.map(|node| PlanNode::from_value(node).label()),
+            total_cost: root.and_then(|node| number(node, "Total Cost")),
+            execution_time_ms: execution_time(&self.plan),
+            captured_at: self.captured_at,
+        }
+    }
+}
+
+/// A captured plan without its tree, for listings
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlanSummary {
+    pub id: String,
+    pub fingerprint: String,
+    pub query: String,
+    pub analyze: bool,
+    pub root_node: Option<String>,
+    pub total_cost: Option<f64>,
+    pub execution_time_ms: Option<f64>,
+    pub captured_at: DateTime<Utc>,
+}
+
+/// Captured plans persisted to a JSON file
+pub struct PlanHistory {
+    path: PathBuf,
+    plans: RwLock<Vec<CapturedPlan>>,
+}
+
+impl PlanHistory {
+    /// Load captured plans from `path`, starting empty if the file does not exist
+    pub async fn load(path: impl Into<PathBuf>) -> Result<Self, String> {
+        let path = path.into();
+        let plans = json_file::load(&path, "plan history").await?;
+        Ok(Self {
+            path,
+            plans: RwLock::new(plans),
+        })
+    }
+
+    /// Store a plan under its statement's fingerprint
+    pub async fn record(
+        &self,
+        query: &str,
+        analyze: bool,
+        plan: Value,
+    ) -> Result<CapturedPlan, String> {
+        let query = query.trim().trim_end_matches(';').trim_end();
+        let captured = CapturedPlan {
+            id: uuid::Uuid::new_v4().to_string(),
+            fingerprint: fingerprint(query),
+            query: query.to_string(),
+            analyze,
+            plan,
+            captured_at: Utc::now(),
+        };
+
+        let mut plans = self.plans.write().await;
+        plans.push(captured.clone());
+        let same_query = plans
+            .iter()
+            .filter(|p| p.fingerprint == captured.fingerprint)
+            .count();
+        if same_query > MAX_PLANS_PER_QUERY {
+            let oldest = plans
+                .iter()
+                .position(|p| p.fingerprint == captured.fingerprint)
+                .expect("at least one plan has this fingerprint");
+            plans.remove(oldest);
+        }
+        self.persist(&plans).await?;
+        Ok(captured)
+    }
+
+    /// Plans of one fingerprint, or all of them, newest first
+    pub async fn list(&self, fingerprint: Option<&str>) -> Vec<PlanSummary> {
+        let plans = self.plans.read().await;
+        plans
+            .iter()
+            .rev()
+            .filter(|p| fingerprint.is_none_or(|f| p.fingerprint == f))
+            .map(CapturedPlan::summary)
+            .collect()
+    }
+
+    /// Get a captured plan by ID
+    pub async fn get(&self, id: &str) -> Option<CapturedPlan> {
+        let plans = self.plans.read().await;
+        plans.iter().find(|p| p.id == id).cloned()
+    }
+
+    /// Delete a captured plan, returning whether it existed
+    pub async fn delete(&self, id: &str) -> Result<bool, String> {
+        let mut plans = self.plans.write().await;
+        let before = plans.len();
+        plans.retain(|p| p.id != id);
+        if plans.len() == before {
+            return Ok(false);
+        }
+
+        self.persist(&plans).await?;
+        Ok(true)
+    }
+
+    async fn persist(&self, plans: &[CapturedPlan]) -> Result<(), String> {
+        json_file::save(&self.path, plans, "plan history").await
+    }
+}
+
+/// `Plan` of the first entry in `EXPLAIN (FORMAT JSON)` output
+fn root_node(plan: &Value) -> Option<&Value> {
+    plan.get(0)?.get("Plan")
+}
+
+fn execution_time(plan: &Value) -> Option<f64> {
+    plan.get(0).and_then(|p| number(p, "Execution Time"))
+}
+
+fn number(node: &Value, key: &str) -> Option<f64> {
+    node.get(key).and_then(Value::as_f64)
+}
+
+fn text(node: &Value, key: &str) -> Option<String> {
+    node.get(key).and_then(Value::as_str).map(str::to_string)
+}
+
+/// The fields of a plan node that comparisons look at
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlanNode {
+    pub node_type: String,
+    pub relation: Option<String>,
+    pub index: Option<String>,
+    pub total_cost: Option<f64>,
+    pub plan_rows: Option<f64>,
+    /// Only present for `EXPLAIN ANALYZE` plans
+    pub actual_total_time: Option<f64>,
+    pub actual_rows: Option<f64>,
+}
+
+impl PlanNode {
+    fn from_value(node: &Value) -> Self {
+        Self {
+            node_type: text(node, "Node Type").unwrap_or_else(|| "Unknown".to_string()),
+            relation: text(node, "Relation Name"),
+            index: text(node, "Index Name"),
+            total_cost: number(node, "Total Cost"),
+            plan_rows: number(node, "Plan Rows"),
+            actual_total_time: number(node, "Actual Total Time"),
+            actual_rows: number(node, "Actual Rows"),
+        }
+    }
+
+    /// `Index Scan using users_email_idx on users`, as in text plans
+    pub fn label(&self) -> String {
+        let mut label = self.node_type.clone();
+        if let Some(index) = &self.index {
+            label.push_str(&format!(" using {}", index));
+        }
+        if let Some(relation) = &self.relation {
+            label.push_str(&format!(" on {}", relation));
+        }
+        label
+    }
+}
+
+/// How a node of the second plan relates to the first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeChange {
+    Unchanged,
+    Changed,
+    Added,
+    Removed,
+}
+
+/// One position of the two plan trees
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NodeDiff {
+    /// Nesting level, 0 for the top node
+    pub depth: usize,
+    pub change: NodeChange,
+    pub before: Option<PlanNode>,
+    pub after: Option<PlanNode>,
+    /// What differs, e.g. `node type: Seq Scan → Index Scan`
+    pub differences: Vec<String>,
+}
+
+impl NodeDiff {
+    /// Label of the node in the second plan, or the first if it was removed
+    pub fn label(&self) -> String {
+        self.after
+            .as_ref()
+            .or(self.before.as_ref())
+            .map(PlanNode::label)
+            .unwrap_or_default()
+    }
+}
+
+/// Walk both plan trees in parallel, pairing children by position
+///
+/// Nodes are listed in tree order (parents before their children). A node is
+/// `changed` when its type, relation, index, cost or row estimate differs;
+/// actual times are reported alongside but vary between runs, so they do not
+/// mark a node as changed on their own.
+pub fn compare_plans(before: &Value, after: &Value) -> Vec<NodeDiff> {
+    let mut diffs = Vec::new();
+    compare_nodes(root_node(before), root_node(after), 0, &mut diffs);
+    diffs
+}
+
+fn compare_nodes(
+    before: Option<&Value>,
+    after: Option<&Value>,
+    depth: usize,
+    diffs: &mut Vec<NodeDiff>,
+) {
+    let (before_node, after_node) = match (before, after) {
+        (None, None) => return,
+        (Some(b), None) => (Some(PlanNode::from_value(b)), None),
+        (None, Some(a)) => (None, Some(PlanNode::from_value(a))),
+        (Some(b), Some(a)) => (Some(PlanNode::from_value(b)), Some(PlanNode::from_value(a))),
+    };
+
+    let (change, differences) = match (&before_node, &after_node) {
+        (Some(b), Some(a)) => {
+            let differences = node_differences(b, a);
+            let structural = differences.iter().any(|d| !d.starts_with("actual "));
+            let change = if structural {
+                NodeChange::Changed
+            } else {
+                NodeChange::Unchanged
+            };
+            (change, differences)
+        }
+        (Some(_), None) => (NodeChange::Removed, Vec::new()),
+        _ => (NodeChange::Added, Vec::new()),
+    };
+    diffs.push(NodeDiff {
+        depth,
+        change,
+        before: before_node,
+        after: after_node,
+        differences,
+    });
+
+    let children = |node: Option<&Value>| -> Vec<Value> {
+        node.and_then(|n| n.get("Plans"))
+            .and_then(Value::as_array)
+            .cloned()
+            .unwrap_or_default()
+    };
+    let before_children = children(before);
+    let after_children = children(after);
+    for i in 0..before_children.len().max(after_children.len()) {
+        compare_nodes(
+            before_children.get(i),
+            after_children.get(i),
+            depth + 1,
+            diffs,
+        );
+    }
+}
+
+fn node_differences(before: &PlanNode, after: &PlanNode) -> Vec<String> {
+    fn describe(value: &Option<String>) -> &str {
+        value.as_deref().unwrap_or("none")
+    }
+    fn number(value: Option<f64>) -> String {
+        value.map_or("none".to_string(), |v| format!("{:.2}", v))
+    }
+
+    let mut differences = Vec::new();
+    if before.node_type != after.node_type {
+        differences.push(format!(
+            "node type: {} → {}",
+            before.node_type, after.node_type
+        ));
+    }
+    if before.relation != after.relation {
+        differences.push(format!(
+            "relation: {} → {}",
+            describe(&before.relation),
+            describe(&after.relation)
+        ));
+    }
+    if before.index != after.index {
+        differences.push(format!(
+            "index: {} → {}",
+            describe(&before.index),
+            describe(&after.index)
+        ));
+    }
+    for (name, b, a) in [
+        ("cost", before.total_cost, after.total_cost),
+        ("estimated rows", before.plan_rows, after.plan_rows),
+        (
+            "actual time",
+            before.actual_total_time,
+            after.actual_total_time,
+        ),
+        ("actual rows", before.actual_rows, after.actual_rows),
+    ] {
+        if number(b) != number(a) {
+            differences.push(format!("{}: {} → {}", name, number(b), number(a)));
+        }
+    }
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("plan-history-{}", uuid::Uuid::new_v4()))
+            .join("plan_history.json")
+    }
+
+    fn seq_scan_plan() -> Value {
+        json!([{
+            "Plan": {
+                "Node Type": "Sort",
+                "Total Cost": 180.5,
+                "Plan Rows": 10,
+                "Plans": [{
+                    "Node Type": "Seq Scan",
+                    "Relation Name": "users",
+                    "Total Cost": 170.0,
+                    "Plan Rows": 10
+                }]
+            }
+        }])
+    }
+
+    fn index_scan_plan() -> Value {
+        json!([{
+            "Plan": {
+                "Node Type": "Sort",
+                "Total Cost": 180.5,
+                "Plan Rows": 10,
+                "Plans": [{
+                    "Node Type": "Index Scan",
+                    "Relation Name": "users",
+                    "Index Name": "users_email_idx",
+                    "Total Cost": 8.3,
+                    "Plan Rows": 10,
+                    "Plans": [{ "Node Type": "Result" }]
+                }]
+            },
+            "Execution Time": 0.42
+        }])
+    }
+
+    #[test]
+    fn test_compare_plans() {
+        let diffs = compare_plans(&seq_scan_plan(), &index_scan_plan());
+        let changes: Vec<(usize, NodeChange)> = diffs.iter().map(|d| (d.depth, d.change)).collect();
+        assert_eq!(
+            changes,
+            vec![
+                (0, NodeChange::Unchanged),
+                (1, NodeChange::Changed),
+                (2, NodeChange::Added)
+            ]
+        );
+        assert_eq!(
+            diffs[1].differences,
+            vec![
+                "node type: Seq Scan → Index Scan",
+                "index: none → users_email_idx",
+                "cost: 170.00 → 8.30",
+            ]
+        );
+        assert_eq!(
+            diffs[1].after.as_ref().unwrap().label(),
+            "Index Scan using users_email_idx on users"
+        );
+
+        let reversed = compare_plans(&index_scan_plan(), &seq_scan_plan());
+        assert_eq!(reversed[2].change, NodeChange::Removed);
+    }
+
+    #[test]
+    fn test_actual_time_alone_is_not_a_change() {
+        let run =
+            |ms: f64| json!([{ "Plan": { "Node Type": "Seq Scan", "Actual Total Time": ms } }]);
+        let diffs = compare_plans(&run(1.0), &run(2.5));
+        assert_eq!(diffs[0].change, NodeChange::Unchanged);
+        assert_eq!(diffs[0].differences, vec!["actual time: 1.00 → 2.50"]);
+    }
+
+    #[tokio::test]
+    async fn test_record_groups_by_fingerprint() {
+        let path = temp_path();
+        let history = PlanHistory::load(&path).await.unwrap();
+        let first = history
+            .record(
+                "SELECT * FROM users WHERE email = 'a'",
+                false,
+                seq_scan_plan(),
+            )
+            .await
+            .unwrap();
+        let second = history
+            .record(
+                "SELECT * FROM users WHERE email = 'b';",
+                true,
+                index_scan_plan(),
+            )
+            .await
+            .unwrap();
+        history.record("SELECT 1", false, json!([])).await.unwrap();
+        assert_eq!(first.fingerprint, second.fingerprint);
+
+        let same_query = history.list(Some(&first.fingerprint)).await;
+        let ids: Vec<&str> = same_query.iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(ids, vec![second.id.as_str(), first.id.as_str()]);
+        assert_eq!(same_query[0].execution_time_ms, Some(0.42));
+        assert_eq!(same_query[1].root_node.as_deref(), Some("Sort"));
+        assert_eq!(history.list(None).await.len(), 3);
+
+        let reloaded = PlanHistory::load(&path).await.unwrap();
+        assert_eq!(reloaded.get(&second.id).await, Some(second.clone()));
+        assert!(reloaded.delete(&second.id).await.unwrap());
+        assert!(!reloaded.delete(&second.id).await.unwrap());
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_oldest_plans_of_a_query_are_dropped() {
+        let path = temp_path();
+        let history = PlanHistory::load(&path).await.unwrap();
+        let other = history
+            .record("SELECT * FROM orders", false, json!([]))
+            .await
+            .unwrap();
+        let first = history
+            .record("SELECT * FROM users", false, json!([]))
+            .await
+            .unwrap();
+        for _ in 0..MAX_PLANS_PER_QUERY {
+            history
+                .record("SELECT * FROM users", false, json!([]))
+                .await
+                .unwrap();
+        }
+        assert!(history.get(&first.id).await.is_none());
+        assert!(history.get(&other.id).await.is_some());
+        assert_eq!(history.list(None).await.len(), MAX_PLANS_PER_QUERY + 1);
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,250 @@
+use crate::i18n;
+use crate::middleware::session::Session;
+use crate::services::session_service::SessionData;
+/// User Preferences
+///
+/// Preferences are a flat JSON object kept in the browser's server-side
+/// session, so they follow the session store (memory, file or database)
+/// rather than the browser. The frontend may store keys of its own; the
+/// keys below are checked when saved and read by handlers:
+/// - `page_size`: rows per page when browsing table data
+/// - `default_schema`: schema the studio opens on
+/// - `confirm_dangerous`: ask before data or schema changes in a transaction
+/// - `theme`: `light` or `dark`
+/// - `timezone`: IANA name (e.g. `Europe/Berlin`) or UTC offset for display
+/// - `locale`: language of pages and messages, e.g. `de`
+use serde_json::Value;
+
+/// Session key holding the preferences object
+pub const SESSION_KEY: &str = "preferences";
+
+/// Largest preferences object accepted, serialized
+pub const MAX_PREFERENCES_BYTES: usize = 16 * 1024;
+
+/// Largest `page_size` preference
+pub const MAX_PAGE_SIZE: u64 = 1000;
+
+/// Schema browsed when no default is set
+pub const DEFAULT_SCHEMA: &str = "public";
+
+const THEMES: [&str; 2] = ["light", "dark"];
+
+/// The preferences handlers act on, with unset keys left empty
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Preferences {
+    pub page_size: Option<u32>,
+    pub default_schema: Option<String>,
+    pub confirm_dangerous: bool,
+    pub theme: Option<String>,
+    pub timezone: Option<String>,
+    pub locale: Option<String>,
+}
+
+impl Preferences {
+    /// The session's preferences (none if the session cannot be loaded;
+    /// tower-sessions logs why)
+    pub async fn load(session: &Session) -> Self {
+        let data = session.get(SESSION_KEY).await.ok().flatten();
+        Self::from_data(&data.unwrap_or_default())
+    }
+
+    /// Read the known keys, ignoring values that do not validate (e.g. saved
+    /// before a key was checked)
+    pub fn from_data(data: &SessionData) -> Self {
+        let valid = |key: &str| data.get(key).filter(|v| validate(key, v).is_ok());
+        let string = |key: &str| valid(key).and_then(Value::as_str).map(str::to_string);
+        Self {
+            page_size: valid("page_size").and_then(Value::as_u64).map(|n| n as u32),
+            default_schema: string("default_schema"),
+            confirm_dangerous: valid("confirm_dangerous")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            theme: string("theme"),
+            timezone: string("timezone"),
+            locale: string("locale"),
+        }
+    }
+
+    /// The requested page size, else the preferred one, else `default`,
+    /// clamped to `1..=MAX_PAGE_SIZE`
+    pub fn page_size(&self, requested: Option<u32>, default: u32) -> u32 {
+        requested
+            .or(self.page_size)
+            .unwrap_or(default)
+            .clamp(1, MAX_PAGE_SIZE as u32)
+    }
+
+    /// The preferred schema, else `public`
+    pub fn schema(&self) -> &str {
+        self.default_schema.as_deref().unwrap_or(DEFAULT_SCHEMA)
+    }
+}
+
+/// Merge changes into stored preferences; a `null` value removes a key
+pub fn merge(preferences: &mut SessionData, changes: SessionData) -> Result<(), String> {
+    for (key, value) in changes {
+        if value.is_null() {
+            preferences.remove(&key);
+        } else {
+            validate(&key, &value)?;
+            preferences.insert(key, value);
+        }
+    }
+    Ok(())
+}
+
+/// Check that preferences fit within `MAX_PREFERENCES_BYTES`
+pub fn check_size(preferences: &SessionData) -> Result<(), String> {
+    let size = Value::Object(preferences.clone()).to_string().len();
+    if size > MAX_PREFERENCES_BYTES {
+        return Err(format!(
+            "Preferences are limited to {} bytes",
+            MAX_PREFERENCES_BYTES
+        ));
+    }
+    Ok(())
+}
+
+/// Check the value of a known key; other keys take any value
+pub fn validate(key: &str, value: &Value) -> Result<(), String> {
+    let valid = match key {
+        "page_size" => value
+            .as_u64()
+            .is_some_and(|n| (1..=MAX_PAGE_SIZE).contains(&n)),
+        "default_schema" => value
+            .as_str()
+            .is_some_and(|s| !s.trim().is_empty() && s.len() <= 63),
+        "confirm_dangerous" => value.is_boolean(),
+        "theme" => value.as_str().is_some_and(|s| THEMES.contains(&s)),
+        "timezone" => value.as_str().is_some_and(is_timezone),
+        "locale" => value.as_str().and_then(i18n::supported).is_some(),
+        _ => true,
+    };
+    if valid {
+        return Ok(());
+    }
+    Err(match key {
+        "page_size" => format!("page_size must be a number from 1 to {}", MAX_PAGE_SIZE),
+        "default_schema" => "default_schema must be a schema name".to_string(),
+        "confirm_dangerous" => "confirm_dangerous must be true or false".to_string(),
+        "theme" => format!("theme must be one of: {}", THEMES.join(", ")),
+        "locale" => format!(
+            "locale must be one of: {}",
+            i18n::LOCALES.map(|(code, _)| code).join(", ")
+        ),
+        _ => "timezone must be a name like Europe/Berlin or an offset like +02:00".to_string(),
+    })
+}
+
+/// Timezone names as Postgres and browsers spell them (`UTC`,
+/// `America/New_York`, `Etc/GMT+5`) or `+HH:MM` offsets; whether a name
+/// exists is left to whoever uses it
+fn is_timezone(value: &str) -> bool {
+    if let Some(offset) = value.strip_prefix(['+', '-']) {
+        let Some((hours, minutes)) = offset.split_once(':') else {
+            return false;
+        };
+        return matches!(hours.parse::<u8>(), Ok(0..=14))
+            && minutes.len() == 2
+            && matches!(minutes.parse::<u8>(), Ok(0..=59));
+    }
+    !value.is_empty()
+        && value.len() <= 64
+        && value.split('/').all(|part| {
+            part.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "_+-".contains(c))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn data(value: Value) -> SessionData {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn test_from_data() {
+        let preferences = Preferences::from_data(&data(json!({
+            "page_size": 25,
+            "default_schema": "sales",
+            "confirm_dangerous": true,
+            "theme": "light",
+            "timezone": "Europe/Berlin",
+            "locale": "de",
+            "editor_font_size": 14,
+        })));
+        assert_eq!(preferences.page_size(None, 100), 25);
+        assert_eq!(preferences.page_size(Some(10), 100), 10);
+        assert_eq!(preferences.page_size(Some(100_000_000), 100), 1000);
+        assert_eq!(preferences.page_size(Some(0), 100), 1);
+        assert_eq!(preferences.schema(), "sales");
+        assert!(preferences.confirm_dangerous);
+        assert_eq!(preferences.theme.as_deref(), Some("light"));
+        assert_eq!(preferences.locale.as_deref(), Some("de"));
+
+        let invalid = Preferences::from_data(&data(json!({
+            "page_size": 0,
+            "theme": "sepia",
+            "confirm_dangerous": "yes",
+            "locale": "tlh",
+        })));
+        assert_eq!(invalid, Preferences::default());
+        assert_eq!(invalid.page_size(None, 100), 100);
+        assert_eq!(invalid.schema(), "public");
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut stored = data(json!({ "theme": "dark", "editor_font_size": 14 }));
+        merge(
+            &mut stored,
+            data(json!({ "theme": null, "page_size": 50, "sidebar": "collapsed" })),
+        )
+        .unwrap();
+        assert_eq!(
+            Value::Object(stored.clone()),
+            json!({ "editor_font_size": 14, "page_size": 50, "sidebar": "collapsed" })
+        );
+
+        let error = merge(&mut stored, data(json!({ "page_size": 5000 }))).unwrap_err();
+        assert!(error.contains("page_size"));
+
+        assert!(check_size(&stored).is_ok());
+        merge(
+            &mut stored,
+            data(json!({ "notes": "x".repeat(MAX_PREFERENCES_BYTES) })),
+        )
+        .unwrap();
+        assert!(check_size(&stored).unwrap_err().contains("limited"));
+    }
+
+    #[test]
+    fn test_is_timezone() {
+        for valid in [
+            "UTC",
+            "America/New_York",
+            "Etc/GMT+5",
+            "America/Port-au-Prince",
+            "+05:30",
+            "-08:00",
+        ] {
+            assert!(is_timezone(valid), "{}", valid);
+        }
+        for invalid in [
+            "",
+            "Europe/",
+            "+5",
+            "+15:00",
+            "+05:60",
+            "UTC; DROP",
+            "../etc",
+        ] {
+            assert!(!is_timezone(invalid), "{}", invalid);
+        }
+    }
+}
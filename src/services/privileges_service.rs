@@ -0,0 +1,260 @@
+use serde::{Deserialize, Serialize};
+/// Privileges Service
+///
+/// Inspects and manages object privileges:
+/// - Table grants (information_schema.role_table_grants)
+/// - Schema grants (USAGE/CREATE on pg_namespace)
+/// - GRANT / REVOKE of SELECT, INSERT, UPDATE, DELETE on tables
+use sqlx::PgPool;
+
+use crate::services::identifier::{quote_ident, quote_qualified};
+use crate::services::schema_ops_service::SchemaOpsService;
+
+/// Privileges that can be granted or revoked through the API
+const ALLOWED_PRIVILEGES: [&str; 4] = ["SELECT", "INSERT", "UPDATE", "DELETE"];
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TablePrivilege {
+    pub grantor: String,
+    pub grantee: String,
+    pub table_schema: String,
+    pub table_name: String,
+    pub privilege_type: String,
+    pub is_grantable: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct SchemaPrivilege {
+    pub grantee: String,
+    pub privilege_type: String,
+    pub is_grantable: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrivilegeRequest {
+    pub role: String,
+    pub schema: String,
+    pub tables: Vec<String>,
+    pub privileges: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PrivilegeAction {
+    Grant,
+    Revoke,
+}
+
+pub struct PrivilegesService;
+
+impl PrivilegesService {
+    /// List table grants in a schema, optionally restricted to one table
+    pub async fn table_privileges(
+        pool: &PgPool,
+        schema: &str,
+        table: Option<&str>,
+    ) -> Result<Vec<TablePrivilege>, String> {
+        SchemaOpsService::validate_identifier(schema)?;
+        if let Some(table) = table {
+            SchemaOpsService::validate_identifier(table)?;
+        }
+
+        let query = r#"
+            SELECT
+                grantor::text,
+                grantee::text,
+                table_schema::text,
+                table_name::text,
+                privilege_type::text,
+                is_grantable::text
+            FROM information_schema.role_table_grants
+            WHERE table_schema = $1
+              AND ($2::text IS NULL OR table_name = $2)
+            ORDER BY table_name, grantee, privilege_type
+        "#;
+
+        sqlx::query_as::<_, TablePrivilege>(query)
+            .bind(schema)
+            .bind(table)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| format!("Failed to get table privileges: {}", e))
+    }
+
+    /// List privileges granted on the schema itself
+    pub async fn schema_privileges(
+        pool: &PgPool,
+        schema: &str,
+    ) -> Result<Vec<SchemaPrivilege>, String> {
+        SchemaOpsService::validate_identifier(schema)?;
+
+        let query = r#"
+            SELECT
+                CASE WHEN acl.grantee = 0 THEN 'PUBLIC'
+                     ELSE pg_get_userbyid(acl.grantee) END as grantee,
+                acl.privilege_type,
+                acl.is_grantable
+            FROM pg_namespace n,
+                 aclexplode(COALESCE(n.nspacl, acldefault('n', n.nspowner))) acl
+            WHERE n.nspname = $1
+            ORDER BY grantee, acl.privilege_type
+        "#;
+
+        sqlx::query_as::<_, SchemaPrivilege>(query)
+            .bind(schema)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| format!("Failed to get schema privileges: {}", e))
+    }
+
+    /// Grant or revoke privileges on the requested tables
+    pub async fn apply(
+        pool: &PgPool,
+        action: PrivilegeAction,
+        req: &PrivilegeRequest,
+    ) -> Result<String, String> {
+        let sql = Self::build_statement(action, req)?;
+
+        sqlx::query(&sql)
+            .execute(pool)
+            .await
+            .map_err(|e| format!("Failed to update privileges: {}", e))?;
+
+        let verb = match action {
+            PrivilegeAction::Grant => "granted to",
+            PrivilegeAction::Revoke => "revoked from",
+        };
+
+        Ok(format!(
+            "{} {} {} on {} table(s)",
+            req.privileges.join(", ").to_uppercase(),
+            verb,
+            req.role,
+            req.tables.len()
+        ))
+    }
+
+    /// Build a GRANT or REVOKE statement from a validated request
+    pub fn build_statement(
+        action: PrivilegeAction,
+        req: &PrivilegeRequest,
+    ) -> Result<String, String> {
+        if req.tables.is_empty() {
+            return Err("At least one table is required".to_string());
+        }
+        if req.privileges.is_empty() {
+            return Err("At least one privilege is required".to_string());
+        }
+
+        SchemaOpsService::validate_identifier(&req.schema)?;
+        for table in &req.tables {
+            SchemaOpsService::validate_identifier(table)?;
+        }
+
+        let mut privileges = Vec::with_capacity(req.privileges.len());
+        for privilege in &req.privileges {
+            let upper = privilege.trim().to_uppercase();
+            if !ALLOWED_PRIVILEGES.contains(&upper.as_str()) {
+                return Err(format!("Unsupported privilege: {}", privilege));
+            }
+            if !privileges.contains(&upper) {
+                privileges.push(upper);
+            }
+        }
+
+        // PUBLIC is a keyword, not a role name, so it must not be quoted
+        let role = if req.role.eq_ignore_ascii_case("public") {
+            "PUBLIC".to_string()
+        } else {
+            SchemaOpsService::validate_identifier(&req.role)?;
+            quote_ident(&req.role)
+        };
+
+        let tables = req
+            .tables
+            .iter()
+            .map(|t| quote_qualified(&req.schema, t))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        Ok(match action {
+            PrivilegeAction::Grant => format!(
+                "GRANT {} ON TABLE {} TO {}",
+                privileges.join(", "),
+                tables,
+                role
+            ),
+            PrivilegeAction::Revoke => format!(
+                "REVOKE {} ON TABLE {} FROM {}",
+                privileges.join(", "),
+                tables,
+                role
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(role: &str, tables: &[&str], privileges: &[&str]) -> PrivilegeRequest {
+        PrivilegeRequest {
+            role: role.to_string(),
+            schema: "public".to_string(),
+            tables: tables.iter().map(|t| t.to_string()).collect(),
+            privileges: privileges.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_build_grant_statement() {
+        let req = request("reporting", &["users", "orders"], &["select", "INSERT"]);
+        let sql = PrivilegesService::build_statement(PrivilegeAction::Grant, &req).unwrap();
+        assert_eq!(
+            sql,
+            "GRANT SELECT, INSERT ON TABLE public.users, public.orders TO reporting"
+        );
+    }
+
+    #[test]
+    fn test_build_revoke_statement_public() {
+        let req = request("public", &["users"], &["DELETE", "delete"]);
+        let sql = PrivilegesService::build_statement(PrivilegeAction::Revoke, &req).unwrap();
+        assert_eq!(sql, "REVOKE DELETE ON TABLE public.users FROM PUBLIC");
+    }
+
+    #[test]
+    fn test_build_statement_rejects_unknown_privilege() {
+        let req = request("reporting", &["users"], &["TRUNCATE"]);
+        assert!(PrivilegesService::build_statement(PrivilegeAction::Grant, &req).is_err());
+
+        let req = request("reporting", &["users"], &["ALL PRIVILEGES"]);
+        assert!(PrivilegesService::build_statement(PrivilegeAction::Grant, &req).is_err());
+    }
+
+    #[test]
+    fn test_build_statement_quotes_identifiers() {
+        let req = request(
+            "Odd \"role\"",
+            &["users; DROP TABLE x", "Orders"],
+            &["SELECT"],
+        );
+        let sql = PrivilegesService::build_statement(PrivilegeAction::Grant, &req).unwrap();
+        assert_eq!(
+            sql,
+            "GRANT SELECT ON TABLE public.\"users; DROP TABLE x\", public.\"Orders\" TO \"Odd \"\"role\"\"\""
+        );
+
+        let req = request("", &["users"], &["SELECT"]);
+        assert!(PrivilegesService::build_statement(PrivilegeAction::Grant, &req).is_err());
+    }
+
+    #[test]
+    fn test_build_statement_requires_tables_and_privileges() {
+        let req = request("reporting", &[], &["SELECT"]);
+        assert!(PrivilegesService::build_statement(PrivilegeAction::Grant, &req).is_err());
+
+        let req = request("reporting", &["users"], &[]);
+        assert!(PrivilegesService::build_statement(PrivilegeAction::Grant, &req).is_err());
+    }
+}
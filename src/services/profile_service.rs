@@ -0,0 +1,306 @@
+use crate::services::identifier::{self, quote_ident};
+use serde::Serialize;
+/// Data Profiling Service
+///
+/// Profiles the columns of a table or view over a sample of its rows, as a
+/// quick data-quality check:
+/// - Share of NULLs
+/// - Distinct values in the sample, and the planner's estimate for the whole
+///   table once it has been analyzed
+/// - Minimum and maximum, for types with a default btree ordering
+/// - Average length of the values' text form
+///
+/// Tables larger than the row budget are sampled with `TABLESAMPLE SYSTEM`,
+/// so profiling stays cheap on huge tables. Everything is computed in one
+/// statement over the sampled rows.
+use sqlx::{PgPool, Row};
+
+/// Rows profiled when no limit is given
+pub const DEFAULT_MAX_ROWS: u32 = 10_000;
+
+/// Largest row budget a caller may ask for
+pub const MAX_ROWS_LIMIT: u32 = 100_000;
+
+/// Sample a bit more than the row budget, since `SYSTEM` sampling returns a
+/// varying number of rows
+const OVERSAMPLE: f64 = 1.5;
+
+/// Profile of a table's columns
+#[derive(Debug, Clone, Serialize)]
+pub struct TableProfile {
+    pub schema: String,
+    pub table: String,
+    /// Planner estimate of the table's rows, 0 when unknown
+    pub estimated_rows: i64,
+    /// Rows the profile was computed over
+    pub sampled_rows: i64,
+    /// Percentage of pages sampled, unset when rows were read from the start
+    pub sample_percent: Option<f64>,
+    pub columns: Vec<ColumnProfile>,
+}
+
+/// Statistics of one column over the sampled rows
+#[derive(Debug, Clone, Serialize)]
+pub struct ColumnProfile {
+    pub name: String,
+    pub data_type: String,
+    pub null_percent: f64,
+    pub distinct_in_sample: i64,
+    /// From `pg_stats`, unset until the table is analyzed
+    pub estimated_distinct: Option<i64>,
+    /// Unset for types without an ordering (e.g. `json`) and all-NULL columns
+    pub min: Option<String>,
+    pub max: Option<String>,
+    /// Average length of the non-NULL values as text
+    pub avg_length: Option<f64>,
+}
+
+/// A column as read from the catalog
+struct ColumnMeta {
+    name: String,
+    data_type: String,
+    orderable: bool,
+    n_distinct: Option<f32>,
+}
+
+/// Profile `schema.table` over at most `max_rows` rows, sampling `sample`
+/// percent of its pages if given, or as many as the row budget needs
+pub async fn profile_table(
+    pool: &PgPool,
+    schema: &str,
+    table: &str,
+    sample: Option<f64>,
+    max_rows: u32,
+) -> Result<TableProfile, sqlx::Error> {
+    let relation = identifier::resolve_relation(pool, schema, table).await?;
+
+    let (relkind, reltuples): (i8, f32) = sqlx::query_as(
+        "SELECT relkind, reltuples FROM pg_catalog.pg_class WHERE oid = $1::regclass",
+    )
+    .bind(&relation)
+    .fetch_one(pool)
+    .await?;
+    let estimated_rows = reltuples.max(0.0) as i64;
+    let sampleable = matches!(relkind as u8, b'r' | b'p' | b'm');
+    let sample_percent = if sampleable {
+        sample.or_else(|| sample_percent_for(estimated_rows, max_rows))
+    } else {
+        None
+    };
+
+    let columns = column_meta(pool, &relation).await?;
+    let row = sqlx::query(&profile_query(
+        &relation,
+        &columns,
+        sample_percent,
+        max_rows,
+    ))
+    .fetch_one(pool)
+    .await?;
+
+    let sampled_rows: i64 = row.try_get(0)?;
+    let mut profiles = Vec::with_capacity(columns.len());
+    for (i, column) in columns.into_iter().enumerate() {
+        let base = 1 + i * 5;
+        let non_null: i64 = row.try_get(base)?;
+        profiles.push(ColumnProfile {
+            null_percent: null_percent(sampled_rows, non_null),
+            distinct_in_sample: row.try_get(base + 1)?,
+            estimated_distinct: column
+                .n_distinct
+                .and_then(|n| estimated_distinct(n, estimated_rows)),
+            min: row.try_get(base + 2)?,
+            max: row.try_get(base + 3)?,
+            avg_length: row.try_get(base + 4)?,
+            name: column.name,
+            data_type: column.data_type,
+        });
+    }
+
+    Ok(TableProfile {
+        schema: schema.to_string(),
+        table: table.to_string(),
+        estimated_rows,
+        sampled_rows,
+        sample_percent,
+        columns: profiles,
+    })
+}
+
+async fn column_meta(pool: &PgPool, relation: &str) -> Result<Vec<ColumnMeta>, sqlx::Error> {
+    let query = r#"
+        SELECT
+            a.attname::text AS name,
+            format_type(a.atttypid, a.atttypmod) AS data_type,
+            EXISTS (
+                SELECT 1
+                FROM pg_catalog.pg_opclass o
+                JOIN pg_catalog.pg_am m ON m.oid = o.opcmethod
+                WHERE m.amname = 'btree' AND o.opcdefault
+                  AND o.opcintype = CASE
+                      WHEN t.typtype = 'e' THEN 'anyenum'::regtype
+                      WHEN t.typcategory = 'A' THEN 'anyarray'::regtype
+                      WHEN t.typtype = 'd' THEN t.typbasetype
+                      ELSE a.atttypid
+                  END
+            ) AS orderable,
+            (
+                SELECT s.n_distinct
+                FROM pg_catalog.pg_stats s
+                JOIN pg_catalog.pg_class c ON c.relname = s.tablename
+                JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace AND n.nspname = s.schemaname
+                WHERE c.oid = a.attrelid AND s.attname = a.attname
+                ORDER BY s.inherited DESC
+                LIMIT 1
+            ) AS n_distinct
+        FROM pg_catalog.pg_attribute a
+        JOIN pg_catalog.pg_type t ON t.oid = a.atttypid
+        WHERE a.attrelid = $1::regclass AND a.attnum > 0 AND NOT a.attisdropped
+        ORDER BY a.attnum
+    "#;
+
+    let rows = sqlx::query(query).bind(relation).fetch_all(pool).await?;
+    Ok(rows
+        .iter()
+        .map(|row| ColumnMeta {
+            name: row.get("name"),
+            data_type: row.get("data_type"),
+            orderable: row.get("orderable"),
+            n_distinct: row.get("n_distinct"),
+        })
+        .collect())
+}
+
+/// The profiling statement: row count, then five values per column (non-NULL
+/// count, distinct count, min, max, average length)
+fn profile_query(
+    relation: &str,
+    columns: &[ColumnMeta],
+    sample_percent: Option<f64>,
+    max_rows: u32,
+) -> String {
+    // Shuffle sampled rows so the limit does not favor the first pages
+    let sampling = sample_percent
+        .map(|percent| format!(" TABLESAMPLE SYSTEM ({}) ORDER BY random()", percent))
+        .unwrap_or_default();
+
+    let mut select = vec!["count(*)".to_string()];
+    for column in columns {
+        let c = quote_ident(&column.name);
+        select.push(format!("count({})", c));
+        select.push(format!("count(DISTINCT {}::text)", c));
+        if column.orderable {
+            for direction in ["ASC", "DESC"] {
+                select.push(format!(
+                    "(SELECT {c} FROM sample WHERE {c} IS NOT NULL ORDER BY {c} {} LIMIT 1)::text",
+                    direction,
+                    c = c
+                ));
+            }
+        } else {
+            select.push("NULL::text".to_string());
+            select.push("NULL::text".to_string());
+        }
+        select.push(format!("avg(length({}::text))::float8", c));
+    }
+
+    format!(
+        "WITH sample AS (SELECT * FROM {}{} LIMIT {}) SELECT {} FROM sample",
+        relation,
+        sampling,
+        max_rows,
+        select.join(", ")
+    )
+}
+
+/// Percentage of pages to sample for about `max_rows` rows, `None` when the
+/// table is small enough (or unanalyzed) to read from the start
+pub fn sample_percent_for(estimated_rows: i64, max_rows: u32) -> Option<f64> {
+    if estimated_rows <= max_rows as i64 {
+        return None;
+    }
+    let percent = max_rows as f64 * OVERSAMPLE * 100.0 / estimated_rows as f64;
+    // Round to 4 significant digits to keep the statement readable
+    let scale = 10f64.powi(3 - percent.log10().floor() as i32);
+    Some(((percent * scale).round() / scale).min(100.0))
+}
+
+/// `pg_stats.n_distinct` as a count: negative values are a fraction of the
+/// rows, and 0 means unknown (e.g. for types without equality)
+pub fn estimated_distinct(n_distinct: f32, estimated_rows: i64) -> Option<i64> {
+    if n_distinct < 0.0 {
+        Some((-n_distinct as f64 * estimated_rows as f64).round() as i64)
+    } else if n_distinct > 0.0 {
+        Some(n_distinct as i64)
+    } else {
+        None
+    }
+}
+
+fn null_percent(rows: i64, non_null: i64) -> f64 {
+    if rows == 0 {
+        return 0.0;
+    }
+    ((rows - non_null) as f64 * 1000.0 / rows as f64).round() / 10.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(name: &str, orderable: bool) -> ColumnMeta {
+        ColumnMeta {
+            name: name.to_string(),
+            data_type: "text".to_string(),
+            orderable,
+            n_distinct: None,
+        }
+    }
+
+    #[test]
+    fn test_sample_percent_for() {
+        assert_eq!(sample_percent_for(0, 10_000), None);
+        assert_eq!(sample_percent_for(10_000, 10_000), None);
+        assert_eq!(sample_percent_for(20_000, 10_000), Some(75.0));
+        assert_eq!(sample_percent_for(12_000, 10_000), Some(100.0));
+        assert_eq!(sample_percent_for(300_000_000, 10_000), Some(0.005));
+        assert_eq!(sample_percent_for(7_000_000, 10_000), Some(0.2143));
+    }
+
+    #[test]
+    fn test_estimated_distinct() {
+        assert_eq!(estimated_distinct(42.0, 1_000), Some(42));
+        assert_eq!(estimated_distinct(-1.0, 1_000), Some(1_000));
+        assert_eq!(estimated_distinct(-0.25, 1_000), Some(250));
+        assert_eq!(estimated_distinct(0.0, 1_000), None);
+    }
+
+    #[test]
+    fn test_null_percent() {
+        assert_eq!(null_percent(0, 0), 0.0);
+        assert_eq!(null_percent(3, 2), 33.3);
+        assert_eq!(null_percent(8, 8), 0.0);
+    }
+
+    #[test]
+    fn test_profile_query() {
+        let query = profile_query(
+            "public.users",
+            &[column("email", true), column("Data", false)],
+            Some(0.5),
+            1000,
+        );
+        assert_eq!(
+            query,
+            "WITH sample AS (SELECT * FROM public.users TABLESAMPLE SYSTEM (0.5) \
+             ORDER BY random() LIMIT 1000) \
+             SELECT count(*), count(email), count(DISTINCT email::text), \
+             (SELECT email FROM sample WHERE email IS NOT NULL ORDER BY email ASC LIMIT 1)::text, \
+             (SELECT email FROM sample WHERE email IS NOT NULL ORDER BY email DESC LIMIT 1)::text, \
+             avg(length(email::text))::float8, \
+             count(\"Data\"), count(DISTINCT \"Data\"::text), NULL::text, NULL::text, \
+             avg(length(\"Data\"::text))::float8 FROM sample"
+        );
+        assert!(!profile_query("t", &[], None, 10).contains("TABLESAMPLE"));
+    }
+}
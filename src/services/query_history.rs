@@ -1,19 +1,29 @@
-use chrono::{DateTime, Utc};
+use crate::services::sql_lexer::fingerprint;
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 /// Query History Service
 ///
 /// Tracks executed queries for easy re-execution and history viewing.
 /// Stores queries in memory with configurable capacity.
+///
+/// Also summarizes the history: overall counts and duration percentiles,
+/// per-day activity and the slowest queries (see `analytics`). Entries are
+/// fingerprinted (literals and whitespace normalized away), so the same
+/// query run many times with different values can be grouped (see `groups`).
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use utoipa::ToSchema;
 
 /// A single query history entry
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct HistoryEntry {
     /// Unique identifier for this query
     pub id: String,
     /// The SQL query text
     pub query: String,
+    /// The query with literals, comments and extra whitespace normalized away
+    #[serde(default)]
+    pub fingerprint: String,
     /// When the query was executed
     pub executed_at: DateTime<Utc>,
     /// Execution time in milliseconds
@@ -24,6 +34,9 @@ pub struct HistoryEntry {
     pub success: bool,
     /// Error message if query failed
     pub error: Option<String>,
+    /// Labels for organizing entries (project, ticket, ...)
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
 impl HistoryEntry {
@@ -31,12 +44,14 @@ impl HistoryEntry {
     pub fn new(query: String, duration_ms: u64, row_count: Option<i64>) -> Self {
         Self {
             id: uuid::Uuid::new_v4().to_string(),
+            fingerprint: fingerprint(&query),
             query,
             executed_at: Utc::now(),
             duration_ms,
             row_count,
             success: true,
             error: None,
+            tags: Vec::new(),
         }
     }
 
@@ -44,14 +59,110 @@ impl HistoryEntry {
     pub fn failed(query: String, duration_ms: u64, error: String) -> Self {
         Self {
             id: uuid::Uuid::new_v4().to_string(),
+            fingerprint: fingerprint(&query),
             query,
             executed_at: Utc::now(),
             duration_ms,
             row_count: None,
             success: false,
             error: Some(error),
+            tags: Vec::new(),
+        }
+    }
+}
+
+/// Maximum length of a single tag
+const MAX_TAG_LENGTH: usize = 50;
+
+/// Trim tags, drop blanks and case-insensitive duplicates, and reject oversized ones
+pub fn normalize_tags(tags: Vec<String>) -> Result<Vec<String>, String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim();
+        if tag.is_empty() {
+            continue;
+        }
+        if tag.chars().count() > MAX_TAG_LENGTH {
+            return Err(format!(
+                "Tag cannot be longer than {} characters",
+                MAX_TAG_LENGTH
+            ));
+        }
+        if !normalized.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+            normalized.push(tag.to_string());
+        }
+    }
+    Ok(normalized)
+}
+
+/// Number of items carrying a tag
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: usize,
+}
+
+/// Count tags across items, merging case variants and ordering by tag
+pub fn count_tags<'a>(tag_lists: impl IntoIterator<Item = &'a [String]>) -> Vec<TagCount> {
+    let mut counts: Vec<TagCount> = Vec::new();
+    for tags in tag_lists {
+        for tag in tags {
+            match counts.iter_mut().find(|c| c.tag.eq_ignore_ascii_case(tag)) {
+                Some(existing) => existing.count += 1,
+                None => counts.push(TagCount {
+                    tag: tag.clone(),
+                    count: 1,
+                }),
+            }
         }
     }
+    counts.sort_by_key(|c| c.tag.to_lowercase());
+    counts
+}
+
+/// Check whether `tags` contains `tag`, ignoring case
+pub fn has_tag(tags: &[String], tag: &str) -> bool {
+    tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+}
+
+/// Criteria for searching history; unset fields match everything
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HistoryFilter {
+    /// Case-insensitive substring of the query text
+    pub search: Option<String>,
+    /// Inclusive lower bound on the execution time
+    pub from: Option<DateTime<Utc>>,
+    /// Inclusive upper bound on the execution time
+    pub to: Option<DateTime<Utc>>,
+    pub success: Option<bool>,
+    pub min_duration_ms: Option<u64>,
+    /// Only entries carrying this tag (case-insensitive)
+    pub tag: Option<String>,
+    /// Only runs of this query, as fingerprinted
+    pub fingerprint: Option<String>,
+}
+
+impl HistoryFilter {
+    /// Check whether an entry satisfies every set criterion
+    pub fn matches(&self, entry: &HistoryEntry) -> bool {
+        self.search
+            .as_deref()
+            .is_none_or(|s| entry.query.to_lowercase().contains(&s.to_lowercase()))
+            && self.from.is_none_or(|from| entry.executed_at >= from)
+            && self.to.is_none_or(|to| entry.executed_at <= to)
+            && self.success.is_none_or(|success| entry.success == success)
+            && self
+                .min_duration_ms
+                .is_none_or(|min| entry.duration_ms >= min)
+            && self
+                .tag
+                .as_deref()
+                .is_none_or(|tag| has_tag(&entry.tags, tag))
+            && self
+                .fingerprint
+                .as_deref()
+                .is_none_or(|f| entry.fingerprint == f)
+    }
 }
 
 /// Query history manager
@@ -97,12 +208,37 @@ impl QueryHistory {
         entries.iter().rev().take(count).cloned().collect()
     }
 
+    /// Get entries matching a filter, newest first
+    pub async fn search(&self, filter: &HistoryFilter) -> Vec<HistoryEntry> {
+        let entries = self.entries.read().await;
+        entries
+            .iter()
+            .rev()
+            .filter(|e| filter.matches(e))
+            .cloned()
+            .collect()
+    }
+
     /// Get a specific entry by ID
     pub async fn get_by_id(&self, id: &str) -> Option<HistoryEntry> {
         let entries = self.entries.read().await;
         entries.iter().find(|e| e.id == id).cloned()
     }
 
+    /// Replace the tags on an entry, returning the updated entry if it exists
+    pub async fn set_tags(&self, id: &str, tags: Vec<String>) -> Option<HistoryEntry> {
+        let mut entries = self.entries.write().await;
+        let entry = entries.iter_mut().find(|e| e.id == id)?;
+        entry.tags = tags;
+        Some(entry.clone())
+    }
+
+    /// Tags used across the history with entry counts
+    pub async fn tag_counts(&self) -> Vec<TagCount> {
+        let entries = self.entries.read().await;
+        count_tags(entries.iter().map(|e| e.tags.as_slice()))
+    }
+
     /// Get history entries by query text
     pub async fn get_by_query(&self, query: &str) -> Vec<HistoryEntry> {
         let entries = self.entries.read().await;
@@ -149,27 +285,140 @@ impl QueryHistory {
         } else {
             0
         };
+        let durations = sorted_durations(entries.iter());
 
-        let most_common = entries
-            .iter()
-            .fold(std::collections::HashMap::new(), |mut map, entry| {
-                *map.entry(entry.query.clone()).or_insert(0) += 1;
-                map
-            })
-            .into_iter()
-            .max_by_key(|&(_, count)| count)
-            .map(|(query, _)| query);
+        let groups = group_entries(entries.iter().rev(), GroupOrder::Executions);
 
         HistoryStats {
             total_queries: total,
             successful_queries: successful,
             failed_queries: failed,
             average_duration_ms: avg_duration,
-            most_common_query: most_common,
+            p50_duration_ms: percentile(&durations, 50.0),
+            p95_duration_ms: percentile(&durations, 95.0),
+            distinct_queries: groups.len(),
+            most_common_query: groups.into_iter().next().map(|g| g.query),
+        }
+    }
+
+    /// Entries matching a filter grouped by fingerprint, in the given order
+    pub async fn groups(&self, filter: &HistoryFilter, order: GroupOrder) -> Vec<QueryGroup> {
+        let entries = self.entries.read().await;
+        group_entries(entries.iter().rev().filter(|e| filter.matches(e)), order)
+    }
+
+    /// Stats plus activity for each of the last `days` days (ending today,
+    /// UTC, days without queries included) and the `top` slowest queries
+    pub async fn analytics(&self, days: u32, top: usize) -> HistoryAnalytics {
+        let stats = self.stats().await;
+        let entries = self.entries.read().await;
+
+        let today = Utc::now().date_naive();
+        let days = (0..days.max(1) as u64)
+            .rev()
+            .filter_map(|ago| today.checked_sub_days(chrono::Days::new(ago)))
+            .map(|date| {
+                let day: Vec<&HistoryEntry> = entries
+                    .iter()
+                    .filter(|e| e.executed_at.date_naive() == date)
+                    .collect();
+                let durations = sorted_durations(day.iter().copied());
+                let failed = day.iter().filter(|e| !e.success).count();
+                DailyStats {
+                    date,
+                    queries: day.len(),
+                    failed,
+                    failure_rate: failure_rate(failed, day.len()),
+                    p50_duration_ms: percentile(&durations, 50.0),
+                    p95_duration_ms: percentile(&durations, 95.0),
+                }
+            })
+            .collect();
+
+        let mut slowest: Vec<HistoryEntry> = entries.iter().cloned().collect();
+        slowest.sort_by_key(|e| std::cmp::Reverse(e.duration_ms));
+        slowest.truncate(top);
+
+        HistoryAnalytics {
+            stats,
+            days,
+            slowest,
         }
     }
 }
 
+/// Group entries, given newest first, by fingerprint
+fn group_entries<'a>(
+    entries: impl Iterator<Item = &'a HistoryEntry>,
+    order: GroupOrder,
+) -> Vec<QueryGroup> {
+    let mut groups: Vec<QueryGroup> = Vec::new();
+    let mut index = std::collections::HashMap::new();
+    let mut durations: Vec<Vec<u64>> = Vec::new();
+    for entry in entries {
+        let i = *index.entry(entry.fingerprint.as_str()).or_insert_with(|| {
+            groups.push(QueryGroup {
+                fingerprint: entry.fingerprint.clone(),
+                query: entry.query.clone(),
+                last_id: entry.id.clone(),
+                executions: 0,
+                failures: 0,
+                total_duration_ms: 0,
+                average_duration_ms: 0,
+                p95_duration_ms: 0,
+                max_duration_ms: 0,
+                first_executed_at: entry.executed_at,
+                last_executed_at: entry.executed_at,
+            });
+            durations.push(Vec::new());
+            groups.len() - 1
+        });
+        let group = &mut groups[i];
+        group.executions += 1;
+        group.failures += usize::from(!entry.success);
+        group.total_duration_ms += entry.duration_ms;
+        group.max_duration_ms = group.max_duration_ms.max(entry.duration_ms);
+        group.first_executed_at = entry.executed_at;
+        durations[i].push(entry.duration_ms);
+    }
+
+    for (group, mut durations) in groups.iter_mut().zip(durations) {
+        durations.sort_unstable();
+        group.average_duration_ms = group.total_duration_ms / group.executions as u64;
+        group.p95_duration_ms = percentile(&durations, 95.0);
+    }
+    // Stable sorts keep the most recently run group first among ties
+    match order {
+        GroupOrder::Executions => groups.sort_by_key(|g| std::cmp::Reverse(g.executions)),
+        GroupOrder::TotalDuration => groups.sort_by_key(|g| std::cmp::Reverse(g.total_duration_ms)),
+        GroupOrder::Recent => {}
+    }
+    groups
+}
+
+fn sorted_durations<'a>(entries: impl Iterator<Item = &'a HistoryEntry>) -> Vec<u64> {
+    let mut durations: Vec<u64> = entries.map(|e| e.duration_ms).collect();
+    durations.sort_unstable();
+    durations
+}
+
+/// Nearest-rank percentile of sorted values, 0 when there are none
+pub fn percentile(sorted: &[u64], p: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Failed share of the queries, as a percentage with one decimal
+fn failure_rate(failed: usize, total: usize) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    (failed as f64 * 1000.0 / total as f64).round() / 10.0
+}
+
 /// Statistics about query history
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HistoryStats {
@@ -177,9 +426,81 @@ pub struct HistoryStats {
     pub successful_queries: usize,
     pub failed_queries: usize,
     pub average_duration_ms: u64,
+    pub p50_duration_ms: u64,
+    pub p95_duration_ms: u64,
+    /// Different queries once literals are ignored
+    pub distinct_queries: usize,
+    /// Latest text of the most often run query
     pub most_common_query: Option<String>,
 }
 
+/// Runs of one query fingerprint
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct QueryGroup {
+    pub fingerprint: String,
+    /// Text of the latest run
+    pub query: String,
+    /// Id of the latest run, e.g. to run it again
+    pub last_id: String,
+    pub executions: usize,
+    pub failures: usize,
+    pub total_duration_ms: u64,
+    pub average_duration_ms: u64,
+    pub p95_duration_ms: u64,
+    pub max_duration_ms: u64,
+    pub first_executed_at: DateTime<Utc>,
+    pub last_executed_at: DateTime<Utc>,
+}
+
+/// How query groups are sorted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GroupOrder {
+    /// Most runs first
+    #[default]
+    Executions,
+    /// Most time spent first
+    TotalDuration,
+    /// Most recently run first
+    Recent,
+}
+
+impl GroupOrder {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "executions" => Ok(GroupOrder::Executions),
+            "total_duration" => Ok(GroupOrder::TotalDuration),
+            "recent" => Ok(GroupOrder::Recent),
+            other => Err(format!(
+                "Unknown sort '{}' (expected executions, total_duration or recent)",
+                other
+            )),
+        }
+    }
+}
+
+/// History stats with activity over time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryAnalytics {
+    #[serde(flatten)]
+    pub stats: HistoryStats,
+    /// Oldest day first
+    pub days: Vec<DailyStats>,
+    /// Slowest first
+    pub slowest: Vec<HistoryEntry>,
+}
+
+/// Queries run on one day (UTC)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DailyStats {
+    pub date: NaiveDate,
+    pub queries: usize,
+    pub failed: usize,
+    /// Percentage of the day's queries that failed
+    pub failure_rate: f64,
+    pub p50_duration_ms: u64,
+    pub p95_duration_ms: u64,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -324,5 +645,228 @@ mod tests {
         assert_eq!(stats.successful_queries, 2);
         assert_eq!(stats.failed_queries, 1);
         assert_eq!(stats.average_duration_ms, 116); // (100 + 200 + 50) / 3
+        assert_eq!(stats.p50_duration_ms, 100);
+        assert_eq!(stats.p95_duration_ms, 200);
+    }
+
+    #[tokio::test]
+    async fn test_groups() {
+        let history = QueryHistory::new(10);
+        for (id, ms) in [(1, 10), (2, 30), (3, 20)] {
+            history
+                .add(HistoryEntry::new(
+                    format!("SELECT * FROM users WHERE id = {}", id),
+                    ms,
+                    Some(1),
+                ))
+                .await;
+        }
+        history
+            .add(HistoryEntry::failed(
+                "SELECT * FROM orders".to_string(),
+                500,
+                "permission denied".to_string(),
+            ))
+            .await;
+        history
+            .add(HistoryEntry::new(
+                "select *  FROM users WHERE id = 4".to_string(),
+                5,
+                Some(1),
+            ))
+            .await;
+
+        let groups = history
+            .groups(&HistoryFilter::default(), GroupOrder::Executions)
+            .await;
+        // Keywords keep their case, so the last query is one of its own
+        assert_eq!(groups.len(), 3);
+        let users = &groups[0];
+        assert_eq!(users.fingerprint, "SELECT * FROM users WHERE id = ?");
+        assert_eq!(users.query, "SELECT * FROM users WHERE id = 3");
+        assert_eq!((users.executions, users.failures), (3, 0));
+        assert_eq!(
+            (users.total_duration_ms, users.average_duration_ms),
+            (60, 20)
+        );
+        assert_eq!((users.p95_duration_ms, users.max_duration_ms), (30, 30));
+        assert!(users.first_executed_at <= users.last_executed_at);
+
+        let by_time = history
+            .groups(&HistoryFilter::default(), GroupOrder::TotalDuration)
+            .await;
+        assert_eq!(by_time[0].fingerprint, "SELECT * FROM orders");
+        assert_eq!(by_time[0].failures, 1);
+
+        let recent = history
+            .groups(&HistoryFilter::default(), GroupOrder::Recent)
+            .await;
+        assert_eq!(recent.len(), 3);
+        assert_eq!(recent[0].query, "select *  FROM users WHERE id = 4");
+
+        let runs = history
+            .search(&HistoryFilter {
+                fingerprint: Some(users.fingerprint.clone()),
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(runs.len(), 3);
+        assert_eq!(runs[0].id, users.last_id);
+
+        let stats = history.stats().await;
+        assert_eq!(stats.distinct_queries, 3);
+        assert_eq!(
+            stats.most_common_query.as_deref(),
+            Some("SELECT * FROM users WHERE id = 3")
+        );
+        assert!(GroupOrder::parse("slowest").is_err());
+    }
+
+    #[test]
+    fn test_percentile() {
+        assert_eq!(percentile(&[], 50.0), 0);
+        assert_eq!(percentile(&[7], 95.0), 7);
+        let values: Vec<u64> = (1..=20).collect();
+        assert_eq!(percentile(&values, 50.0), 10);
+        assert_eq!(percentile(&values, 95.0), 19);
+        assert_eq!(percentile(&values, 0.0), 1);
+        assert_eq!(percentile(&values, 100.0), 20);
+    }
+
+    #[tokio::test]
+    async fn test_analytics() {
+        let history = QueryHistory::new(10);
+        let mut old = HistoryEntry::new("SELECT pg_sleep(5)".to_string(), 5000, Some(1));
+        old.executed_at = Utc::now() - chrono::Duration::days(2);
+        history.add(old).await;
+        history
+            .add(HistoryEntry::new("SELECT 1".to_string(), 10, Some(1)))
+            .await;
+        history
+            .add(HistoryEntry::failed(
+                "SELEC 1".to_string(),
+                2,
+                "syntax error".to_string(),
+            ))
+            .await;
+
+        let analytics = history.analytics(3, 2).await;
+        assert_eq!(analytics.stats.total_queries, 3);
+        let counts: Vec<(usize, usize)> = analytics
+            .days
+            .iter()
+            .map(|d| (d.queries, d.failed))
+            .collect();
+        assert_eq!(counts, vec![(1, 0), (0, 0), (2, 1)]);
+        assert_eq!(analytics.days[2].date, Utc::now().date_naive());
+        assert_eq!(analytics.days[2].failure_rate, 50.0);
+        assert_eq!(analytics.days[2].p95_duration_ms, 10);
+        let slowest: Vec<u64> = analytics.slowest.iter().map(|e| e.duration_ms).collect();
+        assert_eq!(slowest, vec![5000, 10]);
+
+        // Days before the window still count in the totals
+        assert_eq!(history.analytics(1, 5).await.days[0].queries, 2);
+    }
+
+    #[tokio::test]
+    async fn test_search() {
+        let history = QueryHistory::new(10);
+        history
+            .add(HistoryEntry::new(
+                "SELECT * FROM users".to_string(),
+                20,
+                Some(5),
+            ))
+            .await;
+        history
+            .add(HistoryEntry::new(
+                "SELECT count(*) FROM Orders".to_string(),
+                900,
+                Some(1),
+            ))
+            .await;
+        history
+            .add(HistoryEntry::failed(
+                "SELECT * FROM orders_archive".to_string(),
+                5,
+                "relation does not exist".to_string(),
+            ))
+            .await;
+
+        let all = history.search(&HistoryFilter::default()).await;
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[0].query, "SELECT * FROM orders_archive"); // newest first
+
+        let orders = history
+            .search(&HistoryFilter {
+                search: Some("orders".to_string()),
+                success: Some(true),
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].query, "SELECT count(*) FROM Orders");
+
+        let slow = history
+            .search(&HistoryFilter {
+                min_duration_ms: Some(100),
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(slow.len(), 1);
+
+        let future = history
+            .search(&HistoryFilter {
+                from: Some(Utc::now() + chrono::Duration::hours(1)),
+                ..Default::default()
+            })
+            .await;
+        assert!(future.is_empty());
+    }
+
+    #[test]
+    fn test_normalize_tags() {
+        let tags = normalize_tags(vec![
+            " billing ".to_string(),
+            "".to_string(),
+            "Billing".to_string(),
+            "JIRA-123".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(tags, vec!["billing", "JIRA-123"]);
+        assert!(normalize_tags(vec!["x".repeat(51)]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tag_entries_and_filter() {
+        let history = QueryHistory::new(10);
+        let tagged = HistoryEntry::new("SELECT 1".to_string(), 10, Some(1));
+        history.add(tagged.clone()).await;
+        history
+            .add(HistoryEntry::new("SELECT 2".to_string(), 10, Some(1)))
+            .await;
+
+        let updated = history
+            .set_tags(&tagged.id, vec!["Billing".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(updated.tags, vec!["Billing"]);
+        assert!(history.set_tags("missing", Vec::new()).await.is_none());
+
+        let filter = HistoryFilter {
+            tag: Some("billing".to_string()),
+            ..Default::default()
+        };
+        let found = history.search(&filter).await;
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].id, tagged.id);
+
+        assert_eq!(
+            history.tag_counts().await,
+            vec![TagCount {
+                tag: "Billing".to_string(),
+                count: 1
+            }]
+        );
     }
 }
@@ -2,24 +2,254 @@
 // Handles SQL query execution and result processing
 
 use crate::models::QueryResult;
-use serde_json::json;
-use sqlx::{Column, Pool, Postgres, Row};
+use crate::services::pg_value;
+use futures::{Stream, TryStreamExt};
+use serde::Serialize;
+use sqlx::{Column, Connection, PgConnection, Pool, Postgres, Row};
+use std::cell::Cell;
+use std::future::Future;
 use std::time::Instant;
 
+tokio::task_local! {
+    static ROW_LIMIT: RowLimit;
+}
+
+/// Most rows a query run by the current task may fetch
+struct RowLimit {
+    max: usize,
+    exceeded: Cell<bool>,
+}
+
+/// A query was stopped because it returned more rows than allowed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RowLimitExceeded {
+    pub max: usize,
+}
+
+impl std::fmt::Display for RowLimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The query returned more than {} rows, the limit per query; add a LIMIT or a narrower filter",
+            self.max
+        )
+    }
+}
+
+impl std::error::Error for RowLimitExceeded {}
+
+/// Run `f` with every query it executes stopped as soon as it returns more
+/// than `max_rows` rows, so an oversized result is never held in memory
+///
+/// Returns whether a query was stopped; the query itself fails with
+/// `RowLimitExceeded`. Without a limit, `f` runs unchanged.
+pub async fn with_row_limit<F: Future>(max_rows: Option<usize>, f: F) -> (F::Output, bool) {
+    let Some(max) = max_rows else {
+        return (f.await, false);
+    };
+    let limit = RowLimit {
+        max,
+        exceeded: Cell::new(false),
+    };
+    ROW_LIMIT
+        .scope(limit, async {
+            let output = f.await;
+            (output, ROW_LIMIT.with(|limit| limit.exceeded.get()))
+        })
+        .await
+}
+
+/// Refuse one more row once the current task's limit is reached
+pub fn check_row_limit(rows: usize) -> Result<(), RowLimitExceeded> {
+    ROW_LIMIT
+        .try_with(|limit| {
+            if rows > limit.max {
+                limit.exceeded.set(true);
+                Err(RowLimitExceeded { max: limit.max })
+            } else {
+                Ok(())
+            }
+        })
+        .unwrap_or(Ok(()))
+}
+
+/// Collect a row stream, giving up as soon as it passes the row limit
+async fn collect_rows<T, E>(
+    rows: impl Stream<Item = Result<T, E>>,
+) -> Result<Vec<T>, Box<dyn std::error::Error>>
+where
+    E: std::error::Error + 'static,
+{
+    let mut rows = std::pin::pin!(rows);
+    let mut collected = Vec::new();
+    while let Some(row) = rows.try_next().await? {
+        check_row_limit(collected.len() + 1)?;
+        collected.push(row);
+    }
+    Ok(collected)
+}
+
+/// Fetch every row of `query` like `fetch_all`, giving up as soon as it
+/// passes the current task's row limit
+///
+/// A stopped fetch fails with `RowLimitExceeded` as a decode error;
+/// `Quota::limit_rows` reports it as a refusal.
+pub async fn fetch_all_limited<'q>(
+    query: sqlx::query::Query<'q, Postgres, sqlx::postgres::PgArguments>,
+    pool: &Pool<Postgres>,
+) -> Result<Vec<sqlx::postgres::PgRow>, sqlx::Error> {
+    let mut rows = query.fetch(pool);
+    let mut collected = Vec::new();
+    while let Some(row) = rows.try_next().await? {
+        check_row_limit(collected.len() + 1).map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+        collected.push(row);
+    }
+    Ok(collected)
+}
+
 /// Executes a SQL query and returns the results
+///
+/// With `read_only` set, the statement runs inside a `READ ONLY` transaction
+/// that is always rolled back, so the server itself rejects any write that
+/// gets past `validate_query`.
 pub async fn execute_query(
     pool: &Pool<Postgres>,
     query: &str,
+    read_only: bool,
+) -> Result<QueryResult, Box<dyn std::error::Error>> {
+    execute_query_with_params(pool, query, &[], read_only).await
+}
+
+/// A value bound to a positional parameter (`$1`, `$2`, ...)
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryParam {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+}
+
+impl QueryParam {
+    /// Map a JSON value to a parameter; arrays and objects are passed as JSON text
+    pub fn from_json(value: &serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::Null => QueryParam::Null,
+            serde_json::Value::Bool(b) => QueryParam::Bool(*b),
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(i) => QueryParam::Int(i),
+                None => QueryParam::Float(n.as_f64().unwrap_or_default()),
+            },
+            serde_json::Value::String(s) => QueryParam::Text(s.clone()),
+            other => QueryParam::Text(other.to_string()),
+        }
+    }
+}
+
+/// Executes a SQL query with bound parameters and returns the results
+///
+/// Parameters are sent separately from the statement text, never interpolated.
+pub async fn execute_query_with_params(
+    pool: &Pool<Postgres>,
+    query: &str,
+    params: &[QueryParam],
+    read_only: bool,
 ) -> Result<QueryResult, Box<dyn std::error::Error>> {
     // Basic validation
-    let trimmed = query.trim();
-    if trimmed.is_empty() {
+    if query.trim().is_empty() {
         return Err("Query cannot be empty".into());
     }
 
+    let mut conn = pool.acquire().await?;
+    execute_on(&mut conn, query, params, read_only).await
+}
+
+/// Outcome of one statement of a script
+#[derive(Debug, Clone)]
+pub struct StatementResult {
+    pub sql: String,
+    pub duration_ms: u64,
+    pub result: Result<QueryResult, String>,
+}
+
+/// Executes statements in order on one connection, stopping at the first error
+///
+/// Sharing the connection lets session settings (`SET search_path ...`) carry
+/// over to later statements. Statements after a failure are not run, so the
+/// returned list can be shorter than `statements`.
+pub async fn execute_script(
+    pool: &Pool<Postgres>,
+    statements: &[String],
+    read_only: bool,
+) -> Result<Vec<StatementResult>, Box<dyn std::error::Error>> {
+    let mut conn = pool.acquire().await?;
+    let mut results = Vec::with_capacity(statements.len());
+
+    for sql in statements {
+        let start = Instant::now();
+        let result = execute_on(&mut conn, sql, &[], read_only)
+            .await
+            .map_err(|e| e.to_string());
+        let failed = result.is_err();
+        results.push(StatementResult {
+            sql: sql.clone(),
+            duration_ms: start.elapsed().as_millis() as u64,
+            result,
+        });
+        if failed {
+            break;
+        }
+    }
+
+    Ok(results)
+}
+
+/// Executes a query on a specific connection (see `execute_query_with_params`)
+///
+/// Rows are fetched one at a time, so a query run under `with_row_limit` is
+/// stopped as soon as it passes the limit.
+pub async fn execute_on(
+    conn: &mut PgConnection,
+    query: &str,
+    params: &[QueryParam],
+    read_only: bool,
+) -> Result<QueryResult, Box<dyn std::error::Error>> {
+    let trimmed = query.trim();
     let start = Instant::now();
 
-    let rows = sqlx::query(trimmed).fetch_all(pool).await?;
+    // DML without RETURNING produces no rows, only an affected-row count
+    if is_non_returning_dml(trimmed) {
+        let affected_rows = if read_only {
+            let mut tx = begin_read_only(conn).await?;
+            let done = bind_params(sqlx::query(trimmed), params)
+                .execute(&mut *tx)
+                .await?;
+            tx.rollback().await?;
+            done.rows_affected()
+        } else {
+            bind_params(sqlx::query(trimmed), params)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected()
+        };
+
+        return Ok(QueryResult {
+            columns: Vec::new(),
+            rows: Vec::new(),
+            row_count: 0,
+            affected_rows: Some(affected_rows),
+            execution_time_ms: Some(start.elapsed().as_millis()),
+        });
+    }
+
+    let rows = if read_only {
+        let mut tx = begin_read_only(conn).await?;
+        let rows = collect_rows(bind_params(sqlx::query(trimmed), params).fetch(&mut *tx)).await?;
+        tx.rollback().await?;
+        rows
+    } else {
+        collect_rows(bind_params(sqlx::query(trimmed), params).fetch(&mut *conn)).await?
+    };
 
     let execution_time_ms = start.elapsed().as_millis();
 
@@ -35,26 +265,12 @@ pub async fn execute_query(
 
     let row_count = rows.len();
 
-    // Convert SQL rows to JSON values
+    // Convert SQL rows to JSON values by column type
     let rows_data: Vec<Vec<serde_json::Value>> = rows
         .iter()
         .map(|row| {
-            columns
-                .iter()
-                .enumerate()
-                .map(|(i, _)| {
-                    row.try_get::<String, _>(i)
-                        .map(|v| json!(v))
-                        .or_else(|_| row.try_get::<i32, _>(i).map(|v| json!(v)))
-                        .or_else(|_| row.try_get::<i64, _>(i).map(|v| json!(v)))
-                        .or_else(|_| row.try_get::<f64, _>(i).map(|v| json!(v)))
-                        .or_else(|_| row.try_get::<bool, _>(i).map(|v| json!(v)))
-                        .or_else(|_| {
-                            row.try_get::<sqlx::types::Uuid, _>(i)
-                                .map(|v| json!(v.to_string()))
-                        })
-                        .unwrap_or(json!(null))
-                })
+            (0..columns.len())
+                .map(|i| pg_value::cell_to_json(row, i))
                 .collect()
         })
         .collect();
@@ -68,6 +284,261 @@ pub async fn execute_query(
     })
 }
 
+/// Start a transaction the server will refuse to write in
+async fn begin_read_only(
+    conn: &mut PgConnection,
+) -> Result<sqlx::Transaction<'_, Postgres>, sqlx::Error> {
+    let mut tx = conn.begin().await?;
+    sqlx::query("SET TRANSACTION READ ONLY")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("SET LOCAL default_transaction_read_only = on")
+        .execute(&mut *tx)
+        .await?;
+    Ok(tx)
+}
+
+/// INSERT / UPDATE / DELETE / MERGE without a RETURNING clause
+fn is_non_returning_dml(query: &str) -> bool {
+    let upper = strip_leading_comments(query).to_uppercase();
+    let mut words = upper
+        .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .filter(|w| !w.is_empty());
+    matches!(words.next(), Some("INSERT" | "UPDATE" | "DELETE" | "MERGE"))
+        && !words.any(|w| w == "RETURNING")
+}
+
+/// Planner estimate for the top node of a query plan
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlanEstimate {
+    pub total_cost: f64,
+    pub rows: f64,
+}
+
+/// Limits above which a query needs confirmation before it runs
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CostThresholds {
+    pub max_cost: Option<f64>,
+    pub max_rows: Option<f64>,
+}
+
+impl CostThresholds {
+    pub fn is_enabled(&self) -> bool {
+        self.max_cost.is_some() || self.max_rows.is_some()
+    }
+
+    /// Describe the limits the estimate exceeds, if any
+    pub fn exceeded(&self, estimate: &PlanEstimate) -> Option<String> {
+        let mut reasons = Vec::new();
+        if let Some(max) = self.max_cost.filter(|max| estimate.total_cost > *max) {
+            reasons.push(format!(
+                "estimated cost {:.0} exceeds {:.0}",
+                estimate.total_cost, max
+            ));
+        }
+        if let Some(max) = self.max_rows.filter(|max| estimate.rows > *max) {
+            reasons.push(format!(
+                "estimated rows {:.0} exceed {:.0}",
+                estimate.rows, max
+            ));
+        }
+        (!reasons.is_empty()).then(|| reasons.join(", "))
+    }
+}
+
+/// Ask the planner for a query's estimated cost without running it
+///
+/// Returns `None` for statements `EXPLAIN` does not accept (SHOW, DDL, ...).
+pub async fn estimate_cost(
+    pool: &Pool<Postgres>,
+    query: &str,
+    params: &[QueryParam],
+) -> Result<Option<PlanEstimate>, String> {
+    let keyword = strip_leading_comments(query)
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .next()
+        .unwrap_or("")
+        .to_uppercase();
+    if !matches!(
+        keyword.as_str(),
+        "SELECT" | "WITH" | "VALUES" | "TABLE" | "INSERT" | "UPDATE" | "DELETE" | "MERGE"
+    ) {
+        return Ok(None);
+    }
+
+    let explain = format!("EXPLAIN {}", query.trim().trim_end_matches(';'));
+    let row = bind_params(sqlx::query(&explain), params)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| format!("Failed to explain query: {}", e))?;
+    let plan: String = row
+        .try_get(0)
+        .map_err(|e| format!("Failed to explain query: {}", e))?;
+
+    Ok(parse_plan_estimate(&plan))
+}
+
+/// Capture a statement's plan as `EXPLAIN (FORMAT JSON)` output
+///
+/// With `analyze` the statement really runs, so it is executed inside a
+/// transaction that is always rolled back; data changes are discarded.
+pub async fn explain_json(
+    pool: &Pool<Postgres>,
+    query: &str,
+    params: &[QueryParam],
+    analyze: bool,
+) -> Result<serde_json::Value, String> {
+    let keyword = strip_leading_comments(query)
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .next()
+        .unwrap_or("")
+        .to_uppercase();
+    if !matches!(
+        keyword.as_str(),
+        "SELECT" | "WITH" | "VALUES" | "TABLE" | "INSERT" | "UPDATE" | "DELETE" | "MERGE"
+    ) {
+        return Err(format!("{} statements cannot be explained", keyword));
+    }
+
+    let explain = format!(
+        "EXPLAIN (FORMAT JSON{}) {}",
+        if analyze { ", ANALYZE, BUFFERS" } else { "" },
+        query.trim().trim_end_matches(';')
+    );
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| format!("Failed to explain query: {}", e))?;
+    let result = bind_params(sqlx::query(&explain), params)
+        .fetch_one(&mut *tx)
+        .await
+        .and_then(|row| row.try_get::<serde_json::Value, _>(0));
+    // Rolling back is what keeps EXPLAIN ANALYZE free of side effects
+    tx.rollback()
+        .await
+        .map_err(|e| format!("Failed to roll back after explaining: {}", e))?;
+    result.map_err(|e| format!("Failed to explain query: {}", e))
+}
+
+/// Parse `(cost=0.00..35.50 rows=2550 width=4)` from the top line of a text plan
+fn parse_plan_estimate(line: &str) -> Option<PlanEstimate> {
+    let cost = line.split("cost=").nth(1)?;
+    let total_cost = cost.split("..").nth(1)?.split_whitespace().next()?;
+    let rows = cost.split("rows=").nth(1)?.split_whitespace().next()?;
+    Some(PlanEstimate {
+        total_cost: total_cost.parse().ok()?,
+        rows: rows.parse().ok()?,
+    })
+}
+
+fn bind_params<'q>(
+    mut query: sqlx::query::Query<'q, Postgres, sqlx::postgres::PgArguments>,
+    params: &'q [QueryParam],
+) -> sqlx::query::Query<'q, Postgres, sqlx::postgres::PgArguments> {
+    for param in params {
+        query = match param {
+            QueryParam::Null => query.bind(None::<String>),
+            QueryParam::Bool(b) => query.bind(*b),
+            QueryParam::Int(i) => query.bind(*i),
+            QueryParam::Float(f) => query.bind(*f),
+            QueryParam::Text(s) => query.bind(s.as_str()),
+        };
+    }
+    query
+}
+
+/// Broad category of a SQL statement, recorded on audit events
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatementKind {
+    /// SELECT, SHOW, EXPLAIN and other statements that only read
+    Read,
+    /// INSERT, UPDATE, DELETE and other data changes
+    Write,
+    /// CREATE, ALTER, DROP and other schema changes
+    Ddl,
+    /// GRANT / REVOKE
+    Dcl,
+    /// BEGIN, COMMIT, ROLLBACK, SAVEPOINT
+    Transaction,
+    /// SET, VACUUM, ANALYZE and other utility commands
+    Utility,
+    Unknown,
+}
+
+impl StatementKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StatementKind::Read => "read",
+            StatementKind::Write => "write",
+            StatementKind::Ddl => "ddl",
+            StatementKind::Dcl => "dcl",
+            StatementKind::Transaction => "transaction",
+            StatementKind::Utility => "utility",
+            StatementKind::Unknown => "unknown",
+        }
+    }
+}
+
+/// Classify a statement by its leading keyword, skipping comments
+///
+/// `WITH` queries count as writes when they contain a data-modifying
+/// statement (`WITH x AS (DELETE ... RETURNING *) SELECT ...`).
+pub fn classify_statement(query: &str) -> StatementKind {
+    let upper = strip_leading_comments(query).to_uppercase();
+    let keyword = upper
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .find(|w| !w.is_empty())
+        .unwrap_or("");
+
+    match keyword {
+        "SELECT" | "SHOW" | "EXPLAIN" | "VALUES" | "TABLE" | "FETCH" => StatementKind::Read,
+        "WITH" => {
+            let writes = upper
+                .split(|c: char| !c.is_ascii_alphabetic())
+                .any(|w| matches!(w, "INSERT" | "UPDATE" | "DELETE" | "MERGE"));
+            if writes {
+                StatementKind::Write
+            } else {
+                StatementKind::Read
+            }
+        }
+        "INSERT" | "UPDATE" | "DELETE" | "MERGE" | "COPY" | "TRUNCATE" => StatementKind::Write,
+        "CREATE" | "ALTER" | "DROP" | "COMMENT" | "REINDEX" | "CLUSTER" | "REFRESH" => {
+            StatementKind::Ddl
+        }
+        "GRANT" | "REVOKE" => StatementKind::Dcl,
+        "BEGIN" | "START" | "COMMIT" | "END" | "ROLLBACK" | "ABORT" | "SAVEPOINT" | "RELEASE" => {
+            StatementKind::Transaction
+        }
+        "SET" | "RESET" | "VACUUM" | "ANALYZE" | "ANALYSE" | "CHECKPOINT" | "LISTEN" | "NOTIFY"
+        | "UNLISTEN" | "DISCARD" | "LOCK" => StatementKind::Utility,
+        _ => StatementKind::Unknown,
+    }
+}
+
+/// Drop leading whitespace, `--` line comments and `/* */` block comments
+pub fn strip_leading_comments(query: &str) -> &str {
+    let mut rest = query.trim_start();
+    loop {
+        if let Some(after) = rest.strip_prefix("--") {
+            rest = after
+                .split_once('\n')
+                .map(|(_, r)| r)
+                .unwrap_or("")
+                .trim_start();
+        } else if let Some(after) = rest.strip_prefix("/*") {
+            rest = after
+                .split_once("*/")
+                .map(|(_, r)| r)
+                .unwrap_or("")
+                .trim_start();
+        } else {
+            return rest;
+        }
+    }
+}
+
 /// Validates a SQL query for dangerous patterns
 ///
 /// Security: This function prevents SQL injection attacks by:
@@ -139,6 +610,54 @@ pub fn validate_query(query: &str) -> Result<(), String> {
 mod tests {
     use super::*;
 
+    // ============================================================================
+    // Statement Classification
+    // ============================================================================
+
+    #[test]
+    fn test_classify_statement() {
+        assert_eq!(classify_statement("SELECT 1"), StatementKind::Read);
+        assert_eq!(
+            classify_statement("  explain select 1"),
+            StatementKind::Read
+        );
+        assert_eq!(
+            classify_statement("WITH t AS (SELECT 1) SELECT * FROM t"),
+            StatementKind::Read
+        );
+        assert_eq!(
+            classify_statement("WITH d AS (DELETE FROM t RETURNING *) SELECT * FROM d"),
+            StatementKind::Write
+        );
+        assert_eq!(
+            classify_statement("update t set a = 1"),
+            StatementKind::Write
+        );
+        assert_eq!(classify_statement("DROP TABLE t"), StatementKind::Ddl);
+        assert_eq!(
+            classify_statement("GRANT SELECT ON t TO r"),
+            StatementKind::Dcl
+        );
+        assert_eq!(classify_statement("BEGIN"), StatementKind::Transaction);
+        assert_eq!(
+            classify_statement("VACUUM ANALYZE t"),
+            StatementKind::Utility
+        );
+        assert_eq!(classify_statement(""), StatementKind::Unknown);
+    }
+
+    #[test]
+    fn test_classify_statement_skips_comments() {
+        assert_eq!(
+            classify_statement("-- cleanup\n/* old rows */ DELETE FROM t"),
+            StatementKind::Write
+        );
+        assert_eq!(
+            classify_statement("/* unterminated"),
+            StatementKind::Unknown
+        );
+    }
+
     // ============================================================================
     // Safe Operations - Should Pass
     // ============================================================================
@@ -361,4 +880,105 @@ mod tests {
         let query = "SELECT id, ROW_NUMBER() OVER (ORDER BY created_at) FROM users";
         assert!(validate_query(query).is_ok());
     }
+
+    #[test]
+    fn test_is_non_returning_dml() {
+        assert!(is_non_returning_dml("UPDATE users SET active = false"));
+        assert!(is_non_returning_dml("-- cleanup\ndelete from sessions"));
+        assert!(is_non_returning_dml("INSERT INTO t (a) VALUES (1)"));
+        assert!(!is_non_returning_dml(
+            "INSERT INTO t (a) VALUES (1) RETURNING id"
+        ));
+        assert!(!is_non_returning_dml("SELECT * FROM users"));
+        assert!(!is_non_returning_dml(
+            "WITH gone AS (DELETE FROM t RETURNING *) SELECT count(*) FROM gone"
+        ));
+        // Identifiers that merely contain the keyword don't count
+        assert!(is_non_returning_dml(
+            "UPDATE t SET returning_customer = true"
+        ));
+    }
+
+    #[test]
+    fn test_parse_plan_estimate() {
+        let estimate =
+            parse_plan_estimate("Seq Scan on users  (cost=0.00..35.50 rows=2550 width=4)").unwrap();
+        assert_eq!(estimate.total_cost, 35.5);
+        assert_eq!(estimate.rows, 2550.0);
+
+        assert!(parse_plan_estimate("Result").is_none());
+    }
+
+    #[test]
+    fn test_cost_thresholds_exceeded() {
+        let estimate = PlanEstimate {
+            total_cost: 150_000.0,
+            rows: 2_000_000.0,
+        };
+        assert!(!CostThresholds::default().is_enabled());
+        assert!(CostThresholds::default().exceeded(&estimate).is_none());
+
+        let cost_only = CostThresholds {
+            max_cost: Some(100_000.0),
+            max_rows: None,
+        };
+        assert_eq!(
+            cost_only.exceeded(&estimate).unwrap(),
+            "estimated cost 150000 exceeds 100000"
+        );
+
+        let generous = CostThresholds {
+            max_cost: Some(1e9),
+            max_rows: Some(1e9),
+        };
+        assert!(generous.exceeded(&estimate).is_none());
+
+        let both = CostThresholds {
+            max_cost: Some(1.0),
+            max_rows: Some(1.0),
+        };
+        assert!(both
+            .exceeded(&estimate)
+            .unwrap()
+            .contains("estimated rows 2000000 exceed 1"));
+    }
+
+    // ============================================================================
+    // Row Limit
+    // ============================================================================
+
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_row_limit_stops_the_fetch() {
+        let pulled = Cell::new(0);
+        let rows = futures::stream::iter(0..1_000).map(|row| {
+            pulled.set(pulled.get() + 1);
+            Ok::<_, std::io::Error>(row)
+        });
+        let (result, exceeded) = with_row_limit(Some(10), collect_rows(rows)).await;
+        assert!(exceeded);
+        let error = result.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<RowLimitExceeded>(),
+            Some(&RowLimitExceeded { max: 10 })
+        );
+        // The row past the limit is the last one read
+        assert_eq!(pulled.get(), 11);
+    }
+
+    #[tokio::test]
+    async fn test_row_limit_allows_results_up_to_it() {
+        let rows = || futures::stream::iter((0..10).map(Ok::<_, std::io::Error>));
+        let (result, exceeded) = with_row_limit(Some(10), collect_rows(rows())).await;
+        assert!(!exceeded);
+        assert_eq!(result.unwrap().len(), 10);
+
+        let (result, exceeded) = with_row_limit(None, collect_rows(rows())).await;
+        assert!(!exceeded);
+        assert_eq!(result.unwrap().len(), 10);
+
+        // Outside a limited scope nothing is refused
+        assert!(check_row_limit(usize::MAX).is_ok());
+    }
 }
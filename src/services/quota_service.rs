@@ -0,0 +1,291 @@
+use crate::services::json_file;
+/// Quotas
+///
+/// Limits on how much one user can pull out of a shared database, declared
+/// in `QUOTAS_FILE`:
+/// - `max_rows`: rows one query run from the console, the API, an export or
+///   a table browser may fetch
+/// - `max_export_bytes`: size of one exported or downloaded file
+/// - `max_exports_per_day`: exports per client per UTC day
+///
+/// Limits are set per PostgreSQL role: requests on a connection whose role
+/// has an entry under `roles` get that entry, the others get `default`. A
+/// role entry replaces the default limits as a whole, and a limit left out
+/// is unlimited. There are no user accounts, so daily export counts are kept
+/// per client address, in memory (a restart starts them over).
+use chrono::{NaiveDate, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/// Limits for one role; `None` is unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuotaLimits {
+    pub max_rows: Option<usize>,
+    pub max_export_bytes: Option<usize>,
+    pub max_exports_per_day: Option<u32>,
+}
+
+impl QuotaLimits {
+    pub fn is_unlimited(&self) -> bool {
+        *self == QuotaLimits::default()
+    }
+
+    /// Refuse an export file larger than allowed
+    pub fn check_export_size(&self, bytes: usize) -> Result<(), QuotaExceeded> {
+        match self.max_export_bytes {
+            Some(max) if bytes > max => Err(QuotaExceeded::TooLarge(format!(
+                "The export is {} bytes, over the limit of {} bytes per file",
+                bytes, max
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Contents of `QUOTAS_FILE`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuotasFile {
+    #[serde(default)]
+    pub default: QuotaLimits,
+    /// Limits by PostgreSQL role name
+    #[serde(default)]
+    pub roles: BTreeMap<String, QuotaLimits>,
+}
+
+/// Why a request was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QuotaExceeded {
+    /// More than the role may fetch at once; retrying the same request fails
+    /// again
+    TooLarge(String),
+    /// The client used up its allowance for the day
+    Exhausted(String),
+}
+
+impl QuotaExceeded {
+    pub fn message(&self) -> &str {
+        match self {
+            QuotaExceeded::TooLarge(message) | QuotaExceeded::Exhausted(message) => message,
+        }
+    }
+}
+
+/// Exports made today by one client
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExportUsage {
+    pub client: String,
+    pub exports: u32,
+}
+
+/// Configured limits and today's export counts, for the admin listing
+#[derive(Debug, Clone, Serialize)]
+pub struct QuotaSummary {
+    pub default: QuotaLimits,
+    pub roles: BTreeMap<String, QuotaLimits>,
+    /// Busiest clients first
+    pub exports_today: Vec<ExportUsage>,
+}
+
+/// Export counts per client, for one day at a time
+#[derive(Debug)]
+struct DailyCounts {
+    day: NaiveDate,
+    counts: HashMap<String, u32>,
+}
+
+impl DailyCounts {
+    /// The counts for `day`, starting over when the day has changed
+    fn on(&mut self, day: NaiveDate) -> &mut HashMap<String, u32> {
+        if self.day != day {
+            self.day = day;
+            self.counts.clear();
+        }
+        &mut self.counts
+    }
+}
+
+/// Quota limits and usage
+pub struct Quotas {
+    config: QuotasFile,
+    exports: Mutex<DailyCounts>,
+}
+
+impl Quotas {
+    /// Load the limits from a JSON file; without one, nothing is limited
+    pub async fn load(path: &str) -> Result<Self, String> {
+        let config: QuotasFile = json_file::load(Path::new(path), "quotas file").await?;
+        Ok(Self::new(config))
+    }
+
+    pub fn new(config: QuotasFile) -> Self {
+        Self {
+            config,
+            exports: Mutex::new(DailyCounts {
+                day: Utc::now().date_naive(),
+                counts: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Whether any role has a limit
+    pub fn is_enabled(&self) -> bool {
+        !self.config.default.is_unlimited()
+            || self
+                .config
+                .roles
+                .values()
+                .any(|limits| !limits.is_unlimited())
+    }
+
+    /// The limits for a PostgreSQL role
+    pub fn limits(&self, role: &str) -> QuotaLimits {
+        self.config
+            .roles
+            .get(role)
+            .copied()
+            .unwrap_or(self.config.default)
+    }
+
+    /// Refuse another export once the client has made its daily allowance
+    pub fn check_export_allowance(
+        &self,
+        client: &str,
+        limits: &QuotaLimits,
+    ) -> Result<(), QuotaExceeded> {
+        let Some(max) = limits.max_exports_per_day else {
+            return Ok(());
+        };
+        let mut exports = self.exports.lock();
+        let made = exports
+            .on(Utc::now().date_naive())
+            .get(client)
+            .copied()
+            .unwrap_or(0);
+        if made >= max {
+            return Err(QuotaExceeded::Exhausted(format!(
+                "Daily export limit of {} reached; it resets at midnight UTC",
+                max
+            )));
+        }
+        Ok(())
+    }
+
+    /// Count a finished export against the client's daily allowance
+    pub fn record_export(&self, client: &str) {
+        let mut exports = self.exports.lock();
+        *exports
+            .on(Utc::now().date_naive())
+            .entry(client.to_string())
+            .or_insert(0) += 1;
+    }
+
+    pub fn describe(&self) -> QuotaSummary {
+        let mut exports_today: Vec<ExportUsage> = self
+            .exports
+            .lock()
+            .on(Utc::now().date_naive())
+            .iter()
+            .map(|(client, exports)| ExportUsage {
+                client: client.clone(),
+                exports: *exports,
+            })
+            .collect();
+        exports_today.sort_by(|a, b| b.exports.cmp(&a.exports).then(a.client.cmp(&b.client)));
+        QuotaSummary {
+            default: self.config.default,
+            roles: self.config.roles.clone(),
+            exports_today,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quotas() -> Quotas {
+        let config: QuotasFile = serde_json::from_str(
+            r#"{
+                "default": {"max_rows": 100, "max_exports_per_day": 2},
+                "roles": {"reporting": {"max_export_bytes": 10}, "postgres": {}}
+            }"#,
+        )
+        .unwrap();
+        Quotas::new(config)
+    }
+
+    #[test]
+    fn test_limits_by_role() {
+        let quotas = quotas();
+        assert!(quotas.is_enabled());
+        assert_eq!(quotas.limits("app").max_rows, Some(100));
+
+        // A role entry replaces the default as a whole
+        let reporting = quotas.limits("reporting");
+        assert_eq!(reporting.max_rows, None);
+        assert_eq!(reporting.max_export_bytes, Some(10));
+        assert!(quotas.limits("postgres").is_unlimited());
+
+        assert!(!Quotas::new(QuotasFile::default()).is_enabled());
+        assert!(serde_json::from_str::<QuotasFile>(r#"{"default": {"rows": 1}}"#).is_err());
+    }
+
+    #[test]
+    fn test_size_checks() {
+        let reporting = quotas().limits("reporting");
+        assert!(reporting.check_export_size(10).is_ok());
+        let refused = reporting.check_export_size(11).unwrap_err();
+        assert!(matches!(refused, QuotaExceeded::TooLarge(_)));
+        assert!(refused.message().contains("11 bytes"));
+        assert!(quotas().limits("app").check_export_size(usize::MAX).is_ok());
+    }
+
+    #[test]
+    fn test_daily_exports() {
+        let quotas = quotas();
+        let limits = quotas.limits("app");
+        for _ in 0..2 {
+            assert!(quotas.check_export_allowance("10.0.0.1", &limits).is_ok());
+            quotas.record_export("10.0.0.1");
+        }
+        assert!(matches!(
+            quotas.check_export_allowance("10.0.0.1", &limits),
+            Err(QuotaExceeded::Exhausted(_))
+        ));
+        assert!(quotas.check_export_allowance("10.0.0.2", &limits).is_ok());
+        assert!(quotas
+            .check_export_allowance("10.0.0.1", &quotas.limits("postgres"))
+            .is_ok());
+
+        quotas.record_export("10.0.0.2");
+        assert_eq!(
+            quotas.describe().exports_today,
+            vec![
+                ExportUsage {
+                    client: "10.0.0.1".to_string(),
+                    exports: 2
+                },
+                ExportUsage {
+                    client: "10.0.0.2".to_string(),
+                    exports: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_counts_start_over_each_day() {
+        let today = Utc::now().date_naive();
+        let mut daily = DailyCounts {
+            day: today,
+            counts: HashMap::new(),
+        };
+        daily.on(today).insert("10.0.0.1".to_string(), 5);
+        assert_eq!(daily.on(today).len(), 1);
+        assert!(daily.on(today.succ_opt().unwrap()).is_empty());
+    }
+}
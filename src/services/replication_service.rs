@@ -0,0 +1,443 @@
+use crate::services::identifier::{quote_ident, quote_qualified};
+use crate::services::sql_lexer::validate_expression;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+/// Replication Service
+///
+/// Logical replication topology of the current database: publications with
+/// the tables they publish, and subscriptions with their apply worker's
+/// status. Publications can also be created, altered and dropped.
+///
+/// Catalog columns added in later releases (`pubviaroot` in 13, row filters
+/// and column lists in 15) are read through `to_jsonb(row)` so the same
+/// queries work on older servers, where they come back empty.
+use sqlx::PgPool;
+
+/// A publication from `pg_publication`
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Publication {
+    pub name: String,
+    pub owner: String,
+    /// `FOR ALL TABLES`, including tables created later
+    pub all_tables: bool,
+    pub insert: bool,
+    pub update: bool,
+    pub delete: bool,
+    pub truncate: bool,
+    /// Changes to partitions are published as changes to the partitioned table
+    pub via_root: bool,
+    #[sqlx(skip)]
+    pub tables: Vec<PublicationTable>,
+}
+
+impl Publication {
+    /// The published operations, e.g. `insert, update`
+    pub fn operations(&self) -> String {
+        [
+            (self.insert, "insert"),
+            (self.update, "update"),
+            (self.delete, "delete"),
+            (self.truncate, "truncate"),
+        ]
+        .iter()
+        .filter(|(on, _)| *on)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>()
+        .join(", ")
+    }
+}
+
+/// A table published by a publication, from `pg_publication_tables`
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct PublicationTable {
+    #[serde(skip)]
+    pub publication: String,
+    pub schema: String,
+    pub table: String,
+    /// Published columns (PostgreSQL 15+)
+    pub columns: Option<Vec<String>>,
+    /// Row filter (PostgreSQL 15+)
+    pub row_filter: Option<String>,
+}
+
+/// A subscription of the current database with its apply worker's status
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Subscription {
+    pub name: String,
+    pub owner: String,
+    pub enabled: bool,
+    pub publications: Vec<String>,
+    pub slot_name: Option<String>,
+    /// `None` when no apply worker is running
+    pub worker_pid: Option<i32>,
+    pub received_lsn: Option<String>,
+    pub latest_end_lsn: Option<String>,
+    pub last_msg_receipt_time: Option<DateTime<Utc>>,
+    pub latest_end_time: Option<DateTime<Utc>>,
+}
+
+/// An operation a publication replicates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PublishAction {
+    Insert,
+    Update,
+    Delete,
+    Truncate,
+}
+
+impl PublishAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PublishAction::Insert => "insert",
+            PublishAction::Update => "update",
+            PublishAction::Delete => "delete",
+            PublishAction::Truncate => "truncate",
+        }
+    }
+}
+
+/// A table to publish, optionally limited to some columns and rows
+#[derive(Debug, Clone, Deserialize)]
+pub struct PublishedTable {
+    pub schema: String,
+    pub table: String,
+    /// Column list (PostgreSQL 15+); empty publishes all columns
+    #[serde(default)]
+    pub columns: Vec<String>,
+    /// Row filter (PostgreSQL 15+)
+    #[serde(default)]
+    pub where_clause: Option<String>,
+}
+
+/// A table to stop publishing
+#[derive(Debug, Clone, Deserialize)]
+pub struct TableRef {
+    pub schema: String,
+    pub table: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreatePublicationRequest {
+    pub name: String,
+    #[serde(default)]
+    pub all_tables: bool,
+    #[serde(default)]
+    pub tables: Vec<PublishedTable>,
+    /// Operations to publish; all of them when absent
+    #[serde(default)]
+    pub publish: Option<Vec<PublishAction>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AlterPublicationRequest {
+    #[serde(default)]
+    pub add_tables: Vec<PublishedTable>,
+    #[serde(default)]
+    pub drop_tables: Vec<TableRef>,
+    #[serde(default)]
+    pub publish: Option<Vec<PublishAction>>,
+}
+
+/// Publications of the current database with their tables
+pub async fn list_publications(pool: &PgPool) -> Result<Vec<Publication>, sqlx::Error> {
+    let mut publications: Vec<Publication> = sqlx::query_as(
+        r#"
+        SELECT p.pubname::text AS name,
+               pg_get_userbyid(p.pubowner)::text AS owner,
+               p.puballtables AS all_tables,
+               p.pubinsert AS insert,
+               p.pubupdate AS update,
+               p.pubdelete AS delete,
+               p.pubtruncate AS truncate,
+               COALESCE((to_jsonb(p) ->> 'pubviaroot')::bool, false) AS via_root
+        FROM pg_catalog.pg_publication p
+        ORDER BY p.pubname
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let tables: Vec<PublicationTable> = sqlx::query_as(
+        r#"
+        SELECT pt.pubname::text AS publication,
+               pt.schemaname::text AS schema,
+               pt.tablename::text AS "table",
+               CASE WHEN jsonb_typeof(to_jsonb(pt) -> 'attnames') = 'array'
+                    THEN ARRAY(SELECT jsonb_array_elements_text(to_jsonb(pt) -> 'attnames'))
+               END AS columns,
+               to_jsonb(pt) ->> 'rowfilter' AS row_filter
+        FROM pg_catalog.pg_publication_tables pt
+        ORDER BY pt.pubname, pt.schemaname, pt.tablename
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for table in tables {
+        if let Some(publication) = publications
+            .iter_mut()
+            .find(|p| p.name == table.publication)
+        {
+            publication.tables.push(table);
+        }
+    }
+    Ok(publications)
+}
+
+/// Subscriptions of the current database
+pub async fn list_subscriptions(pool: &PgPool) -> Result<Vec<Subscription>, sqlx::Error> {
+    // Table sync and parallel apply workers have their own rows; the leader
+    // apply worker is the one that receives changes
+    sqlx::query_as(
+        r#"
+        SELECT DISTINCT ON (s.subname)
+               s.subname::text AS name,
+               pg_get_userbyid(s.subowner)::text AS owner,
+               s.subenabled AS enabled,
+               s.subpublications::text[] AS publications,
+               s.subslotname::text AS slot_name,
+               st.pid AS worker_pid,
+               st.received_lsn::text AS received_lsn,
+               st.latest_end_lsn::text AS latest_end_lsn,
+               st.last_msg_receipt_time,
+               st.latest_end_time
+        FROM pg_catalog.pg_subscription s
+        LEFT JOIN pg_catalog.pg_stat_subscription st
+               ON st.subid = s.oid AND st.relid IS NULL
+        WHERE s.subdbid = (SELECT oid FROM pg_catalog.pg_database
+                           WHERE datname = current_database())
+        ORDER BY s.subname, st.received_lsn IS NULL
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Create a publication
+pub async fn create_publication(
+    pool: &PgPool,
+    req: &CreatePublicationRequest,
+) -> Result<String, String> {
+    let sql = create_publication_sql(req)?;
+    sqlx::query(&sql)
+        .execute(pool)
+        .await
+        .map_err(|e| format!("Failed to create publication: {}", e))?;
+    Ok(format!("Publication {} created", req.name))
+}
+
+/// Apply the changes to a publication in one transaction
+pub async fn alter_publication(
+    pool: &PgPool,
+    name: &str,
+    req: &AlterPublicationRequest,
+) -> Result<String, String> {
+    let statements = alter_publication_sql(name, req)?;
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    for sql in &statements {
+        sqlx::query(sql)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to alter publication: {}", e))?;
+    }
+    tx.commit().await.map_err(|e| e.to_string())?;
+    Ok(format!("Publication {} updated", name))
+}
+
+/// Drop a publication
+pub async fn drop_publication(pool: &PgPool, name: &str) -> Result<String, String> {
+    sqlx::query(&drop_publication_sql(name)?)
+        .execute(pool)
+        .await
+        .map_err(|e| format!("Failed to drop publication: {}", e))?;
+    Ok(format!("Publication {} dropped", name))
+}
+
+/// Build the `CREATE PUBLICATION` statement
+pub fn create_publication_sql(req: &CreatePublicationRequest) -> Result<String, String> {
+    validate_name(&req.name)?;
+    if req.all_tables && !req.tables.is_empty() {
+        return Err("A publication is either for all tables or for a list of tables".to_string());
+    }
+
+    let mut sql = format!("CREATE PUBLICATION {}", quote_ident(&req.name));
+    if req.all_tables {
+        sql.push_str(" FOR ALL TABLES");
+    } else if !req.tables.is_empty() {
+        sql.push_str(&format!(" FOR TABLE {}", table_list(&req.tables)?));
+    }
+    if let Some(publish) = &req.publish {
+        sql.push_str(&format!(" WITH ({})", publish_option(publish)));
+    }
+    Ok(sql)
+}
+
+/// Build the `ALTER PUBLICATION` statements for the requested changes
+pub fn alter_publication_sql(
+    name: &str,
+    req: &AlterPublicationRequest,
+) -> Result<Vec<String>, String> {
+    validate_name(name)?;
+    let publication = quote_ident(name);
+    let mut statements = Vec::new();
+
+    if !req.add_tables.is_empty() {
+        statements.push(format!(
+            "ALTER PUBLICATION {} ADD TABLE {}",
+            publication,
+            table_list(&req.add_tables)?
+        ));
+    }
+    if !req.drop_tables.is_empty() {
+        let tables = req
+            .drop_tables
+            .iter()
+            .map(|t| quote_qualified(&t.schema, &t.table))
+            .collect::<Vec<_>>()
+            .join(", ");
+        statements.push(format!(
+            "ALTER PUBLICATION {} DROP TABLE {}",
+            publication, tables
+        ));
+    }
+    if let Some(publish) = &req.publish {
+        statements.push(format!(
+            "ALTER PUBLICATION {} SET ({})",
+            publication,
+            publish_option(publish)
+        ));
+    }
+
+    if statements.is_empty() {
+        return Err("Nothing to change: give add_tables, drop_tables or publish".to_string());
+    }
+    Ok(statements)
+}
+
+/// Build the `DROP PUBLICATION` statement
+pub fn drop_publication_sql(name: &str) -> Result<String, String> {
+    validate_name(name)?;
+    Ok(format!("DROP PUBLICATION {}", quote_ident(name)))
+}
+
+fn validate_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("Publication name cannot be empty".to_string());
+    }
+    if name.len() > 63 {
+        return Err("Publication name cannot be longer than 63 characters".to_string());
+    }
+    Ok(())
+}
+
+fn table_list(tables: &[PublishedTable]) -> Result<String, String> {
+    tables
+        .iter()
+        .map(|t| {
+            let mut item = quote_qualified(&t.schema, &t.table);
+            if !t.columns.is_empty() {
+                let columns: Vec<String> = t.columns.iter().map(|c| quote_ident(c)).collect();
+                item.push_str(&format!(" ({})", columns.join(", ")));
+            }
+            if let Some(filter) = t.where_clause.as_deref().filter(|w| !w.trim().is_empty()) {
+                validate_expression(filter)
+                    .map_err(|e| format!("Invalid row filter for {}: {}", t.table, e))?;
+                item.push_str(&format!(" WHERE ({})", filter.trim()));
+            }
+            Ok(item)
+        })
+        .collect::<Result<Vec<_>, String>>()
+        .map(|items| items.join(", "))
+}
+
+fn publish_option(actions: &[PublishAction]) -> String {
+    let actions: Vec<&str> = actions.iter().map(PublishAction::as_str).collect();
+    format!("publish = '{}'", actions.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(name: &str) -> PublishedTable {
+        PublishedTable {
+            schema: "public".to_string(),
+            table: name.to_string(),
+            columns: Vec::new(),
+            where_clause: None,
+        }
+    }
+
+    #[test]
+    fn test_create_publication_sql() {
+        let mut req = CreatePublicationRequest {
+            name: "app_pub".to_string(),
+            all_tables: true,
+            tables: Vec::new(),
+            publish: None,
+        };
+        assert_eq!(
+            create_publication_sql(&req).unwrap(),
+            "CREATE PUBLICATION app_pub FOR ALL TABLES"
+        );
+
+        req.all_tables = false;
+        req.tables = vec![
+            PublishedTable {
+                columns: vec!["id".to_string(), "Status".to_string()],
+                where_clause: Some("status <> 'draft'".to_string()),
+                ..table("orders")
+            },
+            table("My Table"),
+        ];
+        req.publish = Some(vec![PublishAction::Insert, PublishAction::Update]);
+        assert_eq!(
+            create_publication_sql(&req).unwrap(),
+            "CREATE PUBLICATION app_pub FOR TABLE public.orders (id, \"Status\") \
+             WHERE (status <> 'draft'), public.\"My Table\" WITH (publish = 'insert, update')"
+        );
+    }
+
+    #[test]
+    fn test_create_publication_rejects_bad_input() {
+        let req = CreatePublicationRequest {
+            name: "p".to_string(),
+            all_tables: true,
+            tables: vec![table("orders")],
+            publish: None,
+        };
+        assert!(create_publication_sql(&req).is_err());
+
+        let req = CreatePublicationRequest {
+            all_tables: false,
+            tables: vec![PublishedTable {
+                where_clause: Some("true); DROP TABLE orders; --".to_string()),
+                ..table("orders")
+            }],
+            ..req
+        };
+        assert!(create_publication_sql(&req).is_err());
+    }
+
+    #[test]
+    fn test_alter_publication_sql() {
+        let req = AlterPublicationRequest {
+            add_tables: vec![table("orders")],
+            drop_tables: vec![TableRef {
+                schema: "public".to_string(),
+                table: "old".to_string(),
+            }],
+            publish: Some(vec![PublishAction::Insert]),
+        };
+        assert_eq!(
+            alter_publication_sql("App", &req).unwrap(),
+            vec![
+                "ALTER PUBLICATION \"App\" ADD TABLE public.orders",
+                "ALTER PUBLICATION \"App\" DROP TABLE public.old",
+                "ALTER PUBLICATION \"App\" SET (publish = 'insert')",
+            ]
+        );
+        assert!(alter_publication_sql("App", &AlterPublicationRequest::default()).is_err());
+    }
+}
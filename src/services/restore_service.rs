@@ -0,0 +1,683 @@
+use crate::services::sql_lexer::{is_complete_statement, split_statements};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+/// Restore Service
+///
+/// Restores an uploaded dump into a database as a background job:
+/// - Custom-format dumps (`pg_dump -Fc`) are converted to a SQL script by
+///   `pg_restore --file=-`, which needs no database connection of its own
+/// - Plain SQL files are read as they are
+/// - The script is executed statement by statement on a dedicated
+///   connection; `COPY ... FROM stdin` data blocks are streamed with the
+///   copy protocol and psql meta-commands (`\connect`, `\restrict`) are
+///   skipped
+///
+/// Jobs are kept in memory with their progress and a log, so the client can
+/// follow them while they run.
+use sqlx::postgres::{PgConnectOptions, PgConnection};
+use sqlx::Connection;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::Arc;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::sync::RwLock;
+
+/// Finished jobs kept for listing; the oldest are forgotten first
+const MAX_JOBS: usize = 20;
+
+/// Log lines kept per job; earlier ones are dropped
+const MAX_LOG_LINES: usize = 500;
+
+/// COPY data is sent to the server in chunks of about this size
+const COPY_CHUNK_BYTES: usize = 1024 * 1024;
+
+/// Statements are logged up to this many characters
+const LOG_STATEMENT_CHARS: usize = 120;
+
+/// Format of an uploaded dump, recognized from its first bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DumpFormat {
+    /// `pg_dump -Fc`
+    Custom,
+    /// A SQL script (`pg_dump -Fp` or hand written)
+    Plain,
+}
+
+impl DumpFormat {
+    /// Recognize the format from the start of the file
+    pub fn detect(head: &[u8]) -> Result<Self, String> {
+        if head.starts_with(b"PGDMP") {
+            Ok(DumpFormat::Custom)
+        } else if head.starts_with(&[0x1f, 0x8b]) {
+            Err("Compressed SQL files are not supported; upload the .sql file or a custom-format (-Fc) dump".to_string())
+        } else if head.get(257..262) == Some(b"ustar") {
+            Err("Tar-format dumps are not supported; use a custom-format (-Fc) dump".to_string())
+        } else if std::str::from_utf8(&head[..head.len().min(512)]).is_err() {
+            Err("Not a custom-format dump or a UTF-8 SQL file".to_string())
+        } else {
+            Ok(DumpFormat::Plain)
+        }
+    }
+}
+
+/// How to restore an upload
+#[derive(Debug, Clone, Deserialize)]
+pub struct RestoreOptions {
+    /// Target database on the same server; the connection's own by default
+    pub database: Option<String>,
+    /// Name of the uploaded file, for the job list and audit log
+    pub file_name: Option<String>,
+    /// Run the whole script in one transaction, so a failure changes nothing
+    #[serde(default = "default_true")]
+    pub single_transaction: bool,
+    /// Drop objects before recreating them (custom-format dumps)
+    #[serde(default)]
+    pub clean: bool,
+    /// Skip `ALTER ... OWNER` statements (custom-format dumps)
+    #[serde(default = "default_true")]
+    pub no_owner: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+/// A restore job and its progress
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoreJob {
+    pub id: String,
+    pub database: String,
+    pub file_name: String,
+    pub format: DumpFormat,
+    pub status: JobStatus,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Size of the uploaded file
+    pub bytes_total: u64,
+    /// How much of a plain SQL file has been read
+    pub bytes_read: Option<u64>,
+    pub statements: u64,
+    pub rows_copied: u64,
+    pub error: Option<String>,
+    /// Lines dropped from the front of `log`
+    pub log_skipped: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub log: Vec<String>,
+}
+
+impl RestoreJob {
+    /// Log lines after the first `seen` ever written
+    pub fn log_since(&self, seen: usize) -> &[String] {
+        let start = seen.saturating_sub(self.log_skipped).min(self.log.len());
+        &self.log[start..]
+    }
+
+    /// Total log lines ever written
+    pub fn log_written(&self) -> usize {
+        self.log_skipped + self.log.len()
+    }
+
+    fn push_log(&mut self, line: String) {
+        self.log.push(line);
+        if self.log.len() > MAX_LOG_LINES {
+            let excess = self.log.len() - MAX_LOG_LINES;
+            self.log.drain(..excess);
+            self.log_skipped += excess;
+        }
+    }
+}
+
+/// One step of a SQL script read line by line
+#[derive(Debug, PartialEq)]
+pub enum ScriptItem {
+    Statement(String),
+    /// `COPY ... FROM stdin`; data lines follow until `CopyEnd`
+    CopyStart(String),
+    /// One row of COPY data, with its newline
+    CopyData(String),
+    CopyEnd,
+    /// A psql backslash command, which the server would not understand
+    MetaCommand(String),
+}
+
+/// Splits a SQL script into statements and COPY data as lines arrive
+#[derive(Debug, Default)]
+pub struct ScriptReader {
+    buffer: String,
+    in_copy: bool,
+}
+
+impl ScriptReader {
+    /// Feed the next line (without its newline)
+    pub fn push_line(&mut self, line: &str) -> Option<ScriptItem> {
+        if self.in_copy {
+            if line == "\\." {
+                self.in_copy = false;
+                return Some(ScriptItem::CopyEnd);
+            }
+            return Some(ScriptItem::CopyData(format!("{}\n", line)));
+        }
+
+        let trimmed = line.trim();
+        if self.buffer.is_empty() {
+            if trimmed.starts_with('\\') {
+                return Some(ScriptItem::MetaCommand(trimmed.to_string()));
+            }
+            if trimmed.is_empty() || trimmed.starts_with("--") {
+                return None;
+            }
+        }
+
+        self.buffer.push_str(line);
+        self.buffer.push('\n');
+        if !trimmed.ends_with(';') || !is_complete_statement(&self.buffer) {
+            return None;
+        }
+        self.take_statement()
+    }
+
+    /// The last statement, when the script does not end with a semicolon
+    pub fn finish(&mut self) -> Option<ScriptItem> {
+        self.take_statement()
+    }
+
+    /// Inside a COPY data block
+    pub fn in_copy(&self) -> bool {
+        self.in_copy
+    }
+
+    fn take_statement(&mut self) -> Option<ScriptItem> {
+        let buffer = std::mem::take(&mut self.buffer);
+        let statement = split_statements(&buffer).into_iter().next()?;
+        let upper = statement.to_ascii_uppercase();
+        if upper.starts_with("COPY ") && upper.ends_with("FROM STDIN") {
+            self.in_copy = true;
+            Some(ScriptItem::CopyStart(statement))
+        } else {
+            Some(ScriptItem::Statement(statement))
+        }
+    }
+}
+
+/// Arguments for `pg_restore` to write a custom-format dump as SQL
+pub fn pg_restore_args(options: &RestoreOptions, dump: &std::path::Path) -> Vec<String> {
+    let mut args = vec!["--file=-".to_string()];
+    if options.no_owner {
+        args.push("--no-owner".to_string());
+    }
+    if options.clean {
+        args.push("--clean".to_string());
+        args.push("--if-exists".to_string());
+    }
+    args.push(dump.display().to_string());
+    args
+}
+
+/// Running and recent restore jobs
+pub struct RestoreJobs {
+    jobs: Arc<RwLock<Vec<RestoreJob>>>,
+    pg_restore: String,
+    max_upload_bytes: u64,
+}
+
+impl RestoreJobs {
+    /// `pg_restore` is the program used for custom-format dumps
+    pub fn new(pg_restore: impl Into<String>, max_upload_bytes: u64) -> Self {
+        Self {
+            jobs: Arc::new(RwLock::new(Vec::new())),
+            pg_restore: pg_restore.into(),
+            max_upload_bytes,
+        }
+    }
+
+    /// Largest upload accepted
+    pub fn max_upload_bytes(&self) -> u64 {
+        self.max_upload_bytes
+    }
+
+    /// Jobs, newest first, without their logs
+    pub async fn list(&self) -> Vec<RestoreJob> {
+        self.jobs
+            .read()
+            .await
+            .iter()
+            .rev()
+            .map(|job| RestoreJob {
+                log: Vec::new(),
+                ..job.clone()
+            })
+            .collect()
+    }
+
+    pub async fn get(&self, id: &str) -> Option<RestoreJob> {
+        self.jobs.read().await.iter().find(|j| j.id == id).cloned()
+    }
+
+    /// Start restoring the dump at `path` (which is deleted afterwards) into
+    /// the database `connect` points at; `on_finish` gets the finished job
+    pub async fn start<F, Fut>(
+        &self,
+        path: PathBuf,
+        connect: PgConnectOptions,
+        options: RestoreOptions,
+        on_finish: F,
+    ) -> Result<RestoreJob, String>
+    where
+        F: FnOnce(RestoreJob) -> Fut + Send + 'static,
+        Fut: std::future::Future<Output = ()> + Send,
+    {
+        let mut head = Vec::with_capacity(512);
+        let mut file = tokio::fs::File::open(&path)
+            .await
+            .map_err(|e| format!("Failed to read upload: {}", e))?;
+        (&mut file)
+            .take(512)
+            .read_to_end(&mut head)
+            .await
+            .map_err(|e| format!("Failed to read upload: {}", e))?;
+        let format = match DumpFormat::detect(&head) {
+            Ok(format) => format,
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&path).await;
+                return Err(e);
+            }
+        };
+        let bytes_total = file.metadata().await.map(|m| m.len()).unwrap_or(0);
+
+        let job = RestoreJob {
+            id: uuid::Uuid::new_v4().to_string(),
+            database: connect.get_database().unwrap_or_default().to_string(),
+            file_name: options
+                .file_name
+                .clone()
+                .unwrap_or_else(|| "upload".to_string()),
+            format,
+            status: JobStatus::Running,
+            started_at: Utc::now(),
+            finished_at: None,
+            bytes_total,
+            bytes_read: (format == DumpFormat::Plain).then_some(0),
+            statements: 0,
+            rows_copied: 0,
+            error: None,
+            log_skipped: 0,
+            log: Vec::new(),
+        };
+        {
+            let mut jobs = self.jobs.write().await;
+            jobs.push(job.clone());
+            while jobs.len() > MAX_JOBS {
+                match jobs.iter().position(|j| j.status != JobStatus::Running) {
+                    Some(oldest) => jobs.remove(oldest),
+                    None => break,
+                };
+            }
+        }
+
+        let runner = JobRunner {
+            jobs: self.jobs.clone(),
+            id: job.id.clone(),
+        };
+        let pg_restore = self.pg_restore.clone();
+        tokio::spawn(async move {
+            let result = runner
+                .run(&path, format, &connect, &options, &pg_restore)
+                .await;
+            let _ = tokio::fs::remove_file(&path).await;
+            let finished = runner.finish(result).await;
+            if let Some(job) = finished {
+                on_finish(job).await;
+            }
+        });
+
+        Ok(job)
+    }
+}
+
+/// Executes one job and records its progress
+struct JobRunner {
+    jobs: Arc<RwLock<Vec<RestoreJob>>>,
+    id: String,
+}
+
+impl JobRunner {
+    async fn update(&self, f: impl FnOnce(&mut RestoreJob)) {
+        if let Some(job) = self.jobs.write().await.iter_mut().find(|j| j.id == self.id) {
+            f(job);
+        }
+    }
+
+    async fn log(&self, line: String) {
+        self.update(|job| job.push_log(line)).await;
+    }
+
+    async fn finish(&self, result: Result<(), String>) -> Option<RestoreJob> {
+        let mut jobs = self.jobs.write().await;
+        let job = jobs.iter_mut().find(|j| j.id == self.id)?;
+        job.finished_at = Some(Utc::now());
+        match result {
+            Ok(()) => {
+                job.status = JobStatus::Succeeded;
+                job.push_log(format!(
+                    "Restore finished: {} statements, {} rows copied",
+                    job.statements, job.rows_copied
+                ));
+            }
+            Err(e) => {
+                job.status = JobStatus::Failed;
+                job.push_log(format!("Restore failed: {}", e));
+                job.error = Some(e);
+            }
+        }
+        Some(job.clone())
+    }
+
+    async fn run(
+        &self,
+        path: &std::path::Path,
+        format: DumpFormat,
+        connect: &PgConnectOptions,
+        options: &RestoreOptions,
+        pg_restore: &str,
+    ) -> Result<(), String> {
+        let mut conn = PgConnection::connect_with(connect)
+            .await
+            .map_err(|e| format!("Failed to connect: {}", e))?;
+
+        match format {
+            DumpFormat::Plain => {
+                let file = tokio::fs::File::open(path)
+                    .await
+                    .map_err(|e| format!("Failed to read upload: {}", e))?;
+                self.apply(&mut conn, BufReader::new(file), options, true)
+                    .await
+            }
+            DumpFormat::Custom => {
+                let mut child = tokio::process::Command::new(pg_restore)
+                    .args(pg_restore_args(options, path))
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .kill_on_drop(true)
+                    .spawn()
+                    .map_err(|e| format!("Failed to run {}: {}", pg_restore, e))?;
+                let stdout = child.stdout.take().expect("stdout is piped");
+                let mut stderr = child.stderr.take().expect("stderr is piped");
+                let stderr_task = tokio::spawn(async move {
+                    let mut output = String::new();
+                    let _ = stderr.read_to_string(&mut output).await;
+                    output
+                });
+
+                let applied = self
+                    .apply(&mut conn, BufReader::new(stdout), options, false)
+                    .await;
+                if applied.is_err() {
+                    let _ = child.kill().await;
+                }
+                let status = child.wait().await.map_err(|e| e.to_string())?;
+                let stderr = stderr_task.await.unwrap_or_default();
+                for line in stderr.lines().filter(|l| !l.trim().is_empty()) {
+                    self.log(format!("pg_restore: {}", line)).await;
+                }
+                applied?;
+                if !status.success() {
+                    return Err(format!("pg_restore exited with {}", status));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Execute a SQL script, in one transaction when asked to
+    async fn apply<R: AsyncBufRead + Unpin>(
+        &self,
+        conn: &mut PgConnection,
+        script: R,
+        options: &RestoreOptions,
+        count_bytes: bool,
+    ) -> Result<(), String> {
+        if options.single_transaction {
+            execute(conn, "BEGIN").await?;
+        }
+        let result = self.execute_script(conn, script, count_bytes).await;
+        if options.single_transaction {
+            if result.is_ok() {
+                execute(conn, "COMMIT").await?;
+            } else {
+                let _ = execute(conn, "ROLLBACK").await;
+                self.log("Rolled back; no changes were made".to_string())
+                    .await;
+            }
+        }
+        result
+    }
+
+    async fn execute_script<R: AsyncBufRead + Unpin>(
+        &self,
+        conn: &mut PgConnection,
+        script: R,
+        count_bytes: bool,
+    ) -> Result<(), String> {
+        let mut lines = script.lines();
+        let mut reader = ScriptReader::default();
+        let mut bytes_read = 0u64;
+
+        loop {
+            let line = lines
+                .next_line()
+                .await
+                .map_err(|e| format!("Failed to read script: {}", e))?;
+            let item = match &line {
+                Some(line) => {
+                    bytes_read += line.len() as u64 + 1;
+                    reader.push_line(line)
+                }
+                None => reader.finish(),
+            };
+
+            match item {
+                Some(ScriptItem::Statement(sql)) => {
+                    execute(conn, &sql)
+                        .await
+                        .map_err(|e| format!("{} (in: {})", e, summarize(&sql)))?;
+                    self.update(|job| {
+                        job.statements += 1;
+                        if count_bytes {
+                            job.bytes_read = Some(bytes_read);
+                        }
+                        job.push_log(summarize(&sql));
+                    })
+                    .await;
+                }
+                Some(ScriptItem::CopyStart(sql)) => {
+                    let rows = self.copy(conn, &sql, &mut lines, &mut reader).await?;
+                    self.update(|job| {
+                        job.statements += 1;
+                        job.rows_copied += rows;
+                        job.push_log(format!("{}: {} rows", summarize(&sql), rows));
+                    })
+                    .await;
+                }
+                Some(ScriptItem::MetaCommand(command)) => {
+                    self.log(format!("Skipped psql command {}", summarize(&command)))
+                        .await;
+                }
+                Some(ScriptItem::CopyData(_) | ScriptItem::CopyEnd) | None => {}
+            }
+
+            if line.is_none() {
+                break;
+            }
+        }
+        if count_bytes {
+            self.update(|job| job.bytes_read = Some(job.bytes_total))
+                .await;
+        }
+        Ok(())
+    }
+
+    /// Stream the data lines of a `COPY ... FROM stdin` block
+    async fn copy<R: AsyncBufRead + Unpin>(
+        &self,
+        conn: &mut PgConnection,
+        sql: &str,
+        lines: &mut tokio::io::Lines<R>,
+        reader: &mut ScriptReader,
+    ) -> Result<u64, String> {
+        let failed = |e: &dyn std::fmt::Display| format!("{} (in: {})", e, summarize(sql));
+        let mut copy = conn.copy_in_raw(sql).await.map_err(|e| failed(&e))?;
+        let mut chunk = String::new();
+
+        while reader.in_copy() {
+            let Some(line) = lines.next_line().await.map_err(|e| failed(&e))? else {
+                let _ = copy.abort("unexpected end of script").await;
+                return Err(failed(&"Script ended inside COPY data"));
+            };
+            if let Some(ScriptItem::CopyData(row)) = reader.push_line(&line) {
+                chunk.push_str(&row);
+                if chunk.len() >= COPY_CHUNK_BYTES {
+                    copy.send(chunk.as_bytes()).await.map_err(|e| failed(&e))?;
+                    chunk.clear();
+                }
+            }
+        }
+        if !chunk.is_empty() {
+            copy.send(chunk.as_bytes()).await.map_err(|e| failed(&e))?;
+        }
+        copy.finish().await.map_err(|e| failed(&e))
+    }
+}
+
+async fn execute(conn: &mut PgConnection, sql: &str) -> Result<(), String> {
+    sqlx::query(sql)
+        .persistent(false)
+        .execute(&mut *conn)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// First line of a statement, shortened for the log
+fn summarize(sql: &str) -> String {
+    let line = sql.lines().next().unwrap_or_default().trim();
+    if line.chars().count() > LOG_STATEMENT_CHARS {
+        format!(
+            "{}…",
+            line.chars().take(LOG_STATEMENT_CHARS).collect::<String>()
+        )
+    } else {
+        line.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(
+            DumpFormat::detect(b"PGDMP\x01\x0f").unwrap(),
+            DumpFormat::Custom
+        );
+        assert_eq!(
+            DumpFormat::detect(b"--\n-- PostgreSQL database dump\n").unwrap(),
+            DumpFormat::Plain
+        );
+        assert!(DumpFormat::detect(&[0x1f, 0x8b, 0x08]).is_err());
+        let mut tar = vec![0u8; 512];
+        tar[257..262].copy_from_slice(b"ustar");
+        assert!(DumpFormat::detect(&tar).is_err());
+    }
+
+    #[test]
+    fn test_script_reader() {
+        let script = "--\n-- Name: orders\n--\n\\restrict abc\n\
+                      SET statement_timeout = 0;\n\
+                      CREATE FUNCTION f() RETURNS int AS $_$\nSELECT 1;\n$_$ LANGUAGE sql;\n\
+                      \n\
+                      COPY public.orders (id, note) FROM stdin;\n1\ta;b\n2\t\\N\n\\.\n\
+                      SELECT 1";
+        let mut reader = ScriptReader::default();
+        let mut items: Vec<ScriptItem> = script
+            .lines()
+            .filter_map(|line| reader.push_line(line))
+            .collect();
+        items.extend(reader.finish());
+
+        assert_eq!(
+            items,
+            vec![
+                ScriptItem::MetaCommand("\\restrict abc".to_string()),
+                ScriptItem::Statement("SET statement_timeout = 0".to_string()),
+                ScriptItem::Statement(
+                    "CREATE FUNCTION f() RETURNS int AS $_$\nSELECT 1;\n$_$ LANGUAGE sql"
+                        .to_string()
+                ),
+                ScriptItem::CopyStart("COPY public.orders (id, note) FROM stdin".to_string()),
+                ScriptItem::CopyData("1\ta;b\n".to_string()),
+                ScriptItem::CopyData("2\t\\N\n".to_string()),
+                ScriptItem::CopyEnd,
+                ScriptItem::Statement("SELECT 1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pg_restore_args() {
+        let options = RestoreOptions {
+            database: None,
+            file_name: None,
+            single_transaction: true,
+            clean: true,
+            no_owner: true,
+        };
+        assert_eq!(
+            pg_restore_args(&options, std::path::Path::new("/tmp/up.dump")),
+            vec![
+                "--file=-",
+                "--no-owner",
+                "--clean",
+                "--if-exists",
+                "/tmp/up.dump"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_log_since() {
+        let mut job = RestoreJob {
+            id: "j".to_string(),
+            database: "db".to_string(),
+            file_name: "f.sql".to_string(),
+            format: DumpFormat::Plain,
+            status: JobStatus::Running,
+            started_at: Utc::now(),
+            finished_at: None,
+            bytes_total: 200,
+            bytes_read: Some(50),
+            statements: 0,
+            rows_copied: 0,
+            error: None,
+            log_skipped: 0,
+            log: Vec::new(),
+        };
+        for i in 0..MAX_LOG_LINES + 10 {
+            job.push_log(i.to_string());
+        }
+        assert_eq!(job.log_skipped, 10);
+        assert_eq!(job.log_written(), MAX_LOG_LINES + 10);
+        assert_eq!(job.log_since(MAX_LOG_LINES + 8), ["508", "509"]);
+        assert_eq!(job.log_since(0).len(), MAX_LOG_LINES);
+    }
+}
@@ -0,0 +1,263 @@
+use crate::models::QueryResult;
+use crate::services::artifact_service::ArtifactStore;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+/// Persisted Query Results
+///
+/// Console results with more rows than the display threshold are written
+/// to the artifact directory in full, and only the first rows are sent to
+/// the browser. The full result stays downloadable by token until its TTL
+/// runs out, so a reload or a second export does not re-run a slow query.
+///
+/// Results are kept on local disk only (never offloaded) and removed by a
+/// background sweep once expired, ahead of the artifact retention policy.
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Session key holding the browser's last persisted result
+pub const SESSION_KEY: &str = "last_result";
+
+const NAME_PREFIX: &str = "result-";
+const NAME_SUFFIX: &str = ".json";
+
+/// A result as stored on disk
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredResult {
+    pub token: String,
+    pub query: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl StoredResult {
+    /// The full result, for exporting
+    pub fn to_query_result(&self) -> QueryResult {
+        QueryResult {
+            columns: self.columns.clone(),
+            rows: self.rows.clone(),
+            row_count: self.rows.len(),
+            affected_rows: None,
+            execution_time_ms: None,
+        }
+    }
+}
+
+/// What the browser gets instead of the rows past the threshold
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct ResultDownload {
+    #[schema(format = Uuid)]
+    pub token: String,
+    pub total_rows: usize,
+    pub shown_rows: usize,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// The browser's last persisted result, kept in its session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastResult {
+    pub token: String,
+    pub query: String,
+}
+
+/// Stores oversized results in the artifact directory
+pub struct ResultStore {
+    artifacts: Arc<ArtifactStore>,
+    /// Rows sent to the browser before the rest is persisted; 0 disables it
+    threshold_rows: usize,
+    ttl: Duration,
+}
+
+impl ResultStore {
+    pub fn new(artifacts: Arc<ArtifactStore>, threshold_rows: usize, ttl: Duration) -> Self {
+        Self {
+            artifacts,
+            threshold_rows,
+            ttl,
+        }
+    }
+
+    /// Whether a result with `rows` rows is too large to send in full
+    pub fn exceeds(&self, rows: usize) -> bool {
+        self.threshold_rows > 0 && rows > self.threshold_rows
+    }
+
+    /// Persist a result in full, then cut `rows` down to the rows shown
+    pub async fn persist(
+        &self,
+        query: &str,
+        columns: &[String],
+        rows: &mut Vec<Vec<Value>>,
+    ) -> Result<ResultDownload, String> {
+        let created_at = Utc::now();
+        let expires_at =
+            created_at + chrono::Duration::from_std(self.ttl).unwrap_or(chrono::Duration::MAX);
+        let stored = StoredResult {
+            token: Uuid::new_v4().to_string(),
+            query: query.to_string(),
+            created_at,
+            expires_at,
+            columns: columns.to_vec(),
+            rows: rows.clone(),
+        };
+        let contents = serde_json::to_vec(&stored)
+            .map_err(|e| format!("Failed to serialize result: {}", e))?;
+        self.artifacts
+            .store_local(&artifact_name(&stored.token), &contents)
+            .await?;
+
+        rows.truncate(self.threshold_rows);
+        Ok(ResultDownload {
+            token: stored.token,
+            total_rows: stored.rows.len(),
+            shown_rows: rows.len(),
+            expires_at,
+        })
+    }
+
+    /// A persisted result, `None` when the token is unknown or expired
+    pub async fn load(&self, token: &str) -> Result<Option<StoredResult>, String> {
+        if Uuid::parse_str(token).is_err() {
+            return Ok(None);
+        }
+        let path = self.artifacts.path_for(&artifact_name(token))?;
+        let contents = match tokio::fs::read(&path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("Failed to read result {}: {}", token, e)),
+        };
+        let stored: StoredResult = serde_json::from_slice(&contents)
+            .map_err(|e| format!("Failed to parse result {}: {}", token, e))?;
+        if stored.expires_at <= Utc::now() {
+            return Ok(None);
+        }
+        Ok(Some(stored))
+    }
+
+    /// Whether a result is still kept, without reading it
+    pub async fn contains(&self, token: &str) -> bool {
+        let ttl = chrono::Duration::from_std(self.ttl).unwrap_or(chrono::Duration::MAX);
+        let Ok(path) = self.artifacts.path_for(&artifact_name(token)) else {
+            return false;
+        };
+        match tokio::fs::metadata(&path).await.and_then(|m| m.modified()) {
+            Ok(modified) => Utc::now() - DateTime::<Utc>::from(modified) < ttl,
+            Err(_) => false,
+        }
+    }
+
+    /// The download summary of a persisted result, as first returned
+    pub fn download(&self, stored: &StoredResult) -> ResultDownload {
+        ResultDownload {
+            token: stored.token.clone(),
+            total_rows: stored.rows.len(),
+            shown_rows: stored.rows.len().min(self.threshold_rows),
+            expires_at: stored.expires_at,
+        }
+    }
+
+    /// Delete results whose TTL has run out, returning how many
+    pub async fn prune_expired(&self) -> Result<usize, String> {
+        let now = Utc::now();
+        let ttl = chrono::Duration::from_std(self.ttl).unwrap_or(chrono::Duration::MAX);
+        let mut deleted = 0;
+        for artifact in self.artifacts.list().await? {
+            if is_result_artifact(&artifact.name) && now - artifact.created_at >= ttl {
+                match self.artifacts.delete_local(&artifact.name).await {
+                    Ok(()) => deleted += 1,
+                    Err(e) => tracing::warn!("{}", e),
+                }
+            }
+        }
+        Ok(deleted)
+    }
+}
+
+fn artifact_name(token: &str) -> String {
+    format!("{}{}{}", NAME_PREFIX, token, NAME_SUFFIX)
+}
+
+fn is_result_artifact(name: &str) -> bool {
+    name.strip_prefix(NAME_PREFIX)
+        .and_then(|rest| rest.strip_suffix(NAME_SUFFIX))
+        .is_some_and(|token| Uuid::parse_str(token).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::artifact_service::RetentionPolicy;
+    use serde_json::json;
+
+    fn store(dir: &std::path::Path, ttl: Duration) -> ResultStore {
+        let artifacts = Arc::new(ArtifactStore::new(dir, RetentionPolicy::default()));
+        ResultStore::new(artifacts, 3, ttl)
+    }
+
+    #[test]
+    fn test_exceeds() {
+        let dir = std::env::temp_dir();
+        assert!(!store(&dir, Duration::from_secs(60)).exceeds(3));
+        assert!(store(&dir, Duration::from_secs(60)).exceeds(4));
+        let disabled = ResultStore::new(
+            Arc::new(ArtifactStore::new(&dir, RetentionPolicy::default())),
+            0,
+            Duration::from_secs(60),
+        );
+        assert!(!disabled.exceeds(1_000_000));
+    }
+
+    #[test]
+    fn test_is_result_artifact() {
+        let token = Uuid::new_v4().to_string();
+        assert!(is_result_artifact(&artifact_name(&token)));
+        assert!(!is_result_artifact("result-notes.json"));
+        assert!(!is_result_artifact(
+            "backup_manual_20240101T000000Z_app.dump"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_persist_load_and_prune() {
+        let dir = std::env::temp_dir().join(format!("pgadmin-results-{}", Uuid::new_v4()));
+        let store = store(&dir, Duration::from_secs(3600));
+
+        let columns = vec!["n".to_string()];
+        let mut rows: Vec<Vec<Value>> = (0..10).map(|i| vec![json!(i)]).collect();
+        let download = store
+            .persist("SELECT n", &columns, &mut rows)
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!((download.total_rows, download.shown_rows), (10, 3));
+
+        let stored = store.load(&download.token).await.unwrap().unwrap();
+        assert_eq!(stored.query, "SELECT n");
+        assert_eq!(stored.to_query_result().row_count, 10);
+        assert_eq!(store.download(&stored), download);
+        assert!(store.contains(&download.token).await);
+        assert!(!store.contains("../etc/passwd").await);
+        assert!(store.load("../etc/passwd").await.unwrap().is_none());
+        assert!(store
+            .load(&Uuid::new_v4().to_string())
+            .await
+            .unwrap()
+            .is_none());
+
+        assert_eq!(store.prune_expired().await.unwrap(), 0);
+        let expiring = ResultStore {
+            ttl: Duration::ZERO,
+            ..store
+        };
+        assert!(expiring.load(&download.token).await.unwrap().is_some());
+        assert!(!expiring.contains(&download.token).await);
+        assert_eq!(expiring.prune_expired().await.unwrap(), 1);
+        assert!(expiring.load(&download.token).await.unwrap().is_none());
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -0,0 +1,602 @@
+use crate::services::json_file;
+use crate::services::query_history::{count_tags, has_tag, normalize_tags, TagCount};
+use crate::services::query_service::QueryParam;
+use crate::services::sql_lexer::{
+    is_escape_string, is_ident_char, skip_block_comment, skip_dollar_quoted, skip_line_comment,
+    skip_quoted,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+/// Saved Query Service
+///
+/// Keeps named queries ("favorites") that users want to re-run from the
+/// query editor. Queries are held in memory and written to a JSON file after
+/// every change so they survive restarts.
+///
+/// Saved queries may declare named placeholders such as `:start_date`. They
+/// are rewritten to positional parameters (`$1`, ...) at execution time so
+/// values are always bound, never spliced into the SQL text.
+use std::path::PathBuf;
+use tokio::sync::RwLock;
+
+/// Maximum length of a saved query name or folder
+const MAX_NAME_LENGTH: usize = 200;
+
+/// A named, reusable SQL query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedQuery {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub sql: String,
+    /// Named placeholders in the SQL, in order of first appearance
+    #[serde(default)]
+    pub parameters: Vec<String>,
+    /// Folder the query is filed under, e.g. a project name
+    #[serde(default)]
+    pub folder: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Definitions file the query is declared in (see
+    /// `definitions_service`); `None` for queries saved through the API
+    #[serde(default)]
+    pub managed_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Fields supplied when creating or updating a saved query
+#[derive(Debug, Clone, Deserialize)]
+pub struct SavedQueryInput {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub sql: String,
+    #[serde(default)]
+    pub folder: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl SavedQueryInput {
+    /// Trim the fields and reject blank or oversized values
+    pub fn normalize(self) -> Result<Self, String> {
+        let name = self.name.trim().to_string();
+        if name.is_empty() {
+            return Err("Name cannot be empty".to_string());
+        }
+        if name.chars().count() > MAX_NAME_LENGTH {
+            return Err(format!(
+                "Name cannot be longer than {} characters",
+                MAX_NAME_LENGTH
+            ));
+        }
+
+        let sql = self.sql.trim().to_string();
+        if sql.is_empty() {
+            return Err("SQL cannot be empty".to_string());
+        }
+
+        compile_placeholders(&sql)?;
+
+        let description = self
+            .description
+            .map(|d| d.trim().to_string())
+            .filter(|d| !d.is_empty());
+
+        let folder = self
+            .folder
+            .map(|f| f.trim().to_string())
+            .filter(|f| !f.is_empty());
+        if folder
+            .as_ref()
+            .is_some_and(|f| f.chars().count() > MAX_NAME_LENGTH)
+        {
+            return Err(format!(
+                "Folder cannot be longer than {} characters",
+                MAX_NAME_LENGTH
+            ));
+        }
+
+        Ok(Self {
+            name,
+            description,
+            sql,
+            folder,
+            tags: normalize_tags(self.tags)?,
+        })
+    }
+}
+
+/// SQL with named placeholders rewritten to positional parameters
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompiledSql {
+    pub sql: String,
+    /// Placeholder names; `names[0]` binds to `$1`
+    pub names: Vec<String>,
+}
+
+impl CompiledSql {
+    /// Order the supplied values by placeholder position, requiring every one
+    pub fn bind(
+        &self,
+        values: &HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<QueryParam>, String> {
+        self.names
+            .iter()
+            .map(|name| {
+                values
+                    .get(name)
+                    .map(QueryParam::from_json)
+                    .ok_or_else(|| format!("Missing value for parameter :{}", name))
+            })
+            .collect()
+    }
+}
+
+/// Rewrite `:name` placeholders to `$1`, `$2`, ...
+///
+/// Repeated names share one parameter. String literals, quoted identifiers,
+/// dollar-quoted bodies, comments and `::` casts are left untouched, so
+/// `:start_date::date` is a placeholder followed by a cast. Positional `$n`
+/// parameters are rejected to avoid mixing the two styles.
+pub fn compile_placeholders(sql: &str) -> Result<CompiledSql, String> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut out = String::with_capacity(sql.len());
+    let mut names: Vec<String> = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            '\'' | '"' => {
+                let end = skip_quoted(&chars, i, c, c == '\'' && is_escape_string(&chars, i));
+                out.extend(&chars[i..end]);
+                i = end;
+            }
+            '-' if next == Some('-') => {
+                let end = skip_line_comment(&chars, i);
+                out.extend(&chars[i..end]);
+                i = end;
+            }
+            '/' if next == Some('*') => {
+                let end = skip_block_comment(&chars, i);
+                out.extend(&chars[i..end]);
+                i = end;
+            }
+            '$' if next.is_some_and(|c| c.is_ascii_digit()) => {
+                return Err(
+                    "Positional parameters ($1) are not supported; use named placeholders like :name"
+                        .to_string(),
+                );
+            }
+            '$' if i == 0 || !is_ident_char(chars[i - 1]) => {
+                let end = skip_dollar_quoted(&chars, i);
+                out.extend(&chars[i..end]);
+                i = end;
+            }
+            ':' if next == Some(':') => {
+                out.push_str("::");
+                i += 2;
+            }
+            ':' if next.is_some_and(|c| c.is_ascii_alphabetic() || c == '_') => {
+                let start = i + 1;
+                let end = chars[start..]
+                    .iter()
+                    .position(|&c| !is_ident_char(c))
+                    .map_or(chars.len(), |p| start + p);
+                let name: String = chars[start..end].iter().collect();
+                let index = match names.iter().position(|n| *n == name) {
+                    Some(pos) => pos + 1,
+                    None => {
+                        names.push(name);
+                        names.len()
+                    }
+                };
+                out.push_str(&format!("${}", index));
+                i = end;
+            }
+            _ => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    Ok(CompiledSql { sql: out, names })
+}
+
+/// Placeholder names in `sql`, or none if it cannot be compiled
+fn parameter_names(sql: &str) -> Vec<String> {
+    compile_placeholders(sql)
+        .map(|compiled| compiled.names)
+        .unwrap_or_default()
+}
+
+/// Criteria for listing saved queries; unset fields match everything
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SavedQueryFilter {
+    /// Only queries carrying this tag (case-insensitive)
+    pub tag: Option<String>,
+    /// Only queries in this folder (case-insensitive)
+    pub folder: Option<String>,
+}
+
+impl SavedQueryFilter {
+    /// Check whether a saved query satisfies every set criterion
+    pub fn matches(&self, query: &SavedQuery) -> bool {
+        self.tag
+            .as_deref()
+            .is_none_or(|tag| has_tag(&query.tags, tag))
+            && self.folder.as_deref().is_none_or(|folder| {
+                query
+                    .folder
+                    .as_deref()
+                    .is_some_and(|f| f.eq_ignore_ascii_case(folder))
+            })
+    }
+}
+
+/// Number of saved queries filed under a folder
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FolderCount {
+    pub folder: String,
+    pub count: usize,
+}
+
+/// Saved queries persisted to a JSON file
+pub struct SavedQueryStore {
+    path: PathBuf,
+    queries: RwLock<Vec<SavedQuery>>,
+}
+
+impl SavedQueryStore {
+    /// Load saved queries from `path`, starting empty if the file does not exist
+    pub async fn load(path: impl Into<PathBuf>) -> Result<Self, String> {
+        let path = path.into();
+        let mut queries: Vec<SavedQuery> = json_file::load(&path, "saved queries").await?;
+
+        // Files written before placeholders were supported have no parameter list
+        for query in &mut queries {
+            query.parameters = parameter_names(&query.sql);
+        }
+
+        Ok(Self {
+            path,
+            queries: RwLock::new(queries),
+        })
+    }
+
+    /// Saved queries matching a filter, ordered by name
+    pub async fn list(&self, filter: &SavedQueryFilter) -> Vec<SavedQuery> {
+        let mut queries: Vec<SavedQuery> = self
+            .queries
+            .read()
+            .await
+            .iter()
+            .filter(|q| filter.matches(q))
+            .cloned()
+            .collect();
+        queries.sort_by_key(|q| q.name.to_lowercase());
+        queries
+    }
+
+    /// Tags used across saved queries with query counts
+    pub async fn tag_counts(&self) -> Vec<TagCount> {
+        let queries = self.queries.read().await;
+        count_tags(queries.iter().map(|q| q.tags.as_slice()))
+    }
+
+    /// Folders in use with query counts, ordered by name
+    pub async fn folder_counts(&self) -> Vec<FolderCount> {
+        let queries = self.queries.read().await;
+        let mut counts: Vec<FolderCount> = Vec::new();
+        for folder in queries.iter().filter_map(|q| q.folder.as_ref()) {
+            match counts
+                .iter_mut()
+                .find(|c| c.folder.eq_ignore_ascii_case(folder))
+            {
+                Some(existing) => existing.count += 1,
+                None => counts.push(FolderCount {
+                    folder: folder.clone(),
+                    count: 1,
+                }),
+            }
+        }
+        counts.sort_by_key(|c| c.folder.to_lowercase());
+        counts
+    }
+
+    /// Get a saved query by ID
+    pub async fn get(&self, id: &str) -> Option<SavedQuery> {
+        let queries = self.queries.read().await;
+        queries.iter().find(|q| q.id == id).cloned()
+    }
+
+    /// Save a new query
+    pub async fn create(&self, input: SavedQueryInput) -> Result<SavedQuery, String> {
+        self.insert(input, None).await
+    }
+
+    /// Save a query declared in the definitions file `source`
+    pub async fn create_managed(
+        &self,
+        input: SavedQueryInput,
+        source: &str,
+    ) -> Result<SavedQuery, String> {
+        self.insert(input, Some(source.to_string())).await
+    }
+
+    async fn insert(
+        &self,
+        input: SavedQueryInput,
+        managed_by: Option<String>,
+    ) -> Result<SavedQuery, String> {
+        let input = input.normalize()?;
+        let now = Utc::now();
+        let query = SavedQuery {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: input.name,
+            description: input.description,
+            parameters: parameter_names(&input.sql),
+            sql: input.sql,
+            folder: input.folder,
+            tags: input.tags,
+            managed_by,
+            created_at: now,
+            updated_at: now,
+        };
+
+        let mut queries = self.queries.write().await;
+        queries.push(query.clone());
+        self.persist(&queries).await?;
+        Ok(query)
+    }
+
+    /// Replace the fields of an existing query, returning `None` if it does not exist
+    pub async fn update(
+        &self,
+        id: &str,
+        input: SavedQueryInput,
+    ) -> Result<Option<SavedQuery>, String> {
+        let input = input.normalize()?;
+        let mut queries = self.queries.write().await;
+        let Some(query) = queries.iter_mut().find(|q| q.id == id) else {
+            return Ok(None);
+        };
+
+        query.name = input.name;
+        query.description = input.description;
+        query.parameters = parameter_names(&input.sql);
+        query.sql = input.sql;
+        query.folder = input.folder;
+        query.tags = input.tags;
+        query.updated_at = Utc::now();
+        let updated = query.clone();
+
+        self.persist(&queries).await?;
+        Ok(Some(updated))
+    }
+
+    /// Delete a saved query, returning whether it existed
+    pub async fn delete(&self, id: &str) -> Result<bool, String> {
+        let mut queries = self.queries.write().await;
+        let before = queries.len();
+        queries.retain(|q| q.id != id);
+        if queries.len() == before {
+            return Ok(false);
+        }
+
+        self.persist(&queries).await?;
+        Ok(true)
+    }
+
+    async fn persist(&self, queries: &[SavedQuery]) -> Result<(), String> {
+        json_file::save(&self.path, queries, "saved queries").await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("saved-queries-{}", uuid::Uuid::new_v4()))
+            .join("saved_queries.json")
+    }
+
+    fn input(name: &str, sql: &str) -> SavedQueryInput {
+        SavedQueryInput {
+            name: name.to_string(),
+            description: None,
+            sql: sql.to_string(),
+            folder: None,
+            tags: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_input_normalization() {
+        let normalized = SavedQueryInput {
+            name: "  Active users ".to_string(),
+            description: Some("   ".to_string()),
+            sql: "\nSELECT 1\n".to_string(),
+            folder: Some(" ".to_string()),
+            tags: vec![" ops ".to_string(), "OPS".to_string()],
+        }
+        .normalize()
+        .unwrap();
+        assert_eq!(normalized.name, "Active users");
+        assert_eq!(normalized.description, None);
+        assert_eq!(normalized.sql, "SELECT 1");
+        assert_eq!(normalized.folder, None);
+        assert_eq!(normalized.tags, vec!["ops"]);
+
+        assert!(input(" ", "SELECT 1").normalize().is_err());
+        assert!(input("Empty", "  ").normalize().is_err());
+        assert!(input(&"x".repeat(201), "SELECT 1").normalize().is_err());
+    }
+
+    #[test]
+    fn test_compile_placeholders() {
+        let compiled = compile_placeholders(
+            "SELECT * FROM orders WHERE created_at >= :start_date::date \
+             AND created_at < :end_date::date AND status = :status OR :status IS NULL",
+        )
+        .unwrap();
+        assert_eq!(
+            compiled.sql,
+            "SELECT * FROM orders WHERE created_at >= $1::date \
+             AND created_at < $2::date AND status = $3 OR $3 IS NULL"
+        );
+        assert_eq!(compiled.names, vec!["start_date", "end_date", "status"]);
+    }
+
+    #[test]
+    fn test_bind_values_in_placeholder_order() {
+        let compiled = compile_placeholders("SELECT :start, :limit").unwrap();
+        let values: HashMap<String, serde_json::Value> = serde_json::from_value(
+            serde_json::json!({ "limit": 10, "start": "2024-01-01", "unused": true }),
+        )
+        .unwrap();
+
+        assert_eq!(
+            compiled.bind(&values).unwrap(),
+            vec![
+                QueryParam::Text("2024-01-01".to_string()),
+                QueryParam::Int(10)
+            ]
+        );
+
+        let missing = compiled.bind(&HashMap::new()).unwrap_err();
+        assert_eq!(missing, "Missing value for parameter :start");
+    }
+
+    #[test]
+    fn test_placeholders_ignored_in_literals_and_comments() {
+        let sql = "SELECT ':not_a_param', \"col:name\", E'it\\'s :x', $body$ :y $body$ \
+                   -- :comment\n/* :block /* nested */ */ FROM t WHERE a = :real";
+        let compiled = compile_placeholders(sql).unwrap();
+        assert_eq!(compiled.names, vec!["real"]);
+        assert!(compiled.sql.ends_with("WHERE a = $1"));
+        assert!(compiled.sql.contains("':not_a_param'"));
+
+        let none = compile_placeholders("SELECT now()::timestamptz, arr[1:2] FROM t").unwrap();
+        assert!(none.names.is_empty());
+        assert_eq!(none.sql, "SELECT now()::timestamptz, arr[1:2] FROM t");
+    }
+
+    #[test]
+    fn test_positional_parameters_rejected() {
+        assert!(compile_placeholders("SELECT $1").is_err());
+        assert!(input("Mixed", "SELECT :a, $2").normalize().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_crud() {
+        let path = temp_path();
+        let store = SavedQueryStore::load(&path).await.unwrap();
+        let b = store.create(input("b query", "SELECT 2")).await.unwrap();
+        let a = store.create(input("A query", "SELECT 1")).await.unwrap();
+
+        let names: Vec<String> = store
+            .list(&SavedQueryFilter::default())
+            .await
+            .into_iter()
+            .map(|q| q.name)
+            .collect();
+        assert_eq!(names, vec!["A query", "b query"]);
+        assert!(a.parameters.is_empty());
+
+        let updated = store
+            .update(&a.id, input("A query", "SELECT :n + :m + :n"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.sql, "SELECT :n + :m + :n");
+        assert_eq!(updated.parameters, vec!["n", "m"]);
+        assert_eq!(updated.created_at, a.created_at);
+        assert!(updated.updated_at >= a.updated_at);
+        assert!(store
+            .update("missing", input("x", "SELECT 1"))
+            .await
+            .unwrap()
+            .is_none());
+
+        assert!(store.delete(&b.id).await.unwrap());
+        assert!(!store.delete(&b.id).await.unwrap());
+        assert!(store.get(&b.id).await.is_none());
+        assert_eq!(store.get(&a.id).await, Some(updated));
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_filter_by_tag_and_folder() {
+        let path = temp_path();
+        let store = SavedQueryStore::load(&path).await.unwrap();
+        let billing = store
+            .create(SavedQueryInput {
+                folder: Some("Billing".to_string()),
+                tags: vec!["JIRA-1".to_string(), "slow".to_string()],
+                ..input("Invoices", "SELECT 1")
+            })
+            .await
+            .unwrap();
+        store
+            .create(SavedQueryInput {
+                tags: vec!["slow".to_string()],
+                ..input("Sizes", "SELECT 2")
+            })
+            .await
+            .unwrap();
+
+        let by_tag = store
+            .list(&SavedQueryFilter {
+                tag: Some("jira-1".to_string()),
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(by_tag, vec![billing.clone()]);
+
+        let by_folder = store
+            .list(&SavedQueryFilter {
+                folder: Some("billing".to_string()),
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(by_folder, vec![billing]);
+
+        let tags = store.tag_counts().await;
+        assert_eq!(tags.len(), 2);
+        assert_eq!((tags[1].tag.as_str(), tags[1].count), ("slow", 2));
+        assert_eq!(
+            store.folder_counts().await,
+            vec![FolderCount {
+                folder: "Billing".to_string(),
+                count: 1
+            }]
+        );
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_persistence() {
+        let path = temp_path();
+        let store = SavedQueryStore::load(&path).await.unwrap();
+        assert!(store.list(&SavedQueryFilter::default()).await.is_empty());
+        let saved = store.create(input("Sizes", "SELECT 1")).await.unwrap();
+
+        let reloaded = SavedQueryStore::load(&path).await.unwrap();
+        assert_eq!(
+            reloaded.list(&SavedQueryFilter::default()).await,
+            vec![saved]
+        );
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
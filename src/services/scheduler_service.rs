@@ -0,0 +1,1004 @@
+use crate::config::reload::ReadOnlyMode;
+use crate::models::QueryResult;
+use crate::services::audit_service::{AuditEvent, AuditEventType, AuditLogger};
+use crate::services::cron::CronSchedule;
+use crate::services::export_service::{ExportFormat, ExportService};
+use crate::services::json_file;
+use crate::services::notify::{self, Email, SmtpConfig};
+use crate::services::query_service;
+use crate::services::saved_query_service::{compile_placeholders, SavedQueryStore};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+/// Scheduler Service
+///
+/// Runs saved queries on cron schedules (e.g. nightly sanity checks) and
+/// keeps the results of recent runs:
+/// - Schedules and run results are persisted to a JSON file
+/// - A background task checks for due schedules every few seconds and runs
+///   each one on its own task, so a slow query never delays the others
+/// - Runs missed while the server was down are skipped, not replayed
+/// - Each run can be delivered to a webhook or by email, as a summary or
+///   with the stored rows
+/// - An alert condition (e.g. `row_count > 0`) limits delivery to the runs
+///   where it trips (or that fail), for lightweight monitoring
+///
+/// Scheduled queries go through the same validation as the query console and
+/// respect read-only mode.
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+/// How often the background task looks for due schedules
+pub const TICK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Rows kept from each run's result set
+const MAX_STORED_ROWS: usize = 100;
+
+/// Runs kept per schedule (oldest are dropped first)
+const MAX_RUNS_PER_SCHEDULE: usize = 50;
+
+/// A saved query run on a cron expression
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Schedule {
+    pub id: String,
+    pub name: String,
+    pub saved_query_id: String,
+    /// Five-field cron expression, evaluated in UTC
+    pub cron: String,
+    /// Values for the saved query's named placeholders
+    #[serde(default)]
+    pub params: HashMap<String, serde_json::Value>,
+    pub enabled: bool,
+    /// Where results are sent after each run
+    #[serde(default)]
+    pub delivery: Option<Delivery>,
+    /// Only deliver results when this condition trips
+    #[serde(default)]
+    pub alert: Option<AlertCondition>,
+    /// Definitions file the schedule is declared in (see
+    /// `definitions_service`); `None` for schedules made through the API
+    #[serde(default)]
+    pub managed_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub next_run_at: Option<DateTime<Utc>>,
+}
+
+/// Fields supplied when creating or updating a schedule
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScheduleInput {
+    pub name: String,
+    pub saved_query_id: String,
+    pub cron: String,
+    #[serde(default)]
+    pub params: HashMap<String, serde_json::Value>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub delivery: Option<Delivery>,
+    #[serde(default)]
+    pub alert: Option<AlertCondition>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Where a schedule's results are sent after each run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Delivery {
+    #[serde(flatten)]
+    pub target: DeliveryTarget,
+    /// Send the stored rows, not just a summary of the run
+    #[serde(default)]
+    pub include_rows: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DeliveryTarget {
+    /// JSON `POST` of the run to an `http://` URL
+    Webhook { url: String },
+    /// Plain-text email through the SMTP relay
+    Email { to: Vec<String> },
+}
+
+/// A threshold checked against each successful run, e.g. `{"metric": "row_count", "op": "gt", "value": 0}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlertCondition {
+    pub metric: AlertMetric,
+    pub op: AlertOp,
+    pub value: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertMetric {
+    /// Number of rows returned
+    RowCount,
+    /// First column of the first row; must be numeric (cast NUMERIC to float8)
+    FirstValue,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertOp {
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    Eq,
+    Ne,
+}
+
+impl AlertOp {
+    fn symbol(self) -> &'static str {
+        match self {
+            AlertOp::Gt => ">",
+            AlertOp::Gte => ">=",
+            AlertOp::Lt => "<",
+            AlertOp::Lte => "<=",
+            AlertOp::Eq => "=",
+            AlertOp::Ne => "!=",
+        }
+    }
+
+    fn compare(self, observed: f64, threshold: f64) -> bool {
+        match self {
+            AlertOp::Gt => observed > threshold,
+            AlertOp::Gte => observed >= threshold,
+            AlertOp::Lt => observed < threshold,
+            AlertOp::Lte => observed <= threshold,
+            AlertOp::Eq => observed == threshold,
+            AlertOp::Ne => observed != threshold,
+        }
+    }
+}
+
+impl AlertCondition {
+    /// The observed value if the condition trips on this run
+    pub fn check(&self, run: &ScheduleRun) -> Option<f64> {
+        if !run.success {
+            return None;
+        }
+        let observed = match self.metric {
+            AlertMetric::RowCount => run.row_count? as f64,
+            AlertMetric::FirstValue => match run.rows.first()?.first()? {
+                serde_json::Value::Number(n) => n.as_f64()?,
+                serde_json::Value::String(s) => s.trim().parse().ok()?,
+                serde_json::Value::Bool(b) => f64::from(u8::from(*b)),
+                _ => return None,
+            },
+        };
+        self.op.compare(observed, self.value).then_some(observed)
+    }
+}
+
+impl std::fmt::Display for AlertCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let metric = match self.metric {
+            AlertMetric::RowCount => "row_count",
+            AlertMetric::FirstValue => "first_value",
+        };
+        write!(f, "{} {} {}", metric, self.op.symbol(), self.value)
+    }
+}
+
+/// An alert that tripped on a run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TriggeredAlert {
+    pub condition: String,
+    pub observed: f64,
+}
+
+/// The outcome of one scheduled run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduleRun {
+    pub id: String,
+    pub schedule_id: String,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    pub success: bool,
+    /// Total rows returned, even when only the first rows are stored
+    pub row_count: Option<usize>,
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<serde_json::Value>>,
+    /// Whether `rows` was cut to the first rows of the result
+    pub truncated: bool,
+    pub error: Option<String>,
+    /// Why the results could not be delivered, if they were not
+    #[serde(default)]
+    pub delivery_error: Option<String>,
+    /// Set when the schedule's alert condition tripped
+    #[serde(default)]
+    pub alert: Option<TriggeredAlert>,
+}
+
+impl ScheduleRun {
+    fn failed(
+        schedule_id: &str,
+        started_at: DateTime<Utc>,
+        duration_ms: u64,
+        error: String,
+    ) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            schedule_id: schedule_id.to_string(),
+            started_at,
+            duration_ms,
+            success: false,
+            row_count: None,
+            columns: Vec::new(),
+            rows: Vec::new(),
+            truncated: false,
+            error: Some(error),
+            delivery_error: None,
+            alert: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ScheduleData {
+    schedules: Vec<Schedule>,
+    runs: Vec<ScheduleRun>,
+}
+
+/// Schedules and their run results, persisted to a JSON file
+pub struct ScheduleStore {
+    path: PathBuf,
+    data: RwLock<ScheduleData>,
+}
+
+impl ScheduleStore {
+    /// Load schedules from `path`, starting empty if the file does not exist
+    pub async fn load(path: impl Into<PathBuf>) -> Result<Self, String> {
+        let path = path.into();
+        let mut data: ScheduleData = json_file::load(&path, "schedules").await?;
+
+        // Skip runs that were missed while the server was down
+        let now = Utc::now();
+        for schedule in &mut data.schedules {
+            if schedule.next_run_at.is_none_or(|next| next <= now) {
+                schedule.next_run_at = next_run(schedule, now);
+            }
+        }
+
+        Ok(Self {
+            path,
+            data: RwLock::new(data),
+        })
+    }
+
+    /// All schedules, ordered by name
+    pub async fn list(&self) -> Vec<Schedule> {
+        let mut schedules = self.data.read().await.schedules.clone();
+        schedules.sort_by_key(|s| s.name.to_lowercase());
+        schedules
+    }
+
+    /// Get a schedule by ID
+    pub async fn get(&self, id: &str) -> Option<Schedule> {
+        let data = self.data.read().await;
+        data.schedules.iter().find(|s| s.id == id).cloned()
+    }
+
+    /// Create a schedule from validated input
+    pub async fn create(&self, input: ScheduleInput) -> Result<Schedule, String> {
+        self.insert(input, None).await
+    }
+
+    /// Create a schedule declared in the definitions file `source`
+    pub async fn create_managed(
+        &self,
+        input: ScheduleInput,
+        source: &str,
+    ) -> Result<Schedule, String> {
+        self.insert(input, Some(source.to_string())).await
+    }
+
+    async fn insert(
+        &self,
+        input: ScheduleInput,
+        managed_by: Option<String>,
+    ) -> Result<Schedule, String> {
+        let now = Utc::now();
+        let mut schedule = Schedule {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: input.name,
+            saved_query_id: input.saved_query_id,
+            cron: input.cron,
+            params: input.params,
+            enabled: input.enabled,
+            delivery: input.delivery,
+            alert: input.alert,
+            managed_by,
+            created_at: now,
+            updated_at: now,
+            last_run_at: None,
+            next_run_at: None,
+        };
+        schedule.next_run_at = next_run(&schedule, now);
+
+        let mut data = self.data.write().await;
+        data.schedules.push(schedule.clone());
+        self.persist(&data).await?;
+        Ok(schedule)
+    }
+
+    /// Replace a schedule's fields from validated input, returning `None` if it does not exist
+    pub async fn update(&self, id: &str, input: ScheduleInput) -> Result<Option<Schedule>, String> {
+        let now = Utc::now();
+        let mut data = self.data.write().await;
+        let Some(schedule) = data.schedules.iter_mut().find(|s| s.id == id) else {
+            return Ok(None);
+        };
+
+        schedule.name = input.name;
+        schedule.saved_query_id = input.saved_query_id;
+        schedule.cron = input.cron;
+        schedule.params = input.params;
+        schedule.enabled = input.enabled;
+        schedule.delivery = input.delivery;
+        schedule.alert = input.alert;
+        schedule.updated_at = now;
+        schedule.next_run_at = next_run(schedule, now);
+        let updated = schedule.clone();
+
+        self.persist(&data).await?;
+        Ok(Some(updated))
+    }
+
+    /// Delete a schedule and its runs, returning whether it existed
+    pub async fn delete(&self, id: &str) -> Result<bool, String> {
+        let mut data = self.data.write().await;
+        let before = data.schedules.len();
+        data.schedules.retain(|s| s.id != id);
+        if data.schedules.len() == before {
+            return Ok(false);
+        }
+        data.runs.retain(|r| r.schedule_id != id);
+
+        self.persist(&data).await?;
+        Ok(true)
+    }
+
+    /// Runs of a schedule, newest first
+    pub async fn runs(&self, schedule_id: &str) -> Vec<ScheduleRun> {
+        let data = self.data.read().await;
+        data.runs
+            .iter()
+            .rev()
+            .filter(|r| r.schedule_id == schedule_id)
+            .cloned()
+            .collect()
+    }
+
+    /// Claim the schedules due at `now`, advancing their next run time
+    pub async fn take_due(&self, now: DateTime<Utc>) -> Result<Vec<Schedule>, String> {
+        let mut data = self.data.write().await;
+        let mut due = Vec::new();
+        for schedule in &mut data.schedules {
+            if schedule.enabled && schedule.next_run_at.is_some_and(|next| next <= now) {
+                schedule.next_run_at = next_run(schedule, now);
+                due.push(schedule.clone());
+            }
+        }
+
+        if !due.is_empty() {
+            self.persist(&data).await?;
+        }
+        Ok(due)
+    }
+
+    /// Store a run result, keeping only the most recent runs per schedule
+    pub async fn record_run(&self, run: ScheduleRun) -> Result<(), String> {
+        let mut data = self.data.write().await;
+        if let Some(schedule) = data.schedules.iter_mut().find(|s| s.id == run.schedule_id) {
+            schedule.last_run_at = Some(run.started_at);
+        }
+
+        let schedule_id = run.schedule_id.clone();
+        data.runs.push(run);
+        let count = data
+            .runs
+            .iter()
+            .filter(|r| r.schedule_id == schedule_id)
+            .count();
+        if count > MAX_RUNS_PER_SCHEDULE {
+            let mut excess = count - MAX_RUNS_PER_SCHEDULE;
+            data.runs.retain(|r| {
+                if excess > 0 && r.schedule_id == schedule_id {
+                    excess -= 1;
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+
+        self.persist(&data).await
+    }
+
+    async fn persist(&self, data: &ScheduleData) -> Result<(), String> {
+        json_file::save(&self.path, data, "schedules").await
+    }
+}
+
+/// Next fire time of an enabled schedule after `after`
+fn next_run(schedule: &Schedule, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    if !schedule.enabled {
+        return None;
+    }
+    CronSchedule::parse(&schedule.cron)
+        .ok()
+        .and_then(|cron| cron.next_after(after))
+}
+
+/// Runs due schedules against the application database
+pub struct Scheduler {
+    pub store: ScheduleStore,
+    saved_queries: Arc<SavedQueryStore>,
+    db_pool: Arc<Pool<Postgres>>,
+    audit_logger: Arc<AuditLogger>,
+    read_only: ReadOnlyMode,
+    smtp: Option<SmtpConfig>,
+}
+
+impl Scheduler {
+    pub fn new(
+        store: ScheduleStore,
+        saved_queries: Arc<SavedQueryStore>,
+        db_pool: Arc<Pool<Postgres>>,
+        audit_logger: Arc<AuditLogger>,
+        read_only: ReadOnlyMode,
+    ) -> Self {
+        Self {
+            store,
+            saved_queries,
+            db_pool,
+            audit_logger,
+            read_only,
+            smtp: None,
+        }
+    }
+
+    /// Enable email delivery through an SMTP relay
+    pub fn with_smtp(mut self, smtp: SmtpConfig) -> Self {
+        self.smtp = Some(smtp);
+        self
+    }
+
+    /// Trim the input and check the cron expression, saved query and parameters
+    pub async fn validate(&self, input: ScheduleInput) -> Result<ScheduleInput, String> {
+        let saved = self
+            .saved_queries
+            .get(&input.saved_query_id)
+            .await
+            .ok_or_else(|| format!("Saved query {} not found", input.saved_query_id))?;
+        self.check(input, &saved.sql)
+    }
+
+    /// Like `validate`, for a schedule of a saved query with the given SQL
+    /// that may not be saved yet
+    pub fn check(&self, input: ScheduleInput, sql: &str) -> Result<ScheduleInput, String> {
+        let name = input.name.trim().to_string();
+        if name.is_empty() {
+            return Err("Name cannot be empty".to_string());
+        }
+        let cron = CronSchedule::parse(&input.cron)?;
+        if cron.next_after(Utc::now()).is_none() {
+            return Err(format!("Cron expression '{}' never fires", cron));
+        }
+
+        compile_placeholders(sql)?.bind(&input.params)?;
+
+        match input.delivery.as_ref().map(|d| &d.target) {
+            Some(DeliveryTarget::Webhook { url }) => {
+                notify::webhook_url(url)?;
+            }
+            Some(DeliveryTarget::Email { to }) => {
+                if to.is_empty() {
+                    return Err("Email delivery needs at least one recipient".to_string());
+                }
+                for address in to {
+                    notify::validate_email_address(address)?;
+                }
+                if self.smtp.is_none() {
+                    return Err("Email delivery requires SMTP_HOST to be configured".to_string());
+                }
+            }
+            None => {}
+        }
+        if input.alert.as_ref().is_some_and(|a| !a.value.is_finite()) {
+            return Err("Alert threshold must be a finite number".to_string());
+        }
+
+        Ok(ScheduleInput {
+            name,
+            cron: cron.to_string(),
+            ..input
+        })
+    }
+
+    /// Start the background task that runs due schedules
+    pub fn spawn(self: &Arc<Self>) {
+        let scheduler = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(TICK_INTERVAL);
+            loop {
+                interval.tick().await;
+                match scheduler.store.take_due(Utc::now()).await {
+                    Ok(due) => {
+                        for schedule in due {
+                            let scheduler = scheduler.clone();
+                            tokio::spawn(async move {
+                                scheduler.run(&schedule).await;
+                            });
+                        }
+                    }
+                    Err(e) => tracing::warn!("Scheduler failed to claim due schedules: {}", e),
+                }
+            }
+        });
+    }
+
+    /// Run a schedule now, check its alert, deliver and store the result
+    pub async fn run(&self, schedule: &Schedule) -> ScheduleRun {
+        let mut run = self.execute(schedule).await;
+
+        if let Some(condition) = &schedule.alert {
+            run.alert = condition.check(&run).map(|observed| TriggeredAlert {
+                condition: condition.to_string(),
+                observed,
+            });
+            if run.alert.is_some() {
+                tracing::warn!("Alert on schedule {} tripped: {}", schedule.name, condition);
+            }
+        }
+
+        // With an alert, only runs that trip it (or fail) are delivered
+        let notify = schedule.alert.is_none() || run.alert.is_some() || !run.success;
+        if let Some(delivery) = schedule.delivery.as_ref().filter(|_| notify) {
+            if let Err(e) = self.deliver(schedule, delivery, &run).await {
+                tracing::warn!("Failed to deliver run of schedule {}: {}", schedule.id, e);
+                run.delivery_error = Some(e);
+            }
+        }
+
+        let event = AuditEvent::new(
+            if run.success {
+                AuditEventType::QueryExecution
+            } else {
+                AuditEventType::SqlError
+            },
+            "scheduler".to_string(),
+            format!("Scheduled run of {}", schedule.name),
+            format!("schedule:{}", schedule.id),
+        )
+        .with_success(run.success);
+        let event = match &run.error {
+            Some(e) => event.with_details(e.clone()),
+            None => event,
+        };
+        self.audit_logger.log(event).await;
+
+        if let Err(e) = self.store.record_run(run.clone()).await {
+            tracing::warn!("Failed to store run of schedule {}: {}", schedule.id, e);
+        }
+        run
+    }
+
+    async fn deliver(
+        &self,
+        schedule: &Schedule,
+        delivery: &Delivery,
+        run: &ScheduleRun,
+    ) -> Result<(), String> {
+        let send = async {
+            match &delivery.target {
+                DeliveryTarget::Webhook { url } => {
+                    let url = notify::webhook_url(url)?;
+                    let body = webhook_payload(schedule, run, delivery.include_rows);
+                    notify::post_json(&url, body).await
+                }
+                DeliveryTarget::Email { to } => {
+                    let smtp = self
+                        .smtp
+                        .as_ref()
+                        .ok_or_else(|| "SMTP is not configured".to_string())?;
+                    let email = email_message(schedule, run, delivery.include_rows, to);
+                    notify::send_email(smtp, &email).await
+                }
+            }
+        };
+
+        tokio::time::timeout(notify::DELIVERY_TIMEOUT, send)
+            .await
+            .map_err(|_| "Timed out delivering results".to_string())?
+    }
+
+    async fn execute(&self, schedule: &Schedule) -> ScheduleRun {
+        let started_at = Utc::now();
+        let start = Instant::now();
+        let elapsed = |start: Instant| start.elapsed().as_millis() as u64;
+
+        let Some(saved) = self.saved_queries.get(&schedule.saved_query_id).await else {
+            return ScheduleRun::failed(
+                &schedule.id,
+                started_at,
+                0,
+                format!("Saved query {} no longer exists", schedule.saved_query_id),
+            );
+        };
+
+        let prepared = query_service::validate_query(&saved.sql).and_then(|_| {
+            let compiled = compile_placeholders(&saved.sql)?;
+            let params = compiled.bind(&schedule.params)?;
+            Ok((compiled, params))
+        });
+        let (compiled, params) = match prepared {
+            Ok(prepared) => prepared,
+            Err(e) => return ScheduleRun::failed(&schedule.id, started_at, 0, e),
+        };
+
+        let result = query_service::execute_query_with_params(
+            &self.db_pool,
+            &compiled.sql,
+            &params,
+            self.read_only.is_enabled(),
+        )
+        .await
+        .map_err(|e| e.to_string());
+
+        match result {
+            Ok(mut result) => {
+                let truncated = result.rows.len() > MAX_STORED_ROWS;
+                result.rows.truncate(MAX_STORED_ROWS);
+                ScheduleRun {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    schedule_id: schedule.id.clone(),
+                    started_at,
+                    duration_ms: elapsed(start),
+                    success: true,
+                    row_count: Some(result.row_count),
+                    columns: result.columns,
+                    rows: result.rows,
+                    truncated,
+                    error: None,
+                    delivery_error: None,
+                    alert: None,
+                }
+            }
+            Err(e) => ScheduleRun::failed(&schedule.id, started_at, elapsed(start), e),
+        }
+    }
+}
+
+/// JSON body posted to webhooks: the schedule and its run, without rows unless requested
+fn webhook_payload(schedule: &Schedule, run: &ScheduleRun, include_rows: bool) -> Vec<u8> {
+    let mut run = serde_json::to_value(run).unwrap_or_default();
+    if !include_rows {
+        if let Some(run) = run.as_object_mut() {
+            run.remove("rows");
+        }
+    }
+
+    let payload = serde_json::json!({
+        "schedule": {
+            "id": schedule.id,
+            "name": schedule.name,
+            "saved_query_id": schedule.saved_query_id,
+            "cron": schedule.cron,
+        },
+        "run": run,
+    });
+    serde_json::to_vec(&payload).unwrap_or_default()
+}
+
+/// Plain-text email summarizing a run, with the rows as CSV when requested
+fn email_message(
+    schedule: &Schedule,
+    run: &ScheduleRun,
+    include_rows: bool,
+    to: &[String],
+) -> Email {
+    let mut status = match (&run.error, run.row_count) {
+        (Some(_), _) => "failed".to_string(),
+        (None, Some(1)) => "1 row".to_string(),
+        (None, Some(count)) => format!("{} rows", count),
+        (None, None) => "succeeded".to_string(),
+    };
+    if let Some(alert) = &run.alert {
+        status = format!("ALERT {} (observed {})", alert.condition, alert.observed);
+    }
+
+    let mut body = format!(
+        "Schedule: {}\nCron: {}\nStarted: {}\nDuration: {} ms\nResult: {}\n",
+        schedule.name,
+        schedule.cron,
+        run.started_at.to_rfc3339(),
+        run.duration_ms,
+        status
+    );
+    if let Some(error) = &run.error {
+        body.push_str(&format!("Error: {}\n", error));
+    }
+
+    if include_rows && run.success && !run.columns.is_empty() {
+        let result = QueryResult {
+            columns: run.columns.clone(),
+            rows: run.rows.clone(),
+            row_count: run.rows.len(),
+            affected_rows: None,
+            execution_time_ms: None,
+        };
+        if let Ok(csv) = ExportService::export(&result, ExportFormat::Csv) {
+            body.push('\n');
+            body.push_str(&csv);
+            if run.truncated {
+                body.push_str(&format!("\n(first {} rows shown)\n", run.rows.len()));
+            }
+        }
+    }
+
+    Email {
+        to: to.to_vec(),
+        subject: format!("[pgAdmin-rs] {}: {}", schedule.name, status),
+        body,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("schedules-{}", uuid::Uuid::new_v4()))
+            .join("schedules.json")
+    }
+
+    fn input(cron: &str) -> ScheduleInput {
+        ScheduleInput {
+            name: "Nightly check".to_string(),
+            saved_query_id: "q1".to_string(),
+            cron: cron.to_string(),
+            params: HashMap::new(),
+            enabled: true,
+            delivery: None,
+            alert: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_take_due_advances_next_run() {
+        let path = temp_path();
+        let store = ScheduleStore::load(&path).await.unwrap();
+        let schedule = store.create(input("*/5 * * * *")).await.unwrap();
+        let next = schedule.next_run_at.unwrap();
+
+        assert!(store
+            .take_due(next - chrono::Duration::seconds(1))
+            .await
+            .unwrap()
+            .is_empty());
+
+        let due = store.take_due(next).await.unwrap();
+        assert_eq!(due.len(), 1);
+        let advanced = store.get(&schedule.id).await.unwrap().next_run_at.unwrap();
+        assert_eq!(advanced, next + chrono::Duration::minutes(5));
+
+        // Already claimed
+        assert!(store.take_due(next).await.unwrap().is_empty());
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_disabled_schedule_never_due() {
+        let path = temp_path();
+        let store = ScheduleStore::load(&path).await.unwrap();
+        let schedule = store
+            .create(ScheduleInput {
+                enabled: false,
+                ..input("* * * * *")
+            })
+            .await
+            .unwrap();
+        assert_eq!(schedule.next_run_at, None);
+        let later = Utc::now() + chrono::Duration::hours(1);
+        assert!(store.take_due(later).await.unwrap().is_empty());
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_runs_are_capped_and_persisted() {
+        let path = temp_path();
+        let store = ScheduleStore::load(&path).await.unwrap();
+        let schedule = store.create(input("@daily")).await.unwrap();
+
+        for i in 0..(MAX_RUNS_PER_SCHEDULE + 3) {
+            let mut run = ScheduleRun::failed(&schedule.id, Utc::now(), i as u64, "x".to_string());
+            run.duration_ms = i as u64;
+            store.record_run(run).await.unwrap();
+        }
+
+        let runs = store.runs(&schedule.id).await;
+        assert_eq!(runs.len(), MAX_RUNS_PER_SCHEDULE);
+        assert_eq!(runs[0].duration_ms, (MAX_RUNS_PER_SCHEDULE + 2) as u64); // newest first
+        assert!(store.get(&schedule.id).await.unwrap().last_run_at.is_some());
+
+        let reloaded = ScheduleStore::load(&path).await.unwrap();
+        assert_eq!(
+            reloaded.runs(&schedule.id).await.len(),
+            MAX_RUNS_PER_SCHEDULE
+        );
+
+        assert!(reloaded.delete(&schedule.id).await.unwrap());
+        assert!(reloaded.runs(&schedule.id).await.is_empty());
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_delivery_is_stored() {
+        let path = temp_path();
+        let store = ScheduleStore::load(&path).await.unwrap();
+        let delivery: Delivery = serde_json::from_value(serde_json::json!({
+            "type": "webhook",
+            "url": "http://hooks.internal/nightly",
+        }))
+        .unwrap();
+        assert!(!delivery.include_rows);
+
+        let schedule = store
+            .create(ScheduleInput {
+                delivery: Some(delivery.clone()),
+                ..input("@daily")
+            })
+            .await
+            .unwrap();
+
+        let reloaded = ScheduleStore::load(&path).await.unwrap();
+        assert_eq!(
+            reloaded.get(&schedule.id).await.unwrap().delivery,
+            Some(delivery)
+        );
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    fn sample_run(schedule: &Schedule) -> ScheduleRun {
+        ScheduleRun {
+            id: "r1".to_string(),
+            schedule_id: schedule.id.clone(),
+            started_at: Utc::now(),
+            duration_ms: 12,
+            success: true,
+            row_count: Some(2),
+            columns: vec!["id".to_string(), "name".to_string()],
+            rows: vec![
+                vec![serde_json::json!(1), serde_json::json!("alice")],
+                vec![serde_json::json!(2), serde_json::json!("bob")],
+            ],
+            truncated: false,
+            error: None,
+            delivery_error: None,
+            alert: None,
+        }
+    }
+
+    fn sample_schedule() -> Schedule {
+        let now = Utc::now();
+        Schedule {
+            id: "s1".to_string(),
+            name: "Nightly check".to_string(),
+            saved_query_id: "q1".to_string(),
+            cron: "@daily".to_string(),
+            params: HashMap::new(),
+            enabled: true,
+            delivery: None,
+            alert: None,
+            managed_by: None,
+            created_at: now,
+            updated_at: now,
+            last_run_at: None,
+            next_run_at: None,
+        }
+    }
+
+    #[test]
+    fn test_webhook_payload() {
+        let schedule = sample_schedule();
+        let run = sample_run(&schedule);
+
+        let summary: serde_json::Value =
+            serde_json::from_slice(&webhook_payload(&schedule, &run, false)).unwrap();
+        assert_eq!(summary["schedule"]["name"], "Nightly check");
+        assert_eq!(summary["run"]["row_count"], 2);
+        assert!(summary["run"].get("rows").is_none());
+
+        let full: serde_json::Value =
+            serde_json::from_slice(&webhook_payload(&schedule, &run, true)).unwrap();
+        assert_eq!(full["run"]["rows"][1][1], "bob");
+    }
+
+    #[test]
+    fn test_email_message() {
+        let schedule = sample_schedule();
+        let mut run = sample_run(&schedule);
+        let to = vec!["ops@example.com".to_string()];
+
+        let email = email_message(&schedule, &run, true, &to);
+        assert_eq!(email.subject, "[pgAdmin-rs] Nightly check: 2 rows");
+        assert!(email.body.contains("Result: 2 rows"));
+        assert!(email.body.contains("alice"));
+
+        assert!(!email_message(&schedule, &run, false, &to)
+            .body
+            .contains("alice"));
+
+        run.success = false;
+        run.error = Some("relation \"t\" does not exist".to_string());
+        let email = email_message(&schedule, &run, true, &to);
+        assert_eq!(email.subject, "[pgAdmin-rs] Nightly check: failed");
+        assert!(email.body.contains("Error: relation"));
+        assert!(!email.body.contains("alice"));
+    }
+
+    #[test]
+    fn test_alert_condition() {
+        let schedule = sample_schedule();
+        let mut run = sample_run(&schedule);
+        let condition = |metric, op, value| AlertCondition { metric, op, value };
+
+        let any_rows = condition(AlertMetric::RowCount, AlertOp::Gt, 0.0);
+        assert_eq!(any_rows.to_string(), "row_count > 0");
+        assert_eq!(any_rows.check(&run), Some(2.0));
+        assert_eq!(
+            condition(AlertMetric::FirstValue, AlertOp::Gte, 1.0).check(&run),
+            Some(1.0)
+        );
+        assert_eq!(
+            condition(AlertMetric::FirstValue, AlertOp::Gt, 1.0).check(&run),
+            None
+        );
+
+        // Numeric strings count; NULL and text never trip
+        run.rows[0][0] = serde_json::json!("12.5");
+        assert_eq!(
+            condition(AlertMetric::FirstValue, AlertOp::Lt, 20.0).check(&run),
+            Some(12.5)
+        );
+        run.rows[0][0] = serde_json::Value::Null;
+        assert_eq!(
+            condition(AlertMetric::FirstValue, AlertOp::Ne, 0.0).check(&run),
+            None
+        );
+
+        // Failed runs are reported as failures, not alerts
+        run.success = false;
+        assert_eq!(any_rows.check(&run), None);
+    }
+
+    #[test]
+    fn test_alert_email_subject() {
+        let schedule = sample_schedule();
+        let mut run = sample_run(&schedule);
+        run.alert = Some(TriggeredAlert {
+            condition: "row_count > 0".to_string(),
+            observed: 2.0,
+        });
+        let email = email_message(&schedule, &run, false, &["ops@example.com".to_string()]);
+        assert_eq!(
+            email.subject,
+            "[pgAdmin-rs] Nightly check: ALERT row_count > 0 (observed 2)"
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use crate::services::identifier::{quote_ident, quote_qualified};
+use crate::services::sql_lexer::validate_expression;
 use serde::{Deserialize, Serialize};
 /// Schema Operations Service
 ///
@@ -7,6 +9,7 @@ use serde::{Deserialize, Serialize};
 /// - Indexes
 /// - Sequences
 /// - Functions
+/// - Table clones (structure and optionally data)
 use sqlx::PgPool;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,6 +18,14 @@ pub struct ColumnDefinition {
     pub data_type: String,
     pub nullable: bool,
     pub default: Option<String>,
+    /// The column on its own is the primary key
+    #[serde(default)]
+    pub primary_key: bool,
+    #[serde(default)]
+    pub unique: bool,
+    /// Generate values with `GENERATED ... AS IDENTITY` (integer columns only)
+    #[serde(default)]
+    pub identity: Option<IdentityKind>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,6 +33,70 @@ pub struct CreateTableRequest {
     pub table_name: String,
     pub schema: String,
     pub columns: Vec<ColumnDefinition>,
+    /// Columns of a composite primary key; use `primary_key` on the column for a single one
+    #[serde(default)]
+    pub primary_key: Vec<String>,
+    /// Column sets that must be unique together
+    #[serde(default)]
+    pub unique: Vec<Vec<String>>,
+    #[serde(default)]
+    pub checks: Vec<CheckConstraint>,
+    #[serde(default)]
+    pub foreign_keys: Vec<ForeignKey>,
+}
+
+/// `ALWAYS` rejects explicit values on insert unless overridden; `BY DEFAULT` accepts them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdentityKind {
+    Always,
+    ByDefault,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckConstraint {
+    pub name: Option<String>,
+    /// Boolean expression, e.g. `price >= 0`
+    pub expression: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForeignKey {
+    pub name: Option<String>,
+    pub columns: Vec<String>,
+    /// Schema of the referenced table; the new table's schema when omitted
+    pub references_schema: Option<String>,
+    pub references_table: String,
+    /// Referenced columns; the referenced table's primary key when empty
+    #[serde(default)]
+    pub references_columns: Vec<String>,
+    #[serde(default)]
+    pub on_delete: ReferentialAction,
+    #[serde(default)]
+    pub on_update: ReferentialAction,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReferentialAction {
+    #[default]
+    NoAction,
+    Restrict,
+    Cascade,
+    SetNull,
+    SetDefault,
+}
+
+impl ReferentialAction {
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            ReferentialAction::NoAction => "NO ACTION",
+            ReferentialAction::Restrict => "RESTRICT",
+            ReferentialAction::Cascade => "CASCADE",
+            ReferentialAction::SetNull => "SET NULL",
+            ReferentialAction::SetDefault => "SET DEFAULT",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,8 +112,71 @@ pub struct CreateIndexRequest {
     pub index_name: String,
     pub schema: String,
     pub table_name: String,
-    pub columns: Vec<String>,
+    pub columns: Vec<IndexColumn>,
+    #[serde(default)]
     pub unique: bool,
+    /// Build without blocking writes (`CREATE INDEX CONCURRENTLY`)
+    #[serde(default)]
+    pub concurrently: bool,
+    #[serde(default)]
+    pub method: IndexMethod,
+    /// Predicate of a partial index, e.g. `deleted_at IS NULL`
+    #[serde(default)]
+    pub where_clause: Option<String>,
+}
+
+/// An index key: a column name, or `{"expression": "lower(email)"}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum IndexColumn {
+    Name(String),
+    Expression { expression: String },
+}
+
+/// Index access method
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexMethod {
+    #[default]
+    Btree,
+    Hash,
+    Gist,
+    Gin,
+    Brin,
+}
+
+impl IndexMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IndexMethod::Btree => "btree",
+            IndexMethod::Hash => "hash",
+            IndexMethod::Gist => "gist",
+            IndexMethod::Gin => "gin",
+            IndexMethod::Brin => "brin",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CloneTableRequest {
+    pub schema: String,
+    pub table_name: String,
+    /// Schema for the new table; the source schema when omitted
+    pub target_schema: Option<String>,
+    pub target_name: String,
+    /// Copy the rows as well as the structure
+    #[serde(default)]
+    pub copy_data: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommentRequest {
+    pub schema: String,
+    pub table_name: String,
+    /// Column to comment on; the table itself when omitted
+    pub column: Option<String>,
+    /// New comment text; None or empty removes the comment
+    pub comment: Option<String>,
 }
 
 pub struct SchemaOpsService;
@@ -46,6 +184,22 @@ pub struct SchemaOpsService;
 impl SchemaOpsService {
     /// Create a new table
     pub async fn create_table(pool: &PgPool, req: &CreateTableRequest) -> Result<String, String> {
+        let sql = Self::create_table_sql(req)?;
+
+        // Execute the CREATE TABLE statement
+        sqlx::query(&sql)
+            .execute(pool)
+            .await
+            .map_err(|e| format!("Failed to create table: {}", e))?;
+
+        Ok(format!(
+            "Table {}.{} created successfully",
+            req.schema, req.table_name
+        ))
+    }
+
+    /// Build the `CREATE TABLE` statement with its column and table constraints
+    pub fn create_table_sql(req: &CreateTableRequest) -> Result<String, String> {
         if req.columns.is_empty() {
             return Err("At least one column is required".to_string());
         }
@@ -54,19 +208,50 @@ impl SchemaOpsService {
         Self::validate_identifier(&req.schema)?;
         Self::validate_identifier(&req.table_name)?;
 
+        let column_primary_keys = req.columns.iter().filter(|c| c.primary_key).count();
+        if column_primary_keys > 1 || (column_primary_keys == 1 && !req.primary_key.is_empty()) {
+            return Err(
+                "A table has one primary key; list its columns in primary_key for a composite key"
+                    .to_string(),
+            );
+        }
+
         let mut sql = format!(
-            "CREATE TABLE IF NOT EXISTS \"{}\".\"{}\" (",
-            req.schema, req.table_name
+            "CREATE TABLE IF NOT EXISTS {} (",
+            quote_qualified(&req.schema, &req.table_name)
         );
 
-        let column_defs: Result<Vec<String>, String> = req
+        let mut defs = req
             .columns
             .iter()
             .map(|col| {
                 Self::validate_identifier(&col.name)?;
-                let mut def = format!("\n  \"{}\" {}", col.name, col.data_type);
+                let mut def = format!("\n  {} {}", quote_ident(&col.name), col.data_type);
+
+                if let Some(identity) = col.identity {
+                    if col.default.is_some() {
+                        return Err(format!(
+                            "Column {} cannot have both a default and an identity",
+                            col.name
+                        ));
+                    }
+                    let data_type = col.data_type.trim().to_lowercase();
+                    if !matches!(
+                        data_type.as_str(),
+                        "smallint" | "integer" | "int" | "bigint" | "int2" | "int4" | "int8"
+                    ) {
+                        return Err(format!(
+                            "Identity column {} must be smallint, integer or bigint",
+                            col.name
+                        ));
+                    }
+                    def.push_str(match identity {
+                        IdentityKind::Always => " GENERATED ALWAYS AS IDENTITY",
+                        IdentityKind::ByDefault => " GENERATED BY DEFAULT AS IDENTITY",
+                    });
+                }
 
-                if !col.nullable {
+                if !col.nullable && !col.primary_key {
                     def.push_str(" NOT NULL");
                 }
 
@@ -74,24 +259,96 @@ impl SchemaOpsService {
                     def.push_str(&format!(" DEFAULT {}", default));
                 }
 
+                if col.primary_key {
+                    def.push_str(" PRIMARY KEY");
+                } else if col.unique {
+                    def.push_str(" UNIQUE");
+                }
+
                 Ok(def)
             })
-            .collect();
-
-        let column_defs = column_defs?;
-        sql.push_str(&column_defs.join(","));
-        sql.push_str("\n)");
+            .collect::<Result<Vec<String>, String>>()?;
+
+        let column_list = |columns: &[String], what: &str| -> Result<String, String> {
+            if columns.is_empty() {
+                return Err(format!("{} needs at least one column", what));
+            }
+            columns
+                .iter()
+                .map(|c| {
+                    Self::validate_identifier(c)?;
+                    Ok(quote_ident(c))
+                })
+                .collect::<Result<Vec<_>, String>>()
+                .map(|names| names.join(", "))
+        };
+        let constraint_name = |name: &Option<String>| -> Result<String, String> {
+            match name {
+                Some(name) => {
+                    Self::validate_identifier(name)?;
+                    Ok(format!("CONSTRAINT {} ", quote_ident(name)))
+                }
+                None => Ok(String::new()),
+            }
+        };
 
-        // Execute the CREATE TABLE statement
-        sqlx::query(&sql)
-            .execute(pool)
-            .await
-            .map_err(|e| format!("Failed to create table: {}", e))?;
+        if !req.primary_key.is_empty() {
+            defs.push(format!(
+                "\n  PRIMARY KEY ({})",
+                column_list(&req.primary_key, "Primary key")?
+            ));
+        }
+        for columns in &req.unique {
+            defs.push(format!(
+                "\n  UNIQUE ({})",
+                column_list(columns, "Unique constraint")?
+            ));
+        }
+        for check in &req.checks {
+            validate_expression(&check.expression)
+                .map_err(|e| format!("Invalid check constraint: {}", e))?;
+            defs.push(format!(
+                "\n  {}CHECK ({})",
+                constraint_name(&check.name)?,
+                check.expression.trim()
+            ));
+        }
+        for fk in &req.foreign_keys {
+            let ref_schema = fk.references_schema.as_deref().unwrap_or(&req.schema);
+            Self::validate_identifier(ref_schema)?;
+            Self::validate_identifier(&fk.references_table)?;
+            let mut def = format!(
+                "\n  {}FOREIGN KEY ({}) REFERENCES {}",
+                constraint_name(&fk.name)?,
+                column_list(&fk.columns, "Foreign key")?,
+                quote_qualified(ref_schema, &fk.references_table)
+            );
+            if !fk.references_columns.is_empty() {
+                if fk.references_columns.len() != fk.columns.len() {
+                    return Err(format!(
+                        "Foreign key to {} has {} columns but references {}",
+                        fk.references_table,
+                        fk.columns.len(),
+                        fk.references_columns.len()
+                    ));
+                }
+                def.push_str(&format!(
+                    " ({})",
+                    column_list(&fk.references_columns, "Foreign key")?
+                ));
+            }
+            if fk.on_delete != ReferentialAction::NoAction {
+                def.push_str(&format!(" ON DELETE {}", fk.on_delete.as_sql()));
+            }
+            if fk.on_update != ReferentialAction::NoAction {
+                def.push_str(&format!(" ON UPDATE {}", fk.on_update.as_sql()));
+            }
+            defs.push(def);
+        }
 
-        Ok(format!(
-            "Table {}.{} created successfully",
-            req.schema, req.table_name
-        ))
+        sql.push_str(&defs.join(","));
+        sql.push_str("\n)");
+        Ok(sql)
     }
 
     /// Drop a table, view, or other object
@@ -111,8 +368,10 @@ impl SchemaOpsService {
         let cascade = if req.cascade { "CASCADE" } else { "RESTRICT" };
 
         let sql = format!(
-            "DROP {} IF EXISTS \"{}\".\"{}\" {}",
-            object_type, req.schema, req.object_name, cascade
+            "DROP {} IF EXISTS {} {}",
+            object_type,
+            quote_qualified(&req.schema, &req.object_name),
+            cascade
         );
 
         sqlx::query(&sql)
@@ -128,6 +387,26 @@ impl SchemaOpsService {
 
     /// Create an index
     pub async fn create_index(pool: &PgPool, req: &CreateIndexRequest) -> Result<String, String> {
+        let sql = Self::create_index_sql(req)?;
+
+        // CONCURRENTLY cannot run in a transaction, so this must stay a single
+        // statement sent on its own
+        sqlx::query(&sql).execute(pool).await.map_err(|e| {
+            if req.concurrently {
+                format!(
+                    "Failed to create index: {}. A failed concurrent build leaves an invalid index {} behind; drop it before retrying",
+                    e, quote_ident(&req.index_name)
+                )
+            } else {
+                format!("Failed to create index: {}", e)
+            }
+        })?;
+
+        Ok(format!("Index {} created successfully", req.index_name))
+    }
+
+    /// Build the `CREATE INDEX` statement, validating names and expressions
+    pub fn create_index_sql(req: &CreateIndexRequest) -> Result<String, String> {
         Self::validate_identifier(&req.schema)?;
         Self::validate_identifier(&req.index_name)?;
         Self::validate_identifier(&req.table_name)?;
@@ -135,31 +414,174 @@ impl SchemaOpsService {
         if req.columns.is_empty() {
             return Err("At least one column is required for an index".to_string());
         }
-
-        // Validate column names
-        for col in &req.columns {
-            Self::validate_identifier(col)?;
+        if req.unique && req.method != IndexMethod::Btree {
+            return Err(format!(
+                "Unique indexes must use btree, not {}",
+                req.method.as_str()
+            ));
+        }
+        if req.method == IndexMethod::Hash && req.columns.len() > 1 {
+            return Err("Hash indexes support a single column".to_string());
         }
 
-        let unique = if req.unique { "UNIQUE " } else { "" };
         let columns = req
             .columns
             .iter()
-            .map(|c| format!("\"{}\"", c))
-            .collect::<Vec<_>>()
+            .map(|column| match column {
+                IndexColumn::Name(name) => {
+                    Self::validate_identifier(name)?;
+                    Ok(quote_ident(name))
+                }
+                IndexColumn::Expression { expression } => {
+                    validate_expression(expression)
+                        .map_err(|e| format!("Invalid index expression: {}", e))?;
+                    Ok(format!("({})", expression.trim()))
+                }
+            })
+            .collect::<Result<Vec<_>, String>>()?
             .join(", ");
 
-        let sql = format!(
-            "CREATE {}INDEX IF NOT EXISTS \"{}\" ON \"{}\".\"{}\" ({})",
-            unique, req.index_name, req.schema, req.table_name, columns
+        let mut sql = format!(
+            "CREATE {}INDEX {}IF NOT EXISTS {} ON {} USING {} ({})",
+            if req.unique { "UNIQUE " } else { "" },
+            if req.concurrently {
+                "CONCURRENTLY "
+            } else {
+                ""
+            },
+            quote_ident(&req.index_name),
+            quote_qualified(&req.schema, &req.table_name),
+            req.method.as_str(),
+            columns
+        );
+
+        if let Some(predicate) = req.where_clause.as_deref().filter(|w| !w.trim().is_empty()) {
+            validate_expression(predicate).map_err(|e| format!("Invalid WHERE clause: {}", e))?;
+            sql.push_str(&format!(" WHERE {}", predicate.trim()));
+        }
+
+        Ok(sql)
+    }
+
+    /// Clone a table with `CREATE TABLE ... (LIKE ... INCLUDING ALL)`,
+    /// optionally copying its rows in the same transaction
+    pub async fn clone_table(pool: &PgPool, req: &CloneTableRequest) -> Result<String, String> {
+        let target_schema = req.target_schema.as_deref().unwrap_or(&req.schema);
+        Self::validate_identifier(&req.schema)?;
+        Self::validate_identifier(&req.table_name)?;
+        Self::validate_identifier(target_schema)?;
+        Self::validate_identifier(&req.target_name)?;
+
+        let source = quote_qualified(&req.schema, &req.table_name);
+        let target = quote_qualified(target_schema, &req.target_name);
+
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+        sqlx::query(&format!(
+            "CREATE TABLE {} (LIKE {} INCLUDING ALL)",
+            target, source
+        ))
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to clone table: {}", e))?;
+
+        let mut message = format!(
+            "Table {}.{} cloned to {}.{}",
+            req.schema, req.table_name, target_schema, req.target_name
         );
 
+        if req.copy_data {
+            // Generated columns are recomputed on insert, so leave them out
+            let columns: Vec<String> = sqlx::query_scalar(
+                r#"
+                SELECT a.attname::text
+                FROM pg_catalog.pg_attribute a
+                JOIN pg_catalog.pg_class c ON c.oid = a.attrelid
+                JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+                WHERE n.nspname = $1 AND c.relname = $2
+                  AND a.attnum > 0 AND NOT a.attisdropped AND a.attgenerated = ''
+                ORDER BY a.attnum
+                "#,
+            )
+            .bind(&req.schema)
+            .bind(&req.table_name)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to get table columns: {}", e))?;
+
+            let column_list = columns
+                .iter()
+                .map(|c| quote_ident(c))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            let copied = sqlx::query(&format!(
+                "INSERT INTO {target} ({columns}) OVERRIDING SYSTEM VALUE SELECT {columns} FROM {source}",
+                target = target,
+                columns = column_list,
+                source = source
+            ))
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to copy table data: {}", e))?
+            .rows_affected();
+
+            message.push_str(&format!(" with {} rows", copied));
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| format!("Failed to commit clone: {}", e))?;
+
+        Ok(message)
+    }
+
+    /// Set or remove the comment on a table or column (COMMENT ON)
+    pub async fn set_comment(pool: &PgPool, req: &CommentRequest) -> Result<String, String> {
+        Self::validate_identifier(&req.schema)?;
+        Self::validate_identifier(&req.table_name)?;
+        if let Some(column) = &req.column {
+            Self::validate_identifier(column)?;
+        }
+
+        // COMMENT ON does not accept bind parameters, so let the server
+        // quote identifiers and the literal via format()
+        let comment = req.comment.as_deref().filter(|c| !c.is_empty());
+        let sql: String = match &req.column {
+            Some(column) => sqlx::query_scalar(
+                "SELECT format('COMMENT ON COLUMN %I.%I.%I IS %L', $1::text, $2::text, $3::text, $4::text)",
+            )
+            .bind(&req.schema)
+            .bind(&req.table_name)
+            .bind(column)
+            .bind(comment),
+            None => sqlx::query_scalar(
+                "SELECT format('COMMENT ON TABLE %I.%I IS %L', $1::text, $2::text, $3::text)",
+            )
+            .bind(&req.schema)
+            .bind(&req.table_name)
+            .bind(comment),
+        }
+        .fetch_one(pool)
+        .await
+        .map_err(|e| format!("Failed to build comment statement: {}", e))?;
+
         sqlx::query(&sql)
             .execute(pool)
             .await
-            .map_err(|e| format!("Failed to create index: {}", e))?;
+            .map_err(|e| format!("Failed to set comment: {}", e))?;
 
-        Ok(format!("Index {} created successfully", req.index_name))
+        let target = match &req.column {
+            Some(column) => format!("column {}.{}.{}", req.schema, req.table_name, column),
+            None => format!("table {}.{}", req.schema, req.table_name),
+        };
+        Ok(match comment {
+            Some(_) => format!("Comment on {} updated", target),
+            None => format!("Comment on {} removed", target),
+        })
     }
 
     /// Get list of tables in a schema
@@ -215,31 +637,21 @@ impl SchemaOpsService {
     }
 
     /// Validate identifier (table/schema/column names)
-    /// Prevents SQL injection by checking for valid PostgreSQL identifiers
+    ///
+    /// Any name PostgreSQL can store is accepted; callers interpolate it with
+    /// `identifier::quote_ident`, which handles spaces, quotes and mixed case.
     pub fn validate_identifier(name: &str) -> Result<(), String> {
         if name.is_empty() {
             return Err("Identifier cannot be empty".to_string());
         }
 
+        // NAMEDATALEN - 1; longer names would be silently truncated
         if name.len() > 63 {
-            return Err("Identifier cannot be longer than 63 characters".to_string());
+            return Err("Identifier cannot be longer than 63 bytes".to_string());
         }
 
-        // Allow alphanumeric, underscores, and some special chars
-        // PostgreSQL allows: a-z, A-Z, 0-9, _ and non-ASCII
-        if !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
-            return Err(format!(
-                "Invalid identifier '{}': only alphanumeric and underscore allowed",
-                name
-            ));
-        }
-
-        // Cannot start with a digit
-        if name.chars().next().is_some_and(|c| c.is_ascii_digit()) {
-            return Err(format!(
-                "Invalid identifier '{}': cannot start with a digit",
-                name
-            ));
+        if name.contains('\0') {
+            return Err("Identifier cannot contain a NUL character".to_string());
         }
 
         Ok(())
@@ -275,10 +687,18 @@ mod tests {
     #[test]
     fn test_validate_identifier_invalid() {
         assert!(SchemaOpsService::validate_identifier("").is_err());
-        assert!(SchemaOpsService::validate_identifier("123abc").is_err());
-        assert!(SchemaOpsService::validate_identifier("user-table").is_err());
-        assert!(SchemaOpsService::validate_identifier("user.table").is_err());
-        assert!(SchemaOpsService::validate_identifier("user table").is_err());
+        assert!(SchemaOpsService::validate_identifier("nul\0name").is_err());
+    }
+
+    #[test]
+    fn test_validate_identifier_needs_quoting() {
+        // Quoted with quote_ident wherever they are interpolated
+        assert!(SchemaOpsService::validate_identifier("123abc").is_ok());
+        assert!(SchemaOpsService::validate_identifier("user-table").is_ok());
+        assert!(SchemaOpsService::validate_identifier("user.table").is_ok());
+        assert!(SchemaOpsService::validate_identifier("user table").is_ok());
+        assert!(SchemaOpsService::validate_identifier("Say \"hi\"").is_ok());
+        assert!(SchemaOpsService::validate_identifier("Ümlaut").is_ok());
     }
 
     #[test]
@@ -289,4 +709,247 @@ mod tests {
         let valid_name = "a".repeat(63);
         assert!(SchemaOpsService::validate_identifier(&valid_name).is_ok());
     }
+
+    #[test]
+    fn test_clone_table_request_defaults() {
+        let req: CloneTableRequest = serde_json::from_value(serde_json::json!({
+            "schema": "public",
+            "table_name": "orders",
+            "target_name": "orders_backup"
+        }))
+        .unwrap();
+        assert!(!req.copy_data);
+        assert!(req.target_schema.is_none());
+    }
+
+    fn index_request(columns: serde_json::Value) -> CreateIndexRequest {
+        serde_json::from_value(serde_json::json!({
+            "index_name": "users_email_idx",
+            "schema": "public",
+            "table_name": "users",
+            "columns": columns
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_create_index_sql_defaults() {
+        let req = index_request(serde_json::json!(["email", "created_at"]));
+        assert!(!req.concurrently);
+        assert_eq!(
+            SchemaOpsService::create_index_sql(&req).unwrap(),
+            "CREATE INDEX IF NOT EXISTS users_email_idx ON public.users USING btree (email, created_at)"
+        );
+    }
+
+    #[test]
+    fn test_create_index_sql_options() {
+        let req = CreateIndexRequest {
+            unique: true,
+            concurrently: true,
+            where_clause: Some(" deleted_at IS NULL ".to_string()),
+            ..index_request(serde_json::json!([{ "expression": "lower(email)" }, "tenant_id"]))
+        };
+        assert_eq!(
+            req.columns[0],
+            IndexColumn::Expression {
+                expression: "lower(email)".to_string()
+            }
+        );
+        assert_eq!(
+            SchemaOpsService::create_index_sql(&req).unwrap(),
+            "CREATE UNIQUE INDEX CONCURRENTLY IF NOT EXISTS users_email_idx ON public.users \
+             USING btree ((lower(email)), tenant_id) WHERE deleted_at IS NULL"
+        );
+
+        let gin: CreateIndexRequest = serde_json::from_value(serde_json::json!({
+            "index_name": "docs_body_idx",
+            "schema": "public",
+            "table_name": "docs",
+            "columns": [{ "expression": "to_tsvector('english', body)" }],
+            "method": "gin"
+        }))
+        .unwrap();
+        assert!(SchemaOpsService::create_index_sql(&gin)
+            .unwrap()
+            .contains("USING gin ((to_tsvector('english', body)))"));
+    }
+
+    #[test]
+    fn test_create_index_sql_rejects_invalid_options() {
+        let unique_gin = CreateIndexRequest {
+            unique: true,
+            method: IndexMethod::Gin,
+            ..index_request(serde_json::json!(["tags"]))
+        };
+        assert!(SchemaOpsService::create_index_sql(&unique_gin).is_err());
+
+        let injected = CreateIndexRequest {
+            where_clause: Some("true; DROP TABLE users".to_string()),
+            ..index_request(serde_json::json!(["email"]))
+        };
+        assert!(SchemaOpsService::create_index_sql(&injected).is_err());
+
+        let unbalanced = index_request(serde_json::json!([{ "expression": "lower(email))" }]));
+        assert!(SchemaOpsService::create_index_sql(&unbalanced).is_err());
+
+        let empty_column = index_request(serde_json::json!([""]));
+        assert!(SchemaOpsService::create_index_sql(&empty_column).is_err());
+    }
+
+    #[test]
+    fn test_create_index_sql_quotes_names() {
+        let req = CreateIndexRequest {
+            index_name: "Users by email".to_string(),
+            table_name: "User Accounts".to_string(),
+            ..index_request(serde_json::json!(["email\" DESC", "Created At"]))
+        };
+        assert_eq!(
+            SchemaOpsService::create_index_sql(&req).unwrap(),
+            "CREATE INDEX IF NOT EXISTS \"Users by email\" ON public.\"User Accounts\" \
+             USING btree (\"email\"\" DESC\", \"Created At\")"
+        );
+    }
+
+    fn column(name: &str, data_type: &str) -> ColumnDefinition {
+        ColumnDefinition {
+            name: name.to_string(),
+            data_type: data_type.to_string(),
+            nullable: true,
+            default: None,
+            primary_key: false,
+            unique: false,
+            identity: None,
+        }
+    }
+
+    fn table(columns: Vec<ColumnDefinition>) -> CreateTableRequest {
+        CreateTableRequest {
+            table_name: "orders".to_string(),
+            schema: "shop".to_string(),
+            columns,
+            primary_key: Vec::new(),
+            unique: Vec::new(),
+            checks: Vec::new(),
+            foreign_keys: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_create_table_request_defaults() {
+        let req: CreateTableRequest = serde_json::from_value(serde_json::json!({
+            "table_name": "t",
+            "schema": "public",
+            "columns": [{ "name": "a", "data_type": "text", "nullable": true, "default": null }]
+        }))
+        .unwrap();
+        assert!(!req.columns[0].primary_key);
+        assert!(req.columns[0].identity.is_none());
+        assert!(req.foreign_keys.is_empty());
+        assert_eq!(
+            SchemaOpsService::create_table_sql(&req).unwrap(),
+            "CREATE TABLE IF NOT EXISTS public.t (\n  a text\n)"
+        );
+    }
+
+    #[test]
+    fn test_create_table_sql_constraints() {
+        let mut req = table(vec![
+            ColumnDefinition {
+                primary_key: true,
+                identity: Some(IdentityKind::Always),
+                nullable: false,
+                ..column("id", "bigint")
+            },
+            ColumnDefinition {
+                unique: true,
+                nullable: false,
+                ..column("number", "text")
+            },
+            column("customer_id", "bigint"),
+            column("total", "numeric(12,2)"),
+        ]);
+        req.checks.push(CheckConstraint {
+            name: Some("total_positive".to_string()),
+            expression: "total >= 0".to_string(),
+        });
+        req.foreign_keys.push(ForeignKey {
+            name: None,
+            columns: vec!["customer_id".to_string()],
+            references_schema: None,
+            references_table: "customers".to_string(),
+            references_columns: vec!["id".to_string()],
+            on_delete: ReferentialAction::SetNull,
+            on_update: ReferentialAction::NoAction,
+        });
+
+        assert_eq!(
+            SchemaOpsService::create_table_sql(&req).unwrap(),
+            "CREATE TABLE IF NOT EXISTS shop.orders (\n  \
+             id bigint GENERATED ALWAYS AS IDENTITY PRIMARY KEY,\n  \
+             number text NOT NULL UNIQUE,\n  \
+             customer_id bigint,\n  \
+             total numeric(12,2),\n  \
+             CONSTRAINT total_positive CHECK (total >= 0),\n  \
+             FOREIGN KEY (customer_id) REFERENCES shop.customers (id) ON DELETE SET NULL\n)"
+        );
+    }
+
+    #[test]
+    fn test_create_table_sql_composite_keys() {
+        let mut req = table(vec![column("order_id", "bigint"), column("line", "int")]);
+        req.primary_key = vec!["order_id".to_string(), "line".to_string()];
+        req.unique = vec![vec!["line".to_string()]];
+        let sql = SchemaOpsService::create_table_sql(&req).unwrap();
+        assert!(sql.contains("PRIMARY KEY (order_id, line),\n  UNIQUE (line)"));
+
+        // A column-level key on top of the composite one
+        req.columns[0].primary_key = true;
+        assert!(SchemaOpsService::create_table_sql(&req).is_err());
+    }
+
+    #[test]
+    fn test_create_table_sql_quotes_names() {
+        let mut req = CreateTableRequest {
+            schema: "Sales Data".to_string(),
+            table_name: "Order \"Lines\"".to_string(),
+            ..table(vec![column("Line No", "int"), column("select", "text")])
+        };
+        req.unique = vec![vec!["Line No".to_string()]];
+        assert_eq!(
+            SchemaOpsService::create_table_sql(&req).unwrap(),
+            "CREATE TABLE IF NOT EXISTS \"Sales Data\".\"Order \"\"Lines\"\"\" (\n  \
+             \"Line No\" int,\n  \
+             \"select\" text,\n  \
+             UNIQUE (\"Line No\")\n)"
+        );
+    }
+
+    #[test]
+    fn test_create_table_sql_rejects_invalid_constraints() {
+        let identity_text = table(vec![ColumnDefinition {
+            identity: Some(IdentityKind::ByDefault),
+            ..column("id", "text")
+        }]);
+        assert!(SchemaOpsService::create_table_sql(&identity_text).is_err());
+
+        let mut injected = table(vec![column("total", "int")]);
+        injected.checks.push(CheckConstraint {
+            name: None,
+            expression: "true); DROP TABLE users; --".to_string(),
+        });
+        assert!(SchemaOpsService::create_table_sql(&injected).is_err());
+
+        let mut mismatched = table(vec![column("a", "int"), column("b", "int")]);
+        mismatched.foreign_keys.push(ForeignKey {
+            name: None,
+            columns: vec!["a".to_string(), "b".to_string()],
+            references_schema: Some("public".to_string()),
+            references_table: "other".to_string(),
+            references_columns: vec!["id".to_string()],
+            on_delete: ReferentialAction::Cascade,
+            on_update: ReferentialAction::NoAction,
+        });
+        assert!(SchemaOpsService::create_table_sql(&mismatched).is_err());
+    }
 }
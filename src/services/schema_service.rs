@@ -1,8 +1,20 @@
 // Schema service module
 // Handles database schema inspection and metadata retrieval
 
-use crate::models::{ColumnInfo, Schema, TableInfo};
-use sqlx::{Pool, Postgres, Row};
+use crate::models::{
+    AutocompleteFunction, AutocompleteMetadata, AutocompleteRelation, ColumnInfo, PolicyInfo,
+    RowSecurity, Schema, SchemaTreeNode, TableInfo,
+};
+use crate::services::identifier;
+use crate::services::pg_value;
+use crate::services::query_service;
+use crate::services::sql_format;
+use futures::{StreamExt, TryStreamExt};
+use sqlx::{Column, Pool, Postgres, Row};
+
+/// Maximum number of schemas introspected at once when building the tree.
+/// Kept below the pool size so other requests can still get a connection.
+const SCHEMA_TREE_CONCURRENCY: usize = 4;
 
 /// Lists all schemas in the current database
 pub async fn list_schemas(pool: &Pool<Postgres>) -> Result<Vec<Schema>, sqlx::Error> {
@@ -28,21 +40,225 @@ pub async fn list_schemas(pool: &Pool<Postgres>) -> Result<Vec<Schema>, sqlx::Er
     Ok(schemas)
 }
 
-/// Lists all tables and views in a specific schema
+/// Builds the schema tree by introspecting schemas concurrently
+///
+/// Each schema's tables, views and functions are fetched in parallel, and up
+/// to `SCHEMA_TREE_CONCURRENCY` schemas are processed at a time.
+pub async fn get_schema_tree(pool: &Pool<Postgres>) -> Result<Vec<SchemaTreeNode>, sqlx::Error> {
+    let schemas = list_schemas(pool).await?;
+
+    futures::stream::iter(schemas)
+        .map(|schema| get_schema_tree_node(pool, schema.name))
+        .buffered(SCHEMA_TREE_CONCURRENCY)
+        .try_collect()
+        .await
+}
+
+/// Builds the schema tree in a single catalog round trip
+pub async fn get_schema_tree_single_query(
+    pool: &Pool<Postgres>,
+) -> Result<Vec<SchemaTreeNode>, sqlx::Error> {
+    let query = r#"
+        SELECT
+            s.schema_name::text as name,
+            ARRAY(
+                SELECT c.relname::text FROM pg_catalog.pg_class c
+                JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+                WHERE n.nspname = s.schema_name AND c.relkind IN ('r', 'p', 'f')
+                ORDER BY c.relname
+            ) as tables,
+            ARRAY(
+                SELECT c.relname::text FROM pg_catalog.pg_class c
+                JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+                WHERE n.nspname = s.schema_name AND c.relkind IN ('v', 'm')
+                ORDER BY c.relname
+            ) as views,
+            ARRAY(
+                SELECT DISTINCT p.proname::text FROM pg_catalog.pg_proc p
+                JOIN pg_catalog.pg_namespace n ON n.oid = p.pronamespace
+                WHERE n.nspname = s.schema_name
+                ORDER BY 1
+            ) as functions
+        FROM information_schema.schemata s
+        WHERE s.schema_name NOT IN ('pg_catalog', 'information_schema', 'pg_toast')
+        ORDER BY s.schema_name
+    "#;
+
+    let rows = sqlx::query(query).fetch_all(pool).await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| SchemaTreeNode {
+            name: row.get("name"),
+            tables: row.get("tables"),
+            views: row.get("views"),
+            functions: row.get("functions"),
+        })
+        .collect())
+}
+
+/// Fetches the tables, views and functions of one schema concurrently
+async fn get_schema_tree_node(
+    pool: &Pool<Postgres>,
+    schema: String,
+) -> Result<SchemaTreeNode, sqlx::Error> {
+    let (tables, views, functions) = tokio::try_join!(
+        list_relation_names(pool, &schema, &["r", "p", "f"]),
+        list_relation_names(pool, &schema, &["v", "m"]),
+        list_function_names(pool, &schema),
+    )?;
+
+    Ok(SchemaTreeNode {
+        name: schema,
+        tables,
+        views,
+        functions,
+    })
+}
+
+/// Lists relation names of the given kinds (pg_class.relkind) in a schema
+async fn list_relation_names(
+    pool: &Pool<Postgres>,
+    schema: &str,
+    kinds: &[&str],
+) -> Result<Vec<String>, sqlx::Error> {
+    let query = r#"
+        SELECT c.relname::text
+        FROM pg_catalog.pg_class c
+        JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+        WHERE n.nspname = $1 AND c.relkind::text = ANY($2)
+        ORDER BY c.relname
+    "#;
+
+    sqlx::query_scalar(query)
+        .bind(schema)
+        .bind(kinds)
+        .fetch_all(pool)
+        .await
+}
+
+/// Lists distinct function names in a schema
+async fn list_function_names(
+    pool: &Pool<Postgres>,
+    schema: &str,
+) -> Result<Vec<String>, sqlx::Error> {
+    let query = r#"
+        SELECT DISTINCT p.proname::text
+        FROM pg_catalog.pg_proc p
+        JOIN pg_catalog.pg_namespace n ON n.oid = p.pronamespace
+        WHERE n.nspname = $1
+        ORDER BY 1
+    "#;
+
+    sqlx::query_scalar(query).bind(schema).fetch_all(pool).await
+}
+
+/// Collects schemas, relations with their columns, functions and keywords
+/// for editor autocompletion, optionally limited to one schema
+pub async fn get_autocomplete_metadata(
+    pool: &Pool<Postgres>,
+    schema: Option<&str>,
+) -> Result<AutocompleteMetadata, sqlx::Error> {
+    let relations_query = r#"
+        SELECT
+            n.nspname::text as schema,
+            c.relname::text as name,
+            CASE WHEN c.relkind IN ('v', 'm') THEN 'view' ELSE 'table' END as kind,
+            ARRAY(
+                SELECT a.attname::text FROM pg_catalog.pg_attribute a
+                WHERE a.attrelid = c.oid AND a.attnum > 0 AND NOT a.attisdropped
+                ORDER BY a.attnum
+            ) as column_names,
+            ARRAY(
+                SELECT pg_catalog.format_type(a.atttypid, a.atttypmod) FROM pg_catalog.pg_attribute a
+                WHERE a.attrelid = c.oid AND a.attnum > 0 AND NOT a.attisdropped
+                ORDER BY a.attnum
+            ) as column_types
+        FROM pg_catalog.pg_class c
+        JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+        WHERE c.relkind IN ('r', 'p', 'v', 'm', 'f')
+          AND n.nspname NOT IN ('pg_catalog', 'information_schema')
+          AND n.nspname NOT LIKE 'pg_toast%'
+          AND ($1::text IS NULL OR n.nspname = $1)
+        ORDER BY 1, 2
+    "#;
+
+    let functions_query = r#"
+        SELECT DISTINCT n.nspname::text as schema, p.proname::text as name
+        FROM pg_catalog.pg_proc p
+        JOIN pg_catalog.pg_namespace n ON n.oid = p.pronamespace
+        WHERE n.nspname NOT IN ('pg_catalog', 'information_schema')
+          AND n.nspname NOT LIKE 'pg_toast%'
+          AND ($1::text IS NULL OR n.nspname = $1)
+        ORDER BY 1, 2
+    "#;
+
+    let (schemas, relation_rows, function_rows) = tokio::try_join!(
+        list_schemas(pool),
+        sqlx::query(relations_query).bind(schema).fetch_all(pool),
+        sqlx::query(functions_query).bind(schema).fetch_all(pool),
+    )?;
+
+    let tables = relation_rows
+        .iter()
+        .map(|row| {
+            let names: Vec<String> = row.get("column_names");
+            let types: Vec<String> = row.get("column_types");
+            AutocompleteRelation {
+                schema: row.get("schema"),
+                name: row.get("name"),
+                kind: row.get("kind"),
+                columns: names.into_iter().zip(types).collect(),
+            }
+        })
+        .collect();
+
+    let functions = function_rows
+        .iter()
+        .map(|row| AutocompleteFunction {
+            schema: row.get("schema"),
+            name: row.get("name"),
+        })
+        .collect();
+
+    Ok(AutocompleteMetadata {
+        schemas: schemas
+            .into_iter()
+            .map(|s| s.name)
+            .filter(|name| schema.is_none_or(|scope| scope == name))
+            .collect(),
+        tables,
+        functions,
+        keywords: sql_format::keywords()
+            .into_iter()
+            .map(str::to_string)
+            .collect(),
+    })
+}
+
+/// Lists all tables, foreign tables and views in a specific schema
 pub async fn list_tables(
     pool: &Pool<Postgres>,
     schema: &str,
 ) -> Result<Vec<TableInfo>, sqlx::Error> {
+    // Row counts are the planner's estimates (reltuples) so this stays a
+    // single cheap catalog query even on very large tables
     let query = r#"
         SELECT
-            t.table_schema as schema,
-            t.table_name as name,
-            t.table_type,
-            (SELECT count(*) FROM information_schema.columns c 
-             WHERE c.table_schema = t.table_schema AND c.table_name = t.table_name) as col_count
-        FROM information_schema.tables t
-        WHERE t.table_schema = $1 AND t.table_type IN ('BASE TABLE', 'VIEW')
-        ORDER BY t.table_type, t.table_name
+            n.nspname::text as schema,
+            c.relname::text as name,
+            CASE c.relkind WHEN 'v' THEN 'VIEW' WHEN 'm' THEN 'VIEW'
+                 WHEN 'f' THEN 'FOREIGN' ELSE 'BASE TABLE' END as table_type,
+            CASE WHEN c.relkind IN ('r', 'p') AND c.reltuples >= 0
+                 THEN c.reltuples::bigint END as row_count,
+            CASE WHEN c.relkind IN ('r', 'p', 'm')
+                 THEN pg_catalog.pg_total_relation_size(c.oid) END as size
+        FROM pg_catalog.pg_class c
+        JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+        WHERE n.nspname = $1
+          AND c.relkind IN ('r', 'p', 'v', 'f')
+          AND pg_catalog.has_table_privilege(c.oid, 'SELECT, INSERT, UPDATE, DELETE, TRUNCATE, REFERENCES, TRIGGER')
+        ORDER BY table_type, name
     "#;
 
     let rows = sqlx::query(query).bind(schema).fetch_all(pool).await?;
@@ -53,8 +269,8 @@ pub async fn list_tables(
             schema: row.get("schema"),
             name: row.get("name"),
             table_type: row.get("table_type"),
-            row_count: None,
-            size: None,
+            row_count: row.get("row_count"),
+            size: row.get("size"),
         })
         .collect();
 
@@ -73,7 +289,8 @@ pub async fn get_table_columns(
             c.data_type,
             c.is_nullable,
             c.column_default as "default",
-            CASE WHEN tc.constraint_type = 'PRIMARY KEY' THEN true ELSE false END as is_pk
+            CASE WHEN tc.constraint_type = 'PRIMARY KEY' THEN true ELSE false END as is_pk,
+            pd.description as comment
         FROM information_schema.columns c
         LEFT JOIN information_schema.key_column_usage kcu
             ON c.table_schema = kcu.table_schema
@@ -84,6 +301,15 @@ pub async fn get_table_columns(
             AND kcu.table_name = tc.table_name
             AND kcu.constraint_name = tc.constraint_name
             AND tc.constraint_type = 'PRIMARY KEY'
+        LEFT JOIN pg_catalog.pg_namespace pn ON pn.nspname = c.table_schema
+        LEFT JOIN pg_catalog.pg_class pc
+            ON pc.relnamespace = pn.oid AND pc.relname = c.table_name
+        LEFT JOIN pg_catalog.pg_attribute pa
+            ON pa.attrelid = pc.oid AND pa.attname = c.column_name
+        LEFT JOIN pg_catalog.pg_description pd
+            ON pd.objoid = pc.oid
+            AND pd.classoid = 'pg_catalog.pg_class'::regclass
+            AND pd.objsubid = pa.attnum
         WHERE c.table_schema = $1 AND c.table_name = $2
         ORDER BY c.ordinal_position
     "#;
@@ -102,19 +328,43 @@ pub async fn get_table_columns(
             is_nullable: row.get::<String, _>("is_nullable") == "YES",
             is_pk: row.get::<bool, _>("is_pk"),
             default: row.get("default"),
+            comment: row.get("comment"),
         })
         .collect();
 
     Ok(columns)
 }
 
+/// Gets the comment on a table or view, if any
+pub async fn get_table_comment(
+    pool: &Pool<Postgres>,
+    schema: &str,
+    table: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    let query = r#"
+        SELECT pg_catalog.obj_description(c.oid, 'pg_class') as comment
+        FROM pg_catalog.pg_class c
+        JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+        WHERE n.nspname = $1 AND c.relname = $2
+    "#;
+
+    let row = sqlx::query(query)
+        .bind(schema)
+        .bind(table)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(row.and_then(|r| r.get("comment")))
+}
+
 /// Gets row count for a table
 pub async fn get_table_row_count(
     pool: &Pool<Postgres>,
     schema: &str,
     table: &str,
 ) -> Result<i64, sqlx::Error> {
-    let query = format!("SELECT count(*) as count FROM \"{}\".\"{}\"", schema, table);
+    let relation = identifier::resolve_relation(pool, schema, table).await?;
+    let query = format!("SELECT count(*) as count FROM {}", relation);
 
     let count: (i64,) = sqlx::query_as(&query).fetch_one(pool).await?;
 
@@ -127,12 +377,13 @@ pub async fn get_table_size(
     schema: &str,
     table: &str,
 ) -> Result<i64, sqlx::Error> {
-    let query = format!(
-        "SELECT pg_total_relation_size('\"{}\".\"{}\"%')",
-        schema, table
-    );
+    let query = "SELECT pg_total_relation_size(format('%I.%I', $1::text, $2::text)::regclass)";
 
-    let size: (Option<i64>,) = sqlx::query_as(&query).fetch_one(pool).await?;
+    let size: (Option<i64>,) = sqlx::query_as(query)
+        .bind(schema)
+        .bind(table)
+        .fetch_one(pool)
+        .await?;
 
     Ok(size.0.unwrap_or(0))
 }
@@ -182,29 +433,114 @@ pub async fn get_table_data(
 ) -> Result<(Vec<Vec<Option<String>>>, i64), sqlx::Error> {
     let offset = (page - 1) * page_size;
 
+    let relation = identifier::resolve_relation(pool, schema, table).await?;
+
     // Get total row count
-    let count_query = format!("SELECT count(*) FROM \"{}\".\"{}\"", schema, table);
+    let count_query = format!("SELECT count(*) FROM {}", relation);
     let total_rows: (i64,) = sqlx::query_as(&count_query).fetch_one(pool).await?;
 
     // Get paginated data
     let data_query = format!(
-        "SELECT * FROM \"{}\".\"{}\" LIMIT {} OFFSET {}",
-        schema, table, page_size, offset
+        "SELECT * FROM {} LIMIT {} OFFSET {}",
+        relation, page_size, offset
+    );
+
+    let rows = query_service::fetch_all_limited(sqlx::query(&data_query), pool).await?;
+
+    Ok((rows.iter().map(row_strings).collect(), total_rows.0))
+}
+
+/// Gets up to `limit` rows from a random `percent` of a table's pages with
+/// `TABLESAMPLE SYSTEM`, along with the planner's row estimate
+///
+/// Only the sampled pages are read, so this stays cheap on huge tables where
+/// counting and paging from the start would not. Views cannot be sampled.
+pub async fn get_table_sample(
+    pool: &Pool<Postgres>,
+    schema: &str,
+    table: &str,
+    percent: f64,
+    limit: u32,
+) -> Result<(Vec<Vec<Option<String>>>, i64), sqlx::Error> {
+    let relation = identifier::resolve_relation(pool, schema, table).await?;
+
+    let estimate: (f32,) =
+        sqlx::query_as("SELECT reltuples FROM pg_catalog.pg_class WHERE oid = $1::regclass")
+            .bind(&relation)
+            .fetch_one(pool)
+            .await?;
+
+    let data_query = format!(
+        "SELECT * FROM {} TABLESAMPLE SYSTEM ($1) LIMIT {}",
+        relation, limit
     );
+    let rows =
+        query_service::fetch_all_limited(sqlx::query(&data_query).bind(percent), pool).await?;
+
+    // reltuples is -1 for tables that were never vacuumed or analyzed
+    Ok((
+        rows.iter().map(row_strings).collect(),
+        estimate.0.max(0.0) as i64,
+    ))
+}
 
-    let rows = sqlx::query(&data_query).fetch_all(pool).await?;
+/// Check a `TABLESAMPLE` percentage, which must be above 0 and at most 100
+pub fn validate_sample_percent(percent: f64) -> Result<f64, String> {
+    if percent > 0.0 && percent <= 100.0 {
+        Ok(percent)
+    } else {
+        Err(format!(
+            "Sample percentage must be above 0 and at most 100, got {}",
+            percent
+        ))
+    }
+}
+
+/// Cell values of a row as text, `None` for NULLs and undecoded types
+fn row_strings(row: &sqlx::postgres::PgRow) -> Vec<Option<String>> {
+    (0..row.len())
+        .map(|i| {
+            row.try_get::<String, _>(i)
+                .or_else(|_| row.try_get::<i32, _>(i).map(|v| v.to_string()))
+                .or_else(|_| row.try_get::<i64, _>(i).map(|v| v.to_string()))
+                .or_else(|_| row.try_get::<f64, _>(i).map(|v| v.to_string()))
+                .or_else(|_| row.try_get::<bool, _>(i).map(|v| v.to_string()))
+                .ok()
+        })
+        .collect()
+}
+
+/// Gets a page of table rows as JSON objects keyed by column name, with
+/// values decoded by column type
+pub async fn get_table_rows(
+    pool: &Pool<Postgres>,
+    schema: &str,
+    table: &str,
+    page: u32,
+    page_size: u32,
+) -> Result<(Vec<serde_json::Map<String, serde_json::Value>>, i64), sqlx::Error> {
+    let offset = (page as u64 - 1) * page_size as u64;
+    let relation = identifier::resolve_relation(pool, schema, table).await?;
+
+    let count_query = format!("SELECT count(*) FROM {}", relation);
+    let total_rows: (i64,) = sqlx::query_as(&count_query).fetch_one(pool).await?;
+
+    let data_query = format!(
+        "SELECT * FROM {} LIMIT {} OFFSET {}",
+        relation, page_size, offset
+    );
+    let rows = query_service::fetch_all_limited(sqlx::query(&data_query), pool).await?;
 
     let data = rows
         .iter()
         .map(|row| {
-            (0..row.len())
-                .map(|i| {
-                    row.try_get::<String, _>(i)
-                        .or_else(|_| row.try_get::<i32, _>(i).map(|v| v.to_string()))
-                        .or_else(|_| row.try_get::<i64, _>(i).map(|v| v.to_string()))
-                        .or_else(|_| row.try_get::<f64, _>(i).map(|v| v.to_string()))
-                        .or_else(|_| row.try_get::<bool, _>(i).map(|v| v.to_string()))
-                        .ok()
+            row.columns()
+                .iter()
+                .map(|column| {
+                    (
+                        column.name().to_string(),
+                        pg_value::cell_to_json(row, column.ordinal()),
+                    )
                 })
                 .collect()
         })
@@ -252,3 +588,60 @@ pub async fn get_table_indexes(
 
     Ok(indexes)
 }
+
+/// Gets row-level security status and policies for a specific table
+pub async fn get_table_policies(
+    pool: &Pool<Postgres>,
+    schema: &str,
+    table: &str,
+) -> Result<RowSecurity, sqlx::Error> {
+    let status_query = r#"
+        SELECT c.relrowsecurity as enabled, c.relforcerowsecurity as forced
+        FROM pg_class c
+        JOIN pg_namespace n ON n.oid = c.relnamespace
+        WHERE n.nspname = $1 AND c.relname = $2
+    "#;
+
+    let status = sqlx::query(status_query)
+        .bind(schema)
+        .bind(table)
+        .fetch_one(pool)
+        .await?;
+
+    let policies_query = r#"
+        SELECT
+            policyname::text as name,
+            permissive,
+            cmd as command,
+            roles::text[] as roles,
+            qual as using_expr,
+            with_check as with_check_expr
+        FROM pg_policies
+        WHERE schemaname = $1 AND tablename = $2
+        ORDER BY policyname
+    "#;
+
+    let rows = sqlx::query(policies_query)
+        .bind(schema)
+        .bind(table)
+        .fetch_all(pool)
+        .await?;
+
+    let policies = rows
+        .iter()
+        .map(|row| PolicyInfo {
+            name: row.get("name"),
+            permissive: row.get("permissive"),
+            command: row.get("command"),
+            roles: row.get("roles"),
+            using_expr: row.get("using_expr"),
+            with_check_expr: row.get("with_check_expr"),
+        })
+        .collect();
+
+    Ok(RowSecurity {
+        enabled: status.get("enabled"),
+        forced: status.get("forced"),
+        policies,
+    })
+}
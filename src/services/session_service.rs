@@ -0,0 +1,493 @@
+use async_trait::async_trait;
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
+use sqlx::{Pool, Postgres};
+/// Session Store
+///
+/// Server-side storage for the browser sessions handled by tower-sessions
+/// (see `middleware::session`). Session data is a JSON object; what goes in
+/// it (preferences, the open query transaction, later the signed-in user) is
+/// up to the routes.
+///
+/// Backends:
+/// - Memory: tower-sessions' `MemoryStore`, lost on restart and not shared
+///   between replicas; expired sessions are never returned but stay in memory
+/// - Postgres: a `pgadmin_rs_sessions` table in the managed database, purged
+///   of expired sessions periodically
+/// - Redis: keys that expire on their own, through the `redis` crate's
+///   connection manager, which reconnects after failures and handles AUTH,
+///   database selection and TLS (`rediss://`) from the URL
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tower_sessions::session::{Id, Record};
+use tower_sessions::session_store;
+use tower_sessions::{MemoryStore, SessionStore};
+
+/// The contents of a session, as stored under one key
+pub type SessionData = serde_json::Map<String, serde_json::Value>;
+
+/// Timeout for connecting to Redis and for a single Redis round trip
+const REDIS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Reconnection attempts before a Redis command fails
+const REDIS_RETRIES: usize = 3;
+
+/// Longest wait between Redis reconnection attempts
+const REDIS_RETRY_MAX_DELAY: Duration = Duration::from_millis(500);
+
+/// Prefix for session keys in Redis
+const REDIS_KEY_PREFIX: &str = "pgadmin:session:";
+
+/// How often expired sessions are removed from the Postgres store
+const PURGE_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Where sessions are kept, chosen by `SESSION_STORE`
+#[derive(Debug, Clone)]
+pub enum Store {
+    Memory(MemoryStore),
+    Postgres(PostgresStore),
+    Redis(RedisStore),
+}
+
+impl Store {
+    pub fn memory() -> Self {
+        Store::Memory(MemoryStore::default())
+    }
+
+    pub fn postgres(pool: Arc<Pool<Postgres>>) -> Self {
+        Store::Postgres(PostgresStore { pool })
+    }
+
+    /// Use a Redis server, checking up front that it answers
+    pub async fn redis(url: &str) -> Result<Self, String> {
+        RedisStore::connect(url).await.map(Store::Redis)
+    }
+
+    /// Create the Postgres store's table if needed (nothing to do for the others)
+    pub async fn prepare(&self) -> Result<(), String> {
+        match self {
+            Store::Postgres(store) => store.prepare().await,
+            Store::Memory(_) | Store::Redis(_) => Ok(()),
+        }
+    }
+
+    /// Short description for logs
+    pub fn describe(&self) -> String {
+        match self {
+            Store::Memory(_) => "memory".to_string(),
+            Store::Postgres(_) => "postgres".to_string(),
+            Store::Redis(store) => format!("redis {}", store.address),
+        }
+    }
+
+    /// Start the background task that purges expired sessions from the
+    /// Postgres store
+    pub fn spawn_purger(&self) {
+        let Store::Postgres(store) = self else {
+            return;
+        };
+        let store = store.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PURGE_INTERVAL);
+            loop {
+                interval.tick().await;
+                match store.delete_expired().await {
+                    Ok(0) => {}
+                    Ok(purged) => tracing::debug!("Purged {} expired sessions", purged),
+                    Err(e) => tracing::warn!("{}", e),
+                }
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl SessionStore for Store {
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        match self {
+            Store::Memory(store) => store.create(record).await,
+            Store::Postgres(store) => store.create(record).await,
+            Store::Redis(store) => store.create(record).await,
+        }
+    }
+
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        match self {
+            Store::Memory(store) => store.save(record).await,
+            Store::Postgres(store) => store.save(record).await,
+            Store::Redis(store) => store.save(record).await,
+        }
+    }
+
+    async fn load(&self, id: &Id) -> session_store::Result<Option<Record>> {
+        match self {
+            Store::Memory(store) => store.load(id).await,
+            Store::Postgres(store) => store.load(id).await,
+            Store::Redis(store) => store.load(id).await,
+        }
+    }
+
+    async fn delete(&self, id: &Id) -> session_store::Result<()> {
+        match self {
+            Store::Memory(store) => store.delete(id).await,
+            Store::Postgres(store) => store.delete(id).await,
+            Store::Redis(store) => store.delete(id).await,
+        }
+    }
+}
+
+fn backend_error(e: impl fmt::Display) -> session_store::Error {
+    session_store::Error::Backend(e.to_string())
+}
+
+/// Sessions in the `pgadmin_rs_sessions` table
+#[derive(Debug, Clone)]
+pub struct PostgresStore {
+    pool: Arc<Pool<Postgres>>,
+}
+
+impl PostgresStore {
+    async fn prepare(&self) -> Result<(), String> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS pgadmin_rs_sessions (
+                id TEXT PRIMARY KEY,
+                data JSONB NOT NULL,
+                expires_at TIMESTAMPTZ NOT NULL
+            )",
+        )
+        .execute(self.pool.as_ref())
+        .await
+        .map_err(|e| format!("Failed to create sessions table: {}", e))?;
+        Ok(())
+    }
+
+    /// Drop expired sessions, returning how many were removed
+    async fn delete_expired(&self) -> Result<u64, String> {
+        let result = sqlx::query("DELETE FROM pgadmin_rs_sessions WHERE expires_at <= now()")
+            .execute(self.pool.as_ref())
+            .await
+            .map_err(|e| format!("Failed to purge sessions: {}", e))?;
+        Ok(result.rows_affected())
+    }
+
+    /// Write a session, returning false if `overwrite` is off and the ID is taken
+    async fn write(&self, record: &Record, overwrite: bool) -> session_store::Result<bool> {
+        let data = serde_json::to_string(&record.data)
+            .map_err(|e| session_store::Error::Encode(e.to_string()))?;
+        let conflict = if overwrite {
+            "DO UPDATE SET data = EXCLUDED.data, expires_at = EXCLUDED.expires_at"
+        } else {
+            "DO NOTHING"
+        };
+        let result = sqlx::query(&format!(
+            "INSERT INTO pgadmin_rs_sessions (id, data, expires_at)
+             VALUES ($1, $2::jsonb, to_timestamp($3))
+             ON CONFLICT (id) {}",
+            conflict
+        ))
+        .bind(record.id.to_string())
+        .bind(data)
+        .bind(record.expiry_date.unix_timestamp())
+        .execute(self.pool.as_ref())
+        .await
+        .map_err(backend_error)?;
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+#[async_trait]
+impl SessionStore for PostgresStore {
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        // A new ID that is already taken is replaced rather than overwritten
+        while !self.write(record, false).await? {
+            record.id = Id::default();
+        }
+        Ok(())
+    }
+
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        self.write(record, true).await.map(|_| ())
+    }
+
+    async fn load(&self, id: &Id) -> session_store::Result<Option<Record>> {
+        let row: Option<(String, i64)> = sqlx::query_as(
+            "SELECT data::text, extract(epoch FROM expires_at)::bigint
+             FROM pgadmin_rs_sessions WHERE id = $1 AND expires_at > now()",
+        )
+        .bind(id.to_string())
+        .fetch_optional(self.pool.as_ref())
+        .await
+        .map_err(backend_error)?;
+        let Some((data, expires_at)) = row else {
+            return Ok(None);
+        };
+        let decode = |e: &dyn fmt::Display| session_store::Error::Decode(e.to_string());
+        Ok(Some(Record {
+            id: *id,
+            data: serde_json::from_str(&data).map_err(|e| decode(&e))?,
+            expiry_date: OffsetDateTime::from_unix_timestamp(expires_at).map_err(|e| decode(&e))?,
+        }))
+    }
+
+    async fn delete(&self, id: &Id) -> session_store::Result<()> {
+        sqlx::query("DELETE FROM pgadmin_rs_sessions WHERE id = $1")
+            .bind(id.to_string())
+            .execute(self.pool.as_ref())
+            .await
+            .map_err(backend_error)?;
+        Ok(())
+    }
+}
+
+/// Sessions as Redis keys that expire with the session
+#[derive(Clone)]
+pub struct RedisStore {
+    connection: ConnectionManager,
+    /// Host and port, for logs
+    address: String,
+}
+
+impl fmt::Debug for RedisStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisStore")
+            .field("address", &self.address)
+            .finish()
+    }
+}
+
+impl RedisStore {
+    /// Connect to a `redis://` or `rediss://` URL
+    async fn connect(url: &str) -> Result<Self, String> {
+        let client = redis::Client::open(url).map_err(|e| format!("Invalid Redis URL: {}", e))?;
+        let address = client.get_connection_info().addr.to_string();
+        // A few quick retries, so an unreachable server fails requests (and
+        // startup) within seconds rather than backing off for minutes
+        let config = ConnectionManagerConfig::new()
+            .set_connection_timeout(REDIS_TIMEOUT)
+            .set_response_timeout(REDIS_TIMEOUT)
+            .set_number_of_retries(REDIS_RETRIES)
+            .set_max_delay(REDIS_RETRY_MAX_DELAY.as_millis() as u64);
+        let connection = client
+            .get_connection_manager_with_config(config)
+            .await
+            .map_err(|e| format!("Failed to connect to Redis at {}: {}", address, e))?;
+        Ok(Self {
+            connection,
+            address,
+        })
+    }
+
+    fn key(id: &Id) -> String {
+        format!("{}{}", REDIS_KEY_PREFIX, id)
+    }
+
+    /// `SET` the session to expire with it, returning false if `only_new` is
+    /// set and the key exists
+    async fn set(&self, record: &Record, only_new: bool) -> session_store::Result<bool> {
+        let value =
+            serde_json::to_vec(record).map_err(|e| session_store::Error::Encode(e.to_string()))?;
+        let mut command = redis::cmd("SET");
+        command.arg(Self::key(&record.id)).arg(value);
+        if only_new {
+            command.arg("NX");
+        }
+        command.arg("EXAT").arg(record.expiry_date.unix_timestamp());
+        let reply: Option<String> = command
+            .query_async(&mut self.connection.clone())
+            .await
+            .map_err(backend_error)?;
+        Ok(reply.is_some())
+    }
+}
+
+#[async_trait]
+impl SessionStore for RedisStore {
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        while !self.set(record, true).await? {
+            record.id = Id::default();
+        }
+        Ok(())
+    }
+
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        self.set(record, false).await.map(|_| ())
+    }
+
+    async fn load(&self, id: &Id) -> session_store::Result<Option<Record>> {
+        let value: Option<Vec<u8>> = redis::cmd("GET")
+            .arg(Self::key(id))
+            .query_async(&mut self.connection.clone())
+            .await
+            .map_err(backend_error)?;
+        value
+            .map(|value| {
+                serde_json::from_slice(&value)
+                    .map_err(|e| session_store::Error::Decode(e.to_string()))
+            })
+            .transpose()
+    }
+
+    async fn delete(&self, id: &Id) -> session_store::Result<()> {
+        let _: i64 = redis::cmd("DEL")
+            .arg(Self::key(id))
+            .query_async(&mut self.connection.clone())
+            .await
+            .map_err(backend_error)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    fn record(value: serde_json::Value, ttl: time::Duration) -> Record {
+        Record {
+            id: Id::default(),
+            data: serde_json::from_value(value).unwrap(),
+            expiry_date: OffsetDateTime::now_utc() + ttl,
+        }
+    }
+
+    /// Checks a store keeps, replaces and forgets a session
+    async fn round_trip(store: &Store) {
+        let mut session = record(json!({ "transaction": "abc" }), time::Duration::minutes(1));
+        assert!(store.load(&session.id).await.unwrap().is_none());
+
+        store.create(&mut session).await.unwrap();
+        let loaded = store.load(&session.id).await.unwrap().unwrap();
+        assert_eq!(loaded.data, session.data);
+
+        session.data = serde_json::from_value(json!({ "theme": "dark" })).unwrap();
+        store.save(&session).await.unwrap();
+        let loaded = store.load(&session.id).await.unwrap().unwrap();
+        assert_eq!(loaded.data, session.data);
+
+        store.delete(&session.id).await.unwrap();
+        assert!(store.load(&session.id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_memory_store() {
+        let store = Store::memory();
+        round_trip(&store).await;
+
+        // Expired sessions are not returned
+        let mut expired = record(json!({ "theme": "x" }), time::Duration::ZERO);
+        store.create(&mut expired).await.unwrap();
+        assert!(store.load(&expired.id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_postgres_store() {
+        let Ok(url) = std::env::var("TEST_DATABASE_URL") else {
+            eprintln!("TEST_DATABASE_URL is not set, skipping");
+            return;
+        };
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .max_connections(1)
+            .connect(&url)
+            .await
+            .unwrap();
+        let store = Store::postgres(Arc::new(pool));
+        store.prepare().await.unwrap();
+        round_trip(&store).await;
+
+        let mut expired = record(json!({ "theme": "x" }), time::Duration::seconds(-1));
+        store.create(&mut expired).await.unwrap();
+        assert!(store.load(&expired.id).await.unwrap().is_none());
+        let Store::Postgres(postgres) = &store else {
+            unreachable!()
+        };
+        assert!(postgres.delete_expired().await.unwrap() >= 1);
+    }
+
+    /// Read one RESP array of bulk strings
+    async fn read_command<R: AsyncBufReadExt + Unpin>(reader: &mut R) -> Option<Vec<String>> {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await.ok()? == 0 {
+            return None;
+        }
+        let count: usize = line.trim_end().strip_prefix('*')?.parse().ok()?;
+        let mut args = Vec::with_capacity(count);
+        for _ in 0..count {
+            line.clear();
+            reader.read_line(&mut line).await.ok()?;
+            let len: usize = line.trim_end().strip_prefix('$')?.parse().ok()?;
+            let mut arg = vec![0; len + 2];
+            reader.read_exact(&mut arg).await.ok()?;
+            arg.truncate(len);
+            args.push(String::from_utf8(arg).ok()?);
+        }
+        Some(args)
+    }
+
+    /// A Redis stand-in that keeps keys in a map, recording every command
+    async fn fake_redis() -> (u16, Arc<parking_lot::Mutex<Vec<Vec<String>>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let commands = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let received = commands.clone();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read, mut write) = stream.into_split();
+            let mut reader = BufReader::new(read);
+            let mut keys: HashMap<String, String> = HashMap::new();
+            while let Some(args) = read_command(&mut reader).await {
+                let reply = match args[0].to_ascii_uppercase().as_str() {
+                    "SET" if args.iter().any(|a| a == "NX") && keys.contains_key(&args[1]) => {
+                        "$-1\r\n".to_string()
+                    }
+                    "SET" => {
+                        keys.insert(args[1].clone(), args[2].clone());
+                        "+OK\r\n".to_string()
+                    }
+                    "GET" => match keys.get(&args[1]) {
+                        Some(value) => format!("${}\r\n{}\r\n", value.len(), value),
+                        None => "$-1\r\n".to_string(),
+                    },
+                    "DEL" => format!(":{}\r\n", keys.remove(&args[1]).map_or(0, |_| 1)),
+                    // Connection setup (CLIENT SETINFO and the like)
+                    _ => "+OK\r\n".to_string(),
+                };
+                received.lock().push(args);
+                if write.write_all(reply.as_bytes()).await.is_err() {
+                    break;
+                }
+            }
+        });
+        (port, commands)
+    }
+
+    #[tokio::test]
+    async fn test_redis_store() {
+        let (port, commands) = fake_redis().await;
+        let store = Store::redis(&format!("redis://127.0.0.1:{}/", port))
+            .await
+            .unwrap();
+        assert_eq!(store.describe(), format!("redis 127.0.0.1:{}", port));
+        round_trip(&store).await;
+
+        let commands = commands.lock();
+        let set = commands.iter().find(|c| c[0] == "SET").unwrap();
+        assert!(set[1].starts_with(REDIS_KEY_PREFIX));
+        assert_eq!(set[3..5], ["NX".to_string(), "EXAT".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_redis_unreachable() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+        let error = Store::redis(&format!("redis://127.0.0.1:{}", port))
+            .await
+            .unwrap_err();
+        assert!(error.starts_with("Failed to connect to Redis at 127.0.0.1"));
+        assert!(Store::redis("http://localhost").await.is_err());
+    }
+}
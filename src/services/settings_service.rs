@@ -0,0 +1,195 @@
+use serde::Serialize;
+/// Settings Service
+///
+/// Reads server parameters from `pg_settings` and changes a selected set of
+/// them with `ALTER SYSTEM SET` followed by `pg_reload_conf()`:
+/// - Only parameters in `EDITABLE_SETTINGS` can be changed, which also keeps
+///   the parameter name (an identifier that cannot be bound) safe to inline
+/// - Values are always sent as quoted literals; the server validates them
+/// - Parameters with `postmaster` context are written but only take effect
+///   after a restart, which `pending_restart` reports
+use sqlx::PgPool;
+
+/// Parameters that may be changed through the API: memory, planner costs,
+/// timeouts, logging, checkpoints and autovacuum
+pub const EDITABLE_SETTINGS: &[&str] = &[
+    "work_mem",
+    "maintenance_work_mem",
+    "effective_cache_size",
+    "shared_buffers",
+    "max_connections",
+    "random_page_cost",
+    "seq_page_cost",
+    "effective_io_concurrency",
+    "default_statistics_target",
+    "statement_timeout",
+    "lock_timeout",
+    "idle_in_transaction_session_timeout",
+    "log_min_duration_statement",
+    "log_statement",
+    "log_lock_waits",
+    "log_temp_files",
+    "checkpoint_timeout",
+    "checkpoint_completion_target",
+    "max_wal_size",
+    "min_wal_size",
+    "autovacuum",
+    "autovacuum_naptime",
+    "autovacuum_vacuum_scale_factor",
+    "autovacuum_analyze_scale_factor",
+    "autovacuum_vacuum_cost_limit",
+];
+
+/// Longest value accepted for a parameter
+const MAX_VALUE_LEN: usize = 1024;
+
+/// A server parameter as reported by `pg_settings`
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Setting {
+    pub name: String,
+    pub setting: String,
+    pub unit: Option<String>,
+    /// `bool`, `integer`, `real`, `string` or `enum`
+    pub vartype: String,
+    /// When a change takes effect: `postmaster` needs a restart
+    pub context: String,
+    /// Where the current value comes from (`default`, `configuration file`...)
+    pub source: String,
+    pub boot_val: Option<String>,
+    pub min_val: Option<String>,
+    pub max_val: Option<String>,
+    pub enumvals: Option<Vec<String>>,
+    pub short_desc: String,
+    /// Changed in the configuration but waiting for a restart
+    pub pending_restart: bool,
+}
+
+impl Setting {
+    /// Only takes effect after a server restart
+    pub fn requires_restart(&self) -> bool {
+        self.context == "postmaster"
+    }
+}
+
+const SETTING_COLUMNS: &str = "name, setting, unit, vartype, context, source, boot_val, \
+     min_val, max_val, enumvals, short_desc, pending_restart";
+
+/// Current values of the editable parameters
+pub async fn list_editable(pool: &PgPool) -> Result<Vec<Setting>, sqlx::Error> {
+    let names: Vec<String> = EDITABLE_SETTINGS.iter().map(|s| s.to_string()).collect();
+    sqlx::query_as(&format!(
+        "SELECT {} FROM pg_settings WHERE name = ANY($1) ORDER BY name",
+        SETTING_COLUMNS
+    ))
+    .bind(names)
+    .fetch_all(pool)
+    .await
+}
+
+/// Parameters whose value does not come from the built-in default
+pub async fn list_changed(pool: &PgPool) -> Result<Vec<Setting>, sqlx::Error> {
+    sqlx::query_as(&format!(
+        "SELECT {} FROM pg_settings \
+         WHERE source NOT IN ('default', 'override') OR pending_restart \
+         ORDER BY name",
+        SETTING_COLUMNS
+    ))
+    .fetch_all(pool)
+    .await
+}
+
+/// A single parameter, if the server has it
+pub async fn get(pool: &PgPool, name: &str) -> Result<Option<Setting>, sqlx::Error> {
+    sqlx::query_as(&format!(
+        "SELECT {} FROM pg_settings WHERE name = $1",
+        SETTING_COLUMNS
+    ))
+    .bind(name)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Whether the connected role is a superuser (required for `ALTER SYSTEM`)
+pub async fn is_superuser(pool: &PgPool) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar("SELECT current_setting('is_superuser') = 'on'")
+        .fetch_one(pool)
+        .await
+}
+
+/// Check that a parameter may be changed to `value` (`None` resets it)
+pub fn validate_change(name: &str, value: Option<&str>) -> Result<(), String> {
+    if !EDITABLE_SETTINGS.contains(&name) {
+        return Err(format!("Parameter '{}' cannot be changed here", name));
+    }
+    if let Some(value) = value {
+        if value.trim().is_empty() {
+            return Err("Value must not be empty; send null to reset the parameter".to_string());
+        }
+        if value.len() > MAX_VALUE_LEN || value.contains('\0') {
+            return Err(format!("Invalid value for '{}'", name));
+        }
+    }
+    Ok(())
+}
+
+/// The `ALTER SYSTEM` statement for a change; `name` must be validated first
+pub fn alter_system_sql(name: &str, value: Option<&str>) -> String {
+    match value {
+        Some(value) => format!(
+            "ALTER SYSTEM SET {} = '{}'",
+            name,
+            value.trim().replace('\'', "''")
+        ),
+        None => format!("ALTER SYSTEM RESET {}", name),
+    }
+}
+
+/// Write the change to `postgresql.auto.conf` and reload the configuration
+pub async fn apply(pool: &PgPool, name: &str, value: Option<&str>) -> Result<(), String> {
+    validate_change(name, value)?;
+    sqlx::query(&alter_system_sql(name, value))
+        .execute(pool)
+        .await
+        .map_err(|e| format!("Failed to change {}: {}", name, e))?;
+    sqlx::query("SELECT pg_reload_conf()")
+        .execute(pool)
+        .await
+        .map_err(|e| {
+            format!(
+                "Changed {} but failed to reload the configuration: {}",
+                name, e
+            )
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_change() {
+        assert!(validate_change("work_mem", Some("64MB")).is_ok());
+        assert!(validate_change("work_mem", None).is_ok());
+        assert!(validate_change("data_directory", Some("/tmp")).is_err());
+        assert!(validate_change("work_mem; DROP TABLE t", Some("1")).is_err());
+        assert!(validate_change("work_mem", Some("  ")).is_err());
+        assert!(validate_change("work_mem", Some("1\0")).is_err());
+    }
+
+    #[test]
+    fn test_alter_system_sql() {
+        assert_eq!(
+            alter_system_sql("work_mem", Some(" 64MB ")),
+            "ALTER SYSTEM SET work_mem = '64MB'"
+        );
+        assert_eq!(
+            alter_system_sql("log_statement", Some("ddl' ; SELECT '1")),
+            "ALTER SYSTEM SET log_statement = 'ddl'' ; SELECT ''1'"
+        );
+        assert_eq!(
+            alter_system_sql("work_mem", None),
+            "ALTER SYSTEM RESET work_mem"
+        );
+    }
+}
@@ -0,0 +1,359 @@
+use crate::services::sql_lexer::{
+    is_escape_string, is_ident_char, skip_block_comment, skip_dollar_quoted, skip_line_comment,
+    skip_quoted,
+};
+/// SQL Formatter
+///
+/// Reformats queries for the editor's Format button with the sqlformat crate:
+/// each clause starts a new line, list items and conditions are indented and
+/// keywords are uppercased.
+///
+/// sqlformat targets generic SQL, so PostgreSQL syntax it would mangle is
+/// swapped for placeholders before formatting and put back afterwards:
+/// dollar-quoted bodies (it reformats their contents), `E'...'`, `B'...'` and
+/// `U&'...'` strings, operators such as `@-@`, `?|` and `#>>` (it splits them
+/// or reads `#` as a comment), `::` casts and `[...]` subscripts (it spaces
+/// them out) and numbers such as `.5`.
+use sqlformat::{FormatOptions, Indent, QueryParams};
+use std::collections::HashSet;
+use std::sync::LazyLock;
+
+static KEYWORDS: LazyLock<HashSet<&'static str>> = LazyLock::new(|| {
+    "ADD ALL ALTER ANALYZE AND ANY AS ASC BEGIN BETWEEN BY CASCADE CASE CAST CHECK COLUMN \
+        COMMIT CONFLICT CONSTRAINT CREATE CROSS DEFAULT DELETE DESC DISTINCT DO DROP ELSE \
+        END EXCEPT EXISTS EXPLAIN FALSE FETCH FILTER FIRST FOR FOREIGN FROM FULL GRANT GROUP \
+        HAVING ILIKE IN INDEX INNER INSERT INTERSECT INTERVAL INTO IS JOIN LATERAL LEFT LIKE \
+        LAST LIMIT MATERIALIZED NATURAL NOT NOTHING NULL NULLS OFFSET ON OR ORDER OUTER OVER \
+        PARTITION PRIMARY \
+        RECURSIVE REFERENCES RESTRICT RETURNING REVOKE RIGHT ROLLBACK SELECT SET SHOW \
+        SIMILAR SOME TABLE THEN TO TRUE TRUNCATE UNION UNIQUE UPDATE USING VALUES VERBOSE \
+        VIEW WHEN WHERE WINDOW WITH WITHIN"
+        .split_whitespace()
+        .collect()
+});
+
+/// Keywords offered for autocompletion, sorted
+pub fn keywords() -> Vec<&'static str> {
+    let mut keywords: Vec<_> = KEYWORDS.iter().copied().collect();
+    keywords.sort_unstable();
+    keywords
+}
+
+/// Characters that make an operator PostgreSQL-specific
+const SPECIAL_OPERATOR_CHARS: &str = "~!@#%^&|`?";
+const OPERATOR_CHARS: &str = "+-*/<>=~!@#%^&|`?";
+
+/// Text swapped out before formatting, and whether it joins onto its
+/// neighbours again when put back
+struct Protected {
+    text: String,
+    hug_left: bool,
+    hug_right: bool,
+}
+
+/// Replace what sqlformat would mangle with placeholders named `prefix{n}_`
+fn protect(sql: &str, prefix: &str) -> (String, Vec<Protected>) {
+    let chars: Vec<char> = sql.chars().collect();
+    let text = |start: usize, end: usize| -> String { chars[start..end].iter().collect() };
+    let mut out = String::with_capacity(sql.len());
+    let mut protected = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let start = i;
+        let (hug_left, hug_right) = match c {
+            '-' if next == Some('-') => {
+                i = skip_line_comment(&chars, i);
+                out.push_str(&text(start, i));
+                continue;
+            }
+            '/' if next == Some('*') => {
+                i = skip_block_comment(&chars, i);
+                out.push_str(&text(start, i));
+                continue;
+            }
+            '\'' if is_escape_string(&chars, i) => {
+                // The prefix was copied with the word before it
+                out.pop();
+                i = skip_quoted(&chars, i, c, true);
+                protected.push(Protected {
+                    text: text(start - 1, i),
+                    hug_left: false,
+                    hug_right: false,
+                });
+                out.push_str(&format!(" {}{}_ ", prefix, protected.len() - 1));
+                continue;
+            }
+            '\'' | '"' => {
+                i = skip_quoted(&chars, i, c, false);
+                out.push_str(&text(start, i));
+                continue;
+            }
+            '$' if next.is_some_and(|c| c.is_ascii_digit()) => {
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                out.push_str(&text(start, i));
+                continue;
+            }
+            '$' => {
+                i = skip_dollar_quoted(&chars, i);
+                (false, false)
+            }
+            ':' if next == Some(':') => {
+                i += 2;
+                (true, true)
+            }
+            '[' => {
+                i = skip_brackets(&chars, i);
+                (start > 0 && !chars[start - 1].is_whitespace(), false)
+            }
+            '.' if next.is_some_and(|c| c.is_ascii_digit())
+                && (start == 0 || !is_ident_char(chars[start - 1])) =>
+            {
+                i += 1;
+                while i < chars.len() && (is_ident_char(chars[i]) || chars[i] == '.') {
+                    i += 1;
+                }
+                (false, false)
+            }
+            'b' | 'B' | 'x' | 'X' | 'n' | 'N'
+                if next == Some('\'') && (start == 0 || !is_ident_char(chars[start - 1])) =>
+            {
+                i = skip_quoted(&chars, i + 1, '\'', false);
+                (false, false)
+            }
+            'u' | 'U'
+                if next == Some('&')
+                    && matches!(chars.get(i + 2), Some('\'' | '"'))
+                    && (start == 0 || !is_ident_char(chars[start - 1])) =>
+            {
+                i = skip_quoted(&chars, i + 2, chars[i + 2], false);
+                (false, false)
+            }
+            c if OPERATOR_CHARS.contains(c) => {
+                i = scan_operator(&chars, i);
+                if !chars[start..i]
+                    .iter()
+                    .any(|c| SPECIAL_OPERATOR_CHARS.contains(*c))
+                {
+                    out.push_str(&text(start, i));
+                    continue;
+                }
+                (false, false)
+            }
+            c if is_ident_char(c) || c.is_alphabetic() => {
+                while i < chars.len()
+                    && (is_ident_char(chars[i]) || chars[i].is_alphabetic() || chars[i] == '$')
+                {
+                    i += 1;
+                }
+                out.push_str(&text(start, i));
+                continue;
+            }
+            _ => {
+                i += 1;
+                out.push(c);
+                continue;
+            }
+        };
+        if i == start + 1 && c == '$' {
+            out.push(c);
+            continue;
+        }
+        protected.push(Protected {
+            text: text(start, i),
+            hug_left,
+            hug_right,
+        });
+        out.push_str(&format!(" {}{}_ ", prefix, protected.len() - 1));
+    }
+    (out, protected)
+}
+
+/// End index (exclusive) of the `[...]` starting at `start`, with any nested brackets
+fn skip_brackets(chars: &[char], start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            '\'' | '"' => {
+                i = skip_quoted(
+                    chars,
+                    i,
+                    chars[i],
+                    chars[i] == '\'' && is_escape_string(chars, i),
+                );
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    chars.len()
+}
+
+/// End index (exclusive) of the operator starting at `start`, stopping before a comment
+fn scan_operator(chars: &[char], start: usize) -> usize {
+    let mut end = start + 1;
+    while end < chars.len()
+        && OPERATOR_CHARS.contains(chars[end])
+        && !matches!(
+            (chars[end], chars.get(end + 1)),
+            ('-', Some('-')) | ('/', Some('*'))
+        )
+    {
+        end += 1;
+    }
+    end
+}
+
+/// Put protected text back in place of its placeholders, which sqlformat
+/// leaves in their original order
+fn restore(formatted: &str, protected: &[Protected], prefix: &str) -> String {
+    let mut out = String::with_capacity(formatted.len());
+    let mut rest = formatted;
+    for (n, p) in protected.iter().enumerate() {
+        let placeholder = format!("{}{}_", prefix, n);
+        let Some(at) = rest.find(&placeholder) else {
+            break;
+        };
+        out.push_str(&rest[..at]);
+        if p.hug_left {
+            out.truncate(out.trim_end().len());
+        }
+        out.push_str(&p.text);
+        rest = &rest[at + placeholder.len()..];
+        if p.hug_right {
+            rest = rest.trim_start();
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Reformat a query or script
+pub fn format_sql(sql: &str) -> String {
+    // A placeholder prefix the query does not already contain
+    let mut prefix = "pgadmin_fmt_".to_string();
+    while sql.contains(&prefix) {
+        prefix.insert(0, 'x');
+    }
+    let (sql, protected) = protect(sql, &prefix);
+    let options = FormatOptions {
+        indent: Indent::Spaces(2),
+        uppercase: true,
+        lines_between_queries: 2,
+    };
+    let formatted = sqlformat::format(&sql, &QueryParams::None, options);
+    restore(&formatted, &protected, &prefix).trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_select() {
+        assert_eq!(
+            format_sql(
+                "select u.id, count(*) from users u left join orders o on o.user_id = u.id \
+                 where u.active and o.total < -1 group by u.id order by 2 desc limit 10"
+            ),
+            "SELECT\n  u.id,\n  count(*)\nFROM\n  users u\n  \
+             LEFT JOIN orders o ON o.user_id = u.id\n\
+             WHERE\n  u.active\n  AND o.total < -1\n\
+             GROUP BY\n  u.id\nORDER BY\n  2 DESC\nLIMIT\n  10"
+        );
+        assert_eq!(
+            format_sql("select 1; select 2"),
+            "SELECT\n  1;\n\nSELECT\n  2"
+        );
+    }
+
+    #[test]
+    fn test_format_dollar_quoting() {
+        // Bodies are kept verbatim, however SQL-like they look
+        assert_eq!(
+            format_sql(
+                "create function f() returns int as $body$ select 1; -- x\n$body$ language plpgsql"
+            ),
+            "CREATE FUNCTION f() RETURNS int AS $body$ select 1; -- x\n$body$ language plpgsql"
+        );
+        assert_eq!(
+            format_sql("do $$ begin perform 1; end $$"),
+            "DO $$ begin perform 1; end $$"
+        );
+        // A tagged body may contain $$, and $1 is a parameter
+        assert_eq!(
+            format_sql("select $a$ it's $$ nested $$ $a$, $1"),
+            "SELECT\n  $a$ it's $$ nested $$ $a$,\n  $1"
+        );
+    }
+
+    #[test]
+    fn test_format_postgres_syntax() {
+        assert_eq!(
+            format_sql("select a @-@ b, x->>'k', '{1}'::int[], arr[1][2], .5 from t"),
+            "SELECT\n  a @-@ b,\n  x ->> 'k',\n  '{1}'::int[],\n  arr[1][2],\n  .5\nFROM\n  t"
+        );
+        // `#` is an operator, not a comment
+        assert_eq!(
+            format_sql("select 1 where y ?| array['b'] and z #>> '{a}' = 'q' and w # 1 = 0"),
+            "SELECT\n  1\nWHERE\n  y ?| array['b']\n  AND z #>> '{a}' = 'q'\n  AND w # 1 = 0"
+        );
+        assert_eq!(
+            format_sql("select U&'\\0041', E'a\\'b', B'101', X'1F', N'n'"),
+            "SELECT\n  U&'\\0041',\n  E'a\\'b',\n  B'101',\n  X'1F',\n  N'n'"
+        );
+    }
+
+    /// Queries exercising PostgreSQL syntax, for the invariant test
+    const CORPUS: &[&str] = &[
+        "select u.id, count(*) from users u left join orders o on o.user_id = u.id group by 1",
+        "with recursive t(n) as (select 1 union all select n + 1 from t where n < 5) select * from t",
+        "create function f() returns int as $body$ select 1; -- x\n$body$ language sql",
+        "select $a$ it's $$ nested $$ $a$, $1, $2::int",
+        "select f(g(h(1, 2), (3 + 4) * 5)), (select max(a) from (select a from b) s) as m",
+        "select a from t where b = 'it''s -- not a comment' and c = '/* nor this */'",
+        "-- leading\nselect /* inline */ a, /* multi\nline */ b -- trailing\nfrom t",
+        "select array[1, 2][1], '{1}'::int[], x->'a'->>'b', a @-@ b, a !~* 'y' from t",
+        "select -1, a - -3, 1.5e-3, .5, \"weird \"\" name\", U&'\\0041', E'\\n', B'101' from t",
+        "update t set a = 1, b = $1 where id = :id and c <-> d < 1 and e ?| f",
+        "select pgadmin_fmt_0_, x from t where a=-1",
+    ];
+
+    /// A query without whitespace or case, which is all formatting may change
+    fn squeezed(sql: &str) -> String {
+        sql.split_whitespace().collect::<String>().to_uppercase()
+    }
+
+    #[test]
+    fn test_format_only_changes_whitespace_and_case() {
+        for sql in CORPUS {
+            let formatted = format_sql(sql);
+            assert_eq!(squeezed(&formatted), squeezed(sql), "{}", formatted);
+            assert_eq!(format_sql(&formatted), formatted, "not idempotent: {}", sql);
+        }
+    }
+
+    #[test]
+    fn test_format_degenerate_input() {
+        assert_eq!(format_sql(""), "");
+        assert_eq!(format_sql("  \n "), "");
+        assert_eq!(format_sql("select $"), "SELECT\n  $");
+        // Unterminated bodies and strings run to the end
+        assert_eq!(format_sql("do $$ begin"), "DO $$ begin");
+        assert_eq!(format_sql("select E'abc"), "SELECT\n  E'abc");
+    }
+}
@@ -0,0 +1,409 @@
+/// SQL Lexing Helpers
+///
+/// Just enough tokenizing to tell SQL text apart from string literals, quoted
+/// identifiers, dollar-quoted bodies and comments. Used to rewrite named
+/// placeholders in saved queries, to split editor scripts into statements and
+/// to check expressions spliced into DDL.
+///
+/// Functions work on a `char` slice and return the exclusive end index of the
+/// token that starts at the given position.
+pub fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Whether the `'` at `i` opens an `E'...'` string, which allows backslash escapes
+pub fn is_escape_string(chars: &[char], i: usize) -> bool {
+    i > 0 && matches!(chars[i - 1], 'e' | 'E') && (i < 2 || !is_ident_char(chars[i - 2]))
+}
+
+/// End index (exclusive) of a quoted string or identifier starting at `start`
+pub fn skip_quoted(chars: &[char], start: usize, quote: char, backslash_escapes: bool) -> usize {
+    let mut i = start + 1;
+    while i < chars.len() {
+        if backslash_escapes && chars[i] == '\\' {
+            i += 2;
+        } else if chars[i] == quote {
+            // A doubled quote is an escaped quote
+            if chars.get(i + 1) == Some(&quote) {
+                i += 2;
+            } else {
+                return i + 1;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    chars.len()
+}
+
+/// End index (exclusive) of a `--` comment starting at `start`, before its newline
+pub fn skip_line_comment(chars: &[char], start: usize) -> usize {
+    chars[start..]
+        .iter()
+        .position(|&c| c == '\n')
+        .map_or(chars.len(), |p| start + p)
+}
+
+/// End index (exclusive) of a possibly nested `/* */` comment starting at `start`
+pub fn skip_block_comment(chars: &[char], start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+    while i < chars.len() {
+        if chars[i] == '/' && chars.get(i + 1) == Some(&'*') {
+            depth += 1;
+            i += 2;
+        } else if chars[i] == '*' && chars.get(i + 1) == Some(&'/') {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    chars.len()
+}
+
+/// End index (exclusive) of a `$tag$...$tag$` body starting at `start`, or
+/// just past the `$` if it does not open one
+pub fn skip_dollar_quoted(chars: &[char], start: usize) -> usize {
+    let tag_end = match chars[start + 1..].iter().position(|&c| !is_ident_char(c)) {
+        Some(p) if chars[start + 1 + p] == '$' => start + 1 + p,
+        _ => return start + 1,
+    };
+    let tag = &chars[start..=tag_end];
+    let mut i = tag_end + 1;
+    while i + tag.len() <= chars.len() {
+        if &chars[i..i + tag.len()] == tag {
+            return i + tag.len();
+        }
+        i += 1;
+    }
+    chars.len()
+}
+
+/// Split a script into statements on top-level semicolons
+///
+/// Semicolons inside strings, quoted identifiers, dollar-quoted function
+/// bodies and comments do not end a statement. Statements are trimmed and
+/// returned without their semicolon; empty and comment-only ones are dropped.
+pub fn split_statements(sql: &str) -> Vec<String> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut has_code = false;
+    let mut i = 0;
+
+    let mut finish = |start: usize, end: usize, has_code: bool| {
+        if has_code {
+            let statement: String = chars[start..end].iter().collect();
+            statements.push(statement.trim().to_string());
+        }
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            '-' if next == Some('-') => i = skip_line_comment(&chars, i),
+            '/' if next == Some('*') => i = skip_block_comment(&chars, i),
+            ';' => {
+                finish(start, i, has_code);
+                start = i + 1;
+                has_code = false;
+                i += 1;
+            }
+            c if c.is_whitespace() => i += 1,
+            _ => {
+                has_code = true;
+                i = match c {
+                    '\'' | '"' => {
+                        skip_quoted(&chars, i, c, c == '\'' && is_escape_string(&chars, i))
+                    }
+                    '$' if i == 0 || !is_ident_char(chars[i - 1]) => skip_dollar_quoted(&chars, i),
+                    _ => i + 1,
+                };
+            }
+        }
+    }
+    finish(start, chars.len(), has_code);
+
+    statements
+}
+
+/// Whether `sql` ends with a top-level semicolon, so a script read line by
+/// line has a complete statement
+///
+/// Semicolons inside strings, quoted identifiers, dollar-quoted bodies and
+/// comments don't count, and neither does one followed by more code.
+pub fn is_complete_statement(sql: &str) -> bool {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut complete = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            '-' if next == Some('-') => i = skip_line_comment(&chars, i),
+            '/' if next == Some('*') => {
+                i = skip_block_comment(&chars, i);
+                // An unclosed comment swallows whatever follows
+                if i == chars.len() && !sql.ends_with("*/") {
+                    return false;
+                }
+            }
+            ';' => {
+                complete = true;
+                i += 1;
+            }
+            c if c.is_whitespace() => i += 1,
+            _ => {
+                complete = false;
+                i = match c {
+                    '\'' | '"' => {
+                        skip_quoted(&chars, i, c, c == '\'' && is_escape_string(&chars, i))
+                    }
+                    '$' if i == 0 || !is_ident_char(chars[i - 1]) => skip_dollar_quoted(&chars, i),
+                    _ => i + 1,
+                };
+            }
+        }
+    }
+
+    complete
+}
+
+/// Normalize a statement so executions of the same query group together
+///
+/// String, number and dollar-quoted literals become `?`, comments are dropped
+/// and whitespace runs collapse to one space. Identifiers, keywords and `$n`
+/// parameters are kept as written.
+pub fn fingerprint(sql: &str) -> String {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut out = String::with_capacity(sql.len());
+    let mut space = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let after_ident = i > 0 && is_ident_char(chars[i - 1]);
+        let (end, literal) = match c {
+            '-' if next == Some('-') => {
+                i = skip_line_comment(&chars, i);
+                space = true;
+                continue;
+            }
+            '/' if next == Some('*') => {
+                i = skip_block_comment(&chars, i);
+                space = true;
+                continue;
+            }
+            c if c.is_whitespace() => {
+                i += 1;
+                space = true;
+                continue;
+            }
+            // The `E` of an `E'...'` string belongs to the literal
+            'e' | 'E' if next == Some('\'') && !after_ident => {
+                (skip_quoted(&chars, i + 1, '\'', true), true)
+            }
+            '\'' => (skip_quoted(&chars, i, c, false), true),
+            '"' => (skip_quoted(&chars, i, c, false), false),
+            '$' if next.is_some_and(|n| n.is_ascii_digit()) => (end_of_word(&chars, i + 1), false),
+            '$' if !after_ident => {
+                let end = skip_dollar_quoted(&chars, i);
+                (end, end > i + 1)
+            }
+            c if c.is_ascii_digit() && !after_ident => (end_of_number(&chars, i), true),
+            c if is_ident_char(c) => (end_of_word(&chars, i), false),
+            _ => (i + 1, false),
+        };
+
+        if space && !out.is_empty() {
+            out.push(' ');
+        }
+        space = false;
+        if literal {
+            out.push('?');
+        } else {
+            out.extend(&chars[i..end]);
+        }
+        i = end;
+    }
+
+    out
+}
+
+/// Check that `sql` is one self-contained expression, safe to splice into a
+/// larger statement (an index predicate or key expression)
+///
+/// Rejects semicolons, comments, `$n` parameters, parentheses that do not
+/// balance and strings or quoted identifiers that are never closed, so the
+/// fragment cannot end or comment out the statement around it.
+pub fn validate_expression(sql: &str) -> Result<(), String> {
+    if sql.trim().is_empty() {
+        return Err("Expression cannot be empty".to_string());
+    }
+
+    // With a trailing space, a token that runs to the end was never closed
+    let chars: Vec<char> = format!("{} ", sql).chars().collect();
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        i = match c {
+            ';' => return Err("Expression cannot contain ';'".to_string()),
+            '-' if next == Some('-') => {
+                return Err("Expression cannot contain comments".to_string())
+            }
+            '/' if next == Some('*') => {
+                return Err("Expression cannot contain comments".to_string())
+            }
+            '$' if next.is_some_and(|n| n.is_ascii_digit()) => {
+                return Err("Expression cannot contain parameters".to_string())
+            }
+            '\'' | '"' => {
+                let end = skip_quoted(&chars, i, c, c == '\'' && is_escape_string(&chars, i));
+                if end >= chars.len() {
+                    return Err(format!("Unterminated {} in expression", c));
+                }
+                end
+            }
+            '$' if i == 0 || !is_ident_char(chars[i - 1]) => {
+                let end = skip_dollar_quoted(&chars, i);
+                if end >= chars.len() {
+                    return Err("Unterminated dollar-quoted string in expression".to_string());
+                }
+                end
+            }
+            '(' => {
+                depth += 1;
+                i + 1
+            }
+            ')' => {
+                depth = depth
+                    .checked_sub(1)
+                    .ok_or_else(|| "Unbalanced ')' in expression".to_string())?;
+                i + 1
+            }
+            _ => i + 1,
+        };
+    }
+    if depth > 0 {
+        return Err("Unbalanced '(' in expression".to_string());
+    }
+
+    Ok(())
+}
+
+fn end_of_word(chars: &[char], start: usize) -> usize {
+    chars[start..]
+        .iter()
+        .position(|&c| !is_ident_char(c))
+        .map_or(chars.len(), |p| start + p)
+}
+
+/// End index (exclusive) of a numeric literal such as `42`, `1.5` or `2e-3`
+fn end_of_number(chars: &[char], start: usize) -> usize {
+    let mut i = start;
+    while i < chars.len() {
+        let c = chars[i];
+        let exponent_sign = matches!(c, '+' | '-') && matches!(chars[i - 1], 'e' | 'E');
+        if !(c.is_ascii_alphanumeric() || c == '_' || c == '.' || exponent_sign) {
+            break;
+        }
+        i += 1;
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_statements() {
+        assert_eq!(
+            split_statements("SELECT 1;\n  SELECT 2 ;SELECT 3"),
+            vec!["SELECT 1", "SELECT 2", "SELECT 3"]
+        );
+        assert_eq!(split_statements("SELECT 1;"), vec!["SELECT 1"]);
+        assert!(split_statements("  ;; -- nothing\n/* here */ ;").is_empty());
+    }
+
+    #[test]
+    fn test_split_statements_ignores_quoted_semicolons() {
+        let script = "SELECT 'a;b', \"c;d\", E'it\\'s;';\n\
+                      CREATE FUNCTION f() RETURNS int AS $body$ SELECT 1; $body$ LANGUAGE sql;\n\
+                      SELECT 1 -- trailing; comment\n\
+                      /* block; /* nested; */ */ + 1";
+        let statements = split_statements(script);
+        assert_eq!(statements.len(), 3);
+        assert_eq!(statements[0], "SELECT 'a;b', \"c;d\", E'it\\'s;'");
+        assert!(statements[1].ends_with("$body$ LANGUAGE sql"));
+        assert!(statements[2].starts_with("SELECT 1 -- trailing; comment"));
+    }
+
+    #[test]
+    fn test_skip_dollar_quoted() {
+        let chars: Vec<char> = "$tag$ a $$ b $tag$ rest".chars().collect();
+        assert_eq!(skip_dollar_quoted(&chars, 0), 18);
+        // `$1` is a parameter, not a dollar quote
+        let chars: Vec<char> = "$1".chars().collect();
+        assert_eq!(skip_dollar_quoted(&chars, 0), 1);
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        assert_eq!(
+            fingerprint("SELECT *\n  FROM users\tWHERE id = 42 AND name = 'bob'  -- who\n"),
+            "SELECT * FROM users WHERE id = ? AND name = ?"
+        );
+        assert_eq!(
+            fingerprint("select t1.a, $1, E'it\\'s', $$b$$ /* note */ from t1 limit 1.5e-3"),
+            "select t1.a, $1, ?, ? from t1 limit ?"
+        );
+        // Quoted identifiers are not literals
+        assert_eq!(
+            fingerprint("SELECT \"Col 2\" FROM \"My Table\" WHERE x IN (1, 2)"),
+            "SELECT \"Col 2\" FROM \"My Table\" WHERE x IN (?, ?)"
+        );
+    }
+
+    #[test]
+    fn test_is_complete_statement() {
+        assert!(is_complete_statement("CREATE TABLE t (id int);\n"));
+        assert!(is_complete_statement("SELECT 1; -- done\n"));
+        assert!(!is_complete_statement("SELECT 1"));
+        assert!(!is_complete_statement("SELECT 'a;\n"));
+        assert!(!is_complete_statement(
+            "CREATE FUNCTION f() RETURNS int AS $_$\nSELECT 1;\n"
+        ));
+        assert!(is_complete_statement(
+            "CREATE FUNCTION f() RETURNS int AS $_$\nSELECT 1;\n$_$ LANGUAGE sql;"
+        ));
+        assert!(!is_complete_statement("SELECT 1; SELECT"));
+        assert!(!is_complete_statement("SELECT 1; /* open"));
+    }
+
+    #[test]
+    fn test_validate_expression() {
+        assert!(validate_expression("deleted_at IS NULL AND status <> 'a;b'").is_ok());
+        assert!(validate_expression("lower((email)::text)").is_ok());
+        assert!(validate_expression("note = $$it's$$ OR \"Col)\" > 1").is_ok());
+
+        assert!(validate_expression("  ").is_err());
+        assert!(validate_expression("true); DROP TABLE users").is_err());
+        assert!(validate_expression("a > 1) OR (true").is_err());
+        assert!(validate_expression("lower(email").is_err());
+        assert!(validate_expression("a = 1 -- rest").is_err());
+        assert!(validate_expression("a = 'open").is_err());
+        assert!(validate_expression("a = 'x''").is_err());
+        assert!(validate_expression("a = $tag$ open").is_err());
+        assert!(validate_expression("id = $1").is_err());
+    }
+}
@@ -1,3 +1,5 @@
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 /// Statistics Service
 ///
@@ -6,8 +8,18 @@ use serde::{Deserialize, Serialize};
 /// - Table sizes and row counts
 /// - Index information
 /// - Cache hit ratios
+/// - Checkpoint, background writer and WAL activity
+/// - Long-running and idle-in-transaction sessions
+/// - Deadlock, rollback and conflict counters, sampled over time
+/// - Progress of running VACUUM, CREATE INDEX and CLUSTER commands
+/// - Shared buffer contents per relation (with `pg_buffercache`)
+/// - Temporary file usage per database and per query (with
+///   `pg_stat_statements`)
 /// - Slow queries
 use sqlx::PgPool;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DatabaseStats {
@@ -45,6 +57,304 @@ pub struct CacheStats {
     pub idx_blks_hit: i64,
 }
 
+/// Checkpoint and background writer activity since the last stats reset
+///
+/// PostgreSQL 17 moved the checkpoint counters from `pg_stat_bgwriter` to
+/// `pg_stat_checkpointer` and dropped `buffers_backend`.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct CheckpointStats {
+    /// Checkpoints started by `checkpoint_timeout`
+    pub checkpoints_timed: i64,
+    /// Checkpoints forced by WAL volume (`max_wal_size`) or on request
+    pub checkpoints_requested: i64,
+    pub checkpoint_write_time_ms: f64,
+    pub checkpoint_sync_time_ms: f64,
+    /// Buffers written by checkpoints
+    pub buffers_checkpoint: i64,
+    /// Buffers written by the background writer
+    pub buffers_clean: i64,
+    /// Times the background writer stopped at `bgwriter_lru_maxpages`
+    pub maxwritten_clean: i64,
+    /// Buffers written directly by backends (before PostgreSQL 17)
+    pub buffers_backend: Option<i64>,
+    pub buffers_alloc: i64,
+    pub stats_reset: Option<String>,
+}
+
+/// WAL generation since the last stats reset (PostgreSQL 14+)
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct WalStats {
+    pub wal_records: i64,
+    /// Full page images
+    pub wal_fpi: i64,
+    pub wal_bytes: i64,
+    pub wal_size: String,
+    /// Times WAL buffers were full and had to be written out
+    pub wal_buffers_full: i64,
+}
+
+/// A session with a transaction open for a long time, or sitting idle
+/// inside one; both hold back vacuum and may hold locks
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct LongTransaction {
+    pub pid: i32,
+    pub username: Option<String>,
+    pub database_name: Option<String>,
+    pub application_name: Option<String>,
+    pub client_addr: Option<String>,
+    /// `active`, `idle in transaction`, `idle in transaction (aborted)`...
+    pub state: Option<String>,
+    pub xact_age_secs: f64,
+    /// Transaction age as `HH:MM:SS`
+    pub xact_age: String,
+    /// Time since the last state change, i.e. how long it has been idle
+    pub state_age_secs: f64,
+    pub wait_event_type: Option<String>,
+    /// The current or last statement, truncated
+    pub query: String,
+}
+
+impl LongTransaction {
+    pub fn is_idle_in_transaction(&self) -> bool {
+        self.state
+            .as_deref()
+            .is_some_and(|state| state.starts_with("idle in transaction"))
+    }
+}
+
+/// Transactions open longer than this are reported by default
+pub const DEFAULT_LONG_TRANSACTION_SECS: u64 = 300;
+
+/// Overall shared buffer usage from `pg_buffercache`
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct BufferCacheSummary {
+    pub total_buffers: i64,
+    pub used_buffers: i64,
+    pub dirty_buffers: i64,
+    /// Size of `shared_buffers`
+    pub total_size: String,
+}
+
+/// Shared buffers held by one relation of the current database
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct BufferCacheRelation {
+    pub schema_name: String,
+    pub relation_name: String,
+    /// `pg_class.relkind`: `r` table, `i` index, `t` TOAST...
+    pub relation_kind: String,
+    pub buffers: i64,
+    pub buffered_size: String,
+    pub dirty_buffers: i64,
+    /// Share of all shared buffers, as a percentage
+    pub percent_of_cache: f64,
+    /// Share of the relation that is cached, as a percentage
+    pub percent_of_relation: Option<f64>,
+}
+
+impl BufferCacheRelation {
+    /// Share of this relation's buffers that are dirty, as a percentage
+    pub fn percent_dirty(&self) -> f64 {
+        if self.buffers == 0 {
+            return 0.0;
+        }
+        (self.dirty_buffers as f64 / self.buffers as f64) * 100.0
+    }
+}
+
+/// Temporary files written by one database since the last stats reset
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct DatabaseTempUsage {
+    pub database_name: String,
+    pub temp_files: i64,
+    pub temp_bytes: i64,
+    pub temp_size: String,
+}
+
+/// A statement from `pg_stat_statements` that spilled to temporary files
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct QueryTempUsage {
+    pub query: String,
+    pub calls: i64,
+    pub temp_blks_written: i64,
+    pub temp_size: String,
+    pub avg_temp_bytes_per_call: i64,
+}
+
+/// Spills up to this many times `work_mem` would likely fit in memory with
+/// a larger `work_mem`; bigger ones need the query or its indexes fixed
+const WORK_MEM_STARVED_FACTOR: i64 = 4;
+
+impl QueryTempUsage {
+    /// The statement spills by an amount a larger `work_mem` would absorb
+    pub fn work_mem_starved(&self, work_mem_bytes: i64) -> bool {
+        self.avg_temp_bytes_per_call > 0
+            && self.avg_temp_bytes_per_call <= work_mem_bytes * WORK_MEM_STARVED_FACTOR
+    }
+}
+
+/// Temporary file usage of the server and the current database's statements
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TempUsageReport {
+    pub work_mem: String,
+    pub work_mem_bytes: i64,
+    pub databases: Vec<DatabaseTempUsage>,
+    /// `None` when `pg_stat_statements` is not installed
+    pub queries: Option<Vec<QueryTempUsage>>,
+}
+
+impl TempUsageReport {
+    /// Statements whose spills a larger `work_mem` would likely avoid
+    pub fn starved_query_count(&self) -> usize {
+        self.queries.as_ref().map_or(0, |queries| {
+            queries
+                .iter()
+                .filter(|q| q.work_mem_starved(self.work_mem_bytes))
+                .count()
+        })
+    }
+}
+
+/// A maintenance command in progress, from the `pg_stat_progress_*` views
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct OperationProgress {
+    pub pid: i32,
+    pub database_name: String,
+    /// `VACUUM`, `CREATE INDEX`, `REINDEX`, `CLUSTER`, `VACUUM FULL`...
+    pub command: String,
+    pub relation: Option<String>,
+    pub phase: String,
+    /// Work done and total in the current phase's unit (blocks or tuples)
+    pub work_done: i64,
+    pub work_total: i64,
+    pub elapsed_secs: Option<f64>,
+}
+
+impl OperationProgress {
+    /// Share of the current phase done, as a percentage, when it is known
+    pub fn percent(&self) -> Option<f64> {
+        (self.work_total > 0)
+            .then(|| (self.work_done as f64 / self.work_total as f64 * 100.0).min(100.0))
+    }
+}
+
+/// Cumulative error counters of one database from `pg_stat_database`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatabaseCounters {
+    pub database_name: String,
+    pub xact_commit: i64,
+    pub xact_rollback: i64,
+    pub deadlocks: i64,
+    /// Queries cancelled by recovery conflicts (standbys only)
+    pub conflicts: i64,
+    pub sampled_at: DateTime<Utc>,
+}
+
+/// How a database's counters moved over the sampled window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CounterTrend {
+    pub database_name: String,
+    pub current: DatabaseCounters,
+    pub window_secs: i64,
+    pub new_deadlocks: i64,
+    pub new_rollbacks: i64,
+    pub new_conflicts: i64,
+    /// Deadlocks happened within the window
+    pub deadlocks_rising: bool,
+    /// The samples, oldest first
+    pub samples: Vec<DatabaseCounters>,
+}
+
+/// How often `CounterHistory` samples `pg_stat_database`
+pub const COUNTER_SAMPLE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Samples kept per database (an hour at the default interval)
+const COUNTER_HISTORY_SAMPLES: usize = 60;
+
+/// Recent samples of per-database error counters, for one connection
+#[derive(Default)]
+pub struct CounterHistory {
+    samples: Mutex<HashMap<String, VecDeque<DatabaseCounters>>>,
+}
+
+impl CounterHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a sample; databases missing from it are forgotten
+    pub fn record(&self, counters: Vec<DatabaseCounters>) {
+        let mut samples = self.samples.lock();
+        samples.retain(|name, _| counters.iter().any(|c| &c.database_name == name));
+        for sample in counters {
+            let history = samples.entry(sample.database_name.clone()).or_default();
+            if history.len() == COUNTER_HISTORY_SAMPLES {
+                history.pop_front();
+            }
+            history.push_back(sample);
+        }
+    }
+
+    /// Per-database change across the sampled window, by database name
+    pub fn trends(&self) -> Vec<CounterTrend> {
+        let samples = self.samples.lock();
+        let mut trends: Vec<CounterTrend> = samples
+            .iter()
+            .filter_map(|(name, history)| {
+                let first = history.front()?;
+                let current = history.back()?;
+                // Sum the increase between consecutive samples; a counter
+                // lower than before means the stats were reset in between
+                let increase = |counter: fn(&DatabaseCounters) -> i64| -> i64 {
+                    history
+                        .iter()
+                        .zip(history.iter().skip(1))
+                        .map(|(before, after)| {
+                            let (from, to) = (counter(before), counter(after));
+                            if to >= from {
+                                to - from
+                            } else {
+                                to
+                            }
+                        })
+                        .sum()
+                };
+                let new_deadlocks = increase(|c| c.deadlocks);
+                Some(CounterTrend {
+                    database_name: name.clone(),
+                    current: current.clone(),
+                    window_secs: (current.sampled_at - first.sampled_at).num_seconds(),
+                    new_deadlocks,
+                    new_rollbacks: increase(|c| c.xact_rollback),
+                    new_conflicts: increase(|c| c.conflicts),
+                    deadlocks_rising: new_deadlocks > 0,
+                    samples: history.iter().cloned().collect(),
+                })
+            })
+            .collect();
+        trends.sort_by(|a, b| a.database_name.cmp(&b.database_name));
+        trends
+    }
+
+    /// Sample `pool` every `COUNTER_SAMPLE_INTERVAL`, skipping connections
+    /// that have never been used so idle profiles are not dialled
+    pub fn spawn_sampler(self: &Arc<Self>, pool: Arc<PgPool>) {
+        let history = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(COUNTER_SAMPLE_INTERVAL);
+            loop {
+                interval.tick().await;
+                if pool.size() == 0 {
+                    continue;
+                }
+                match StatsService::database_counters(&pool).await {
+                    Ok(counters) => history.record(counters),
+                    Err(e) => tracing::debug!("{}", e),
+                }
+            }
+        });
+    }
+}
+
 pub struct StatsService;
 
 impl StatsService {
@@ -167,6 +477,340 @@ impl StatsService {
         })
     }
 
+    /// Get checkpoint and background writer statistics
+    pub async fn checkpoint_stats(pool: &PgPool) -> Result<CheckpointStats, String> {
+        let query = if Self::server_version_num(pool).await? >= 170000 {
+            r#"
+            SELECT
+                c.num_timed as checkpoints_timed,
+                c.num_requested as checkpoints_requested,
+                c.write_time as checkpoint_write_time_ms,
+                c.sync_time as checkpoint_sync_time_ms,
+                c.buffers_written as buffers_checkpoint,
+                b.buffers_clean,
+                b.maxwritten_clean,
+                NULL::bigint as buffers_backend,
+                b.buffers_alloc,
+                c.stats_reset::text as stats_reset
+            FROM pg_stat_checkpointer c, pg_stat_bgwriter b
+            "#
+        } else {
+            r#"
+            SELECT
+                checkpoints_timed,
+                checkpoints_req as checkpoints_requested,
+                checkpoint_write_time as checkpoint_write_time_ms,
+                checkpoint_sync_time as checkpoint_sync_time_ms,
+                buffers_checkpoint,
+                buffers_clean,
+                maxwritten_clean,
+                buffers_backend,
+                buffers_alloc,
+                stats_reset::text as stats_reset
+            FROM pg_stat_bgwriter
+            "#
+        };
+
+        sqlx::query_as::<_, CheckpointStats>(query)
+            .fetch_one(pool)
+            .await
+            .map_err(|e| format!("Failed to get checkpoint stats: {}", e))
+    }
+
+    /// Get WAL statistics, or `None` before PostgreSQL 14 (no `pg_stat_wal`)
+    pub async fn wal_stats(pool: &PgPool) -> Result<Option<WalStats>, String> {
+        if Self::server_version_num(pool).await? < 140000 {
+            return Ok(None);
+        }
+
+        let query = r#"
+            SELECT
+                wal_records,
+                wal_fpi,
+                wal_bytes::bigint as wal_bytes,
+                pg_size_pretty(wal_bytes) as wal_size,
+                wal_buffers_full
+            FROM pg_stat_wal
+        "#;
+
+        sqlx::query_as::<_, WalStats>(query)
+            .fetch_one(pool)
+            .await
+            .map(Some)
+            .map_err(|e| format!("Failed to get WAL stats: {}", e))
+    }
+
+    async fn server_version_num(pool: &PgPool) -> Result<i32, String> {
+        sqlx::query_scalar("SELECT current_setting('server_version_num')::int")
+            .fetch_one(pool)
+            .await
+            .map_err(|e| format!("Failed to get server version: {}", e))
+    }
+
+    /// Get transactions open for at least `min_age_secs`, plus every session
+    /// that is idle in a transaction, oldest first
+    pub async fn long_transactions(
+        pool: &PgPool,
+        min_age_secs: u64,
+    ) -> Result<Vec<LongTransaction>, String> {
+        let query = r#"
+            SELECT
+                pid,
+                usename::text as username,
+                datname::text as database_name,
+                application_name,
+                client_addr::text as client_addr,
+                state,
+                EXTRACT(EPOCH FROM now() - xact_start)::float8 as xact_age_secs,
+                date_trunc('second', now() - xact_start)::text as xact_age,
+                EXTRACT(EPOCH FROM now() - state_change)::float8 as state_age_secs,
+                wait_event_type,
+                left(query, 500) as query
+            FROM pg_stat_activity
+            WHERE xact_start IS NOT NULL
+              AND pid <> pg_backend_pid()
+              AND backend_type = 'client backend'
+              AND (now() - xact_start >= make_interval(secs => $1)
+                   OR state LIKE 'idle in transaction%')
+            ORDER BY xact_start
+            LIMIT 100
+        "#;
+
+        sqlx::query_as::<_, LongTransaction>(query)
+            .bind(min_age_secs as f64)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| format!("Failed to get long-running transactions: {}", e))
+    }
+
+    /// Whether the `pg_buffercache` extension is installed in this database
+    pub async fn buffercache_available(pool: &PgPool) -> Result<bool, String> {
+        sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'pg_buffercache')",
+        )
+        .fetch_one(pool)
+        .await
+        .map_err(|e| format!("Failed to check for pg_buffercache: {}", e))
+    }
+
+    /// Get overall shared buffer usage (requires `pg_buffercache`)
+    pub async fn buffercache_summary(pool: &PgPool) -> Result<BufferCacheSummary, String> {
+        let query = r#"
+            SELECT
+                count(*) as total_buffers,
+                count(relfilenode) as used_buffers,
+                count(*) FILTER (WHERE isdirty) as dirty_buffers,
+                pg_size_pretty(count(*) * current_setting('block_size')::bigint) as total_size
+            FROM pg_buffercache
+        "#;
+
+        sqlx::query_as::<_, BufferCacheSummary>(query)
+            .fetch_one(pool)
+            .await
+            .map_err(|e| format!("Failed to read pg_buffercache: {}", e))
+    }
+
+    /// Get the relations of the current database holding the most shared
+    /// buffers (requires `pg_buffercache`)
+    pub async fn buffercache_relations(
+        pool: &PgPool,
+        limit: i64,
+    ) -> Result<Vec<BufferCacheRelation>, String> {
+        let query = r#"
+            SELECT
+                n.nspname::text as schema_name,
+                c.relname::text as relation_name,
+                c.relkind::text as relation_kind,
+                count(*) as buffers,
+                pg_size_pretty(count(*) * current_setting('block_size')::bigint) as buffered_size,
+                count(*) FILTER (WHERE b.isdirty) as dirty_buffers,
+                (100.0 * count(*) / (SELECT setting::bigint FROM pg_settings
+                                     WHERE name = 'shared_buffers'))::float8 as percent_of_cache,
+                -- Free space and visibility map buffers can push this past 100
+                LEAST(100.0 * count(*) * current_setting('block_size')::bigint
+                    / NULLIF(pg_relation_size(c.oid), 0), 100)::float8 as percent_of_relation
+            FROM pg_buffercache b
+            JOIN pg_class c ON b.relfilenode = pg_relation_filenode(c.oid)
+            JOIN pg_namespace n ON n.oid = c.relnamespace
+            WHERE b.reldatabase IN (0, (SELECT oid FROM pg_database
+                                        WHERE datname = current_database()))
+            GROUP BY c.oid, n.nspname, c.relname, c.relkind
+            ORDER BY buffers DESC
+            LIMIT $1
+        "#;
+
+        sqlx::query_as::<_, BufferCacheRelation>(query)
+            .bind(limit)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| format!("Failed to read pg_buffercache: {}", e))
+    }
+
+    /// Get temporary file usage per database and, with `pg_stat_statements`,
+    /// the statements of the current database that spill the most
+    pub async fn temp_usage(pool: &PgPool) -> Result<TempUsageReport, String> {
+        let (work_mem, work_mem_bytes) = sqlx::query_as::<_, (String, i64)>(
+            "SELECT current_setting('work_mem'), pg_size_bytes(current_setting('work_mem'))",
+        )
+        .fetch_one(pool)
+        .await
+        .map_err(|e| format!("Failed to read work_mem: {}", e))?;
+
+        let databases = sqlx::query_as::<_, DatabaseTempUsage>(
+            r#"
+            SELECT
+                datname::text as database_name,
+                temp_files,
+                temp_bytes,
+                pg_size_pretty(temp_bytes) as temp_size
+            FROM pg_stat_database
+            WHERE datname IS NOT NULL
+            ORDER BY temp_bytes DESC
+            "#,
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to get temporary file stats: {}", e))?;
+
+        let has_statements: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'pg_stat_statements')",
+        )
+        .fetch_one(pool)
+        .await
+        .map_err(|e| format!("Failed to check for pg_stat_statements: {}", e))?;
+
+        let queries = if has_statements {
+            let block_size = "current_setting('block_size')::bigint";
+            let query = format!(
+                r#"
+                SELECT
+                    left(s.query, 500) as query,
+                    s.calls,
+                    s.temp_blks_written,
+                    pg_size_pretty(s.temp_blks_written * {block_size}) as temp_size,
+                    (s.temp_blks_written * {block_size} / GREATEST(s.calls, 1))::bigint
+                        as avg_temp_bytes_per_call
+                FROM pg_stat_statements s
+                WHERE s.dbid = (SELECT oid FROM pg_database WHERE datname = current_database())
+                  AND s.temp_blks_written > 0
+                ORDER BY s.temp_blks_written DESC
+                LIMIT 20
+                "#
+            );
+            Some(
+                sqlx::query_as::<_, QueryTempUsage>(&query)
+                    .fetch_all(pool)
+                    .await
+                    .map_err(|e| format!("Failed to read pg_stat_statements: {}", e))?,
+            )
+        } else {
+            None
+        };
+
+        Ok(TempUsageReport {
+            work_mem,
+            work_mem_bytes,
+            databases,
+            queries,
+        })
+    }
+
+    /// Get running VACUUM, CREATE INDEX / REINDEX and CLUSTER / VACUUM FULL
+    /// commands with their progress
+    pub async fn operation_progress(pool: &PgPool) -> Result<Vec<OperationProgress>, String> {
+        let mut parts = vec![
+            r#"
+            SELECT
+                p.pid, p.datname::text as database_name, 'VACUUM' as command,
+                p.relid::regclass::text as relation, p.phase,
+                CASE WHEN p.phase = 'vacuuming heap' THEN p.heap_blks_vacuumed
+                     ELSE p.heap_blks_scanned END as work_done,
+                p.heap_blks_total as work_total,
+                a.query_start
+            FROM pg_stat_progress_vacuum p
+            LEFT JOIN pg_stat_activity a ON a.pid = p.pid
+            "#,
+        ];
+        // The CREATE INDEX and CLUSTER views were added in PostgreSQL 12
+        if Self::server_version_num(pool).await? >= 120000 {
+            parts.push(
+                r#"
+                SELECT
+                    p.pid, p.datname::text, p.command, p.relid::regclass::text, p.phase,
+                    CASE WHEN p.blocks_total > 0 THEN p.blocks_done ELSE p.tuples_done END,
+                    CASE WHEN p.blocks_total > 0 THEN p.blocks_total ELSE p.tuples_total END,
+                    a.query_start
+                FROM pg_stat_progress_create_index p
+                LEFT JOIN pg_stat_activity a ON a.pid = p.pid
+                "#,
+            );
+            parts.push(
+                r#"
+                SELECT
+                    p.pid, p.datname::text, p.command, p.relid::regclass::text, p.phase,
+                    p.heap_blks_scanned, p.heap_blks_total,
+                    a.query_start
+                FROM pg_stat_progress_cluster p
+                LEFT JOIN pg_stat_activity a ON a.pid = p.pid
+                "#,
+            );
+        }
+        let query = format!(
+            "SELECT pid, database_name, command, relation, phase, work_done, work_total, \
+             EXTRACT(EPOCH FROM now() - query_start)::float8 as elapsed_secs \
+             FROM ({}) progress ORDER BY query_start NULLS LAST, pid",
+            parts.join(" UNION ALL ")
+        );
+
+        sqlx::query_as::<_, OperationProgress>(&query)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| format!("Failed to get operation progress: {}", e))
+    }
+
+    /// Get deadlock, rollback and conflict counters for every database
+    pub async fn database_counters(pool: &PgPool) -> Result<Vec<DatabaseCounters>, String> {
+        let query = r#"
+            SELECT
+                datname::text,
+                xact_commit,
+                xact_rollback,
+                deadlocks,
+                conflicts
+            FROM pg_stat_database
+            WHERE datname IS NOT NULL
+            ORDER BY datname
+        "#;
+
+        let rows = sqlx::query_as::<_, (String, i64, i64, i64, i64)>(query)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| format!("Failed to get database counters: {}", e))?;
+
+        let sampled_at = Utc::now();
+        Ok(rows
+            .into_iter()
+            .map(|row| DatabaseCounters {
+                database_name: row.0,
+                xact_commit: row.1,
+                xact_rollback: row.2,
+                deadlocks: row.3,
+                conflicts: row.4,
+                sampled_at,
+            })
+            .collect())
+    }
+
+    /// Share of checkpoints that were requested rather than timed, as a
+    /// percentage; a high share usually means `max_wal_size` is too small
+    pub fn requested_checkpoint_ratio(stats: &CheckpointStats) -> f64 {
+        let total = stats.checkpoints_timed + stats.checkpoints_requested;
+        if total == 0 {
+            return 0.0;
+        }
+        (stats.checkpoints_requested as f64 / total as f64) * 100.0
+    }
+
     /// Calculate cache hit ratio as percentage
     pub fn cache_hit_ratio(stats: &CacheStats) -> f64 {
         let total_heap = stats.heap_blks_read + stats.heap_blks_hit;
@@ -226,4 +870,162 @@ mod tests {
 
         assert_eq!(StatsService::cache_hit_ratio(&stats), 0.0);
     }
+
+    fn checkpoint_stats(timed: i64, requested: i64) -> CheckpointStats {
+        CheckpointStats {
+            checkpoints_timed: timed,
+            checkpoints_requested: requested,
+            checkpoint_write_time_ms: 0.0,
+            checkpoint_sync_time_ms: 0.0,
+            buffers_checkpoint: 0,
+            buffers_clean: 0,
+            maxwritten_clean: 0,
+            buffers_backend: None,
+            buffers_alloc: 0,
+            stats_reset: None,
+        }
+    }
+
+    fn counters(name: &str, minute: i64, deadlocks: i64, rollbacks: i64) -> DatabaseCounters {
+        DatabaseCounters {
+            database_name: name.to_string(),
+            xact_commit: 0,
+            xact_rollback: rollbacks,
+            deadlocks,
+            conflicts: 0,
+            sampled_at: DateTime::from_timestamp(minute * 60, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_counter_trends() {
+        let history = CounterHistory::new();
+        history.record(vec![counters("app", 0, 2, 10), counters("other", 0, 0, 0)]);
+        history.record(vec![counters("app", 1, 3, 15), counters("other", 1, 0, 0)]);
+        history.record(vec![counters("app", 2, 5, 12), counters("other", 2, 0, 1)]);
+
+        let trends = history.trends();
+        assert_eq!(trends.len(), 2);
+        let app = &trends[0];
+        assert_eq!(app.database_name, "app");
+        assert_eq!(app.window_secs, 120);
+        assert_eq!(app.new_deadlocks, 3);
+        assert!(app.deadlocks_rising);
+        // Rollbacks dropped in the last sample: a reset, counted from zero
+        assert_eq!(app.new_rollbacks, 5 + 12);
+        assert_eq!(app.samples.len(), 3);
+        assert!(!trends[1].deadlocks_rising);
+
+        // Dropped databases are forgotten
+        history.record(vec![counters("app", 3, 5, 12)]);
+        assert_eq!(history.trends().len(), 1);
+    }
+
+    #[test]
+    fn test_counter_history_is_bounded() {
+        let history = CounterHistory::new();
+        for minute in 0..(COUNTER_HISTORY_SAMPLES as i64 + 10) {
+            history.record(vec![counters("app", minute, minute, 0)]);
+        }
+        let trends = history.trends();
+        assert_eq!(trends[0].samples.len(), COUNTER_HISTORY_SAMPLES);
+        assert_eq!(trends[0].new_deadlocks, COUNTER_HISTORY_SAMPLES as i64 - 1);
+    }
+
+    #[test]
+    fn test_buffer_cache_percent_dirty() {
+        let mut relation = BufferCacheRelation {
+            schema_name: "public".to_string(),
+            relation_name: "orders".to_string(),
+            relation_kind: "r".to_string(),
+            buffers: 200,
+            buffered_size: "1600 kB".to_string(),
+            dirty_buffers: 50,
+            percent_of_cache: 1.2,
+            percent_of_relation: Some(80.0),
+        };
+        assert_eq!(relation.percent_dirty(), 25.0);
+
+        relation.buffers = 0;
+        relation.dirty_buffers = 0;
+        assert_eq!(relation.percent_dirty(), 0.0);
+    }
+
+    #[test]
+    fn test_work_mem_starved() {
+        let work_mem = 4 * 1024 * 1024;
+        let query = |avg_temp_bytes_per_call| QueryTempUsage {
+            query: "SELECT 1".to_string(),
+            calls: 10,
+            temp_blks_written: 100,
+            temp_size: "800 kB".to_string(),
+            avg_temp_bytes_per_call,
+        };
+        assert!(query(1024).work_mem_starved(work_mem));
+        assert!(query(4 * work_mem).work_mem_starved(work_mem));
+        assert!(!query(4 * work_mem + 1).work_mem_starved(work_mem));
+        assert!(!query(0).work_mem_starved(work_mem));
+
+        let report = TempUsageReport {
+            work_mem: "4MB".to_string(),
+            work_mem_bytes: work_mem,
+            databases: Vec::new(),
+            queries: Some(vec![query(1024), query(100 * work_mem)]),
+        };
+        assert_eq!(report.starved_query_count(), 1);
+    }
+
+    #[test]
+    fn test_operation_progress_percent() {
+        let mut progress = OperationProgress {
+            pid: 7,
+            database_name: "app".to_string(),
+            command: "VACUUM".to_string(),
+            relation: Some("public.orders".to_string()),
+            phase: "scanning heap".to_string(),
+            work_done: 250,
+            work_total: 1000,
+            elapsed_secs: Some(3.0),
+        };
+        assert_eq!(progress.percent(), Some(25.0));
+
+        // Unknown totals (e.g. while waiting for locks) have no percentage
+        progress.work_total = 0;
+        assert_eq!(progress.percent(), None);
+    }
+
+    #[test]
+    fn test_idle_in_transaction() {
+        let mut transaction = LongTransaction {
+            pid: 42,
+            username: None,
+            database_name: None,
+            application_name: None,
+            client_addr: None,
+            state: Some("idle in transaction (aborted)".to_string()),
+            xact_age_secs: 0.0,
+            xact_age: "00:00:00".to_string(),
+            state_age_secs: 0.0,
+            wait_event_type: None,
+            query: String::new(),
+        };
+        assert!(transaction.is_idle_in_transaction());
+
+        transaction.state = Some("active".to_string());
+        assert!(!transaction.is_idle_in_transaction());
+        transaction.state = None;
+        assert!(!transaction.is_idle_in_transaction());
+    }
+
+    #[test]
+    fn test_requested_checkpoint_ratio() {
+        assert_eq!(
+            StatsService::requested_checkpoint_ratio(&checkpoint_stats(75, 25)),
+            25.0
+        );
+        assert_eq!(
+            StatsService::requested_checkpoint_ratio(&checkpoint_stats(0, 0)),
+            0.0
+        );
+    }
 }
@@ -0,0 +1,182 @@
+use crate::services::identifier::{quote_ident, resolve_relation};
+use crate::services::pg_value::cell_to_json;
+use serde::Serialize;
+/// Table Tail
+///
+/// Follows a table as rows arrive by polling on an ordering column: each
+/// poll returns the rows whose value is past the highest one already sent.
+/// With an increasing id this picks up inserts; with an `updated_at` column
+/// that is set on every write it also picks up changed rows.
+use sqlx::{Column, PgPool, Row};
+
+/// Most rows sent in one batch; the rest follow on the next poll
+pub const MAX_BATCH_ROWS: i64 = 500;
+
+/// Alias of the ordering value read back as text to move the cursor
+const CURSOR_ALIAS: &str = "__tail_cursor";
+
+/// New rows of a followed table
+#[derive(Debug, Default, Serialize)]
+pub struct TailBatch {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<serde_json::Value>>,
+}
+
+/// Position in a followed table
+#[derive(Debug, Clone)]
+pub struct TailCursor {
+    relation: String,
+    column: String,
+    column_type: String,
+    /// Highest ordering value sent so far, as text
+    last: Option<String>,
+}
+
+impl TailCursor {
+    /// Start following `schema.table` ordered by `column`
+    ///
+    /// The cursor starts at the current highest value, so only rows added
+    /// afterwards are returned; the last `backlog` rows up to it come back
+    /// as the first batch.
+    pub async fn open(
+        pool: &PgPool,
+        schema: &str,
+        table: &str,
+        column: &str,
+        backlog: i64,
+    ) -> Result<(Self, TailBatch), String> {
+        let relation = resolve_relation(pool, schema, table)
+            .await
+            .map_err(|_| format!("Table {}.{} not found", schema, table))?;
+        let column_type: Option<String> = sqlx::query_scalar(
+            "SELECT format_type(a.atttypid, a.atttypmod) \
+             FROM pg_catalog.pg_attribute a \
+             WHERE a.attrelid = $1::regclass AND a.attname = $2 \
+               AND a.attnum > 0 AND NOT a.attisdropped",
+        )
+        .bind(&relation)
+        .bind(column)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+        let column_type =
+            column_type.ok_or_else(|| format!("Column {} not found on {}", column, relation))?;
+
+        let mut cursor = TailCursor {
+            relation,
+            column: quote_ident(column),
+            column_type,
+            last: None,
+        };
+        if backlog <= 0 {
+            cursor.last = sqlx::query_scalar(&format!(
+                "SELECT max({})::text FROM {}",
+                cursor.column, cursor.relation
+            ))
+            .fetch_one(pool)
+            .await
+            .map_err(|e| e.to_string())?;
+            return Ok((cursor, TailBatch::default()));
+        }
+
+        let rows = sqlx::query(&cursor.backlog_sql())
+            .bind(backlog.min(MAX_BATCH_ROWS))
+            .fetch_all(pool)
+            .await
+            .map_err(|e| e.to_string())?;
+        let batch = cursor.take(&rows);
+        Ok((cursor, batch))
+    }
+
+    /// Rows added since the last call
+    pub async fn poll(&mut self, pool: &PgPool) -> Result<TailBatch, String> {
+        let rows = sqlx::query(&self.poll_sql())
+            .bind(self.last.as_deref())
+            .bind(MAX_BATCH_ROWS)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(self.take(&rows))
+    }
+
+    /// The last `$1` rows up to the current highest value, oldest first
+    fn backlog_sql(&self) -> String {
+        format!(
+            "SELECT s.* FROM (SELECT t.*, t.{col}::text AS {alias} FROM {rel} t \
+             ORDER BY t.{col} DESC LIMIT $1) s ORDER BY s.{col}",
+            col = self.column,
+            alias = CURSOR_ALIAS,
+            rel = self.relation,
+        )
+    }
+
+    /// Rows past `$1` (all rows while the table was empty), up to `$2` of them
+    fn poll_sql(&self) -> String {
+        format!(
+            "SELECT t.*, t.{col}::text AS {alias} FROM {rel} t \
+             WHERE $1::text IS NULL OR t.{col} > $1::text::{ty} \
+             ORDER BY t.{col} LIMIT $2",
+            col = self.column,
+            alias = CURSOR_ALIAS,
+            rel = self.relation,
+            ty = self.column_type,
+        )
+    }
+
+    /// Convert fetched rows, dropping the cursor column and advancing past them
+    fn take(&mut self, rows: &[sqlx::postgres::PgRow]) -> TailBatch {
+        let Some(first) = rows.first() else {
+            return TailBatch::default();
+        };
+        let width = first.columns().len() - 1;
+        let columns = first.columns()[..width]
+            .iter()
+            .map(|c| c.name().to_string())
+            .collect();
+        let values = rows
+            .iter()
+            .map(|row| (0..width).map(|i| cell_to_json(row, i)).collect())
+            .collect();
+        if let Some(last) = rows
+            .last()
+            .and_then(|row| row.try_get::<Option<String>, _>(width).ok().flatten())
+        {
+            self.last = Some(last);
+        }
+        TailBatch {
+            columns,
+            rows: values,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cursor() -> TailCursor {
+        TailCursor {
+            relation: "public.\"Events\"".to_string(),
+            column: quote_ident("created_at"),
+            column_type: "timestamp with time zone".to_string(),
+            last: None,
+        }
+    }
+
+    #[test]
+    fn test_poll_sql() {
+        assert_eq!(
+            cursor().poll_sql(),
+            "SELECT t.*, t.created_at::text AS __tail_cursor FROM public.\"Events\" t \
+             WHERE $1::text IS NULL OR t.created_at > $1::text::timestamp with time zone \
+             ORDER BY t.created_at LIMIT $2"
+        );
+    }
+
+    #[test]
+    fn test_backlog_sql() {
+        let sql = cursor().backlog_sql();
+        assert!(sql.contains("ORDER BY t.created_at DESC LIMIT $1"));
+        assert!(sql.ends_with(") s ORDER BY s.created_at"));
+    }
+}
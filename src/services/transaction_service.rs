@@ -0,0 +1,520 @@
+use crate::config::reload::ReadOnlyMode;
+use crate::models::QueryResult;
+use crate::services::query_service::{self, QueryParam, RowLimitExceeded, StatementKind};
+use crate::services::sql_lexer::split_statements;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::pool::PoolConnection;
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+/// Transaction Service
+///
+/// Query sessions that keep one connection checked out inside an open
+/// transaction, so a data fix can be applied statement by statement,
+/// inspected, and then committed or rolled back:
+/// - `begin` takes a connection from the pool and issues `BEGIN`
+///   (`BEGIN READ ONLY` in read-only mode)
+/// - Statements run on that connection until `commit` or `rollback`, which
+///   close it rather than return it to the pool
+/// - Settings may only change with `SET LOCAL`, and never the transaction's
+///   characteristics
+/// - Sessions idle longer than the timeout are rolled back by a background task
+/// - Only a few sessions may be open at once so the pool is never drained
+/// - Each session belongs to the owner that began it; other owners neither
+///   see it nor can run statements in it, commit it or roll it back
+///
+/// Data changes are allowed inside a session because nothing takes effect
+/// until an explicit commit. Schema and permission changes still go through
+/// their own APIs.
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, RwLock};
+
+/// Open sessions allowed at once (each holds a pool connection)
+pub const MAX_OPEN_TRANSACTIONS: usize = 2;
+
+/// How often the background task looks for idle sessions
+const REAP_INTERVAL: Duration = Duration::from_secs(15);
+
+/// An open transaction as reported by the API
+#[derive(Debug, Clone, Serialize)]
+pub struct TransactionInfo {
+    pub id: String,
+    pub client_ip: String,
+    pub read_only: bool,
+    pub started_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+    /// Statements run so far
+    pub statements: usize,
+    /// An earlier statement failed, so the transaction can only be rolled back
+    pub aborted: bool,
+}
+
+struct Session {
+    info: TransactionInfo,
+    /// Taken when the session ends; a statement waiting on the lock then finds it closed
+    conn: Option<PoolConnection<Postgres>>,
+    last_used: Instant,
+}
+
+/// A session and the owner it belongs to, kept outside the session's lock
+/// so ownership can be checked while a statement is running
+struct Owned {
+    owner: String,
+    session: Arc<Mutex<Session>>,
+}
+
+/// Open transactions, each pinned to its own connection
+pub struct TransactionManager {
+    sessions: RwLock<HashMap<String, Owned>>,
+    idle_timeout: Duration,
+    read_only: ReadOnlyMode,
+}
+
+/// Check that a statement may run inside a transaction session
+pub fn validate_statement(query: &str) -> Result<(), String> {
+    if split_statements(query).len() > 1 {
+        return Err("Run one statement at a time inside a transaction".to_string());
+    }
+
+    let words: Vec<String> = query_service::strip_leading_comments(query)
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .filter(|w| !w.is_empty())
+        .map(str::to_uppercase)
+        .collect();
+    let keyword = words.first().map(String::as_str).unwrap_or("");
+    match query_service::classify_statement(query) {
+        // COPY can read and write server files and run programs
+        StatementKind::Write if keyword == "COPY" => {
+            Err("COPY is not allowed in a transaction session".to_string())
+        }
+        StatementKind::Utility if keyword == "SET" => validate_set(&words[1..]),
+        StatementKind::Utility if matches!(keyword, "RESET" | "DISCARD") => Err(format!(
+            "{} is not allowed in a transaction session",
+            keyword
+        )),
+        StatementKind::Read | StatementKind::Write | StatementKind::Utility => Ok(()),
+        StatementKind::Transaction => {
+            Err("Use the commit and rollback actions to end the transaction".to_string())
+        }
+        StatementKind::Ddl | StatementKind::Dcl => Err(
+            "Schema and permission changes are not allowed in a transaction session".to_string(),
+        ),
+        StatementKind::Unknown => query_service::validate_query(query),
+    }
+}
+
+/// Check the words after `SET`
+///
+/// Only `SET LOCAL` is allowed, since a session setting (`SET ROLE`,
+/// `SET default_transaction_read_only = off`) would outlive the transaction.
+/// Transaction characteristics are never changed, so a `BEGIN READ ONLY`
+/// session stays read-only.
+fn validate_set(words: &[String]) -> Result<(), String> {
+    let characteristics = || {
+        Err("Transaction characteristics cannot be changed in a transaction session".to_string())
+    };
+    let target = match words {
+        [first, ..] if first == "TRANSACTION" => return characteristics(),
+        [first, second, ..] if first == "SESSION" && second == "CHARACTERISTICS" => {
+            return characteristics()
+        }
+        [first, target, ..] if first == "LOCAL" => target.as_str(),
+        [first] if first == "LOCAL" => return Ok(()),
+        _ => {
+            return Err("Only SET LOCAL is allowed in a transaction session".to_string());
+        }
+    };
+    if target == "TRANSACTION"
+        || target.starts_with("TRANSACTION_")
+        || target.starts_with("DEFAULT_TRANSACTION_")
+    {
+        return characteristics();
+    }
+    Ok(())
+}
+
+impl TransactionManager {
+    pub fn new(idle_timeout: Duration, read_only: ReadOnlyMode) -> Self {
+        Self {
+            sessions: RwLock::new(HashMap::new()),
+            idle_timeout,
+            read_only,
+        }
+    }
+
+    /// Sessions idle longer than this are rolled back
+    pub fn idle_timeout(&self) -> Duration {
+        self.idle_timeout
+    }
+
+    /// Check out a connection from `pool` and open a transaction on it for `owner`
+    pub async fn begin(
+        &self,
+        pool: &Pool<Postgres>,
+        client_ip: String,
+        owner: String,
+    ) -> Result<TransactionInfo, String> {
+        if self.sessions.read().await.len() >= MAX_OPEN_TRANSACTIONS {
+            return Err(format!(
+                "At most {} transactions can be open at once; commit or roll one back first",
+                MAX_OPEN_TRANSACTIONS
+            ));
+        }
+
+        let mut conn = pool
+            .acquire()
+            .await
+            .map_err(|e| format!("Failed to acquire connection: {}", e))?;
+        let read_only = self.read_only.is_enabled();
+        let begin = if read_only {
+            "BEGIN READ ONLY"
+        } else {
+            "BEGIN"
+        };
+        sqlx::query(begin)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to begin transaction: {}", e))?;
+
+        let now = Utc::now();
+        let info = TransactionInfo {
+            id: uuid::Uuid::new_v4().to_string(),
+            client_ip,
+            read_only,
+            started_at: now,
+            last_used_at: now,
+            statements: 0,
+            aborted: false,
+        };
+        let session = Session {
+            info: info.clone(),
+            conn: Some(conn),
+            last_used: Instant::now(),
+        };
+
+        let mut sessions = self.sessions.write().await;
+        if sessions.len() >= MAX_OPEN_TRANSACTIONS {
+            // Lost a race with another begin
+            drop(sessions);
+            let mut session = session;
+            close(&mut session, "ROLLBACK").await?;
+            return Err(format!(
+                "At most {} transactions can be open at once; commit or roll one back first",
+                MAX_OPEN_TRANSACTIONS
+            ));
+        }
+        sessions.insert(
+            info.id.clone(),
+            Owned {
+                owner,
+                session: Arc::new(Mutex::new(session)),
+            },
+        );
+        Ok(info)
+    }
+
+    /// Whether `owner` has this transaction open
+    pub async fn is_open(&self, id: &str, owner: &str) -> bool {
+        self.owned(id, owner).await.is_some()
+    }
+
+    /// The session `id` if it is open and belongs to `owner`
+    async fn owned(&self, id: &str, owner: &str) -> Option<Arc<Mutex<Session>>> {
+        self.sessions
+            .read()
+            .await
+            .get(id)
+            .filter(|open| open.owner == owner)
+            .map(|open| open.session.clone())
+    }
+
+    /// Remove the session `id` if it is open and belongs to `owner`
+    async fn take(&self, id: &str, owner: &str) -> Option<Arc<Mutex<Session>>> {
+        let mut sessions = self.sessions.write().await;
+        if sessions.get(id)?.owner != owner {
+            return None;
+        }
+        sessions.remove(id).map(|open| open.session)
+    }
+
+    /// Transactions `owner` has open, oldest first
+    pub async fn list(&self, owner: &str) -> Vec<TransactionInfo> {
+        let sessions: Vec<_> = self
+            .sessions
+            .read()
+            .await
+            .values()
+            .filter(|open| open.owner == owner)
+            .map(|open| open.session.clone())
+            .collect();
+        let mut infos = Vec::with_capacity(sessions.len());
+        for session in sessions {
+            infos.push(session.lock().await.info.clone());
+        }
+        infos.sort_by_key(|info| info.started_at);
+        infos
+    }
+
+    /// Run a statement in a transaction, returning `None` if `owner` does not
+    /// have it open
+    pub async fn execute(
+        &self,
+        id: &str,
+        owner: &str,
+        query: &str,
+        params: &[QueryParam],
+    ) -> Option<Result<QueryResult, String>> {
+        let session = self.owned(id, owner).await?;
+        let mut session = session.lock().await;
+        let conn = session.conn.as_mut()?;
+
+        // Box<dyn Error> is not Send, so convert it right away; a fetch
+        // stopped at the row limit leaves the transaction usable
+        let result = query_service::execute_on(conn, query, params, false)
+            .await
+            .map_err(|e| (e.is::<RowLimitExceeded>(), e.to_string()));
+
+        session.last_used = Instant::now();
+        session.info.last_used_at = Utc::now();
+        session.info.statements += 1;
+        if matches!(result, Err((false, _))) {
+            session.info.aborted = true;
+        }
+        Some(result.map_err(|(_, e)| e))
+    }
+
+    /// Commit a transaction, returning `Ok(false)` if `owner` does not have it open
+    ///
+    /// An aborted transaction is rolled back instead and reported as an error,
+    /// since PostgreSQL would silently turn the `COMMIT` into a rollback.
+    pub async fn commit(&self, id: &str, owner: &str) -> Result<bool, String> {
+        let Some(session) = self.take(id, owner).await else {
+            return Ok(false);
+        };
+        let mut session = session.lock().await;
+        if session.info.aborted {
+            close(&mut session, "ROLLBACK").await?;
+            return Err(
+                "Transaction was aborted by an earlier error and has been rolled back".to_string(),
+            );
+        }
+        close(&mut session, "COMMIT").await?;
+        Ok(true)
+    }
+
+    /// Roll back a transaction, returning `Ok(false)` if `owner` does not have it open
+    pub async fn rollback(&self, id: &str, owner: &str) -> Result<bool, String> {
+        let Some(session) = self.take(id, owner).await else {
+            return Ok(false);
+        };
+        let mut session = session.lock().await;
+        close(&mut session, "ROLLBACK").await?;
+        Ok(true)
+    }
+
+    /// Roll back sessions idle longer than the timeout, returning what was closed
+    ///
+    /// Sessions busy running a statement are left alone.
+    pub async fn reap_idle(&self) -> Vec<TransactionInfo> {
+        let expired: Vec<String> = self
+            .sessions
+            .read()
+            .await
+            .iter()
+            .filter(|(_, open)| {
+                open.session
+                    .try_lock()
+                    .is_ok_and(|s| s.last_used.elapsed() > self.idle_timeout)
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        let mut reaped = Vec::new();
+        for id in expired {
+            let Some(open) = self.sessions.write().await.remove(&id) else {
+                continue;
+            };
+            let mut session = open.session.lock().await;
+            if let Err(e) = close(&mut session, "ROLLBACK").await {
+                tracing::warn!("{}", e);
+            }
+            reaped.push(session.info.clone());
+        }
+        reaped
+    }
+
+    /// Roll back every open session, returning what was closed
+    ///
+    /// Used at shutdown, waiting for statements still running to finish.
+    pub async fn rollback_all(&self) -> Vec<TransactionInfo> {
+        let sessions: Vec<_> = self.sessions.write().await.drain().collect();
+        let mut closed = Vec::with_capacity(sessions.len());
+        for (_, open) in sessions {
+            let mut session = open.session.lock().await;
+            if let Err(e) = close(&mut session, "ROLLBACK").await {
+                tracing::warn!("{}", e);
+            }
+            closed.push(session.info.clone());
+        }
+        closed
+    }
+
+    /// Start the background task that rolls back idle sessions
+    pub fn spawn_reaper(self: &Arc<Self>) {
+        let manager = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(REAP_INTERVAL);
+            loop {
+                interval.tick().await;
+                for info in manager.reap_idle().await {
+                    tracing::info!(
+                        "Rolled back transaction {} from {} after {}s idle",
+                        info.id,
+                        info.client_ip,
+                        manager.idle_timeout.as_secs()
+                    );
+                }
+            }
+        });
+    }
+}
+
+/// End a session's transaction and close its connection
+///
+/// The connection is never handed back to the pool: statements in the session
+/// may have left temporary tables, prepared statements, listeners or settings
+/// on it, and the next request must not inherit them. The pool opens a
+/// replacement with its usual settings.
+async fn close(session: &mut Session, command: &str) -> Result<(), String> {
+    let Some(mut conn) = session.conn.take() else {
+        return Ok(());
+    };
+    let result = sqlx::query(command).execute(&mut *conn).await;
+    let _ = conn.close().await;
+    result
+        .map(|_| ())
+        .map_err(|e| format!("Failed to {} transaction: {}", command.to_lowercase(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_statement() {
+        assert!(validate_statement("SELECT * FROM accounts").is_ok());
+        assert!(validate_statement("UPDATE accounts SET balance = 0 WHERE id = 7").is_ok());
+        assert!(validate_statement("DELETE FROM accounts WHERE id = 7;").is_ok());
+        assert!(validate_statement("SET LOCAL statement_timeout = '5s'").is_ok());
+
+        assert!(validate_statement("DROP TABLE accounts").is_err());
+        assert!(validate_statement("GRANT ALL ON accounts TO bob").is_err());
+        assert!(validate_statement("COMMIT").is_err());
+        assert!(validate_statement("/* sneaky */ copy accounts TO PROGRAM 'sh'").is_err());
+        assert!(validate_statement("UPDATE a SET x = 1; DROP TABLE b").is_err());
+    }
+
+    #[test]
+    fn test_validate_statement_settings() {
+        assert!(validate_statement("SET LOCAL work_mem = '64MB'").is_ok());
+        assert!(validate_statement("set local search_path TO app, public").is_ok());
+        assert!(validate_statement("SET LOCAL ROLE reporting").is_ok());
+        assert!(validate_statement("LOCK TABLE accounts IN SHARE MODE").is_ok());
+
+        // Transaction characteristics, which would lift BEGIN READ ONLY
+        assert!(validate_statement("SET TRANSACTION READ WRITE").is_err());
+        assert!(
+            validate_statement("SET SESSION CHARACTERISTICS AS TRANSACTION READ WRITE").is_err()
+        );
+        assert!(validate_statement("SET LOCAL transaction_read_only = off").is_err());
+        assert!(validate_statement("SET LOCAL \"transaction_read_only\" TO off").is_err());
+        assert!(validate_statement("SET LOCAL default_transaction_read_only = off").is_err());
+        assert!(validate_statement("SET LOCAL TRANSACTION ISOLATION LEVEL SERIALIZABLE").is_err());
+
+        // Session-level settings survive the commit
+        assert!(validate_statement("SET ROLE postgres").is_err());
+        assert!(validate_statement("SET SESSION AUTHORIZATION postgres").is_err());
+        assert!(validate_statement("SET default_transaction_read_only = off").is_err());
+        assert!(validate_statement("SET SESSION work_mem = '1GB'").is_err());
+        assert!(validate_statement("/* x */ set statement_timeout = 0").is_err());
+        assert!(validate_statement("RESET ROLE").is_err());
+        assert!(validate_statement("RESET ALL").is_err());
+        assert!(validate_statement("DISCARD ALL").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sessions_belong_to_their_owner() {
+        let Ok(url) = std::env::var("TEST_DATABASE_URL") else {
+            eprintln!("TEST_DATABASE_URL is not set, skipping");
+            return;
+        };
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .max_connections(2)
+            .connect(&url)
+            .await
+            .unwrap();
+        let manager = TransactionManager::new(Duration::from_secs(60), ReadOnlyMode::new(false));
+        let info = manager
+            .begin(&pool, "127.0.0.1".to_string(), "alice".to_string())
+            .await
+            .unwrap();
+
+        // Another owner cannot see, use or end it
+        assert!(manager.list("mallory").await.is_empty());
+        assert!(!manager.is_open(&info.id, "mallory").await);
+        assert!(manager
+            .execute(&info.id, "mallory", "SELECT 1", &[])
+            .await
+            .is_none());
+        assert_eq!(manager.commit(&info.id, "mallory").await, Ok(false));
+        assert_eq!(manager.rollback(&info.id, "mallory").await, Ok(false));
+
+        let listed = manager.list("alice").await;
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, info.id);
+        assert!(manager
+            .execute(&info.id, "alice", "SELECT 1", &[])
+            .await
+            .unwrap()
+            .is_ok());
+        assert_eq!(manager.rollback(&info.id, "alice").await, Ok(true));
+        assert!(!manager.is_open(&info.id, "alice").await);
+    }
+
+    #[tokio::test]
+    async fn test_session_state_does_not_reach_the_pool() {
+        let Ok(url) = std::env::var("TEST_DATABASE_URL") else {
+            eprintln!("TEST_DATABASE_URL is not set, skipping");
+            return;
+        };
+        // One connection, so a returned one would be handed out next
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .max_connections(1)
+            .connect(&url)
+            .await
+            .unwrap();
+        let manager = TransactionManager::new(Duration::from_secs(60), ReadOnlyMode::new(false));
+        let info = manager
+            .begin(&pool, "127.0.0.1".to_string(), "alice".to_string())
+            .await
+            .unwrap();
+        manager
+            .execute(
+                &info.id,
+                "alice",
+                "SELECT set_config('application_name', 'leaked', false)",
+                &[],
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(manager.commit(&info.id, "alice").await, Ok(true));
+
+        let name: String = sqlx::query_scalar("SELECT current_setting('application_name')")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_ne!(name, "leaked");
+    }
+}
@@ -0,0 +1,763 @@
+use crate::services::audit_service::{AuditEvent, AuditEventType};
+/// Outbound Webhooks
+///
+/// Pushes application events to the endpoints declared in `WEBHOOKS_FILE`
+/// (a Slack incoming webhook, a chat bridge, an automation service):
+/// - `ddl_executed`: a schema change succeeded, from the console or the UI
+/// - `query_failed`: a statement returned an error
+/// - `export_completed`: results or history were downloaded as a file
+/// - `backup_finished`: a backup run ended, successfully or not
+///
+/// Each endpoint has its own delivery task and queue, so it receives events
+/// in order and a slow endpoint never holds up another. Connection errors,
+/// timeouts, `429` and `5xx` responses are retried after `RETRY_DELAYS`;
+/// any other response is final. With a secret, each attempt is signed with
+/// HMAC-SHA256 over `<timestamp>.<body>`.
+use crate::services::{json_file, notify, query_service};
+use chrono::{DateTime, Utc};
+use hyper::{StatusCode, Uri};
+use parking_lot::Mutex;
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashSet, VecDeque};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+/// Waits between attempts; a delivery is tried once more than there are delays
+pub const RETRY_DELAYS: [Duration; 3] = [
+    Duration::from_secs(5),
+    Duration::from_secs(30),
+    Duration::from_secs(120),
+];
+
+/// Notifications waiting per endpoint before new ones are dropped
+const QUEUE_CAPACITY: usize = 256;
+
+/// Deliveries kept per endpoint for the admin listing
+const RECENT_DELIVERIES: usize = 20;
+
+/// Longest statement text quoted in a summary
+const SUMMARY_QUERY_CHARS: usize = 200;
+
+/// Application events sent to webhooks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    DdlExecuted,
+    QueryFailed,
+    ExportCompleted,
+    BackupFinished,
+    /// Sent by the admin test endpoint only
+    Test,
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::DdlExecuted => "ddl_executed",
+            WebhookEvent::QueryFailed => "query_failed",
+            WebhookEvent::ExportCompleted => "export_completed",
+            WebhookEvent::BackupFinished => "backup_finished",
+            WebhookEvent::Test => "test",
+        }
+    }
+
+    /// The webhook event an audit event stands for, if any
+    pub fn from_audit(event: &AuditEvent) -> Option<Self> {
+        match event.event_type {
+            AuditEventType::SchemaModification if event.success => Some(WebhookEvent::DdlExecuted),
+            AuditEventType::QueryExecution
+                if event.success
+                    && query_service::classify_statement(&event.action)
+                        == query_service::StatementKind::Ddl =>
+            {
+                Some(WebhookEvent::DdlExecuted)
+            }
+            AuditEventType::SqlError => Some(WebhookEvent::QueryFailed),
+            AuditEventType::Backup => Some(WebhookEvent::BackupFinished),
+            _ => None,
+        }
+    }
+}
+
+/// Body of a `json` delivery
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    /// Kept across retries, so receivers can drop duplicates
+    pub id: String,
+    pub event: WebhookEvent,
+    pub timestamp: DateTime<Utc>,
+    /// One line for people; the text of a `slack` delivery
+    pub summary: String,
+    pub data: serde_json::Value,
+}
+
+impl Notification {
+    pub fn new(event: WebhookEvent, summary: String, data: serde_json::Value) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            event,
+            timestamp: Utc::now(),
+            summary,
+            data,
+        }
+    }
+
+    /// Notification for an audit event that maps to a webhook event, with
+    /// the audit event as data
+    pub fn from_audit(event: &AuditEvent) -> Option<Self> {
+        let kind = WebhookEvent::from_audit(event)?;
+        let details = event.details.as_deref();
+        let summary = match kind {
+            WebhookEvent::DdlExecuted => format!(
+                "Schema changed on {}: {}",
+                event.resource,
+                truncate(&event.action)
+            ),
+            WebhookEvent::QueryFailed => format!(
+                "Query failed on {}: {}{}",
+                event.resource,
+                truncate(&event.action),
+                details.map(|d| format!(" ({})", d)).unwrap_or_default()
+            ),
+            _ if event.success => format!("{} finished: {}", event.action, event.resource),
+            _ => format!(
+                "{} failed: {}",
+                event.action,
+                details.unwrap_or(&event.resource)
+            ),
+        };
+        Some(Self::new(
+            kind,
+            summary,
+            serde_json::to_value(event).unwrap_or_default(),
+        ))
+    }
+
+    /// A query result or the history was downloaded as a file
+    pub fn export_completed(
+        source: &str,
+        format: &str,
+        rows: usize,
+        bytes: usize,
+        client_ip: &str,
+    ) -> Self {
+        Self::new(
+            WebhookEvent::ExportCompleted,
+            format!(
+                "Exported {} rows of {} as {} ({} bytes)",
+                rows, source, format, bytes
+            ),
+            json!({
+                "source": source,
+                "format": format,
+                "rows": rows,
+                "bytes": bytes,
+                "client_ip": client_ip,
+            }),
+        )
+    }
+}
+
+fn truncate(query: &str) -> String {
+    let query = query.trim();
+    match query.char_indices().nth(SUMMARY_QUERY_CHARS) {
+        Some((end, _)) => format!("{}…", &query[..end]),
+        None => query.to_string(),
+    }
+}
+
+/// Shape of the request body
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PayloadFormat {
+    /// The full `Notification`
+    #[default]
+    Json,
+    /// `{"text": summary}`, as Slack-compatible incoming webhooks expect
+    Slack,
+}
+
+/// An endpoint declared in `WEBHOOKS_FILE`
+///
+/// The URL and secret can be read from environment variables instead, so
+/// the file itself holds no secrets (a Slack webhook URL is one).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub id: String,
+    /// `http://` or `https://` URL
+    pub url: Option<String>,
+    /// Environment variable holding the URL
+    pub url_env: Option<String>,
+    /// Events to send; all of them when empty
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+    #[serde(default)]
+    pub format: PayloadFormat,
+    /// HMAC key for the signature header
+    pub secret: Option<String>,
+    /// Environment variable holding the secret
+    pub secret_env: Option<String>,
+}
+
+/// Contents of `WEBHOOKS_FILE`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WebhooksFile {
+    webhooks: Vec<WebhookConfig>,
+}
+
+/// Outcome of one notification sent to one endpoint
+#[derive(Debug, Clone, Serialize)]
+pub struct Delivery {
+    /// The notification's id
+    pub id: String,
+    pub event: WebhookEvent,
+    pub success: bool,
+    pub attempts: usize,
+    /// Status of the last response, if there was one
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub finished_at: DateTime<Utc>,
+}
+
+/// An endpoint as shown by the admin listing
+#[derive(Debug, Serialize)]
+pub struct WebhookInfo {
+    pub id: String,
+    /// Scheme and host only; the path often carries a token
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+    pub format: PayloadFormat,
+    pub signed: bool,
+    /// Most recent first
+    pub deliveries: Vec<Delivery>,
+}
+
+struct Endpoint {
+    id: String,
+    url: Uri,
+    events: Vec<WebhookEvent>,
+    format: PayloadFormat,
+    secret: Option<String>,
+    recent: Mutex<VecDeque<Delivery>>,
+}
+
+impl Endpoint {
+    fn from_config(config: &WebhookConfig) -> Result<Self, String> {
+        let env = |name: &str| {
+            std::env::var(name).map_err(|_| {
+                format!(
+                    "Webhook '{}': environment variable {} is not set",
+                    config.id, name
+                )
+            })
+        };
+        let url = match (&config.url, &config.url_env) {
+            (_, Some(name)) => env(name)?,
+            (Some(url), None) => url.clone(),
+            (None, None) => return Err(format!("Webhook '{}': url is required", config.id)),
+        };
+        let secret = match (&config.secret, &config.secret_env) {
+            (_, Some(name)) => Some(env(name)?),
+            (secret, None) => secret.clone(),
+        };
+
+        Ok(Self {
+            id: config.id.clone(),
+            url: notify::endpoint_url(&url)
+                .map_err(|e| format!("Webhook '{}': {}", config.id, e))?,
+            events: config.events.clone(),
+            format: config.format,
+            secret,
+            recent: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    fn wants(&self, event: WebhookEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+
+    fn body(&self, notification: &Notification) -> Result<Vec<u8>, String> {
+        match self.format {
+            PayloadFormat::Json => serde_json::to_vec(notification),
+            PayloadFormat::Slack => serde_json::to_vec(&json!({ "text": notification.summary })),
+        }
+        .map_err(|e| format!("Failed to serialize notification: {}", e))
+    }
+
+    /// Send once, signing with the current time
+    async fn send(&self, notification: &Notification, body: &[u8]) -> Result<StatusCode, String> {
+        let mut headers = vec![
+            ("X-Pgadmin-Event", notification.event.as_str().to_string()),
+            ("X-Pgadmin-Delivery", notification.id.clone()),
+        ];
+        if let Some(secret) = &self.secret {
+            let timestamp = Utc::now().timestamp();
+            headers.push(("X-Pgadmin-Timestamp", timestamp.to_string()));
+            headers.push(("X-Pgadmin-Signature", signature(secret, timestamp, body)));
+        }
+
+        tokio::time::timeout(
+            notify::DELIVERY_TIMEOUT,
+            notify::post(&self.url, &headers, body.to_vec()),
+        )
+        .await
+        .map_err(|_| format!("Timed out posting to {}", self.describe()))?
+    }
+
+    /// Send, retrying transient failures after each of `retry_delays`
+    async fn deliver(&self, notification: &Notification, retry_delays: &[Duration]) -> Delivery {
+        let mut delivery = Delivery {
+            id: notification.id.clone(),
+            event: notification.event,
+            success: false,
+            attempts: 0,
+            status: None,
+            error: None,
+            finished_at: Utc::now(),
+        };
+        let body = match self.body(notification) {
+            Ok(body) => body,
+            Err(e) => {
+                delivery.error = Some(e);
+                return delivery;
+            }
+        };
+
+        loop {
+            delivery.attempts += 1;
+            let result = self.send(notification, &body).await;
+            let retry = match &result {
+                Ok(status) => status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS,
+                Err(_) => true,
+            };
+            match result {
+                Ok(status) if status.is_success() => {
+                    delivery.success = true;
+                    delivery.status = Some(status.as_u16());
+                    delivery.error = None;
+                }
+                Ok(status) => {
+                    delivery.status = Some(status.as_u16());
+                    delivery.error = Some(format!("{} responded with {}", self.describe(), status));
+                }
+                Err(e) => {
+                    delivery.status = None;
+                    delivery.error = Some(e);
+                }
+            }
+
+            match retry_delays.get(delivery.attempts - 1) {
+                Some(delay) if retry && !delivery.success => {
+                    tracing::debug!(
+                        "Webhook {} attempt {} failed, retrying in {:?}",
+                        self.id,
+                        delivery.attempts,
+                        delay
+                    );
+                    tokio::time::sleep(*delay).await;
+                }
+                _ => break,
+            }
+        }
+        delivery.finished_at = Utc::now();
+        delivery
+    }
+
+    fn record(&self, delivery: Delivery) {
+        let mut recent = self.recent.lock();
+        recent.push_front(delivery);
+        recent.truncate(RECENT_DELIVERIES);
+    }
+
+    /// Scheme and host of the URL
+    fn describe(&self) -> String {
+        format!(
+            "{}://{}",
+            self.url.scheme_str().unwrap_or("http"),
+            self.url.authority().map(|a| a.as_str()).unwrap_or_default()
+        )
+    }
+}
+
+/// `sha256=` and the hex HMAC-SHA256 of `<timestamp>.<body>`
+pub fn signature(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let mut context = hmac::Context::with_key(&key);
+    context.update(timestamp.to_string().as_bytes());
+    context.update(b".");
+    context.update(body);
+    let hex: String = context
+        .sign()
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("sha256={}", hex)
+}
+
+/// Work for an endpoint's delivery task
+enum Message {
+    Notify(Notification),
+    /// Answered once every notification queued before it has been handled
+    Flush(oneshot::Sender<()>),
+}
+
+/// The configured endpoints and their delivery tasks
+pub struct Webhooks {
+    endpoints: Vec<(Arc<Endpoint>, mpsc::Sender<Message>)>,
+}
+
+impl Webhooks {
+    /// Read `WEBHOOKS_FILE` (none configured when it does not exist) and
+    /// start a delivery task per endpoint
+    pub async fn load(path: &str, retry_delays: &[Duration]) -> Result<Self, String> {
+        let file: WebhooksFile = json_file::load(Path::new(path), "webhooks file").await?;
+        Self::new(&file.webhooks, retry_delays)
+    }
+
+    pub fn new(configs: &[WebhookConfig], retry_delays: &[Duration]) -> Result<Self, String> {
+        validate_ids(configs)?;
+        let mut endpoints = Vec::with_capacity(configs.len());
+        for config in configs {
+            let endpoint = Arc::new(Endpoint::from_config(config)?);
+            let (sender, mut receiver) = mpsc::channel::<Message>(QUEUE_CAPACITY);
+            let worker = endpoint.clone();
+            let retry_delays = retry_delays.to_vec();
+            tokio::spawn(async move {
+                while let Some(message) = receiver.recv().await {
+                    match message {
+                        Message::Notify(notification) => {
+                            let delivery = worker.deliver(&notification, &retry_delays).await;
+                            if let Some(e) = &delivery.error {
+                                tracing::warn!(
+                                    "Webhook {} gave up on {} after {} attempts: {}",
+                                    worker.id,
+                                    notification.event.as_str(),
+                                    delivery.attempts,
+                                    e
+                                );
+                            }
+                            worker.record(delivery);
+                        }
+                        Message::Flush(done) => {
+                            let _ = done.send(());
+                        }
+                    }
+                }
+            });
+            endpoints.push((endpoint, sender));
+        }
+        Ok(Self { endpoints })
+    }
+
+    pub fn len(&self) -> usize {
+        self.endpoints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.endpoints.is_empty()
+    }
+
+    /// Endpoint descriptions for startup logs
+    pub fn describe(&self) -> Vec<String> {
+        self.endpoints
+            .iter()
+            .map(|(endpoint, _)| format!("{} ({})", endpoint.id, endpoint.describe()))
+            .collect()
+    }
+
+    /// Queue a notification for every endpoint that wants its event, without
+    /// waiting for delivery
+    pub fn emit(&self, notification: Notification) {
+        for (endpoint, sender) in &self.endpoints {
+            if !endpoint.wants(notification.event) {
+                continue;
+            }
+            if let Err(e) = sender.try_send(Message::Notify(notification.clone())) {
+                tracing::warn!("Dropping webhook notification for {}: {}", endpoint.id, e);
+            }
+        }
+    }
+
+    /// Wait until every notification queued so far has been delivered (or
+    /// given up on)
+    pub async fn flush(&self) {
+        for (_, sender) in &self.endpoints {
+            let (done, flushed) = oneshot::channel();
+            if sender.send(Message::Flush(done)).await.is_ok() {
+                let _ = flushed.await;
+            }
+        }
+    }
+
+    /// Every endpoint with its recent deliveries
+    pub fn list(&self) -> Vec<WebhookInfo> {
+        self.endpoints
+            .iter()
+            .map(|(endpoint, _)| WebhookInfo {
+                id: endpoint.id.clone(),
+                url: endpoint.describe(),
+                events: endpoint.events.clone(),
+                format: endpoint.format,
+                signed: endpoint.secret.is_some(),
+                deliveries: endpoint.recent.lock().iter().cloned().collect(),
+            })
+            .collect()
+    }
+
+    /// Send a `test` notification to one endpoint now, without retrying;
+    /// `None` if there is no such endpoint
+    pub async fn test(&self, id: &str) -> Option<Delivery> {
+        let (endpoint, _) = self.endpoints.iter().find(|(e, _)| e.id == id)?;
+        let notification = Notification::new(
+            WebhookEvent::Test,
+            "Test notification from pgAdmin-rs".to_string(),
+            json!({}),
+        );
+        let delivery = endpoint.deliver(&notification, &[]).await;
+        endpoint.record(delivery.clone());
+        Some(delivery)
+    }
+}
+
+fn validate_ids(configs: &[WebhookConfig]) -> Result<(), String> {
+    let mut seen = HashSet::new();
+    for config in configs {
+        let id = config.id.as_str();
+        if id.is_empty()
+            || !id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!(
+                "Webhook id '{}' must be letters, digits, '-' or '_'",
+                id
+            ));
+        }
+        if !seen.insert(id) {
+            return Err(format!("Webhook id '{}' is declared twice", id));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    fn config(json: serde_json::Value) -> WebhookConfig {
+        serde_json::from_value(json).unwrap()
+    }
+
+    /// Answer each request with the next status, returning the requests'
+    /// headers (lowercased names) and bodies
+    async fn serve(statuses: Vec<u16>) -> (String, tokio::task::JoinHandle<Vec<(String, String)>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for status in statuses {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut headers = String::new();
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).await.unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    let line = line.to_lowercase();
+                    if let Some(value) = line.strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                    headers.push_str(&line);
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).await.unwrap();
+                let response = format!(
+                    "HTTP/1.1 {} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                );
+                reader
+                    .get_mut()
+                    .write_all(response.as_bytes())
+                    .await
+                    .unwrap();
+                requests.push((headers, String::from_utf8(body).unwrap()));
+            }
+            requests
+        });
+        (url, server)
+    }
+
+    fn audit(event_type: AuditEventType, action: &str, success: bool) -> AuditEvent {
+        AuditEvent::new(
+            event_type,
+            "10.0.0.1".to_string(),
+            action.to_string(),
+            "query".to_string(),
+        )
+        .with_success(success)
+    }
+
+    #[test]
+    fn test_from_audit() {
+        let event = |t, action, success| WebhookEvent::from_audit(&audit(t, action, success));
+        assert_eq!(
+            event(AuditEventType::QueryExecution, "DROP TABLE t", true),
+            Some(WebhookEvent::DdlExecuted)
+        );
+        assert_eq!(
+            event(AuditEventType::QueryExecution, "SELECT 1", true),
+            None
+        );
+        assert_eq!(
+            event(AuditEventType::SchemaModification, "CREATE INDEX", true),
+            Some(WebhookEvent::DdlExecuted)
+        );
+        assert_eq!(
+            event(AuditEventType::SchemaModification, "CREATE INDEX", false),
+            None
+        );
+        assert_eq!(
+            event(AuditEventType::SqlError, "SELECT x", false),
+            Some(WebhookEvent::QueryFailed)
+        );
+        assert_eq!(
+            event(AuditEventType::Backup, "Backup of app", false),
+            Some(WebhookEvent::BackupFinished)
+        );
+        assert_eq!(
+            event(AuditEventType::AuthenticationFailure, "login", false),
+            None
+        );
+
+        let failed = Notification::from_audit(
+            &audit(AuditEventType::Backup, "Backup of app", false)
+                .with_details("pg_dump exited with 1".to_string()),
+        )
+        .unwrap();
+        assert_eq!(
+            failed.summary,
+            "Backup of app failed: pg_dump exited with 1"
+        );
+        assert_eq!(failed.data["action"], "Backup of app");
+
+        let long = format!("CREATE TABLE t ({})", "x int, ".repeat(100));
+        let ddl =
+            Notification::from_audit(&audit(AuditEventType::QueryExecution, &long, true)).unwrap();
+        assert!(ddl.summary.ends_with('…'));
+    }
+
+    #[test]
+    fn test_signature() {
+        assert_eq!(
+            signature("s3cret", 1_700_000_000, br#"{"a":1}"#),
+            "sha256=1698a50bc74d1ff1db85c4e0a5297c2ad9fdba245d5737cdb789e4cc6e098940"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validate() {
+        let new = |configs: Vec<WebhookConfig>| Webhooks::new(&configs, &[]).map(|w| w.len());
+        let slack = || config(json!({"id": "slack", "url": "https://hooks.example.com/T0/B0"}));
+
+        assert_eq!(new(vec![slack()]).unwrap(), 1);
+        assert!(new(vec![slack(), slack()]).unwrap_err().contains("twice"));
+        assert!(new(vec![config(json!({"id": "a b", "url": "http://h/"}))]).is_err());
+        assert!(new(vec![config(json!({"id": "a", "url": "ftp://h/"}))]).is_err());
+        assert!(new(vec![config(json!({"id": "a"}))])
+            .unwrap_err()
+            .contains("url is required"));
+        assert!(new(vec![config(
+            json!({"id": "a", "url": "http://h/", "secret_env": "PGADMIN_TEST_UNSET_VAR"})
+        )])
+        .unwrap_err()
+        .contains("PGADMIN_TEST_UNSET_VAR"));
+        assert!(serde_json::from_value::<WebhookConfig>(
+            json!({"id": "a", "url": "http://h/", "events": ["dropped_table"]})
+        )
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_delivery_retries_and_signs() {
+        let (url, server) = serve(vec![503, 200]).await;
+        let webhooks = Webhooks::new(
+            &[config(json!({"id": "ops", "url": url, "secret": "s3cret"}))],
+            &[Duration::from_millis(10)],
+        )
+        .unwrap();
+
+        let notification = Notification::export_completed("query", "csv", 3, 42, "10.0.0.1");
+        webhooks.emit(notification.clone());
+        webhooks.flush().await;
+
+        let requests = server.await.unwrap();
+        assert_eq!(requests.len(), 2);
+        let (headers, body) = &requests[1];
+        assert!(headers.contains("x-pgadmin-event: export_completed\r\n"));
+        assert!(headers.contains(&format!("x-pgadmin-delivery: {}\r\n", notification.id)));
+        let timestamp: i64 = headers
+            .lines()
+            .find_map(|l| l.strip_prefix("x-pgadmin-timestamp: "))
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(headers.contains(&format!(
+            "x-pgadmin-signature: {}\r\n",
+            signature("s3cret", timestamp, body.as_bytes())
+        )));
+        let sent: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(sent["event"], "export_completed");
+        assert_eq!(sent["data"]["rows"], 3);
+
+        let info = webhooks.list();
+        assert_eq!(info[0].url, url.trim_end_matches("/hook"));
+        let delivery = &info[0].deliveries[0];
+        assert!(delivery.success);
+        assert_eq!((delivery.attempts, delivery.status), (2, Some(200)));
+    }
+
+    #[tokio::test]
+    async fn test_delivery_rejected_and_filtered() {
+        let (url, server) = serve(vec![400]).await;
+        let webhooks = Webhooks::new(
+            &[config(json!({
+                "id": "slack",
+                "url": url,
+                "format": "slack",
+                "events": ["query_failed"],
+            }))],
+            &[Duration::from_millis(10)],
+        )
+        .unwrap();
+
+        // Not subscribed, so never sent
+        webhooks.emit(Notification::export_completed("query", "csv", 1, 1, ""));
+        webhooks.emit(
+            Notification::from_audit(&audit(AuditEventType::SqlError, "SELECT x", false)).unwrap(),
+        );
+        webhooks.flush().await;
+
+        let requests = server.await.unwrap();
+        assert_eq!(requests.len(), 1);
+        let (headers, body) = &requests[0];
+        assert!(!headers.contains("x-pgadmin-signature"));
+        let sent: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(sent, json!({"text": "Query failed on query: SELECT x"}));
+
+        let delivery = &webhooks.list()[0].deliveries[0];
+        assert!(!delivery.success);
+        assert_eq!((delivery.attempts, delivery.status), (1, Some(400)));
+    }
+}
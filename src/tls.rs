@@ -0,0 +1,369 @@
+use axum::serve::Listener;
+/// Native HTTPS
+///
+/// Serves the app over TLS when `TLS_CERT_FILE` and `TLS_KEY_FILE` are set, so
+/// deployments without a reverse proxy do not send credentials and query
+/// results in plaintext. Handshakes run on their own tasks, so a slow client
+/// never holds up the accept loop, and one that has not finished within
+/// `TLS_HANDSHAKE_TIMEOUT_SECS` is dropped.
+///
+/// Client sessions for outbound HTTPS (object storage, webhooks) use the same
+/// stream type, verified against the bundled web PKI roots.
+use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName};
+use rustls::{ClientConfig, RootCertStore, ServerConfig};
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::{TlsAcceptor, TlsConnector, TlsStream};
+
+/// How long an outbound TLS handshake may take
+const CONNECT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Handshaken connections waiting for `accept`
+const ACCEPT_QUEUE: usize = 64;
+
+/// Build a server config from PEM files: a certificate chain (leaf first) and its private key
+pub fn load_config(cert_file: &str, key_file: &str) -> Result<Arc<ServerConfig>, String> {
+    let certs = CertificateDer::pem_file_iter(cert_file)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Failed to read TLS certificate {}: {}", cert_file, e))?;
+    if certs.is_empty() {
+        return Err(format!("No certificates found in {}", cert_file));
+    }
+    let key = PrivateKeyDer::from_pem_file(key_file)
+        .map_err(|e| format!("Failed to read TLS private key {}: {}", key_file, e))?;
+
+    let mut config =
+        ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|e| format!("Invalid TLS configuration: {}", e))?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(|e| format!("Invalid TLS certificate or key: {}", e))?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(Arc::new(config))
+}
+
+/// Client config trusting the web PKI roots
+pub fn client_config() -> Arc<ClientConfig> {
+    let roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let mut config =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .expect("the ring provider supports the default protocol versions")
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Arc::new(config)
+}
+
+/// Open a TLS session to `host` over a connected socket
+pub async fn connect(
+    config: Arc<ClientConfig>,
+    host: &str,
+    io: TcpStream,
+) -> Result<TlsStream<TcpStream>, String> {
+    let name = ServerName::try_from(host.to_string())
+        .map_err(|e| format!("Invalid TLS server name {}: {}", host, e))?;
+    match tokio::time::timeout(
+        CONNECT_HANDSHAKE_TIMEOUT,
+        TlsConnector::from(config).connect(name, io),
+    )
+    .await
+    {
+        Ok(Ok(stream)) => Ok(stream.into()),
+        Ok(Err(e)) => Err(format!("TLS handshake with {} failed: {}", host, e)),
+        Err(_) => Err(format!("TLS handshake with {} timed out", host)),
+    }
+}
+
+/// The server's listening socket, accepting plain TCP or TLS connections
+pub enum AppListener {
+    Plain(TcpListener),
+    Tls {
+        local_addr: SocketAddr,
+        connections: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+    },
+}
+
+impl AppListener {
+    /// Listen for plain TCP, or for TLS when given a server config; TLS
+    /// clients get `handshake_timeout` to finish their handshake
+    pub fn new(
+        tcp: TcpListener,
+        tls: Option<Arc<ServerConfig>>,
+        handshake_timeout: Duration,
+    ) -> io::Result<Self> {
+        let Some(config) = tls else {
+            return Ok(AppListener::Plain(tcp));
+        };
+        let local_addr = tcp.local_addr()?;
+        let (tx, connections) = mpsc::channel(ACCEPT_QUEUE);
+        tokio::spawn(accept_tls(
+            tcp,
+            TlsAcceptor::from(config),
+            handshake_timeout,
+            tx,
+        ));
+        Ok(AppListener::Tls {
+            local_addr,
+            connections,
+        })
+    }
+}
+
+/// Accept TCP connections and handshake each on its own task, queueing the
+/// ones that complete in time, until the listener is dropped
+async fn accept_tls(
+    tcp: TcpListener,
+    acceptor: TlsAcceptor,
+    handshake_timeout: Duration,
+    tx: mpsc::Sender<(TlsStream<TcpStream>, SocketAddr)>,
+) {
+    loop {
+        let (stream, addr) = tokio::select! {
+            accepted = accept_tcp(&tcp) => accepted,
+            _ = tx.closed() => return,
+        };
+        let acceptor = acceptor.clone();
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            match tokio::time::timeout(handshake_timeout, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => {
+                    let _ = tx.send((stream.into(), addr)).await;
+                }
+                Ok(Err(e)) => tracing::debug!("TLS handshake with {} failed: {}", addr, e),
+                Err(_) => tracing::debug!("TLS handshake with {} timed out", addr),
+            }
+        });
+    }
+}
+
+/// Accept the next TCP connection, riding out errors
+async fn accept_tcp(tcp: &TcpListener) -> (TcpStream, SocketAddr) {
+    loop {
+        match tcp.accept().await {
+            Ok(accepted) => return accepted,
+            // Per-connection errors are not worth a pause; running out of
+            // file descriptors is
+            Err(e) => {
+                if !matches!(
+                    e.kind(),
+                    io::ErrorKind::ConnectionAborted | io::ErrorKind::ConnectionReset
+                ) {
+                    tracing::error!("Failed to accept connection: {}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    }
+}
+
+impl Listener for AppListener {
+    type Io = Connection;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self {
+            AppListener::Plain(tcp) => {
+                let (stream, addr) = accept_tcp(tcp).await;
+                (Connection::Plain(stream), addr)
+            }
+            AppListener::Tls { connections, .. } => {
+                // The sender lives as long as the accept task, which only
+                // stops once this receiver is gone
+                let (stream, addr) = connections
+                    .recv()
+                    .await
+                    .expect("the TLS accept task outlives the listener");
+                (Connection::Tls(Box::new(stream)), addr)
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        match self {
+            AppListener::Plain(tcp) => tcp.local_addr(),
+            AppListener::Tls { local_addr, .. } => Ok(*local_addr),
+        }
+    }
+}
+
+/// An accepted or outbound connection
+pub enum Connection {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl AsyncRead for Connection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Connection::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            Connection::Tls(stream) => Pin::new(stream.as_mut()).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Connection {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Connection::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            Connection::Tls(stream) => Pin::new(stream.as_mut()).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Connection::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            Connection::Tls(stream) => Pin::new(stream.as_mut()).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Connection::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            Connection::Tls(stream) => Pin::new(stream.as_mut()).poll_shutdown(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Router};
+    use std::io::{Read, Write};
+    use std::net::TcpStream as StdTcpStream;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls");
+
+    fn fixture(name: &str) -> String {
+        format!("{}/{}", FIXTURES, name)
+    }
+
+    /// Client config trusting the fixtures' CA
+    fn test_client_config() -> Arc<ClientConfig> {
+        let mut roots = RootCertStore::empty();
+        for cert in CertificateDer::pem_file_iter(fixture("ca.pem")).unwrap() {
+            roots.add(cert.unwrap()).unwrap();
+        }
+        Arc::new(
+            ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_root_certificates(roots)
+                .with_no_client_auth(),
+        )
+    }
+
+    /// Serve a one-route app over TLS, returning its address
+    async fn serve_https(handshake_timeout: Duration) -> SocketAddr {
+        let config = load_config(&fixture("cert.pem"), &fixture("key.pem")).unwrap();
+        let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let listener = AppListener::new(tcp, Some(config), handshake_timeout).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/", get(|| async { "secure" }));
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        addr
+    }
+
+    /// GET / over TLS from a blocking client
+    async fn get_root(addr: SocketAddr) -> String {
+        tokio::task::spawn_blocking(move || {
+            let conn = rustls::ClientConnection::new(
+                test_client_config(),
+                "localhost".try_into().unwrap(),
+            )
+            .unwrap();
+            let mut stream = rustls::StreamOwned::new(conn, StdTcpStream::connect(addr).unwrap());
+            stream
+                .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            let mut response = String::new();
+            let _ = stream.read_to_string(&mut response);
+            response
+        })
+        .await
+        .unwrap()
+    }
+
+    #[test]
+    fn test_load_config_errors() {
+        assert!(load_config(&fixture("cert.pem"), &fixture("key.pem")).is_ok());
+
+        let missing = load_config(&fixture("missing.pem"), &fixture("key.pem")).unwrap_err();
+        assert!(missing.contains("missing.pem"));
+        // A certificate file has no private key in it
+        assert!(load_config(&fixture("cert.pem"), &fixture("cert.pem")).is_err());
+        assert!(load_config(&fixture("key.pem"), &fixture("key.pem")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_serves_https() {
+        let addr = serve_https(Duration::from_secs(10)).await;
+        let response = get_root(addr).await;
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.ends_with("secure"));
+    }
+
+    #[tokio::test]
+    async fn test_stalled_handshake_is_dropped() {
+        let addr = serve_https(Duration::from_millis(200)).await;
+
+        // A client that never sends a ClientHello holds up no one else...
+        let mut stalled = TcpStream::connect(addr).await.unwrap();
+        let response = get_root(addr).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+
+        // ...and is disconnected once the timeout passes
+        let mut buf = [0u8; 16];
+        let read = tokio::time::timeout(Duration::from_secs(5), stalled.read(&mut buf))
+            .await
+            .expect("the server should close the stalled connection");
+        assert!(matches!(read, Ok(0) | Err(_)), "{:?}", read);
+    }
+
+    #[tokio::test]
+    async fn test_truncated_stream_is_an_error() {
+        let config = load_config(&fixture("cert.pem"), &fixture("key.pem")).unwrap();
+        let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = tcp.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = tcp.accept().await.unwrap();
+            let mut stream = TlsAcceptor::from(config).accept(stream).await.unwrap();
+            stream.write_all(b"partial").await.unwrap();
+            stream.flush().await.unwrap();
+            // Close the socket without a close_notify alert
+            let (mut tcp, _) = stream.into_inner();
+            tcp.shutdown().await.unwrap();
+        });
+
+        let tcp = TcpStream::connect(addr).await.unwrap();
+        let mut stream = connect(test_client_config(), "localhost", tcp)
+            .await
+            .unwrap();
+        let mut received = Vec::new();
+        let error = stream.read_to_end(&mut received).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(received, b"partial");
+    }
+}
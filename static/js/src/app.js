@@ -166,11 +166,94 @@ window.switchTab = function (tabName) {
   }
 };
 
+/**
+ * CSRF Protection
+ */
+// The server delivers the session's token in the pgadmin_csrf cookie;
+// mutating requests send it back
+const csrfToken = () => {
+  const cookie = document.cookie
+    .split("; ")
+    .find((c) => c.startsWith("pgadmin_csrf="));
+  return cookie ? cookie.split("=")[1] : "";
+};
+
+const isMutating = (method) =>
+  !["GET", "HEAD", "OPTIONS"].includes((method || "GET").toUpperCase());
+
+const isSameOrigin = (url) =>
+  new URL(url, document.baseURI).origin === window.location.origin;
+
+document.addEventListener("htmx:configRequest", (evt) => {
+  if (isMutating(evt.detail.verb)) {
+    evt.detail.headers["X-CSRF-Token"] = csrfToken();
+  }
+});
+
+// fetch() calls in page scripts
+const nativeFetch = window.fetch.bind(window);
+window.fetch = (input, init = {}) => {
+  const url = input instanceof Request ? input.url : String(input);
+  const method = init.method || (input instanceof Request ? input.method : "GET");
+  if (isMutating(method) && isSameOrigin(url)) {
+    const headers = new Headers(
+      init.headers || (input instanceof Request ? input.headers : undefined),
+    );
+    headers.set("X-CSRF-Token", csrfToken());
+    init = { ...init, headers };
+  }
+  return nativeFetch(input, init);
+};
+
+// Plain form posts carry the token as a hidden field
+const addCsrfField = (form) => {
+  let field = form.querySelector('input[name="csrf_token"]');
+  if (!field) {
+    field = document.createElement("input");
+    field.type = "hidden";
+    field.name = "csrf_token";
+    form.appendChild(field);
+  }
+  field.value = csrfToken();
+};
+
+document.addEventListener(
+  "submit",
+  (evt) => {
+    const form = evt.target;
+    if (form instanceof HTMLFormElement && isMutating(form.method)) {
+      addCsrfField(form);
+    }
+  },
+  true,
+);
+
+window.addCsrfField = addCsrfField;
+
 /**
  * HTMX Event Handling
  */
+// Error responses to HTMX requests are HTML alerts; show them in place
+document.addEventListener("htmx:beforeSwap", (evt) => {
+  const xhr = evt.detail.xhr;
+  const contentType = xhr.getResponseHeader("Content-Type") || "";
+  if (xhr.status >= 400 && contentType.startsWith("text/html")) {
+    evt.detail.shouldSwap = true;
+    evt.detail.isError = false;
+  }
+});
+
 document.addEventListener("htmx:responseError", (evt) => {
-  const error = evt.detail.xhr.responseText || "An error occurred";
+  let error = evt.detail.xhr.responseText || "An error occurred";
+  try {
+    const body = JSON.parse(error);
+    error = body.details ? `${body.error}: ${body.details}` : body.error;
+    if (body.request_id) {
+      error += ` (request ${body.request_id})`;
+    }
+  } catch (_) {
+    // Not a JSON error body; show it as is
+  }
   ToastManager.error(`Request failed: ${error}`);
 });
 